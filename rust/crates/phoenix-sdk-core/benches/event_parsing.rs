@@ -0,0 +1,121 @@
+//! Compares decoding whole `Log` instructions with scanning them through `WrapperEventIterator`.
+//! Run with `cargo bench -p phoenix-sdk-core --bench event_parsing`.
+
+// criterion does not build for wasm32
+#![cfg_attr(target_arch = "wasm32", no_main)]
+#![cfg(not(target_arch = "wasm32"))]
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use phoenix::program::{AuditLogHeader, FillEvent, PhoenixMarketEvent, PlaceEvent, ReduceEvent};
+use phoenix_sdk_core::event_iter::{WrapperEventIterator, AUDIT_LOG_HEADER_LEN};
+use solana_sdk::pubkey::Pubkey;
+
+const INSTRUCTIONS: u64 = 2_000;
+const EVENTS_PER_INSTRUCTION: u16 = 200;
+
+/// Synthetic `Log` instruction data: one fill for every two places and reduces.
+fn log_data() -> Vec<Vec<u8>> {
+    (0..INSTRUCTIONS)
+        .map(|sequence_number| {
+            let header = PhoenixMarketEvent::Header(AuditLogHeader {
+                instruction: 2,
+                sequence_number,
+                timestamp: 1_700_000_000,
+                slot: sequence_number,
+                market: Pubkey::new_unique(),
+                signer: Pubkey::new_unique(),
+                total_events: EVENTS_PER_INSTRUCTION,
+            });
+            let mut data = header.try_to_vec().unwrap();
+            for index in 0..EVENTS_PER_INSTRUCTION {
+                let event = match index % 3 {
+                    0 => PhoenixMarketEvent::Fill(FillEvent {
+                        index,
+                        maker_id: Pubkey::new_unique(),
+                        order_sequence_number: index as u64,
+                        price_in_ticks: 1000,
+                        base_lots_filled: 10,
+                        base_lots_remaining: 0,
+                    }),
+                    1 => PhoenixMarketEvent::Place(PlaceEvent {
+                        index,
+                        order_sequence_number: index as u64,
+                        client_order_id: index as u128,
+                        price_in_ticks: 999,
+                        base_lots_placed: 10,
+                    }),
+                    _ => PhoenixMarketEvent::Reduce(ReduceEvent {
+                        index,
+                        order_sequence_number: index as u64,
+                        price_in_ticks: 999,
+                        base_lots_removed: 10,
+                        base_lots_remaining: 0,
+                    }),
+                };
+                data.extend(event.try_to_vec().unwrap());
+            }
+            data
+        })
+        .collect()
+}
+
+/// What `parse_raw_phoenix_events` did before the iterator: a length-prefixed copy of the events,
+/// decoded as one `Vec`. Returns the number of fills.
+fn decode_as_vec(data: &[Vec<u8>]) -> u64 {
+    let mut fills = 0;
+    for chunk in data.iter() {
+        let header = PhoenixMarketEvent::try_from_slice(&chunk[..AUDIT_LOG_HEADER_LEN]).unwrap();
+        let PhoenixMarketEvent::Header(header) = header else {
+            unreachable!()
+        };
+        let mut bytes = (header.total_events as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&chunk[AUDIT_LOG_HEADER_LEN..]);
+        let events = Vec::<PhoenixMarketEvent>::try_from_slice(&bytes).unwrap();
+        fills += events
+            .iter()
+            .filter(|event| matches!(event, PhoenixMarketEvent::Fill(_)))
+            .count() as u64;
+    }
+    fills
+}
+
+fn decode_every_event(data: &[Vec<u8>]) -> u64 {
+    let mut fills = 0;
+    for chunk in data.iter() {
+        for event in WrapperEventIterator::new(chunk).unwrap() {
+            let event = event.unwrap().to_event().unwrap();
+            fills += matches!(event, PhoenixMarketEvent::Fill(_)) as u64;
+        }
+    }
+    fills
+}
+
+/// Sums the base lots filled, reading only the fills.
+fn scan_fills(data: &[Vec<u8>]) -> u64 {
+    let mut base_lots_filled = 0;
+    for chunk in data.iter() {
+        for event in WrapperEventIterator::new(chunk).unwrap() {
+            if let Some(fill) = event.unwrap().as_fill() {
+                base_lots_filled += fill.base_lots_filled();
+            }
+        }
+    }
+    base_lots_filled
+}
+
+fn event_parsing(c: &mut Criterion) {
+    let data = log_data();
+    let mut group = c.benchmark_group("event_parsing");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    group.sample_size(20);
+    group.bench_function("decode_as_vec", |b| b.iter(|| decode_as_vec(&data)));
+    group.bench_function("iterator_decode_every_event", |b| {
+        b.iter(|| decode_every_event(&data))
+    });
+    group.bench_function("iterator_fills_only", |b| b.iter(|| scan_fills(&data)));
+    group.finish();
+}
+
+criterion_group!(benches, event_parsing);
+criterion_main!(benches);
@@ -0,0 +1,83 @@
+//! Compares unit conversions through `SDKClientCore`, which looks markets up in a `MarketMap`,
+//! with the same conversions on a plain `BTreeMap`.
+//! Run with `cargo bench -p phoenix-sdk-core --bench market_map`.
+
+// criterion does not build for wasm32
+#![cfg_attr(target_arch = "wasm32", no_main)]
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use phoenix_sdk_core::sdk_client_core::{MarketMetadata, SDKClientCore};
+use solana_sdk::pubkey::Pubkey;
+
+const MARKETS: usize = 50;
+const CONVERSIONS: u64 = 1_000_000;
+
+fn metadata() -> MarketMetadata {
+    MarketMetadata {
+        base_atoms_per_raw_base_unit: 1_000_000_000,
+        quote_atoms_per_quote_unit: 1_000_000,
+        quote_atoms_per_quote_lot: 1,
+        base_atoms_per_base_lot: 1_000_000,
+        tick_size_in_quote_atoms_per_base_unit: 1000,
+        num_base_lots_per_base_unit: 1000,
+        raw_base_units_per_base_unit: 1,
+        ..Default::default()
+    }
+}
+
+/// The best of a few runs of `f`, in time per conversion.
+fn measure(name: &str, runs: usize, mut f: impl FnMut() -> f64) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..runs {
+        let start = Instant::now();
+        black_box(f());
+        best = best.min(start.elapsed());
+    }
+    let per_conversion = best / CONVERSIONS as u32;
+    println!("{:<40} {:>10.2?} per conversion", name, per_conversion);
+    per_conversion
+}
+
+fn main() {
+    let markets = (0..MARKETS)
+        .map(|_| (Pubkey::new_unique(), metadata()))
+        .collect::<BTreeMap<_, _>>();
+    let keys = markets.keys().copied().collect::<Vec<_>>();
+    let core = SDKClientCore {
+        markets: markets.clone().into(),
+        trader: Pubkey::new_unique(),
+        default_self_trade_behavior: Default::default(),
+    };
+    // `cargo test` runs benches once to check they work
+    let runs = if std::env::args().any(|arg| arg == "--bench") {
+        10
+    } else {
+        1
+    };
+
+    let plain = measure("BTreeMap lookup", runs, || {
+        let mut total = 0.0;
+        for i in 0..CONVERSIONS {
+            let meta = markets.get(&keys[i as usize % MARKETS]).unwrap();
+            total += meta.ticks_to_float_price(black_box(i));
+        }
+        total
+    });
+    let shared = measure("SDKClientCore::ticks_to_float_price", runs, || {
+        let mut total = 0.0;
+        for i in 0..CONVERSIONS {
+            total += core
+                .ticks_to_float_price(&keys[i as usize % MARKETS], black_box(i))
+                .unwrap();
+        }
+        total
+    });
+    println!(
+        "Conversions through the market map take {:.2}x as long as on a plain map",
+        shared.as_secs_f64() / plain.as_secs_f64().max(1e-12)
+    );
+}
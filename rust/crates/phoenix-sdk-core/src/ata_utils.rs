@@ -9,6 +9,9 @@ use solana_sdk::{
 
 const ATA_PROGRAM_ID: Pubkey = pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
 
+/// The Token-2022 program. Its token accounts share the SPL Token layout, followed by extensions.
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
 pub(crate) fn get_associated_token_address_and_bump_seed(
     wallet_address: &Pubkey,
     token_mint_address: &Pubkey,
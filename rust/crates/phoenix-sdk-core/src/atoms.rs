@@ -0,0 +1,258 @@
+//! Exact conversions between decimal strings and integer amounts.
+//!
+//! Every amount the program sees is an integer: prices in ticks, sizes in base lots and quote
+//! lots, balances in atoms. The SDK can be used end to end without floating point by
+//!
+//! - converting user input with `MarketMetadata::decimal_str_to_base_lots`,
+//!   `decimal_str_to_ticks` and `decimal_str_to_quote_lots`, which fail instead of silently
+//!   rounding or overflowing,
+//! - building orders from ticks and lots with `SDKClientCore::get_limit_order_ix_from_tick_price`,
+//!   `get_post_only_ix_from_tick_price` and `get_ioc_from_tick_price_generic_ix`,
+//! - reading results as integers from `PhoenixEvent`s, the analytics module and
+//!   `export::fill_records_in_atoms`, and
+//! - formatting integers for display with `base_lots_to_decimal_str`, `ticks_to_decimal_str`
+//!   and `quote_atoms_to_decimal_str`, or, for logs and UIs, with `MarketMetadata::format_base`,
+//!   `format_quote` and `format_price` and the `DisplayBase`, `DisplayQuote` and `DisplayPrice`
+//!   adapters.
+//!
+//! Methods that take or return `f64` are named with `float` or `_as_float`.
+
+use std::fmt::{self, Display};
+
+use anyhow::{anyhow, Result};
+
+use crate::sdk_client_core::MarketMetadata;
+
+/// How to round a conversion that does not come out to a whole number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+    /// Fail unless the conversion is exact.
+    Exact,
+}
+
+/// Parses a non-negative decimal string such as `"12.345"` into `(12345, 3)`, i.e. its digits
+/// and the number of them after the decimal point. Trailing zeros after the point are dropped.
+pub fn parse_decimal_str(s: &str) -> Result<(u128, u32)> {
+    let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if whole.is_empty()
+        || !whole.bytes().all(|b| b.is_ascii_digit())
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+        || (s.contains('.') && fraction.is_empty())
+    {
+        return Err(anyhow!("Invalid decimal amount: {:?}", s));
+    }
+    let fraction = fraction.trim_end_matches('0');
+    let mut digits = 0u128;
+    for b in whole.bytes().chain(fraction.bytes()) {
+        digits = digits
+            .checked_mul(10)
+            .and_then(|d| d.checked_add((b - b'0') as u128))
+            .ok_or_else(|| anyhow!("Decimal amount {} has too many digits", s))?;
+    }
+    Ok((digits, fraction.len() as u32))
+}
+
+/// Formats `atoms / 10^decimals` exactly, without trailing zeros.
+pub fn atoms_to_decimal_str(atoms: u128, decimals: u32) -> String {
+    let digits = atoms.to_string();
+    let decimals = decimals as usize;
+    let (whole, fraction) = if digits.len() > decimals {
+        let (whole, fraction) = digits.split_at(digits.len() - decimals);
+        (whole.to_string(), fraction.to_string())
+    } else {
+        ("0".to_string(), format!("{:0>1$}", digits, decimals))
+    };
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// How `MarketMetadata::format_base`, `format_quote` and `format_price` write amounts. The
+/// default groups whole digits with commas and keeps every fractional digit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountFormat {
+    /// Separate groups of three whole digits with commas, as in `1,234.56`.
+    pub thousands_separator: bool,
+    /// Cut the fraction after this many digits. The rest is truncated, not rounded, so that the
+    /// amount is never shown as more precise than it is, and a single atom may show as `0`.
+    pub max_fraction_digits: Option<u32>,
+}
+
+impl Default for AmountFormat {
+    fn default() -> Self {
+        Self {
+            thousands_separator: true,
+            max_fraction_digits: None,
+        }
+    }
+}
+
+impl AmountFormat {
+    pub fn with_thousands_separator(mut self, thousands_separator: bool) -> Self {
+        self.thousands_separator = thousands_separator;
+        self
+    }
+
+    pub fn with_max_fraction_digits(mut self, max_fraction_digits: u32) -> Self {
+        self.max_fraction_digits = Some(max_fraction_digits);
+        self
+    }
+
+    /// Formats `numerator / denominator` to `fraction_digits` digits after the point, or
+    /// `max_fraction_digits` if fewer, without trailing zeros. Digits are computed by long
+    /// division, so the result is exact up to the last digit shown.
+    pub(crate) fn format_ratio(
+        &self,
+        numerator: u128,
+        denominator: u128,
+        fraction_digits: u32,
+    ) -> String {
+        let denominator = denominator.max(1);
+        let whole = (numerator / denominator).to_string();
+        let mut formatted = if self.thousands_separator {
+            let mut grouped = String::with_capacity(whole.len() + whole.len() / 3);
+            for (i, digit) in whole.chars().enumerate() {
+                if i > 0 && (whole.len() - i).is_multiple_of(3) {
+                    grouped.push(',');
+                }
+                grouped.push(digit);
+            }
+            grouped
+        } else {
+            whole
+        };
+
+        let fraction_digits = self
+            .max_fraction_digits
+            .map_or(fraction_digits, |max| max.min(fraction_digits));
+        // The remainder is below the denominator, so multiplying it by 10 cannot overflow for
+        // denominators of up to 124 bits
+        let mut remainder = numerator % denominator;
+        let mut fraction = String::new();
+        for _ in 0..fraction_digits {
+            if remainder == 0 {
+                break;
+            }
+            remainder *= 10;
+            fraction.push(char::from(b'0' + (remainder / denominator) as u8));
+            remainder %= denominator;
+        }
+        let fraction = fraction.trim_end_matches('0');
+        if !fraction.is_empty() {
+            formatted.push('.');
+            formatted.push_str(fraction);
+        }
+        formatted
+    }
+}
+
+/// Writes base atoms with `MarketMetadata::format_base`, e.g. `format!("{} SOL", DisplayBase(
+/// atoms, &meta))`. A precision, as in `{:.2}`, sets the maximum fractional digits.
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayBase<'a>(pub u64, pub &'a MarketMetadata);
+
+/// Writes quote atoms with `MarketMetadata::format_quote`, like `DisplayBase`.
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayQuote<'a>(pub u64, pub &'a MarketMetadata);
+
+/// Writes a price in ticks with `MarketMetadata::format_price`, like `DisplayBase`.
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayPrice<'a>(pub u64, pub &'a MarketMetadata);
+
+/// The default format, with the formatter's precision as the maximum fractional digits.
+fn formatter_format(f: &fmt::Formatter<'_>) -> AmountFormat {
+    let format = AmountFormat::default();
+    match f.precision() {
+        Some(precision) => format.with_max_fraction_digits(precision as u32),
+        None => format,
+    }
+}
+
+impl Display for DisplayBase<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.1.format_base_with(self.0, formatter_format(f)))
+    }
+}
+
+impl Display for DisplayQuote<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.1.format_quote_with(self.0, formatter_format(f)))
+    }
+}
+
+impl Display for DisplayPrice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.1.format_price_with(self.0, formatter_format(f)))
+    }
+}
+
+/// Computes `value * numerator / denominator`, rounding as requested, failing on overflow or if
+/// the result does not fit in a `u64`.
+pub fn mul_div(value: u128, numerator: u128, denominator: u128, rounding: Rounding) -> Result<u64> {
+    if denominator == 0 {
+        return Err(anyhow!("Division by zero"));
+    }
+    let product = value
+        .checked_mul(numerator)
+        .ok_or_else(|| anyhow!("Overflow converting {}", value))?;
+    let quotient = product / denominator;
+    let quotient = match (product % denominator, rounding) {
+        (0, _) | (_, Rounding::Down) => quotient,
+        (_, Rounding::Up) => quotient + 1,
+        (_, Rounding::Exact) => {
+            return Err(anyhow!(
+                "{} * {} / {} is not a whole number",
+                value,
+                numerator,
+                denominator
+            ))
+        }
+    };
+    u64::try_from(quotient).map_err(|_| anyhow!("{} does not fit in a u64", quotient))
+}
+
+/// `10^exponent`, failing on overflow.
+pub(crate) fn pow10(exponent: u32) -> Result<u128> {
+    10u128
+        .checked_pow(exponent)
+        .ok_or_else(|| anyhow!("10^{} overflows", exponent))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        assert_eq!(parse_decimal_str("12.345").unwrap(), (12345, 3));
+        assert_eq!(parse_decimal_str("7").unwrap(), (7, 0));
+        assert_eq!(parse_decimal_str("0.000100").unwrap(), (1, 4));
+        assert_eq!(parse_decimal_str("2.000").unwrap(), (2, 0));
+        for invalid in ["", ".5", "5.", "-1", "1e5", "1.2.3", " 1", "NaN"] {
+            assert!(parse_decimal_str(invalid).is_err(), "{:?}", invalid);
+        }
+        assert!(parse_decimal_str(&"9".repeat(40)).is_err());
+
+        assert_eq!(atoms_to_decimal_str(12345, 3), "12.345");
+        assert_eq!(atoms_to_decimal_str(1_000_000, 6), "1");
+        assert_eq!(atoms_to_decimal_str(100, 6), "0.0001");
+        assert_eq!(atoms_to_decimal_str(0, 9), "0");
+        assert_eq!(atoms_to_decimal_str(42, 0), "42");
+    }
+
+    #[test]
+    fn test_mul_div_rounding() {
+        assert_eq!(mul_div(7, 1, 2, Rounding::Down).unwrap(), 3);
+        assert_eq!(mul_div(7, 1, 2, Rounding::Up).unwrap(), 4);
+        assert!(mul_div(7, 1, 2, Rounding::Exact).is_err());
+        assert_eq!(mul_div(8, 1, 2, Rounding::Exact).unwrap(), 4);
+        assert!(mul_div(u128::MAX, 2, 1, Rounding::Down).is_err());
+        assert!(mul_div(u64::MAX as u128 + 1, 1, 1, Rounding::Down).is_err());
+        assert!(mul_div(1, 1, 0, Rounding::Down).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use solana_sdk::compute_budget::{self, ComputeBudgetInstruction};
+use solana_sdk::instruction::Instruction;
+
+/// The most compute units a transaction may request.
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// A fingerprint of what `ixs` do, for caching their compute unit estimate: the program,
+/// accounts and instruction tag of each instruction, and the length of its data. Compute budget
+/// instructions are left out, so changing the priority fee keeps the fingerprint.
+///
+/// Arguments such as prices and sizes are not part of it. An order that fills against more
+/// resting orders uses more compute than one of the same shape that fills fewer, which the
+/// margin of the limit has to cover.
+pub fn instruction_shape(ixs: &[Instruction]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for ix in ixs
+        .iter()
+        .filter(|ix| ix.program_id != compute_budget::id())
+    {
+        ix.program_id.hash(&mut hasher);
+        for meta in &ix.accounts {
+            (meta.pubkey, meta.is_signer, meta.is_writable).hash(&mut hasher);
+        }
+        (ix.data.first(), ix.data.len()).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Whether `ixs` already set their own compute budget.
+pub fn has_compute_budget_instruction(ixs: &[Instruction]) -> bool {
+    ixs.iter().any(|ix| ix.program_id == compute_budget::id())
+}
+
+/// Prepends a compute unit limit of `units` to `ixs`.
+pub fn with_compute_unit_limit(ixs: Vec<Instruction>, units: u32) -> Vec<Instruction> {
+    std::iter::once(ComputeBudgetInstruction::set_compute_unit_limit(units))
+        .chain(ixs)
+        .collect()
+}
+
+/// The headroom added to a compute unit estimate when it is used as the transaction's limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComputeUnitMargin {
+    /// Added in proportion to the estimate, in basis points of it.
+    pub margin_bps: u64,
+    /// Added on top of the proportional margin.
+    pub extra_units: u64,
+}
+
+impl Default for ComputeUnitMargin {
+    fn default() -> Self {
+        Self {
+            margin_bps: 2_000,
+            extra_units: 5_000,
+        }
+    }
+}
+
+impl ComputeUnitMargin {
+    /// The limit to request for a transaction estimated to use `estimate` units, at most
+    /// `MAX_COMPUTE_UNIT_LIMIT`.
+    pub fn limit(&self, estimate: u64) -> u32 {
+        let margin = estimate.saturating_mul(self.margin_bps) / 10_000;
+        estimate
+            .saturating_add(margin)
+            .saturating_add(self.extra_units)
+            .min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+    }
+}
+
+/// Compute unit estimates by `instruction_shape`, keeping the `capacity` most recently added.
+#[derive(Debug, Clone)]
+pub struct ComputeUnitCache {
+    capacity: usize,
+    estimates: HashMap<u64, u64>,
+    order: VecDeque<u64>,
+}
+
+impl Default for ComputeUnitCache {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl ComputeUnitCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            estimates: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn get(&self, shape: u64) -> Option<u64> {
+        self.estimates.get(&shape).copied()
+    }
+
+    pub fn insert(&mut self, shape: u64, units: u64) {
+        if self.estimates.insert(shape, units).is_some() {
+            return;
+        }
+        self.order.push_back(shape);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.estimates.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.estimates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.estimates.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.estimates.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::instruction::AccountMeta;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn ix(program_id: Pubkey, account: Pubkey, data: Vec<u8>) -> Instruction {
+        Instruction::new_with_bytes(program_id, &data, vec![AccountMeta::new(account, true)])
+    }
+
+    #[test]
+    fn test_instruction_shape() {
+        let (program, account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let order = ix(program, account, vec![2, 0, 100]);
+        let shape = instruction_shape(std::slice::from_ref(&order));
+
+        // Arguments and compute budget instructions don't change the shape
+        assert_eq!(
+            instruction_shape(&[ix(program, account, vec![2, 0, 250])]),
+            shape
+        );
+        let priced = vec![
+            ComputeBudgetInstruction::set_compute_unit_price(1_000),
+            order.clone(),
+        ];
+        assert_eq!(instruction_shape(&priced), shape);
+        assert!(has_compute_budget_instruction(&priced));
+        assert!(!has_compute_budget_instruction(std::slice::from_ref(
+            &order
+        )));
+
+        // The instruction tag, accounts and number of instructions do
+        assert_ne!(
+            instruction_shape(&[ix(program, account, vec![3, 0, 100])]),
+            shape
+        );
+        assert_ne!(
+            instruction_shape(&[ix(program, Pubkey::new_unique(), vec![2, 0, 100])]),
+            shape
+        );
+        assert_ne!(instruction_shape(&[order.clone(), order]), shape);
+    }
+
+    #[test]
+    fn test_margin_and_limit() {
+        let margin = ComputeUnitMargin {
+            margin_bps: 1_000,
+            extra_units: 500,
+        };
+        assert_eq!(margin.limit(20_000), 22_500);
+        assert_eq!(margin.limit(1_300_000), MAX_COMPUTE_UNIT_LIMIT);
+        assert_eq!(margin.limit(u64::MAX), MAX_COMPUTE_UNIT_LIMIT);
+
+        let order = ix(Pubkey::new_unique(), Pubkey::new_unique(), vec![2]);
+        let ixs = with_compute_unit_limit(vec![order.clone()], 22_500);
+        assert_eq!(
+            ixs,
+            [
+                ComputeBudgetInstruction::set_compute_unit_limit(22_500),
+                order
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cache_evicts_oldest() {
+        let mut cache = ComputeUnitCache::new(2);
+        cache.insert(1, 10);
+        cache.insert(2, 20);
+        // Replacing an estimate doesn't make it newer
+        cache.insert(1, 11);
+        cache.insert(3, 30);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(1), None);
+        assert_eq!((cache.get(2), cache.get(3)), (Some(20), Some(30)));
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
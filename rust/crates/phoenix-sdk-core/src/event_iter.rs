@@ -0,0 +1,631 @@
+use std::fmt;
+
+use anyhow::{anyhow, bail, Result};
+use borsh::BorshDeserialize;
+use phoenix::program::PhoenixMarketEvent;
+use solana_sdk::pubkey::Pubkey;
+
+/// Length of the header that starts the data of each Phoenix `Log` instruction: the enum tag
+/// followed by the program's `AuditLogHeader`. This is the length of the current format,
+/// `AuditLogHeaderVersion::V1`.
+pub const AUDIT_LOG_HEADER_LEN: usize = 92;
+
+/// The format of an audit log header, given by its first byte.
+///
+/// The program writes the header as a `PhoenixMarketEvent::Header`, so the first byte is the
+/// variant's tag. A new header format would be logged under a new tag, which `parse` rejects
+/// until a variant is added here for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuditLogHeaderVersion {
+    V1,
+}
+
+impl AuditLogHeaderVersion {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(AuditLogHeaderVersion::V1),
+            _ => None,
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            AuditLogHeaderVersion::V1 => 1,
+        }
+    }
+
+    /// Serialized length of the header, including the version byte.
+    pub fn header_len(self) -> usize {
+        match self {
+            AuditLogHeaderVersion::V1 => AUDIT_LOG_HEADER_LEN,
+        }
+    }
+}
+
+/// The header of a Phoenix `Log` instruction, which says which transaction, market and signer
+/// its events belong to. Parsing it alone is enough to classify a log without reading the
+/// events, e.g. to keep only the markets an indexer follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditLogHeader {
+    pub version: AuditLogHeaderVersion,
+    /// The tag of the Phoenix instruction that logged the events.
+    pub instruction: u8,
+    pub sequence_number: u64,
+    /// The block time, in seconds since the Unix epoch.
+    pub timestamp: i64,
+    pub slot: u64,
+    pub market: Pubkey,
+    pub signer: Pubkey,
+    /// The number of events the header declares follow it.
+    pub total_events: u16,
+}
+
+impl AuditLogHeader {
+    /// Parses the header at the start of `data`, the data of a `Log` instruction without the
+    /// instruction tag, and returns it with the event bytes that follow it.
+    pub fn parse(data: &[u8]) -> Result<(Self, &[u8])> {
+        let Some(&version_byte) = data.first() else {
+            bail!("Log data is empty");
+        };
+        let version = AuditLogHeaderVersion::from_byte(version_byte).ok_or_else(|| {
+            match RawEventKind::from_tag(version_byte) {
+                Some(kind) => anyhow!("Expected a header event, got a {:?} event", kind),
+                None => anyhow!("Unsupported audit log header version {}", version_byte),
+            }
+        })?;
+        let len = version.header_len();
+        if data.len() < len {
+            bail!(
+                "Log data of {} bytes is shorter than the event header",
+                data.len()
+            );
+        }
+        let (header, rest) = data.split_at(len);
+        let header = match version {
+            AuditLogHeaderVersion::V1 => AuditLogHeader {
+                version,
+                instruction: header[1],
+                sequence_number: u64_at(header, 2),
+                timestamp: u64_at(header, 10) as i64,
+                slot: u64_at(header, 18),
+                market: pubkey_at(header, 26),
+                signer: pubkey_at(header, 58),
+                total_events: u16::from_le_bytes([header[90], header[91]]),
+            },
+        };
+        Ok((header, rest))
+    }
+}
+
+impl From<phoenix::program::AuditLogHeader> for AuditLogHeader {
+    fn from(header: phoenix::program::AuditLogHeader) -> Self {
+        Self {
+            version: AuditLogHeaderVersion::V1,
+            instruction: header.instruction,
+            sequence_number: header.sequence_number,
+            timestamp: header.timestamp,
+            slot: header.slot,
+            market: header.market,
+            signer: header.signer,
+            total_events: header.total_events,
+        }
+    }
+}
+
+/// The number of events in a `Log` instruction differs from the number its header declares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventCountMismatch {
+    pub declared: u16,
+    /// The events parsed before the data ended, or the whole events found in the data left
+    /// after the declared ones.
+    pub parsed: usize,
+}
+
+impl fmt::Display for EventCountMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The log header declares {} events but the data holds {}",
+            self.declared, self.parsed
+        )
+    }
+}
+
+impl std::error::Error for EventCountMismatch {}
+
+/// The borsh tag of each `PhoenixMarketEvent` variant, in declaration order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RawEventKind {
+    Uninitialized,
+    Header,
+    Fill,
+    Place,
+    Reduce,
+    Evict,
+    FillSummary,
+    Fee,
+    TimeInForce,
+    ExpiredOrder,
+}
+
+impl RawEventKind {
+    fn from_tag(tag: u8) -> Option<Self> {
+        Some(match tag {
+            0 => RawEventKind::Uninitialized,
+            1 => RawEventKind::Header,
+            2 => RawEventKind::Fill,
+            3 => RawEventKind::Place,
+            4 => RawEventKind::Reduce,
+            5 => RawEventKind::Evict,
+            6 => RawEventKind::FillSummary,
+            7 => RawEventKind::Fee,
+            8 => RawEventKind::TimeInForce,
+            9 => RawEventKind::ExpiredOrder,
+            _ => return None,
+        })
+    }
+
+    /// Serialized length of the variant's fields, which are all fixed-size.
+    fn payload_len(&self) -> usize {
+        match self {
+            RawEventKind::Uninitialized => 0,
+            RawEventKind::Header => AUDIT_LOG_HEADER_LEN - 1,
+            RawEventKind::Fill => 66,
+            RawEventKind::Place => 42,
+            RawEventKind::Reduce => 34,
+            RawEventKind::Evict => 58,
+            RawEventKind::FillSummary => 42,
+            RawEventKind::Fee => 10,
+            RawEventKind::TimeInForce => 26,
+            RawEventKind::ExpiredOrder => 58,
+        }
+    }
+}
+
+/// A serialized `PhoenixMarketEvent` borrowed from the log data, decoded field by field on access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketEventRef<'a> {
+    kind: RawEventKind,
+    /// The variant's fields, after the tag.
+    data: &'a [u8],
+    /// The tag followed by `data`, for decoding the whole event.
+    bytes: &'a [u8],
+}
+
+impl<'a> MarketEventRef<'a> {
+    pub fn kind(&self) -> RawEventKind {
+        self.kind
+    }
+
+    /// The event's index within its instruction. `None` for the variants without one.
+    pub fn index(&self) -> Option<u16> {
+        match self.kind {
+            RawEventKind::Uninitialized | RawEventKind::Header => None,
+            _ => Some(u16::from_le_bytes([self.data[0], self.data[1]])),
+        }
+    }
+
+    pub fn as_fill(&self) -> Option<FillRef<'a>> {
+        (self.kind == RawEventKind::Fill).then_some(FillRef(self.data))
+    }
+
+    pub fn as_place(&self) -> Option<PlaceRef<'a>> {
+        (self.kind == RawEventKind::Place).then_some(PlaceRef(self.data))
+    }
+
+    /// Decodes the whole event.
+    pub fn to_event(&self) -> Result<PhoenixMarketEvent> {
+        Ok(PhoenixMarketEvent::try_from_slice(self.bytes)?)
+    }
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn u128_at(data: &[u8], offset: usize) -> u128 {
+    u128::from_le_bytes(data[offset..offset + 16].try_into().unwrap())
+}
+
+fn pubkey_at(data: &[u8], offset: usize) -> Pubkey {
+    Pubkey::new_from_array(data[offset..offset + 32].try_into().unwrap())
+}
+
+/// The fields of a borrowed `FillEvent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FillRef<'a>(&'a [u8]);
+
+impl FillRef<'_> {
+    pub fn maker_id(&self) -> Pubkey {
+        pubkey_at(self.0, 2)
+    }
+
+    pub fn order_sequence_number(&self) -> u64 {
+        u64_at(self.0, 34)
+    }
+
+    pub fn price_in_ticks(&self) -> u64 {
+        u64_at(self.0, 42)
+    }
+
+    pub fn base_lots_filled(&self) -> u64 {
+        u64_at(self.0, 50)
+    }
+
+    pub fn base_lots_remaining(&self) -> u64 {
+        u64_at(self.0, 58)
+    }
+}
+
+/// The fields of a borrowed `PlaceEvent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaceRef<'a>(&'a [u8]);
+
+impl PlaceRef<'_> {
+    pub fn order_sequence_number(&self) -> u64 {
+        u64_at(self.0, 2)
+    }
+
+    pub fn client_order_id(&self) -> u128 {
+        u128_at(self.0, 10)
+    }
+
+    pub fn price_in_ticks(&self) -> u64 {
+        u64_at(self.0, 26)
+    }
+
+    pub fn base_lots_placed(&self) -> u64 {
+        u64_at(self.0, 34)
+    }
+}
+
+/// Iterates over the events logged by one Phoenix `Log` instruction without copying them.
+///
+/// `new` parses the header; each call to `next` reads one event at a cursor into the data and
+/// yields a view of it, so events that are skipped are never decoded. Yields an error and stops
+/// if the data is malformed, an `EventCountMismatch` if it holds fewer or more events than the
+/// header's `total_events`.
+///
+/// ```
+/// # use phoenix_sdk_core::event_iter::WrapperEventIterator;
+/// # fn fills(log_data: &[u8]) -> anyhow::Result<u64> {
+/// let mut base_lots_filled = 0;
+/// for event in WrapperEventIterator::new(log_data)? {
+///     if let Some(fill) = event?.as_fill() {
+///         base_lots_filled += fill.base_lots_filled();
+///     }
+/// }
+/// # Ok(base_lots_filled)
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct WrapperEventIterator<'a> {
+    header: AuditLogHeader,
+    data_len: usize,
+    remaining: &'a [u8],
+    events_left: u16,
+    failed: bool,
+}
+
+impl<'a> WrapperEventIterator<'a> {
+    /// `data` is the data of the `Log` instruction, without the instruction tag.
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        let (header, remaining) = AuditLogHeader::parse(data)?;
+        Ok(Self {
+            header,
+            data_len: data.len(),
+            remaining,
+            events_left: header.total_events,
+            failed: false,
+        })
+    }
+
+    pub fn header(&self) -> &AuditLogHeader {
+        &self.header
+    }
+
+    /// The bytes of the data read so far, the header included. After an error this is the
+    /// offset of the event that could not be read.
+    pub fn bytes_consumed(&self) -> usize {
+        self.data_len - self.remaining.len()
+    }
+
+    fn read_event(&mut self) -> Result<MarketEventRef<'a>> {
+        let Some((&tag, rest)) = self.remaining.split_first() else {
+            let declared = self.header.total_events;
+            return Err(EventCountMismatch {
+                declared,
+                parsed: (declared - self.events_left - 1) as usize,
+            }
+            .into());
+        };
+        let kind =
+            RawEventKind::from_tag(tag).ok_or_else(|| anyhow!("Unknown event tag {}", tag))?;
+        let len = kind.payload_len();
+        if rest.len() < len {
+            bail!("Log data ended inside a {:?} event", kind);
+        }
+        let event = MarketEventRef {
+            kind,
+            data: &rest[..len],
+            bytes: &self.remaining[..len + 1],
+        };
+        self.remaining = &rest[len..];
+        Ok(event)
+    }
+}
+
+/// The number of events `data` holds, if it is made of whole events.
+fn count_whole_events(mut data: &[u8]) -> Option<usize> {
+    let mut count = 0;
+    while let Some((&tag, rest)) = data.split_first() {
+        let len = RawEventKind::from_tag(tag)?.payload_len();
+        data = rest.get(len..)?;
+        count += 1;
+    }
+    Some(count)
+}
+
+impl<'a> Iterator for WrapperEventIterator<'a> {
+    type Item = Result<MarketEventRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        if self.events_left == 0 {
+            if self.remaining.is_empty() {
+                return None;
+            }
+            self.failed = true;
+            return Some(Err(match count_whole_events(self.remaining) {
+                Some(extra) => EventCountMismatch {
+                    declared: self.header.total_events,
+                    parsed: self.header.total_events as usize + extra,
+                }
+                .into(),
+                None => anyhow!("{} bytes left after the last event", self.remaining.len()),
+            }));
+        }
+        self.events_left -= 1;
+        let event = self.read_event();
+        self.failed = event.is_err();
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use borsh::BorshSerialize;
+    use phoenix::program::{
+        AuditLogHeader as ProgramAuditLogHeader, FillEvent, PlaceEvent, ReduceEvent,
+    };
+
+    use super::*;
+
+    /// Serializes `events` as the data of one `Log` instruction, without the instruction tag.
+    pub(crate) fn log_data(sequence_number: u64, events: &[PhoenixMarketEvent]) -> Vec<u8> {
+        let header = PhoenixMarketEvent::Header(ProgramAuditLogHeader {
+            instruction: 2,
+            sequence_number,
+            timestamp: 1_700_000_000,
+            slot: 100,
+            market: Pubkey::new_unique(),
+            signer: Pubkey::new_unique(),
+            total_events: events.len() as u16,
+        });
+        let mut data = header.try_to_vec().unwrap();
+        for event in events {
+            data.extend(event.try_to_vec().unwrap());
+        }
+        data
+    }
+
+    pub(crate) fn sample_events(count: u16) -> Vec<PhoenixMarketEvent> {
+        (0..count)
+            .map(|index| match index % 3 {
+                0 => PhoenixMarketEvent::Fill(FillEvent {
+                    index,
+                    maker_id: Pubkey::new_unique(),
+                    order_sequence_number: index as u64,
+                    price_in_ticks: 1000 + index as u64,
+                    base_lots_filled: 10,
+                    base_lots_remaining: 5,
+                }),
+                1 => PhoenixMarketEvent::Place(PlaceEvent {
+                    index,
+                    order_sequence_number: !(index as u64),
+                    client_order_id: u128::MAX - index as u128,
+                    price_in_ticks: 999,
+                    base_lots_placed: 20,
+                }),
+                _ => PhoenixMarketEvent::Reduce(ReduceEvent {
+                    index,
+                    order_sequence_number: 7,
+                    price_in_ticks: 998,
+                    base_lots_removed: 1,
+                    base_lots_remaining: 0,
+                }),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_payload_lengths_match_borsh() {
+        let events = sample_events(3);
+        let expected = [
+            (RawEventKind::Fill, 66),
+            (RawEventKind::Place, 42),
+            (RawEventKind::Reduce, 34),
+        ];
+        for (event, (kind, len)) in events.iter().zip(expected) {
+            assert_eq!(event.try_to_vec().unwrap().len(), len + 1);
+            assert_eq!(kind.payload_len(), len);
+        }
+        assert_eq!(AUDIT_LOG_HEADER_LEN, log_data(0, &[]).len());
+    }
+
+    #[test]
+    fn test_views_match_decoded_events() {
+        let events = sample_events(30);
+        let data = log_data(42, &events);
+        let iter = WrapperEventIterator::new(&data).unwrap();
+        assert_eq!(iter.header().sequence_number, 42);
+        let views = iter.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(views.len(), events.len());
+        for (view, event) in views.iter().zip(events.iter()) {
+            assert_eq!(
+                view.to_event().unwrap().try_to_vec().unwrap(),
+                event.try_to_vec().unwrap()
+            );
+            match event {
+                PhoenixMarketEvent::Fill(fill) => {
+                    let view = view.as_fill().unwrap();
+                    assert_eq!(view.maker_id(), fill.maker_id);
+                    assert_eq!(view.order_sequence_number(), fill.order_sequence_number);
+                    assert_eq!(view.price_in_ticks(), fill.price_in_ticks);
+                    assert_eq!(view.base_lots_filled(), fill.base_lots_filled);
+                    assert_eq!(view.base_lots_remaining(), fill.base_lots_remaining);
+                }
+                PhoenixMarketEvent::Place(place) => {
+                    let view = view.as_place().unwrap();
+                    assert_eq!(view.order_sequence_number(), place.order_sequence_number);
+                    assert_eq!(view.client_order_id(), place.client_order_id);
+                    assert_eq!(view.price_in_ticks(), place.price_in_ticks);
+                    assert_eq!(view.base_lots_placed(), place.base_lots_placed);
+                }
+                _ => assert!(view.as_fill().is_none() && view.as_place().is_none()),
+            }
+        }
+        assert_eq!(views[4].index(), Some(4));
+    }
+
+    #[test]
+    fn test_malformed_data() {
+        let data = log_data(1, &sample_events(2));
+        assert!(WrapperEventIterator::new(&data[..50]).is_err());
+        assert!(WrapperEventIterator::new(&data[AUDIT_LOG_HEADER_LEN..]).is_err());
+
+        // Truncated inside the second event: one event, then an error, then nothing
+        let mut iter = WrapperEventIterator::new(&data[..data.len() - 1]).unwrap();
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+
+        let mut trailing = data.clone();
+        trailing.push(0);
+        let results = WrapperEventIterator::new(&trailing)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(results.len(), 3);
+        assert!(results[2].is_err());
+
+        let mut unknown_tag = data.clone();
+        unknown_tag[AUDIT_LOG_HEADER_LEN] = 42;
+        assert!(WrapperEventIterator::new(&unknown_tag)
+            .unwrap()
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_header_alone() {
+        let events = sample_events(3);
+        let data = log_data(7, &events);
+        let (header, rest) = AuditLogHeader::parse(&data).unwrap();
+        let PhoenixMarketEvent::Header(expected) =
+            PhoenixMarketEvent::try_from_slice(&data[..AUDIT_LOG_HEADER_LEN]).unwrap()
+        else {
+            unreachable!()
+        };
+        assert_eq!(header, AuditLogHeader::from(expected));
+        assert_eq!(header.version, AuditLogHeaderVersion::V1);
+        assert_eq!(header.total_events, 3);
+        assert_eq!(rest, &data[AUDIT_LOG_HEADER_LEN..]);
+
+        // Another event where the header should be, and a format this version does not know
+        let error = AuditLogHeader::parse(&data[AUDIT_LOG_HEADER_LEN..]).unwrap_err();
+        assert!(error.to_string().contains("Expected a header event"));
+        let mut future = data;
+        future[0] = 200;
+        let error = AuditLogHeader::parse(&future).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unsupported audit log header version 200"
+        );
+    }
+
+    #[test]
+    fn test_lenient_parse_keeps_events_before_corrupt_record() {
+        let core = crate::sdk_client_core::SDKClientCore {
+            markets: Default::default(),
+            trader: Pubkey::new_unique(),
+            default_self_trade_behavior: Default::default(),
+        };
+        let sig = solana_sdk::signature::Signature::new_unique();
+        let events = sample_events(3);
+        let first = log_data(1, &events);
+        // The second log's last event is cut off mid-record
+        let mut truncated = log_data(2, &events);
+        truncated.truncate(truncated.len() - 5);
+        let after = log_data(3, &events);
+
+        let logs = vec![first.clone(), truncated.clone(), after];
+        assert!(core.parse_raw_phoenix_events(&sig, logs.clone()).is_none());
+        let partial = core
+            .parse_raw_phoenix_events_lenient(&sig, logs)
+            .unwrap_err();
+        assert_eq!(partial.events.len(), 2);
+        assert_eq!(partial.events[0].batch.len(), 3);
+        assert_eq!(partial.events[1].header.sequence_number, 2);
+        assert_eq!(partial.events[1].batch.len(), 2);
+        assert_eq!(partial.error.log_index, 1);
+        assert!(partial
+            .error
+            .to_string()
+            .contains("Log data ended inside a Reduce event"));
+        let mut iter = WrapperEventIterator::new(&truncated).unwrap();
+        iter.by_ref().take(2).for_each(drop);
+        assert_eq!(partial.bytes_consumed, iter.bytes_consumed());
+
+        // A header that cannot be parsed keeps only the earlier logs
+        let partial = core
+            .parse_raw_phoenix_events_lenient(&sig, vec![first, vec![]])
+            .unwrap_err();
+        assert_eq!(partial.events.len(), 1);
+        assert_eq!(partial.bytes_consumed, 0);
+    }
+
+    #[test]
+    fn test_event_count_mismatch() {
+        let events = sample_events(3);
+        let mismatch = |data: &[u8]| {
+            WrapperEventIterator::new(data)
+                .unwrap()
+                .find_map(Result::err)
+                .unwrap()
+                .downcast::<EventCountMismatch>()
+                .unwrap()
+        };
+        // The header declares 3 events; the data holds 2, then 4
+        let mut fewer = log_data(1, &events[..2]);
+        fewer[AUDIT_LOG_HEADER_LEN - 2] = 3;
+        assert_eq!(
+            mismatch(&fewer),
+            EventCountMismatch {
+                declared: 3,
+                parsed: 2
+            }
+        );
+        let mut more = log_data(1, &sample_events(4));
+        more[AUDIT_LOG_HEADER_LEN - 2] = 3;
+        assert_eq!(
+            mismatch(&more),
+            EventCountMismatch {
+                declared: 3,
+                parsed: 4
+            }
+        );
+    }
+}
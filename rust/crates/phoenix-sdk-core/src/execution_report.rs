@@ -0,0 +1,295 @@
+use phoenix::state::enums::Side;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::market_event::{FillSummary, MarketEventDetails, PhoenixEvent};
+use crate::sdk_client_core::MarketMetadata;
+
+/// The part of an order that executed at one price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelExecution {
+    pub price_in_ticks: u64,
+    pub base_lots: u64,
+    pub quote_atoms: u64,
+}
+
+/// What a taking order actually did, built from its `FillSummary` and constituent fills.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionReport {
+    pub market: Pubkey,
+    pub signature: Signature,
+    pub client_order_id: u128,
+    /// The taker's side, or `None` if the order matched nothing.
+    pub side: Option<Side>,
+    pub filled_base_atoms: u64,
+    /// Quote filled, excluding fees.
+    pub filled_quote_atoms: u64,
+    pub fee_quote_atoms: u64,
+    /// Quote paid for a buy or received for a sell, i.e. the filled quote plus or minus fees.
+    pub net_quote_atoms: u64,
+    /// Average price in quote units per raw base unit, excluding fees.
+    pub average_price: Option<f64>,
+    pub average_price_in_ticks: Option<f64>,
+    /// Average price in ticks including fees, i.e. from `net_quote_atoms`.
+    pub net_average_price_in_ticks: Option<f64>,
+    /// Executed size per price, in the order the levels were taken.
+    pub levels: Vec<LevelExecution>,
+    /// How much worse than the reference price the average price was, in basis points. Negative
+    /// when the order did better than the reference, e.g. by taking liquidity inside it.
+    pub slippage_bps: Option<f64>,
+    /// Set when the fills among the events do not account for the summary's totals. Totals and
+    /// prices then come from the summary alone and `levels` is incomplete.
+    pub is_partial: bool,
+}
+
+impl ExecutionReport {
+    /// Builds the report for the first order among `events` with `client_order_id`. Fills are
+    /// matched to its `FillSummary` by transaction and instruction, since fills carry no client
+    /// order id. Returns `None` if no summary for the order is present.
+    pub fn from_events<E: AsRef<PhoenixEvent>>(
+        events: &[E],
+        client_order_id: u128,
+        reference_price_ticks: Option<u64>,
+        meta: &MarketMetadata,
+    ) -> Option<Self> {
+        let events = || events.iter().map(AsRef::as_ref);
+        let (summary_event, summary) = events().find_map(|event| match event.details {
+            MarketEventDetails::FillSummary(summary)
+                if summary.client_order_id == client_order_id =>
+            {
+                Some((event, summary))
+            }
+            _ => None,
+        })?;
+        let FillSummary {
+            total_base_filled,
+            total_quote_filled_including_fees,
+            total_quote_fees,
+            trade_direction,
+            ..
+        } = summary;
+
+        let mut levels: Vec<LevelExecution> = vec![];
+        for event in events().filter(|event| {
+            event.signature == summary_event.signature
+                && event.market == summary_event.market
+                && event.sequence_number == summary_event.sequence_number
+        }) {
+            let MarketEventDetails::Fill(fill) = event.details else {
+                continue;
+            };
+            // A fill whose quote atoms overflow is left out, which marks the report partial
+            let Some(quote_atoms) = meta.checked_base_lots_and_price_to_quote_atoms(
+                fill.base_lots_filled,
+                fill.price_in_ticks,
+            ) else {
+                continue;
+            };
+            match levels.last_mut() {
+                Some(level) if level.price_in_ticks == fill.price_in_ticks => {
+                    level.base_lots += fill.base_lots_filled;
+                    level.quote_atoms += quote_atoms;
+                }
+                _ => levels.push(LevelExecution {
+                    price_in_ticks: fill.price_in_ticks,
+                    base_lots: fill.base_lots_filled,
+                    quote_atoms,
+                }),
+            }
+        }
+        let fills_base_atoms = levels
+            .iter()
+            .map(|level| meta.checked_base_lots_to_base_atoms(level.base_lots))
+            .try_fold(0u64, |sum, base_atoms| sum.checked_add(base_atoms?));
+
+        let side = match trade_direction {
+            1 => Some(Side::Bid),
+            -1 => Some(Side::Ask),
+            _ => None,
+        };
+        // The program adds the fee to a buy's quote total and subtracts it from a sell's
+        let net_quote_atoms = total_quote_filled_including_fees;
+        let filled_quote_atoms = match side {
+            Some(Side::Ask) => net_quote_atoms + total_quote_fees,
+            _ => net_quote_atoms.saturating_sub(total_quote_fees),
+        };
+        let (average_price, average_price_in_ticks, net_average_price_in_ticks) =
+            if total_base_filled > 0 {
+                let base_units = meta.base_atoms_to_raw_base_units_as_float(total_base_filled);
+                let base_lots = total_base_filled as f64 / meta.base_atoms_per_base_lot as f64;
+                let to_ticks = |quote_atoms: u64| {
+                    quote_atoms as f64 * meta.num_base_lots_per_base_unit as f64
+                        / (base_lots * meta.tick_size_in_quote_atoms_per_base_unit as f64)
+                };
+                (
+                    Some(meta.quote_atoms_to_quote_units_as_float(filled_quote_atoms) / base_units),
+                    Some(to_ticks(filled_quote_atoms)),
+                    Some(to_ticks(net_quote_atoms)),
+                )
+            } else {
+                (None, None, None)
+            };
+        let slippage_bps = match (side, average_price_in_ticks, reference_price_ticks) {
+            (Some(side), Some(average), Some(reference)) if reference > 0 => {
+                let reference = reference as f64;
+                let worse_by = match side {
+                    Side::Bid => average - reference,
+                    Side::Ask => reference - average,
+                };
+                Some(worse_by / reference * 10_000.0)
+            }
+            _ => None,
+        };
+
+        Some(Self {
+            market: summary_event.market,
+            signature: summary_event.signature,
+            client_order_id,
+            side,
+            filled_base_atoms: total_base_filled,
+            filled_quote_atoms,
+            fee_quote_atoms: total_quote_fees,
+            net_quote_atoms,
+            average_price,
+            average_price_in_ticks,
+            net_average_price_in_ticks,
+            levels,
+            slippage_bps,
+            is_partial: fills_base_atoms != Some(total_base_filled),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::market_event::Fill;
+    use crate::test_utils::{event, sol_usdc_metadata};
+
+    fn order_events(side: Side, client_order_id: u128) -> Vec<PhoenixEvent> {
+        let signature = Signature::new_unique();
+        let order_event = |event_index, details| PhoenixEvent {
+            sequence_number: 1,
+            signature,
+            event_index,
+            ..event(Pubkey::default(), details)
+        };
+        let (maker, taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // Buys take asks 101, 101, 102; sells take bids 99, 98
+        let fills = match side {
+            Side::Bid => vec![
+                Fill::new(1, maker, taker, 101, 100, 0),
+                Fill::new(2, maker, taker, 101, 100, 0),
+                Fill::new(3, maker, taker, 102, 200, 0),
+            ],
+            Side::Ask => vec![
+                Fill::new(!1, maker, taker, 99, 300, 0),
+                Fill::new(!2, maker, taker, 98, 100, 0),
+            ],
+        };
+        let meta = sol_usdc_metadata();
+        let base_lots = fills.iter().map(|fill| fill.base_lots_filled).sum::<u64>();
+        let quote_atoms = fills
+            .iter()
+            .map(|fill| {
+                meta.base_lots_and_price_to_quote_atoms(fill.base_lots_filled, fill.price_in_ticks)
+            })
+            .sum::<u64>();
+        let mut events = fills
+            .into_iter()
+            .enumerate()
+            .map(|(i, fill)| order_event(i as u64, MarketEventDetails::Fill(fill)))
+            .collect::<Vec<_>>();
+        events.push(order_event(
+            events.len() as u64,
+            MarketEventDetails::FillSummary(FillSummary {
+                client_order_id,
+                total_base_filled: base_lots * meta.base_atoms_per_base_lot,
+                total_quote_filled_including_fees: match side {
+                    Side::Bid => quote_atoms + 50,
+                    Side::Ask => quote_atoms - 50,
+                },
+                total_quote_fees: 50,
+                trade_direction: match side {
+                    Side::Bid => 1,
+                    Side::Ask => -1,
+                },
+            }),
+        ));
+        events
+    }
+
+    #[test]
+    fn test_buy_across_levels() {
+        let meta = sol_usdc_metadata();
+        let mut events = order_events(Side::Bid, 7);
+        // Another order in the same slice is ignored
+        events.extend(order_events(Side::Ask, 8));
+        let report = ExecutionReport::from_events(&events, 7, Some(100), &meta).unwrap();
+
+        assert_eq!(report.side, Some(Side::Bid));
+        assert!(!report.is_partial);
+        assert_eq!(
+            report.levels,
+            vec![
+                LevelExecution {
+                    price_in_ticks: 101,
+                    base_lots: 200,
+                    quote_atoms: 20_200,
+                },
+                LevelExecution {
+                    price_in_ticks: 102,
+                    base_lots: 200,
+                    quote_atoms: 20_400,
+                },
+            ]
+        );
+        assert_eq!(report.filled_base_atoms, 400_000_000);
+        assert_eq!(report.filled_quote_atoms, 40_600);
+        assert_eq!(report.fee_quote_atoms, 50);
+        assert_eq!(report.net_quote_atoms, 40_650);
+        assert_eq!(report.average_price_in_ticks, Some(101.5));
+        assert_eq!(report.net_average_price_in_ticks, Some(101.625));
+        assert!((report.average_price.unwrap() - 0.1015).abs() < 1e-12);
+        assert!((report.slippage_bps.unwrap() - 150.0).abs() < 1e-9);
+
+        assert!(ExecutionReport::from_events(&events, 9, None, &meta).is_none());
+    }
+
+    #[test]
+    fn test_sell_and_summary_without_fills() {
+        let meta = sol_usdc_metadata();
+        let events = order_events(Side::Ask, 8);
+        let report = ExecutionReport::from_events(&events, 8, Some(100), &meta).unwrap();
+        assert_eq!(report.side, Some(Side::Ask));
+        assert_eq!(report.average_price_in_ticks, Some(98.75));
+        assert_eq!(report.filled_quote_atoms, 39_500);
+        assert_eq!(report.net_quote_atoms, 39_500 - 50);
+        assert_eq!(report.net_average_price_in_ticks, Some(98.625));
+        assert!((report.slippage_bps.unwrap() - 125.0).abs() < 1e-9);
+
+        // Only the summary arrived: totals still come through, flagged as partial
+        let summary_only = &events[events.len() - 1..];
+        let partial = ExecutionReport::from_events(summary_only, 8, None, &meta).unwrap();
+        assert!(partial.is_partial);
+        assert!(partial.levels.is_empty());
+        assert_eq!(partial.filled_base_atoms, report.filled_base_atoms);
+        assert_eq!(partial.average_price_in_ticks, Some(98.75));
+        assert_eq!(partial.slippage_bps, None);
+    }
+
+    #[test]
+    fn test_fill_overflowing_quote_atoms() {
+        let meta = sol_usdc_metadata();
+        let mut events = order_events(Side::Bid, 7);
+        let MarketEventDetails::Fill(fill) = &mut events[2].details else {
+            unreachable!()
+        };
+        fill.price_in_ticks = u64::MAX;
+        fill.base_lots_filled = u64::MAX;
+        let report = ExecutionReport::from_events(&events, 7, None, &meta).unwrap();
+        assert!(report.is_partial);
+        assert_eq!(report.levels.len(), 1);
+        assert_eq!(report.levels[0].quote_atoms, 20_200);
+    }
+}
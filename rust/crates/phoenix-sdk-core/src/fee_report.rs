@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::market_event::{Fill, FillSummary, MarketEventDetails, PhoenixEvent};
+use crate::sdk_client_core::MarketMetadata;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Fees and volume over some set of fills. All quote amounts are in quote atoms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeeTotals {
+    pub taker_fees_quote_atoms: u64,
+    /// Notional of taken liquidity, excluding fees.
+    pub taker_volume_quote_atoms: u64,
+    pub taker_orders: u64,
+    /// Notional of the trader's resting orders that were filled. Makers pay no fee.
+    pub maker_volume_quote_atoms: u64,
+    pub maker_fills: u64,
+}
+
+impl FeeTotals {
+    fn add(&mut self, other: &FeeTotals) {
+        self.taker_fees_quote_atoms += other.taker_fees_quote_atoms;
+        self.taker_volume_quote_atoms += other.taker_volume_quote_atoms;
+        self.taker_orders += other.taker_orders;
+        self.maker_volume_quote_atoms += other.maker_volume_quote_atoms;
+        self.maker_fills += other.maker_fills;
+    }
+}
+
+/// Aggregates a trader's fees by market and UTC day from their fill history.
+///
+/// Taker fees come from the `FillSummary` of each of the trader's orders, which carries the fee
+/// the program actually charged. Maker volume comes from the fills against the trader's resting
+/// orders. Days are counted from the Unix epoch, so day 19723 is 2024-01-01.
+#[derive(Debug, Clone)]
+pub struct FeeReport {
+    pub trader: Pubkey,
+    totals: BTreeMap<(Pubkey, i64), FeeTotals>,
+}
+
+impl FeeReport {
+    pub fn new(trader: Pubkey) -> Self {
+        Self {
+            trader,
+            totals: BTreeMap::new(),
+        }
+    }
+
+    /// Builds the report from `events`, looking up each market's metadata in `markets`. Events
+    /// on markets missing from `markets` are skipped.
+    pub fn from_events<'a>(
+        trader: Pubkey,
+        events: impl IntoIterator<Item = &'a PhoenixEvent>,
+        markets: &BTreeMap<Pubkey, MarketMetadata>,
+    ) -> Self {
+        let mut report = Self::new(trader);
+        for event in events {
+            if let Some(meta) = markets.get(&event.market) {
+                report.apply_event(event, meta);
+            }
+        }
+        report
+    }
+
+    /// Applies a parsed event, returning true if it was a fill or fill summary of the trader.
+    pub fn apply_event(&mut self, event: &PhoenixEvent, meta: &MarketMetadata) -> bool {
+        let day = event.timestamp_seconds().div_euclid(SECONDS_PER_DAY);
+        match event.details {
+            MarketEventDetails::Fill(Fill {
+                maker,
+                price_in_ticks,
+                base_lots_filled,
+                ..
+            }) if maker == self.trader => {
+                let totals = self.totals.entry((event.market, day)).or_default();
+                totals.maker_volume_quote_atoms +=
+                    meta.base_lots_and_price_to_quote_atoms(base_lots_filled, price_in_ticks);
+                totals.maker_fills += 1;
+                true
+            }
+            MarketEventDetails::FillSummary(FillSummary {
+                total_quote_filled_including_fees,
+                total_quote_fees,
+                trade_direction,
+                ..
+            }) if event.signer == self.trader && trade_direction != 0 => {
+                let totals = self.totals.entry((event.market, day)).or_default();
+                totals.taker_fees_quote_atoms += total_quote_fees;
+                // Fees are added to a buy's quote total and subtracted from a sell's
+                totals.taker_volume_quote_atoms += if trade_direction < 0 {
+                    total_quote_filled_including_fees + total_quote_fees
+                } else {
+                    total_quote_filled_including_fees.saturating_sub(total_quote_fees)
+                };
+                totals.taker_orders += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Totals for one market on one day.
+    pub fn get(&self, market: &Pubkey, day: i64) -> FeeTotals {
+        self.totals
+            .get(&(*market, day))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn total(&self) -> FeeTotals {
+        let mut total = FeeTotals::default();
+        for totals in self.totals.values() {
+            total.add(totals);
+        }
+        total
+    }
+
+    pub fn by_market(&self) -> BTreeMap<Pubkey, FeeTotals> {
+        let mut by_market = BTreeMap::<Pubkey, FeeTotals>::new();
+        for ((market, _), totals) in &self.totals {
+            by_market.entry(*market).or_default().add(totals);
+        }
+        by_market
+    }
+
+    pub fn by_day(&self) -> BTreeMap<i64, FeeTotals> {
+        let mut by_day = BTreeMap::<i64, FeeTotals>::new();
+        for ((_, day), totals) in &self.totals {
+            by_day.entry(*day).or_default().add(totals);
+        }
+        by_day
+    }
+
+    /// Totals per market and day, ordered by market and then day.
+    pub fn iter(&self) -> impl Iterator<Item = (&(Pubkey, i64), &FeeTotals)> {
+        self.totals.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix::state::enums::Side;
+
+    use super::*;
+    use crate::test_utils::event;
+
+    fn meta(quote_atoms_per_quote_lot: u64, taker_fee_bps: u64) -> MarketMetadata {
+        MarketMetadata {
+            quote_atoms_per_quote_lot,
+            tick_size_in_quote_atoms_per_base_unit: 1000,
+            num_base_lots_per_base_unit: 1000,
+            taker_fee_bps,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fee_for_notional() {
+        let meta = meta(1, 10);
+        // 10 bps of 1 quote unit
+        assert_eq!(meta.fee_for_notional(1_000_000, false), 1_000);
+        // Fractions of an atom round up, against the taker
+        assert_eq!(meta.fee_for_notional(1_000_001, false), 1_001);
+        assert_eq!(meta.fee_for_notional(999_999, false), 1_000);
+        assert_eq!(meta.fee_for_notional(1, false), 1);
+        assert_eq!(meta.fee_for_notional(0, false), 0);
+        assert_eq!(meta.fee_for_notional(1_000_000, true), 0);
+        assert_eq!(
+            meta.fee_for_notional(u64::MAX, false),
+            (u64::MAX as u128 * 10).div_ceil(10_000) as u64
+        );
+
+        // And then up to a whole quote lot
+        let meta = self::meta(100, 10);
+        assert_eq!(meta.fee_for_notional(1_000_000, false), 1_000);
+        assert_eq!(meta.fee_for_notional(1_000_100, false), 1_100);
+        assert_eq!(meta.fee_for_notional(100, false), 100);
+        assert_eq!(self::meta(100, 0).fee_for_notional(1_000_000, false), 0);
+
+        // 2 bps, the fee on most Phoenix markets
+        let meta = self::meta(1, 2);
+        assert_eq!(meta.fee_for_notional(12_345_678, false), 2_470);
+        assert_eq!(meta.fee_for_notional(5_000, false), 1);
+        assert_eq!(meta.fee_for_notional(5_001, false), 2);
+    }
+
+    #[test]
+    fn test_fee_report() {
+        let trader = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let (sol, eth) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let markets = BTreeMap::from([(sol, meta(1, 2)), (eth, meta(1, 2))]);
+        let day = 19_723;
+        let signed = |market, timestamp, signer, details| PhoenixEvent {
+            timestamp,
+            signer,
+            ..event(market, details)
+        };
+        // As the program reports them: buyers pay the fee on top, sellers receive less
+        let summary = |quote_atoms, fees, trade_direction| {
+            MarketEventDetails::FillSummary(FillSummary {
+                client_order_id: 0,
+                total_base_filled: 0,
+                total_quote_filled_including_fees: if trade_direction < 0 {
+                    quote_atoms - fees
+                } else {
+                    quote_atoms + fees
+                },
+                total_quote_fees: fees,
+                trade_direction,
+            })
+        };
+        let maker_fill = |maker| {
+            MarketEventDetails::Fill(Fill {
+                order_sequence_number: 1,
+                maker,
+                taker: other,
+                price_in_ticks: 100,
+                base_lots_filled: 500,
+                base_lots_remaining: 0,
+                side_filled: Side::Ask,
+                maker_side: Side::Ask,
+                taker_side: Side::Bid,
+                is_full_fill: true,
+            })
+        };
+        let start = day * SECONDS_PER_DAY;
+        let events = [
+            signed(sol, start, trader, summary(1_000_000, 200, 1)),
+            signed(sol, start + 3_600, trader, summary(2_000_000, 400, -1)),
+            signed(
+                sol,
+                start + SECONDS_PER_DAY,
+                trader,
+                summary(500_000, 100, 1),
+            ),
+            signed(eth, start + 60, trader, summary(3_000_000, 600, -1)),
+            signed(sol, start + 60, other, maker_fill(trader)),
+            // Another trader's order and a fill against someone else
+            signed(sol, start, other, summary(1_000_000, 200, 1)),
+            signed(sol, start, trader, maker_fill(other)),
+            // A market without metadata
+            signed(Pubkey::new_unique(), start, trader, summary(1, 1, 1)),
+        ];
+        let report = FeeReport::from_events(trader, &events, &markets);
+
+        assert_eq!(
+            report.get(&sol, day),
+            FeeTotals {
+                taker_fees_quote_atoms: 600,
+                taker_volume_quote_atoms: 3_000_000,
+                taker_orders: 2,
+                maker_volume_quote_atoms: 50_000,
+                maker_fills: 1,
+            }
+        );
+        assert_eq!(report.get(&sol, day + 1).taker_fees_quote_atoms, 100);
+        assert_eq!(report.get(&eth, day - 1), FeeTotals::default());
+        assert_eq!(report.by_market()[&sol].taker_fees_quote_atoms, 700);
+        assert_eq!(report.by_market()[&eth].taker_fees_quote_atoms, 600);
+        assert_eq!(report.by_day()[&day].taker_fees_quote_atoms, 1_200);
+        assert_eq!(report.by_day()[&(day + 1)].taker_orders, 1);
+        let total = report.total();
+        assert_eq!(total.taker_fees_quote_atoms, 1_300);
+        assert_eq!(total.taker_volume_quote_atoms, 6_500_000);
+        assert_eq!(report.iter().count(), 3);
+
+        // The fees charged match 2 bps of each order's notional
+        for event in &events[..4] {
+            let MarketEventDetails::FillSummary(summary) = event.details else {
+                unreachable!()
+            };
+            let notional = if summary.trade_direction < 0 {
+                summary.total_quote_filled_including_fees + summary.total_quote_fees
+            } else {
+                summary.total_quote_filled_including_fees - summary.total_quote_fees
+            };
+            assert_eq!(
+                markets[&event.market].fee_for_notional(notional, false),
+                summary.total_quote_fees
+            );
+        }
+    }
+}
@@ -0,0 +1,758 @@
+#[cfg(feature = "transaction-utils")]
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use borsh::BorshDeserialize;
+#[cfg(feature = "transaction-utils")]
+use ellipsis_transaction_utils::{ParsedInstruction, ParsedTransaction};
+use phoenix::program::deposit::DepositParams;
+use phoenix::program::new_order::{CondensedOrder, MultipleOrderPacket};
+use phoenix::program::status::{MarketStatus, SeatApprovalStatus};
+use phoenix::program::withdraw::WithdrawParams;
+use phoenix::program::{
+    CancelMultipleOrdersByIdParams, CancelOrderParams, CancelUpToParams, InitializeParams,
+    PhoenixInstruction, ReduceOrderParams,
+};
+use phoenix::state::{OrderPacket, Side};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::packet_decoder::decode_order_packet;
+
+/// An order to cancel or reduce, as given to `ReduceOrder` and `CancelMultipleOrdersById`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderToCancel {
+    pub side: Side,
+    pub price_in_ticks: u64,
+    pub order_sequence_number: u64,
+}
+
+impl From<&CancelOrderParams> for OrderToCancel {
+    fn from(params: &CancelOrderParams) -> Self {
+        OrderToCancel {
+            side: params.side,
+            price_in_ticks: params.price_in_ticks,
+            order_sequence_number: params.order_sequence_number,
+        }
+    }
+}
+
+/// The orders on one side of a `PlaceMultiplePostOnlyOrders`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PostOnlyOrder {
+    pub price_in_ticks: u64,
+    pub size_in_base_lots: u64,
+    pub last_valid_slot: Option<u64>,
+    pub last_valid_unix_timestamp_in_seconds: Option<u64>,
+}
+
+impl From<&CondensedOrder> for PostOnlyOrder {
+    fn from(order: &CondensedOrder) -> Self {
+        PostOnlyOrder {
+            price_in_ticks: order.price_in_ticks,
+            size_in_base_lots: order.size_in_base_lots,
+            last_valid_slot: order.last_valid_slot,
+            last_valid_unix_timestamp_in_seconds: order.last_valid_unix_timestamp_in_seconds,
+        }
+    }
+}
+
+/// The parameters of a `CancelUpTo` or `ForceCancelOrders`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CancelUpTo {
+    pub side: Side,
+    pub tick_limit: Option<u64>,
+    pub num_orders_to_search: Option<u32>,
+    pub num_orders_to_cancel: Option<u32>,
+}
+
+impl From<CancelUpToParams> for CancelUpTo {
+    fn from(params: CancelUpToParams) -> Self {
+        CancelUpTo {
+            side: params.side,
+            tick_limit: params.tick_limit,
+            num_orders_to_search: params.num_orders_to_search,
+            num_orders_to_cancel: params.num_orders_to_cancel,
+        }
+    }
+}
+
+/// The parameters of a Phoenix instruction, by instruction. The free-funds variants of an
+/// instruction decode to the same parameters; `PhoenixInstructionDecoded::instruction` tells them
+/// apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PhoenixInstructionParams {
+    /// `Swap`, `PlaceLimitOrder` and their free-funds variants.
+    NewOrder(OrderPacket),
+    MultiplePostOnlyOrders {
+        bids: Vec<PostOnlyOrder>,
+        asks: Vec<PostOnlyOrder>,
+        client_order_id: Option<u128>,
+        fail_on_cross: bool,
+        skip_orders_with_insufficient_funds: bool,
+    },
+    ReduceOrder {
+        order: OrderToCancel,
+        base_lots: u64,
+    },
+    CancelAllOrders,
+    CancelUpTo(CancelUpTo),
+    CancelMultipleOrdersById(Vec<OrderToCancel>),
+    WithdrawFunds {
+        quote_lots_to_withdraw: Option<u64>,
+        base_lots_to_withdraw: Option<u64>,
+    },
+    DepositFunds {
+        quote_lots_to_deposit: u64,
+        base_lots_to_deposit: u64,
+    },
+    RequestSeat,
+    /// Events logged by the program itself. Parse them with
+    /// `SDKClientCore::parse_events_from_instruction_data`.
+    Log,
+    InitializeMarket {
+        bids_size: u64,
+        asks_size: u64,
+        num_seats: u64,
+        num_quote_lots_per_quote_unit: u64,
+        tick_size_in_quote_lots_per_base_unit: u64,
+        num_base_lots_per_base_unit: u64,
+        taker_fee_bps: u16,
+        fee_collector: Pubkey,
+        raw_base_units_per_base_unit: Option<u32>,
+    },
+    ClaimAuthority,
+    NameSuccessor(Pubkey),
+    ChangeMarketStatus(MarketStatus),
+    ChangeSeatStatus(SeatApprovalStatus),
+    RequestSeatAuthorized,
+    EvictSeat,
+    ForceCancelOrders(CancelUpTo),
+    CollectFees,
+    ChangeFeeRecipient,
+}
+
+/// A Phoenix instruction with its parameters and the accounts it names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhoenixInstructionDecoded {
+    pub instruction: PhoenixInstruction,
+    pub params: PhoenixInstructionParams,
+    /// `None` only for `Log`, which names no market.
+    pub market: Option<Pubkey>,
+    /// The trader whose orders, funds or seat the instruction acts on, if any. For
+    /// `RequestSeat` this is the payer, who is also the trader.
+    pub trader: Option<Pubkey>,
+    /// The account that signs for the instruction: the trader, the market authority, the payer
+    /// of a seat or the fee sweeper.
+    pub signer: Option<Pubkey>,
+}
+
+impl PhoenixInstructionDecoded {
+    /// Whether the instruction places one or more orders.
+    pub fn is_new_order(&self) -> bool {
+        matches!(
+            self.params,
+            PhoenixInstructionParams::NewOrder(_)
+                | PhoenixInstructionParams::MultiplePostOnlyOrders { .. }
+        )
+    }
+
+    /// Whether the instruction cancels or reduces orders, including a forced cancel by the
+    /// market authority.
+    pub fn is_cancel(&self) -> bool {
+        matches!(
+            self.params,
+            PhoenixInstructionParams::ReduceOrder { .. }
+                | PhoenixInstructionParams::CancelAllOrders
+                | PhoenixInstructionParams::CancelUpTo(_)
+                | PhoenixInstructionParams::CancelMultipleOrdersById(_)
+                | PhoenixInstructionParams::ForceCancelOrders(_)
+        )
+    }
+}
+
+/// Decodes the data of a Phoenix instruction, whose first byte is the instruction tag, and the
+/// accounts it was given. Fails on an unknown tag or parameters that do not decode.
+pub fn parse_phoenix_instruction(
+    data: &[u8],
+    accounts: &[Pubkey],
+) -> Result<PhoenixInstructionDecoded> {
+    let (tag, data) = data
+        .split_first()
+        .ok_or_else(|| anyhow!("Instruction has no data"))?;
+    let instruction = PhoenixInstruction::try_from(*tag)
+        .map_err(|_| anyhow!("Unknown Phoenix instruction tag {}", tag))?;
+    let decode_err = |e: std::io::Error| anyhow!("Failed to decode {:?}: {}", instruction, e);
+    let account = |i: usize| accounts.get(i).copied();
+
+    use PhoenixInstruction as Ix;
+    let params = match instruction {
+        Ix::Swap
+        | Ix::SwapWithFreeFunds
+        | Ix::PlaceLimitOrder
+        | Ix::PlaceLimitOrderWithFreeFunds => {
+            PhoenixInstructionParams::NewOrder(decode_order_packet(data)?)
+        }
+        Ix::PlaceMultiplePostOnlyOrders | Ix::PlaceMultiplePostOnlyOrdersWithFreeFunds => {
+            let packet = MultipleOrderPacket::try_from_slice(data).map_err(decode_err)?;
+            let behavior = &packet.failed_multiple_limit_order_behavior;
+            PhoenixInstructionParams::MultiplePostOnlyOrders {
+                bids: packet.bids.iter().map(PostOnlyOrder::from).collect(),
+                asks: packet.asks.iter().map(PostOnlyOrder::from).collect(),
+                client_order_id: packet.client_order_id,
+                fail_on_cross: behavior.should_fail_on_cross(),
+                skip_orders_with_insufficient_funds: behavior
+                    .should_skip_orders_with_insufficient_funds(),
+            }
+        }
+        Ix::ReduceOrder | Ix::ReduceOrderWithFreeFunds => {
+            let params = ReduceOrderParams::try_from_slice(data).map_err(decode_err)?;
+            PhoenixInstructionParams::ReduceOrder {
+                order: (&params.base_params).into(),
+                base_lots: params.size,
+            }
+        }
+        Ix::CancelAllOrders | Ix::CancelAllOrdersWithFreeFunds => {
+            PhoenixInstructionParams::CancelAllOrders
+        }
+        Ix::CancelUpTo | Ix::CancelUpToWithFreeFunds => PhoenixInstructionParams::CancelUpTo(
+            CancelUpToParams::try_from_slice(data)
+                .map_err(decode_err)?
+                .into(),
+        ),
+        Ix::CancelMultipleOrdersById | Ix::CancelMultipleOrdersByIdWithFreeFunds => {
+            let params =
+                CancelMultipleOrdersByIdParams::try_from_slice(data).map_err(decode_err)?;
+            PhoenixInstructionParams::CancelMultipleOrdersById(
+                params.orders.iter().map(OrderToCancel::from).collect(),
+            )
+        }
+        Ix::WithdrawFunds => {
+            let params = WithdrawParams::try_from_slice(data).map_err(decode_err)?;
+            PhoenixInstructionParams::WithdrawFunds {
+                quote_lots_to_withdraw: params.quote_lots_to_withdraw,
+                base_lots_to_withdraw: params.base_lots_to_withdraw,
+            }
+        }
+        Ix::DepositFunds => {
+            let params = DepositParams::try_from_slice(data).map_err(decode_err)?;
+            PhoenixInstructionParams::DepositFunds {
+                quote_lots_to_deposit: params.quote_lots_to_deposit,
+                base_lots_to_deposit: params.base_lots_to_deposit,
+            }
+        }
+        Ix::RequestSeat => PhoenixInstructionParams::RequestSeat,
+        Ix::Log => PhoenixInstructionParams::Log,
+        Ix::InitializeMarket => {
+            let params = InitializeParams::try_from_slice(data).map_err(decode_err)?;
+            PhoenixInstructionParams::InitializeMarket {
+                bids_size: params.market_size_params.bids_size,
+                asks_size: params.market_size_params.asks_size,
+                num_seats: params.market_size_params.num_seats,
+                num_quote_lots_per_quote_unit: params.num_quote_lots_per_quote_unit,
+                tick_size_in_quote_lots_per_base_unit: params.tick_size_in_quote_lots_per_base_unit,
+                num_base_lots_per_base_unit: params.num_base_lots_per_base_unit,
+                taker_fee_bps: params.taker_fee_bps,
+                fee_collector: params.fee_collector,
+                raw_base_units_per_base_unit: params.raw_base_units_per_base_unit,
+            }
+        }
+        Ix::ClaimAuthority => PhoenixInstructionParams::ClaimAuthority,
+        Ix::NameSuccessor => PhoenixInstructionParams::NameSuccessor(
+            Pubkey::try_from_slice(data).map_err(decode_err)?,
+        ),
+        Ix::ChangeMarketStatus => PhoenixInstructionParams::ChangeMarketStatus(
+            MarketStatus::try_from_slice(data).map_err(decode_err)?,
+        ),
+        Ix::ChangeSeatStatus => PhoenixInstructionParams::ChangeSeatStatus(
+            SeatApprovalStatus::try_from_slice(data).map_err(decode_err)?,
+        ),
+        Ix::RequestSeatAuthorized => PhoenixInstructionParams::RequestSeatAuthorized,
+        Ix::EvictSeat => PhoenixInstructionParams::EvictSeat,
+        Ix::ForceCancelOrders => PhoenixInstructionParams::ForceCancelOrders(
+            CancelUpToParams::try_from_slice(data)
+                .map_err(decode_err)?
+                .into(),
+        ),
+        Ix::CollectFees => PhoenixInstructionParams::CollectFees,
+        Ix::ChangeFeeRecipient => PhoenixInstructionParams::ChangeFeeRecipient,
+    };
+
+    // Every instruction but `Log` names the market third and its signer fourth
+    let (market, signer) = match instruction {
+        Ix::Log => (None, account(0)),
+        _ => (account(2), account(3)),
+    };
+    let trader = match instruction {
+        Ix::RequestSeatAuthorized => account(5),
+        Ix::EvictSeat | Ix::ForceCancelOrders => account(4),
+        Ix::Log
+        | Ix::InitializeMarket
+        | Ix::ClaimAuthority
+        | Ix::NameSuccessor
+        | Ix::ChangeMarketStatus
+        | Ix::ChangeSeatStatus
+        | Ix::CollectFees
+        | Ix::ChangeFeeRecipient => None,
+        _ => signer,
+    };
+    Ok(PhoenixInstructionDecoded {
+        instruction,
+        params,
+        market,
+        trader,
+        signer,
+    })
+}
+
+/// A Phoenix instruction of a transaction and where it was invoked.
+#[cfg(feature = "transaction-utils")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionInstruction {
+    /// The index of the top-level instruction, or of the one that invoked it if `inner`.
+    pub index: usize,
+    /// Whether another program invoked it, e.g. a router or the seat manager.
+    pub inner: bool,
+    pub decoded: PhoenixInstructionDecoded,
+}
+
+/// Decodes the Phoenix instructions of `tx`, top-level and inner, in the order they were
+/// invoked. The `Log` instructions Phoenix uses to record events are left out. Failed
+/// transactions are decoded too, so that intents that never emitted events can be counted.
+#[cfg(feature = "transaction-utils")]
+pub fn parse_transaction_instructions(
+    tx: &ParsedTransaction,
+) -> Result<Vec<TransactionInstruction>> {
+    let phoenix_program_id = phoenix::id().to_string();
+    let decode = |ix: &ParsedInstruction| {
+        let accounts = ix
+            .accounts
+            .iter()
+            .map(|key| {
+                Pubkey::from_str(key).map_err(|e| anyhow!("Invalid account key {}: {}", key, e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        parse_phoenix_instruction(&ix.data, &accounts)
+            .map_err(|e| anyhow!("{} in transaction {}", e, tx.signature))
+    };
+
+    let mut instructions = vec![];
+    for (index, ix) in tx.instructions.iter().enumerate() {
+        if ix.program_id == phoenix_program_id {
+            instructions.push(TransactionInstruction {
+                index,
+                inner: false,
+                decoded: decode(ix)?,
+            });
+        }
+        for inner_ix in tx
+            .inner_instructions
+            .iter()
+            .flatten()
+            .filter(|inner_ix| inner_ix.parent_index == index)
+        {
+            if inner_ix.instruction.program_id != phoenix_program_id
+                || inner_ix.instruction.data.first() == Some(&(PhoenixInstruction::Log as u8))
+            {
+                continue;
+            }
+            instructions.push(TransactionInstruction {
+                index,
+                inner: true,
+                decoded: decode(&inner_ix.instruction)?,
+            });
+        }
+    }
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "transaction-utils")]
+    use ellipsis_transaction_utils::ParsedInnerInstruction;
+    use phoenix::program::instruction_builders::*;
+    use phoenix::program::MarketSizeParams;
+    use solana_sdk::instruction::Instruction;
+
+    /// Instruction data from a tag and its little-endian Borsh fields.
+    fn data(tag: PhoenixInstruction, fields: &[&[u8]]) -> Vec<u8> {
+        let mut data = vec![tag as u8];
+        fields
+            .iter()
+            .for_each(|field| data.extend_from_slice(field));
+        data
+    }
+
+    fn parse(ix: &Instruction) -> PhoenixInstructionDecoded {
+        let accounts = ix.accounts.iter().map(|a| a.pubkey).collect::<Vec<_>>();
+        parse_phoenix_instruction(&ix.data, &accounts).unwrap()
+    }
+
+    #[test]
+    fn test_pinned_instruction_data() {
+        use PhoenixInstruction as Ix;
+        use PhoenixInstructionParams as Params;
+        let successor = Pubkey::new_unique();
+        let fixtures = vec![
+            (
+                data(
+                    Ix::PlaceLimitOrder,
+                    &[
+                        &[0, 1],
+                        &22_500u64.to_le_bytes(),
+                        &7u64.to_le_bytes(),
+                        &42u128.to_le_bytes(),
+                        &[1, 0, 0, 0, 0],
+                    ],
+                ),
+                Params::NewOrder(OrderPacket::new_post_only(
+                    Side::Ask,
+                    22_500,
+                    7,
+                    42,
+                    true,
+                    false,
+                )),
+            ),
+            (
+                data(
+                    Ix::ReduceOrderWithFreeFunds,
+                    &[
+                        &[1],
+                        &100u64.to_le_bytes(),
+                        &9u64.to_le_bytes(),
+                        &3u64.to_le_bytes(),
+                    ],
+                ),
+                Params::ReduceOrder {
+                    order: OrderToCancel {
+                        side: Side::Ask,
+                        price_in_ticks: 100,
+                        order_sequence_number: 9,
+                    },
+                    base_lots: 3,
+                },
+            ),
+            (data(Ix::CancelAllOrders, &[]), Params::CancelAllOrders),
+            (
+                data(
+                    Ix::CancelUpTo,
+                    &[&[0, 1], &150u64.to_le_bytes(), &[0, 1], &5u32.to_le_bytes()],
+                ),
+                Params::CancelUpTo(CancelUpTo {
+                    side: Side::Bid,
+                    tick_limit: Some(150),
+                    num_orders_to_search: None,
+                    num_orders_to_cancel: Some(5),
+                }),
+            ),
+            (
+                data(
+                    Ix::CancelMultipleOrdersById,
+                    &[
+                        &2u32.to_le_bytes(),
+                        &[0],
+                        &99u64.to_le_bytes(),
+                        &1u64.to_le_bytes(),
+                        &[1],
+                        &101u64.to_le_bytes(),
+                        &u64::MAX.to_le_bytes(),
+                    ],
+                ),
+                Params::CancelMultipleOrdersById(vec![
+                    OrderToCancel {
+                        side: Side::Bid,
+                        price_in_ticks: 99,
+                        order_sequence_number: 1,
+                    },
+                    OrderToCancel {
+                        side: Side::Ask,
+                        price_in_ticks: 101,
+                        order_sequence_number: u64::MAX,
+                    },
+                ]),
+            ),
+            (
+                data(Ix::WithdrawFunds, &[&[1], &500u64.to_le_bytes(), &[0]]),
+                Params::WithdrawFunds {
+                    quote_lots_to_withdraw: Some(500),
+                    base_lots_to_withdraw: None,
+                },
+            ),
+            (
+                data(
+                    Ix::DepositFunds,
+                    &[&1_000u64.to_le_bytes(), &20u64.to_le_bytes()],
+                ),
+                Params::DepositFunds {
+                    quote_lots_to_deposit: 1_000,
+                    base_lots_to_deposit: 20,
+                },
+            ),
+            (data(Ix::RequestSeat, &[]), Params::RequestSeat),
+            (data(Ix::ClaimAuthority, &[]), Params::ClaimAuthority),
+            (
+                data(Ix::NameSuccessor, &[successor.as_ref()]),
+                Params::NameSuccessor(successor),
+            ),
+            (
+                data(Ix::ChangeMarketStatus, &[&[3]]),
+                Params::ChangeMarketStatus(MarketStatus::Paused),
+            ),
+            (
+                data(Ix::ChangeSeatStatus, &[&[1]]),
+                Params::ChangeSeatStatus(SeatApprovalStatus::Approved),
+            ),
+            (
+                data(Ix::RequestSeatAuthorized, &[]),
+                Params::RequestSeatAuthorized,
+            ),
+            (data(Ix::EvictSeat, &[]), Params::EvictSeat),
+            (
+                data(Ix::ForceCancelOrders, &[&[1], &[0], &[0], &[0]]),
+                Params::ForceCancelOrders(CancelUpTo {
+                    side: Side::Ask,
+                    tick_limit: None,
+                    num_orders_to_search: None,
+                    num_orders_to_cancel: None,
+                }),
+            ),
+            (data(Ix::CollectFees, &[]), Params::CollectFees),
+            (
+                data(Ix::ChangeFeeRecipient, &[]),
+                Params::ChangeFeeRecipient,
+            ),
+        ];
+
+        let accounts = (0..6).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        for (data, params) in fixtures {
+            let decoded = parse_phoenix_instruction(&data, &accounts).unwrap();
+            assert_eq!(decoded.instruction as u8, data[0]);
+            assert_eq!(decoded.params, params);
+            assert_eq!(decoded.market, Some(accounts[2]));
+            assert_eq!(decoded.signer, Some(accounts[3]));
+        }
+
+        assert!(parse_phoenix_instruction(&[], &accounts).is_err());
+        assert!(parse_phoenix_instruction(&[99], &accounts).is_err());
+        assert!(parse_phoenix_instruction(&[Ix::DepositFunds as u8, 1], &accounts).is_err());
+    }
+
+    #[test]
+    fn test_builder_round_trips() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let base = Pubkey::new_unique();
+        let quote = Pubkey::new_unique();
+
+        let packet = OrderPacket::new_ioc_by_lots(
+            Side::Bid,
+            1_000,
+            10,
+            phoenix::state::SelfTradeBehavior::CancelProvide,
+            Some(4),
+            7,
+            false,
+        );
+        let decoded = parse(&create_new_order_instruction(
+            &market, &trader, &base, &quote, &packet,
+        ));
+        assert_eq!(decoded.instruction, PhoenixInstruction::Swap);
+        assert_eq!(decoded.params, PhoenixInstructionParams::NewOrder(packet));
+        assert_eq!(decoded.market, Some(market));
+        assert_eq!(decoded.trader, Some(trader));
+        assert!(decoded.is_new_order() && !decoded.is_cancel());
+
+        let packet = MultipleOrderPacket::new(
+            vec![CondensedOrder::new_default(99, 5)],
+            vec![
+                CondensedOrder::new_default(101, 5),
+                CondensedOrder {
+                    price_in_ticks: 102,
+                    size_in_base_lots: 6,
+                    last_valid_slot: Some(1_000),
+                    last_valid_unix_timestamp_in_seconds: None,
+                },
+            ],
+            Some(11),
+            false,
+        );
+        let decoded = parse(&create_new_multiple_order_instruction(
+            &market, &trader, &base, &quote, &packet,
+        ));
+        assert_eq!(
+            decoded.params,
+            PhoenixInstructionParams::MultiplePostOnlyOrders {
+                bids: vec![PostOnlyOrder {
+                    price_in_ticks: 99,
+                    size_in_base_lots: 5,
+                    last_valid_slot: None,
+                    last_valid_unix_timestamp_in_seconds: None,
+                }],
+                asks: vec![
+                    PostOnlyOrder {
+                        price_in_ticks: 101,
+                        size_in_base_lots: 5,
+                        last_valid_slot: None,
+                        last_valid_unix_timestamp_in_seconds: None,
+                    },
+                    PostOnlyOrder {
+                        price_in_ticks: 102,
+                        size_in_base_lots: 6,
+                        last_valid_slot: Some(1_000),
+                        last_valid_unix_timestamp_in_seconds: None,
+                    },
+                ],
+                client_order_id: Some(11),
+                fail_on_cross: false,
+                skip_orders_with_insufficient_funds: false,
+            }
+        );
+
+        let decoded = parse(&create_cancel_all_orders_instruction(
+            &market, &trader, &base, &quote,
+        ));
+        assert!(decoded.is_cancel());
+        assert_eq!(decoded.trader, Some(trader));
+
+        let decoded = parse(&create_evict_seat_instruction(
+            &authority, &market, &trader, &base, &quote,
+        ));
+        assert_eq!(decoded.params, PhoenixInstructionParams::EvictSeat);
+        assert_eq!(decoded.signer, Some(authority));
+        assert_eq!(decoded.trader, Some(trader));
+
+        let payer = Pubkey::new_unique();
+        let decoded = parse(&create_request_seat_authorized_instruction(
+            &authority, &payer, &market, &trader,
+        ));
+        assert_eq!(decoded.signer, Some(authority));
+        assert_eq!(decoded.trader, Some(trader));
+
+        let decoded = parse(&create_change_seat_status_instruction(
+            &authority,
+            &market,
+            &trader,
+            SeatApprovalStatus::Retired,
+        ));
+        assert_eq!(
+            decoded.params,
+            PhoenixInstructionParams::ChangeSeatStatus(SeatApprovalStatus::Retired)
+        );
+        assert_eq!(decoded.trader, None);
+
+        let fee_collector = Pubkey::new_unique();
+        let params = InitializeParams {
+            market_size_params: MarketSizeParams {
+                bids_size: 512,
+                asks_size: 512,
+                num_seats: 128,
+            },
+            num_quote_lots_per_quote_unit: 100_000,
+            tick_size_in_quote_lots_per_base_unit: 100,
+            num_base_lots_per_base_unit: 1_000,
+            taker_fee_bps: 2,
+            fee_collector,
+            raw_base_units_per_base_unit: Some(1_000),
+        };
+        let mut data = vec![PhoenixInstruction::InitializeMarket as u8];
+        data.extend(borsh::to_vec(&params).unwrap());
+        let decoded = parse_phoenix_instruction(&data, &[market, authority]).unwrap();
+        assert_eq!(
+            decoded.params,
+            PhoenixInstructionParams::InitializeMarket {
+                bids_size: 512,
+                asks_size: 512,
+                num_seats: 128,
+                num_quote_lots_per_quote_unit: 100_000,
+                tick_size_in_quote_lots_per_base_unit: 100,
+                num_base_lots_per_base_unit: 1_000,
+                taker_fee_bps: 2,
+                fee_collector,
+                raw_base_units_per_base_unit: Some(1_000),
+            }
+        );
+        assert_eq!((decoded.market, decoded.trader), (None, None));
+    }
+
+    #[test]
+    #[cfg(feature = "transaction-utils")]
+    fn test_parse_transaction_instructions() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let base = Pubkey::new_unique();
+        let quote = Pubkey::new_unique();
+        let parsed = |ix: Instruction| ParsedInstruction {
+            program_id: ix.program_id.to_string(),
+            accounts: ix.accounts.iter().map(|a| a.pubkey.to_string()).collect(),
+            data: ix.data,
+        };
+        let deposit = create_deposit_funds_instruction(
+            &market,
+            &trader,
+            &base,
+            &quote,
+            &DepositParams {
+                quote_lots_to_deposit: 10,
+                base_lots_to_deposit: 0,
+            },
+        );
+        let cancel = create_cancel_up_to_instruction(
+            &market,
+            &trader,
+            &base,
+            &quote,
+            &CancelUpToParams {
+                side: Side::Bid,
+                tick_limit: None,
+                num_orders_to_search: None,
+                num_orders_to_cancel: None,
+            },
+        );
+        let log = ParsedInstruction {
+            program_id: phoenix::id().to_string(),
+            accounts: vec![phoenix::id().to_string()],
+            data: vec![PhoenixInstruction::Log as u8, 1, 2, 3],
+        };
+        let memo = ParsedInstruction {
+            program_id: Pubkey::new_unique().to_string(),
+            accounts: vec![],
+            data: vec![PhoenixInstruction::Swap as u8],
+        };
+        let inner = |parent_index, instruction| ParsedInnerInstruction {
+            parent_index,
+            instruction,
+        };
+        let tx = ParsedTransaction {
+            slot: 1,
+            block_time: None,
+            instructions: vec![memo.clone(), parsed(deposit.clone()), memo.clone()],
+            inner_instructions: vec![
+                vec![inner(1, log.clone())],
+                vec![inner(2, parsed(cancel.clone())), inner(2, log)],
+            ],
+            logs: vec![],
+            is_err: true,
+            signature: String::new(),
+            fee_payer: trader.to_string(),
+        };
+
+        let instructions = parse_transaction_instructions(&tx).unwrap();
+        assert_eq!(
+            instructions,
+            vec![
+                TransactionInstruction {
+                    index: 1,
+                    inner: false,
+                    decoded: parse(&deposit),
+                },
+                TransactionInstruction {
+                    index: 2,
+                    inner: true,
+                    decoded: parse(&cancel),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Properties that tie the lot, tick and atom conversions of `MarketMetadata` together, checked
+//! on random but realistic markets: mint decimals up to 9, base units of up to a million raw
+//! base units, lot sizes that divide the unit multipliers and tick sizes in whole quote lots.
+
+use proptest::prelude::*;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::atoms::Rounding;
+use crate::sdk_client_core::{MarketMetadata, SDKClientCore};
+
+/// Like a market header that passes `MarketMetadata::from_header`.
+fn market_metadata() -> impl Strategy<Value = MarketMetadata> {
+    (
+        0u32..=9,
+        0u32..=9,
+        prop::sample::select(vec![1u32, 10, 1000, 1_000_000]),
+        0u32..=15,
+        0u32..=9,
+        1u64..=1000,
+    )
+        .prop_map(
+            |(
+                base_decimals,
+                quote_decimals,
+                raw_base_units_per_base_unit,
+                base_lot_exponent,
+                quote_lot_exponent,
+                ticks_in_quote_lots,
+            )| {
+                let base_atoms_per_raw_base_unit = 10u64.pow(base_decimals);
+                let base_atoms_per_base_unit =
+                    base_atoms_per_raw_base_unit * raw_base_units_per_base_unit as u64;
+                // Lot sizes are powers of 10 that divide the unit, as in practice
+                let base_atoms_per_base_lot = (0..=base_lot_exponent)
+                    .rev()
+                    .map(|exponent| 10u64.pow(exponent))
+                    .find(|lot| base_atoms_per_base_unit.is_multiple_of(*lot))
+                    .unwrap();
+                let quote_atoms_per_quote_unit = 10u64.pow(quote_decimals);
+                let quote_atoms_per_quote_lot = 10u64.pow(quote_lot_exponent.min(quote_decimals));
+                MarketMetadata {
+                    base_mint: Pubkey::new_unique(),
+                    quote_mint: Pubkey::new_unique(),
+                    base_decimals,
+                    quote_decimals,
+                    base_atoms_per_raw_base_unit,
+                    quote_atoms_per_quote_unit,
+                    quote_atoms_per_quote_lot,
+                    base_atoms_per_base_lot,
+                    tick_size_in_quote_atoms_per_base_unit: ticks_in_quote_lots
+                        * quote_atoms_per_quote_lot,
+                    num_base_lots_per_base_unit: base_atoms_per_base_unit / base_atoms_per_base_lot,
+                    raw_base_units_per_base_unit,
+                    ..Default::default()
+                }
+            },
+        )
+}
+
+/// The notional of `base_lots` at `price_in_ticks` from the definitions of the sizes: a tick is
+/// `tick_size_in_quote_atoms_per_base_unit` per base unit, and the program counts quote in whole
+/// lots, rounding down.
+fn notional_by_definition(meta: &MarketMetadata, base_lots: u64, price_in_ticks: u64) -> u128 {
+    let quote_atoms_per_base_unit =
+        price_in_ticks as u128 * meta.tick_size_in_quote_atoms_per_base_unit as u128;
+    let quote_lots = base_lots as u128 * quote_atoms_per_base_unit
+        / (meta.num_base_lots_per_base_unit as u128 * meta.quote_atoms_per_quote_lot as u128);
+    quote_lots * meta.quote_atoms_per_quote_lot as u128
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn base_lots_and_atoms_invert(
+        meta in market_metadata(),
+        base_lots in 0u64..1 << 24,
+        offset in any::<u64>(),
+    ) {
+        let lot = meta.base_atoms_per_base_lot;
+        prop_assume!((base_lots + 1).checked_mul(lot).is_some());
+        let base_atoms = meta.base_lots_to_base_atoms(base_lots);
+        prop_assert_eq!(meta.base_atoms_to_base_lots_rounded_down(base_atoms), base_lots);
+        prop_assert_eq!(meta.base_atoms_to_base_lots_rounded_up(base_atoms), base_lots);
+
+        // Amounts between lots round to the lots on either side
+        let inside = offset % lot;
+        let down = meta.base_atoms_to_base_lots_rounded_down(base_atoms + inside);
+        let up = meta.base_atoms_to_base_lots_rounded_up(base_atoms + inside);
+        prop_assert_eq!(down, base_lots);
+        prop_assert_eq!(up, base_lots + (inside > 0) as u64);
+    }
+
+    #[test]
+    fn quote_lots_and_atoms_invert(
+        meta in market_metadata(),
+        quote_lots in 0u64..1 << 24,
+        offset in any::<u64>(),
+    ) {
+        let lot = meta.quote_atoms_per_quote_lot;
+        let quote_atoms = meta.quote_lots_to_quote_atoms(quote_lots);
+        prop_assert_eq!(meta.quote_atoms_to_quote_lots_rounded_down(quote_atoms), quote_lots);
+        prop_assert_eq!(meta.quote_atoms_to_quote_lots_rounded_up(quote_atoms), quote_lots);
+
+        let inside = offset % lot;
+        let down = meta.quote_atoms_to_quote_lots_rounded_down(quote_atoms + inside);
+        let up = meta.quote_atoms_to_quote_lots_rounded_up(quote_atoms + inside);
+        prop_assert_eq!(down, quote_lots);
+        prop_assert_eq!(up, quote_lots + (inside > 0) as u64);
+    }
+
+    #[test]
+    fn decimal_strings_invert(
+        meta in market_metadata(),
+        lots in 0u64..1 << 32,
+        ticks in 0u64..1 << 32,
+    ) {
+        let base = meta.base_lots_to_decimal_str(lots);
+        prop_assert_eq!(meta.decimal_str_to_base_lots(&base, Rounding::Exact).unwrap(), lots);
+        let quote = meta.quote_atoms_to_decimal_str(meta.quote_lots_to_quote_atoms(lots) as u128);
+        prop_assert_eq!(meta.decimal_str_to_quote_lots(&quote, Rounding::Exact).unwrap(), lots);
+        let price = meta.ticks_to_decimal_str(ticks).unwrap();
+        prop_assert_eq!(meta.decimal_str_to_ticks(&price, Rounding::Exact).unwrap(), ticks);
+    }
+
+    #[test]
+    fn decimal_strings_round_between_lots(meta in market_metadata(), lots in 0u64..1 << 32) {
+        // Half a lot more than `lots`, in atoms, when the lot has more than one atom
+        let lot = meta.base_atoms_per_base_lot as u128;
+        prop_assume!(lot > 1);
+        let base = meta.base_atoms_to_decimal_str(lots as u128 * lot + lot / 2);
+        prop_assert_eq!(meta.decimal_str_to_base_lots(&base, Rounding::Down).unwrap(), lots);
+        prop_assert_eq!(meta.decimal_str_to_base_lots(&base, Rounding::Up).unwrap(), lots + 1);
+        prop_assert!(meta.decimal_str_to_base_lots(&base, Rounding::Exact).is_err());
+    }
+
+    #[test]
+    fn float_conversions_invert_on_whole_amounts(
+        meta in market_metadata(),
+        lots in 0u64..1 << 32,
+        ticks in 0u64..1 << 32,
+    ) {
+        let raw_base_units = lots as f64 * meta.raw_base_units_per_base_lot_as_float();
+        let down = meta.raw_base_units_to_base_lots_rounded_down_as_float(raw_base_units);
+        let up = meta.raw_base_units_to_base_lots_rounded_up_as_float(raw_base_units);
+        prop_assert_eq!((down, up), (lots, lots));
+
+        let quote_atoms = meta.quote_lots_to_quote_atoms(lots);
+        let quote_units = meta.quote_atoms_to_quote_units_as_float(quote_atoms);
+        prop_assert_eq!(meta.quote_units_to_quote_lots_as_float(quote_units), lots);
+
+        let price = meta.ticks_to_float_price(ticks);
+        let down = meta.float_price_to_ticks_rounded_down(price);
+        let up = meta.float_price_to_ticks_rounded_up(price);
+        prop_assert_eq!((down, up), (ticks, ticks));
+    }
+
+    #[test]
+    fn float_conversions_round_between_amounts(
+        meta in market_metadata(),
+        lots in 0u64..1 << 32,
+        ticks in 0u64..1 << 32,
+    ) {
+        let raw_base_units = (lots as f64 + 0.5) * meta.raw_base_units_per_base_lot_as_float();
+        let down = meta.raw_base_units_to_base_lots_rounded_down_as_float(raw_base_units);
+        let up = meta.raw_base_units_to_base_lots_rounded_up_as_float(raw_base_units);
+        prop_assert_eq!((down, up), (lots, lots + 1));
+
+        let price = (ticks as f64 + 0.5) * meta.quote_units_per_raw_base_unit_per_tick_as_float();
+        let down = meta.float_price_to_ticks_rounded_down(price);
+        let up = meta.float_price_to_ticks_rounded_up(price);
+        prop_assert_eq!((down, up), (ticks, ticks + 1));
+    }
+
+    #[test]
+    fn notional_follows_the_definition(
+        meta in market_metadata(),
+        base_lots in 0u64..1 << 40,
+        price_in_ticks in 0u64..1 << 40,
+    ) {
+        let expected = notional_by_definition(&meta, base_lots, price_in_ticks);
+        prop_assert_eq!(
+            meta.checked_base_lots_and_price_to_quote_atoms(base_lots, price_in_ticks),
+            u64::try_from(expected).ok()
+        );
+        prop_assert_eq!(
+            meta.base_lots_and_price_to_quote_atoms(base_lots, price_in_ticks),
+            u64::try_from(expected).unwrap_or(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn notional_of_whole_units(
+        meta in market_metadata(),
+        base_units in 0u64..1 << 20,
+        price_in_ticks in 0u64..1 << 20,
+    ) {
+        // A whole base unit at one tick is worth exactly the tick size
+        let base_lots = base_units.checked_mul(meta.num_base_lots_per_base_unit);
+        prop_assume!(base_lots.is_some());
+        let base_lots = base_lots.unwrap();
+        let expected = base_units as u128
+            * price_in_ticks as u128
+            * meta.tick_size_in_quote_atoms_per_base_unit as u128;
+        prop_assert_eq!(
+            meta.base_lots_and_price_to_quote_atoms(base_lots, price_in_ticks),
+            u64::try_from(expected).unwrap_or(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn notional_is_monotonic_in_whole_quote_lots(
+        meta in market_metadata(),
+        base_lots in 0u64..1 << 30,
+        price_in_ticks in 0u64..1 << 30,
+    ) {
+        let notional = |base_lots, price_in_ticks| {
+            meta.base_lots_and_price_to_quote_atoms(base_lots, price_in_ticks)
+        };
+        let at = notional(base_lots, price_in_ticks);
+        prop_assert!(notional(base_lots + 1, price_in_ticks) >= at);
+        prop_assert!(notional(base_lots, price_in_ticks + 1) >= at);
+        prop_assert!(at == u64::MAX || at % meta.quote_atoms_per_quote_lot == 0);
+    }
+
+    #[test]
+    fn client_core_agrees_with_metadata(
+        meta in market_metadata(),
+        lots in 0u64..1 << 20,
+        ticks in 0u64..1 << 32,
+    ) {
+        let market = Pubkey::new_unique();
+        let core = SDKClientCore {
+            markets: [(market, meta)].into_iter().collect(),
+            trader: Pubkey::new_unique(),
+            default_self_trade_behavior: Default::default(),
+        };
+        prop_assume!(lots.checked_mul(meta.base_atoms_per_base_lot).is_some());
+        prop_assert_eq!(
+            core.base_lots_to_base_atoms(&market, lots).unwrap(),
+            meta.base_lots_to_base_atoms(lots)
+        );
+        prop_assert_eq!(
+            core.quote_lots_to_quote_atoms(&market, lots).unwrap(),
+            meta.quote_lots_to_quote_atoms(lots)
+        );
+        prop_assert_eq!(
+            core.base_lots_and_price_to_quote_atoms(&market, lots, ticks).unwrap(),
+            meta.base_lots_and_price_to_quote_atoms(lots, ticks)
+        );
+        prop_assert_eq!(
+            core.ticks_to_float_price(&market, ticks).unwrap(),
+            meta.ticks_to_float_price(ticks)
+        );
+    }
+}
@@ -1,7 +1,27 @@
 pub mod ata_utils;
+pub mod atoms;
+pub mod compute_units;
+pub mod event_iter;
+pub mod execution_report;
+pub mod fee_report;
+pub mod instruction_parser;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod invariants;
 pub mod market_event;
+pub mod market_map;
+pub mod order_id;
+pub mod order_lifecycle;
+pub mod order_manager;
 pub mod orderbook;
 pub mod packet_decoder;
+pub mod position_tracker;
+pub mod program_error;
+pub mod quote_converter;
+pub mod quote_ladder;
+pub mod reference_price;
 pub mod sdk_client_core;
 #[cfg(test)]
 pub mod test_unit_conversion;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod tick_math;
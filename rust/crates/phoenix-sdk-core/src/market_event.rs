@@ -1,8 +1,11 @@
+use borsh::{BorshDeserialize, BorshSerialize};
 use phoenix::state::enums::Side;
+use solana_sdk::clock::DEFAULT_MS_PER_SLOT;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
+use std::io::{Error, ErrorKind, Write};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
 pub struct Fill {
     /// The sequence number of the order that was filled.
     pub order_sequence_number: u64,
@@ -16,13 +19,54 @@ pub struct Fill {
     pub base_lots_filled: u64,
     /// The number of lots that remain in the order.
     pub base_lots_remaining: u64,
-    /// The side of the order that was filled.
+    /// The side of the resting (maker) order that was filled, NOT the side of the taker.
+    /// A taker buying from the asks produces fills with `side_filled == Side::Ask`. Kept for
+    /// compatibility; prefer `maker_side` and `taker_side`.
     pub side_filled: Side,
     /// Whether the order was fully filled.
     pub is_full_fill: bool,
+    /// The side of the resting order, always equal to `side_filled`.
+    pub maker_side: Side,
+    /// The side of the incoming order that crossed the book (the aggressor).
+    pub taker_side: Side,
 }
 
-#[derive(Clone, Copy, Debug)]
+impl Fill {
+    /// Builds a fill against the resting order `order_sequence_number`, deriving the maker and
+    /// taker sides from the sequence number.
+    pub fn new(
+        order_sequence_number: u64,
+        maker: Pubkey,
+        taker: Pubkey,
+        price_in_ticks: u64,
+        base_lots_filled: u64,
+        base_lots_remaining: u64,
+    ) -> Self {
+        let maker_side = Side::from_order_sequence_number(order_sequence_number);
+        Self {
+            order_sequence_number,
+            maker,
+            taker,
+            price_in_ticks,
+            base_lots_filled,
+            base_lots_remaining,
+            side_filled: maker_side,
+            is_full_fill: base_lots_remaining == 0,
+            maker_side,
+            taker_side: maker_side.opposite(),
+        }
+    }
+
+    /// 1 if the taker bought, -1 if the taker sold, matching `FillSummary::trade_direction`.
+    pub fn trade_direction(&self) -> i8 {
+        match self.taker_side {
+            Side::Bid => 1,
+            Side::Ask => -1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct PhoenixEvent {
     /// The pubkey of the market the trade occurred in
     pub market: Pubkey,
@@ -30,7 +74,9 @@ pub struct PhoenixEvent {
     pub sequence_number: u64,
     /// The slot of the trade event.
     pub slot: u64,
-    /// The timestamp of the trade event.
+    /// The block time of the trade event, in seconds since the Unix epoch, or 0 if the log did not
+    /// carry one. Kept for compatibility; prefer `time`, `timestamp_seconds` and
+    /// `timestamp_millis`, which make the unit explicit.
     pub timestamp: i64,
     /// The signature of the transaction that contains this event.
     pub signature: Signature,
@@ -42,7 +88,104 @@ pub struct PhoenixEvent {
     pub details: MarketEventDetails,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Lets helpers that read batches of events take `&[PhoenixEvent]` and shared
+/// `&[Arc<PhoenixEvent>]` alike.
+impl AsRef<PhoenixEvent> for PhoenixEvent {
+    fn as_ref(&self) -> &PhoenixEvent {
+        self
+    }
+}
+
+impl PhoenixEvent {
+    pub fn time(&self) -> EventTime {
+        EventTime {
+            unix_seconds: self.timestamp,
+            slot: self.slot,
+        }
+    }
+
+    pub fn timestamp_seconds(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn timestamp_millis(&self) -> i64 {
+        self.time().unix_millis()
+    }
+
+    /// The block time as a UTC datetime, or `None` if it is unknown.
+    #[cfg(feature = "chrono")]
+    pub fn datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.time().datetime()
+    }
+}
+
+/// When an event happened: its slot, and the block time in seconds since the Unix epoch. The
+/// block time is 0 when it is unknown, see `is_known` and `or_estimate_from`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct EventTime {
+    pub unix_seconds: i64,
+    pub slot: u64,
+}
+
+impl EventTime {
+    pub fn is_known(&self) -> bool {
+        self.unix_seconds != 0
+    }
+
+    pub fn unix_millis(&self) -> i64 {
+        self.unix_seconds.saturating_mul(1000)
+    }
+
+    /// Estimates the block time of `slot` from this one, assuming `DEFAULT_MS_PER_SLOT` per slot.
+    /// Slots are often faster or slower than that, so the estimate drifts with distance.
+    pub fn estimate_at_slot(&self, slot: u64) -> EventTime {
+        let elapsed_ms = (slot as i128 - self.slot as i128) * DEFAULT_MS_PER_SLOT as i128;
+        let unix_ms = self.unix_millis() as i128 + elapsed_ms;
+        EventTime {
+            unix_seconds: unix_ms
+                .div_euclid(1000)
+                .clamp(i64::MIN as i128, i64::MAX as i128) as i64,
+            slot,
+        }
+    }
+
+    /// This time if it is known, otherwise an estimate from `reference`, if that is known.
+    pub fn or_estimate_from(&self, reference: &EventTime) -> EventTime {
+        if self.is_known() || !reference.is_known() {
+            *self
+        } else {
+            reference.estimate_at_slot(self.slot)
+        }
+    }
+
+    /// The block time as a UTC datetime, or `None` if it is unknown.
+    #[cfg(feature = "chrono")]
+    pub fn datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        if !self.is_known() {
+            return None;
+        }
+        chrono::DateTime::from_timestamp(self.unix_seconds, 0)
+    }
+}
+
+/// Fills in the timestamp of events that have none, estimating it from the slot of the closest
+/// earlier event with a timestamp, or the first later one if no earlier event has one. Events
+/// are left unchanged if none of them has a timestamp.
+pub fn estimate_missing_timestamps(events: &mut [PhoenixEvent]) {
+    let Some(first_known) = events.iter().map(|e| e.time()).find(|t| t.is_known()) else {
+        return;
+    };
+    let mut reference = first_known;
+    for event in events.iter_mut() {
+        if event.time().is_known() {
+            reference = event.time();
+        } else {
+            event.timestamp = event.time().or_estimate_from(&reference).unix_seconds;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
 pub struct Reduce {
     /// The sequence number of the order that was reduced.
     pub order_sequence_number: u64,
@@ -58,7 +201,7 @@ pub struct Reduce {
     pub is_full_cancel: bool,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
 pub struct Evict {
     /// The sequence number of the order that was evicted.
     pub order_sequence_number: u64,
@@ -70,7 +213,7 @@ pub struct Evict {
     pub base_lots_evicted: u64,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
 pub struct Place {
     /// The sequence number of the order that was placed.
     pub order_sequence_number: u64,
@@ -84,7 +227,7 @@ pub struct Place {
     pub base_lots_placed: u64,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
 pub struct FillSummary {
     /// The client_order_id of the order that was filled.
     pub client_order_id: u128,
@@ -98,14 +241,14 @@ pub struct FillSummary {
     pub trade_direction: i8,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
 pub struct TimeInForce {
     pub order_sequence_number: u64,
     pub last_valid_slot: u64,
     pub last_valid_unix_timestamp_in_seconds: u64,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
 pub enum MarketEventDetails {
     Fill(Fill),
     Place(Place),
@@ -115,3 +258,122 @@ pub enum MarketEventDetails {
     Fee(u64),
     TimeInForce(TimeInForce),
 }
+
+// `Signature` does not implement borsh, so the signature is written as its raw 64 bytes.
+impl BorshSerialize for PhoenixEvent {
+    fn serialize<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.market.serialize(writer)?;
+        self.sequence_number.serialize(writer)?;
+        self.slot.serialize(writer)?;
+        self.timestamp.serialize(writer)?;
+        writer.write_all(self.signature.as_ref())?;
+        self.signer.serialize(writer)?;
+        self.event_index.serialize(writer)?;
+        self.details.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for PhoenixEvent {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        let market = Pubkey::deserialize(buf)?;
+        let sequence_number = u64::deserialize(buf)?;
+        let slot = u64::deserialize(buf)?;
+        let timestamp = i64::deserialize(buf)?;
+        let signature_bytes = <[u8; 64]>::deserialize(buf)?;
+        let signature = Signature::try_from(signature_bytes.as_slice())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        let signer = Pubkey::deserialize(buf)?;
+        let event_index = u64::deserialize(buf)?;
+        let details = MarketEventDetails::deserialize(buf)?;
+        Ok(PhoenixEvent {
+            market,
+            sequence_number,
+            slot,
+            timestamp,
+            signature,
+            signer,
+            event_index,
+            details,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::event;
+
+    #[test]
+    fn test_fill_sides() {
+        let (maker, taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // A taker buy sweeping the asks fills resting asks, which have plain sequence numbers
+        let buy = Fill::new(7, maker, taker, 100, 5, 0);
+        assert_eq!(buy.side_filled, Side::Ask);
+        assert_eq!(buy.maker_side, Side::Ask);
+        assert_eq!(buy.taker_side, Side::Bid);
+        assert_eq!(buy.trade_direction(), 1);
+        assert!(buy.is_full_fill);
+
+        // A taker sell hitting the bids fills resting bids, whose sequence numbers are inverted
+        let sell = Fill::new(!7, maker, taker, 99, 5, 3);
+        assert_eq!(sell.side_filled, Side::Bid);
+        assert_eq!(sell.maker_side, Side::Bid);
+        assert_eq!(sell.taker_side, Side::Ask);
+        assert_eq!(sell.trade_direction(), -1);
+        assert!(!sell.is_full_fill);
+    }
+
+    #[test]
+    fn test_event_time() {
+        let stamped = |slot, timestamp| PhoenixEvent {
+            sequence_number: slot,
+            slot,
+            timestamp,
+            ..event(Pubkey::default(), MarketEventDetails::Fee(0))
+        };
+        let known = stamped(100, 1_700_000_000);
+        assert_eq!(known.timestamp_seconds(), 1_700_000_000);
+        assert_eq!(known.timestamp_millis(), 1_700_000_000_000);
+        assert_eq!(
+            known.time(),
+            EventTime {
+                unix_seconds: 1_700_000_000,
+                slot: 100
+            }
+        );
+
+        #[cfg(feature = "chrono")]
+        {
+            assert_eq!(known.datetime().unwrap().timestamp(), 1_700_000_000);
+            assert_eq!(stamped(100, 0).datetime(), None);
+        }
+
+        // 25 slots of 400ms are 10s, and estimates work backwards too
+        assert_eq!(
+            known.time().estimate_at_slot(125).unix_seconds,
+            1_700_000_010
+        );
+        assert_eq!(
+            known.time().estimate_at_slot(99).unix_seconds,
+            1_699_999_999
+        );
+        assert_eq!(
+            stamped(110, 1_700_000_009)
+                .time()
+                .or_estimate_from(&known.time()),
+            stamped(110, 1_700_000_009).time()
+        );
+
+        let mut events = [stamped(95, 0), known, stamped(105, 0), stamped(200, 0)];
+        estimate_missing_timestamps(&mut events);
+        let timestamps = events.map(|e| e.timestamp_seconds());
+        assert_eq!(
+            timestamps,
+            [1_699_999_998, 1_700_000_000, 1_700_000_002, 1_700_000_040]
+        );
+
+        let mut unknown = [stamped(1, 0), stamped(2, 0)];
+        estimate_missing_timestamps(&mut unknown);
+        assert!(unknown.iter().all(|e| !e.time().is_known()));
+    }
+}
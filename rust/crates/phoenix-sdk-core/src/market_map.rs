@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+pub use arc_swap::Guard;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::sdk_client_core::MarketMetadata;
+
+/// Market metadata by market address, shared between threads without a lock.
+///
+/// Readers load the current map, which only costs a few atomic operations and never waits on a
+/// writer. Writers copy the map, change the copy and swap it in, so every read sees the map as
+/// it was before or after a write, never in between. Writes are expected to be rare, such as
+/// adding a market at startup, while conversions read the map on every call.
+#[derive(Default)]
+pub struct MarketMap {
+    markets: ArcSwap<BTreeMap<Pubkey, MarketMetadata>>,
+}
+
+impl MarketMap {
+    pub fn new(markets: BTreeMap<Pubkey, MarketMetadata>) -> Self {
+        Self {
+            markets: ArcSwap::from_pointee(markets),
+        }
+    }
+
+    /// The current map. Hold it only briefly: a held guard keeps the map it loaded alive, and
+    /// holding many of them makes loads slower. Use `snapshot` to keep a map around.
+    pub fn load(&self) -> Guard<Arc<BTreeMap<Pubkey, MarketMetadata>>> {
+        self.markets.load()
+    }
+
+    /// The current map, to keep.
+    pub fn snapshot(&self) -> Arc<BTreeMap<Pubkey, MarketMetadata>> {
+        self.markets.load_full()
+    }
+
+    pub fn get(&self, market_key: &Pubkey) -> Option<MarketMetadata> {
+        self.markets.load().get(market_key).copied()
+    }
+
+    pub fn contains_key(&self, market_key: &Pubkey) -> bool {
+        self.markets.load().contains_key(market_key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.markets.load().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.markets.load().is_empty()
+    }
+
+    pub fn keys(&self) -> Vec<Pubkey> {
+        self.markets.load().keys().copied().collect()
+    }
+
+    /// Adds or replaces a market's metadata, returning the metadata it replaced.
+    pub fn insert(&self, market_key: Pubkey, metadata: MarketMetadata) -> Option<MarketMetadata> {
+        self.update(|markets| markets.insert(market_key, metadata))
+    }
+
+    pub fn remove(&self, market_key: &Pubkey) -> Option<MarketMetadata> {
+        self.update(|markets| markets.remove(market_key))
+    }
+
+    /// Adds or replaces several markets in a single swap.
+    pub fn extend(&self, markets: impl IntoIterator<Item = (Pubkey, MarketMetadata)>) {
+        let markets = markets.into_iter().collect::<Vec<_>>();
+        self.update(|current| current.extend(markets.iter().copied()));
+    }
+
+    /// Applies `f` to a copy of the map and swaps the copy in. `f` runs again if another write
+    /// lands in the meantime, so it should not have side effects.
+    fn update<T>(&self, mut f: impl FnMut(&mut BTreeMap<Pubkey, MarketMetadata>) -> T) -> T {
+        let mut result = None;
+        self.markets.rcu(|current| {
+            let mut markets = BTreeMap::clone(current);
+            result = Some(f(&mut markets));
+            markets
+        });
+        result.expect("rcu runs the update at least once")
+    }
+}
+
+impl Clone for MarketMap {
+    /// A new map that starts out with this map's markets. Writes to either are not seen by the
+    /// other.
+    fn clone(&self) -> Self {
+        Self {
+            markets: ArcSwap::new(self.snapshot()),
+        }
+    }
+}
+
+impl fmt::Debug for MarketMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.load().iter()).finish()
+    }
+}
+
+impl From<BTreeMap<Pubkey, MarketMetadata>> for MarketMap {
+    fn from(markets: BTreeMap<Pubkey, MarketMetadata>) -> Self {
+        Self::new(markets)
+    }
+}
+
+impl FromIterator<(Pubkey, MarketMetadata)> for MarketMap {
+    fn from_iter<I: IntoIterator<Item = (Pubkey, MarketMetadata)>>(markets: I) -> Self {
+        Self::new(markets.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    use super::*;
+    use crate::sdk_client_core::SDKClientCore;
+
+    /// Metadata whose sizes all derive from `n`, so that a read mixing two markets' metadata
+    /// would be caught.
+    fn metadata(n: u64) -> MarketMetadata {
+        MarketMetadata {
+            base_atoms_per_raw_base_unit: 1_000_000,
+            quote_atoms_per_quote_unit: 1_000_000,
+            quote_atoms_per_quote_lot: n,
+            base_atoms_per_base_lot: 1000 * n,
+            tick_size_in_quote_atoms_per_base_unit: 10 * n,
+            num_base_lots_per_base_unit: 1000 / n,
+            raw_base_units_per_base_unit: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let markets = MarketMap::default();
+        let market = Pubkey::new_unique();
+        let before = markets.snapshot();
+        let lot_size = |meta: Option<MarketMetadata>| meta.map(|m| m.quote_atoms_per_quote_lot);
+        assert_eq!(lot_size(markets.insert(market, metadata(1))), None);
+        assert_eq!(lot_size(markets.insert(market, metadata(2))), Some(1));
+        // Snapshots are not changed by later writes, and clones are independent
+        assert!(before.is_empty());
+        let clone = markets.clone();
+        assert_eq!(lot_size(markets.remove(&market)), Some(2));
+        assert!(markets.is_empty());
+        assert_eq!(lot_size(clone.get(&market)), Some(2));
+    }
+
+    #[test]
+    fn test_add_markets_during_conversions() {
+        let first = Pubkey::new_unique();
+        let core = SDKClientCore {
+            markets: MarketMap::from_iter([(first, metadata(1))]),
+            trader: Pubkey::new_unique(),
+            default_self_trade_behavior: Default::default(),
+        };
+        let new_markets = (1..=500)
+            .map(|i| (Pubkey::new_unique(), metadata(i % 10 + 1)))
+            .collect::<Vec<_>>();
+        let done = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            let readers = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut conversions = 0u64;
+                        let mut seen = 0;
+                        loop {
+                            assert_eq!(core.ticks_to_float_price(&first, 100).unwrap(), 0.001);
+                            // Every market a reader sees is whole, and markets only get added
+                            let markets = core.markets.load();
+                            assert!(markets.len() >= seen);
+                            seen = markets.len();
+                            for meta in markets.values() {
+                                let n = meta.quote_atoms_per_quote_lot;
+                                assert_eq!(meta.base_atoms_per_base_lot, 1000 * n);
+                                assert_eq!(meta.tick_size_in_quote_atoms_per_base_unit, 10 * n);
+                            }
+                            conversions += 1;
+                            if done.load(Ordering::Acquire) {
+                                return conversions;
+                            }
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            // Two writers, so that some of their copies race and are retried
+            let (left, right) = new_markets.split_at(new_markets.len() / 2);
+            let writers = [left, right].map(|markets| {
+                let core = &core;
+                scope.spawn(move || {
+                    for (market, meta) in markets {
+                        core.markets.insert(*market, *meta);
+                    }
+                })
+            });
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            done.store(true, Ordering::Release);
+            for reader in readers {
+                assert!(reader.join().unwrap() > 0);
+            }
+        });
+        assert_eq!(core.markets.len(), new_markets.len() + 1);
+        for (market, meta) in &new_markets {
+            assert_eq!(
+                core.markets.get(market).unwrap().quote_atoms_per_quote_lot,
+                meta.quote_atoms_per_quote_lot
+            );
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+/// Provides `FIFOOrderId::price_in_ticks()` as a `u64`.
+pub use phoenix::state::markets::OrderId;
+use solana_sdk::hash::hashv;
+
+use crate::market_event::Place;
+
+/// Helpers on `FIFOOrderId` that hide its bit layout. The price accessor is `OrderId`'s
+/// `price_in_ticks()`, re-exported here.
+pub trait FIFOOrderIdExt {
+    /// The order id of the order placed by a `Place` event.
+    fn from_place_event(place: &Place) -> FIFOOrderId;
+
+    /// Bids have the bits of their sequence number inverted, asks do not.
+    fn side(&self) -> Side;
+
+    /// The market's sequence number for the order, with the inversion of bids undone.
+    fn sequence_number_without_side_bit(&self) -> u64;
+}
+
+impl FIFOOrderIdExt for FIFOOrderId {
+    fn from_place_event(place: &Place) -> FIFOOrderId {
+        FIFOOrderId::new_from_untyped(place.price_in_ticks, place.order_sequence_number)
+    }
+
+    fn side(&self) -> Side {
+        Side::from_order_sequence_number(self.order_sequence_number)
+    }
+
+    fn sequence_number_without_side_bit(&self) -> u64 {
+        match self.side() {
+            Side::Bid => !self.order_sequence_number,
+            Side::Ask => self.order_sequence_number,
+        }
+    }
+}
+
+/// A `FIFOOrderId` that displays and parses as `"{price_in_ticks}:{order_sequence_number}"`, the
+/// sequence number as stored in the id (i.e. inverted for bids), so the encoding is lossless.
+///
+/// ```
+/// use phoenix::state::enums::Side;
+/// use phoenix::state::markets::FIFOOrderId;
+/// use phoenix_sdk_core::order_id::{CompactOrderId, FIFOOrderIdExt};
+///
+/// let id: CompactOrderId = "1250:42".parse().unwrap();
+/// assert_eq!(FIFOOrderId::from(id).side(), Side::Ask);
+/// assert_eq!(id.to_string(), "1250:42");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CompactOrderId(pub FIFOOrderId);
+
+impl From<FIFOOrderId> for CompactOrderId {
+    fn from(order_id: FIFOOrderId) -> Self {
+        Self(order_id)
+    }
+}
+
+impl From<CompactOrderId> for FIFOOrderId {
+    fn from(order_id: CompactOrderId) -> Self {
+        order_id.0
+    }
+}
+
+impl fmt::Display for CompactOrderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            self.0.price_in_ticks(),
+            self.0.order_sequence_number
+        )
+    }
+}
+
+impl FromStr for CompactOrderId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (price_in_ticks, order_sequence_number) = s
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Expected an order id of the form price:sequence, got {}", s))?;
+        let parse = |field: &str, name| {
+            field
+                .parse::<u64>()
+                .map_err(|e| anyhow!("Invalid {} in order id {}: {}", name, s, e))
+        };
+        Ok(Self(FIFOOrderId::new_from_untyped(
+            parse(price_in_ticks, "price")?,
+            parse(order_sequence_number, "sequence number")?,
+        )))
+    }
+}
+
+/// Names one order intent of a strategy, e.g. the third quote it decided to place. Every send of
+/// the intent gets the same client order id from `client_order_id`, so that when a retry and the
+/// original both land, `OrderManager` flags the second as a `DuplicateOrder`.
+///
+/// [`DuplicateOrder`]: crate::order_manager::DuplicateOrder
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IntentKey {
+    pub strategy_id: String,
+    pub nonce: u64,
+}
+
+impl IntentKey {
+    pub fn new(strategy_id: impl Into<String>, nonce: u64) -> Self {
+        Self {
+            strategy_id: strategy_id.into(),
+            nonce,
+        }
+    }
+
+    /// The first 16 bytes of the SHA-256 of the strategy id and nonce. Never 0, which Phoenix
+    /// events use for orders without a client order id.
+    pub fn client_order_id(&self) -> u128 {
+        let hash = hashv(&[
+            &(self.strategy_id.len() as u64).to_le_bytes(),
+            self.strategy_id.as_bytes(),
+            &self.nonce.to_le_bytes(),
+        ]);
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&hash.as_ref()[..16]);
+        u128::from_le_bytes(bytes).max(1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip_both_sides() {
+        for (side, raw_sequence_number) in [(Side::Bid, !42), (Side::Ask, 42)] {
+            let place = Place {
+                order_sequence_number: raw_sequence_number,
+                client_order_id: 7,
+                maker: Pubkey::new_unique(),
+                price_in_ticks: 1250,
+                base_lots_placed: 10,
+            };
+            let order_id = FIFOOrderId::from_place_event(&place);
+            assert_eq!(order_id.side(), side);
+            assert_eq!(order_id.sequence_number_without_side_bit(), 42);
+            assert_eq!(order_id.price_in_ticks(), 1250);
+
+            let encoded = CompactOrderId(order_id).to_string();
+            assert_eq!(encoded, format!("1250:{}", raw_sequence_number));
+            let parsed = encoded.parse::<CompactOrderId>().unwrap();
+            assert_eq!(FIFOOrderId::from(parsed), order_id);
+        }
+        assert_eq!(
+            " 0:18446744073709551615 "
+                .parse::<CompactOrderId>()
+                .unwrap()
+                .0,
+            FIFOOrderId::new_from_untyped(0, u64::MAX)
+        );
+
+        for invalid in ["", "1250", "1250:", ":42", "1250:-1", "1250:42:1", "a:42"] {
+            assert!(
+                invalid.parse::<CompactOrderId>().is_err(),
+                "{} should not parse",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_intent_client_order_id() {
+        let key = IntentKey::new("mm-sol-usdc", 3);
+        assert_eq!(key.client_order_id(), key.clone().client_order_id());
+        assert_ne!(key.client_order_id(), 0);
+        for other in [
+            IntentKey::new("mm-sol-usdc", 4),
+            IntentKey::new("mm-sol-usdt", 3),
+            IntentKey::new("", 3),
+        ] {
+            assert_ne!(other.client_order_id(), key.client_order_id());
+        }
+    }
+}
@@ -0,0 +1,777 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::market_event::{
+    Evict, Fill, MarketEventDetails, PhoenixEvent, Place, Reduce, TimeInForce,
+};
+
+/// Where an order is in its life, from submission to leaving the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OrderStatus {
+    /// Sent, but its `Place` event has not been seen.
+    PendingSubmit,
+    /// Resting on the book with nothing filled.
+    Acknowledged,
+    /// Resting on the book with some of it filled.
+    PartiallyFilled,
+    /// Filled in full.
+    Filled,
+    /// A cancel was sent, but its `Reduce` event has not been seen.
+    PendingCancel,
+    /// Canceled in full, by the trader or by eviction.
+    Canceled,
+    /// Removed after its time in force ran out.
+    Expired,
+    /// No `Place` event arrived within the pending timeout. The order may or may not be on the
+    /// book, so it needs reconciling against a snapshot.
+    Lost,
+}
+
+impl OrderStatus {
+    pub const ALL: [OrderStatus; 8] = [
+        OrderStatus::PendingSubmit,
+        OrderStatus::Acknowledged,
+        OrderStatus::PartiallyFilled,
+        OrderStatus::Filled,
+        OrderStatus::PendingCancel,
+        OrderStatus::Canceled,
+        OrderStatus::Expired,
+        OrderStatus::Lost,
+    ];
+
+    /// No transition leaves a terminal status.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            OrderStatus::Filled | OrderStatus::Canceled | OrderStatus::Expired
+        )
+    }
+
+    /// The transition table. A `Lost` order whose `Place` event turns up late is acknowledged,
+    /// and a rejected cancel puts a `PendingCancel` order back to its resting status.
+    pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+        use OrderStatus::*;
+        match self {
+            PendingSubmit => matches!(next, Acknowledged | Lost),
+            Acknowledged => matches!(
+                next,
+                PartiallyFilled | Filled | PendingCancel | Canceled | Expired
+            ),
+            PartiallyFilled => matches!(next, Filled | PendingCancel | Canceled | Expired),
+            PendingCancel => matches!(
+                next,
+                Acknowledged | PartiallyFilled | Filled | Canceled | Expired
+            ),
+            Lost => matches!(next, Acknowledged),
+            Filled | Canceled | Expired => false,
+        }
+    }
+}
+
+/// An order followed by `OrderLifecycle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackedOrder {
+    pub market: Pubkey,
+    pub client_order_id: u128,
+    pub side: Side,
+    /// Known once the `Place` event is seen.
+    pub order_id: Option<FIFOOrderId>,
+    pub status: OrderStatus,
+    pub base_lots_placed: u64,
+    pub base_lots_filled: u64,
+    pub base_lots_remaining: u64,
+    /// When the order was sent, in milliseconds on the caller's clock. `None` for orders first
+    /// seen through their `Place` event.
+    pub submitted_at_ms: Option<u64>,
+    time_in_force: Option<TimeInForce>,
+}
+
+/// A status change of one order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderTransition {
+    pub market: Pubkey,
+    pub client_order_id: u128,
+    pub order_id: Option<FIFOOrderId>,
+    /// `None` when the order was first seen through its `Place` event.
+    pub from: Option<OrderStatus>,
+    pub to: OrderStatus,
+}
+
+/// A transition the table does not allow, e.g. a fill on a canceled order. The order keeps its
+/// status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IllegalTransition {
+    pub market: Pubkey,
+    pub client_order_id: u128,
+    pub order_id: Option<FIFOOrderId>,
+    pub from: OrderStatus,
+    pub to: OrderStatus,
+}
+
+impl fmt::Display for IllegalTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Illegal order transition {:?} -> {:?} on market {} (client order id {}",
+            self.from, self.to, self.market, self.client_order_id
+        )?;
+        if let Some(order_id) = self.order_id {
+            write!(
+                f,
+                ", order sequence number {}",
+                order_id.order_sequence_number
+            )?;
+        }
+        write!(f, ")")
+    }
+}
+
+type TransitionCallback = Box<dyn FnMut(&OrderTransition) + Send>;
+
+/// Tracks the status of a trader's orders from their submissions, cancels and events.
+///
+/// Submitted orders are matched to their `Place` event by market, side and client order id, and
+/// afterwards to their fill, reduce and evict events by order id. Orders that never rest, such
+/// as IOC and FOK orders, get no `Place` event and should not be submitted here.
+///
+/// Orders stay tracked after reaching a terminal status, so that late events on them are caught
+/// as anomalies. `remove_terminal` drops them.
+pub struct OrderLifecycle {
+    pub trader: Pubkey,
+    pending_timeout: Duration,
+    orders: Vec<TrackedOrder>,
+    /// Index into `orders` by market and order sequence number.
+    by_sequence_number: BTreeMap<(Pubkey, u64), usize>,
+    anomalies: Vec<IllegalTransition>,
+    on_transition: Option<TransitionCallback>,
+}
+
+impl OrderLifecycle {
+    /// Orders still `PendingSubmit` after `pending_timeout` are marked `Lost` by `expire_pending`.
+    pub fn new(trader: Pubkey, pending_timeout: Duration) -> Self {
+        Self {
+            trader,
+            pending_timeout,
+            orders: vec![],
+            by_sequence_number: BTreeMap::new(),
+            anomalies: vec![],
+            on_transition: None,
+        }
+    }
+
+    /// Called on every transition, after the order's status has changed.
+    pub fn set_on_transition(
+        &mut self,
+        on_transition: Option<impl FnMut(&OrderTransition) + Send + 'static>,
+    ) {
+        self.on_transition = on_transition.map(|f| Box::new(f) as TransitionCallback);
+    }
+
+    pub fn orders(&self) -> impl Iterator<Item = &TrackedOrder> {
+        self.orders.iter()
+    }
+
+    pub fn order(&self, market: &Pubkey, order_id: &FIFOOrderId) -> Option<&TrackedOrder> {
+        self.by_sequence_number
+            .get(&(*market, order_id.order_sequence_number))
+            .map(|&index| &self.orders[index])
+    }
+
+    /// Illegal transitions seen so far, oldest first.
+    pub fn anomalies(&self) -> &[IllegalTransition] {
+        &self.anomalies
+    }
+
+    /// Records a resting order that has just been sent.
+    pub fn on_submit(
+        &mut self,
+        market: Pubkey,
+        client_order_id: u128,
+        side: Side,
+        num_base_lots: u64,
+        now_ms: u64,
+    ) {
+        self.orders.push(TrackedOrder {
+            market,
+            client_order_id,
+            side,
+            order_id: None,
+            status: OrderStatus::PendingSubmit,
+            base_lots_placed: num_base_lots,
+            base_lots_filled: 0,
+            base_lots_remaining: num_base_lots,
+            submitted_at_ms: Some(now_ms),
+            time_in_force: None,
+        });
+    }
+
+    /// Marks the orders behind a cancel that has just been sent. Unknown ids are ignored.
+    pub fn on_cancel(&mut self, market: &Pubkey, order_ids: &[FIFOOrderId]) {
+        for order_id in order_ids {
+            if let Some(index) = self.index_of(market, order_id.order_sequence_number) {
+                self.transition(index, OrderStatus::PendingCancel);
+            }
+        }
+    }
+
+    /// Puts the orders behind a failed cancel back to their resting status.
+    pub fn on_cancel_rejected(&mut self, market: &Pubkey, order_ids: &[FIFOOrderId]) {
+        for order_id in order_ids {
+            let Some(index) = self.index_of(market, order_id.order_sequence_number) else {
+                continue;
+            };
+            if self.orders[index].status == OrderStatus::PendingCancel {
+                let resting = self.resting_status(index);
+                self.transition(index, resting);
+            }
+        }
+    }
+
+    /// Marks orders submitted more than the pending timeout before `now_ms` and still without a
+    /// `Place` event as `Lost`, and returns them for reconciliation.
+    pub fn expire_pending(&mut self, now_ms: u64) -> Vec<TrackedOrder> {
+        let timeout_ms = self.pending_timeout.as_millis() as u64;
+        let mut lost = vec![];
+        for index in 0..self.orders.len() {
+            let order = &self.orders[index];
+            let timed_out = order
+                .submitted_at_ms
+                .is_some_and(|submitted| now_ms.saturating_sub(submitted) >= timeout_ms);
+            if order.status == OrderStatus::PendingSubmit
+                && timed_out
+                && self.transition(index, OrderStatus::Lost)
+            {
+                lost.push(self.orders[index]);
+            }
+        }
+        lost
+    }
+
+    /// Drops the orders that reached a terminal status and returns them.
+    pub fn remove_terminal(&mut self) -> Vec<TrackedOrder> {
+        let (terminal, live): (Vec<_>, Vec<_>) = self
+            .orders
+            .drain(..)
+            .partition(|order| order.status.is_terminal());
+        self.orders = live;
+        self.by_sequence_number = self
+            .orders
+            .iter()
+            .enumerate()
+            .filter_map(|(index, order)| {
+                order
+                    .order_id
+                    .map(|order_id| ((order.market, order_id.order_sequence_number), index))
+            })
+            .collect();
+        terminal
+    }
+
+    /// Applies a parsed event, returning true if it changed one of the trader's orders.
+    pub fn apply_event(&mut self, event: &PhoenixEvent) -> bool {
+        match event.details {
+            MarketEventDetails::Place(Place {
+                order_sequence_number,
+                client_order_id,
+                maker,
+                price_in_ticks,
+                base_lots_placed,
+            }) if maker == self.trader => {
+                let order_id = FIFOOrderId::new_from_untyped(price_in_ticks, order_sequence_number);
+                if let Some(index) = self.index_of(&event.market, order_sequence_number) {
+                    // The same order placed twice
+                    self.transition(index, OrderStatus::Acknowledged);
+                    return false;
+                }
+                let side = Side::from_order_sequence_number(order_sequence_number);
+                let pending = self.orders.iter().position(|order| {
+                    matches!(order.status, OrderStatus::PendingSubmit | OrderStatus::Lost)
+                        && order.market == event.market
+                        && order.side == side
+                        && order.client_order_id == client_order_id
+                });
+                let index = match pending {
+                    Some(index) => index,
+                    None => {
+                        self.orders.push(TrackedOrder {
+                            market: event.market,
+                            client_order_id,
+                            side,
+                            order_id: None,
+                            status: OrderStatus::Acknowledged,
+                            base_lots_placed,
+                            base_lots_filled: 0,
+                            base_lots_remaining: base_lots_placed,
+                            submitted_at_ms: None,
+                            time_in_force: None,
+                        });
+                        self.orders.len() - 1
+                    }
+                };
+                self.by_sequence_number
+                    .insert((event.market, order_sequence_number), index);
+                let order = &mut self.orders[index];
+                order.order_id = Some(order_id);
+                // The order rests with what is left after crossing
+                order.base_lots_remaining = base_lots_placed;
+                if pending.is_some() {
+                    self.transition(index, OrderStatus::Acknowledged);
+                } else {
+                    self.notify(index, None);
+                }
+                true
+            }
+            MarketEventDetails::TimeInForce(time_in_force) => {
+                match self.index_of(&event.market, time_in_force.order_sequence_number) {
+                    Some(index) => {
+                        self.orders[index].time_in_force = Some(time_in_force);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            MarketEventDetails::Fill(Fill {
+                maker,
+                order_sequence_number,
+                base_lots_filled,
+                base_lots_remaining,
+                ..
+            }) if maker == self.trader => {
+                let Some(index) = self.index_of(&event.market, order_sequence_number) else {
+                    return false;
+                };
+                let status = self.orders[index].status;
+                let next = if base_lots_remaining == 0 {
+                    OrderStatus::Filled
+                } else if status == OrderStatus::Acknowledged {
+                    OrderStatus::PartiallyFilled
+                } else {
+                    // Further partial fills, including ones racing a cancel, keep the status
+                    status
+                };
+                if next != status && !self.transition(index, next) {
+                    return false;
+                }
+                if status.is_terminal() {
+                    self.record_anomaly(index, OrderStatus::PartiallyFilled);
+                    return false;
+                }
+                let order = &mut self.orders[index];
+                order.base_lots_filled += base_lots_filled;
+                order.base_lots_remaining = base_lots_remaining;
+                true
+            }
+            MarketEventDetails::Reduce(Reduce {
+                maker,
+                order_sequence_number,
+                base_lots_remaining,
+                ..
+            }) if maker == self.trader => {
+                let Some(index) = self.index_of(&event.market, order_sequence_number) else {
+                    return false;
+                };
+                if base_lots_remaining > 0 {
+                    if self.orders[index].status.is_terminal() {
+                        self.record_anomaly(index, self.orders[index].status);
+                        return false;
+                    }
+                    self.orders[index].base_lots_remaining = base_lots_remaining;
+                    return true;
+                }
+                let next = if self.is_past_time_in_force(index, event) {
+                    OrderStatus::Expired
+                } else {
+                    OrderStatus::Canceled
+                };
+                if !self.transition(index, next) {
+                    return false;
+                }
+                self.orders[index].base_lots_remaining = 0;
+                true
+            }
+            MarketEventDetails::Evict(Evict {
+                maker,
+                order_sequence_number,
+                ..
+            }) if maker == self.trader => {
+                let Some(index) = self.index_of(&event.market, order_sequence_number) else {
+                    return false;
+                };
+                if !self.transition(index, OrderStatus::Canceled) {
+                    return false;
+                }
+                self.orders[index].base_lots_remaining = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn index_of(&self, market: &Pubkey, order_sequence_number: u64) -> Option<usize> {
+        self.by_sequence_number
+            .get(&(*market, order_sequence_number))
+            .copied()
+    }
+
+    fn resting_status(&self, index: usize) -> OrderStatus {
+        if self.orders[index].base_lots_filled > 0 {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::Acknowledged
+        }
+    }
+
+    /// Whether a full reduce at `event` came after the order's time in force ran out, which is
+    /// how the program reports expired orders.
+    fn is_past_time_in_force(&self, index: usize, event: &PhoenixEvent) -> bool {
+        let Some(time_in_force) = self.orders[index].time_in_force else {
+            return false;
+        };
+        (time_in_force.last_valid_slot != 0 && event.slot > time_in_force.last_valid_slot)
+            || (time_in_force.last_valid_unix_timestamp_in_seconds != 0
+                && event.timestamp > time_in_force.last_valid_unix_timestamp_in_seconds as i64)
+    }
+
+    /// Moves an order to `next` if the table allows it and records an anomaly otherwise.
+    fn transition(&mut self, index: usize, next: OrderStatus) -> bool {
+        let from = self.orders[index].status;
+        if !from.can_transition_to(next) {
+            self.record_anomaly(index, next);
+            return false;
+        }
+        self.orders[index].status = next;
+        self.notify(index, Some(from));
+        true
+    }
+
+    fn notify(&mut self, index: usize, from: Option<OrderStatus>) {
+        let order = &self.orders[index];
+        let transition = OrderTransition {
+            market: order.market,
+            client_order_id: order.client_order_id,
+            order_id: order.order_id,
+            from,
+            to: order.status,
+        };
+        if let Some(on_transition) = self.on_transition.as_mut() {
+            on_transition(&transition);
+        }
+    }
+
+    fn record_anomaly(&mut self, index: usize, to: OrderStatus) {
+        let order = &self.orders[index];
+        let anomaly = IllegalTransition {
+            market: order.market,
+            client_order_id: order.client_order_id,
+            order_id: order.order_id,
+            from: order.status,
+            to,
+        };
+        println!("{}", anomaly);
+        self.anomalies.push(anomaly);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::test_utils::event;
+    use OrderStatus::*;
+
+    type Transitions = Arc<Mutex<Vec<(Option<OrderStatus>, OrderStatus)>>>;
+
+    #[test]
+    fn test_transition_table() {
+        let legal = [
+            (PendingSubmit, Acknowledged),
+            (PendingSubmit, Lost),
+            (Acknowledged, PartiallyFilled),
+            (Acknowledged, Filled),
+            (Acknowledged, PendingCancel),
+            (Acknowledged, Canceled),
+            (Acknowledged, Expired),
+            (PartiallyFilled, Filled),
+            (PartiallyFilled, PendingCancel),
+            (PartiallyFilled, Canceled),
+            (PartiallyFilled, Expired),
+            (PendingCancel, Acknowledged),
+            (PendingCancel, PartiallyFilled),
+            (PendingCancel, Filled),
+            (PendingCancel, Canceled),
+            (PendingCancel, Expired),
+            (Lost, Acknowledged),
+        ];
+        for from in OrderStatus::ALL {
+            for to in OrderStatus::ALL {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    legal.contains(&(from, to)),
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+            }
+            if from.is_terminal() {
+                assert!(OrderStatus::ALL
+                    .iter()
+                    .all(|&to| !from.can_transition_to(to)));
+            }
+        }
+    }
+
+    struct Harness {
+        market: Pubkey,
+        trader: Pubkey,
+        lifecycle: OrderLifecycle,
+        transitions: Transitions,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let trader = Pubkey::new_unique();
+            let mut lifecycle = OrderLifecycle::new(trader, Duration::from_secs(5));
+            let transitions = Arc::new(Mutex::new(vec![]));
+            let recorded = transitions.clone();
+            lifecycle.set_on_transition(Some(move |transition: &OrderTransition| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((transition.from, transition.to))
+            }));
+            Self {
+                market: Pubkey::new_unique(),
+                trader,
+                lifecycle,
+                transitions,
+            }
+        }
+
+        fn apply(&mut self, slot: u64, details: MarketEventDetails) -> bool {
+            self.lifecycle.apply_event(&PhoenixEvent {
+                slot,
+                signer: self.trader,
+                ..event(self.market, details)
+            })
+        }
+
+        fn place(&mut self, order_sequence_number: u64, client_order_id: u128) -> bool {
+            self.apply(
+                0,
+                MarketEventDetails::Place(Place {
+                    order_sequence_number,
+                    client_order_id,
+                    maker: self.trader,
+                    price_in_ticks: 100,
+                    base_lots_placed: 10,
+                }),
+            )
+        }
+
+        fn fill(&mut self, order_sequence_number: u64, filled: u64, remaining: u64) -> bool {
+            self.apply(
+                0,
+                MarketEventDetails::Fill(Fill {
+                    order_sequence_number,
+                    maker: self.trader,
+                    taker: Pubkey::new_unique(),
+                    price_in_ticks: 100,
+                    base_lots_filled: filled,
+                    base_lots_remaining: remaining,
+                    side_filled: Side::Bid,
+                    maker_side: Side::Bid,
+                    taker_side: Side::Ask,
+                    is_full_fill: remaining == 0,
+                }),
+            )
+        }
+
+        fn reduce(&mut self, slot: u64, order_sequence_number: u64, remaining: u64) -> bool {
+            self.apply(
+                slot,
+                MarketEventDetails::Reduce(Reduce {
+                    order_sequence_number,
+                    maker: self.trader,
+                    price_in_ticks: 100,
+                    base_lots_removed: 10 - remaining,
+                    base_lots_remaining: remaining,
+                    is_full_cancel: remaining == 0,
+                }),
+            )
+        }
+
+        fn order_id(order_sequence_number: u64) -> FIFOOrderId {
+            FIFOOrderId::new_from_untyped(100, order_sequence_number)
+        }
+
+        fn status(&self, order_sequence_number: u64) -> OrderStatus {
+            self.lifecycle
+                .order(&self.market, &Self::order_id(order_sequence_number))
+                .unwrap()
+                .status
+        }
+
+        fn take_transitions(&self) -> Vec<(Option<OrderStatus>, OrderStatus)> {
+            std::mem::take(&mut *self.transitions.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn test_fill_lifecycle() {
+        let mut h = Harness::new();
+        let bid = !1;
+        h.lifecycle.on_submit(h.market, 7, Side::Bid, 10, 1_000);
+        assert!(h.place(bid, 7));
+        assert!(h.fill(bid, 4, 6));
+        assert!(h.fill(bid, 3, 3));
+        assert!(h.fill(bid, 3, 0));
+        assert_eq!(
+            h.take_transitions(),
+            vec![
+                (Some(PendingSubmit), Acknowledged),
+                (Some(Acknowledged), PartiallyFilled),
+                (Some(PartiallyFilled), Filled),
+            ]
+        );
+        let order = h
+            .lifecycle
+            .order(&h.market, &Harness::order_id(bid))
+            .unwrap();
+        assert_eq!(order.client_order_id, 7);
+        assert_eq!(order.submitted_at_ms, Some(1_000));
+        assert_eq!((order.base_lots_filled, order.base_lots_remaining), (10, 0));
+        assert!(h.lifecycle.anomalies().is_empty());
+
+        // Orders placed elsewhere are picked up on their place event
+        assert!(h.place(2, 8));
+        assert_eq!(h.take_transitions(), vec![(None, Acknowledged)]);
+        assert_eq!(h.lifecycle.orders().count(), 2);
+        assert_eq!(h.lifecycle.remove_terminal().len(), 1);
+        assert_eq!(h.status(2), Acknowledged);
+
+        // Events on other traders' orders are ignored
+        let other = Pubkey::new_unique();
+        assert!(!h.apply(
+            0,
+            MarketEventDetails::Evict(Evict {
+                order_sequence_number: 2,
+                maker: other,
+                price_in_ticks: 100,
+                base_lots_evicted: 10,
+            })
+        ));
+        assert_eq!(h.status(2), Acknowledged);
+    }
+
+    #[test]
+    fn test_cancel_lifecycle() {
+        let mut h = Harness::new();
+        for (order_sequence_number, client_order_id) in [(1, 1), (2, 2), (3, 3)] {
+            h.lifecycle
+                .on_submit(h.market, client_order_id, Side::Ask, 10, 0);
+            h.place(order_sequence_number, client_order_id);
+        }
+        h.take_transitions();
+
+        // A fill racing the cancel keeps it pending, and a partial reduce changes only the size
+        h.lifecycle.on_cancel(&h.market, &[Harness::order_id(1)]);
+        assert!(h.fill(1, 2, 8));
+        assert_eq!(h.status(1), PendingCancel);
+        assert!(h.reduce(0, 1, 0));
+        assert!(h.reduce(0, 2, 5));
+        assert_eq!(h.status(2), Acknowledged);
+
+        // A rejected cancel goes back to resting
+        h.lifecycle.on_cancel(&h.market, &[Harness::order_id(3)]);
+        h.lifecycle
+            .on_cancel_rejected(&h.market, &[Harness::order_id(3)]);
+        assert!(h.apply(
+            0,
+            MarketEventDetails::Evict(Evict {
+                order_sequence_number: 3,
+                maker: h.trader,
+                price_in_ticks: 100,
+                base_lots_evicted: 10,
+            })
+        ));
+        assert_eq!(
+            h.take_transitions(),
+            vec![
+                (Some(Acknowledged), PendingCancel),
+                (Some(PendingCancel), Canceled),
+                (Some(Acknowledged), PendingCancel),
+                (Some(PendingCancel), Acknowledged),
+                (Some(Acknowledged), Canceled),
+            ]
+        );
+
+        // A fill after the cancel is an anomaly and leaves the order canceled
+        assert!(!h.fill(1, 8, 0));
+        assert!(!h.reduce(0, 3, 0));
+        assert_eq!(h.status(1), Canceled);
+        assert_eq!(
+            h.lifecycle.anomalies(),
+            &[
+                IllegalTransition {
+                    market: h.market,
+                    client_order_id: 1,
+                    order_id: Some(Harness::order_id(1)),
+                    from: Canceled,
+                    to: Filled,
+                },
+                IllegalTransition {
+                    market: h.market,
+                    client_order_id: 3,
+                    order_id: Some(Harness::order_id(3)),
+                    from: Canceled,
+                    to: Canceled,
+                },
+            ]
+        );
+        assert!(h.take_transitions().is_empty());
+    }
+
+    #[test]
+    fn test_lost_and_expired() {
+        let mut h = Harness::new();
+        h.lifecycle.on_submit(h.market, 1, Side::Ask, 10, 0);
+        h.lifecycle.on_submit(h.market, 2, Side::Ask, 10, 3_000);
+        assert!(h.lifecycle.expire_pending(4_999).is_empty());
+        let lost = h.lifecycle.expire_pending(5_000);
+        assert_eq!(lost.len(), 1);
+        assert_eq!((lost[0].client_order_id, lost[0].status), (1, Lost));
+
+        // Reconciliation finds the order after all
+        assert!(h.place(1, 1));
+        assert_eq!(
+            h.take_transitions(),
+            vec![(Some(PendingSubmit), Lost), (Some(Lost), Acknowledged)]
+        );
+
+        // Removed after its last valid slot, so it expired rather than being canceled
+        assert!(h.apply(
+            0,
+            MarketEventDetails::TimeInForce(TimeInForce {
+                order_sequence_number: 1,
+                last_valid_slot: 50,
+                last_valid_unix_timestamp_in_seconds: 0,
+            })
+        ));
+        assert!(h.reduce(51, 1, 0));
+        assert_eq!(h.status(1), Expired);
+        assert_eq!(h.take_transitions(), vec![(Some(Acknowledged), Expired)]);
+        assert_eq!(
+            h.lifecycle
+                .orders()
+                .map(|order| order.status)
+                .collect::<Vec<_>>(),
+            vec![Expired, PendingSubmit]
+        );
+    }
+}
@@ -0,0 +1,873 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use phoenix::quantities::WrapperU64;
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::market_event::{Evict, Fill, MarketEventDetails, PhoenixEvent, Place, Reduce};
+use crate::orderbook::{Orderbook, QueuePosition};
+use crate::sdk_client_core::PhoenixOrder;
+
+/// A resting order of the tracked trader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenOrder {
+    pub order_id: FIFOOrderId,
+    pub side: Side,
+    pub client_order_id: u128,
+    pub num_base_lots: u64,
+}
+
+impl OpenOrder {
+    pub fn price_in_ticks(&self) -> u64 {
+        self.order_id.price_in_ticks.as_u64()
+    }
+}
+
+/// What `OrderManager` does about a duplicate order, see `DuplicateOrder`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateOrderPolicy {
+    /// Record the duplicate and print a warning.
+    #[default]
+    Warn,
+    /// Also queue the duplicate to be cancelled, see `OrderManager::take_duplicates_to_cancel`.
+    Cancel,
+}
+
+/// An order placed with the client order id of one of the trader's open orders on the same
+/// market, by another transaction. This is what a retried send looks like when both the original
+/// and the retry land, e.g. with different blockhashes. Orders that share a client order id
+/// because one transaction placed them, like the orders of a `PlaceMultiplePostOnlyOrders`, are
+/// not duplicates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DuplicateOrder {
+    pub market: Pubkey,
+    pub client_order_id: u128,
+    /// The open order that was placed first.
+    pub original: FIFOOrderId,
+    pub duplicate: FIFOOrderId,
+    /// The transaction that placed the duplicate.
+    pub signature: Signature,
+}
+
+impl fmt::Display for DuplicateOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Duplicate order for client order id {} on market {}: order {} placed by {} while order {} is open",
+            self.client_order_id,
+            self.market,
+            self.duplicate.order_sequence_number,
+            self.signature,
+            self.original.order_sequence_number,
+        )
+    }
+}
+
+/// How an open order left the book, passed to the callback set with
+/// `OrderManager::set_on_order_ended`. Each holds the order as it was last tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderEnded {
+    Filled(OpenOrder),
+    /// Cancelled by the trader, or reduced to nothing.
+    Cancelled(OpenOrder),
+    /// Removed together with the trader's seat. The trader can no longer place orders on the
+    /// market until a seat is approved again.
+    Evicted(OpenOrder),
+}
+
+impl OrderEnded {
+    pub fn order(&self) -> &OpenOrder {
+        match self {
+            OrderEnded::Filled(order)
+            | OrderEnded::Cancelled(order)
+            | OrderEnded::Evicted(order) => order,
+        }
+    }
+}
+
+type OrderEndedCallback = Arc<dyn Fn(&PhoenixEvent, &OrderEnded) + Send + Sync>;
+
+/// `FIFOOrderId` orders bids and asks in opposite directions, so each side needs its own map.
+#[derive(Debug, Clone, Default)]
+struct MarketOrders {
+    bids: BTreeMap<FIFOOrderId, OpenOrder>,
+    asks: BTreeMap<FIFOOrderId, OpenOrder>,
+    /// Orders reduced by `reduce_pending`, as they were before the reduce.
+    pending_reduces: BTreeMap<FIFOOrderId, OpenOrder>,
+    /// The transactions that placed the open orders with a client order id.
+    placed_by: BTreeMap<FIFOOrderId, Signature>,
+    /// The timestamps of the open orders' place events, by order sequence number. Bid and ask
+    /// ids do not share an order, so they cannot share a `FIFOOrderId` key.
+    placed_at: BTreeMap<u64, i64>,
+}
+
+impl MarketOrders {
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<FIFOOrderId, OpenOrder> {
+        match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        }
+    }
+}
+
+/// Tracks a trader's resting orders across markets from their place, fill, reduce and evict events.
+/// `OrderLifecycle` follows the same orders through their statuses, from submission on.
+#[derive(Clone)]
+pub struct OrderManager {
+    pub trader: Pubkey,
+    orders: BTreeMap<Pubkey, MarketOrders>,
+    duplicate_policy: DuplicateOrderPolicy,
+    duplicates: Vec<DuplicateOrder>,
+    duplicates_to_cancel: Vec<DuplicateOrder>,
+    on_order_ended: Option<OrderEndedCallback>,
+}
+
+impl fmt::Debug for OrderManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OrderManager")
+            .field("trader", &self.trader)
+            .field("orders", &self.orders)
+            .field("duplicate_policy", &self.duplicate_policy)
+            .field("duplicates", &self.duplicates)
+            .field("duplicates_to_cancel", &self.duplicates_to_cancel)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OrderManager {
+    pub fn new(trader: Pubkey) -> Self {
+        Self {
+            trader,
+            orders: BTreeMap::new(),
+            duplicate_policy: DuplicateOrderPolicy::default(),
+            duplicates: vec![],
+            duplicates_to_cancel: vec![],
+            on_order_ended: None,
+        }
+    }
+
+    /// Called with the event that removed each of the trader's open orders. Clones of the manager
+    /// share the callback.
+    pub fn set_on_order_ended(
+        &mut self,
+        on_order_ended: Option<impl Fn(&PhoenixEvent, &OrderEnded) + Send + Sync + 'static>,
+    ) {
+        self.on_order_ended = on_order_ended.map(|f| Arc::new(f) as OrderEndedCallback);
+    }
+
+    pub fn set_duplicate_policy(&mut self, policy: DuplicateOrderPolicy) {
+        self.duplicate_policy = policy;
+    }
+
+    pub fn duplicate_policy(&self) -> DuplicateOrderPolicy {
+        self.duplicate_policy
+    }
+
+    /// Every duplicate order seen so far, oldest first.
+    pub fn duplicates(&self) -> &[DuplicateOrder] {
+        &self.duplicates
+    }
+
+    /// Returns the duplicates to cancel under `DuplicateOrderPolicy::Cancel` and forgets them,
+    /// so that each is handed out once. Duplicates that are no longer open are left out.
+    pub fn take_duplicates_to_cancel(&mut self) -> Vec<DuplicateOrder> {
+        let mut duplicates = std::mem::take(&mut self.duplicates_to_cancel);
+        duplicates.retain(|duplicate| {
+            self.orders.get(&duplicate.market).is_some_and(|orders| {
+                let side =
+                    Side::from_order_sequence_number(duplicate.duplicate.order_sequence_number);
+                match side {
+                    Side::Bid => orders.bids.contains_key(&duplicate.duplicate),
+                    Side::Ask => orders.asks.contains_key(&duplicate.duplicate),
+                }
+            })
+        });
+        duplicates
+    }
+
+    /// Returns the open orders on a market, bids best-first followed by asks best-first.
+    pub fn open_orders(&self, market: &Pubkey) -> impl Iterator<Item = &OpenOrder> {
+        self.orders
+            .get(market)
+            .into_iter()
+            .flat_map(|orders| orders.bids.values().chain(orders.asks.values()))
+    }
+
+    /// The markets with open orders.
+    pub fn markets(&self) -> impl Iterator<Item = &Pubkey> {
+        self.orders
+            .iter()
+            .filter(|(_, orders)| !orders.bids.is_empty() || !orders.asks.is_empty())
+            .map(|(market, _)| market)
+    }
+
+    /// The unix timestamp of the place event of an open order on `market`, e.g. to find orders
+    /// that have rested for too long. `None` if the order is not open.
+    pub fn placed_at(&self, market: &Pubkey, order_id: &FIFOOrderId) -> Option<i64> {
+        self.orders
+            .get(market)?
+            .placed_at
+            .get(&order_id.order_sequence_number)
+            .copied()
+    }
+
+    pub fn open_order_count(&self) -> usize {
+        self.orders
+            .values()
+            .map(|orders| orders.bids.len() + orders.asks.len())
+            .sum()
+    }
+
+    /// Total size of the open orders on one side of a market.
+    pub fn resting_base_lots(&self, market: &Pubkey, side: Side) -> u64 {
+        self.open_orders(market)
+            .filter(|order| order.side == side)
+            .map(|order| order.num_base_lots)
+            .sum()
+    }
+
+    /// Pairs each open order on `market` with its queue position in `book`, a snapshot of that
+    /// market, in the order of `open_orders`. Orders missing from the book, e.g. because the
+    /// snapshot predates them, get `None`.
+    pub fn queue_positions(
+        &self,
+        market: &Pubkey,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+    ) -> Vec<(&OpenOrder, Option<QueuePosition>)> {
+        let Some(orders) = self.orders.get(market) else {
+            return vec![];
+        };
+        let mut result = vec![];
+        for (side, side_orders) in [(Side::Bid, &orders.bids), (Side::Ask, &orders.asks)] {
+            let positions = book.queue_positions(side, &self.trader);
+            result.extend(
+                side_orders
+                    .iter()
+                    .map(|(order_id, order)| (order, positions.get(order_id).copied())),
+            );
+        }
+        result
+    }
+
+    /// A book holding only the trader's open orders on `market`. It stands in for a fresh
+    /// snapshot in `SDKClientCore::check_self_cross`, which only looks at the trader's orders.
+    pub fn to_orderbook(&self, market: &Pubkey) -> Orderbook<FIFOOrderId, PhoenixOrder> {
+        let mut book = Orderbook::default();
+        for order in self.open_orders(market) {
+            let resting = PhoenixOrder {
+                num_base_lots: order.num_base_lots,
+                maker_id: self.trader,
+            };
+            match order.side {
+                Side::Bid => book.bids.insert(order.order_id, resting),
+                Side::Ask => book.asks.insert(order.order_id, resting),
+            };
+        }
+        book
+    }
+
+    /// Shrinks an open order by `lots_to_remove` ahead of its reduce instruction landing, e.g. for
+    /// `AmendPath::Reduce`. The next event for the order confirms it: a reduce event, or a fill or
+    /// evict that replaces the optimistic size with the order's actual size. Until then
+    /// `revert_pending_reduce` undoes it. Returns false if the order is not open.
+    pub fn reduce_pending(
+        &mut self,
+        market: &Pubkey,
+        order_id: FIFOOrderId,
+        lots_to_remove: u64,
+    ) -> bool {
+        let Some(orders) = self.orders.get_mut(market) else {
+            return false;
+        };
+        let side = Side::from_order_sequence_number(order_id.order_sequence_number);
+        let Some(order) = orders.side_mut(side).get(&order_id).copied() else {
+            return false;
+        };
+        orders.pending_reduces.entry(order_id).or_insert(order);
+        if lots_to_remove >= order.num_base_lots {
+            orders.side_mut(side).remove(&order_id);
+        } else if let Some(order) = orders.side_mut(side).get_mut(&order_id) {
+            order.num_base_lots -= lots_to_remove;
+        }
+        true
+    }
+
+    /// Restores an order's size from before `reduce_pending`, e.g. because the reduce failed.
+    /// Returns false if no reduce is pending, including once an event has confirmed it.
+    pub fn revert_pending_reduce(&mut self, market: &Pubkey, order_id: FIFOOrderId) -> bool {
+        let Some(orders) = self.orders.get_mut(market) else {
+            return false;
+        };
+        let Some(order) = orders.pending_reduces.remove(&order_id) else {
+            return false;
+        };
+        orders.side_mut(order.side).insert(order_id, order);
+        true
+    }
+
+    pub fn is_reduce_pending(&self, market: &Pubkey, order_id: &FIFOOrderId) -> bool {
+        self.orders
+            .get(market)
+            .is_some_and(|orders| orders.pending_reduces.contains_key(order_id))
+    }
+
+    /// Applies a parsed event, returning true if it changed one of the trader's orders.
+    pub fn apply_event(&mut self, event: &PhoenixEvent) -> bool {
+        let (maker, order_sequence_number, price_in_ticks, base_lots_remaining) = match event
+            .details
+        {
+            MarketEventDetails::Place(Place {
+                order_sequence_number,
+                client_order_id,
+                maker,
+                price_in_ticks,
+                base_lots_placed,
+            }) => {
+                if maker != self.trader {
+                    return false;
+                }
+                let order_id = FIFOOrderId::new_from_untyped(price_in_ticks, order_sequence_number);
+                let side = Side::from_order_sequence_number(order_sequence_number);
+                let orders = self.orders.entry(event.market).or_default();
+                orders
+                    .placed_at
+                    .insert(order_sequence_number, event.timestamp);
+                if client_order_id != 0 {
+                    let original = orders
+                        .bids
+                        .values()
+                        .chain(orders.asks.values())
+                        .find(|order| {
+                            order.client_order_id == client_order_id
+                                && order.order_id != order_id
+                                && orders.placed_by.get(&order.order_id) != Some(&event.signature)
+                        })
+                        .map(|order| order.order_id);
+                    orders.placed_by.insert(order_id, event.signature);
+                    if let Some(original) = original {
+                        self.record_duplicate(DuplicateOrder {
+                            market: event.market,
+                            client_order_id,
+                            original,
+                            duplicate: order_id,
+                            signature: event.signature,
+                        });
+                    }
+                }
+                self.orders
+                    .entry(event.market)
+                    .or_default()
+                    .side_mut(side)
+                    .insert(
+                        order_id,
+                        OpenOrder {
+                            order_id,
+                            side,
+                            client_order_id,
+                            num_base_lots: base_lots_placed,
+                        },
+                    );
+                return true;
+            }
+            MarketEventDetails::Fill(Fill {
+                maker,
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_remaining,
+                ..
+            })
+            | MarketEventDetails::Reduce(Reduce {
+                maker,
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_remaining,
+                ..
+            }) => (
+                maker,
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_remaining,
+            ),
+            MarketEventDetails::Evict(Evict {
+                maker,
+                order_sequence_number,
+                price_in_ticks,
+                ..
+            }) => (maker, order_sequence_number, price_in_ticks, 0),
+            _ => return false,
+        };
+        if maker != self.trader {
+            return false;
+        }
+        let Some(orders) = self.orders.get_mut(&event.market) else {
+            return false;
+        };
+        let order_id = FIFOOrderId::new_from_untyped(price_in_ticks, order_sequence_number);
+        let pending_reduce = orders.pending_reduces.remove(&order_id);
+        if base_lots_remaining == 0 {
+            orders.placed_by.remove(&order_id);
+            orders.placed_at.remove(&order_sequence_number);
+        }
+        let orders = orders.side_mut(Side::from_order_sequence_number(order_sequence_number));
+        if base_lots_remaining == 0 {
+            let removed = orders.remove(&order_id);
+            // An order cancelled by `reduce_pending` ends when its reduce lands
+            if let (Some(order), Some(on_order_ended)) =
+                (removed.or(pending_reduce), self.on_order_ended.as_ref())
+            {
+                let ended = match event.details {
+                    MarketEventDetails::Fill(_) => OrderEnded::Filled(order),
+                    MarketEventDetails::Evict(_) => OrderEnded::Evicted(order),
+                    _ => OrderEnded::Cancelled(order),
+                };
+                on_order_ended(event, &ended);
+            }
+            return removed.is_some();
+        }
+        // An order that was optimistically cancelled is still open until its reduce lands
+        if let Some(order) = pending_reduce {
+            orders.entry(order_id).or_insert(order);
+        }
+        match orders.get_mut(&order_id) {
+            Some(order) => {
+                order.num_base_lots = base_lots_remaining;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn record_duplicate(&mut self, duplicate: DuplicateOrder) {
+        println!("{}", duplicate);
+        if self.duplicate_policy == DuplicateOrderPolicy::Cancel {
+            self.duplicates_to_cancel.push(duplicate);
+        }
+        self.duplicates.push(duplicate);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sdk_client_core::SDKClientCore;
+    use crate::test_utils::event;
+
+    #[test]
+    fn test_order_lifecycle() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let by_trader = |details| PhoenixEvent {
+            signer: trader,
+            ..event(market, details)
+        };
+        let place = |order_sequence_number, maker| {
+            by_trader(MarketEventDetails::Place(Place {
+                order_sequence_number,
+                client_order_id: 1,
+                maker,
+                price_in_ticks: 100,
+                base_lots_placed: 10,
+            }))
+        };
+
+        let mut manager = OrderManager::new(trader);
+        assert!(manager.apply_event(&place(!1, trader)));
+        assert!(manager.apply_event(&place(2, trader)));
+        assert!(!manager.apply_event(&place(3, Pubkey::new_unique())));
+        assert_eq!(manager.open_order_count(), 2);
+        assert_eq!(manager.resting_base_lots(&market, Side::Bid), 10);
+
+        assert!(
+            manager.apply_event(&by_trader(MarketEventDetails::Fill(Fill {
+                order_sequence_number: !1,
+                maker: trader,
+                taker: Pubkey::new_unique(),
+                price_in_ticks: 100,
+                base_lots_filled: 4,
+                base_lots_remaining: 6,
+                side_filled: Side::Bid,
+                maker_side: Side::Bid,
+                taker_side: Side::Bid.opposite(),
+                is_full_fill: false,
+            })))
+        );
+        assert_eq!(manager.resting_base_lots(&market, Side::Bid), 6);
+        let bid = FIFOOrderId::new_from_untyped(100, !1);
+        assert_eq!(manager.placed_at(&market, &bid), Some(0));
+
+        assert!(
+            manager.apply_event(&by_trader(MarketEventDetails::Reduce(Reduce {
+                order_sequence_number: 2,
+                maker: trader,
+                price_in_ticks: 100,
+                base_lots_removed: 10,
+                base_lots_remaining: 0,
+                is_full_cancel: true,
+            })))
+        );
+        assert!(
+            manager.apply_event(&by_trader(MarketEventDetails::Evict(Evict {
+                order_sequence_number: !1,
+                maker: trader,
+                price_in_ticks: 100,
+                base_lots_evicted: 6,
+            })))
+        );
+        assert_eq!(manager.open_order_count(), 0);
+        assert_eq!(manager.placed_at(&market, &bid), None);
+    }
+
+    #[test]
+    fn test_order_ended() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let by_trader = |details| PhoenixEvent {
+            signer: trader,
+            ..event(market, details)
+        };
+        let mut manager = OrderManager::new(trader);
+        let ended = Arc::new(std::sync::Mutex::new(vec![]));
+        let recorded = ended.clone();
+        manager.set_on_order_ended(Some(move |_: &PhoenixEvent, ended: &OrderEnded| {
+            recorded.lock().unwrap().push(*ended)
+        }));
+        for order_sequence_number in [!1, !2, 3] {
+            manager.apply_event(&by_trader(MarketEventDetails::Place(Place {
+                order_sequence_number,
+                client_order_id: 0,
+                maker: trader,
+                price_in_ticks: 100,
+                base_lots_placed: 10,
+            })));
+        }
+        let order = |order_sequence_number| OpenOrder {
+            order_id: FIFOOrderId::new_from_untyped(100, order_sequence_number),
+            side: Side::from_order_sequence_number(order_sequence_number),
+            client_order_id: 0,
+            num_base_lots: 10,
+        };
+
+        // Partial fills and other traders' evictions end nothing
+        manager.apply_event(&by_trader(MarketEventDetails::Fill(Fill {
+            order_sequence_number: !1,
+            maker: trader,
+            taker: Pubkey::new_unique(),
+            price_in_ticks: 100,
+            base_lots_filled: 4,
+            base_lots_remaining: 6,
+            side_filled: Side::Bid,
+            maker_side: Side::Bid,
+            taker_side: Side::Ask,
+            is_full_fill: false,
+        })));
+        manager.apply_event(&by_trader(MarketEventDetails::Evict(Evict {
+            order_sequence_number: !2,
+            maker: Pubkey::new_unique(),
+            price_in_ticks: 100,
+            base_lots_evicted: 10,
+        })));
+        assert!(ended.lock().unwrap().is_empty());
+
+        // An optimistic cancel ends the order once its reduce lands
+        let ask = FIFOOrderId::new_from_untyped(100, 3);
+        assert!(manager.reduce_pending(&market, ask, 10));
+        manager.apply_event(&by_trader(MarketEventDetails::Reduce(Reduce {
+            order_sequence_number: 3,
+            maker: trader,
+            price_in_ticks: 100,
+            base_lots_removed: 10,
+            base_lots_remaining: 0,
+            is_full_cancel: true,
+        })));
+        for order_sequence_number in [!1, !2] {
+            manager.apply_event(&by_trader(MarketEventDetails::Evict(Evict {
+                order_sequence_number,
+                maker: trader,
+                price_in_ticks: 100,
+                base_lots_evicted: 10,
+            })));
+        }
+        assert_eq!(
+            *ended.lock().unwrap(),
+            vec![
+                OrderEnded::Cancelled(order(3)),
+                OrderEnded::Evicted(OpenOrder {
+                    num_base_lots: 6,
+                    ..order(!1)
+                }),
+                OrderEnded::Evicted(order(!2)),
+            ]
+        );
+        assert_eq!(manager.open_order_count(), 0);
+    }
+
+    #[test]
+    fn test_pending_reduce() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let by_trader = |details| PhoenixEvent {
+            signer: trader,
+            ..event(market, details)
+        };
+        let reduce = |base_lots_remaining| {
+            by_trader(MarketEventDetails::Reduce(Reduce {
+                order_sequence_number: !1,
+                maker: trader,
+                price_in_ticks: 100,
+                base_lots_removed: 10 - base_lots_remaining,
+                base_lots_remaining,
+                is_full_cancel: base_lots_remaining == 0,
+            }))
+        };
+        let order_id = FIFOOrderId::new_from_untyped(100, !1);
+        let mut manager = OrderManager::new(trader);
+        assert!(!manager.reduce_pending(&market, order_id, 4));
+        manager.apply_event(&by_trader(MarketEventDetails::Place(Place {
+            order_sequence_number: !1,
+            client_order_id: 7,
+            maker: trader,
+            price_in_ticks: 100,
+            base_lots_placed: 10,
+        })));
+
+        // The reduce shows up immediately and is confirmed by its event
+        assert!(manager.reduce_pending(&market, order_id, 4));
+        assert_eq!(manager.resting_base_lots(&market, Side::Bid), 6);
+        assert!(manager.is_reduce_pending(&market, &order_id));
+        assert!(manager.apply_event(&reduce(6)));
+        assert!(!manager.is_reduce_pending(&market, &order_id));
+        assert!(!manager.revert_pending_reduce(&market, order_id));
+        assert_eq!(manager.resting_base_lots(&market, Side::Bid), 6);
+
+        // A failed reduce is reverted, even one that removed the whole order
+        assert!(manager.reduce_pending(&market, order_id, 6));
+        assert_eq!(manager.open_order_count(), 0);
+        assert!(manager.revert_pending_reduce(&market, order_id));
+        let order = *manager.open_orders(&market).next().unwrap();
+        assert_eq!((order.num_base_lots, order.client_order_id), (6, 7));
+
+        // A fill before the reduce lands gives the actual size
+        assert!(manager.reduce_pending(&market, order_id, 6));
+        assert!(
+            manager.apply_event(&by_trader(MarketEventDetails::Fill(Fill {
+                order_sequence_number: !1,
+                maker: trader,
+                taker: Pubkey::new_unique(),
+                price_in_ticks: 100,
+                base_lots_filled: 1,
+                base_lots_remaining: 5,
+                side_filled: Side::Bid,
+                maker_side: Side::Bid,
+                taker_side: Side::Ask,
+                is_full_fill: false,
+            })))
+        );
+        assert_eq!(manager.resting_base_lots(&market, Side::Bid), 5);
+        assert!(!manager.is_reduce_pending(&market, &order_id));
+        assert!(manager.apply_event(&reduce(0)));
+        assert_eq!(manager.open_order_count(), 0);
+    }
+
+    #[test]
+    fn test_duplicate_orders() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let (first, retry) = (Signature::new_unique(), Signature::new_unique());
+        let place = |order_sequence_number, client_order_id, signature| PhoenixEvent {
+            signature,
+            signer: trader,
+            ..event(
+                market,
+                MarketEventDetails::Place(Place {
+                    order_sequence_number,
+                    client_order_id,
+                    maker: trader,
+                    price_in_ticks: 100,
+                    base_lots_placed: 10,
+                }),
+            )
+        };
+
+        // Orders of one transaction may share a client order id, and 0 means none
+        let mut manager = OrderManager::new(trader);
+        for event in [
+            place(!1, 7, first),
+            place(2, 7, first),
+            place(!3, 0, first),
+            place(!4, 0, retry),
+        ] {
+            manager.apply_event(&event);
+        }
+        assert!(manager.duplicates().is_empty());
+
+        // The same client order id from another transaction is a duplicate
+        manager.apply_event(&place(!5, 7, retry));
+        let duplicate = DuplicateOrder {
+            market,
+            client_order_id: 7,
+            original: FIFOOrderId::new_from_untyped(100, !1),
+            duplicate: FIFOOrderId::new_from_untyped(100, !5),
+            signature: retry,
+        };
+        assert_eq!(manager.duplicates(), &[duplicate]);
+        assert!(manager.take_duplicates_to_cancel().is_empty());
+
+        // Under the cancel policy it is queued once, while it is open
+        manager.set_duplicate_policy(DuplicateOrderPolicy::Cancel);
+        manager.apply_event(&place(!6, 7, Signature::new_unique()));
+        manager.apply_event(&place(8, 7, Signature::new_unique()));
+        manager.apply_event(&PhoenixEvent {
+            details: MarketEventDetails::Reduce(Reduce {
+                order_sequence_number: 8,
+                maker: trader,
+                price_in_ticks: 100,
+                base_lots_removed: 10,
+                base_lots_remaining: 0,
+                is_full_cancel: true,
+            }),
+            ..place(0, 0, first)
+        });
+        let to_cancel = manager.take_duplicates_to_cancel();
+        assert_eq!(
+            to_cancel
+                .iter()
+                .map(|duplicate| duplicate.duplicate.order_sequence_number)
+                .collect::<Vec<_>>(),
+            vec![!6]
+        );
+        assert!(manager.take_duplicates_to_cancel().is_empty());
+        assert_eq!(manager.duplicates().len(), 3);
+
+        // Once the original is gone, its client order id is free again
+        let mut manager = OrderManager::new(trader);
+        manager.apply_event(&place(!1, 9, first));
+        manager.apply_event(&PhoenixEvent {
+            details: MarketEventDetails::Evict(Evict {
+                order_sequence_number: !1,
+                maker: trader,
+                price_in_ticks: 100,
+                base_lots_evicted: 10,
+            }),
+            ..place(0, 0, first)
+        });
+        manager.apply_event(&place(!2, 9, retry));
+        assert!(manager.duplicates().is_empty());
+    }
+
+    #[test]
+    fn test_queue_positions() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let place = |order_sequence_number, maker, price_in_ticks| PhoenixEvent {
+            signer: maker,
+            ..event(
+                market,
+                MarketEventDetails::Place(Place {
+                    order_sequence_number,
+                    client_order_id: 0,
+                    maker,
+                    price_in_ticks,
+                    base_lots_placed: 10,
+                }),
+            )
+        };
+        let mut manager = OrderManager::new(trader);
+        let mut book = Orderbook::<FIFOOrderId, PhoenixOrder>::default();
+        let other = Pubkey::new_unique();
+        for event in [
+            place(1, other, 100),
+            place(2, trader, 100),
+            place(!3, trader, 90),
+        ] {
+            manager.apply_event(&event);
+            book.apply_event(&event);
+        }
+        // Placed after the snapshot was taken
+        manager.apply_event(&place(4, trader, 101));
+
+        let positions = manager
+            .queue_positions(&market, &book)
+            .into_iter()
+            .map(|(order, position)| (order.order_id.order_sequence_number, position))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            vec![
+                (!3, Some(QueuePosition::default())),
+                (
+                    2,
+                    Some(QueuePosition {
+                        lots_ahead_at_level: 10,
+                        orders_ahead_at_level: 1,
+                        lots_ahead_total_better_prices: 0,
+                    })
+                ),
+                (4, None),
+            ]
+        );
+        assert!(manager
+            .queue_positions(&Pubkey::new_unique(), &book)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_self_cross_from_cached_orders() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let place = |order_sequence_number, maker, price_in_ticks| PhoenixEvent {
+            signer: maker,
+            ..event(
+                market,
+                MarketEventDetails::Place(Place {
+                    order_sequence_number,
+                    client_order_id: 0,
+                    maker,
+                    price_in_ticks,
+                    base_lots_placed: 10,
+                }),
+            )
+        };
+        let mut manager = OrderManager::new(trader);
+        let mut book = Orderbook::<FIFOOrderId, PhoenixOrder>::default();
+        for event in [
+            place(1, other, 101),
+            place(2, trader, 102),
+            place(3, trader, 105),
+            place(!4, trader, 99),
+            place(!5, other, 100),
+        ] {
+            manager.apply_event(&event);
+            book.apply_event(&event);
+        }
+        let core = SDKClientCore {
+            markets: Default::default(),
+            trader,
+            default_self_trade_behavior: Default::default(),
+        };
+        let crossed = |side, price_in_ticks, book: &Orderbook<FIFOOrderId, PhoenixOrder>| {
+            core.check_self_cross(side, price_in_ticks, book, &trader)
+                .iter()
+                .map(|order_id| order_id.order_sequence_number)
+                .collect::<Vec<_>>()
+        };
+
+        // The cached orders give the same answer as the full book
+        let cached = manager.to_orderbook(&market);
+        for book in [&book, &cached] {
+            assert_eq!(crossed(Side::Bid, 101, book), Vec::<u64>::new());
+            assert_eq!(crossed(Side::Bid, 104, book), vec![2]);
+            assert_eq!(crossed(Side::Bid, u64::MAX, book), vec![2, 3]);
+            assert_eq!(crossed(Side::Ask, 100, book), Vec::<u64>::new());
+            assert_eq!(crossed(Side::Ask, 0, book), vec![!4]);
+        }
+        assert_eq!(
+            core.check_self_cross(Side::Bid, u64::MAX, &book, &other),
+            vec![FIFOOrderId::new_from_untyped(101, 1)]
+        );
+    }
+}
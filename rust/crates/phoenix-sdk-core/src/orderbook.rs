@@ -1,14 +1,17 @@
+use std::collections::btree_map;
 use std::collections::BTreeMap;
+use std::iter::Peekable;
 
 use itertools::Itertools;
 use num_traits::ToPrimitive;
 use phoenix::quantities::WrapperU64;
 use phoenix::state::enums::Side;
-use phoenix::state::markets::{FIFOOrderId, FIFORestingOrder, Market};
+use phoenix::state::markets::{FIFOOrderId, FIFORestingOrder, LadderOrder, Market};
 use phoenix::state::OrderPacket;
 use rust_decimal::Decimal;
 use solana_sdk::pubkey::Pubkey;
 
+use crate::market_event::{Evict, Fill, MarketEventDetails, PhoenixEvent, Place, Reduce};
 use crate::sdk_client_core::PhoenixOrder;
 
 pub trait OrderbookKey {
@@ -67,6 +70,31 @@ impl OrderbookValue for Decimal {
     }
 }
 
+/// Size resting ahead of an order in price-time priority.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueuePosition {
+    /// Lots of earlier orders at the same price.
+    pub lots_ahead_at_level: u64,
+    pub orders_ahead_at_level: u64,
+    /// Lots at strictly better prices, which fill before the order's level is reached.
+    pub lots_ahead_total_better_prices: u64,
+}
+
+/// What `Orderbook::sync_from_events` did with a batch of events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventSync {
+    pub applied: usize,
+    /// Events already reflected in the book, or delivered more than once.
+    pub skipped: usize,
+    /// Sequence numbers between the book's and the last applied event's for which no event was
+    /// seen. Instructions that do not touch the book, such as deposits and seat changes, take a
+    /// sequence number without emitting events, so a gap is not necessarily a missed event.
+    pub missing_sequence_numbers: u64,
+    /// The sequence number the book is now synced to. Pass it as `snapshot_seq` for the next
+    /// batch.
+    pub next_sequence_number: u64,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Orderbook<K: Ord + OrderbookKey + Copy, V: OrderbookValue + Copy> {
     pub raw_base_units_per_base_lot: f64,
@@ -128,6 +156,252 @@ impl Orderbook<FIFOOrderId, PhoenixOrder> {
         }
         orderbook
     }
+
+    /// Applies a parsed market event to the book so that it can be maintained incrementally
+    /// from an event stream instead of re-fetching the market account.
+    ///
+    /// Events for orders that are not in the book (e.g. placed before the snapshot was taken) are
+    /// ignored, except for `Place` events, which always insert the order.
+    pub fn apply_event(&mut self, event: &PhoenixEvent) {
+        match event.details {
+            MarketEventDetails::Place(Place {
+                order_sequence_number,
+                maker,
+                price_in_ticks,
+                base_lots_placed,
+                ..
+            }) => {
+                let side = Side::from_order_sequence_number(order_sequence_number);
+                let book = match side {
+                    Side::Bid => &mut self.bids,
+                    Side::Ask => &mut self.asks,
+                };
+                book.insert(
+                    FIFOOrderId::new_from_untyped(price_in_ticks, order_sequence_number),
+                    PhoenixOrder {
+                        num_base_lots: base_lots_placed,
+                        maker_id: maker,
+                    },
+                );
+            }
+            MarketEventDetails::Fill(Fill {
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_remaining,
+                ..
+            })
+            | MarketEventDetails::Reduce(Reduce {
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_remaining,
+                ..
+            }) => {
+                self.set_remaining_lots(order_sequence_number, price_in_ticks, base_lots_remaining)
+            }
+            MarketEventDetails::Evict(Evict {
+                order_sequence_number,
+                price_in_ticks,
+                ..
+            }) => self.set_remaining_lots(order_sequence_number, price_in_ticks, 0),
+            MarketEventDetails::FillSummary(_)
+            | MarketEventDetails::Fee(_)
+            | MarketEventDetails::TimeInForce(_) => {}
+        }
+    }
+
+    /// Splices events onto a book loaded from a snapshot. `snapshot_seq` is the market sequence
+    /// number the snapshot was taken at, see `MarketSnapshot::sequence_number`: events with a
+    /// lower sequence number are already reflected in the book and are skipped, and the rest are
+    /// applied in order.
+    ///
+    /// Events are expected in stream order. Events of an instruction are delivered together, so
+    /// an event at or before the last applied one, by sequence number and then event index, is a
+    /// duplicate and is skipped.
+    pub fn sync_from_events(
+        &mut self,
+        snapshot_seq: u64,
+        events: impl Iterator<Item = PhoenixEvent>,
+    ) -> EventSync {
+        let mut sync = EventSync {
+            next_sequence_number: snapshot_seq,
+            ..Default::default()
+        };
+        let mut last_applied: Option<(u64, u64)> = None;
+        for event in events {
+            let position = (event.sequence_number, event.event_index);
+            if event.sequence_number < snapshot_seq
+                || last_applied.is_some_and(|last| position <= last)
+            {
+                sync.skipped += 1;
+                continue;
+            }
+            sync.missing_sequence_numbers += event
+                .sequence_number
+                .saturating_sub(sync.next_sequence_number);
+            self.apply_event(&event);
+            sync.applied += 1;
+            sync.next_sequence_number = event.sequence_number + 1;
+            last_applied = Some(position);
+        }
+        sync
+    }
+
+    /// Bids in price-time priority: highest price first, then earliest order.
+    pub fn iter_bids(&self) -> btree_map::Iter<'_, FIFOOrderId, PhoenixOrder> {
+        self.bids.iter()
+    }
+
+    /// Asks in price-time priority: lowest price first, then earliest order.
+    pub fn iter_asks(&self) -> btree_map::Iter<'_, FIFOOrderId, PhoenixOrder> {
+        self.asks.iter()
+    }
+
+    pub fn iter_side(&self, side: Side) -> btree_map::Iter<'_, FIFOOrderId, PhoenixOrder> {
+        match side {
+            Side::Bid => self.iter_bids(),
+            Side::Ask => self.iter_asks(),
+        }
+    }
+
+    /// The side's orders aggregated by price, best level first, computed as the iterator advances.
+    pub fn iter_levels(&self, side: Side) -> Levels<'_> {
+        Levels {
+            orders: self.iter_side(side).peekable(),
+        }
+    }
+
+    /// Number of resting orders on `side`.
+    pub fn len(&self, side: Side) -> usize {
+        match side {
+            Side::Bid => self.bids.len(),
+            Side::Ask => self.asks.len(),
+        }
+    }
+
+    pub fn total_base_lots(&self, side: Side) -> u64 {
+        self.iter_side(side)
+            .map(|(_, order)| order.num_base_lots)
+            .sum()
+    }
+
+    /// Number of resting orders a taker order on `side` for `size_lots` base lots would match,
+    /// filling at `limit_ticks` or better, or at any price if `None`. If the book cannot fill the
+    /// whole size, this is the number of orders that cross.
+    ///
+    /// Compute scales with the orders matched, so use this to pick a `match_limit` and a compute
+    /// unit limit for a deep-sweeping order. The program stops matching once an order has matched
+    /// `match_limit` resting orders: an IOC's unfilled rest is cancelled, and the transaction only
+    /// fails if the fill is below the order's `min_base_lots_to_fill` or `min_quote_lots_to_fill`,
+    /// so a truncated fill-or-kill order is killed. The book can change before the order lands,
+    /// so leave a margin.
+    pub fn count_orders_to_fill(
+        &self,
+        side: Side,
+        size_lots: u64,
+        limit_ticks: Option<u64>,
+    ) -> usize {
+        let mut lots_remaining = size_lots;
+        let mut orders = 0;
+        for (order_id, order) in self.iter_side(side.opposite()) {
+            let price_in_ticks = order_id.price_in_ticks.as_u64();
+            let crosses = match (side, limit_ticks) {
+                (_, None) => true,
+                (Side::Bid, Some(limit)) => price_in_ticks <= limit,
+                (Side::Ask, Some(limit)) => price_in_ticks >= limit,
+            };
+            if lots_remaining == 0 || !crosses {
+                break;
+            }
+            lots_remaining = lots_remaining.saturating_sub(order.num_base_lots);
+            orders += 1;
+        }
+        orders
+    }
+
+    /// How much rests ahead of `order_id`, or `None` if it is not in the book. Orders at the same
+    /// price with lower sequence numbers are ahead of it.
+    pub fn queue_position(&self, order_id: &FIFOOrderId) -> Option<QueuePosition> {
+        let book = match Side::from_order_sequence_number(order_id.order_sequence_number) {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        if !book.contains_key(order_id) {
+            return None;
+        }
+        let mut position = QueuePosition::default();
+        for (ahead_id, order) in book.range(..order_id) {
+            if ahead_id.price_in_ticks == order_id.price_in_ticks {
+                position.lots_ahead_at_level += order.num_base_lots;
+                position.orders_ahead_at_level += 1;
+            } else {
+                position.lots_ahead_total_better_prices += order.num_base_lots;
+            }
+        }
+        Some(position)
+    }
+
+    /// Queue positions of all of `maker`'s orders on `side`, computed in one pass over the side.
+    pub fn queue_positions(
+        &self,
+        side: Side,
+        maker: &Pubkey,
+    ) -> BTreeMap<FIFOOrderId, QueuePosition> {
+        let mut positions = BTreeMap::new();
+        let mut level_price = None;
+        let mut position = QueuePosition::default();
+        for (order_id, order) in self.iter_side(side) {
+            if level_price != Some(order_id.price_in_ticks) {
+                level_price = Some(order_id.price_in_ticks);
+                position.lots_ahead_total_better_prices += position.lots_ahead_at_level;
+                position.lots_ahead_at_level = 0;
+                position.orders_ahead_at_level = 0;
+            }
+            if order.maker_id == *maker {
+                positions.insert(*order_id, position);
+            }
+            position.lots_ahead_at_level += order.num_base_lots;
+            position.orders_ahead_at_level += 1;
+        }
+        positions
+    }
+
+    fn set_remaining_lots(&mut self, order_sequence_number: u64, price_in_ticks: u64, lots: u64) {
+        let book = match Side::from_order_sequence_number(order_sequence_number) {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        let order_id = FIFOOrderId::new_from_untyped(price_in_ticks, order_sequence_number);
+        if lots == 0 {
+            book.remove(&order_id);
+        } else if let Some(order) = book.get_mut(&order_id) {
+            order.num_base_lots = lots;
+        }
+    }
+}
+
+/// Price levels of one side of an `Orderbook`, as returned by `Orderbook::iter_levels`.
+pub struct Levels<'a> {
+    orders: Peekable<btree_map::Iter<'a, FIFOOrderId, PhoenixOrder>>,
+}
+
+impl Iterator for Levels<'_> {
+    type Item = LadderOrder;
+
+    fn next(&mut self) -> Option<LadderOrder> {
+        let (first_id, first) = self.orders.next()?;
+        let price_in_ticks = first_id.price_in_ticks;
+        let mut size_in_base_lots = first.num_base_lots;
+        while let Some((_, order)) = self
+            .orders
+            .next_if(|(order_id, _)| order_id.price_in_ticks == price_in_ticks)
+        {
+            size_in_base_lots += order.num_base_lots;
+        }
+        Some(LadderOrder {
+            price_in_ticks: price_in_ticks.as_u64(),
+            size_in_base_lots,
+        })
+    }
 }
 
 impl<K: Ord + OrderbookKey + Copy, V: OrderbookValue + Copy> Orderbook<K, V> {
@@ -206,7 +480,7 @@ impl<K: Ord + OrderbookKey + Copy, V: OrderbookValue + Copy> Orderbook<K, V> {
             loop {
                 let key = if let Some((key, _)) = match side {
                     Side::Bid => opposite_book.iter().next(), // Smallest ask
-                    Side::Ask => opposite_book.iter().rev().next(), // Largest bid
+                    Side::Ask => opposite_book.iter().next_back(), // Largest bid
                 } {
                     // We use the sign to determine whether the order crosses the book
                     let sign = 2.0 * (side == Side::Bid) as u64 as f64 - 1.0; // 1 for bid, -1 for ask
@@ -256,3 +530,257 @@ impl<K: Ord + OrderbookKey + Copy, V: OrderbookValue + Copy> Orderbook<K, V> {
         num / (denom * self.quote_units_per_raw_base_unit_per_tick)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::event;
+
+    fn place(
+        order_sequence_number: u64,
+        price_in_ticks: u64,
+        base_lots_placed: u64,
+    ) -> PhoenixEvent {
+        event(
+            Pubkey::default(),
+            MarketEventDetails::Place(Place {
+                order_sequence_number,
+                client_order_id: 0,
+                maker: Pubkey::default(),
+                price_in_ticks,
+                base_lots_placed,
+            }),
+        )
+    }
+
+    #[test]
+    fn test_apply_event() {
+        let mut book = Orderbook::<FIFOOrderId, PhoenixOrder>::default();
+        book.apply_event(&place(!1, 99, 10));
+        book.apply_event(&place(!2, 100, 5));
+        book.apply_event(&place(3, 101, 7));
+        assert_eq!(book.bids.len(), 2);
+        assert_eq!(book.asks.len(), 1);
+        // Bids iterate best price first
+        assert_eq!(
+            book.bids.keys().next().unwrap().price_in_ticks.as_u64(),
+            100
+        );
+
+        book.apply_event(&event(
+            Pubkey::default(),
+            MarketEventDetails::Fill(Fill {
+                order_sequence_number: !2,
+                maker: Pubkey::default(),
+                taker: Pubkey::default(),
+                price_in_ticks: 100,
+                base_lots_filled: 5,
+                base_lots_remaining: 0,
+                side_filled: Side::Bid,
+                maker_side: Side::Bid,
+                taker_side: Side::Bid.opposite(),
+                is_full_fill: true,
+            }),
+        ));
+        book.apply_event(&event(
+            Pubkey::default(),
+            MarketEventDetails::Reduce(Reduce {
+                order_sequence_number: 3,
+                maker: Pubkey::default(),
+                price_in_ticks: 101,
+                base_lots_removed: 3,
+                base_lots_remaining: 4,
+                is_full_cancel: false,
+            }),
+        ));
+        // Unknown orders are ignored
+        book.apply_event(&event(
+            Pubkey::default(),
+            MarketEventDetails::Evict(Evict {
+                order_sequence_number: !9,
+                maker: Pubkey::default(),
+                price_in_ticks: 50,
+                base_lots_evicted: 1,
+            }),
+        ));
+
+        assert_eq!(book.bids.len(), 1);
+        assert_eq!(book.bids.values().next().unwrap().num_base_lots, 10);
+        assert_eq!(book.asks.values().next().unwrap().num_base_lots, 4);
+    }
+
+    #[test]
+    fn test_sync_from_events() {
+        let at = |sequence_number, event_index, mut event: PhoenixEvent| {
+            event.sequence_number = sequence_number;
+            event.event_index = event_index;
+            event
+        };
+        let reduce = |order_sequence_number, base_lots_remaining| {
+            event(
+                Pubkey::default(),
+                MarketEventDetails::Reduce(Reduce {
+                    order_sequence_number,
+                    maker: Pubkey::default(),
+                    price_in_ticks: 100,
+                    base_lots_removed: 1,
+                    base_lots_remaining,
+                    is_full_cancel: false,
+                }),
+            )
+        };
+        // The snapshot, taken at sequence number 10, has an order placed at 8 and reduced at 9
+        let mut book = Orderbook::<FIFOOrderId, PhoenixOrder>::default();
+        book.apply_event(&place(!1, 100, 5));
+        let events = vec![
+            at(8, 0, place(!1, 100, 6)),
+            at(9, 0, reduce(!1, 5)),
+            at(10, 0, reduce(!1, 4)),
+            at(10, 1, place(!2, 100, 3)),
+            // Delivered twice
+            at(10, 1, place(!2, 100, 3)),
+            at(13, 0, reduce(!2, 1)),
+        ];
+        let sync = book.sync_from_events(10, events.into_iter());
+        assert_eq!(
+            sync,
+            EventSync {
+                applied: 3,
+                skipped: 3,
+                missing_sequence_numbers: 2,
+                next_sequence_number: 14,
+            }
+        );
+        let sizes = book
+            .iter_bids()
+            .map(|(_, order)| order.num_base_lots)
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![4, 1]);
+
+        // The next batch overlaps the previous one
+        let sync = book.sync_from_events(
+            sync.next_sequence_number,
+            vec![at(13, 0, reduce(!2, 1)), at(14, 0, reduce(!2, 0))].into_iter(),
+        );
+        assert_eq!((sync.applied, sync.skipped), (1, 1));
+        assert_eq!(sync.missing_sequence_numbers, 0);
+        assert_eq!(book.len(Side::Bid), 1);
+    }
+
+    #[test]
+    fn test_price_time_iteration() {
+        let mut book = Orderbook::<FIFOOrderId, PhoenixOrder>::default();
+        // Placed out of priority order, with two orders sharing a level on each side
+        for (sequence_number, price, lots) in [(!4, 99, 1), (!2, 100, 2), (!1, 99, 3), (!3, 100, 4)]
+        {
+            book.apply_event(&place(sequence_number, price, lots));
+        }
+        for (sequence_number, price, lots) in [(6, 102, 5), (5, 101, 6), (7, 101, 7)] {
+            book.apply_event(&place(sequence_number, price, lots));
+        }
+
+        let bids = book
+            .iter_bids()
+            .map(|(id, order)| (!id.order_sequence_number, order.num_base_lots))
+            .collect::<Vec<_>>();
+        assert_eq!(bids, vec![(2, 2), (3, 4), (1, 3), (4, 1)]);
+        let asks = book
+            .iter_asks()
+            .map(|(id, order)| (id.order_sequence_number, order.num_base_lots))
+            .collect::<Vec<_>>();
+        assert_eq!(asks, vec![(5, 6), (7, 7), (6, 5)]);
+
+        let levels = |side| {
+            book.iter_levels(side)
+                .map(|level| (level.price_in_ticks, level.size_in_base_lots))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(levels(Side::Bid), vec![(100, 6), (99, 4)]);
+        assert_eq!(levels(Side::Ask), vec![(101, 13), (102, 5)]);
+        assert_eq!(
+            book.iter_levels(Side::Ask)
+                .next()
+                .unwrap()
+                .size_in_base_lots,
+            13
+        );
+
+        assert_eq!(book.len(Side::Bid), 4);
+        assert_eq!(book.len(Side::Ask), 3);
+        assert_eq!(book.total_base_lots(Side::Bid), 10);
+        assert_eq!(book.total_base_lots(Side::Ask), 18);
+
+        // A buy of 7 lots takes the 6 lots of order 5 and 1 of order 7
+        assert_eq!(book.count_orders_to_fill(Side::Bid, 7, None), 2);
+        assert_eq!(book.count_orders_to_fill(Side::Bid, 6, Some(101)), 1);
+        // Only the level at 101 crosses
+        assert_eq!(book.count_orders_to_fill(Side::Bid, 100, Some(101)), 2);
+        assert_eq!(book.count_orders_to_fill(Side::Bid, 100, None), 3);
+        assert_eq!(book.count_orders_to_fill(Side::Ask, 3, Some(100)), 2);
+        assert_eq!(book.count_orders_to_fill(Side::Ask, 3, Some(101)), 0);
+        assert_eq!(book.count_orders_to_fill(Side::Ask, 0, None), 0);
+        assert_eq!(
+            Orderbook::<FIFOOrderId, PhoenixOrder>::default()
+                .iter_levels(Side::Bid)
+                .next(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_queue_position() {
+        let me = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let mut book = Orderbook::<FIFOOrderId, PhoenixOrder>::default();
+        // My bids at 100 are interleaved with others', behind a better bid at 101
+        for (sequence_number, price, lots, maker) in [
+            (!3, 100, 7, other),
+            (!4, 100, 2, me),
+            (!1, 100, 5, other),
+            (!5, 101, 10, other),
+            (!2, 100, 3, me),
+            (!6, 99, 1, me),
+        ] {
+            let mut place = place(sequence_number, price, lots);
+            if let MarketEventDetails::Place(details) = &mut place.details {
+                details.maker = maker;
+            }
+            book.apply_event(&place);
+        }
+        let position = |sequence_number: u64, price: u64| {
+            book.queue_position(&FIFOOrderId::new_from_untyped(price, sequence_number))
+        };
+
+        let second = QueuePosition {
+            lots_ahead_at_level: 5,
+            orders_ahead_at_level: 1,
+            lots_ahead_total_better_prices: 10,
+        };
+        let fourth = QueuePosition {
+            lots_ahead_at_level: 15,
+            orders_ahead_at_level: 3,
+            lots_ahead_total_better_prices: 10,
+        };
+        let below = QueuePosition {
+            lots_ahead_at_level: 0,
+            orders_ahead_at_level: 0,
+            lots_ahead_total_better_prices: 27,
+        };
+        assert_eq!(position(!2, 100), Some(second));
+        assert_eq!(position(!4, 100), Some(fourth));
+        assert_eq!(position(!6, 99), Some(below));
+        assert_eq!(position(!5, 101), Some(QueuePosition::default()));
+        assert_eq!(position(!7, 100), None);
+
+        let positions = book.queue_positions(Side::Bid, &me);
+        assert_eq!(
+            positions.into_iter().collect::<Vec<_>>(),
+            vec![
+                (FIFOOrderId::new_from_untyped(100, !2), second),
+                (FIFOOrderId::new_from_untyped(100, !4), fourth),
+                (FIFOOrderId::new_from_untyped(99, !6), below),
+            ]
+        );
+        assert!(book.queue_positions(Side::Ask, &me).is_empty());
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use phoenix::state::enums::Side;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::market_event::{Fill, MarketEventDetails, PhoenixEvent};
+use crate::quote_converter::QuoteConverter;
+use crate::sdk_client_core::MarketMetadata;
+
+/// Net position and PnL for a single market. All quote amounts are in quote atoms.
+///
+/// The position uses average-cost accounting: `cost_basis_quote_atoms` is the signed amount of
+/// quote paid for the open position (negative for a short, which received quote when opened).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Position {
+    /// Signed position in base lots (positive is long).
+    pub base_lots: i64,
+    pub cost_basis_quote_atoms: i128,
+    /// Realized PnL net of fees.
+    pub realized_pnl_quote_atoms: i128,
+    pub fees_paid_quote_atoms: u64,
+    pub volume_base_lots: u64,
+    pub volume_quote_atoms: u64,
+    pub fill_count: u64,
+}
+
+impl Position {
+    /// Realized PnL before fees.
+    pub fn gross_realized_pnl(&self) -> i128 {
+        self.realized_pnl_quote_atoms + self.fees_paid_quote_atoms as i128
+    }
+
+    /// Returns the unrealized PnL of the open position if it were marked at `mark_price_in_ticks`.
+    pub fn unrealized_pnl(&self, mark_price_in_ticks: u64, meta: &MarketMetadata) -> i128 {
+        let mark_value = meta
+            .base_lots_and_price_to_quote_atoms(self.base_lots.unsigned_abs(), mark_price_in_ticks)
+            as i128;
+        let signed_mark_value = if self.base_lots < 0 {
+            -mark_value
+        } else {
+            mark_value
+        };
+        signed_mark_value - self.cost_basis_quote_atoms
+    }
+}
+
+/// Positions on several markets summed in a single quote mint, in atoms of that mint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PortfolioSummary {
+    pub realized_pnl_quote_atoms: i128,
+    pub unrealized_pnl_quote_atoms: i128,
+    pub fees_paid_quote_atoms: u128,
+    pub volume_quote_atoms: u128,
+}
+
+impl PortfolioSummary {
+    pub fn total_pnl(&self) -> i128 {
+        self.realized_pnl_quote_atoms + self.unrealized_pnl_quote_atoms
+    }
+}
+
+/// Tracks a trader's positions across markets from their fills.
+#[derive(Debug, Clone)]
+pub struct PositionTracker {
+    pub trader: Pubkey,
+    positions: BTreeMap<Pubkey, Position>,
+}
+
+impl PositionTracker {
+    pub fn new(trader: Pubkey) -> Self {
+        Self {
+            trader,
+            positions: BTreeMap::new(),
+        }
+    }
+
+    pub fn position(&self, market: &Pubkey) -> Position {
+        self.positions.get(market).copied().unwrap_or_default()
+    }
+
+    pub fn positions(&self) -> &BTreeMap<Pubkey, Position> {
+        &self.positions
+    }
+
+    /// Sums all positions into `quote_mint`, converting each market's quote amounts with
+    /// `converter`, where every market must be registered. Open positions are marked at
+    /// `mark_prices_in_ticks` with the metadata in `markets`; closed ones need neither.
+    ///
+    /// Fails if any amount cannot be converted, e.g. because a rate is missing or stale, rather
+    /// than leaving it out of the total.
+    pub fn portfolio_summary(
+        &self,
+        markets: &BTreeMap<Pubkey, MarketMetadata>,
+        mark_prices_in_ticks: &BTreeMap<Pubkey, u64>,
+        converter: &QuoteConverter,
+        quote_mint: &Pubkey,
+    ) -> Result<PortfolioSummary> {
+        let now = Instant::now();
+        let mut summary = PortfolioSummary::default();
+        for (market, position) in self.positions.iter() {
+            let convert = |quote_atoms: i128| {
+                converter.normalize_signed_at(quote_atoms, market, quote_mint, now)
+            };
+            summary.realized_pnl_quote_atoms += convert(position.realized_pnl_quote_atoms)?;
+            summary.fees_paid_quote_atoms +=
+                convert(position.fees_paid_quote_atoms as i128)? as u128;
+            summary.volume_quote_atoms += convert(position.volume_quote_atoms as i128)? as u128;
+            if position.base_lots == 0 {
+                continue;
+            }
+            let (Some(meta), Some(mark_price_in_ticks)) =
+                (markets.get(market), mark_prices_in_ticks.get(market))
+            else {
+                return Err(anyhow!(
+                    "No mark price or metadata for market {} with an open position",
+                    market
+                ));
+            };
+            summary.unrealized_pnl_quote_atoms +=
+                convert(position.unrealized_pnl(*mark_price_in_ticks, meta))?;
+        }
+        Ok(summary)
+    }
+
+    /// Records a trade from the trader's perspective: `side` is `Bid` for a buy and `Ask` for a sell.
+    /// `quote_atoms` is the notional of the trade excluding fees.
+    pub fn apply_trade(
+        &mut self,
+        market: &Pubkey,
+        side: Side,
+        base_lots: u64,
+        quote_atoms: u64,
+        fee_quote_atoms: u64,
+    ) {
+        let position = self.positions.entry(*market).or_default();
+        position.fill_count += 1;
+        position.volume_base_lots += base_lots;
+        position.volume_quote_atoms += quote_atoms;
+        position.fees_paid_quote_atoms += fee_quote_atoms;
+        position.realized_pnl_quote_atoms -= fee_quote_atoms as i128;
+        if base_lots == 0 {
+            return;
+        }
+
+        let (direction, signed_quote) = match side {
+            Side::Bid => (1i64, quote_atoms as i128),
+            Side::Ask => (-1i64, -(quote_atoms as i128)),
+        };
+        if position.base_lots == 0 || position.base_lots.signum() == direction {
+            position.base_lots += direction * base_lots as i64;
+            position.cost_basis_quote_atoms += signed_quote;
+            return;
+        }
+
+        // The trade reduces (and possibly flips) the existing position.
+        let open_lots = position.base_lots.unsigned_abs();
+        let closing_lots = open_lots.min(base_lots);
+        let closed_cost =
+            position.cost_basis_quote_atoms * closing_lots as i128 / open_lots as i128;
+        let closing_quote = quote_atoms as i128 * closing_lots as i128 / base_lots as i128;
+        position.realized_pnl_quote_atoms += match side {
+            // Selling out of a long: proceeds minus cost
+            Side::Ask => closing_quote - closed_cost,
+            // Buying back a short: proceeds received (negative cost) minus amount paid
+            Side::Bid => -closed_cost - closing_quote,
+        };
+        position.cost_basis_quote_atoms -= closed_cost;
+        position.base_lots += direction * closing_lots as i64;
+
+        let opening_lots = base_lots - closing_lots;
+        if opening_lots > 0 {
+            position.base_lots += direction * opening_lots as i64;
+            position.cost_basis_quote_atoms =
+                direction as i128 * (quote_atoms as i128 - closing_quote);
+        }
+    }
+
+    /// Applies a parsed event, returning true if it was a fill involving the tracked trader.
+    ///
+    /// Fill events do not carry fees, so taker fills are charged `MarketMetadata::fee_for_notional`
+    /// of their notional. The program rounds the fee up once per order rather than per fill, so
+    /// this can overstate it by up to a quote lot per fill; use `apply_trade` with the fee from
+    /// the order's `FillSummary` when it must be exact.
+    pub fn apply_event(&mut self, event: &PhoenixEvent, meta: &MarketMetadata) -> bool {
+        let MarketEventDetails::Fill(Fill {
+            maker,
+            taker,
+            price_in_ticks,
+            base_lots_filled,
+            maker_side,
+            taker_side,
+            ..
+        }) = event.details
+        else {
+            return false;
+        };
+        let quote_atoms = meta.base_lots_and_price_to_quote_atoms(base_lots_filled, price_in_ticks);
+        let (side, is_maker) = if maker == self.trader {
+            (maker_side, true)
+        } else if taker == self.trader {
+            (taker_side, false)
+        } else {
+            return false;
+        };
+        let fee_quote_atoms = meta.fee_for_notional(quote_atoms, is_maker);
+        self.apply_trade(
+            &event.market,
+            side,
+            base_lots_filled,
+            quote_atoms,
+            fee_quote_atoms,
+        );
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::event;
+
+    #[test]
+    fn test_round_trip_long() {
+        let market = Pubkey::new_unique();
+        let mut tracker = PositionTracker::new(Pubkey::new_unique());
+        tracker.apply_trade(&market, Side::Bid, 10, 1_000, 1);
+        tracker.apply_trade(&market, Side::Bid, 10, 1_200, 1);
+        let position = tracker.position(&market);
+        assert_eq!(position.base_lots, 20);
+        assert_eq!(position.cost_basis_quote_atoms, 2_200);
+
+        // Sell half at 130 per lot: proceeds 1300, closed cost 1100
+        tracker.apply_trade(&market, Side::Ask, 10, 1_300, 1);
+        let position = tracker.position(&market);
+        assert_eq!(position.base_lots, 10);
+        assert_eq!(position.cost_basis_quote_atoms, 1_100);
+        assert_eq!(position.realized_pnl_quote_atoms, 200 - 3);
+        assert_eq!(position.fees_paid_quote_atoms, 3);
+        assert_eq!(position.fill_count, 3);
+        assert_eq!(position.volume_quote_atoms, 3_500);
+    }
+
+    #[test]
+    fn test_flip_short_to_long() {
+        let market = Pubkey::new_unique();
+        let mut tracker = PositionTracker::new(Pubkey::new_unique());
+        tracker.apply_trade(&market, Side::Ask, 10, 1_000, 0);
+        assert_eq!(tracker.position(&market).base_lots, -10);
+        assert_eq!(tracker.position(&market).cost_basis_quote_atoms, -1_000);
+
+        // Buy 15 lots at 90 per lot: 10 close the short for +100, 5 open a long costing 450
+        tracker.apply_trade(&market, Side::Bid, 15, 1_350, 0);
+        let position = tracker.position(&market);
+        assert_eq!(position.base_lots, 5);
+        assert_eq!(position.realized_pnl_quote_atoms, 100);
+        assert_eq!(position.cost_basis_quote_atoms, 450);
+    }
+
+    #[test]
+    fn test_apply_fill_event_as_maker_and_taker() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let meta = MarketMetadata {
+            quote_atoms_per_quote_lot: 1,
+            tick_size_in_quote_atoms_per_base_unit: 1000,
+            num_base_lots_per_base_unit: 100,
+            taker_fee_bps: 3,
+            ..Default::default()
+        };
+        let fill = |maker, taker, side_filled: Side| PhoenixEvent {
+            signer: taker,
+            ..event(
+                market,
+                MarketEventDetails::Fill(Fill {
+                    order_sequence_number: 0,
+                    maker,
+                    taker,
+                    price_in_ticks: 50,
+                    base_lots_filled: 10,
+                    base_lots_remaining: 0,
+                    side_filled,
+                    is_full_fill: true,
+                    maker_side: side_filled,
+                    taker_side: side_filled.opposite(),
+                }),
+            )
+        };
+        let mut tracker = PositionTracker::new(trader);
+        // Our resting bid is hit: we bought
+        assert!(tracker.apply_event(&fill(trader, other, Side::Bid), &meta));
+        assert_eq!(tracker.position(&market).base_lots, 10);
+        // We take a resting bid: we sold
+        assert!(tracker.apply_event(&fill(other, trader, Side::Bid), &meta));
+        assert_eq!(tracker.position(&market).base_lots, 0);
+        assert!(!tracker.apply_event(&fill(other, other, Side::Bid), &meta));
+        assert_eq!(tracker.position(&market).fill_count, 2);
+        assert_eq!(tracker.position(&market).unrealized_pnl(60, &meta), 0);
+
+        // Only the taker fill paid a fee: 3 bps of 5000 quote atoms is 1.5, rounded up to 2
+        let position = tracker.position(&market);
+        assert_eq!(position.fees_paid_quote_atoms, 2);
+        assert_eq!(position.gross_realized_pnl(), 0);
+        assert_eq!(position.realized_pnl_quote_atoms, -2);
+    }
+
+    #[test]
+    fn test_portfolio_summary() {
+        use rust_decimal::Decimal;
+        use std::time::Duration;
+
+        let usdc = Pubkey::new_unique();
+        let sol = Pubkey::new_unique();
+        let meta = |quote_mint| MarketMetadata {
+            quote_mint,
+            num_base_lots_per_base_unit: 1,
+            tick_size_in_quote_atoms_per_base_unit: 1,
+            ..Default::default()
+        };
+        let (usdc_market, sol_market) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let markets = BTreeMap::from([(usdc_market, meta(usdc)), (sol_market, meta(sol))]);
+        let mut converter = QuoteConverter::new(Duration::from_secs(60));
+        for (market, meta) in markets.iter() {
+            converter.register_market(market, meta);
+        }
+
+        let mut tracker = PositionTracker::new(Pubkey::new_unique());
+        // Closed out for +100 USDC atoms, less 2 in fees
+        tracker.apply_trade(&usdc_market, Side::Bid, 10, 1_000, 1);
+        tracker.apply_trade(&usdc_market, Side::Ask, 10, 1_100, 1);
+        // Long 10 lots bought for 50 SOL atoms
+        tracker.apply_trade(&sol_market, Side::Bid, 10, 50, 0);
+        let marks = BTreeMap::from([(sol_market, 6)]);
+
+        // Without a SOL rate the SOL market cannot be counted
+        assert!(tracker
+            .portfolio_summary(&markets, &marks, &converter, &usdc)
+            .is_err());
+        converter.set_rate(&sol, &usdc, Decimal::from(150));
+        let summary = tracker
+            .portfolio_summary(&markets, &marks, &converter, &usdc)
+            .unwrap();
+        assert_eq!(
+            summary,
+            PortfolioSummary {
+                realized_pnl_quote_atoms: 98,
+                // 10 SOL atoms of unrealized PnL
+                unrealized_pnl_quote_atoms: 1_500,
+                fees_paid_quote_atoms: 2,
+                volume_quote_atoms: 2_100 + 50 * 150,
+            }
+        );
+        assert_eq!(summary.total_pnl(), 1_598);
+
+        // Open positions need a mark
+        assert!(tracker
+            .portfolio_summary(&markets, &BTreeMap::new(), &converter, &usdc)
+            .is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+
+use phoenix::program::error::PhoenixError;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::TransactionError;
+
+/// Custom error code of the token program for a transfer larger than the source balance.
+const TOKEN_INSUFFICIENT_FUNDS: u32 = 1;
+
+/// Why a Phoenix instruction failed, decoded from its custom error code and the program logs.
+///
+/// Phoenix reports most order failures as a generic `NewOrderError`; the specific reason is only
+/// in the logs, so the variants below are only returned when the logs are available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhoenixProgramError {
+    /// A post-only order would have crossed the book and was rejected.
+    PostOnlyCrosses,
+    /// An adjustable post-only order crossed the book and no price one tick inside it is valid.
+    PostOnlyCannotBeAmended,
+    /// The trader's deposited or wallet funds do not cover the order or deposit.
+    InsufficientFunds,
+    /// A multiple-order packet has bids at or above its asks.
+    CrossingOrderPacket,
+    /// Any other Phoenix error, by code.
+    Program(PhoenixError),
+    /// A custom code Phoenix does not define.
+    Unknown(u32),
+}
+
+impl PhoenixProgramError {
+    /// Maps a Phoenix custom error code, without looking at logs.
+    pub fn from_code(code: u32) -> Self {
+        use PhoenixError::*;
+        let error = match code {
+            0 => InvalidMarketParameters,
+            1 => InvalidMarketAuthority,
+            2 => FailedToLoadMarketFromAccount,
+            3 => MarketAlreadyInitialized,
+            4 => MarketUninitialized,
+            5 => InvalidStateTransition,
+            6 => InvalidMarketSigner,
+            7 => InvalidLotSize,
+            8 => InvalidTickSize,
+            9 => InvalidMint,
+            10 => InvalidBaseVault,
+            11 => InvalidQuoteVault,
+            12 => InvalidBaseAccount,
+            13 => InvalidQuoteAccount,
+            14 => TooManyEvents,
+            15 => NewOrderError,
+            16 => ReduceOrderError,
+            17 => CancelMultipleOrdersError,
+            18 => WithdrawFundsError,
+            19 => RemoveEmptyOrdersError,
+            20 => TraderNotFound,
+            21 => InvalidSeatStatus,
+            22 => EvictionError,
+            23 => NonEmptyScratchBuffer,
+            24 => FailedToSerializeEvent,
+            25 => FailedToFlushBuffer,
+            _ => return PhoenixProgramError::Unknown(code),
+        };
+        PhoenixProgramError::Program(error)
+    }
+
+    /// A human readable explanation, with a hint at the fix where there is an obvious one.
+    pub fn message(&self) -> String {
+        match self {
+            PhoenixProgramError::PostOnlyCrosses => {
+                "Post-only order crosses the book. Lower the bid or raise the ask, or send it as adjustable".to_string()
+            }
+            PhoenixProgramError::PostOnlyCannotBeAmended => {
+                "Post-only order crosses the book and cannot be amended to a valid price".to_string()
+            }
+            PhoenixProgramError::InsufficientFunds => {
+                "Insufficient funds: deposit more or reduce the order size".to_string()
+            }
+            PhoenixProgramError::CrossingOrderPacket => {
+                "The bids and asks of a multiple-order packet cross each other".to_string()
+            }
+            PhoenixProgramError::Program(PhoenixError::TraderNotFound) => {
+                "Trader has no seat on the market. Request one first".to_string()
+            }
+            PhoenixProgramError::Program(PhoenixError::InvalidSeatStatus) => {
+                "Trader's seat is not approved".to_string()
+            }
+            PhoenixProgramError::Program(error) => error.to_string(),
+            PhoenixProgramError::Unknown(code) => format!("Unknown Phoenix error code {}", code),
+        }
+    }
+}
+
+impl fmt::Display for PhoenixProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message())
+    }
+}
+
+/// Decodes why a transaction failed in the Phoenix program, or returns `None` if the failure is
+/// not a custom program error of Phoenix (e.g. an expired blockhash).
+///
+/// `logs` are the transaction's program logs, from simulation or from the confirmed transaction.
+/// They are used to tell which program raised the error, since inner failures propagate their
+/// code unchanged, and to refine Phoenix's generic order errors. Without logs, the custom code is
+/// assumed to come from Phoenix.
+pub fn decode_phoenix_error(
+    err: &TransactionError,
+    logs: &[String],
+) -> Option<PhoenixProgramError> {
+    let TransactionError::InstructionError(_, InstructionError::Custom(code)) = err else {
+        return None;
+    };
+    // The innermost failing program logs its failure first
+    let failed_program = logs.iter().find_map(|log| {
+        let rest = log.strip_prefix("Program ")?;
+        let (program, _) = rest.split_once(" failed: ")?;
+        program.parse::<Pubkey>().ok()
+    });
+    match failed_program {
+        Some(program)
+            if program == spl_token::id() || program == crate::ata_utils::TOKEN_2022_PROGRAM_ID =>
+        {
+            return (*code == TOKEN_INSUFFICIENT_FUNDS)
+                .then_some(PhoenixProgramError::InsufficientFunds);
+        }
+        Some(program) if program != phoenix::id() => return None,
+        _ => {}
+    }
+    let logged = |message: &str| logs.iter().any(|log| log.contains(message));
+    let error = if logged("can not be amended to a valid price") {
+        PhoenixProgramError::PostOnlyCannotBeAmended
+    } else if logged("PostOnly order crosses the book - order rejected") {
+        PhoenixProgramError::PostOnlyCrosses
+    } else if logged("insufficient to execute the order")
+        || logged("does not have enough deposited funds")
+    {
+        PhoenixProgramError::InsufficientFunds
+    } else if logged("contains crossing bids and asks") {
+        PhoenixProgramError::CrossingOrderPacket
+    } else {
+        PhoenixProgramError::from_code(*code)
+    };
+    Some(error)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn custom(code: u32) -> TransactionError {
+        TransactionError::InstructionError(0, InstructionError::Custom(code))
+    }
+
+    fn failed(program: Pubkey, code: u32) -> String {
+        format!(
+            "Program {} failed: custom program error: {:#x}",
+            program, code
+        )
+    }
+
+    #[test]
+    fn test_known_codes() {
+        for (code, error) in [
+            (0, PhoenixError::InvalidMarketParameters),
+            (4, PhoenixError::MarketUninitialized),
+            (7, PhoenixError::InvalidLotSize),
+            (14, PhoenixError::TooManyEvents),
+            (15, PhoenixError::NewOrderError),
+            (18, PhoenixError::WithdrawFundsError),
+            (20, PhoenixError::TraderNotFound),
+            (21, PhoenixError::InvalidSeatStatus),
+            (25, PhoenixError::FailedToFlushBuffer),
+        ] {
+            assert_eq!(u32::from(error), code);
+            assert_eq!(
+                decode_phoenix_error(&custom(code), &[failed(phoenix::id(), code)]),
+                Some(PhoenixProgramError::Program(error))
+            );
+        }
+        assert_eq!(
+            decode_phoenix_error(&custom(26), &[]),
+            Some(PhoenixProgramError::Unknown(26))
+        );
+        assert_eq!(
+            decode_phoenix_error(&TransactionError::BlockhashNotFound, &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_refined_from_logs() {
+        let program_log = |message: &str| format!("Program log: {}", message);
+        for (message, error) in [
+            (
+                "PostOnly order crosses the book - order rejected",
+                PhoenixProgramError::PostOnlyCrosses,
+            ),
+            (
+                "PostOnly order crosses the book and can not be amended to a valid price - order rejected",
+                PhoenixProgramError::PostOnlyCannotBeAmended,
+            ),
+            (
+                "Deposited amount of funds were insufficient to execute the order",
+                PhoenixProgramError::InsufficientFunds,
+            ),
+            (
+                "Invalid input. MultipleOrderPacket contains crossing bids and asks",
+                PhoenixProgramError::CrossingOrderPacket,
+            ),
+        ] {
+            let logs = [program_log(message), failed(phoenix::id(), 15)];
+            assert_eq!(decode_phoenix_error(&custom(15), &logs), Some(error));
+        }
+
+        // A token transfer failing inside Phoenix propagates the token program's code
+        let logs = [failed(spl_token::id(), 1), failed(phoenix::id(), 1)];
+        assert_eq!(
+            decode_phoenix_error(&custom(1), &logs),
+            Some(PhoenixProgramError::InsufficientFunds)
+        );
+        // Errors of other programs are not Phoenix's
+        let logs = [failed(Pubkey::new_unique(), 1)];
+        assert_eq!(decode_phoenix_error(&custom(1), &logs), None);
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::orderbook::Orderbook;
+use crate::sdk_client_core::{MarketMetadata, PhoenixOrder};
+
+/// Why `QuoteConverter` could not convert an amount. Returned inside the `anyhow::Error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionError {
+    /// The market was never registered, so its quote mint is unknown.
+    UnknownMarket(Pubkey),
+    /// No rate was set between the two mints, in either direction.
+    MissingRoute { from_mint: Pubkey, to_mint: Pubkey },
+    /// The rate between the two mints is older than the converter's maximum age.
+    StaleRate {
+        from_mint: Pubkey,
+        to_mint: Pubkey,
+        age: Duration,
+        max_age: Duration,
+    },
+    /// The converted amount does not fit the result type.
+    Overflow,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionError::UnknownMarket(market) => {
+                write!(f, "Market {} is not registered with the converter", market)
+            }
+            ConversionError::MissingRoute { from_mint, to_mint } => {
+                write!(f, "No conversion rate from {} to {}", from_mint, to_mint)
+            }
+            ConversionError::StaleRate {
+                from_mint,
+                to_mint,
+                age,
+                max_age,
+            } => write!(
+                f,
+                "Conversion rate from {} to {} is {:?} old, more than {:?}",
+                from_mint, to_mint, age, max_age
+            ),
+            ConversionError::Overflow => write!(f, "Converted amount overflows"),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rate {
+    /// Atoms of the target mint per atom of the source mint.
+    atoms_per_atom: Decimal,
+    updated_at: Instant,
+}
+
+/// Converts quote amounts of different markets into a common quote mint, e.g. to add up the PnL
+/// of USDC-quoted and SOL-quoted markets.
+///
+/// Rates are between mints, in atoms per atom, so the mints' decimals are accounted for. A rate
+/// set one way is used inverted for the other, and a rate older than `max_age` is an error rather
+/// than silently used.
+#[derive(Debug, Clone)]
+pub struct QuoteConverter {
+    market_quote_mints: BTreeMap<Pubkey, Pubkey>,
+    rates: BTreeMap<(Pubkey, Pubkey), Rate>,
+    pub max_age: Duration,
+}
+
+impl QuoteConverter {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            market_quote_mints: BTreeMap::new(),
+            rates: BTreeMap::new(),
+            max_age,
+        }
+    }
+
+    /// Registers a market whose quote amounts will be converted.
+    pub fn register_market(&mut self, market: &Pubkey, meta: &MarketMetadata) {
+        self.market_quote_mints.insert(*market, meta.quote_mint);
+    }
+
+    /// Sets the rate from `from_mint` to `to_mint` in atoms of `to_mint` per atom of `from_mint`.
+    pub fn set_rate(&mut self, from_mint: &Pubkey, to_mint: &Pubkey, atoms_per_atom: Decimal) {
+        self.set_rate_at(from_mint, to_mint, atoms_per_atom, Instant::now());
+    }
+
+    pub fn set_rate_at(
+        &mut self,
+        from_mint: &Pubkey,
+        to_mint: &Pubkey,
+        atoms_per_atom: Decimal,
+        updated_at: Instant,
+    ) {
+        self.rates.remove(&(*to_mint, *from_mint));
+        self.rates.insert(
+            (*from_mint, *to_mint),
+            Rate {
+                atoms_per_atom,
+                updated_at,
+            },
+        );
+    }
+
+    /// Sets the rate from a market's base mint to its quote mint at the mid price of `book`, a
+    /// snapshot of the market. Returns the mid price in ticks, or `None`, leaving the rate
+    /// unchanged, if either side of the book is empty.
+    pub fn set_rate_from_market_mid(
+        &mut self,
+        meta: &MarketMetadata,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+    ) -> Option<Decimal> {
+        let best_bid = book.iter_levels(Side::Bid).next()?.price_in_ticks;
+        let best_ask = book.iter_levels(Side::Ask).next()?.price_in_ticks;
+        let mid_price_in_ticks = (Decimal::from(best_bid) + Decimal::from(best_ask)) / Decimal::TWO;
+        self.set_rate(
+            &meta.base_mint,
+            &meta.quote_mint,
+            base_to_quote_atoms_per_atom(meta, mid_price_in_ticks),
+        );
+        Some(mid_price_in_ticks)
+    }
+
+    /// Atoms of `to_mint` per atom of `from_mint`, as of `now`.
+    pub fn rate_at(&self, from_mint: &Pubkey, to_mint: &Pubkey, now: Instant) -> Result<Decimal> {
+        if from_mint == to_mint {
+            return Ok(Decimal::ONE);
+        }
+        let (rate, inverted) = match self.rates.get(&(*from_mint, *to_mint)) {
+            Some(rate) => (rate, false),
+            None => match self.rates.get(&(*to_mint, *from_mint)) {
+                Some(rate) => (rate, true),
+                None => {
+                    return Err(ConversionError::MissingRoute {
+                        from_mint: *from_mint,
+                        to_mint: *to_mint,
+                    }
+                    .into())
+                }
+            },
+        };
+        let age = now.saturating_duration_since(rate.updated_at);
+        if age > self.max_age {
+            return Err(ConversionError::StaleRate {
+                from_mint: *from_mint,
+                to_mint: *to_mint,
+                age,
+                max_age: self.max_age,
+            }
+            .into());
+        }
+        if !inverted {
+            return Ok(rate.atoms_per_atom);
+        }
+        Decimal::ONE
+            .checked_div(rate.atoms_per_atom)
+            .ok_or_else(|| ConversionError::Overflow.into())
+    }
+
+    /// Converts quote atoms of `from_market` into atoms of `to_quote_mint`, rounding down.
+    pub fn normalize(
+        &self,
+        amount_quote_atoms: u64,
+        from_market: &Pubkey,
+        to_quote_mint: &Pubkey,
+    ) -> Result<u64> {
+        self.normalize_at(
+            amount_quote_atoms,
+            from_market,
+            to_quote_mint,
+            Instant::now(),
+        )
+    }
+
+    pub fn normalize_at(
+        &self,
+        amount_quote_atoms: u64,
+        from_market: &Pubkey,
+        to_quote_mint: &Pubkey,
+        now: Instant,
+    ) -> Result<u64> {
+        let converted =
+            self.normalize_signed_at(amount_quote_atoms as i128, from_market, to_quote_mint, now)?;
+        Ok(u64::try_from(converted).map_err(|_| ConversionError::Overflow)?)
+    }
+
+    /// Like `normalize_at`, for signed amounts such as PnL. Rounds towards zero.
+    pub fn normalize_signed_at(
+        &self,
+        amount_quote_atoms: i128,
+        from_market: &Pubkey,
+        to_quote_mint: &Pubkey,
+        now: Instant,
+    ) -> Result<i128> {
+        let from_mint = self
+            .market_quote_mints
+            .get(from_market)
+            .ok_or(ConversionError::UnknownMarket(*from_market))?;
+        if from_mint == to_quote_mint {
+            return Ok(amount_quote_atoms);
+        }
+        let rate = self.rate_at(from_mint, to_quote_mint, now)?;
+        Decimal::from_i128(amount_quote_atoms)
+            .and_then(|amount| amount.checked_mul(rate))
+            .and_then(|converted| converted.trunc().to_i128())
+            .ok_or_else(|| ConversionError::Overflow.into())
+    }
+}
+
+/// Quote atoms per base atom at `price_in_ticks`, consistent with
+/// `MarketMetadata::base_lots_and_price_to_quote_atoms`.
+fn base_to_quote_atoms_per_atom(meta: &MarketMetadata, price_in_ticks: Decimal) -> Decimal {
+    price_in_ticks * Decimal::from(meta.tick_size_in_quote_atoms_per_base_unit)
+        / Decimal::from(meta.num_base_lots_per_base_unit)
+        / Decimal::from(meta.base_atoms_per_base_lot)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::market_event::{MarketEventDetails, PhoenixEvent, Place};
+    use crate::test_utils::{event, sol_usdc_metadata};
+
+    fn book(bid: u64, ask: u64) -> Orderbook<FIFOOrderId, PhoenixOrder> {
+        let mut book = Orderbook::default();
+        for (order_sequence_number, price_in_ticks) in [(!1, bid), (2, ask)] {
+            book.apply_event(&PhoenixEvent {
+                ..event(
+                    Pubkey::default(),
+                    MarketEventDetails::Place(Place {
+                        order_sequence_number,
+                        client_order_id: 0,
+                        maker: Pubkey::default(),
+                        price_in_ticks,
+                        base_lots_placed: 1,
+                    }),
+                )
+            });
+        }
+        book
+    }
+
+    #[test]
+    fn test_sol_quoted_to_usdc() {
+        let sol_usdc = sol_usdc_metadata();
+        // A market quoted in SOL, e.g. BONK/SOL
+        let bonk_sol_market = Pubkey::new_unique();
+        let bonk_sol = MarketMetadata {
+            quote_mint: sol_usdc.base_mint,
+            ..sol_usdc_metadata()
+        };
+        let usdc = sol_usdc.quote_mint;
+
+        let mut converter = QuoteConverter::new(Duration::from_secs(60));
+        converter.register_market(&bonk_sol_market, &bonk_sol);
+        assert!(matches!(
+            converter
+                .normalize(1, &bonk_sol_market, &usdc)
+                .unwrap_err()
+                .downcast_ref::<ConversionError>(),
+            Some(ConversionError::MissingRoute { .. })
+        ));
+
+        // Mid of 150 USDC per SOL, in ticks of 0.001 USDC
+        let mid = converter
+            .set_rate_from_market_mid(&sol_usdc, &book(149_990, 150_010))
+            .unwrap();
+        assert_eq!(mid, Decimal::from(150_000));
+        // 2 SOL of PnL is 300 USDC
+        assert_eq!(
+            converter
+                .normalize(2_000_000_000, &bonk_sol_market, &usdc)
+                .unwrap(),
+            300_000_000
+        );
+        let usdc_market = Pubkey::new_unique();
+        converter.register_market(&usdc_market, &sol_usdc);
+        assert_eq!(converter.normalize(5, &usdc_market, &usdc).unwrap(), 5);
+        // The inverse route, and signed amounts
+        assert_eq!(
+            converter
+                .normalize_signed_at(
+                    -300_000_000,
+                    &usdc_market,
+                    &sol_usdc.base_mint,
+                    Instant::now()
+                )
+                .unwrap(),
+            -2_000_000_000
+        );
+
+        // No mid without both sides
+        let mut one_sided = book(149_990, 150_010);
+        one_sided.asks.clear();
+        assert_eq!(
+            converter.set_rate_from_market_mid(&sol_usdc, &one_sided),
+            None
+        );
+
+        let error = converter
+            .normalize(1, &Pubkey::new_unique(), &usdc)
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ConversionError>(),
+            Some(ConversionError::UnknownMarket(_))
+        ));
+    }
+
+    #[test]
+    fn test_stale_rate() {
+        let sol_usdc = sol_usdc_metadata();
+        let market = Pubkey::new_unique();
+        let mut converter = QuoteConverter::new(Duration::from_secs(10));
+        converter.register_market(&market, &sol_usdc);
+        let set_at = Instant::now();
+        converter.set_rate_at(
+            &sol_usdc.quote_mint,
+            &sol_usdc.base_mint,
+            Decimal::new(6667, 3),
+            set_at,
+        );
+        assert_eq!(
+            converter
+                .normalize_at(
+                    3,
+                    &market,
+                    &sol_usdc.base_mint,
+                    set_at + Duration::from_secs(10)
+                )
+                .unwrap(),
+            20
+        );
+        let error = converter
+            .normalize_at(
+                3,
+                &market,
+                &sol_usdc.base_mint,
+                set_at + Duration::from_secs(11),
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ConversionError>(),
+            Some(&ConversionError::StaleRate {
+                from_mint: sol_usdc.quote_mint,
+                to_mint: sol_usdc.base_mint,
+                age: Duration::from_secs(11),
+                max_age: Duration::from_secs(10),
+            })
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::orderbook::Orderbook;
+use crate::reference_price::{ReferencePriceError, ReferencePriceFeed};
+use crate::sdk_client_core::{adjusted_post_only_price, MarketMetadata, PhoenixOrder};
+use crate::tick_math::clamp_to_band;
+
+const BPS_DIVISOR: f64 = 10_000.0;
+
+/// Absorbs float error so that prices landing exactly on a tick are not pushed to the next one.
+const TICK_EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LadderConfig {
+    /// Number of levels per side before coalescing.
+    pub levels: usize,
+    /// Distance between consecutive levels, as a fraction of the previous level's price in bps.
+    pub spacing_bps: u64,
+    /// Size of the innermost level in Phoenix base units.
+    pub size_per_level_base_units: f64,
+    /// Each level is this many times the size of the level inside it (1.0 for a flat ladder).
+    pub size_growth: f64,
+    /// Minimum distance between the best bid and best ask, in bps of the mid price.
+    pub min_spread_bps: u64,
+}
+
+/// Post-only quotes around a reference price, as `(price_in_ticks, num_base_lots)` pairs ordered
+/// from the inside out on each side.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuoteLadder {
+    pub bids: Vec<(u64, u64)>,
+    pub asks: Vec<(u64, u64)>,
+}
+
+impl QuoteLadder {
+    /// Generates a geometrically spaced ladder around `mid_price_ticks`.
+    ///
+    /// Bid prices are rounded down and ask prices up, and every bid is strictly below the mid and
+    /// every ask strictly above it, so the ladder never crosses or locks itself no matter how
+    /// small the spread. Sizes are rounded down to whole lots and empty levels are dropped. When
+    /// the spacing is smaller than a tick, several levels land on the same price; these are
+    /// coalesced into a single level with their combined size.
+    pub fn generate(meta: &MarketMetadata, mid_price_ticks: u64, cfg: LadderConfig) -> Self {
+        let mid = mid_price_ticks as f64;
+        let half_spread = cfg.min_spread_bps as f64 / BPS_DIVISOR / 2.0;
+        let spacing = cfg.spacing_bps as f64 / BPS_DIVISOR;
+
+        let mut ladder = QuoteLadder::default();
+        for level in 0..cfg.levels {
+            let num_base_lots = (cfg.size_per_level_base_units
+                * cfg.size_growth.powi(level as i32)
+                * meta.num_base_lots_per_base_unit as f64)
+                .floor() as u64;
+            if num_base_lots == 0 {
+                continue;
+            }
+
+            let bid = (mid * (1.0 - half_spread) * (1.0 - spacing).powi(level as i32)
+                + TICK_EPSILON)
+                .floor();
+            let bid = (bid.max(0.0) as u64).min(mid_price_ticks.saturating_sub(1));
+            if bid > 0 {
+                push_level(&mut ladder.bids, bid, num_base_lots);
+            }
+
+            let ask = (mid * (1.0 + half_spread) * (1.0 + spacing).powi(level as i32)
+                - TICK_EPSILON)
+                .ceil();
+            let ask = (ask as u64).max(mid_price_ticks + 1);
+            push_level(&mut ladder.asks, ask, num_base_lots);
+        }
+        ladder
+    }
+
+    /// Like `generate`, around the reference price `feed` has for `market`, rounded to the
+    /// nearest tick. A missing or stale price is an error rather than a ladder around old data;
+    /// callers should stop quoting, and pull their resting quotes, until it is fresh again.
+    pub fn generate_from_reference(
+        meta: &MarketMetadata,
+        market: &Pubkey,
+        feed: &ReferencePriceFeed,
+        cfg: LadderConfig,
+    ) -> Result<Self, ReferencePriceError> {
+        let reference = feed.price(market)?;
+        Ok(Self::generate(meta, reference.price_in_ticks(meta), cfg))
+    }
+
+    /// Like `generate`, with levels that would cross `book` moved one tick inside its opposite
+    /// best, as `SDKClientCore::adjusted_post_only_price` predicts the on-chain adjustment would.
+    /// Levels landing on the same price are coalesced, and bids that would rest at 0 ticks are
+    /// dropped.
+    ///
+    /// The adjusted ladder can be sent with `reject_post_only` set, so that the prices it rests at
+    /// are the ones computed here: if the book moves before the transaction lands, crossing orders
+    /// fail instead of being re-priced against the new book.
+    pub fn generate_against_book(
+        meta: &MarketMetadata,
+        mid_price_ticks: u64,
+        cfg: LadderConfig,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+    ) -> Self {
+        let ladder = Self::generate(meta, mid_price_ticks, cfg);
+        let adjust = |side, levels: Vec<(u64, u64)>| {
+            let mut adjusted = vec![];
+            for (price_in_ticks, num_base_lots) in levels {
+                let price_in_ticks = adjusted_post_only_price(side, price_in_ticks, book);
+                if price_in_ticks > 0 {
+                    push_level(&mut adjusted, price_in_ticks, num_base_lots);
+                }
+            }
+            adjusted
+        };
+        QuoteLadder {
+            bids: adjust(Side::Bid, ladder.bids),
+            asks: adjust(Side::Ask, ladder.asks),
+        }
+    }
+}
+
+impl QuoteLadder {
+    /// The ladder with every level clamped to within `max_deviation_bps` of `reference_ticks`,
+    /// e.g. to keep it inside a `RiskConfig::price_band_pct`. Outer levels beyond the band are
+    /// coalesced at its edge.
+    pub fn clamped_to_band(self, reference_ticks: u64, max_deviation_bps: u64) -> Self {
+        let clamp = |levels: Vec<(u64, u64)>| {
+            let mut clamped = vec![];
+            for (price_in_ticks, num_base_lots) in levels {
+                let price_in_ticks =
+                    clamp_to_band(price_in_ticks, reference_ticks, max_deviation_bps, 1);
+                push_level(&mut clamped, price_in_ticks, num_base_lots);
+            }
+            clamped
+        };
+        QuoteLadder {
+            bids: clamp(self.bids),
+            asks: clamp(self.asks),
+        }
+    }
+}
+
+impl From<QuoteLadder> for (Vec<(u64, u64)>, Vec<(u64, u64)>) {
+    fn from(ladder: QuoteLadder) -> Self {
+        (ladder.bids, ladder.asks)
+    }
+}
+
+/// Levels are generated from the inside out, so a repeated price can only be the last one pushed.
+fn push_level(levels: &mut Vec<(u64, u64)>, price_in_ticks: u64, num_base_lots: u64) {
+    match levels.last_mut() {
+        Some((last_price, last_size)) if *last_price == price_in_ticks => {
+            *last_size += num_base_lots
+        }
+        _ => levels.push((price_in_ticks, num_base_lots)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::market_map::MarketMap;
+    use crate::sdk_client_core::SDKClientCore;
+    use borsh::BorshDeserialize;
+    use phoenix::program::new_order::MultipleOrderPacket;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn meta() -> MarketMetadata {
+        MarketMetadata {
+            num_base_lots_per_base_unit: 100,
+            tick_size_in_quote_atoms_per_base_unit: 1000,
+            quote_atoms_per_quote_lot: 1,
+            base_atoms_per_base_lot: 1000,
+            ..Default::default()
+        }
+    }
+
+    fn config(levels: usize, spacing_bps: u64, min_spread_bps: u64) -> LadderConfig {
+        LadderConfig {
+            levels,
+            spacing_bps,
+            size_per_level_base_units: 1.0,
+            size_growth: 2.0,
+            min_spread_bps,
+        }
+    }
+
+    #[test]
+    fn test_geometric_levels() {
+        let ladder = QuoteLadder::generate(&meta(), 10_000, config(3, 100, 20));
+        // 10 bps either side of the mid, then 1% further per level
+        assert_eq!(ladder.bids, vec![(9990, 100), (9890, 200), (9791, 400)]);
+        assert_eq!(ladder.asks, vec![(10010, 100), (10111, 200), (10212, 400)]);
+    }
+
+    #[test]
+    fn test_sub_tick_spacing_coalesces() {
+        // 1 bp of 100 ticks is a hundredth of a tick: every level lands on the same price
+        let ladder = QuoteLadder::generate(&meta(), 100, config(3, 1, 0));
+        assert_eq!(ladder.bids, vec![(99, 700)]);
+        assert_eq!(ladder.asks, vec![(101, 700)]);
+    }
+
+    #[test]
+    fn test_tiny_spread_never_crosses() {
+        for mid in [1, 2, 3, 10, 1_000_000] {
+            let ladder = QuoteLadder::generate(&meta(), mid, config(5, 1, 0));
+            let best_bid = ladder.bids.first().map(|(price, _)| *price).unwrap_or(0);
+            let best_ask = ladder.asks.first().unwrap().0;
+            assert!(best_bid < mid && mid < best_ask);
+        }
+        // No bid can be placed below one tick
+        assert!(QuoteLadder::generate(&meta(), 1, config(2, 1, 0))
+            .bids
+            .is_empty());
+    }
+
+    #[test]
+    fn test_sizes_round_down_to_lots() {
+        let cfg = LadderConfig {
+            size_per_level_base_units: 0.015,
+            size_growth: 0.5,
+            ..config(2, 100, 20)
+        };
+        // 1.5 lots rounds to 1, 0.75 lots is dropped
+        let ladder = QuoteLadder::generate(&meta(), 10_000, cfg);
+        assert_eq!(ladder.bids, vec![(9990, 1)]);
+        assert_eq!(ladder.asks, vec![(10010, 1)]);
+    }
+
+    #[test]
+    fn test_clamped_to_band() {
+        // A 2% band keeps the inner level and stacks the outer two on its edge
+        let ladder =
+            QuoteLadder::generate(&meta(), 10_000, config(3, 100, 20)).clamped_to_band(10_000, 200);
+        assert_eq!(ladder.bids, vec![(9990, 100), (9890, 200), (9800, 400)]);
+        assert_eq!(ladder.asks, vec![(10010, 100), (10111, 200), (10200, 400)]);
+        let ladder =
+            QuoteLadder::generate(&meta(), 10_000, config(3, 100, 20)).clamped_to_band(10_000, 50);
+        assert_eq!(ladder.bids, vec![(9990, 100), (9950, 600)]);
+        assert_eq!(ladder.asks, vec![(10010, 100), (10050, 600)]);
+    }
+
+    #[test]
+    fn test_generate_from_reference() {
+        use crate::reference_price::{now_ms, ReferencePrice, SharedReferencePrices};
+        use std::time::Duration;
+
+        // One tick is 0.001 quote units
+        let meta = MarketMetadata {
+            quote_atoms_per_quote_unit: 1_000_000,
+            raw_base_units_per_base_unit: 1,
+            ..meta()
+        };
+        let market = Pubkey::new_unique();
+        let prices = SharedReferencePrices::new();
+        let feed = ReferencePriceFeed::new(prices.clone(), Duration::from_secs(60));
+        let generate =
+            || QuoteLadder::generate_from_reference(&meta, &market, &feed, config(3, 100, 20));
+        assert_eq!(generate(), Err(ReferencePriceError::Missing { market }));
+
+        prices.set(
+            &market,
+            ReferencePrice {
+                price: 9.9996,
+                confidence: 0.001,
+                timestamp_ms: now_ms(),
+            },
+        );
+        assert_eq!(
+            generate().unwrap(),
+            QuoteLadder::generate(&meta, 10_000, config(3, 100, 20))
+        );
+
+        // Quoting stops once the price is older than the feed allows
+        prices.set(
+            &market,
+            ReferencePrice {
+                price: 10.0,
+                confidence: 0.001,
+                timestamp_ms: now_ms() - 61_000,
+            },
+        );
+        assert!(matches!(generate(), Err(ReferencePriceError::Stale { .. })));
+    }
+
+    #[test]
+    fn test_adjusted_against_book() {
+        let core = SDKClientCore {
+            markets: Default::default(),
+            trader: Pubkey::new_unique(),
+            default_self_trade_behavior: Default::default(),
+        };
+        let mut book = Orderbook::<FIFOOrderId, PhoenixOrder>::default();
+        let order = PhoenixOrder {
+            num_base_lots: 10,
+            maker_id: Pubkey::new_unique(),
+        };
+        book.asks
+            .insert(FIFOOrderId::new_from_untyped(9890, 1), order);
+        book.bids
+            .insert(FIFOOrderId::new_from_untyped(9800, !2), order);
+        assert_eq!(core.adjusted_post_only_price(Side::Bid, 9950, &book), 9889);
+        assert_eq!(core.adjusted_post_only_price(Side::Bid, 9889, &book), 9889);
+        assert_eq!(core.adjusted_post_only_price(Side::Ask, 9800, &book), 9801);
+        assert_eq!(core.adjusted_post_only_price(Side::Ask, 9890, &book), 9890);
+
+        // The book has moved down: the two inner bids cross and coalesce one tick under the ask
+        let ladder = QuoteLadder::generate_against_book(&meta(), 10_000, config(3, 100, 20), &book);
+        assert_eq!(ladder.bids, vec![(9889, 300), (9791, 400)]);
+        assert_eq!(ladder.asks, vec![(10010, 100), (10111, 200), (10212, 400)]);
+
+        // A bid cannot rest below an ask at one tick
+        book.asks.clear();
+        book.asks.insert(FIFOOrderId::new_from_untyped(1, 3), order);
+        assert_eq!(core.adjusted_post_only_price(Side::Bid, 5, &book), 0);
+        let ladder = QuoteLadder::generate_against_book(&meta(), 100, config(1, 100, 20), &book);
+        assert!(ladder.bids.is_empty());
+    }
+
+    #[test]
+    fn test_place_ladder_ix() {
+        let market = Pubkey::new_unique();
+        let core = SDKClientCore {
+            markets: MarketMap::from_iter([(market, meta())]),
+            trader: Pubkey::new_unique(),
+            default_self_trade_behavior: Default::default(),
+        };
+        let ladder = QuoteLadder::generate(&meta(), 10_000, config(3, 100, 20));
+        let ix = core
+            .get_place_multiple_post_only_ix(&market, ladder.bids, ladder.asks, None, true)
+            .unwrap();
+
+        let packet = MultipleOrderPacket::try_from_slice(&ix.data[1..]).unwrap();
+        assert_eq!(packet.bids.len(), 3);
+        assert_eq!(packet.asks[2].price_in_ticks, 10212);
+        assert_eq!(packet.asks[2].size_in_base_lots, 400);
+    }
+}
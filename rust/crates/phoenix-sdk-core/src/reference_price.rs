@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::sdk_client_core::MarketMetadata;
+
+/// An externally sourced fair value for a market, e.g. from an oracle or a pricing model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReferencePrice {
+    /// Quote units per raw base unit, the same float price as the order templates use.
+    pub price: f64,
+    /// Uncertainty of `price`, in the same units.
+    pub confidence: f64,
+    /// When the price was published, in Unix milliseconds.
+    pub timestamp_ms: u64,
+}
+
+impl ReferencePrice {
+    /// How old the price is at `now_ms`. A timestamp in the future counts as zero age.
+    pub fn age(&self, now_ms: u64) -> Duration {
+        Duration::from_millis(now_ms.saturating_sub(self.timestamp_ms))
+    }
+
+    /// The price rounded to the nearest tick.
+    pub fn price_in_ticks(&self, meta: &MarketMetadata) -> u64 {
+        (self.price / meta.ticks_to_float_price(1)).round() as u64
+    }
+}
+
+/// Provides reference prices by market. Implementations return the latest price they have and
+/// leave the staleness decision to the caller, see `ReferencePriceFeed`.
+pub trait ReferencePriceSource: Send + Sync {
+    fn price(&self, market: &Pubkey) -> Option<ReferencePrice>;
+}
+
+impl<S: ReferencePriceSource + ?Sized> ReferencePriceSource for Arc<S> {
+    fn price(&self, market: &Pubkey) -> Option<ReferencePrice> {
+        (**self).price(market)
+    }
+}
+
+/// Reference prices set by hand, e.g. from the caller's own model. Clones share the same prices,
+/// so one clone can be updated while another is read.
+#[derive(Debug, Clone, Default)]
+pub struct SharedReferencePrices {
+    prices: Arc<RwLock<BTreeMap<Pubkey, ReferencePrice>>>,
+}
+
+impl SharedReferencePrices {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, market: &Pubkey, price: ReferencePrice) {
+        self.prices
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(*market, price);
+    }
+
+    pub fn remove(&self, market: &Pubkey) -> Option<ReferencePrice> {
+        self.prices
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(market)
+    }
+}
+
+impl ReferencePriceSource for SharedReferencePrices {
+    fn price(&self, market: &Pubkey) -> Option<ReferencePrice> {
+        self.prices
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(market)
+            .copied()
+    }
+}
+
+/// Why `ReferencePriceFeed` has no usable price for a market.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferencePriceError {
+    Missing {
+        market: Pubkey,
+    },
+    Stale {
+        market: Pubkey,
+        age: Duration,
+        max_age: Duration,
+    },
+}
+
+impl fmt::Display for ReferencePriceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReferencePriceError::Missing { market } => {
+                write!(f, "No reference price for market {}", market)
+            }
+            ReferencePriceError::Stale {
+                market,
+                age,
+                max_age,
+            } => write!(
+                f,
+                "Reference price for market {} is {:?} old, more than {:?}",
+                market, age, max_age
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReferencePriceError {}
+
+/// A `ReferencePriceSource` with a staleness threshold. Prices older than `max_age` are errors
+/// rather than values, so that quoting and risk checks stop instead of using old data.
+#[derive(Clone)]
+pub struct ReferencePriceFeed {
+    source: Arc<dyn ReferencePriceSource>,
+    pub max_age: Duration,
+}
+
+impl fmt::Debug for ReferencePriceFeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReferencePriceFeed")
+            .field("max_age", &self.max_age)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ReferencePriceFeed {
+    pub fn new<S: ReferencePriceSource + 'static>(source: S, max_age: Duration) -> Self {
+        Self {
+            source: Arc::new(source),
+            max_age,
+        }
+    }
+
+    /// The market's price if it is at most `max_age` old.
+    pub fn price(&self, market: &Pubkey) -> Result<ReferencePrice, ReferencePriceError> {
+        self.price_at(market, now_ms())
+    }
+
+    /// Like `price`, as of `now_ms` in Unix milliseconds.
+    pub fn price_at(
+        &self,
+        market: &Pubkey,
+        now_ms: u64,
+    ) -> Result<ReferencePrice, ReferencePriceError> {
+        let price = self
+            .source
+            .price(market)
+            .ok_or(ReferencePriceError::Missing { market: *market })?;
+        let age = price.age(now_ms);
+        if age > self.max_age {
+            return Err(ReferencePriceError::Stale {
+                market: *market,
+                age,
+                max_age: self.max_age,
+            });
+        }
+        Ok(price)
+    }
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_feed_staleness() {
+        let market = Pubkey::new_unique();
+        let prices = SharedReferencePrices::new();
+        let feed = ReferencePriceFeed::new(prices.clone(), Duration::from_secs(5));
+        assert_eq!(
+            feed.price_at(&market, 0),
+            Err(ReferencePriceError::Missing { market })
+        );
+
+        let price = ReferencePrice {
+            price: 25.0,
+            confidence: 0.01,
+            timestamp_ms: 10_000,
+        };
+        prices.set(&market, price);
+        // Updates through one clone are seen through the other
+        assert_eq!(feed.price_at(&market, 15_000), Ok(price));
+        assert_eq!(feed.price_at(&market, 9_000), Ok(price));
+        assert_eq!(
+            feed.price_at(&market, 15_001),
+            Err(ReferencePriceError::Stale {
+                market,
+                age: Duration::from_millis(5_001),
+                max_age: Duration::from_secs(5),
+            })
+        );
+
+        prices.remove(&market);
+        assert!(feed.price_at(&market, 10_000).is_err());
+    }
+}
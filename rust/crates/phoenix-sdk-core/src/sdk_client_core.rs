@@ -1,41 +1,69 @@
 use anyhow::anyhow;
 
 use anyhow::Result;
-use borsh::BorshDeserialize;
+#[cfg(feature = "transaction-utils")]
 use ellipsis_transaction_utils::ParsedTransaction;
 use itertools::Itertools;
+use phoenix::program::dispatch_market::{get_market_size, load_with_dispatch};
+use phoenix::program::error::PhoenixError;
 use phoenix::program::MarketHeader;
 use phoenix::program::MarketSizeParams;
 use phoenix::program::PhoenixInstruction;
 use phoenix::quantities::QuoteLots;
 use phoenix::{
     program::cancel_multiple_orders::{CancelMultipleOrdersByIdParams, CancelUpToParams},
+    program::deposit::DepositParams,
     program::events::PhoenixMarketEvent,
     program::instruction_builders::{
         create_cancel_all_orders_instruction, create_cancel_multiple_orders_by_id_instruction,
-        create_cancel_up_to_instruction, create_new_order_instruction,
-        create_withdraw_funds_instruction,
+        create_cancel_up_to_instruction, create_deposit_funds_instruction,
+        create_new_multiple_order_instruction, create_new_order_instruction,
+        create_reduce_order_instruction_with_custom_token_accounts,
+        create_withdraw_funds_instruction, create_withdraw_funds_with_custom_amounts_instruction,
     },
-    program::reduce_order::CancelOrderParams,
+    program::new_order::{CondensedOrder, MultipleOrderPacket},
+    program::reduce_order::{CancelOrderParams, ReduceOrderParams},
     quantities::{BaseLots, Ticks, WrapperU64},
     state::enums::{SelfTradeBehavior, Side},
-    state::markets::FIFOOrderId,
+    state::markets::{FIFOOrderId, FIFORestingOrder, MarketWrapper},
     state::order_packet::OrderPacket,
     state::trader_state::TraderState,
 };
+#[cfg(feature = "rand")]
 use rand::{rngs::StdRng, Rng};
+use solana_sdk::program_error::ProgramError;
 use solana_sdk::signature::Signature;
 use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
-use std::str::FromStr;
+use std::mem::size_of;
 use std::{
     collections::BTreeMap,
     fmt::Display,
     ops::{Div, Rem},
 };
 
-use crate::{market_event::Fill, orderbook::Orderbook};
+use crate::{
+    ata_utils::get_associated_token_address,
+    atoms::{atoms_to_decimal_str, mul_div, parse_decimal_str, pow10, AmountFormat, Rounding},
+    event_iter::WrapperEventIterator,
+    market_event::Fill,
+    market_map::MarketMap,
+    order_id::{FIFOOrderIdExt, OrderId},
+    order_manager::OpenOrder,
+    orderbook::Orderbook,
+    tick_math::{n_ticks_inside, would_cross},
+};
 
-const AUDIT_LOG_HEADER_LEN: usize = 92;
+/// Loads the market stored after the header, like `load_with_dispatch`, but fails instead of
+/// panicking when `bytes` is shorter than the market.
+pub fn load_market<'a>(
+    market_size_params: &'a MarketSizeParams,
+    bytes: &'a [u8],
+) -> Result<MarketWrapper<'a, Pubkey, FIFOOrderId, FIFORestingOrder, OrderPacket>, ProgramError> {
+    if bytes.len() < get_market_size(market_size_params)? {
+        return Err(PhoenixError::FailedToLoadMarketFromAccount.into());
+    }
+    load_with_dispatch(market_size_params, bytes)
+}
 
 pub struct MarketState {
     /// State of the bids and offers in the market.
@@ -44,6 +72,58 @@ pub struct MarketState {
     pub traders: BTreeMap<Pubkey, TraderState>,
 }
 
+impl MarketState {
+    /// Deserializes the state of a market from the raw data of its account.
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        if data.len() < size_of::<MarketHeader>() {
+            return Err(anyhow!("Market account data is too short"));
+        }
+        let (header_bytes, bytes) = data.split_at(size_of::<MarketHeader>());
+        let meta = bytemuck::try_from_bytes(header_bytes)
+            .map_err(|_| anyhow!("Failed to deserialize market header"))
+            .and_then(MarketMetadata::from_header)?;
+        let market = load_market(&meta.market_size_params, bytes)
+            .map_err(|_| anyhow!("Market configuration not found"))?
+            .inner;
+        let orderbook = Orderbook::from_market(
+            market,
+            meta.raw_base_units_per_base_lot_as_float(),
+            meta.quote_units_per_raw_base_unit_per_tick_as_float(),
+        );
+        let traders = market
+            .get_registered_traders()
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .collect();
+        Ok(MarketState { orderbook, traders })
+    }
+}
+
+/// A market's state and its position in the market's event stream, for keeping a book current
+/// with `Orderbook::sync_from_events`.
+pub struct MarketSnapshot {
+    pub state: MarketState,
+    /// The market header's sequence number, which the events of the market's next instruction
+    /// will carry. Events with lower sequence numbers are reflected in `state`.
+    pub sequence_number: u64,
+    /// The slot the market account was read at.
+    pub slot: u64,
+}
+
+impl MarketSnapshot {
+    /// Deserializes a market account read at `slot`.
+    pub fn from_account_data(data: &[u8], slot: u64) -> Result<Self> {
+        let state = MarketState::from_account_data(data)?;
+        let header: &MarketHeader = bytemuck::try_from_bytes(&data[..size_of::<MarketHeader>()])
+            .map_err(|_| anyhow!("Failed to deserialize market header"))?;
+        Ok(MarketSnapshot {
+            state,
+            sequence_number: header.market_sequence_number,
+            slot,
+        })
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct RawPhoenixHeader {
     pub signature: Signature,
@@ -61,12 +141,255 @@ pub struct RawPhoenixEvent {
     pub batch: Vec<PhoenixMarketEvent>,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// A `Log` instruction of a transaction whose events could not all be parsed.
+#[derive(Debug)]
+pub struct EventParseError {
+    pub signature: Signature,
+    /// The index of the instruction among the transaction's `Log` instructions.
+    pub log_index: usize,
+    pub error: anyhow::Error,
+}
+
+impl Display for EventParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Log instruction {} of {}: {}",
+            self.log_index, self.signature, self.error
+        )
+    }
+}
+
+impl std::error::Error for EventParseError {}
+
+/// The events of a transaction up to its first malformed record, from
+/// `SDKClientCore::parse_raw_phoenix_events_lenient`.
+#[derive(Debug)]
+pub struct PartialParse {
+    /// The events of the `Log` instructions before the malformed one, and of the malformed one
+    /// up to the record that could not be parsed.
+    pub events: Vec<RawPhoenixEvent>,
+    pub error: EventParseError,
+    /// The bytes of the malformed instruction's data parsed before the error, its header
+    /// included. 0 if the header itself could not be parsed.
+    pub bytes_consumed: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
 pub struct PhoenixOrder {
     pub num_base_lots: u64,
     pub maker_id: Pubkey,
 }
 
+/// What a checked order builder does when the order would trade against the trader's own
+/// resting orders, which `SelfTradeBehavior::CancelProvide` cancels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelfCrossPolicy {
+    /// Build the instruction and return the crossed orders as a warning.
+    #[default]
+    Warn,
+    /// Return a `SelfCross` error instead of building the instruction.
+    Reject,
+}
+
+/// Self-trade behaviors the order builders use when the caller passes `None`. A market's
+/// override wins over `global`; with neither set, each builder keeps its own default
+/// (`CancelProvide` for IOC, FOK and tick-price limit orders, `DecrementTake` for
+/// `get_limit_order_generic_ix`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelfTradeDefaults {
+    pub global: Option<SelfTradeBehavior>,
+    pub per_market: BTreeMap<Pubkey, SelfTradeBehavior>,
+}
+
+impl SelfTradeDefaults {
+    /// The configured default for `market_key`, if any.
+    pub fn for_market(&self, market_key: &Pubkey) -> Option<SelfTradeBehavior> {
+        self.per_market.get(market_key).copied().or(self.global)
+    }
+}
+
+/// The trader's resting orders that a new order would trade against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelfCross {
+    pub side: Side,
+    pub price_in_ticks: u64,
+    pub order_ids: Vec<FIFOOrderId>,
+}
+
+impl Display for SelfCross {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} at {} ticks would cross {} of the trader's own orders",
+            self.side,
+            self.price_in_ticks,
+            self.order_ids.len()
+        )
+    }
+}
+
+impl std::error::Error for SelfCross {}
+
+/// An order size that converts to zero lots. `min_size` is the smallest size that does not, one
+/// lot in atoms of the token the size was given in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SizeTooSmall {
+    pub min_size: u64,
+}
+
+impl Display for SizeTooSmall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Order size rounds to zero lots, the minimum is {} atoms",
+            self.min_size
+        )
+    }
+}
+
+impl std::error::Error for SizeTooSmall {}
+
+/// An order worth less than the minimum notional passed to `normalize_order_size`, in quote
+/// atoms at the order's price. `min_base_lots` is the smallest size that is not, or `None` if no
+/// size is at this price.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BelowMinNotional {
+    pub base_lots: u64,
+    pub notional_quote_atoms: u64,
+    pub min_notional_quote_atoms: u64,
+    pub min_base_lots: Option<u64>,
+}
+
+impl Display for BelowMinNotional {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Order of {} base lots is worth {} quote atoms, below the minimum of {}",
+            self.base_lots, self.notional_quote_atoms, self.min_notional_quote_atoms
+        )?;
+        match self.min_base_lots {
+            Some(min_base_lots) => write!(
+                f,
+                " (the smallest valid size is {} base lots)",
+                min_base_lots
+            ),
+            None => write!(f, " at any size"),
+        }
+    }
+}
+
+impl std::error::Error for BelowMinNotional {}
+
+/// How `normalize_order_size` turns a size into whole base lots.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizeRounding {
+    /// Round down, failing if that is below the minimum.
+    #[default]
+    Down,
+    /// Round up, failing if that is below the minimum.
+    Up,
+    /// Round down, raising a size below the minimum to the smallest valid size.
+    UpToMinimum,
+}
+
+/// A valid order size from `normalize_order_size`. `base_units` is in raw base units, like the
+/// desired size, and `notional_quote_atoms` is what a bid of this size locks or an ask receives.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NormalizedSize {
+    pub base_lots: u64,
+    pub base_units: f64,
+    pub notional_quote_atoms: u64,
+}
+
+/// The largest mint decimals `MarketMetadata` accepts, so that one raw unit in atoms fits in a
+/// `u64`.
+pub const MAX_MINT_DECIMALS: u32 = 18;
+
+/// Why a market header does not describe a usable market. Only a malformed or malicious market
+/// account produces these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarketMetadataError {
+    DecimalsOutOfRange {
+        mint: Pubkey,
+        decimals: u32,
+    },
+    InvalidBaseLotSize,
+    InvalidQuoteLotSize,
+    InvalidTickSize,
+    /// The base atoms per base unit do not fit in a `u64`.
+    Overflow,
+}
+
+impl Display for MarketMetadataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarketMetadataError::DecimalsOutOfRange { mint, decimals } => write!(
+                f,
+                "Mint {} has {} decimals, more than the maximum of {}",
+                mint, decimals, MAX_MINT_DECIMALS
+            ),
+            MarketMetadataError::InvalidBaseLotSize => {
+                write!(f, "Invalid base lot size (in base atoms per base lot)")
+            }
+            MarketMetadataError::InvalidQuoteLotSize => {
+                write!(f, "Invalid quote lot size (in quote atoms per quote lot)")
+            }
+            MarketMetadataError::InvalidTickSize => {
+                write!(f, "Invalid tick size (in quote atoms per base unit)")
+            }
+            MarketMetadataError::Overflow => {
+                write!(f, "Base atoms per base unit overflow a u64")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MarketMetadataError {}
+
+/// One leg of a multi-market IOC, see `SDKClientCore::get_multi_market_ioc_ixs`. Sizes are in
+/// lots of the token the leg spends and receives: a bid spends `size_lots` quote lots and must
+/// receive `min_lots_out` base lots, an ask the reverse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IocLeg {
+    pub market: Pubkey,
+    pub side: Side,
+    /// `None` is a market order, bounded only by `min_lots_out`.
+    pub price_in_ticks: Option<u64>,
+    pub size_lots: u64,
+    pub min_lots_out: u64,
+}
+
+/// How `SDKClientCore::get_amend_order_ixs` changes an order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AmendPath {
+    /// The order already has the requested size and price, so no instruction is needed.
+    Unchanged,
+    /// The order is shrunk in place and keeps its queue position.
+    Reduce { lots_to_remove: u64 },
+    /// The order is cancelled and a post-only order is placed in the same transaction, at the back
+    /// of the queue.
+    CancelReplace,
+}
+
+impl AmendPath {
+    /// The cheapest way to give `order` the new size and price. Only a shrink at the same price
+    /// can be done in place, and a new size of zero cancels the order at any price.
+    pub fn choose(order: &OpenOrder, new_size_lots: u64, new_price_in_ticks: u64) -> Self {
+        if new_size_lots > 0
+            && (new_price_in_ticks != order.price_in_ticks() || new_size_lots > order.num_base_lots)
+        {
+            AmendPath::CancelReplace
+        } else if new_size_lots == order.num_base_lots {
+            AmendPath::Unchanged
+        } else {
+            AmendPath::Reduce {
+                lots_to_remove: order.num_base_lots - new_size_lots,
+            }
+        }
+    }
+}
+
 pub fn get_decimal_string<N: Display + Div + Rem + Copy + TryFrom<u64>>(
     amount: N,
     decimals: u32,
@@ -101,38 +424,51 @@ pub struct MarketMetadata {
     pub quote_atoms_per_quote_unit: u64,
     pub quote_atoms_per_quote_lot: u64,
     pub base_atoms_per_base_lot: u64,
+    /// Per Phoenix base unit, which is `raw_base_units_per_base_unit` raw base units.
     pub tick_size_in_quote_atoms_per_base_unit: u64,
+    /// Per Phoenix base unit, which is `raw_base_units_per_base_unit` raw base units.
     pub num_base_lots_per_base_unit: u64,
     /// The adjustment factor to convert from the raw base unit (i.e. 1 BONK token) to the Phoenix BaseUnit (which may be a multiple of whole tokens).
     /// The adjustment factor is almost always 1, unless one base token is worth less than one quote atom (i.e. 1e-6 USDC)
     pub raw_base_units_per_base_unit: u32,
     pub market_size_params: MarketSizeParams,
+    /// The fee charged to takers, in basis points of the notional. Makers pay no fee and receive
+    /// no rebate. Only set by `from_account_data`, since it is stored after the header.
+    pub taker_fee_bps: u64,
 }
 
 impl MarketMetadata {
+    /// Validates the header, so that a malformed market account is an error here instead of a
+    /// panic or a division by zero in the conversions. Fails with `MarketMetadataError`.
     pub fn from_header(header: &MarketHeader) -> Result<Self> {
-        let quote_atoms_per_quote_lot = header.get_quote_lot_size().into();
-        let base_atoms_per_base_lot = header.get_base_lot_size().into();
-        let quote_atoms_per_quote_unit = 10u64.pow(header.quote_params.decimals);
-        let base_atoms_per_raw_base_unit = 10u64.pow(header.base_params.decimals);
+        let quote_atoms_per_quote_lot: u64 = header.get_quote_lot_size().into();
+        let base_atoms_per_base_lot: u64 = header.get_base_lot_size().into();
         let base_mint = header.base_params.mint_key;
         let quote_mint = header.quote_params.mint_key;
-        let tick_size_in_quote_atoms_per_base_unit =
+        let quote_atoms_per_quote_unit =
+            decimals_multiplier(quote_mint, header.quote_params.decimals)?;
+        let base_atoms_per_raw_base_unit =
+            decimals_multiplier(base_mint, header.base_params.decimals)?;
+        let tick_size_in_quote_atoms_per_base_unit: u64 =
             header.get_tick_size_in_quote_atoms_per_base_unit().into();
+        if quote_atoms_per_quote_lot == 0 {
+            return Err(MarketMetadataError::InvalidQuoteLotSize.into());
+        }
+        if tick_size_in_quote_atoms_per_base_unit == 0 {
+            return Err(MarketMetadataError::InvalidTickSize.into());
+        }
         // max(1) is only relevant for old markets where the raw_base_units_per_base_unit was not set
         let raw_base_units_per_base_unit = header.raw_base_units_per_base_unit.max(1);
-        if base_atoms_per_raw_base_unit * raw_base_units_per_base_unit as u64
-            % base_atoms_per_base_lot
-            != 0
+        let base_atoms_per_base_unit = base_atoms_per_raw_base_unit
+            .checked_mul(raw_base_units_per_base_unit as u64)
+            .ok_or(MarketMetadataError::Overflow)?;
+        if base_atoms_per_base_lot == 0
+            || !base_atoms_per_base_unit.is_multiple_of(base_atoms_per_base_lot)
         {
-            return Err(anyhow!(
-                "Invalid base lot size (in base atoms per base lot)"
-            ));
+            return Err(MarketMetadataError::InvalidBaseLotSize.into());
         }
 
-        let num_base_lots_per_base_unit = (base_atoms_per_raw_base_unit
-            * raw_base_units_per_base_unit as u64)
-            / base_atoms_per_base_lot;
+        let num_base_lots_per_base_unit = base_atoms_per_base_unit / base_atoms_per_base_lot;
 
         Ok(MarketMetadata {
             base_mint,
@@ -147,21 +483,109 @@ impl MarketMetadata {
             num_base_lots_per_base_unit,
             raw_base_units_per_base_unit,
             market_size_params: header.market_size_params,
+            taker_fee_bps: 0,
         })
     }
+
+    /// Reads the metadata, including the fee schedule, from the raw data of a market account.
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        if data.len() < size_of::<MarketHeader>() {
+            return Err(anyhow!("Market account data is too short"));
+        }
+        let (header_bytes, bytes) = data.split_at(size_of::<MarketHeader>());
+        let mut meta = bytemuck::try_from_bytes(header_bytes)
+            .map_err(|_| anyhow!("Failed to deserialize market header"))
+            .and_then(MarketMetadata::from_header)?;
+        meta.taker_fee_bps = load_market(&meta.market_size_params, bytes)
+            .map_err(|_| anyhow!("Market configuration not found"))?
+            .inner
+            .get_taker_fee_bps();
+        Ok(meta)
+    }
+
+    /// The fee on a trade of `quote_atoms` notional. Like the program, it rounds the taker fee up
+    /// to a whole quote lot.
+    pub fn fee_for_notional(&self, quote_atoms: u64, is_maker: bool) -> u64 {
+        if is_maker || self.taker_fee_bps == 0 {
+            return 0;
+        }
+        let fee_quote_atoms = (quote_atoms as u128 * self.taker_fee_bps as u128).div_ceil(10_000);
+        let quote_atoms_per_quote_lot = self.quote_atoms_per_quote_lot.max(1) as u128;
+        (fee_quote_atoms.div_ceil(quote_atoms_per_quote_lot) * quote_atoms_per_quote_lot) as u64
+    }
+}
+
+/// 10^decimals, or an error if the mint has more than `MAX_MINT_DECIMALS` decimals.
+fn decimals_multiplier(mint: Pubkey, decimals: u32) -> Result<u64, MarketMetadataError> {
+    if decimals > MAX_MINT_DECIMALS {
+        return Err(MarketMetadataError::DecimalsOutOfRange { mint, decimals });
+    }
+    10u64
+        .checked_pow(decimals)
+        .ok_or(MarketMetadataError::DecimalsOutOfRange { mint, decimals })
+}
+
+/// Relative error tolerated by the float conversions before rounding, a few times the error of
+/// the handful of operations each one does.
+const FLOAT_TOLERANCE: f64 = 8.0 * f64::EPSILON;
+
+/// `x` rounded down, or to the nearest integer if it is within rounding error of it, so that an
+/// amount that converts to a whole number does not lose one to floating point error, e.g.
+/// 0.29 * 100 = 28.999999999999996.
+fn floor_tolerant(x: f64) -> u64 {
+    let nearest = x.round();
+    if (x - nearest).abs() <= FLOAT_TOLERANCE * x.abs() {
+        nearest as u64
+    } else {
+        x.floor() as u64
+    }
+}
+
+/// `x` rounded up, or to the nearest integer if it is within rounding error of it.
+fn ceil_tolerant(x: f64) -> u64 {
+    let nearest = x.round();
+    if (x - nearest).abs() <= FLOAT_TOLERANCE * x.abs() {
+        nearest as u64
+    } else {
+        x.ceil() as u64
+    }
 }
 
 impl MarketMetadata {
     /// Given a number of raw base units, returns the equivalent number of base lots (rounded down).
+    #[deprecated(
+        note = "Use raw_base_units_to_base_lots_rounded_down_as_float, or decimal_str_to_base_lots to avoid floating point"
+    )]
     pub fn raw_base_units_to_base_lots_rounded_down(&self, raw_base_units: f64) -> u64 {
+        self.raw_base_units_to_base_lots_rounded_down_as_float(raw_base_units)
+    }
+
+    /// Given a number of raw base units (as a float), returns the equivalent number of base lots
+    /// (rounded down).
+    pub fn raw_base_units_to_base_lots_rounded_down_as_float(&self, raw_base_units: f64) -> u64 {
         let base_units = raw_base_units / self.raw_base_units_per_base_unit as f64;
-        (base_units * (self.num_base_lots_per_base_unit as f64)).floor() as u64
+        floor_tolerant(base_units * self.num_base_lots_per_base_unit as f64)
     }
 
     /// Given a number of raw base units, returns the equivalent number of base lots (rounded up).
+    #[deprecated(
+        note = "Use raw_base_units_to_base_lots_rounded_up_as_float, or decimal_str_to_base_lots to avoid floating point"
+    )]
     pub fn raw_base_units_to_base_lots_rounded_up(&self, raw_base_units: f64) -> u64 {
+        self.raw_base_units_to_base_lots_rounded_up_as_float(raw_base_units)
+    }
+
+    /// Given a number of raw base units (as a float), returns the equivalent number of base lots
+    /// (rounded up).
+    pub fn raw_base_units_to_base_lots_rounded_up_as_float(&self, raw_base_units: f64) -> u64 {
         let base_units = raw_base_units / self.raw_base_units_per_base_unit as f64;
-        (base_units * (self.num_base_lots_per_base_unit as f64)).ceil() as u64
+        ceil_tolerant(base_units * self.num_base_lots_per_base_unit as f64)
+    }
+
+    /// The smallest order sizes that do not round to zero lots: one base lot in base atoms and
+    /// one quote lot in quote atoms.
+    pub fn min_order_sizes(&self) -> (u64, u64) {
+        (self.base_atoms_per_base_lot, self.quote_atoms_per_quote_lot)
     }
 
     /// Given a number of base atoms, returns the equivalent number of base lots (rounded down).
@@ -171,18 +595,49 @@ impl MarketMetadata {
 
     /// Given a number of base atoms, returns the equivalent number of base lots (rounded up).
     pub fn base_atoms_to_base_lots_rounded_up(&self, base_atoms: u64) -> u64 {
-        1 + base_atoms.saturating_sub(1) / self.base_atoms_per_base_lot
+        base_atoms.div_ceil(self.base_atoms_per_base_lot)
     }
 
-    /// Given a number of base lots, returns the equivalent number of base atoms.
+    /// Given a number of base lots, returns the equivalent number of base atoms, saturating at
+    /// `u64::MAX`.
     pub fn base_lots_to_base_atoms(&self, base_lots: u64) -> u64 {
-        base_lots * self.base_atoms_per_base_lot
+        base_lots.saturating_mul(self.base_atoms_per_base_lot)
+    }
+
+    /// Like `base_lots_to_base_atoms`, but `None` if the base atoms do not fit in a `u64`.
+    pub fn checked_base_lots_to_base_atoms(&self, base_lots: u64) -> Option<u64> {
+        base_lots.checked_mul(self.base_atoms_per_base_lot)
+    }
+
+    /// Given a number of Phoenix base units, returns the equivalent number of base lots. A base
+    /// unit is `raw_base_units_per_base_unit` raw base units, so this differs from
+    /// `raw_base_units_to_base_lots` on markets where that is not 1.
+    pub fn base_units_to_base_lots(&self, base_units: u64) -> u64 {
+        base_units.saturating_mul(self.num_base_lots_per_base_unit)
+    }
+
+    /// Given a number of raw base units (whole tokens), returns the equivalent number of base lots
+    /// (rounded down).
+    pub fn raw_base_units_to_base_lots(&self, raw_base_units: u64) -> u64 {
+        let base_atoms = raw_base_units as u128 * self.base_atoms_per_raw_base_unit as u128;
+        u64::try_from(base_atoms / self.base_atoms_per_base_lot as u128).unwrap_or(u64::MAX)
     }
 
     /// Given a number of quote units, returns the equivalent number of quote lots.
+    #[deprecated(
+        note = "Use quote_units_to_quote_lots_as_float, or decimal_str_to_quote_lots to avoid floating point"
+    )]
     pub fn quote_units_to_quote_lots(&self, quote_units: f64) -> u64 {
-        (quote_units * (self.quote_atoms_per_quote_unit / self.quote_atoms_per_quote_lot) as f64)
-            as u64
+        self.quote_units_to_quote_lots_as_float(quote_units)
+    }
+
+    /// Given a number of quote units (as a float), returns the equivalent number of quote lots
+    /// (rounded down).
+    pub fn quote_units_to_quote_lots_as_float(&self, quote_units: f64) -> u64 {
+        floor_tolerant(
+            quote_units * self.quote_atoms_per_quote_unit as f64
+                / self.quote_atoms_per_quote_lot as f64,
+        )
     }
 
     /// Given a number of quote atoms, returns the equivalent number of quote lots (rounded down).
@@ -192,7 +647,7 @@ impl MarketMetadata {
 
     /// Given a number of quote atoms, returns the equivalent number of quote lots (rounded up).
     pub fn quote_atoms_to_quote_lots_rounded_up(&self, quote_atoms: u64) -> u64 {
-        1 + quote_atoms.saturating_sub(1) / self.quote_atoms_per_quote_lot
+        quote_atoms.div_ceil(self.quote_atoms_per_quote_lot)
     }
 
     /// Given a number of quote lots, returns the equivalent number of quote atoms.
@@ -211,29 +666,50 @@ impl MarketMetadata {
     }
 
     /// Given a number of base lots and price in ticks, returns the equivalent number of quote atoms
-    /// for that price and number of base lots.
+    /// for that price and number of base lots. Like the program, it rounds down to a whole quote
+    /// lot, and saturates at `u64::MAX`.
     pub fn base_lots_and_price_to_quote_atoms(&self, base_lots: u64, price_in_ticks: u64) -> u64 {
-        base_lots * price_in_ticks * self.tick_size_in_quote_atoms_per_base_unit
-            / self.num_base_lots_per_base_unit
+        self.checked_base_lots_and_price_to_quote_atoms(base_lots, price_in_ticks)
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Like `base_lots_and_price_to_quote_atoms`, but `None` if the quote atoms do not fit in a
+    /// `u64`.
+    pub fn checked_base_lots_and_price_to_quote_atoms(
+        &self,
+        base_lots: u64,
+        price_in_ticks: u64,
+    ) -> Option<u64> {
+        let quote_atoms_per_quote_lot = self.quote_atoms_per_quote_lot.max(1) as u128;
+        let tick_size_in_quote_lots_per_base_unit =
+            self.tick_size_in_quote_atoms_per_base_unit as u128 / quote_atoms_per_quote_lot;
+        (base_lots as u128 * price_in_ticks as u128)
+            .checked_mul(tick_size_in_quote_lots_per_base_unit)
+            .map(|quote_lots| quote_lots / self.num_base_lots_per_base_unit.max(1) as u128)
+            .and_then(|quote_lots| quote_lots.checked_mul(quote_atoms_per_quote_lot))
+            .and_then(|quote_atoms| u64::try_from(quote_atoms).ok())
     }
 
     /// Given a price in quote units per raw base unit (represented as a float), returns
     /// the corresponding number of ticks (rounded down)
     pub fn float_price_to_ticks_rounded_down(&self, price: f64) -> u64 {
-        ((price
-            * self.raw_base_units_per_base_unit as f64
-            * self.quote_atoms_per_quote_unit as f64)
-            / self.tick_size_in_quote_atoms_per_base_unit as f64) as u64
+        floor_tolerant(
+            price
+                * self.raw_base_units_per_base_unit as f64
+                * self.quote_atoms_per_quote_unit as f64
+                / self.tick_size_in_quote_atoms_per_base_unit as f64,
+        )
     }
 
     /// Given a price in quote units per raw base unit (represented as a float), returns
     /// the corresponding number of ticks (rounded up)
     pub fn float_price_to_ticks_rounded_up(&self, price: f64) -> u64 {
-        ((price
-            * self.raw_base_units_per_base_unit as f64
-            * self.quote_atoms_per_quote_unit as f64)
-            / self.tick_size_in_quote_atoms_per_base_unit as f64)
-            .ceil() as u64
+        ceil_tolerant(
+            price
+                * self.raw_base_units_per_base_unit as f64
+                * self.quote_atoms_per_quote_unit as f64
+                / self.tick_size_in_quote_atoms_per_base_unit as f64,
+        )
     }
 
     /// Given a number of ticks, returns the corresponding price in quote units per raw base unit (as a float)
@@ -243,46 +719,383 @@ impl MarketMetadata {
     }
 
     /// Returns the base lot size in raw base units (as a float)
+    #[deprecated(
+        note = "Use raw_base_units_per_base_lot_as_float, or base_lots_to_decimal_str to avoid floating point"
+    )]
     pub fn raw_base_units_per_base_lot(&self) -> f64 {
+        self.raw_base_units_per_base_lot_as_float()
+    }
+
+    /// Returns the base lot size in raw base units (as a float)
+    pub fn raw_base_units_per_base_lot_as_float(&self) -> f64 {
         self.base_atoms_per_base_lot as f64 / self.base_atoms_per_raw_base_unit as f64
     }
 
     /// Returns the tick size in quote units per raw base unit
+    #[deprecated(
+        note = "Use quote_units_per_raw_base_unit_per_tick_as_float, or ticks_to_decimal_str to avoid floating point"
+    )]
     pub fn quote_units_per_raw_base_unit_per_tick(&self) -> f64 {
+        self.quote_units_per_raw_base_unit_per_tick_as_float()
+    }
+
+    /// Returns the tick size in quote units per raw base unit (as a float)
+    pub fn quote_units_per_raw_base_unit_per_tick_as_float(&self) -> f64 {
         self.tick_size_in_quote_atoms_per_base_unit as f64
             / (self.quote_atoms_per_quote_unit as f64 * self.raw_base_units_per_base_unit as f64)
     }
+
+    /// Given a decimal string of raw base units, e.g. `"1.5"`, returns the equivalent number of
+    /// base lots, without floating point.
+    pub fn decimal_str_to_base_lots(
+        &self,
+        raw_base_units: &str,
+        rounding: Rounding,
+    ) -> Result<u64> {
+        let (digits, scale) = parse_decimal_str(raw_base_units)?;
+        mul_div(
+            digits,
+            self.base_atoms_per_raw_base_unit as u128,
+            pow10(scale)?
+                .checked_mul(self.base_atoms_per_base_lot as u128)
+                .ok_or_else(|| anyhow!("Overflow converting {}", raw_base_units))?,
+            rounding,
+        )
+    }
+
+    /// Given a number of base lots, returns the exact number of raw base units as a decimal
+    /// string.
+    pub fn base_lots_to_decimal_str(&self, base_lots: u64) -> String {
+        self.base_atoms_to_decimal_str(base_lots as u128 * self.base_atoms_per_base_lot as u128)
+    }
+
+    /// Given a number of base atoms, returns the exact number of raw base units as a decimal
+    /// string.
+    pub fn base_atoms_to_decimal_str(&self, base_atoms: u128) -> String {
+        atoms_to_decimal_str(base_atoms, self.base_decimals)
+    }
+
+    /// Given a decimal string of quote units, returns the equivalent number of quote lots, without
+    /// floating point.
+    pub fn decimal_str_to_quote_lots(&self, quote_units: &str, rounding: Rounding) -> Result<u64> {
+        let (digits, scale) = parse_decimal_str(quote_units)?;
+        mul_div(
+            digits,
+            self.quote_atoms_per_quote_unit as u128,
+            pow10(scale)?
+                .checked_mul(self.quote_atoms_per_quote_lot as u128)
+                .ok_or_else(|| anyhow!("Overflow converting {}", quote_units))?,
+            rounding,
+        )
+    }
+
+    /// Given a number of quote atoms, returns the exact number of quote units as a decimal string.
+    pub fn quote_atoms_to_decimal_str(&self, quote_atoms: u128) -> String {
+        atoms_to_decimal_str(quote_atoms, self.quote_decimals)
+    }
+
+    /// Given a decimal string price in quote units per raw base unit, returns the corresponding
+    /// number of ticks, without floating point.
+    pub fn decimal_str_to_ticks(&self, price: &str, rounding: Rounding) -> Result<u64> {
+        let (digits, scale) = parse_decimal_str(price)?;
+        mul_div(
+            digits,
+            self.quote_atoms_per_quote_unit as u128 * self.raw_base_units_per_base_unit as u128,
+            pow10(scale)?
+                .checked_mul(self.tick_size_in_quote_atoms_per_base_unit as u128)
+                .ok_or_else(|| anyhow!("Overflow converting {}", price))?,
+            rounding,
+        )
+    }
+
+    /// Given a number of ticks, returns the exact price in quote units per raw base unit as a
+    /// decimal string. Fails if `raw_base_units_per_base_unit` is not a power of 10, in which
+    /// case the price may have no finite decimal representation.
+    pub fn ticks_to_decimal_str(&self, ticks: u64) -> Result<String> {
+        let mut extra_decimals = 0;
+        let mut raw_base_units_per_base_unit = self.raw_base_units_per_base_unit;
+        while raw_base_units_per_base_unit > 1 && raw_base_units_per_base_unit.is_multiple_of(10) {
+            raw_base_units_per_base_unit /= 10;
+            extra_decimals += 1;
+        }
+        if raw_base_units_per_base_unit != 1 {
+            return Err(anyhow!(
+                "Cannot format prices exactly with {} raw base units per base unit",
+                self.raw_base_units_per_base_unit
+            ));
+        }
+        Ok(atoms_to_decimal_str(
+            ticks as u128 * self.tick_size_in_quote_atoms_per_base_unit as u128,
+            self.quote_decimals + extra_decimals,
+        ))
+    }
+
+    /// Formats base atoms in raw base units for logs and UIs, e.g. `1,234.5`, with the default
+    /// `AmountFormat`. Exact, since it is integer arithmetic throughout.
+    pub fn format_base(&self, base_atoms: u64) -> String {
+        self.format_base_with(base_atoms, AmountFormat::default())
+    }
+
+    pub fn format_base_with(&self, base_atoms: u64, format: AmountFormat) -> String {
+        format.format_ratio(
+            base_atoms as u128,
+            self.base_atoms_per_raw_base_unit as u128,
+            self.base_decimals,
+        )
+    }
+
+    /// Formats quote atoms in quote units for logs and UIs, e.g. `1,234.56`, with the default
+    /// `AmountFormat`.
+    pub fn format_quote(&self, quote_atoms: u64) -> String {
+        self.format_quote_with(quote_atoms, AmountFormat::default())
+    }
+
+    pub fn format_quote_with(&self, quote_atoms: u64, format: AmountFormat) -> String {
+        format.format_ratio(
+            quote_atoms as u128,
+            self.quote_atoms_per_quote_unit as u128,
+            self.quote_decimals,
+        )
+    }
+
+    /// Formats a price in ticks in quote units per raw base unit, with the default
+    /// `AmountFormat`. Unlike `ticks_to_decimal_str`, it does not fail if
+    /// `raw_base_units_per_base_unit` is not a power of 10, but truncates the price to as many
+    /// digits as a power of 10 at least as large would need.
+    pub fn format_price(&self, ticks: u64) -> String {
+        self.format_price_with(ticks, AmountFormat::default())
+    }
+
+    pub fn format_price_with(&self, ticks: u64, format: AmountFormat) -> String {
+        let raw_base_units_per_base_unit = self.raw_base_units_per_base_unit.max(1);
+        let extra_digits = (raw_base_units_per_base_unit - 1)
+            .checked_ilog10()
+            .map_or(0, |digits| digits + 1);
+        format.format_ratio(
+            ticks as u128 * self.tick_size_in_quote_atoms_per_base_unit as u128,
+            self.quote_atoms_per_quote_unit as u128 * raw_base_units_per_base_unit as u128,
+            self.quote_decimals + extra_digits,
+        )
+    }
+
+    /// Given `(price_in_ticks, num_base_lots)` quotes, returns the base atoms and quote atoms that
+    /// resting all of them at once locks. Asks lock their size and bids their notional, rounded
+    /// up to a whole quote lot per order, so both amounts are whole lots.
+    pub fn required_deposits_for_quotes(
+        &self,
+        bids: &[(u64, u64)],
+        asks: &[(u64, u64)],
+    ) -> (u64, u64) {
+        let base_lots = asks.iter().map(|&(_, size)| size as u128).sum::<u128>();
+        let tick_size_in_quote_lots_per_base_unit = self.tick_size_in_quote_atoms_per_base_unit
+            as u128
+            / self.quote_atoms_per_quote_lot as u128;
+        let quote_lots = bids
+            .iter()
+            .map(|&(price_in_ticks, size)| {
+                (price_in_ticks as u128 * tick_size_in_quote_lots_per_base_unit * size as u128)
+                    .div_ceil(self.num_base_lots_per_base_unit as u128)
+            })
+            .sum::<u128>();
+        let to_u64 = |value: u128| u64::try_from(value).unwrap_or(u64::MAX);
+        (
+            to_u64(base_lots * self.base_atoms_per_base_lot as u128),
+            to_u64(quote_lots * self.quote_atoms_per_quote_lot as u128),
+        )
+    }
+
+    /// The quote atoms an order of `base_lots` at `price_in_ticks` is worth: what a bid locks,
+    /// rounded up to a quote lot, or what an ask receives, rounded down.
+    pub fn order_notional_quote_atoms(
+        &self,
+        side: Side,
+        price_in_ticks: u64,
+        base_lots: u64,
+    ) -> u64 {
+        match side {
+            Side::Bid => {
+                self.required_deposits_for_quotes(&[(price_in_ticks, base_lots)], &[])
+                    .1
+            }
+            Side::Ask => self.base_lots_and_price_to_quote_atoms(base_lots, price_in_ticks),
+        }
+    }
+
+    /// The smallest order, in base lots and at least one, whose `order_notional_quote_atoms` is
+    /// at least `min_notional_quote_atoms`. `None` if no order is, e.g. at a price of zero.
+    pub fn min_base_lots_for_notional(
+        &self,
+        side: Side,
+        price_in_ticks: u64,
+        min_notional_quote_atoms: u64,
+    ) -> Option<u64> {
+        let quote_atoms_per_quote_lot = self.quote_atoms_per_quote_lot.max(1) as u128;
+        let min_quote_lots = (min_notional_quote_atoms as u128).div_ceil(quote_atoms_per_quote_lot);
+        if min_quote_lots == 0 {
+            return Some(1);
+        }
+        let quote_lots_per_base_unit = price_in_ticks as u128
+            * (self.tick_size_in_quote_atoms_per_base_unit as u128 / quote_atoms_per_quote_lot);
+        if quote_lots_per_base_unit == 0 {
+            return None;
+        }
+        let num_base_lots_per_base_unit = self.num_base_lots_per_base_unit.max(1) as u128;
+        let base_lots = match side {
+            // The bid's quote lots round up, so any size past `min_quote_lots - 1` lots will do
+            Side::Bid => {
+                (min_quote_lots - 1) * num_base_lots_per_base_unit / quote_lots_per_base_unit + 1
+            }
+            Side::Ask => {
+                (min_quote_lots * num_base_lots_per_base_unit).div_ceil(quote_lots_per_base_unit)
+            }
+        };
+        u64::try_from(base_lots).ok()
+    }
+
+    /// Turns a size entered in raw base units into a valid order at `price_in_ticks`: whole base
+    /// lots, at least one, and worth at least `min_notional_quote_atoms`. Fails with
+    /// `SizeTooSmall` for sizes under one lot and `BelowMinNotional` for sizes under the minimum,
+    /// unless `rounding` raises them.
+    pub fn normalize_order_size(
+        &self,
+        side: Side,
+        price_in_ticks: u64,
+        desired_base_units: f64,
+        min_notional_quote_atoms: u64,
+        rounding: SizeRounding,
+    ) -> Result<NormalizedSize> {
+        if !desired_base_units.is_finite() || desired_base_units < 0.0 {
+            return Err(anyhow!("Invalid order size {}", desired_base_units));
+        }
+        let min_base_lots =
+            self.min_base_lots_for_notional(side, price_in_ticks, min_notional_quote_atoms);
+        let base_lots = match rounding {
+            SizeRounding::Down => {
+                self.raw_base_units_to_base_lots_rounded_down_as_float(desired_base_units)
+            }
+            SizeRounding::Up => {
+                self.raw_base_units_to_base_lots_rounded_up_as_float(desired_base_units)
+            }
+            SizeRounding::UpToMinimum => {
+                let base_lots =
+                    self.raw_base_units_to_base_lots_rounded_down_as_float(desired_base_units);
+                min_base_lots.map_or(base_lots, |min_base_lots| base_lots.max(min_base_lots))
+            }
+        };
+        if base_lots == 0 {
+            return Err(SizeTooSmall {
+                min_size: self.base_atoms_per_base_lot,
+            }
+            .into());
+        }
+        let notional_quote_atoms = self.order_notional_quote_atoms(side, price_in_ticks, base_lots);
+        if notional_quote_atoms < min_notional_quote_atoms {
+            return Err(BelowMinNotional {
+                base_lots,
+                notional_quote_atoms,
+                min_notional_quote_atoms,
+                min_base_lots,
+            }
+            .into());
+        }
+        Ok(NormalizedSize {
+            base_lots,
+            base_units: base_lots as f64 * self.raw_base_units_per_base_lot_as_float(),
+            notional_quote_atoms,
+        })
+    }
 }
 
+#[derive(Debug)]
 pub struct SDKClientCore {
-    pub markets: BTreeMap<Pubkey, MarketMetadata>,
+    pub markets: MarketMap,
     pub trader: Pubkey,
+    pub default_self_trade_behavior: SelfTradeDefaults,
 }
 
 /// Unit conversions
 impl SDKClientCore {
+    /// Given a market pubkey and the notional of a trade in quote atoms, returns the fee charged on
+    /// it, rounded up to a whole quote lot. Makers pay no fee.
+    pub fn fee_for_notional(
+        &self,
+        market_key: &Pubkey,
+        quote_atoms: u64,
+        is_maker: bool,
+    ) -> Result<u64> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .map(|m| m.fee_for_notional(quote_atoms, is_maker))
+    }
+
     /// Given a market pubkey and a number of raw base units, returns the equivalent number of base lots (rounded down).
+    #[deprecated(
+        note = "Use raw_base_units_to_base_lots_rounded_down_as_float, or decimal_str_to_base_lots to avoid floating point"
+    )]
     pub fn raw_base_units_to_base_lots_rounded_down(
         &self,
         market_key: &Pubkey,
         raw_base_units: f64,
+    ) -> Result<u64> {
+        self.raw_base_units_to_base_lots_rounded_down_as_float(market_key, raw_base_units)
+    }
+
+    /// Given a market pubkey and a number of raw base units (as a float), returns the equivalent number of base lots (rounded down).
+    pub fn raw_base_units_to_base_lots_rounded_down_as_float(
+        &self,
+        market_key: &Pubkey,
+        raw_base_units: f64,
     ) -> Result<u64> {
         self.markets
             .get(market_key)
             .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
-            .map(|m| m.raw_base_units_to_base_lots_rounded_down(raw_base_units))
+            .map(|m| m.raw_base_units_to_base_lots_rounded_down_as_float(raw_base_units))
     }
 
     /// Given a market pubkey and a number of raw base units, returns the equivalent number of base lots (rounded up).
+    #[deprecated(
+        note = "Use raw_base_units_to_base_lots_rounded_up_as_float, or decimal_str_to_base_lots to avoid floating point"
+    )]
     pub fn raw_base_units_to_base_lots_rounded_up(
         &self,
         market_key: &Pubkey,
         raw_base_units: f64,
+    ) -> Result<u64> {
+        self.raw_base_units_to_base_lots_rounded_up_as_float(market_key, raw_base_units)
+    }
+
+    /// Given a market pubkey and a number of raw base units (as a float), returns the equivalent number of base lots (rounded up).
+    pub fn raw_base_units_to_base_lots_rounded_up_as_float(
+        &self,
+        market_key: &Pubkey,
+        raw_base_units: f64,
     ) -> Result<u64> {
         self.markets
             .get(market_key)
             .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
-            .map(|m| m.raw_base_units_to_base_lots_rounded_up(raw_base_units))
+            .map(|m| m.raw_base_units_to_base_lots_rounded_up_as_float(raw_base_units))
+    }
+
+    /// Given a market pubkey and a decimal string of raw base units, returns the equivalent number of base lots, without floating point.
+    pub fn decimal_str_to_base_lots(
+        &self,
+        market_key: &Pubkey,
+        raw_base_units: &str,
+        rounding: Rounding,
+    ) -> Result<u64> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .and_then(|m| m.decimal_str_to_base_lots(raw_base_units, rounding))
+    }
+
+    /// Given a market pubkey and a number of base lots, returns the exact number of raw base units as a decimal string.
+    pub fn base_lots_to_decimal_str(&self, market_key: &Pubkey, base_lots: u64) -> Result<String> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .map(|m| m.base_lots_to_decimal_str(base_lots))
     }
 
     /// Given a market pubkey and a number of base atoms, returns the equivalent number of base lots (rounded down).
@@ -317,12 +1130,69 @@ impl SDKClientCore {
             .map(|m| m.base_lots_to_base_atoms(base_lots))
     }
 
+    /// Given a market pubkey and a number of Phoenix base units, returns the equivalent number of base lots.
+    pub fn base_units_to_base_lots(&self, market_key: &Pubkey, base_units: u64) -> Result<u64> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .map(|m| m.base_units_to_base_lots(base_units))
+    }
+
+    /// Given a market pubkey and a whole number of raw base units, returns the equivalent number of base lots (rounded down).
+    pub fn raw_base_units_to_base_lots(
+        &self,
+        market_key: &Pubkey,
+        raw_base_units: u64,
+    ) -> Result<u64> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .map(|m| m.raw_base_units_to_base_lots(raw_base_units))
+    }
+
     /// Given a market pubkey and a number of quote units, returns the equivalent number of quote lots.
+    #[deprecated(
+        note = "Use quote_units_to_quote_lots_as_float, or decimal_str_to_quote_lots to avoid floating point"
+    )]
     pub fn quote_units_to_quote_lots(&self, market_key: &Pubkey, quote_units: f64) -> Result<u64> {
+        self.quote_units_to_quote_lots_as_float(market_key, quote_units)
+    }
+
+    /// Given a market pubkey and a number of quote units (as a float), returns the equivalent number of quote lots (rounded down).
+    pub fn quote_units_to_quote_lots_as_float(
+        &self,
+        market_key: &Pubkey,
+        quote_units: f64,
+    ) -> Result<u64> {
         self.markets
             .get(market_key)
             .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
-            .map(|m| m.quote_units_to_quote_lots(quote_units))
+            .map(|m| m.quote_units_to_quote_lots_as_float(quote_units))
+    }
+
+    /// Given a market pubkey and a decimal string of quote units, returns the equivalent number of quote lots, without floating point.
+    pub fn decimal_str_to_quote_lots(
+        &self,
+        market_key: &Pubkey,
+        quote_units: &str,
+        rounding: Rounding,
+    ) -> Result<u64> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .and_then(|m| m.decimal_str_to_quote_lots(quote_units, rounding))
+    }
+
+    /// Given a market pubkey and a number of quote atoms, returns the exact number of quote units as a decimal string.
+    pub fn quote_atoms_to_decimal_str(
+        &self,
+        market_key: &Pubkey,
+        quote_atoms: u128,
+    ) -> Result<String> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .map(|m| m.quote_atoms_to_decimal_str(quote_atoms))
     }
 
     /// Given a market pubkey and a number of quote atoms, returns the equivalent number of quote lots (rounded down).
@@ -435,63 +1305,351 @@ impl SDKClientCore {
             .map(|m| m.ticks_to_float_price(ticks))
     }
 
-    /// Given a market, returns the base lot size in raw base units (as a float)
-    pub fn raw_base_units_per_base_lot(&self, market_key: &Pubkey) -> Result<f64> {
+    /// Given a market pubkey and a decimal string price in quote units per raw base unit, returns the corresponding number of ticks, without floating point.
+    pub fn decimal_str_to_ticks(
+        &self,
+        market_key: &Pubkey,
+        price: &str,
+        rounding: Rounding,
+    ) -> Result<u64> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .and_then(|m| m.decimal_str_to_ticks(price, rounding))
+    }
+
+    /// Given a market pubkey and a number of ticks, returns the exact price in quote units per raw base unit as a decimal string.
+    pub fn ticks_to_decimal_str(&self, market_key: &Pubkey, ticks: u64) -> Result<String> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .and_then(|m| m.ticks_to_decimal_str(ticks))
+    }
+
+    /// Given a market, returns the base lot size in raw base units (as a float)
+    #[deprecated(
+        note = "Use raw_base_units_per_base_lot_as_float, or base_lots_to_decimal_str to avoid floating point"
+    )]
+    pub fn raw_base_units_per_base_lot(&self, market_key: &Pubkey) -> Result<f64> {
+        self.raw_base_units_per_base_lot_as_float(market_key)
+    }
+
+    /// Given a market, returns the base lot size in raw base units (as a float)
+    pub fn raw_base_units_per_base_lot_as_float(&self, market_key: &Pubkey) -> Result<f64> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .map(|m| m.raw_base_units_per_base_lot_as_float())
+    }
+
+    /// Given a market, returns the tick size in quote units per raw base unit
+    #[deprecated(
+        note = "Use quote_units_per_raw_base_unit_per_tick_as_float, or ticks_to_decimal_str to avoid floating point"
+    )]
+    pub fn quote_units_per_raw_base_unit_per_tick(&self, market_key: &Pubkey) -> Result<f64> {
+        self.quote_units_per_raw_base_unit_per_tick_as_float(market_key)
+    }
+
+    /// Given a market, returns the tick size in quote units per raw base unit (as a float)
+    pub fn quote_units_per_raw_base_unit_per_tick_as_float(
+        &self,
+        market_key: &Pubkey,
+    ) -> Result<f64> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .map(|m| m.quote_units_per_raw_base_unit_per_tick_as_float())
+    }
+}
+
+/// See `SDKClientCore::adjusted_post_only_price`.
+pub(crate) fn adjusted_post_only_price(
+    side: Side,
+    desired_price_ticks: u64,
+    book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+) -> u64 {
+    let Some((best_opposite, _)) = book.iter_side(side.opposite()).next() else {
+        return desired_price_ticks;
+    };
+    let best_opposite = best_opposite.price_in_ticks.as_u64();
+    if would_cross(side, desired_price_ticks, best_opposite) {
+        n_ticks_inside(best_opposite, side, 1).unwrap_or(0)
+    } else {
+        desired_price_ticks
+    }
+}
+
+impl SDKClientCore {
+    /// Generate a random client order id. Takes the RNG so that callers without OS entropy,
+    /// e.g. on wasm32, can pass a seeded one.
+    #[cfg(feature = "rand")]
+    pub fn get_next_client_order_id(&self, rng: &mut StdRng) -> u128 {
+        rng.gen::<u128>()
+    }
+
+    /// Returns `trader`'s orders in `book` that a new order on `side` at `price_in_ticks` would
+    /// trade against, best price first. For a market order, pass `u64::MAX` for a bid and 0 for
+    /// an ask.
+    pub fn check_self_cross(
+        &self,
+        side: Side,
+        price_in_ticks: u64,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        trader: &Pubkey,
+    ) -> Vec<FIFOOrderId> {
+        book.iter_side(side.opposite())
+            .take_while(|(order_id, _)| match side {
+                Side::Bid => order_id.price_in_ticks.as_u64() <= price_in_ticks,
+                Side::Ask => order_id.price_in_ticks.as_u64() >= price_in_ticks,
+            })
+            .filter(|(_, order)| order.maker_id == *trader)
+            .map(|(order_id, _)| *order_id)
+            .collect()
+    }
+
+    /// The price a post-only order on `side` at `desired_price_ticks` would rest at if sent with
+    /// `improve_price_on_cross` set, given `book`: one tick inside the opposite best if it would
+    /// cross, or the desired price otherwise. A bid that would have to rest at 0 ticks is rejected
+    /// on-chain; 0 is returned for it.
+    ///
+    /// The on-chain adjustment uses the book at the time the transaction lands, so the price can
+    /// diverge from this one if the opposite side moves in between. Pre-adjusting the price
+    /// client-side makes the order rest at the returned price or fail, rather than rest at
+    /// whatever the book allows on landing.
+    pub fn adjusted_post_only_price(
+        &self,
+        side: Side,
+        desired_price_ticks: u64,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+    ) -> u64 {
+        adjusted_post_only_price(side, desired_price_ticks, book)
+    }
+
+    /// Runs `check_self_cross` for this client's trader and applies `policy` to the result.
+    fn apply_self_cross_policy(
+        &self,
+        side: Side,
+        price_in_ticks: u64,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        policy: SelfCrossPolicy,
+    ) -> Result<Option<SelfCross>> {
+        let order_ids = self.check_self_cross(side, price_in_ticks, book, &self.trader);
+        if order_ids.is_empty() {
+            return Ok(None);
+        }
+        let self_cross = SelfCross {
+            side,
+            price_in_ticks,
+            order_ids,
+        };
+        match policy {
+            SelfCrossPolicy::Warn => Ok(Some(self_cross)),
+            SelfCrossPolicy::Reject => Err(self_cross.into()),
+        }
+    }
+
+    /// The self-trade behavior an order on `market_key` is built with: `self_trade_behavior` if
+    /// given, then the configured default for the market, then `builder_default`.
+    pub fn effective_self_trade_behavior(
+        &self,
+        market_key: &Pubkey,
+        self_trade_behavior: Option<SelfTradeBehavior>,
+        builder_default: SelfTradeBehavior,
+    ) -> SelfTradeBehavior {
+        self_trade_behavior
+            .or_else(|| self.default_self_trade_behavior.for_market(market_key))
+            .unwrap_or(builder_default)
+    }
+
+    pub fn get_market_metadata(&self, market_key: &Pubkey) -> MarketMetadata {
+        match self.markets.get(market_key) {
+            Some(market_metadata) => market_metadata,
+            None => panic!("Market not found! Please load in the market first."),
+        }
+    }
+
+    /// Given a market pubkey, returns one base lot in base atoms and one quote lot in quote atoms,
+    /// below which the order builders return `SizeTooSmall`.
+    pub fn min_order_sizes(&self, market_key: &Pubkey) -> Result<(u64, u64)> {
+        self.markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .map(|m| m.min_order_sizes())
+    }
+
+    /// Given a market pubkey, rounds a size in raw base units to a valid order at `price_in_ticks`.
+    /// See `MarketMetadata::normalize_order_size`.
+    pub fn normalize_order_size(
+        &self,
+        market_key: &Pubkey,
+        side: Side,
+        price_in_ticks: u64,
+        desired_base_units: f64,
+        min_notional_quote_atoms: u64,
+        rounding: SizeRounding,
+    ) -> Result<NormalizedSize> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
-            .map(|m| m.raw_base_units_per_base_lot())
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))?
+            .normalize_order_size(
+                side,
+                price_in_ticks,
+                desired_base_units,
+                min_notional_quote_atoms,
+                rounding,
+            )
     }
 
-    /// Given a market, returns the tick size in quote units per raw base unit
-    pub fn quote_units_per_raw_base_unit_per_tick(&self, market_key: &Pubkey) -> Result<f64> {
+    /// Given a market pubkey and `(price_in_ticks, num_base_lots)` quotes, returns the base atoms
+    /// and quote atoms that must be deposited for all of them to rest with
+    /// `use_only_deposited_funds` set.
+    pub fn required_deposits_for_quotes(
+        &self,
+        market_key: &Pubkey,
+        bids: &[(u64, u64)],
+        asks: &[(u64, u64)],
+    ) -> Result<(u64, u64)> {
         self.markets
             .get(market_key)
             .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
-            .map(|m| m.quote_units_per_raw_base_unit_per_tick())
+            .map(|m| m.required_deposits_for_quotes(bids, asks))
     }
-}
 
-impl SDKClientCore {
-    /// Generate a random client order id
-    pub fn get_next_client_order_id(&self, rng: &mut StdRng) -> u128 {
-        rng.gen::<u128>()
+    /// Returns the base and quote lots that `get_top_up_deposits_ixs` would deposit for the quotes,
+    /// given the trader's current `trader_state`.
+    pub fn deposit_shortfall_in_lots(
+        &self,
+        market_key: &Pubkey,
+        trader_state: &TraderState,
+        bids: &[(u64, u64)],
+        asks: &[(u64, u64)],
+    ) -> Result<(u64, u64)> {
+        let meta = self
+            .markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))?;
+        let (base_atoms, quote_atoms) = meta.required_deposits_for_quotes(bids, asks);
+        let base_lots =
+            trader_state.base_lots_locked.as_u64() + trader_state.base_lots_free.as_u64();
+        let quote_lots =
+            trader_state.quote_lots_locked.as_u64() + trader_state.quote_lots_free.as_u64();
+        Ok((
+            meta.base_atoms_to_base_lots_rounded_up(base_atoms)
+                .saturating_sub(base_lots),
+            meta.quote_atoms_to_quote_lots_rounded_up(quote_atoms)
+                .saturating_sub(quote_lots),
+        ))
     }
 
-    pub fn get_market_metadata(&self, market_key: &Pubkey) -> &MarketMetadata {
-        match self.markets.get(market_key) {
-            Some(market_metadata) => market_metadata,
-            None => panic!("Market not found! Please load in the market first."),
+    /// Returns the instructions that bring the trader's deposits on a market in line with the
+    /// quotes, given its current `trader_state`. The quotes are assumed to replace the trader's
+    /// resting orders, so locked funds count as deposited.
+    ///
+    /// A shortfall on either side is deposited. With `withdraw_buffer_bps` set, free funds in
+    /// excess of the requirement plus that buffer are withdrawn; otherwise nothing is withdrawn.
+    pub fn get_top_up_deposits_ixs(
+        &self,
+        market_key: &Pubkey,
+        trader_state: &TraderState,
+        bids: &[(u64, u64)],
+        asks: &[(u64, u64)],
+        withdraw_buffer_bps: Option<u64>,
+    ) -> Result<Vec<Instruction>> {
+        let meta = self
+            .markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))?;
+        let (base_atoms, quote_atoms) = meta.required_deposits_for_quotes(bids, asks);
+        let required_base_lots = meta.base_atoms_to_base_lots_rounded_up(base_atoms);
+        let required_quote_lots = meta.quote_atoms_to_quote_lots_rounded_up(quote_atoms);
+        let base_lots_free = trader_state.base_lots_free.as_u64();
+        let quote_lots_free = trader_state.quote_lots_free.as_u64();
+        let base_lots = trader_state.base_lots_locked.as_u64() + base_lots_free;
+        let quote_lots = trader_state.quote_lots_locked.as_u64() + quote_lots_free;
+
+        let base_lots_to_deposit = required_base_lots.saturating_sub(base_lots);
+        let quote_lots_to_deposit = required_quote_lots.saturating_sub(quote_lots);
+        let (base_lots_to_withdraw, quote_lots_to_withdraw) = match withdraw_buffer_bps {
+            Some(buffer_bps) => {
+                let excess = |lots: u64, required: u64, free: u64| {
+                    let allowed = required as u128 * (10_000 + buffer_bps as u128) / 10_000;
+                    (lots as u128).saturating_sub(allowed).min(free as u128) as u64
+                };
+                (
+                    excess(base_lots, required_base_lots, base_lots_free),
+                    excess(quote_lots, required_quote_lots, quote_lots_free),
+                )
+            }
+            None => (0, 0),
+        };
+
+        let mut ixs = vec![];
+        if base_lots_to_deposit > 0 || quote_lots_to_deposit > 0 {
+            ixs.push(self.get_deposit_ix(
+                market_key,
+                base_lots_to_deposit,
+                quote_lots_to_deposit,
+            )?);
+        }
+        if base_lots_to_withdraw > 0 || quote_lots_to_withdraw > 0 {
+            ixs.push(self.get_withdraw_lots_ix(
+                market_key,
+                base_lots_to_withdraw,
+                quote_lots_to_withdraw,
+            )?);
         }
+        Ok(ixs)
     }
 
+    /// Decodes the data of Phoenix `Log` instructions, merging chunks of the same instruction.
+    /// Returns `None` if any chunk is malformed. Built on `WrapperEventIterator`, which indexers
+    /// can use directly to skip events without decoding them.
     pub fn parse_raw_phoenix_events(
         &self,
         sig: &Signature,
         events: Vec<Vec<u8>>,
     ) -> Option<Vec<RawPhoenixEvent>> {
-        let mut market_events: Vec<RawPhoenixEvent> = vec![];
+        match self.parse_raw_phoenix_events_lenient(sig, events) {
+            Ok(events) => Some(events),
+            Err(partial) => {
+                println!("Error parsing events: {}", partial.error);
+                None
+            }
+        }
+    }
 
-        for event in events.iter() {
-            let header_event =
-                PhoenixMarketEvent::try_from_slice(&event[..AUDIT_LOG_HEADER_LEN]).ok()?;
-            let header = match header_event {
-                PhoenixMarketEvent::Header(header) => Some(header),
-                _ => {
-                    panic!("Expected a header event");
+    /// Like `parse_raw_phoenix_events`, but a malformed chunk, e.g. one truncated by a node,
+    /// does not lose the events before it: the error is returned with every event parsed up to
+    /// the malformed record. Chunks after it are not parsed.
+    pub fn parse_raw_phoenix_events_lenient(
+        &self,
+        sig: &Signature,
+        events: Vec<Vec<u8>>,
+    ) -> std::result::Result<Vec<RawPhoenixEvent>, PartialParse> {
+        let mut market_events: Vec<RawPhoenixEvent> = vec![];
+        let mut failure = None;
+
+        for (log_index, event) in events.iter().enumerate() {
+            let mut iter = match WrapperEventIterator::new(event) {
+                Ok(iter) => iter,
+                Err(error) => {
+                    failure = Some((log_index, error, 0));
+                    break;
                 }
-            }?;
-            let offset = AUDIT_LOG_HEADER_LEN;
-            let mut phoenix_event_bytes = (header.total_events as u32).to_le_bytes().to_vec();
-            phoenix_event_bytes.extend_from_slice(&event[offset..]);
-            let phoenix_events =
-                match Vec::<PhoenixMarketEvent>::try_from_slice(&phoenix_event_bytes) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        println!("Error parsing events: {:?}", e);
-                        return None;
+            };
+            let header = *iter.header();
+            let mut phoenix_events = vec![];
+            let mut bytes_consumed = iter.bytes_consumed();
+            while let Some(event) = iter.next() {
+                match event.and_then(|event| event.to_event()) {
+                    Ok(event) => phoenix_events.push(event),
+                    Err(error) => {
+                        failure = Some((log_index, error, bytes_consumed));
+                        break;
                     }
-                };
+                }
+                bytes_consumed = iter.bytes_consumed();
+            }
 
             market_events.push(RawPhoenixEvent {
                 header: RawPhoenixHeader {
@@ -505,6 +1663,9 @@ impl SDKClientCore {
                 },
                 batch: phoenix_events,
             });
+            if failure.is_some() {
+                break;
+            }
         }
 
         // This dedupes chunks with the same sequence number into a single list of events
@@ -521,42 +1682,99 @@ impl SDKClientCore {
             })
             .collect();
 
-        Some(market_events)
+        match failure {
+            None => Ok(market_events),
+            Some((log_index, error, bytes_consumed)) => Err(PartialParse {
+                events: market_events,
+                error: EventParseError {
+                    signature: *sig,
+                    log_index,
+                    error,
+                },
+                bytes_consumed,
+            }),
+        }
     }
 
+    /// Searches every inner instruction, so events logged when Phoenix is called by another
+    /// program, e.g. an aggregator routing a swap, are included. `tx` must be parsed with the
+    /// addresses loaded from lookup tables, as `EllipsisClient::get_transaction` does.
+    #[cfg(feature = "transaction-utils")]
     pub fn parse_events_from_transaction(
         &self,
         tx: &ParsedTransaction,
     ) -> Option<Vec<RawPhoenixEvent>> {
-        let sig = Signature::from_str(&tx.signature).ok()?;
-        let mut event_list = vec![];
-        for inner_ixs in tx.inner_instructions.iter() {
-            for inner_ix in inner_ixs.iter() {
-                let current_program_id = inner_ix.instruction.program_id.clone();
-                if current_program_id != phoenix::id().to_string() {
-                    continue;
-                }
-                if inner_ix.instruction.data.is_empty() {
-                    continue;
-                }
-                let (tag, data) = match inner_ix.instruction.data.split_first() {
-                    Some((tag, data)) => (*tag, data),
-                    None => continue,
-                };
-                let ix_enum = match PhoenixInstruction::try_from(tag).ok() {
-                    Some(ix) => ix,
-                    None => continue,
-                };
-                if matches!(ix_enum, PhoenixInstruction::Log) {
-                    event_list.push(data.to_vec());
-                }
-            }
+        let sig = tx.signature.parse::<Signature>().ok()?;
+        self.parse_events_from_instruction_data(&sig, phoenix_inner_instructions(tx))
+    }
+
+    /// Parses the events of transaction `sig` from the instructions it invoked, given as
+    /// `(program_id, data)` pairs in order, e.g. its decoded inner instructions. Phoenix logs its
+    /// events with `Log` instructions to itself, so all other instructions are skipped.
+    pub fn parse_events_from_instruction_data<'a>(
+        &self,
+        sig: &Signature,
+        instructions: impl IntoIterator<Item = (Pubkey, &'a [u8])>,
+    ) -> Option<Vec<RawPhoenixEvent>> {
+        self.parse_raw_phoenix_events(sig, log_instruction_data(instructions))
+    }
+
+    /// Like `parse_events_from_instruction_data`, keeping the events before a malformed record.
+    /// See `parse_raw_phoenix_events_lenient`.
+    pub fn parse_events_from_instruction_data_lenient<'a>(
+        &self,
+        sig: &Signature,
+        instructions: impl IntoIterator<Item = (Pubkey, &'a [u8])>,
+    ) -> std::result::Result<Vec<RawPhoenixEvent>, PartialParse> {
+        self.parse_raw_phoenix_events_lenient(sig, log_instruction_data(instructions))
+    }
+}
+
+/// The instructions a parsed transaction invoked on the Phoenix program, as the
+/// `(program_id, data)` pairs `SDKClientCore::parse_events_from_instruction_data` takes. `tx`
+/// must be parsed with the addresses loaded from lookup tables.
+#[cfg(feature = "transaction-utils")]
+pub fn phoenix_inner_instructions(tx: &ParsedTransaction) -> impl Iterator<Item = (Pubkey, &[u8])> {
+    let phoenix_program_id = phoenix::id().to_string();
+    tx.inner_instructions
+        .iter()
+        .flatten()
+        .filter(move |inner_ix| inner_ix.instruction.program_id == phoenix_program_id)
+        .map(|inner_ix| (phoenix::id(), inner_ix.instruction.data.as_slice()))
+}
+
+/// The data of the Phoenix `Log` instructions among `instructions`, without the instruction tag.
+fn log_instruction_data<'a>(
+    instructions: impl IntoIterator<Item = (Pubkey, &'a [u8])>,
+) -> Vec<Vec<u8>> {
+    let mut event_list = vec![];
+    for (program_id, data) in instructions {
+        if program_id != phoenix::id() {
+            continue;
+        }
+        let (tag, data) = match data.split_first() {
+            Some((tag, data)) => (*tag, data),
+            None => continue,
+        };
+        let ix_enum = match PhoenixInstruction::try_from(tag).ok() {
+            Some(ix) => ix,
+            None => continue,
+        };
+        if matches!(ix_enum, PhoenixInstruction::Log) {
+            event_list.push(data.to_vec());
         }
-        self.parse_raw_phoenix_events(&sig, event_list)
     }
+    event_list
 }
 
 /// SDKClientCore instruction builders
+///
+/// Most builders take sizes in base lots, and prices in quote atoms per Phoenix base unit, which
+/// they round down to whole ticks of `tick_size_in_quote_atoms_per_base_unit`. The
+/// `_from_tick_price` builders take prices in ticks. A Phoenix base unit is
+/// `raw_base_units_per_base_unit` raw base units (whole tokens), so use
+/// `raw_base_units_to_base_lots` for token amounts and `base_units_to_base_lots` for amounts in
+/// Phoenix base units.
 impl SDKClientCore {
     pub fn get_ioc_ix(
         &self,
@@ -598,7 +1816,11 @@ impl SDKClientCore {
             .get(market_key)
             .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
         let num_quote_ticks_per_base_unit = price / market.tick_size_in_quote_atoms_per_base_unit;
-        let self_trade_behavior = self_trade_behavior.unwrap_or(SelfTradeBehavior::CancelProvide);
+        let self_trade_behavior = self.effective_self_trade_behavior(
+            market_key,
+            self_trade_behavior,
+            SelfTradeBehavior::CancelProvide,
+        );
         let client_order_id = client_order_id.unwrap_or(0);
         let use_only_deposited_funds = use_only_deposited_funds.unwrap_or(false);
         let order_packet = OrderPacket::ImmediateOrCancel {
@@ -624,6 +1846,125 @@ impl SDKClientCore {
         ))
     }
 
+    /// Builds an IOC order with every field of the packet set explicitly.
+    ///
+    /// `price_in_ticks` of `None` is a market order. Either size may be used as the budget;
+    /// `min_base_lots_out` and `min_quote_lots_out` make the order fail unless at least that much
+    /// is received, so a bid may only set the former and an ask only the latter.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_ioc_full_ix(
+        &self,
+        market_key: &Pubkey,
+        side: Side,
+        price_in_ticks: Option<u64>,
+        num_base_lots: u64,
+        num_quote_lots: u64,
+        min_base_lots_out: u64,
+        min_quote_lots_out: u64,
+        self_trade_behavior: SelfTradeBehavior,
+        match_limit: Option<u64>,
+        client_order_id: u128,
+        use_only_deposited_funds: bool,
+    ) -> Result<Instruction> {
+        let market = self
+            .markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+        if num_base_lots == 0 && num_quote_lots == 0 {
+            return Err(anyhow!(
+                "One of num_base_lots or num_quote_lots must be non-zero"
+            ));
+        }
+        match side {
+            Side::Bid if min_quote_lots_out != 0 => {
+                return Err(anyhow!(
+                    "A bid receives base lots, so min_quote_lots_out must be zero"
+                ))
+            }
+            Side::Ask if min_base_lots_out != 0 => {
+                return Err(anyhow!(
+                    "An ask receives quote lots, so min_base_lots_out must be zero"
+                ))
+            }
+            _ => {}
+        }
+        if num_base_lots != 0 && min_base_lots_out > num_base_lots {
+            return Err(anyhow!(
+                "min_base_lots_out ({}) exceeds num_base_lots ({})",
+                min_base_lots_out,
+                num_base_lots
+            ));
+        }
+        let order_packet = OrderPacket::ImmediateOrCancel {
+            side,
+            price_in_ticks: price_in_ticks.map(Ticks::new),
+            num_base_lots: BaseLots::new(num_base_lots),
+            num_quote_lots: QuoteLots::new(num_quote_lots),
+            min_base_lots_to_fill: BaseLots::new(min_base_lots_out),
+            min_quote_lots_to_fill: QuoteLots::new(min_quote_lots_out),
+            self_trade_behavior,
+            match_limit,
+            client_order_id,
+            use_only_deposited_funds,
+            last_valid_slot: None,
+            last_valid_unix_timestamp_in_seconds: None,
+        };
+        Ok(create_new_order_instruction(
+            &market_key.clone(),
+            &self.trader,
+            &market.base_mint,
+            &market.quote_mint,
+            &order_packet,
+        ))
+    }
+
+    /// Builds one IOC instruction per leg, each against its own market's metadata, to be sent
+    /// together in a single transaction.
+    ///
+    /// The legs are atomic only because of that: if any leg fills less than its `min_lots_out`,
+    /// its instruction fails and the whole transaction reverts, including legs that already
+    /// filled. Sending the instructions in separate transactions loses that guarantee.
+    pub fn get_multi_market_ioc_ixs(&self, legs: &[IocLeg]) -> Result<Vec<Instruction>> {
+        if legs.is_empty() {
+            return Err(anyhow!("At least one leg is required"));
+        }
+        if let Some(leg) = legs
+            .iter()
+            .find(|leg| !self.markets.contains_key(&leg.market))
+        {
+            return Err(anyhow!(
+                "Market {} not found! Please load in the market first.",
+                leg.market
+            ));
+        }
+        legs.iter()
+            .map(|leg| {
+                let (num_base_lots, num_quote_lots, min_base_lots_out, min_quote_lots_out) =
+                    match leg.side {
+                        Side::Bid => (0, leg.size_lots, leg.min_lots_out, 0),
+                        Side::Ask => (leg.size_lots, 0, 0, leg.min_lots_out),
+                    };
+                self.get_ioc_full_ix(
+                    &leg.market,
+                    leg.side,
+                    leg.price_in_ticks,
+                    num_base_lots,
+                    num_quote_lots,
+                    min_base_lots_out,
+                    min_quote_lots_out,
+                    self.effective_self_trade_behavior(
+                        &leg.market,
+                        None,
+                        SelfTradeBehavior::CancelProvide,
+                    ),
+                    None,
+                    0,
+                    false,
+                )
+            })
+            .collect()
+    }
+
     pub fn get_fok_sell_ix(
         &self,
         market_key: &Pubkey,
@@ -722,13 +2063,23 @@ impl SDKClientCore {
             .markets
             .get(market_key)
             .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
-        let self_trade_behavior = self_trade_behavior.unwrap_or(SelfTradeBehavior::CancelProvide);
+        let self_trade_behavior = self.effective_self_trade_behavior(
+            market_key,
+            self_trade_behavior,
+            SelfTradeBehavior::CancelProvide,
+        );
         let client_order_id = client_order_id.unwrap_or(0);
         let target_price_in_ticks = price / market.tick_size_in_quote_atoms_per_base_unit;
         let use_only_deposited_funds = use_only_deposited_funds.unwrap_or(false);
         match side {
             Side::Bid => {
                 let quote_lot_budget = size / market.quote_atoms_per_quote_lot;
+                if quote_lot_budget == 0 {
+                    return Err(SizeTooSmall {
+                        min_size: market.quote_atoms_per_quote_lot,
+                    }
+                    .into());
+                }
                 Ok(create_new_order_instruction(
                     &market_key.clone(),
                     &self.trader,
@@ -746,6 +2097,12 @@ impl SDKClientCore {
             }
             Side::Ask => {
                 let num_base_lots = size / market.base_atoms_per_base_lot;
+                if num_base_lots == 0 {
+                    return Err(SizeTooSmall {
+                        min_size: market.base_atoms_per_base_lot,
+                    }
+                    .into());
+                }
                 Ok(create_new_order_instruction(
                     &market_key.clone(),
                     &self.trader,
@@ -775,10 +2132,24 @@ impl SDKClientCore {
             .markets
             .get(market_key)
             .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
-        let order_type = match side {
-            Side::Bid => OrderPacket::new_ioc_buy_with_slippage(lots_in, min_lots_out),
-            Side::Ask => OrderPacket::new_ioc_sell_with_slippage(lots_in, min_lots_out),
+        let (num_base_lots, num_quote_lots, min_base_lots_out, min_quote_lots_out) = match side {
+            Side::Bid => (0, lots_in, min_lots_out, 0),
+            Side::Ask => (lots_in, 0, 0, min_lots_out),
         };
+        let order_type = OrderPacket::new_ioc(
+            side,
+            None,
+            num_base_lots,
+            num_quote_lots,
+            min_base_lots_out,
+            min_quote_lots_out,
+            self.effective_self_trade_behavior(market_key, None, SelfTradeBehavior::CancelProvide),
+            None,
+            0,
+            false,
+            None,
+            None,
+        );
 
         Ok(create_new_order_instruction(
             &market_key.clone(),
@@ -789,12 +2160,34 @@ impl SDKClientCore {
         ))
     }
 
+    #[deprecated(
+        note = "Use get_ioc_from_tick_price_generic_ix, which takes the client order id, self-trade behavior and match limit"
+    )]
     pub fn get_ioc_from_tick_price_ix(
         &self,
         market_key: &Pubkey,
         tick_price: u64,
         side: Side,
         size: u64,
+    ) -> Result<Instruction> {
+        self.get_ioc_from_tick_price_generic_ix(
+            market_key, tick_price, side, size, None, None, None,
+        )
+    }
+
+    /// An IOC order for `size` base lots at a limit of `tick_price` ticks. A `self_trade_behavior`
+    /// of `None` uses the configured default, or `CancelProvide`. A `client_order_id` of `None`
+    /// is 0; to tag the order with a random id, pass one from `get_next_client_order_id`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_ioc_from_tick_price_generic_ix(
+        &self,
+        market_key: &Pubkey,
+        tick_price: u64,
+        side: Side,
+        size: u64,
+        self_trade_behavior: Option<SelfTradeBehavior>,
+        match_limit: Option<u64>,
+        client_order_id: Option<u128>,
     ) -> Result<Instruction> {
         let market = self
             .markets
@@ -809,9 +2202,13 @@ impl SDKClientCore {
                 side,
                 tick_price,
                 size,
-                SelfTradeBehavior::CancelProvide,
-                None,
-                0,
+                self.effective_self_trade_behavior(
+                    market_key,
+                    self_trade_behavior,
+                    SelfTradeBehavior::CancelProvide,
+                ),
+                match_limit,
+                client_order_id.unwrap_or(0),
                 false,
             ),
         ))
@@ -909,6 +2306,45 @@ impl SDKClientCore {
         ))
     }
 
+    /// Places several post-only orders in one instruction. `bids` and `asks` are
+    /// `(price_in_ticks, num_base_lots)` pairs, e.g. the levels of a `QuoteLadder`.
+    /// If `reject_post_only` is false, crossing orders are amended to the best non-crossing price
+    /// instead of failing the instruction.
+    pub fn get_place_multiple_post_only_ix(
+        &self,
+        market_key: &Pubkey,
+        bids: Vec<(u64, u64)>,
+        asks: Vec<(u64, u64)>,
+        client_order_id: Option<u128>,
+        reject_post_only: bool,
+    ) -> Result<Instruction> {
+        let market = self
+            .markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+        let to_condensed = |orders: Vec<(u64, u64)>| {
+            orders
+                .into_iter()
+                .map(|(price_in_ticks, num_base_lots)| {
+                    CondensedOrder::new_default(price_in_ticks, num_base_lots)
+                })
+                .collect::<Vec<_>>()
+        };
+        let multiple_order_packet = MultipleOrderPacket::new(
+            to_condensed(bids),
+            to_condensed(asks),
+            client_order_id,
+            reject_post_only,
+        );
+        Ok(create_new_multiple_order_instruction(
+            &market_key.clone(),
+            &self.trader,
+            &market.base_mint,
+            &market.quote_mint,
+            &multiple_order_packet,
+        ))
+    }
+
     pub fn get_limit_order_ix(
         &self,
         market_key: &Pubkey,
@@ -941,7 +2377,11 @@ impl SDKClientCore {
             .get(market_key)
             .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
         let num_quote_ticks_per_base_unit = price / market.tick_size_in_quote_atoms_per_base_unit;
-        let self_trade_behavior = self_trade_behavior.unwrap_or(SelfTradeBehavior::DecrementTake);
+        let self_trade_behavior = self.effective_self_trade_behavior(
+            market_key,
+            self_trade_behavior,
+            SelfTradeBehavior::DecrementTake,
+        );
         let client_order_id = client_order_id.unwrap_or(0);
         let use_only_deposited_funds = use_only_deposited_funds.unwrap_or(false);
         let fail_silently_on_insufficient_funds =
@@ -984,35 +2424,83 @@ impl SDKClientCore {
             &self.trader,
             &market.base_mint,
             &market.quote_mint,
-            &OrderPacket::new_limit_order_default_with_client_order_id(
+            &OrderPacket::new_limit_order(
                 side,
                 tick_price,
                 size,
+                self.effective_self_trade_behavior(
+                    market_key,
+                    None,
+                    SelfTradeBehavior::CancelProvide,
+                ),
+                None,
                 client_order_id,
+                false,
             ),
         ))
     }
 
+    /// Like `get_limit_order_ix_from_tick_price`, but first checks the order against `book`, or
+    /// against `OrderManager::to_orderbook` when no fresh book is available. With
+    /// `SelfCrossPolicy::Reject`, a crossing order fails with an error that downcasts to
+    /// `SelfCross`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_limit_order_ix_from_tick_price_checked(
+        &self,
+        market_key: &Pubkey,
+        tick_price: u64,
+        side: Side,
+        size: u64,
+        client_order_id: u128,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        policy: SelfCrossPolicy,
+    ) -> Result<(Instruction, Option<SelfCross>)> {
+        let self_cross = self.apply_self_cross_policy(side, tick_price, book, policy)?;
+        let ix = self.get_limit_order_ix_from_tick_price(
+            market_key,
+            tick_price,
+            side,
+            size,
+            client_order_id,
+        )?;
+        Ok((ix, self_cross))
+    }
+
+    /// Like `get_ioc_from_tick_price_generic_ix` with the defaults, with the check of
+    /// `get_limit_order_ix_from_tick_price_checked`.
+    pub fn get_ioc_from_tick_price_ix_checked(
+        &self,
+        market_key: &Pubkey,
+        tick_price: u64,
+        side: Side,
+        size: u64,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        policy: SelfCrossPolicy,
+    ) -> Result<(Instruction, Option<SelfCross>)> {
+        let self_cross = self.apply_self_cross_policy(side, tick_price, book, policy)?;
+        let ix = self.get_ioc_from_tick_price_generic_ix(
+            market_key, tick_price, side, size, None, None, None,
+        )?;
+        Ok((ix, self_cross))
+    }
+
+    /// Cancels orders by id. Ids can be given as `FIFOOrderId`s or anything that converts into
+    /// one, e.g. a `CompactOrderId` parsed from `"{price_in_ticks}:{order_sequence_number}"`.
     pub fn get_cancel_ids_ix(
         &self,
         market_key: &Pubkey,
-        ids: Vec<FIFOOrderId>,
+        ids: impl IntoIterator<Item = impl Into<FIFOOrderId>>,
     ) -> Result<Instruction> {
         let market = self
             .markets
             .get(market_key)
             .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
         let mut cancel_orders = vec![];
-        for &FIFOOrderId {
-            price_in_ticks,
-            order_sequence_number,
-            ..
-        } in ids.iter()
-        {
+        for order_id in ids.into_iter().map(Into::into) {
             cancel_orders.push(CancelOrderParams {
-                side: Side::from_order_sequence_number(order_sequence_number),
-                price_in_ticks: price_in_ticks.as_u64(),
-                order_sequence_number,
+                side: order_id.side(),
+                price_in_ticks: order_id.price_in_ticks(),
+                order_sequence_number: order_id.order_sequence_number,
             });
         }
         let cancel_multiple_orders = CancelMultipleOrdersByIdParams {
@@ -1028,6 +2516,91 @@ impl SDKClientCore {
         ))
     }
 
+    /// Removes `lots_to_remove` base lots from a resting order, which keeps its place in the
+    /// queue. Removing at least the order's size cancels it.
+    pub fn get_reduce_order_ix(
+        &self,
+        market_key: &Pubkey,
+        order_id: impl Into<FIFOOrderId>,
+        lots_to_remove: u64,
+    ) -> Result<Instruction> {
+        let market = self
+            .markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+        let order_id = order_id.into();
+        let params = ReduceOrderParams {
+            base_params: CancelOrderParams {
+                side: order_id.side(),
+                price_in_ticks: order_id.price_in_ticks(),
+                order_sequence_number: order_id.order_sequence_number,
+            },
+            size: lots_to_remove,
+        };
+        Ok(create_reduce_order_instruction_with_custom_token_accounts(
+            market_key,
+            &self.trader,
+            &get_associated_token_address(&self.trader, &market.base_mint),
+            &get_associated_token_address(&self.trader, &market.quote_mint),
+            &market.base_mint,
+            &market.quote_mint,
+            &params,
+        ))
+    }
+
+    /// Gives a resting order a new size and price with the instructions of `AmendPath::choose`,
+    /// returning the path alongside them. A replacement keeps the order's client order id and is
+    /// post-only, so that the transaction fails instead of taking liquidity if the new price
+    /// crosses.
+    pub fn get_amend_order_ixs(
+        &self,
+        market_key: &Pubkey,
+        order: &OpenOrder,
+        new_size_lots: u64,
+        new_price_in_ticks: u64,
+    ) -> Result<(AmendPath, Vec<Instruction>)> {
+        let path = AmendPath::choose(order, new_size_lots, new_price_in_ticks);
+        let ixs = match path {
+            AmendPath::Unchanged => vec![],
+            AmendPath::Reduce { lots_to_remove } => {
+                vec![self.get_reduce_order_ix(market_key, order.order_id, lots_to_remove)?]
+            }
+            AmendPath::CancelReplace => {
+                self.get_move_order_ixs(market_key, order, new_price_in_ticks, new_size_lots)?
+            }
+        };
+        Ok((path, ixs))
+    }
+
+    /// Moves a resting order to a new price and size: cancels it by id, then places a post-only
+    /// order on the same side with the same client order id, so that tracking by client order
+    /// id carries over to the replacement.
+    ///
+    /// The two instructions are one transaction, so if the replacement fails, e.g. because it
+    /// would cross, the cancel is rolled back and the order stays. The reverse does not hold:
+    /// Phoenix skips ids it does not find on the book, so if the order filled before the
+    /// transaction landed, the cancel succeeds without removing anything and the replacement is
+    /// placed anyway. `SDKClient::move_order` checks the transaction's events for this.
+    pub fn get_move_order_ixs(
+        &self,
+        market_key: &Pubkey,
+        order: &OpenOrder,
+        new_price_in_ticks: u64,
+        new_size_lots: u64,
+    ) -> Result<Vec<Instruction>> {
+        Ok(vec![
+            self.get_cancel_ids_ix(market_key, [order.order_id])?,
+            self.get_post_only_ix_from_tick_price(
+                market_key,
+                new_price_in_ticks,
+                order.side,
+                new_size_lots,
+                order.client_order_id,
+                false,
+            )?,
+        ])
+    }
+
     pub fn get_cancel_up_to_ix(
         &self,
         market_key: &Pubkey,
@@ -1079,4 +2652,47 @@ impl SDKClientCore {
             &market.quote_mint,
         ))
     }
+
+    pub fn get_deposit_ix(
+        &self,
+        market_key: &Pubkey,
+        base_lots: u64,
+        quote_lots: u64,
+    ) -> Result<Instruction> {
+        let market = self
+            .markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+        Ok(create_deposit_funds_instruction(
+            market_key,
+            &self.trader,
+            &market.base_mint,
+            &market.quote_mint,
+            &DepositParams {
+                quote_lots_to_deposit: quote_lots,
+                base_lots_to_deposit: base_lots,
+            },
+        ))
+    }
+
+    /// Like `get_withdraw_ix`, but withdraws only the given amounts of free funds.
+    pub fn get_withdraw_lots_ix(
+        &self,
+        market_key: &Pubkey,
+        base_lots: u64,
+        quote_lots: u64,
+    ) -> Result<Instruction> {
+        let market = self
+            .markets
+            .get(market_key)
+            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+        Ok(create_withdraw_funds_with_custom_amounts_instruction(
+            market_key,
+            &self.trader,
+            &market.base_mint,
+            &market.quote_mint,
+            base_lots,
+            quote_lots,
+        ))
+    }
 }
@@ -1,11 +1,30 @@
 use std::collections::BTreeMap;
+use std::mem::size_of;
 
-use phoenix::{program::MarketSizeParams, state::Side};
+use borsh::BorshDeserialize;
+use phoenix::{
+    program::{
+        deposit::DepositParams, withdraw::WithdrawParams, MarketHeader, MarketSizeParams,
+        PhoenixInstruction, TokenParams,
+    },
+    quantities::{
+        BaseAtomsPerBaseLot, BaseLots, QuoteAtomsPerBaseUnitPerTick, QuoteAtomsPerQuoteLot,
+        QuoteLots, WrapperU64,
+    },
+    state::{markets::FIFOOrderId, trader_state::TraderState, Side},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use solana_sdk::pubkey::Pubkey;
 
 use crate::{
+    atoms::{AmountFormat, DisplayBase, DisplayPrice, DisplayQuote, Rounding},
     market_event::Fill,
-    sdk_client_core::{MarketMetadata, SDKClientCore},
+    order_manager::OpenOrder,
+    orderbook::Orderbook,
+    sdk_client_core::{
+        AmendPath, MarketMetadata, MarketMetadataError, MarketState, PhoenixOrder, SDKClientCore,
+        SelfCross, SelfCrossPolicy, MAX_MINT_DECIMALS,
+    },
 };
 
 fn setup(market: &Pubkey) -> SDKClientCore {
@@ -24,6 +43,7 @@ fn setup(market: &Pubkey) -> SDKClientCore {
         quote_mint: Pubkey::new_unique(),
         // Irrelevant for tests
         market_size_params: MarketSizeParams::default(),
+        taker_fee_bps: 2,
     };
     assert_eq!(
         meta.base_atoms_per_raw_base_unit * meta.raw_base_units_per_base_unit as u64
@@ -39,8 +59,9 @@ fn setup(market: &Pubkey) -> SDKClientCore {
     markets.insert(*market, meta);
 
     SDKClientCore {
-        markets,
+        markets: markets.into(),
         trader: Pubkey::new_unique(),
+        default_self_trade_behavior: Default::default(),
     }
 }
 
@@ -64,6 +85,7 @@ fn setup_with_raw_base_unit_multiplier(
         quote_mint: Pubkey::new_unique(),
         // Irrelevant for tests
         market_size_params: MarketSizeParams::default(),
+        taker_fee_bps: 2,
     };
     assert_eq!(
         meta.base_atoms_per_raw_base_unit * meta.raw_base_units_per_base_unit as u64
@@ -79,8 +101,9 @@ fn setup_with_raw_base_unit_multiplier(
     markets.insert(*market, meta);
 
     SDKClientCore {
-        markets,
+        markets: markets.into(),
         trader: Pubkey::new_unique(),
+        default_self_trade_behavior: Default::default(),
     }
 }
 
@@ -90,7 +113,7 @@ fn test_raw_base_units_to_base_lots_rounded_down() {
     let core = setup(&market);
     let raw_base_units = 1.0001_f64;
     let base_lots = core
-        .raw_base_units_to_base_lots_rounded_down(&market, raw_base_units)
+        .raw_base_units_to_base_lots_rounded_down_as_float(&market, raw_base_units)
         .unwrap();
     let meta = core.markets.get(&market).unwrap();
     assert_eq!(base_lots, meta.num_base_lots_per_base_unit);
@@ -102,12 +125,32 @@ fn test_raw_base_units_to_base_lots_rounded_up() {
     let core = setup(&market);
     let raw_base_units = 1.001_f64;
     let base_lots = core
-        .raw_base_units_to_base_lots_rounded_up(&market, raw_base_units)
+        .raw_base_units_to_base_lots_rounded_up_as_float(&market, raw_base_units)
         .unwrap();
     let meta = core.markets.get(&market).unwrap();
     assert_eq!(base_lots, meta.num_base_lots_per_base_unit + 1);
 }
 
+#[test]
+fn test_fee_for_notional() {
+    let market = Pubkey::new_unique();
+    let core = setup(&market);
+    // 2 bps of 1 quote unit is 200 quote atoms, a whole number of 10-atom quote lots
+    assert_eq!(
+        core.fee_for_notional(&market, 1_000_000, false).unwrap(),
+        200
+    );
+    // 2 bps of 1_000_050 atoms is 200.01, rounded up to the next quote lot
+    assert_eq!(
+        core.fee_for_notional(&market, 1_000_050, false).unwrap(),
+        210
+    );
+    assert_eq!(core.fee_for_notional(&market, 1_000_050, true).unwrap(), 0);
+    assert!(core
+        .fee_for_notional(&Pubkey::new_unique(), 1_000_000, false)
+        .is_err());
+}
+
 #[test]
 fn test_base_atoms_to_base_lots_rounded_down() {
     let market = Pubkey::new_unique();
@@ -147,6 +190,11 @@ fn test_base_lots_to_base_atoms() {
     let base_lots = 1;
     let base_atoms = core.base_lots_to_base_atoms(&market, base_lots).unwrap();
     assert_eq!(base_atoms, 10000000);
+
+    let meta = core.get_market_metadata(&market);
+    assert_eq!(meta.checked_base_lots_to_base_atoms(1), Some(10000000));
+    assert_eq!(meta.checked_base_lots_to_base_atoms(u64::MAX), None);
+    assert_eq!(meta.base_lots_to_base_atoms(u64::MAX), u64::MAX);
 }
 
 #[test]
@@ -155,7 +203,7 @@ fn test_quote_units_to_quote_lots() {
     let core = setup(&market);
     let quote_units = 1.0001_f64;
     let quote_lots = core
-        .quote_units_to_quote_lots(&market, quote_units)
+        .quote_units_to_quote_lots_as_float(&market, quote_units)
         .unwrap();
     assert_eq!(quote_lots, 100000 + 10);
 }
@@ -304,6 +352,151 @@ fn test_ticks_to_float_price() {
     assert_eq!(float_price, 10.907);
 }
 
+#[test]
+fn test_decimal_str_conversions() {
+    let market = Pubkey::new_unique();
+    let core = setup(&market);
+    assert_eq!(
+        core.decimal_str_to_base_lots(&market, "1.0001", Rounding::Down)
+            .unwrap(),
+        100
+    );
+    assert_eq!(
+        core.decimal_str_to_base_lots(&market, "1.0001", Rounding::Up)
+            .unwrap(),
+        101
+    );
+    assert!(core
+        .decimal_str_to_base_lots(&market, "1.0001", Rounding::Exact)
+        .is_err());
+    assert_eq!(
+        core.decimal_str_to_base_lots(&market, "1.50", Rounding::Exact)
+            .unwrap(),
+        150
+    );
+    assert_eq!(core.base_lots_to_decimal_str(&market, 150).unwrap(), "1.5");
+    // 10^20 raw base units is more base lots than fit in a u64
+    assert!(core
+        .decimal_str_to_base_lots(&market, "100000000000000000000", Rounding::Down)
+        .is_err());
+    assert!(core
+        .decimal_str_to_base_lots(&market, "-1", Rounding::Down)
+        .is_err());
+
+    assert_eq!(
+        core.decimal_str_to_quote_lots(&market, "1.23456", Rounding::Exact)
+            .unwrap(),
+        123456
+    );
+    assert_eq!(
+        core.decimal_str_to_quote_lots(&market, "0.000001", Rounding::Down)
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        core.decimal_str_to_quote_lots(&market, "0.000001", Rounding::Up)
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        core.quote_atoms_to_decimal_str(&market, 1_234_560).unwrap(),
+        "1.23456"
+    );
+
+    assert_eq!(
+        core.decimal_str_to_ticks(&market, "10.907", Rounding::Exact)
+            .unwrap(),
+        10907
+    );
+    assert_eq!(core.ticks_to_decimal_str(&market, 10907).unwrap(), "10.907");
+
+    // The multiplier shifts the decimal point of the price by its number of zeros
+    let core = setup_with_raw_base_unit_multiplier(&market, 100);
+    assert_eq!(
+        core.decimal_str_to_ticks(&market, "10.907", Rounding::Exact)
+            .unwrap(),
+        10907
+    );
+    assert_eq!(core.ticks_to_decimal_str(&market, 10907).unwrap(), "10.907");
+    let core = setup_with_raw_base_unit_multiplier(&market, 3);
+    assert!(core.ticks_to_decimal_str(&market, 10907).is_err());
+}
+
+#[test]
+fn test_format_amounts() {
+    let market = Pubkey::new_unique();
+    let core = setup(&market);
+    let meta = core.markets.get(&market).unwrap();
+    let two_digits = AmountFormat::default().with_max_fraction_digits(2);
+
+    assert_eq!(meta.format_base(0), "0");
+    assert_eq!(meta.format_base(1), "0.000000001");
+    assert_eq!(meta.format_base(u64::MAX), "18,446,744,073.709551615");
+    assert_eq!(
+        meta.format_base_with(
+            u64::MAX,
+            AmountFormat::default().with_thousands_separator(false)
+        ),
+        "18446744073.709551615"
+    );
+    // Truncated, not rounded, and without trailing zeros
+    assert_eq!(
+        meta.format_base_with(u64::MAX, two_digits),
+        "18,446,744,073.7"
+    );
+    assert_eq!(meta.format_base_with(1, two_digits), "0");
+
+    assert_eq!(meta.format_quote(0), "0");
+    assert_eq!(meta.format_quote(1), "0.000001");
+    assert_eq!(meta.format_quote(999), "0.000999");
+    assert_eq!(meta.format_quote(1_234_560_000), "1,234.56");
+    assert_eq!(meta.format_quote(u64::MAX), "18,446,744,073,709.551615");
+    assert_eq!(
+        meta.format_quote_with(
+            u64::MAX,
+            AmountFormat::default().with_max_fraction_digits(3)
+        ),
+        "18,446,744,073,709.551"
+    );
+    assert_eq!(
+        meta.format_quote_with(
+            123_456_789,
+            AmountFormat::default().with_max_fraction_digits(0)
+        ),
+        "123"
+    );
+
+    assert_eq!(meta.format_price(0), "0");
+    assert_eq!(meta.format_price(1), "0.001");
+    assert_eq!(meta.format_price(10907), "10.907");
+    assert_eq!(meta.format_price(u64::MAX), "18,446,744,073,709,551.615");
+
+    assert_eq!(
+        format!("{} USDC", DisplayQuote(1_234_560_000, &meta)),
+        "1,234.56 USDC"
+    );
+    assert_eq!(
+        format!("{:.1}", DisplayQuote(1_234_560_000, &meta)),
+        "1,234.5"
+    );
+    assert_eq!(format!("{:.2}", DisplayBase(1, &meta)), "0");
+    assert_eq!(format!("{}", DisplayBase(1_500_000_000, &meta)), "1.5");
+    assert_eq!(format!("{}", DisplayPrice(10907, &meta)), "10.907");
+
+    // Prices stay exact with a power of 10 multiplier, and do not fail without one
+    let core = setup_with_raw_base_unit_multiplier(&market, 100);
+    assert_eq!(
+        core.markets.get(&market).unwrap().format_price(10907),
+        "10.907"
+    );
+    assert_eq!(core.markets.get(&market).unwrap().format_price(1), "0.001");
+    let core = setup_with_raw_base_unit_multiplier(&market, 3);
+    assert_eq!(
+        core.markets.get(&market).unwrap().format_price(10907),
+        "10.907"
+    );
+}
+
 #[test]
 fn test_fill_event_to_quote_atoms() {
     let market = Pubkey::new_unique();
@@ -316,6 +509,8 @@ fn test_fill_event_to_quote_atoms() {
         taker: Pubkey::new_unique(),
         order_sequence_number: 12345,
         side_filled: Side::Ask,
+        maker_side: Side::Ask,
+        taker_side: Side::Ask.opposite(),
         is_full_fill: true,
     };
     let quote_atoms = core
@@ -360,4 +555,749 @@ fn test_base_lots_and_price_to_quote_atoms() {
         quote_atoms,
         base_lots * price_in_ticks * meta.quote_atoms_per_quote_lot // tick_size_in_quote_lots_per_base_unit == base_lots_per_base_unit
     );
+
+    assert_eq!(
+        meta.checked_base_lots_and_price_to_quote_atoms(base_lots, price_in_ticks),
+        Some(quote_atoms)
+    );
+    assert_eq!(
+        meta.checked_base_lots_and_price_to_quote_atoms(u64::MAX, u64::MAX),
+        None
+    );
+    assert_eq!(
+        meta.base_lots_and_price_to_quote_atoms(u64::MAX, u64::MAX),
+        u64::MAX
+    );
+}
+
+#[test]
+fn test_raw_base_units_and_base_units() {
+    // Like BONK/USDC, where a Phoenix base unit is 1000 tokens and a base lot is 100 tokens
+    let market = Pubkey::new_unique();
+    let meta = MarketMetadata {
+        base_atoms_per_raw_base_unit: 1e5 as u64,
+        quote_atoms_per_quote_unit: 1e6 as u64,
+        base_atoms_per_base_lot: 1e7 as u64,
+        num_base_lots_per_base_unit: 10,
+        tick_size_in_quote_atoms_per_base_unit: 10,
+        quote_atoms_per_quote_lot: 1,
+        raw_base_units_per_base_unit: 1000,
+        base_decimals: 5,
+        quote_decimals: 6,
+        ..Default::default()
+    };
+    let core = SDKClientCore {
+        markets: [(market, meta)].into_iter().collect(),
+        trader: Pubkey::new_unique(),
+        default_self_trade_behavior: Default::default(),
+    };
+
+    assert_eq!(core.base_units_to_base_lots(&market, 1).unwrap(), 10);
+    assert_eq!(core.raw_base_units_to_base_lots(&market, 1000).unwrap(), 10);
+    assert_eq!(core.raw_base_units_to_base_lots(&market, 150).unwrap(), 1);
+    assert_eq!(core.raw_base_units_to_base_lots(&market, 99).unwrap(), 0);
+    assert_eq!(
+        core.raw_base_units_to_base_lots_rounded_down_as_float(&market, 1000.0)
+            .unwrap(),
+        10
+    );
+
+    // 0.00002 USDC per token is 0.02 USDC per base unit, or 2000 ticks
+    let price_in_ticks = core
+        .float_price_to_ticks_rounded_down(&market, 0.00002)
+        .unwrap();
+    assert_eq!(price_in_ticks, 2000);
+    assert!((core.ticks_to_float_price(&market, 2000).unwrap() - 0.00002).abs() < 1e-12);
+    let base_lots = core
+        .raw_base_units_to_base_lots(&market, 1_000_000)
+        .unwrap();
+    assert_eq!(
+        base_lots,
+        core.base_units_to_base_lots(&market, 1000).unwrap()
+    );
+    assert_eq!(
+        core.base_lots_and_price_to_quote_atoms(&market, base_lots, price_in_ticks)
+            .unwrap(),
+        20 * 1_000_000
+    );
+}
+
+#[test]
+fn test_get_ioc_full_ix() {
+    use phoenix::{
+        quantities::{BaseLots, QuoteLots, Ticks, WrapperU64},
+        state::{decode_order_packet, OrderPacket, SelfTradeBehavior},
+    };
+
+    let market = Pubkey::new_unique();
+    let core = setup(&market);
+    let ix = core
+        .get_ioc_full_ix(
+            &market,
+            Side::Ask,
+            Some(1000),
+            50,
+            0,
+            0,
+            4000,
+            SelfTradeBehavior::DecrementTake,
+            Some(3),
+            7,
+            true,
+        )
+        .unwrap();
+    let packet = decode_order_packet(&ix.data[1..]).unwrap();
+    assert_eq!(
+        packet,
+        OrderPacket::ImmediateOrCancel {
+            side: Side::Ask,
+            price_in_ticks: Some(Ticks::new(1000)),
+            num_base_lots: BaseLots::new(50),
+            num_quote_lots: QuoteLots::new(0),
+            min_base_lots_to_fill: BaseLots::new(0),
+            min_quote_lots_to_fill: QuoteLots::new(4000),
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            match_limit: Some(3),
+            client_order_id: 7,
+            use_only_deposited_funds: true,
+            last_valid_slot: None,
+            last_valid_unix_timestamp_in_seconds: None,
+        }
+    );
+
+    let ioc = |side, num_base_lots, num_quote_lots, min_base_lots_out, min_quote_lots_out| {
+        core.get_ioc_full_ix(
+            &market,
+            side,
+            None,
+            num_base_lots,
+            num_quote_lots,
+            min_base_lots_out,
+            min_quote_lots_out,
+            SelfTradeBehavior::CancelProvide,
+            None,
+            0,
+            false,
+        )
+    };
+    // Buy with a quote budget, requiring a minimum amount of base
+    assert!(ioc(Side::Bid, 0, 1000, 10, 0).is_ok());
+    // No size
+    assert!(ioc(Side::Bid, 0, 0, 0, 0).is_err());
+    // Min-out on the wrong side
+    assert!(ioc(Side::Bid, 10, 0, 0, 100).is_err());
+    assert!(ioc(Side::Ask, 10, 0, 5, 0).is_err());
+    // Min-out larger than the order
+    assert!(ioc(Side::Bid, 10, 0, 11, 0).is_err());
+}
+
+#[test]
+fn test_required_deposits_for_quotes() {
+    // One tick is 100 quote lots per base unit, so bid notionals fall between quote lots
+    let meta = MarketMetadata {
+        base_atoms_per_base_lot: 1000,
+        num_base_lots_per_base_unit: 1000,
+        tick_size_in_quote_atoms_per_base_unit: 1000,
+        quote_atoms_per_quote_lot: 10,
+        ..Default::default()
+    };
+    let bids = [(101, 7), (99, 3)];
+    let asks = [(105, 5), (110, 2)];
+    // 70.7 and 29.7 quote lots are each rounded up
+    assert_eq!(
+        meta.required_deposits_for_quotes(&bids, &asks),
+        (7000, 1010)
+    );
+    assert_eq!(meta.required_deposits_for_quotes(&[], &[]), (0, 0));
+
+    let market = Pubkey::new_unique();
+    let core = setup(&market);
+    assert_eq!(
+        core.required_deposits_for_quotes(&market, &[(150, 3)], &[(160, 4)])
+            .unwrap(),
+        (4 * 10000000, 450 * 10)
+    );
+    assert!(core
+        .required_deposits_for_quotes(&Pubkey::new_unique(), &[], &[])
+        .is_err());
+}
+
+#[test]
+fn test_top_up_deposits_ixs() {
+    let market = Pubkey::new_unique();
+    let core = setup(&market);
+    // Requires 4 base lots and 450 quote lots
+    let (bids, asks) = ([(150, 3)], [(160, 4)]);
+    let mut trader_state = TraderState::default();
+    trader_state.quote_lots_locked = QuoteLots::new(200);
+    trader_state.quote_lots_free = QuoteLots::new(100);
+    trader_state.base_lots_free = BaseLots::new(10);
+
+    let ixs = core
+        .get_top_up_deposits_ixs(&market, &trader_state, &bids, &asks, None)
+        .unwrap();
+    assert_eq!(ixs.len(), 1);
+    assert_eq!(ixs[0].data[0], PhoenixInstruction::DepositFunds as u8);
+    let deposit = DepositParams::try_from_slice(&ixs[0].data[1..]).unwrap();
+    assert_eq!(deposit.quote_lots_to_deposit, 150);
+    assert_eq!(deposit.base_lots_to_deposit, 0);
+
+    // 10 base lots is more than 4 plus a 50% buffer
+    let ixs = core
+        .get_top_up_deposits_ixs(&market, &trader_state, &bids, &asks, Some(5000))
+        .unwrap();
+    assert_eq!(ixs.len(), 2);
+    assert_eq!(ixs[1].data[0], PhoenixInstruction::WithdrawFunds as u8);
+    let withdraw = WithdrawParams::try_from_slice(&ixs[1].data[1..]).unwrap();
+    assert_eq!(withdraw.base_lots_to_withdraw, Some(4));
+    assert_eq!(withdraw.quote_lots_to_withdraw, Some(0));
+
+    // Fully funded
+    trader_state.quote_lots_free = QuoteLots::new(250);
+    trader_state.base_lots_free = BaseLots::new(4);
+    assert!(core
+        .get_top_up_deposits_ixs(&market, &trader_state, &bids, &asks, Some(0))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_self_cross_policy() {
+    let market = Pubkey::new_unique();
+    let core = setup(&market);
+    let mut book = Orderbook::<FIFOOrderId, PhoenixOrder>::default();
+    let own_ask = FIFOOrderId::new_from_untyped(1000, 7);
+    book.asks.insert(
+        own_ask,
+        PhoenixOrder {
+            num_base_lots: 5,
+            maker_id: core.trader,
+        },
+    );
+
+    let (_, warning) = core
+        .get_limit_order_ix_from_tick_price_checked(
+            &market,
+            999,
+            Side::Bid,
+            10,
+            0,
+            &book,
+            SelfCrossPolicy::Reject,
+        )
+        .unwrap();
+    assert_eq!(warning, None);
+
+    let (_, warning) = core
+        .get_ioc_from_tick_price_ix_checked(
+            &market,
+            1000,
+            Side::Bid,
+            10,
+            &book,
+            SelfCrossPolicy::Warn,
+        )
+        .unwrap();
+    assert_eq!(warning.unwrap().order_ids, vec![own_ask]);
+
+    let error = core
+        .get_limit_order_ix_from_tick_price_checked(
+            &market,
+            1000,
+            Side::Bid,
+            10,
+            0,
+            &book,
+            SelfCrossPolicy::Reject,
+        )
+        .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<SelfCross>(),
+        Some(&SelfCross {
+            side: Side::Bid,
+            price_in_ticks: 1000,
+            order_ids: vec![own_ask],
+        })
+    );
+}
+
+#[test]
+fn test_fok_size_too_small() {
+    use crate::sdk_client_core::SizeTooSmall;
+
+    let market = Pubkey::new_unique();
+    let core = setup(&market);
+    let (min_base_atoms, min_quote_atoms) = core.min_order_sizes(&market).unwrap();
+    assert_eq!((min_base_atoms, min_quote_atoms), (10000000, 10));
+
+    let fok =
+        |side, size| core.get_fok_generic_ix(&market, 1000, side, size, None, None, None, None);
+    let too_small = |side, size| {
+        fok(side, size)
+            .unwrap_err()
+            .downcast::<SizeTooSmall>()
+            .unwrap()
+    };
+    // Bids are sized in quote atoms, asks in base atoms
+    assert_eq!(
+        too_small(Side::Bid, min_quote_atoms - 1),
+        SizeTooSmall {
+            min_size: min_quote_atoms
+        }
+    );
+    assert!(fok(Side::Bid, min_quote_atoms).is_ok());
+    assert_eq!(
+        too_small(Side::Ask, min_base_atoms - 1),
+        SizeTooSmall {
+            min_size: min_base_atoms
+        }
+    );
+    assert!(fok(Side::Ask, min_base_atoms).is_ok());
+    assert!(core.min_order_sizes(&Pubkey::new_unique()).is_err());
+}
+
+#[test]
+fn test_normalize_order_size() {
+    use crate::sdk_client_core::{BelowMinNotional, NormalizedSize, SizeRounding, SizeTooSmall};
+
+    let market = Pubkey::new_unique();
+    let core = setup(&market);
+    // One base lot at 1000 ticks is worth 10_000 quote atoms, so the minimum is 5 lots
+    let normalize = |side, desired_base_units, rounding| {
+        core.normalize_order_size(&market, side, 1000, desired_base_units, 50_000, rounding)
+    };
+    let below_minimum = |result: anyhow::Result<NormalizedSize>| {
+        result.unwrap_err().downcast::<BelowMinNotional>().unwrap()
+    };
+
+    // Rounding down lands exactly on the minimum
+    let size = normalize(Side::Bid, 0.0599, SizeRounding::Down).unwrap();
+    assert_eq!(size.base_lots, 5);
+    assert!((size.base_units - 0.05).abs() < 1e-12);
+    assert_eq!(size.notional_quote_atoms, 50_000);
+    assert_eq!(
+        normalize(Side::Ask, 0.05, SizeRounding::Down).unwrap(),
+        size
+    );
+
+    // One lot less is rejected, with the smallest valid size
+    assert_eq!(
+        below_minimum(normalize(Side::Ask, 0.0499, SizeRounding::Down)),
+        BelowMinNotional {
+            base_lots: 4,
+            notional_quote_atoms: 40_000,
+            min_notional_quote_atoms: 50_000,
+            min_base_lots: Some(5),
+        }
+    );
+    assert_eq!(
+        normalize(Side::Ask, 0.0401, SizeRounding::Up)
+            .unwrap()
+            .base_lots,
+        5
+    );
+    assert_eq!(
+        normalize(Side::Bid, 0.001, SizeRounding::UpToMinimum)
+            .unwrap()
+            .base_lots,
+        5
+    );
+    assert_eq!(
+        normalize(Side::Bid, 0.08, SizeRounding::UpToMinimum)
+            .unwrap()
+            .base_lots,
+        8
+    );
+
+    // Under one lot, and sizes no price makes valid
+    let too_small = core
+        .normalize_order_size(&market, Side::Bid, 1000, 0.009, 0, SizeRounding::Down)
+        .unwrap_err();
+    assert_eq!(
+        too_small.downcast_ref::<SizeTooSmall>(),
+        Some(&SizeTooSmall {
+            min_size: 10_000_000
+        })
+    );
+    let free = core.normalize_order_size(&market, Side::Bid, 0, 1.0, 1, SizeRounding::UpToMinimum);
+    assert_eq!(below_minimum(free).min_base_lots, None);
+    assert!(normalize(Side::Bid, f64::NAN, SizeRounding::Down).is_err());
+    assert!(normalize(Side::Bid, -1.0, SizeRounding::Down).is_err());
+    assert!(core
+        .normalize_order_size(
+            &Pubkey::new_unique(),
+            Side::Bid,
+            1000,
+            1.0,
+            0,
+            SizeRounding::Down
+        )
+        .is_err());
+}
+
+#[test]
+fn test_min_base_lots_for_notional() {
+    // Notionals that round to a quote lot: bids up, asks down
+    let meta = MarketMetadata {
+        base_atoms_per_raw_base_unit: 7,
+        quote_atoms_per_quote_unit: 1,
+        quote_atoms_per_quote_lot: 1,
+        base_atoms_per_base_lot: 1,
+        tick_size_in_quote_atoms_per_base_unit: 3,
+        num_base_lots_per_base_unit: 7,
+        raw_base_units_per_base_unit: 1,
+        ..Default::default()
+    };
+    for side in [Side::Bid, Side::Ask] {
+        for price_in_ticks in 1..20 {
+            for min_notional in 0..100 {
+                let expected = (1..1000).find(|&base_lots| {
+                    meta.order_notional_quote_atoms(side, price_in_ticks, base_lots) >= min_notional
+                });
+                assert_eq!(
+                    meta.min_base_lots_for_notional(side, price_in_ticks, min_notional),
+                    expected,
+                    "{:?} at {} ticks for {}",
+                    side,
+                    price_in_ticks,
+                    min_notional
+                );
+            }
+        }
+    }
+    assert_eq!(meta.min_base_lots_for_notional(Side::Bid, 0, 1), None);
+}
+
+#[test]
+fn test_default_self_trade_behavior() {
+    use phoenix::state::{decode_order_packet, SelfTradeBehavior};
+
+    let market = Pubkey::new_unique();
+    let mut core = setup(&market);
+    let builders = |core: &SDKClientCore, explicit: Option<SelfTradeBehavior>| {
+        let ixs = [
+            core.get_ioc_generic_ix(
+                &market,
+                1000,
+                Side::Bid,
+                10,
+                explicit,
+                None,
+                None,
+                None,
+                None,
+                None,
+            ),
+            core.get_fok_generic_ix(
+                &market,
+                1000,
+                Side::Ask,
+                100_000_000,
+                explicit,
+                None,
+                None,
+                None,
+            ),
+            core.get_limit_order_generic_ix(
+                &market,
+                1000,
+                Side::Bid,
+                10,
+                explicit,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            ),
+        ];
+        ixs.into_iter()
+            .map(|ix| {
+                decode_order_packet(&ix.unwrap().data[1..])
+                    .unwrap()
+                    .self_trade_behavior()
+            })
+            .collect::<Vec<_>>()
+    };
+    // Builders without a self-trade argument always use the configured default
+    let implicit = |core: &SDKClientCore| {
+        let ixs = [
+            core.get_ioc_from_tick_price_generic_ix(&market, 1, Side::Bid, 10, None, None, None),
+            core.get_ioc_with_slippage_ix(&market, 100, 1, Side::Bid),
+            core.get_limit_order_ix_from_tick_price(&market, 1, Side::Bid, 10, 0),
+        ];
+        ixs.into_iter()
+            .map(|ix| {
+                decode_order_packet(&ix.unwrap().data[1..])
+                    .unwrap()
+                    .self_trade_behavior()
+            })
+            .collect::<Vec<_>>()
+    };
+    use SelfTradeBehavior::*;
+
+    // Unconfigured, each order type keeps its own default
+    assert_eq!(
+        builders(&core, None),
+        vec![CancelProvide, CancelProvide, DecrementTake]
+    );
+    assert_eq!(implicit(&core), vec![CancelProvide; 3]);
+
+    core.default_self_trade_behavior.global = Some(Abort);
+    assert_eq!(builders(&core, None), vec![Abort; 3]);
+    assert_eq!(implicit(&core), vec![Abort; 3]);
+
+    // A market override wins over the global default, and only for that market
+    core.default_self_trade_behavior
+        .per_market
+        .insert(market, DecrementTake);
+    assert_eq!(builders(&core, None), vec![DecrementTake; 3]);
+    assert_eq!(implicit(&core), vec![DecrementTake; 3]);
+    assert_eq!(
+        core.effective_self_trade_behavior(&Pubkey::new_unique(), None, CancelProvide),
+        Abort
+    );
+
+    // Explicit arguments win over both
+    assert_eq!(builders(&core, Some(CancelProvide)), vec![CancelProvide; 3]);
+    assert!(format!("{:?}", core).contains("per_market"));
+}
+
+#[test]
+fn test_amend_order_ixs() {
+    use phoenix::{
+        program::{cancel_multiple_orders::CancelMultipleOrdersByIdParams, ReduceOrderParams},
+        state::{decode_order_packet, OrderPacket},
+    };
+
+    let market = Pubkey::new_unique();
+    let core = setup(&market);
+    let order = OpenOrder {
+        order_id: FIFOOrderId::new_from_untyped(1000, !5),
+        side: Side::Bid,
+        client_order_id: 42,
+        num_base_lots: 10,
+    };
+    let amend = |new_size_lots, new_price_in_ticks| {
+        core.get_amend_order_ixs(&market, &order, new_size_lots, new_price_in_ticks)
+            .unwrap()
+    };
+
+    // Shrinking at the same price reduces in place
+    let (path, ixs) = amend(6, 1000);
+    assert_eq!(path, AmendPath::Reduce { lots_to_remove: 4 });
+    assert_eq!(ixs.len(), 1);
+    assert_eq!(ixs[0].data[0], PhoenixInstruction::ReduceOrder as u8);
+    let reduce = ReduceOrderParams::try_from_slice(&ixs[0].data[1..]).unwrap();
+    assert_eq!(reduce.size, 4);
+    assert_eq!(reduce.base_params.order_sequence_number, !5);
+    assert_eq!(reduce.base_params.price_in_ticks, 1000);
+    assert_eq!(amend(0, 999).0, AmendPath::Reduce { lots_to_remove: 10 });
+    let (path, ixs) = amend(10, 1000);
+    assert_eq!(path, AmendPath::Unchanged);
+    assert!(ixs.is_empty());
+
+    // Growing or moving the order must replace it
+    for (new_size_lots, new_price_in_ticks) in [(11, 1000), (6, 1001), (11, 999)] {
+        let (path, ixs) = amend(new_size_lots, new_price_in_ticks);
+        assert_eq!(path, AmendPath::CancelReplace);
+        assert_eq!(
+            ixs[0].data[0],
+            PhoenixInstruction::CancelMultipleOrdersById as u8
+        );
+        let cancel = CancelMultipleOrdersByIdParams::try_from_slice(&ixs[0].data[1..]).unwrap();
+        assert_eq!(cancel.orders[0].order_sequence_number, !5);
+        let packet = decode_order_packet(&ixs[1].data[1..]).unwrap();
+        assert!(matches!(packet, OrderPacket::PostOnly { .. }));
+        assert_eq!(packet.side(), Side::Bid);
+        assert_eq!(packet.get_price_in_ticks().as_u64(), new_price_in_ticks);
+        assert_eq!(packet.num_base_lots().as_u64(), new_size_lots);
+        assert_eq!(packet.client_order_id(), 42);
+    }
+}
+
+fn market_header(
+    base_decimals: u32,
+    quote_decimals: u32,
+    base_lot_size: u64,
+    quote_lot_size: u64,
+    tick_size: u64,
+    raw_base_units_per_base_unit: u32,
+) -> MarketHeader {
+    let token_params = |decimals| TokenParams {
+        decimals,
+        vault_bump: 0,
+        mint_key: Pubkey::new_unique(),
+        vault_key: Pubkey::new_unique(),
+    };
+    MarketHeader::new(
+        MarketSizeParams {
+            bids_size: 512,
+            asks_size: 512,
+            num_seats: 128,
+        },
+        token_params(base_decimals),
+        BaseAtomsPerBaseLot::new(base_lot_size),
+        token_params(quote_decimals),
+        QuoteAtomsPerQuoteLot::new(quote_lot_size),
+        QuoteAtomsPerBaseUnitPerTick::new(tick_size),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        raw_base_units_per_base_unit,
+    )
+}
+
+fn metadata_error(header: &MarketHeader) -> MarketMetadataError {
+    *MarketMetadata::from_header(header)
+        .unwrap_err()
+        .downcast_ref::<MarketMetadataError>()
+        .unwrap()
+}
+
+#[test]
+fn test_metadata_validation() {
+    let meta = MarketMetadata::from_header(&market_header(9, 6, 1_000_000, 10, 1000, 1)).unwrap();
+    assert_eq!(meta.base_atoms_per_raw_base_unit, 1_000_000_000);
+    assert_eq!(meta.num_base_lots_per_base_unit, 1000);
+
+    // 18 decimals is the most accepted
+    let meta = MarketMetadata::from_header(&market_header(18, 18, 1, 1, 1, 1)).unwrap();
+    assert_eq!(meta.quote_atoms_per_quote_unit, 10u64.pow(18));
+    for decimals in [19, 20, 64, u32::MAX] {
+        let header = market_header(decimals, 6, 1, 1, 1, 1);
+        assert_eq!(
+            metadata_error(&header),
+            MarketMetadataError::DecimalsOutOfRange {
+                mint: header.base_params.mint_key,
+                decimals,
+            }
+        );
+        assert!(matches!(
+            metadata_error(&market_header(9, decimals, 1, 1, 1, 1)),
+            MarketMetadataError::DecimalsOutOfRange { .. }
+        ));
+    }
+
+    assert_eq!(
+        metadata_error(&market_header(9, 6, 0, 10, 1000, 1)),
+        MarketMetadataError::InvalidBaseLotSize
+    );
+    assert_eq!(
+        metadata_error(&market_header(9, 6, 3, 10, 1000, 1)),
+        MarketMetadataError::InvalidBaseLotSize
+    );
+    assert_eq!(
+        metadata_error(&market_header(9, 6, 1000, 0, 1000, 1)),
+        MarketMetadataError::InvalidQuoteLotSize
+    );
+    assert_eq!(
+        metadata_error(&market_header(9, 6, 1000, 10, 0, 1)),
+        MarketMetadataError::InvalidTickSize
+    );
+    assert_eq!(
+        metadata_error(&market_header(18, 6, 1, 10, 1000, u32::MAX)),
+        MarketMetadataError::Overflow
+    );
+}
+
+#[test]
+fn test_metadata_from_adversarial_account_data() {
+    let mut rng = StdRng::seed_from_u64(384);
+    let interesting_u32 = [0, 1, 6, 9, 18, 19, 20, 39, u32::MAX / 2, u32::MAX];
+    let interesting_u64 = [0, 1, 3, 10, 1000, 1 << 32, u64::MAX / 2, u64::MAX];
+    let pick_u32 = |rng: &mut StdRng| {
+        if rng.gen_bool(0.5) {
+            interesting_u32[rng.gen_range(0, interesting_u32.len())]
+        } else {
+            rng.gen()
+        }
+    };
+    let pick_u64 = |rng: &mut StdRng| {
+        if rng.gen_bool(0.5) {
+            interesting_u64[rng.gen_range(0, interesting_u64.len())]
+        } else {
+            rng.gen()
+        }
+    };
+
+    for i in 0..5000 {
+        let mut data = if i % 2 == 0 {
+            let header = market_header(
+                pick_u32(&mut rng),
+                pick_u32(&mut rng),
+                pick_u64(&mut rng),
+                pick_u64(&mut rng),
+                pick_u64(&mut rng),
+                pick_u32(&mut rng),
+            );
+            bytemuck::bytes_of(&header).to_vec()
+        } else {
+            (0..size_of::<MarketHeader>()).map(|_| rng.gen()).collect()
+        };
+        // Sometimes a truncated account, sometimes a market body that is too short to load
+        let len = rng.gen_range(0, data.len() + 64);
+        data.resize(len, 0);
+
+        // Errors are fine, panics are not
+        if let Ok(meta) = MarketMetadata::from_account_data(&data) {
+            panic!("Loaded a market from a header alone: {:?}", meta);
+        }
+        assert!(MarketState::from_account_data(&data).is_err());
+        if data.len() >= size_of::<MarketHeader>() {
+            let header: MarketHeader =
+                bytemuck::pod_read_unaligned(&data[..size_of::<MarketHeader>()]);
+            if let Ok(meta) = MarketMetadata::from_header(&header) {
+                assert!(meta.base_decimals <= MAX_MINT_DECIMALS);
+                assert!(meta.quote_decimals <= MAX_MINT_DECIMALS);
+                assert!(meta.num_base_lots_per_base_unit > 0);
+                assert!(meta.quote_atoms_per_quote_lot > 0);
+                assert!(meta.tick_size_in_quote_atoms_per_base_unit > 0);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ioc_from_tick_price_generic_ix() {
+    use phoenix::state::{decode_order_packet, SelfTradeBehavior};
+
+    let market = Pubkey::new_unique();
+    let core = setup(&market);
+    let packet = |ix: anyhow::Result<solana_sdk::instruction::Instruction>| {
+        decode_order_packet(&ix.unwrap().data[1..]).unwrap()
+    };
+
+    let explicit = packet(core.get_ioc_from_tick_price_generic_ix(
+        &market,
+        100,
+        Side::Bid,
+        10,
+        Some(SelfTradeBehavior::Abort),
+        Some(3),
+        Some(42),
+    ));
+    assert_eq!(explicit.self_trade_behavior(), SelfTradeBehavior::Abort);
+    assert_eq!(explicit.match_limit(), 3);
+    assert_eq!(explicit.client_order_id(), 42);
+
+    // Without arguments, no client order id is made up and the shim builds the same order
+    let defaults = packet(core.get_ioc_from_tick_price_generic_ix(
+        &market,
+        100,
+        Side::Bid,
+        10,
+        None,
+        None,
+        None,
+    ));
+    assert_eq!(defaults.client_order_id(), 0);
+    assert_eq!(
+        defaults.self_trade_behavior(),
+        SelfTradeBehavior::CancelProvide
+    );
+    #[allow(deprecated)]
+    let shim = core.get_ioc_from_tick_price_ix(&market, 100, Side::Bid, 10);
+    assert_eq!(packet(shim), defaults);
 }
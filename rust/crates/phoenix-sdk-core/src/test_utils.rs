@@ -0,0 +1,57 @@
+//! Helpers shared by the tests of this crate and of `phoenix-sdk`, which enables the `test-utils`
+//! feature for its own tests.
+
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::market_event::{MarketEventDetails, PhoenixEvent};
+use crate::sdk_client_core::MarketMetadata;
+
+/// An event on `market` with every other field zeroed. Tests that care about the slot, sequence
+/// number or signature set them with struct update syntax.
+pub fn event(market: Pubkey, details: MarketEventDetails) -> PhoenixEvent {
+    PhoenixEvent {
+        market,
+        sequence_number: 0,
+        slot: 0,
+        timestamp: 0,
+        signature: Signature::default(),
+        signer: Pubkey::default(),
+        event_index: 0,
+        details,
+    }
+}
+
+/// SOL/USDC with 9 and 6 decimals: 1000 lots per SOL and ticks of 0.001 USDC.
+pub fn sol_usdc_metadata() -> MarketMetadata {
+    MarketMetadata {
+        base_mint: Pubkey::new_unique(),
+        quote_mint: Pubkey::new_unique(),
+        base_decimals: 9,
+        quote_decimals: 6,
+        base_atoms_per_raw_base_unit: 1_000_000_000,
+        quote_atoms_per_quote_unit: 1_000_000,
+        quote_atoms_per_quote_lot: 1,
+        base_atoms_per_base_lot: 1_000_000,
+        tick_size_in_quote_atoms_per_base_unit: 1000,
+        num_base_lots_per_base_unit: 1000,
+        raw_base_units_per_base_unit: 1,
+        ..Default::default()
+    }
+}
+
+/// 1000 base lots and 1000 ticks per base unit, one quote atom per quote lot.
+pub fn test_market_metadata() -> MarketMetadata {
+    MarketMetadata {
+        base_mint: Pubkey::new_unique(),
+        quote_mint: Pubkey::new_unique(),
+        base_atoms_per_raw_base_unit: 1_000_000,
+        quote_atoms_per_quote_unit: 1_000_000,
+        quote_atoms_per_quote_lot: 1,
+        base_atoms_per_base_lot: 1000,
+        tick_size_in_quote_atoms_per_base_unit: 1000,
+        num_base_lots_per_base_unit: 1000,
+        raw_base_units_per_base_unit: 1,
+        ..Default::default()
+    }
+}
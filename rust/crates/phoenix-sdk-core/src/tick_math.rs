@@ -0,0 +1,169 @@
+//! Side-aware price arithmetic in ticks. Moving "towards the spread" is up for a bid and down for
+//! an ask, and prices must stay at or above one tick; these helpers do both so that callers don't
+//! write `best_ask - 1` and underflow at the bottom of the book.
+
+use phoenix::state::enums::Side;
+
+const BPS_DIVISOR: u128 = 10_000;
+
+/// `price_ticks` moved `n` ticks more aggressive: up for a bid, down for an ask. An ask never
+/// goes below one tick, and a bid saturates at `u64::MAX`.
+pub fn improve(side: Side, price_ticks: u64, n: u64) -> u64 {
+    match side {
+        Side::Bid => price_ticks.saturating_add(n),
+        Side::Ask => price_ticks.saturating_sub(n).max(1),
+    }
+}
+
+/// The price `n` ticks inside the opposite side's best, i.e. the most aggressive price on `side`
+/// that rests `n - 1` ticks clear of crossing: `best_opposite - n` for a bid, `best_opposite + n`
+/// for an ask. `None` for a bid that would have to rest at 0 ticks or below.
+pub fn n_ticks_inside(best_opposite: u64, side: Side, n: u64) -> Option<u64> {
+    match side {
+        Side::Bid => best_opposite.checked_sub(n).filter(|price| *price > 0),
+        Side::Ask => Some(best_opposite.saturating_add(n)),
+    }
+}
+
+/// Whether an order on `side` at `price_ticks` would trade against a resting best of
+/// `best_opposite`. Locking the book, at an equal price, counts as crossing.
+pub fn would_cross(side: Side, price_ticks: u64, best_opposite: u64) -> bool {
+    match side {
+        Side::Bid => price_ticks >= best_opposite,
+        Side::Ask => price_ticks <= best_opposite,
+    }
+}
+
+/// `price_ticks` clamped to within `max_deviation_bps` of `reference_ticks`.
+///
+/// `tick_size` is the increment, in ticks, that prices are quoted on (1 for every tick). The band
+/// edges are rounded inwards to it, so a clamped price is on that grid and never further from the
+/// reference than allowed. Prices already inside the band are returned unchanged, and the lower
+/// edge is at least one increment. If the band is too narrow to hold a multiple of `tick_size`,
+/// `reference_ticks` is returned.
+pub fn clamp_to_band(
+    price_ticks: u64,
+    reference_ticks: u64,
+    max_deviation_bps: u64,
+    tick_size: u64,
+) -> u64 {
+    let tick_size = tick_size.max(1) as u128;
+    let reference = reference_ticks as u128;
+    let deviation = max_deviation_bps as u128;
+    let lower = (reference * BPS_DIVISOR.saturating_sub(deviation)).div_ceil(BPS_DIVISOR);
+    let lower = lower.div_ceil(tick_size).max(1) * tick_size;
+    let upper = reference * (BPS_DIVISOR + deviation) / BPS_DIVISOR;
+    let upper = (upper / tick_size * tick_size).min(u64::MAX as u128);
+    if lower > upper {
+        return reference_ticks;
+    }
+    (price_ticks as u128).clamp(lower, upper) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_improve() {
+        assert_eq!(improve(Side::Bid, 100, 2), 102);
+        assert_eq!(improve(Side::Ask, 100, 2), 98);
+        assert_eq!(improve(Side::Ask, 2, 5), 1);
+        assert_eq!(improve(Side::Bid, u64::MAX - 1, 5), u64::MAX);
+    }
+
+    #[test]
+    fn test_n_ticks_inside() {
+        assert_eq!(n_ticks_inside(100, Side::Bid, 1), Some(99));
+        assert_eq!(n_ticks_inside(100, Side::Ask, 3), Some(103));
+        assert_eq!(n_ticks_inside(1, Side::Bid, 1), None);
+        assert_eq!(n_ticks_inside(3, Side::Bid, 5), None);
+    }
+
+    #[test]
+    fn test_would_cross() {
+        assert!(would_cross(Side::Bid, 100, 100));
+        assert!(!would_cross(Side::Bid, 99, 100));
+        assert!(would_cross(Side::Ask, 100, 100));
+        assert!(!would_cross(Side::Ask, 101, 100));
+    }
+
+    #[test]
+    fn test_clamp_to_band() {
+        // 5% of 100 ticks
+        assert_eq!(clamp_to_band(120, 100, 500, 1), 105);
+        assert_eq!(clamp_to_band(80, 100, 500, 1), 95);
+        assert_eq!(clamp_to_band(101, 100, 500, 1), 101);
+        // Edges rounded inwards to multiples of 4 ticks
+        assert_eq!(clamp_to_band(120, 100, 500, 4), 104);
+        assert_eq!(clamp_to_band(80, 100, 500, 4), 96);
+        // Never below one tick, even with a band of more than 100%
+        assert_eq!(clamp_to_band(0, 10, 20_000, 1), 1);
+        // Too narrow for the increment
+        assert_eq!(clamp_to_band(120, 101, 0, 4), 101);
+    }
+
+    // proptest does not build for wasm32
+    #[cfg(not(target_arch = "wasm32"))]
+    mod properties {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(512))]
+
+            #[test]
+            fn improve_moves_towards_the_spread(price in 1u64..=u64::MAX / 2, n in 0u64..1_000_000) {
+                prop_assert!(improve(Side::Bid, price, n) >= price);
+                let ask = improve(Side::Ask, price, n);
+                prop_assert!(ask <= price && ask >= 1);
+            }
+
+            #[test]
+            fn n_ticks_inside_never_crosses(best in 0u64..=u64::MAX / 2, n in 1u64..1_000_000) {
+                if let Some(bid) = n_ticks_inside(best, Side::Bid, n) {
+                    prop_assert!(bid >= 1 && !would_cross(Side::Bid, bid, best));
+                    prop_assert_eq!(improve(Side::Bid, bid, n), best);
+                } else {
+                    prop_assert!(best <= n);
+                }
+                let ask = n_ticks_inside(best, Side::Ask, n).unwrap();
+                prop_assert!(!would_cross(Side::Ask, ask, best));
+                prop_assert_eq!(improve(Side::Ask, ask, n), best.max(1));
+            }
+
+            #[test]
+            fn would_cross_is_inclusive_on_one_side(
+                price in 0u64..=u64::MAX,
+                best in 0u64..=u64::MAX,
+            ) {
+                let bid_crosses = would_cross(Side::Bid, price, best);
+                let ask_crosses = would_cross(Side::Ask, price, best);
+                prop_assert!(bid_crosses || ask_crosses);
+                prop_assert_eq!(bid_crosses && ask_crosses, price == best);
+            }
+
+            #[test]
+            fn clamp_stays_in_band(
+                price in 0u64..=u64::MAX,
+                reference in 1u64..=u64::MAX / 2,
+                bps in 0u64..=20_000,
+                tick_size in 1u64..=100,
+            ) {
+                let clamped = clamp_to_band(price, reference, bps, tick_size);
+                if clamped == reference {
+                    return Ok(());
+                }
+                prop_assert!(clamped >= 1);
+                let deviation = (clamped as u128).abs_diff(reference as u128) * BPS_DIVISOR;
+                prop_assert!(deviation <= reference as u128 * bps as u128);
+                if clamped != price {
+                    prop_assert_eq!(clamped % tick_size, 0);
+                }
+                // Idempotent
+                prop_assert_eq!(clamp_to_band(clamped, reference, bps, tick_size), clamped);
+            }
+        }
+    }
+}
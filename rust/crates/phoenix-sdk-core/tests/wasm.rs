@@ -0,0 +1,103 @@
+//! Smoke test for the wasm32 build, which has no default features. Run from `rust/crates` with
+//! `CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner cargo test -p
+//! phoenix-sdk-core --target wasm32-unknown-unknown --no-default-features`.
+#![cfg(target_arch = "wasm32")]
+
+use borsh::BorshSerialize;
+use phoenix::program::{AuditLogHeader, FillEvent, PhoenixInstruction, PhoenixMarketEvent};
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent, Place};
+use phoenix_sdk_core::orderbook::Orderbook;
+use phoenix_sdk_core::sdk_client_core::{MarketMetadata, PhoenixOrder, SDKClientCore};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// The data of a `Log` instruction with one fill, as Phoenix logs it.
+fn log_instruction_data(market: Pubkey, maker: Pubkey) -> Vec<u8> {
+    let mut data = vec![PhoenixInstruction::Log as u8];
+    let header = PhoenixMarketEvent::Header(AuditLogHeader {
+        instruction: PhoenixInstruction::Swap as u8,
+        sequence_number: 7,
+        timestamp: 1_700_000_000,
+        slot: 250_000_000,
+        market,
+        signer: Pubkey::new_unique(),
+        total_events: 1,
+    });
+    let fill = PhoenixMarketEvent::Fill(FillEvent {
+        index: 0,
+        maker_id: maker,
+        order_sequence_number: 42,
+        price_in_ticks: 1000,
+        base_lots_filled: 10,
+        base_lots_remaining: 0,
+    });
+    data.extend(header.try_to_vec().unwrap());
+    data.extend(fill.try_to_vec().unwrap());
+    data
+}
+
+#[wasm_bindgen_test]
+fn parses_log_instructions() {
+    let (market, maker) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let core = SDKClientCore {
+        markets: Default::default(),
+        trader: Pubkey::default(),
+        default_self_trade_behavior: Default::default(),
+    };
+    let data = log_instruction_data(market, maker);
+    let events = core
+        .parse_events_from_instruction_data(&Signature::default(), [(phoenix::id(), &data[..])])
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].header.market, market);
+    assert_eq!(events[0].header.sequence_number, 7);
+    match &events[0].batch[..] {
+        [PhoenixMarketEvent::Fill(fill)] => {
+            assert_eq!((fill.maker_id, fill.base_lots_filled), (maker, 10))
+        }
+        batch => panic!("Expected one fill, got {:?}", batch),
+    }
+}
+
+#[wasm_bindgen_test]
+fn converts_and_books_orders() {
+    let meta = MarketMetadata {
+        base_atoms_per_raw_base_unit: 1_000_000_000,
+        quote_atoms_per_quote_unit: 1_000_000,
+        quote_atoms_per_quote_lot: 1,
+        base_atoms_per_base_lot: 1_000_000,
+        tick_size_in_quote_atoms_per_base_unit: 1000,
+        num_base_lots_per_base_unit: 1000,
+        raw_base_units_per_base_unit: 1,
+        ..Default::default()
+    };
+    assert_eq!(meta.base_lots_to_base_atoms(10), 10_000_000);
+    assert_eq!(meta.base_lots_and_price_to_quote_atoms(1000, 25), 25_000);
+
+    let maker = Pubkey::new_unique();
+    let mut book = Orderbook::<FIFOOrderId, PhoenixOrder>::default();
+    book.apply_event(&PhoenixEvent {
+        market: Pubkey::new_unique(),
+        sequence_number: 0,
+        slot: 0,
+        timestamp: 0,
+        signature: Signature::default(),
+        signer: maker,
+        event_index: 0,
+        details: MarketEventDetails::Place(Place {
+            order_sequence_number: !1,
+            client_order_id: 0,
+            maker,
+            price_in_ticks: 25,
+            base_lots_placed: 10,
+        }),
+    });
+    let (order_id, order) = book.iter_side(Side::Bid).next().unwrap();
+    assert_eq!(
+        (u64::from(order_id.price_in_ticks), order.num_base_lots),
+        (25, 10)
+    );
+}
@@ -0,0 +1,143 @@
+//! Fans one second of events at 50k events/sec out to four subscribers through an `EventRouter`.
+//! `boxed_copies` measures only the copying of each event into its own box per subscriber, which
+//! the router did on top of routing while `SDKMarketEvent::PhoenixEvent` held a `Box`. Before
+//! timing, prints how many allocations each makes.
+//!
+//! ```text
+//! cargo bench -p phoenix-sdk --bench router_fanout
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use phoenix_sdk::prelude::*;
+use phoenix_sdk_core::test_utils::event;
+use tokio::runtime::Runtime;
+
+/// Counts allocations, to show what the fan-out costs beyond time.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const EVENTS_PER_SECOND: usize = 50_000;
+const EVENTS_PER_BATCH: usize = 10;
+const SUBSCRIBERS: usize = 4;
+
+fn batches(market: Pubkey) -> Vec<Vec<SDKMarketEvent>> {
+    (0..EVENTS_PER_SECOND / EVENTS_PER_BATCH)
+        .map(|batch| {
+            (0..EVENTS_PER_BATCH)
+                .map(|index| {
+                    let fill = Fill::new(1, Pubkey::default(), Pubkey::default(), 1000, 1, 0);
+                    SDKMarketEvent::from(PhoenixEvent {
+                        sequence_number: batch as u64,
+                        slot: 1,
+                        timestamp: 1_700_000_000,
+                        event_index: index as u64,
+                        ..event(market, MarketEventDetails::Fill(fill))
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Routes `batches` to `SUBSCRIBERS` subscribers of `market` and drains them, returning the
+/// number of events received.
+fn route(runtime: &Runtime, market: Pubkey, batches: &[Vec<SDKMarketEvent>]) -> usize {
+    runtime.block_on(async {
+        let router = EventRouter::new(batches.len());
+        let mut receivers = (0..SUBSCRIBERS)
+            .map(|_| router.subscribe(market, Backpressure::Wait))
+            .collect::<Vec<_>>();
+        for batch in batches {
+            router.route(batch.clone()).await;
+        }
+        drop(router);
+        let mut received = 0;
+        for receiver in receivers.iter_mut() {
+            while let Some(batch) = receiver.recv().await {
+                received += batch.len();
+            }
+        }
+        received
+    })
+}
+
+/// The copies the router used to make: one boxed copy of every event for each subscriber.
+fn copy_into_boxes(batches: &[Vec<SDKMarketEvent>]) -> usize {
+    let mut received = 0;
+    for batch in batches {
+        for _ in 0..SUBSCRIBERS {
+            let copies = batch
+                .iter()
+                .filter_map(SDKMarketEvent::as_phoenix_event)
+                .map(|event| Box::new(*event))
+                .collect::<Vec<_>>();
+            received += criterion::black_box(copies).len();
+        }
+    }
+    received
+}
+
+fn count_allocations(f: impl FnOnce() -> usize) -> (u64, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let received = f();
+    (ALLOCATIONS.load(Ordering::Relaxed) - before, received)
+}
+
+fn router_fanout(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let market = Pubkey::new_unique();
+    let batches = batches(market);
+
+    for (name, (allocations, received)) in [
+        (
+            "shared events",
+            count_allocations(|| route(&runtime, market, &batches)),
+        ),
+        (
+            "boxed copies",
+            count_allocations(|| copy_into_boxes(&batches)),
+        ),
+    ] {
+        assert_eq!(received, EVENTS_PER_SECOND * SUBSCRIBERS);
+        println!(
+            "{}: {} allocations for {} events to {} subscribers, {:.2} per delivered event",
+            name,
+            allocations,
+            EVENTS_PER_SECOND,
+            SUBSCRIBERS,
+            allocations as f64 / received as f64
+        );
+    }
+
+    let mut group = c.benchmark_group("router_fanout");
+    group.throughput(Throughput::Elements(EVENTS_PER_SECOND as u64));
+    group.sample_size(20);
+    group.bench_function("shared_events", |b| {
+        b.iter(|| route(&runtime, market, &batches))
+    });
+    group.bench_function("boxed_copies", |b| b.iter(|| copy_into_boxes(&batches)));
+    group.finish();
+}
+
+criterion_group!(benches, router_fanout);
+criterion_main!(benches);
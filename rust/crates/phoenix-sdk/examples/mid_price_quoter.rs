@@ -0,0 +1,134 @@
+//! Quotes a bid and an ask a few ticks around a market's mid price, requoting every few seconds,
+//! on a `TradingStack`. Trades on paper unless `--live` is passed.
+//!
+//! ```text
+//! cargo run --example mid_price_quoter -- <market> [--rpc-url <url>] [--keypair <path>] [--live]
+//! ```
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use phoenix_sdk::prelude::*;
+use solana_sdk::signature::read_keypair_file;
+
+#[derive(Parser)]
+struct Args {
+    /// The market to quote on.
+    market: Pubkey,
+    #[clap(long, default_value = "https://api.devnet.solana.com")]
+    rpc_url: String,
+    /// Defaults to the Solana CLI's keypair.
+    #[clap(long)]
+    keypair: Option<String>,
+    /// Sends real orders instead of paper trading.
+    #[clap(long)]
+    live: bool,
+    /// Distance of each quote from the mid price.
+    #[clap(long, default_value = "5")]
+    half_spread_ticks: u64,
+    #[clap(long, default_value = "10")]
+    size_base_lots: u64,
+    #[clap(long, default_value = "5")]
+    requote_seconds: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let keypair_path = match args.keypair {
+        Some(path) => path,
+        None => format!("{}/.config/solana/id.json", std::env::var("HOME")?),
+    };
+    let payer = read_keypair_file(&keypair_path)
+        .map_err(|e| anyhow!("Failed to read keypair {}: {}", keypair_path, e))?;
+
+    let mut builder = TradingStack::builder(args.rpc_url, payer)
+        .market(args.market)
+        // Rejects quotes more than 5% from the mid set by `refresh_market`
+        .with_risk(RiskConfig {
+            price_band_pct: Some(5.0),
+            ..Default::default()
+        });
+    if !args.live {
+        builder = builder.with_paper_mode();
+    }
+    let stack = builder.build().await?;
+    let mut events = stack.subscribe(args.market);
+    let mut requote = tokio::time::interval(Duration::from_secs(args.requote_seconds));
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            Some(batch) = events.recv() => {
+                for event in batch.iter().filter_map(SDKMarketEvent::as_phoenix_event) {
+                    if let MarketEventDetails::Fill(fill) = event.details {
+                        println!(
+                            "Filled {} lots at {} ticks",
+                            fill.base_lots_filled, fill.price_in_ticks
+                        );
+                    }
+                }
+            }
+            _ = requote.tick() => {
+                if let Err(e) = quote(&stack, &args.market, args.half_spread_ticks, args.size_base_lots).await {
+                    println!("Failed to quote: {}", e);
+                }
+            }
+        }
+    }
+
+    println!("Shutting down");
+    stack.shutdown().await
+}
+
+/// Replaces the resting quotes with a bid and an ask `half_spread_ticks` from the mid, in one
+/// transaction.
+async fn quote(
+    stack: &TradingStack,
+    market: &Pubkey,
+    half_spread_ticks: u64,
+    size_base_lots: u64,
+) -> Result<()> {
+    let state = stack.refresh_market(market).await?;
+    let (Some(best_bid), Some(best_ask)) = (
+        state.orderbook.bids.keys().next(),
+        state.orderbook.asks.keys().next(),
+    ) else {
+        println!("The book is one-sided, not quoting");
+        return Ok(());
+    };
+    let mid = (best_bid.price_in_ticks.as_u64() + best_ask.price_in_ticks.as_u64()) / 2;
+    let bid = mid.saturating_sub(half_spread_ticks).max(1);
+    let ask = mid.saturating_add(half_spread_ticks);
+
+    let sdk = stack.sdk();
+    let mut ixs = vec![];
+    if !stack.open_orders(market).is_empty() {
+        ixs.push(sdk.get_cancel_all_ix(market)?);
+    }
+    ixs.push(sdk.get_post_only_ix_from_tick_price(
+        market,
+        bid,
+        Side::Bid,
+        size_base_lots,
+        1,
+        true,
+    )?);
+    ixs.push(sdk.get_post_only_ix_from_tick_price(
+        market,
+        ask,
+        Side::Ask,
+        size_base_lots,
+        2,
+        true,
+    )?);
+    let signature = stack.send_ixs(ixs).await?;
+
+    let position = stack.position(market);
+    println!(
+        "Quoted {} @ {} ticks in {}, position {} lots, realized PnL {} quote atoms",
+        bid, ask, signature, position.base_lots, position.realized_pnl_quote_atoms
+    );
+    Ok(())
+}
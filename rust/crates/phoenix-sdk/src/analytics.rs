@@ -0,0 +1,558 @@
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use phoenix::state::enums::Side;
+use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent};
+use phoenix_sdk_core::sdk_client_core::MarketMetadata;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::candles::pubkey_string;
+use crate::market_quality::{MarketQualitySummary, QualitySample};
+
+/// A trader's traded volume on one market, in base and quote atoms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraderVolume {
+    pub maker_base: u128,
+    pub maker_quote: u128,
+    pub taker_base: u128,
+    pub taker_quote: u128,
+    /// Fills the trader was a party to. A fill against the trader's own order counts once.
+    pub trade_count: u64,
+}
+
+impl TraderVolume {
+    pub fn total_quote(&self) -> u128 {
+        self.maker_quote + self.taker_quote
+    }
+}
+
+/// Quote volume on several markets, normalized to a common number of decimals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuoteVolume {
+    pub maker_quote: u128,
+    pub taker_quote: u128,
+    pub trade_count: u64,
+}
+
+impl QuoteVolume {
+    pub fn total_quote(&self) -> u128 {
+        self.maker_quote + self.taker_quote
+    }
+}
+
+/// Attributes every fill among `events` to both its maker and its taker. The events must all be
+/// from the market described by `meta`. Accepts owned or borrowed events, so it can consume an
+/// `EventReplayer` or other stream without collecting it first.
+pub fn trader_volume<E: Borrow<PhoenixEvent>>(
+    events: impl IntoIterator<Item = E>,
+    meta: &MarketMetadata,
+) -> BTreeMap<Pubkey, TraderVolume> {
+    let mut volumes = BTreeMap::<Pubkey, TraderVolume>::new();
+    for event in events {
+        let MarketEventDetails::Fill(fill) = event.borrow().details else {
+            continue;
+        };
+        let base = meta.base_lots_to_base_atoms(fill.base_lots_filled) as u128;
+        let quote = meta
+            .base_lots_and_price_to_quote_atoms(fill.base_lots_filled, fill.price_in_ticks)
+            as u128;
+
+        let maker = volumes.entry(fill.maker).or_default();
+        maker.maker_base += base;
+        maker.maker_quote += quote;
+        maker.trade_count += 1;
+
+        let taker = volumes.entry(fill.taker).or_default();
+        taker.taker_base += base;
+        taker.taker_quote += quote;
+        if fill.taker != fill.maker {
+            taker.trade_count += 1;
+        }
+    }
+    volumes
+}
+
+/// Each trader's percentage of all traded quote volume, counting both sides of every fill so
+/// that the shares add up to 100.
+pub fn market_share<'a, V: VolumeTotal + 'a>(
+    volumes: impl IntoIterator<Item = (&'a Pubkey, &'a V)> + Clone,
+) -> BTreeMap<Pubkey, Decimal> {
+    let total = volumes
+        .clone()
+        .into_iter()
+        .map(|(_, volume)| volume.total_quote())
+        .sum::<u128>();
+    volumes
+        .into_iter()
+        .map(|(trader, volume)| {
+            let share = match (to_decimal(volume.total_quote()), to_decimal(total)) {
+                (Some(volume), Some(total)) if !total.is_zero() => {
+                    volume * Decimal::ONE_HUNDRED / total
+                }
+                _ => Decimal::ZERO,
+            };
+            (*trader, share)
+        })
+        .collect()
+}
+
+/// The `n` traders with the most maker quote volume, largest first.
+pub fn top_makers(
+    volumes: &BTreeMap<Pubkey, TraderVolume>,
+    n: usize,
+) -> Vec<(Pubkey, TraderVolume)> {
+    let mut makers = volumes
+        .iter()
+        .filter(|(_, volume)| volume.maker_quote > 0)
+        .map(|(trader, volume)| (*trader, *volume))
+        .collect::<Vec<_>>();
+    makers.sort_by_key(|(_, volume)| std::cmp::Reverse(volume.maker_quote));
+    makers.truncate(n);
+    makers
+}
+
+/// Sums per-market volumes into quote volume with `quote_decimals` decimals, converting each
+/// market's quote atoms from its own `quote_decimals`. Converting to fewer decimals rounds down.
+/// Base volume is not comparable across markets and is dropped.
+pub fn merge_quote_volumes<'a>(
+    markets: impl IntoIterator<Item = (&'a BTreeMap<Pubkey, TraderVolume>, &'a MarketMetadata)>,
+    quote_decimals: u32,
+) -> BTreeMap<Pubkey, QuoteVolume> {
+    let mut merged = BTreeMap::<Pubkey, QuoteVolume>::new();
+    for (volumes, meta) in markets {
+        let normalize = |atoms: u128| {
+            if quote_decimals >= meta.quote_decimals {
+                atoms * 10u128.pow(quote_decimals - meta.quote_decimals)
+            } else {
+                atoms / 10u128.pow(meta.quote_decimals - quote_decimals)
+            }
+        };
+        for (trader, volume) in volumes.iter() {
+            let entry = merged.entry(*trader).or_default();
+            entry.maker_quote += normalize(volume.maker_quote);
+            entry.taker_quote += normalize(volume.taker_quote);
+            entry.trade_count += volume.trade_count;
+        }
+    }
+    merged
+}
+
+/// Volumes whose share of the total can be computed with `market_share`.
+pub trait VolumeTotal {
+    fn total_quote(&self) -> u128;
+}
+
+impl VolumeTotal for TraderVolume {
+    fn total_quote(&self) -> u128 {
+        TraderVolume::total_quote(self)
+    }
+}
+
+impl VolumeTotal for QuoteVolume {
+    fn total_quote(&self) -> u128 {
+        QuoteVolume::total_quote(self)
+    }
+}
+
+/// The horizons `maker_report` measures markouts at, in seconds.
+pub const MARKOUT_HORIZONS_SECS: [u64; 3] = [1, 10, 60];
+
+/// Where the mid was `horizon_secs` after each of a maker's fills, measured from the fill price
+/// in the maker's favor and averaged over the fills. Negative values are adverse selection: the
+/// price moved against the maker. Fills with no two-sided sample that late are left out.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Markout {
+    pub horizon_secs: u64,
+    pub fills: u64,
+    pub average_ticks: Option<f64>,
+    pub average_bps: Option<f64>,
+}
+
+/// How a trader performed as a maker on one market, from `maker_report`. Volumes are in base and
+/// quote atoms.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MakerReport {
+    #[serde(with = "pubkey_string")]
+    pub trader: Pubkey,
+    pub maker_base_volume: u128,
+    pub maker_quote_volume: u128,
+    pub fill_count: u64,
+    pub place_count: u64,
+    /// Orders reduced to nothing, i.e. cancelled.
+    pub cancel_count: u64,
+    /// `cancel_count` per fill, or `None` without fills.
+    pub cancel_to_fill_ratio: Option<f64>,
+    /// From each order's `Place` to its first fill, over the orders placed and filled within the
+    /// events. Block times have a resolution of one second.
+    pub average_time_to_fill_ms: Option<f64>,
+    /// One for each of `MARKOUT_HORIZONS_SECS`.
+    pub markouts: Vec<Markout>,
+    /// Fraction of the sampled time the trader quoted both sides, see
+    /// `MarketQualitySummary::maker_two_sided_uptime`.
+    pub two_sided_uptime: f64,
+}
+
+/// Measures `trader`'s market making from the events of the market described by `meta` and from
+/// `book_samples` of that market, recorded for `trader` in time order, e.g. by a
+/// `MarketQualityRecorder`. Events without a block time count towards volumes and counts but not
+/// towards times and markouts. Uptime covers the time from the first sample to the last.
+pub fn maker_report<E: AsRef<PhoenixEvent>>(
+    events: &[E],
+    book_samples: &[QualitySample],
+    trader: Pubkey,
+    meta: &MarketMetadata,
+) -> MakerReport {
+    let mut report = MakerReport {
+        trader,
+        maker_base_volume: 0,
+        maker_quote_volume: 0,
+        fill_count: 0,
+        place_count: 0,
+        cancel_count: 0,
+        cancel_to_fill_ratio: None,
+        average_time_to_fill_ms: None,
+        markouts: vec![],
+        two_sided_uptime: 0.0,
+    };
+    // Place times of the orders not filled yet, by order sequence number
+    let mut placed_at_ms = BTreeMap::new();
+    let mut times_to_fill_ms = vec![];
+    // The fill price in ticks, the maker's side and the fill time of each fill with a block time
+    let mut timed_fills = vec![];
+    for event in events.iter().map(AsRef::as_ref) {
+        match event.details {
+            MarketEventDetails::Place(place) if place.maker == trader => {
+                report.place_count += 1;
+                if event.time().is_known() {
+                    placed_at_ms.insert(place.order_sequence_number, event.timestamp_millis());
+                }
+            }
+            MarketEventDetails::Reduce(reduce)
+                if reduce.maker == trader && reduce.base_lots_remaining == 0 =>
+            {
+                report.cancel_count += 1;
+                placed_at_ms.remove(&reduce.order_sequence_number);
+            }
+            MarketEventDetails::Fill(fill) if fill.maker == trader => {
+                report.fill_count += 1;
+                report.maker_base_volume +=
+                    meta.base_lots_to_base_atoms(fill.base_lots_filled) as u128;
+                report.maker_quote_volume += meta
+                    .base_lots_and_price_to_quote_atoms(fill.base_lots_filled, fill.price_in_ticks)
+                    as u128;
+                if event.time().is_known() {
+                    if let Some(placed_at_ms) = placed_at_ms.remove(&fill.order_sequence_number) {
+                        times_to_fill_ms.push((event.timestamp_millis() - placed_at_ms) as f64);
+                    }
+                    timed_fills.push((
+                        fill.price_in_ticks,
+                        fill.maker_side,
+                        event.timestamp_millis(),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    report.cancel_to_fill_ratio =
+        (report.fill_count > 0).then(|| report.cancel_count as f64 / report.fill_count as f64);
+    report.average_time_to_fill_ms = average(&times_to_fill_ms);
+    report.markouts = MARKOUT_HORIZONS_SECS
+        .iter()
+        .map(|&horizon_secs| markout(&timed_fills, book_samples, horizon_secs))
+        .collect();
+    report.two_sided_uptime = MarketQualitySummary::from_samples(book_samples, Duration::ZERO, 0.0)
+        .maker_two_sided_uptime;
+    report
+}
+
+fn markout(fills: &[(u64, Side, i64)], samples: &[QualitySample], horizon_secs: u64) -> Markout {
+    let mut ticks = vec![];
+    let mut bps = vec![];
+    for &(price_in_ticks, maker_side, filled_at_ms) in fills {
+        let target_ms = filled_at_ms.max(0) as u64 + horizon_secs * 1000;
+        let first = samples.partition_point(|sample| sample.timestamp_ms < target_ms);
+        let Some(mid) = samples[first..].iter().find_map(QualitySample::mid) else {
+            continue;
+        };
+        let moved = match maker_side {
+            Side::Bid => mid - price_in_ticks as f64,
+            Side::Ask => price_in_ticks as f64 - mid,
+        };
+        ticks.push(moved);
+        if price_in_ticks > 0 {
+            bps.push(moved / price_in_ticks as f64 * 10_000.0);
+        }
+    }
+    Markout {
+        horizon_secs,
+        fills: ticks.len() as u64,
+        average_ticks: average(&ticks),
+        average_bps: average(&bps),
+    }
+}
+
+fn average(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+fn to_decimal(value: u128) -> Option<Decimal> {
+    Decimal::try_from_i128_with_scale(i128::try_from(value).ok()?, 0).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix_sdk_core::market_event::{Fill, Place, Reduce};
+    use solana_sdk::signature::Signature;
+
+    use super::*;
+    use crate::event_recorder::{EventRecorder, EventReplayer};
+    use phoenix_sdk_core::test_utils::event;
+
+    #[test]
+    fn test_trader_volume_from_recording() {
+        let market = Pubkey::new_from_array([1; 32]);
+        let alice = Pubkey::new_from_array([2; 32]);
+        let bob = Pubkey::new_from_array([3; 32]);
+        let carol = Pubkey::new_from_array([4; 32]);
+        let meta = MarketMetadata {
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_atoms_per_raw_base_unit: 1_000_000_000,
+            quote_atoms_per_quote_unit: 1_000_000,
+            quote_atoms_per_quote_lot: 1,
+            base_atoms_per_base_lot: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 100,
+            num_base_lots_per_base_unit: 1000,
+            raw_base_units_per_base_unit: 1,
+            ..Default::default()
+        };
+        let fill_event = |sequence_number, details| PhoenixEvent {
+            sequence_number,
+            slot: 250_000_000 + sequence_number,
+            timestamp: 1_700_000_000,
+            signature: Signature::from([sequence_number as u8; 64]),
+            ..event(market, MarketEventDetails::Fill(details))
+        };
+
+        // Record three transactions, then stream them back from the recording
+        let mut recorder = EventRecorder::new(vec![]);
+        for batch in [
+            // Bob buys 100 lots from Alice at 3000 ticks, i.e. 0.1 at 0.3
+            vec![fill_event(1, Fill::new(5, alice, bob, 3000, 100, 0))],
+            // Carol sells 200 lots to Alice's bid at 2999, then crosses her own ask
+            vec![
+                fill_event(2, Fill::new(!6, alice, carol, 2999, 200, 0)),
+                fill_event(2, Fill::new(7, carol, carol, 3001, 50, 0)),
+            ],
+            // Not a fill, so ignored
+            vec![PhoenixEvent {
+                details: MarketEventDetails::Place(Place {
+                    order_sequence_number: 8,
+                    client_order_id: 0,
+                    maker: bob,
+                    price_in_ticks: 3002,
+                    base_lots_placed: 10,
+                }),
+                ..fill_event(3, Fill::new(8, bob, bob, 0, 0, 0))
+            }],
+        ] {
+            let batch = batch.into_iter().map(Into::into).collect::<Vec<_>>();
+            recorder.record_with_timestamp(&batch, 0).unwrap();
+        }
+        let recording = recorder.into_inner();
+        let events =
+            EventReplayer::new(recording.as_slice()).flat_map(|batch| batch.unwrap().events);
+        let volumes = trader_volume(events, &meta);
+
+        let expected = BTreeMap::from([
+            (
+                alice,
+                TraderVolume {
+                    maker_base: 300_000_000,
+                    maker_quote: 30_000 + 59_980,
+                    taker_base: 0,
+                    taker_quote: 0,
+                    trade_count: 2,
+                },
+            ),
+            (
+                bob,
+                TraderVolume {
+                    taker_base: 100_000_000,
+                    taker_quote: 30_000,
+                    trade_count: 1,
+                    ..Default::default()
+                },
+            ),
+            (
+                carol,
+                TraderVolume {
+                    maker_base: 50_000_000,
+                    maker_quote: 15_005,
+                    taker_base: 250_000_000,
+                    taker_quote: 59_980 + 15_005,
+                    trade_count: 2,
+                },
+            ),
+        ]);
+        assert_eq!(volumes, expected);
+
+        // Both sides of every fill count: 2 * (30000 + 59980 + 15005) = 209970
+        let shares = market_share(&volumes);
+        assert_eq!(shares[&alice].round_dp(4), Decimal::new(428537, 4));
+        assert_eq!(shares[&bob].round_dp(4), Decimal::new(142878, 4));
+        assert_eq!(
+            shares.values().sum::<Decimal>().round_dp(8),
+            Decimal::ONE_HUNDRED
+        );
+
+        let top = top_makers(&volumes, 1);
+        assert_eq!(top, vec![(alice, expected[&alice])]);
+
+        // A second market with 9 quote decimals merges at 6 decimals
+        let other_meta = MarketMetadata {
+            quote_decimals: 9,
+            ..meta
+        };
+        let other = BTreeMap::from([(
+            bob,
+            TraderVolume {
+                maker_quote: 2_000_999,
+                trade_count: 3,
+                ..Default::default()
+            },
+        )]);
+        let merged = merge_quote_volumes([(&volumes, &meta), (&other, &other_meta)], 6);
+        assert_eq!(
+            merged[&bob],
+            QuoteVolume {
+                maker_quote: 2_000,
+                taker_quote: 30_000,
+                trade_count: 4,
+            }
+        );
+        assert_eq!(merged[&alice].maker_quote, 89_980);
+    }
+
+    #[test]
+    fn test_maker_report() {
+        let market = Pubkey::new_unique();
+        let (maker, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let meta = MarketMetadata {
+            base_atoms_per_raw_base_unit: 1_000_000_000,
+            quote_atoms_per_quote_unit: 1_000_000,
+            quote_atoms_per_quote_lot: 1,
+            base_atoms_per_base_lot: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 100,
+            num_base_lots_per_base_unit: 1000,
+            raw_base_units_per_base_unit: 1,
+            ..Default::default()
+        };
+        let at = |timestamp, details| PhoenixEvent {
+            timestamp,
+            ..event(market, details)
+        };
+        let place = |order_sequence_number, price_in_ticks| {
+            MarketEventDetails::Place(Place {
+                order_sequence_number,
+                client_order_id: 0,
+                maker,
+                price_in_ticks,
+                base_lots_placed: 10,
+            })
+        };
+        let reduce = |order_sequence_number, base_lots_remaining| {
+            MarketEventDetails::Reduce(Reduce {
+                order_sequence_number,
+                maker,
+                price_in_ticks: 90,
+                base_lots_removed: 5,
+                base_lots_remaining,
+                is_full_cancel: base_lots_remaining == 0,
+            })
+        };
+        let taker = Pubkey::new_unique();
+        let events = [
+            at(1000, place(!1, 100)),
+            at(1000, place(2, 101)),
+            at(1000, place(!3, 90)),
+            // The bid is half filled after 2 seconds, the ask filled after 10
+            at(
+                1002,
+                MarketEventDetails::Fill(Fill::new(!1, maker, taker, 100, 5, 5)),
+            ),
+            at(
+                1010,
+                MarketEventDetails::Fill(Fill::new(2, maker, taker, 101, 10, 0)),
+            ),
+            // A partial reduce and a cancel of the third order
+            at(1012, reduce(!3, 5)),
+            at(1013, reduce(!3, 0)),
+            // Another maker's fill
+            at(
+                1014,
+                MarketEventDetails::Fill(Fill::new(7, other, taker, 101, 10, 0)),
+            ),
+        ];
+        let sample =
+            |timestamp_secs: u64, best_bid, best_ask, maker_bid, maker_ask| QualitySample {
+                timestamp_ms: timestamp_secs * 1000,
+                best_bid: Some(best_bid),
+                best_ask: Some(best_ask),
+                maker_bid,
+                maker_ask,
+            };
+        let samples = [
+            sample(1000, 99, 101, Some(100), Some(101)),
+            sample(1003, 101, 103, Some(100), Some(101)),
+            sample(1011, 102, 104, Some(100), None),
+            sample(1020, 103, 105, None, None),
+        ];
+
+        let report = maker_report(&events, &samples, maker, &meta);
+        assert_eq!(report.trader, maker);
+        assert_eq!(report.maker_base_volume, 15_000_000);
+        // 5 lots at 100 ticks and 10 lots at 101 ticks
+        assert_eq!(report.maker_quote_volume, 50 + 101);
+        assert_eq!(
+            (report.fill_count, report.place_count, report.cancel_count),
+            (2, 3, 1)
+        );
+        assert_eq!(report.cancel_to_fill_ratio, Some(0.5));
+        assert_eq!(report.average_time_to_fill_ms, Some(6000.0));
+        // Two-sided from 1000 to 1011 out of 1000 to 1020
+        assert!((report.two_sided_uptime - 0.55).abs() < 1e-12);
+
+        // After 1 second the mids are 102 and 103: the bid bought 2 ticks low, the ask sold 2
+        // ticks low. After 10 seconds the mid is 104 for both.
+        let [one, ten, sixty] = report.markouts[..] else {
+            panic!("Expected 3 markouts, got {:?}", report.markouts);
+        };
+        assert_eq!((one.horizon_secs, one.fills), (1, 2));
+        assert_eq!(one.average_ticks, Some(0.0));
+        let bps = one.average_bps.unwrap();
+        assert!((bps - (200.0 - 2.0 / 101.0 * 10_000.0) / 2.0).abs() < 1e-9);
+        assert_eq!((ten.fills, ten.average_ticks), (2, Some(0.5)));
+        let bps = ten.average_bps.unwrap();
+        assert!((bps - (400.0 - 3.0 / 101.0 * 10_000.0) / 2.0).abs() < 1e-9);
+        assert_eq!(
+            sixty,
+            Markout {
+                horizon_secs: 60,
+                fills: 0,
+                average_ticks: None,
+                average_bps: None,
+            }
+        );
+
+        // The report serializes for report generators, with the trader as a string
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["trader"], maker.to_string());
+        let round_trip: MakerReport = serde_json::from_value(json).unwrap();
+        assert_eq!(round_trip, report);
+    }
+}
@@ -0,0 +1,600 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::event_sink::EventSink;
+use crate::sdk_client::{ParsedPhoenixTransaction, SDKClient};
+use crate::sdk_market_event::SDKMarketEvent;
+
+/// How far a `BackfillJob` has got. It is saved after every completed page, so a job that is
+/// stopped or crashes resumes with the page after the last completed one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackfillCheckpoint {
+    /// The oldest signature of the last completed page. The next page starts before it.
+    #[serde(with = "optional_signature")]
+    pub before: Option<Signature>,
+    pub pages_completed: u64,
+    pub signatures_processed: u64,
+    /// The slot of the oldest transaction processed.
+    pub earliest_slot: Option<u64>,
+    /// The block time of the oldest transaction processed, in seconds since the Unix epoch.
+    pub earliest_block_time: Option<i64>,
+    /// The job reached its `since` or the start of the market's history.
+    pub done: bool,
+}
+
+mod optional_signature {
+    use std::str::FromStr;
+
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use solana_sdk::signature::Signature;
+
+    pub fn serialize<S: Serializer>(
+        signature: &Option<Signature>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match signature {
+            Some(signature) => serializer.collect_str(signature),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Signature>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|signature| Signature::from_str(&signature).map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Stores the checkpoints of backfill jobs, by job id.
+pub trait Checkpointer: Send + Sync {
+    fn load(&self, job_id: &str) -> Result<Option<BackfillCheckpoint>>;
+    fn save(&self, job_id: &str, checkpoint: &BackfillCheckpoint) -> Result<()>;
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryCheckpointer {
+    checkpoints: Mutex<BTreeMap<String, BackfillCheckpoint>>,
+}
+
+impl Checkpointer for InMemoryCheckpointer {
+    fn load(&self, job_id: &str) -> Result<Option<BackfillCheckpoint>> {
+        Ok(lock(&self.checkpoints).get(job_id).cloned())
+    }
+
+    fn save(&self, job_id: &str, checkpoint: &BackfillCheckpoint) -> Result<()> {
+        lock(&self.checkpoints).insert(job_id.to_string(), checkpoint.clone());
+        Ok(())
+    }
+}
+
+/// Checkpoints kept in a JSON file, rewritten on every save. Unlike `JsonFileMetadataCache`, an
+/// unreadable file is an error rather than empty, so that a job never silently starts over.
+#[derive(Debug)]
+pub struct JsonFileCheckpointer {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JsonFileCheckpointer {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<BTreeMap<String, BackfillCheckpoint>> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Checkpointer for JsonFileCheckpointer {
+    fn load(&self, job_id: &str) -> Result<Option<BackfillCheckpoint>> {
+        let _guard = lock(&self.lock);
+        Ok(self.read()?.remove(job_id))
+    }
+
+    /// Writes to a temporary file first so that a crash mid-write leaves the previous file intact.
+    fn save(&self, job_id: &str, checkpoint: &BackfillCheckpoint) -> Result<()> {
+        let _guard = lock(&self.lock);
+        let mut checkpoints = self.read()?;
+        checkpoints.insert(job_id.to_string(), checkpoint.clone());
+        let tmp_path = self.path.with_extension("tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&serde_json::to_vec_pretty(&checkpoints)?)?;
+        tmp.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Where a `BackfillJob` reads a market's history from, `SDKClient` in practice.
+#[async_trait]
+pub trait BackfillSource: Send + Sync {
+    /// Up to `limit` signatures of the market's transactions, newest first, before `before`.
+    async fn signatures(
+        &self,
+        market: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>>;
+
+    /// A transaction with its events and fee. Must fail rather than return no events if the
+    /// transaction cannot be fetched, or the backfill would skip it.
+    async fn transaction(&self, signature: &Signature) -> Result<ParsedPhoenixTransaction>;
+}
+
+#[async_trait]
+impl BackfillSource for SDKClient {
+    async fn signatures(
+        &self,
+        market: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.get_market_signatures(market, before, limit).await
+    }
+
+    async fn transaction(&self, signature: &Signature) -> Result<ParsedPhoenixTransaction> {
+        self.parse_transaction_full(signature).await
+    }
+}
+
+/// Reported by a `BackfillJob` after every page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackfillProgress {
+    pub checkpoint: BackfillCheckpoint,
+    /// Estimated time left, from the history covered so far in this run. Only known with a
+    /// `since` to reach.
+    pub eta: Option<Duration>,
+}
+
+/// Stops a running `BackfillJob` after its current page. See `BackfillJob::stop_handle`.
+#[derive(Debug, Clone, Default)]
+pub struct BackfillStopHandle(Arc<AtomicBool>);
+
+impl BackfillStopHandle {
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+type ProgressCallback = Box<dyn Fn(&BackfillProgress) + Send + Sync>;
+
+/// Copies a market's history into an `EventSink`, newest first, as a job that survives restarts.
+///
+/// The job pages backwards through the market's signatures. Each page's transactions are sent to
+/// the sink, one batch per transaction with the events on the market followed by the
+/// transaction's fee and compute units, and only then is the checkpoint saved. A crash mid-page therefore re-sends that page's first transactions when the
+/// job is resumed. The sink must ignore duplicates, as `EventStore` does, for the copy to be
+/// exact. Failed transactions are skipped.
+///
+/// To resume, create a job with the same id and checkpointer and `run` it again. A job that is
+/// `done` returns at once.
+pub struct BackfillJob<S: BackfillSource> {
+    source: Arc<S>,
+    market: Pubkey,
+    job_id: String,
+    checkpointer: Arc<dyn Checkpointer>,
+    since: Option<i64>,
+    page_size: usize,
+    on_progress: Option<ProgressCallback>,
+    stop: BackfillStopHandle,
+}
+
+impl<S: BackfillSource> BackfillJob<S> {
+    /// The job id defaults to `backfill-<market>`.
+    pub fn new(source: Arc<S>, market: Pubkey, checkpointer: Arc<dyn Checkpointer>) -> Self {
+        Self {
+            source,
+            market,
+            job_id: format!("backfill-{}", market),
+            checkpointer,
+            since: None,
+            page_size: 1000,
+            on_progress: None,
+            stop: BackfillStopHandle::default(),
+        }
+    }
+
+    /// Identifies the job's checkpoint, e.g. to backfill the same market into two sinks.
+    pub fn with_job_id(mut self, job_id: impl Into<String>) -> Self {
+        self.job_id = job_id.into();
+        self
+    }
+
+    /// Stops at the first transaction with a block time before `since`, in seconds since the
+    /// Unix epoch, instead of at the start of the market's history.
+    pub fn with_since(mut self, since: i64) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Signatures per page, at most 1000, the RPC's limit. Defaults to 1000.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.clamp(1, 1000);
+        self
+    }
+
+    /// Called after every completed page.
+    pub fn with_progress(
+        mut self,
+        on_progress: impl Fn(&BackfillProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    pub fn job_id(&self) -> &str {
+        &self.job_id
+    }
+
+    /// Stopping is permanent for this job; resume with a new one.
+    pub fn stop_handle(&self) -> BackfillStopHandle {
+        self.stop.clone()
+    }
+
+    /// The saved checkpoint, or a fresh one if the job has not completed a page.
+    pub fn checkpoint(&self) -> Result<BackfillCheckpoint> {
+        Ok(self.checkpointer.load(&self.job_id)?.unwrap_or_default())
+    }
+
+    /// Runs until the job is done or stopped, returning the last checkpoint. An error from the
+    /// source, the sink or the checkpointer stops the job with the current page incomplete.
+    pub async fn run<K: EventSink + ?Sized>(&self, sink: &K) -> Result<BackfillCheckpoint> {
+        let mut checkpoint = self.checkpoint()?;
+        let started = Instant::now();
+        let mut run_start_block_time = checkpoint.earliest_block_time;
+        while !checkpoint.done && !self.stop.is_stopped() {
+            let page = self
+                .source
+                .signatures(&self.market, checkpoint.before, self.page_size)
+                .await?;
+            let mut next = checkpoint.clone();
+            let mut reached_since = false;
+            for status in page.iter() {
+                if let (Some(since), Some(block_time)) = (self.since, status.block_time) {
+                    if block_time < since {
+                        reached_since = true;
+                        break;
+                    }
+                }
+                let signature = Signature::from_str(&status.signature)?;
+                if status.err.is_none() {
+                    let transaction = self.source.transaction(&signature).await?;
+                    let events = transaction
+                        .events
+                        .iter()
+                        .filter(|event| event.market == self.market)
+                        .map(|event| SDKMarketEvent::from(*event))
+                        .collect::<Vec<_>>();
+                    if !events.is_empty() {
+                        sink.send_events(events).await?;
+                    }
+                    sink.record_transaction(&transaction).await?;
+                }
+                next.before = Some(signature);
+                next.signatures_processed += 1;
+                next.earliest_slot = Some(status.slot);
+                if let Some(block_time) = status.block_time {
+                    run_start_block_time.get_or_insert(block_time);
+                    next.earliest_block_time = Some(block_time);
+                }
+            }
+            if !page.is_empty() {
+                next.pages_completed += 1;
+            }
+            next.done = page.is_empty() || reached_since;
+            self.checkpointer.save(&self.job_id, &next)?;
+            checkpoint = next;
+            if let Some(on_progress) = &self.on_progress {
+                on_progress(&BackfillProgress {
+                    eta: self.eta(&checkpoint, run_start_block_time, started.elapsed()),
+                    checkpoint: checkpoint.clone(),
+                });
+            }
+        }
+        Ok(checkpoint)
+    }
+
+    /// Extrapolates the rate at which this run has covered history to what is left before `since`.
+    fn eta(
+        &self,
+        checkpoint: &BackfillCheckpoint,
+        run_start_block_time: Option<i64>,
+        elapsed: Duration,
+    ) -> Option<Duration> {
+        if checkpoint.done {
+            return Some(Duration::ZERO);
+        }
+        let earliest = checkpoint.earliest_block_time?;
+        let covered = run_start_block_time?.saturating_sub(earliest);
+        let left = earliest.saturating_sub(self.since?);
+        if covered <= 0 {
+            return None;
+        }
+        Some(elapsed.mul_f64(left as f64 / covered as f64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix_sdk_core::market_event::{Fill, MarketEventDetails, PhoenixEvent};
+    use solana_sdk::transaction::TransactionError;
+
+    use super::*;
+    use crate::event_sink::FnSink;
+    use phoenix_sdk_core::test_utils::event;
+
+    /// A market with `pages * 3` transactions, one fill each, 10 seconds apart. The third is failed.
+    struct MockHistory {
+        market: Pubkey,
+        statuses: Vec<RpcConfirmedTransactionStatusWithSignature>,
+    }
+
+    impl MockHistory {
+        fn new(pages: usize) -> Self {
+            let statuses = (0..pages * 3)
+                .map(|index| RpcConfirmedTransactionStatusWithSignature {
+                    signature: Signature::new_unique().to_string(),
+                    slot: 1_000 - index as u64,
+                    err: (index == 2).then_some(TransactionError::AccountInUse),
+                    memo: None,
+                    block_time: Some(1_700_000_000 - 10 * index as i64),
+                    confirmation_status: None,
+                })
+                .collect();
+            Self {
+                market: Pubkey::new_unique(),
+                statuses,
+            }
+        }
+
+        fn fill(&self, status: &RpcConfirmedTransactionStatusWithSignature) -> PhoenixEvent {
+            PhoenixEvent {
+                sequence_number: status.slot,
+                slot: status.slot,
+                timestamp: status.block_time.unwrap(),
+                signature: Signature::from_str(&status.signature).unwrap(),
+                ..event(
+                    self.market,
+                    MarketEventDetails::Fill(Fill::new(
+                        status.slot,
+                        Pubkey::default(),
+                        Pubkey::default(),
+                        1000,
+                        1,
+                        0,
+                    )),
+                )
+            }
+        }
+    }
+
+    #[async_trait]
+    impl BackfillSource for MockHistory {
+        async fn signatures(
+            &self,
+            _market: &Pubkey,
+            before: Option<Signature>,
+            limit: usize,
+        ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+            let start = match before {
+                Some(before) => {
+                    self.statuses
+                        .iter()
+                        .position(|status| status.signature == before.to_string())
+                        .unwrap()
+                        + 1
+                }
+                None => 0,
+            };
+            Ok(self
+                .statuses
+                .iter()
+                .skip(start)
+                .take(limit)
+                .cloned()
+                .collect())
+        }
+
+        async fn transaction(&self, signature: &Signature) -> Result<ParsedPhoenixTransaction> {
+            let status = self
+                .statuses
+                .iter()
+                .find(|status| status.signature == signature.to_string())
+                .unwrap();
+            Ok(ParsedPhoenixTransaction {
+                signature: *signature,
+                slot: status.slot,
+                block_time: status.block_time,
+                signer: Pubkey::default(),
+                fee_lamports: 5_000,
+                compute_units: Some(status.slot),
+                is_err: false,
+                events: vec![self.fill(status)],
+            })
+        }
+    }
+
+    /// A sink that fails on the transaction at `crash_at` once, as if the process died there.
+    fn crashing_sink(
+        crash_at: Signature,
+        received: Arc<Mutex<Vec<Signature>>>,
+    ) -> FnSink<impl Fn(Vec<SDKMarketEvent>) -> Result<()> + Send + Sync> {
+        let crashed = AtomicBool::new(false);
+        FnSink(move |batch: Vec<SDKMarketEvent>| {
+            let signature = batch[0].as_phoenix_event().unwrap().signature;
+            if signature == crash_at && !crashed.swap(true, Ordering::Relaxed) {
+                anyhow::bail!("Crashed");
+            }
+            lock(&received).push(signature);
+            Ok(())
+        })
+    }
+
+    #[tokio::test]
+    async fn test_resumes_after_crash_between_pages() {
+        let history = Arc::new(MockHistory::new(5));
+        let market = history.market;
+        let signature_at =
+            |index: usize| Signature::from_str(&history.statuses[index].signature).unwrap();
+        let checkpointer = Arc::new(InMemoryCheckpointer::default());
+        let received = Arc::new(Mutex::new(vec![]));
+        // Dies on the second transaction of the third page
+        let sink = crashing_sink(signature_at(7), received.clone());
+
+        let progress = Arc::new(Mutex::new(vec![]));
+        let job = BackfillJob::new(history.clone(), market, checkpointer.clone())
+            .with_page_size(3)
+            .with_progress({
+                let progress = progress.clone();
+                move |update| lock(&progress).push(update.checkpoint.pages_completed)
+            });
+        assert!(job.run(&sink).await.is_err());
+        let checkpoint = job.checkpoint().unwrap();
+        assert_eq!(checkpoint.pages_completed, 2);
+        assert_eq!(checkpoint.signatures_processed, 6);
+        assert_eq!(checkpoint.before, Some(signature_at(5)));
+        assert_eq!(checkpoint.earliest_slot, Some(995));
+        assert_eq!(*lock(&progress), vec![1, 2]);
+
+        // A new job picks up at the third page, re-sending only its first transaction
+        let job = BackfillJob::new(history.clone(), market, checkpointer.clone()).with_page_size(3);
+        let checkpoint = job.run(&sink).await.unwrap();
+        assert!(checkpoint.done);
+        assert_eq!(checkpoint.pages_completed, 5);
+        assert_eq!(checkpoint.signatures_processed, 15);
+        assert_eq!(checkpoint.earliest_slot, Some(986));
+        let mut expected = (0..15)
+            .filter(|&index| index != 2)
+            .map(signature_at)
+            .collect::<Vec<_>>();
+        expected.insert(6, signature_at(6));
+        assert_eq!(*lock(&received), expected);
+
+        // A finished job does nothing
+        let job = BackfillJob::new(history, market, checkpointer).with_page_size(3);
+        assert_eq!(job.run(&sink).await.unwrap(), checkpoint);
+        assert_eq!(lock(&received).len(), 15);
+    }
+
+    #[tokio::test]
+    async fn test_since_stop_and_file_checkpoints() {
+        let history = Arc::new(MockHistory::new(5));
+        let market = history.market;
+        let path = std::env::temp_dir().join(format!("backfill-{}.json", Pubkey::new_unique()));
+        let checkpointer = Arc::new(JsonFileCheckpointer::new(&path));
+        let sink = FnSink(|_| Ok(()));
+
+        // Stopped after the first page, with an estimate for the rest of the window
+        let etas = Arc::new(Mutex::new(vec![]));
+        let job = BackfillJob::new(history.clone(), market, checkpointer.clone())
+            .with_page_size(3)
+            .with_since(1_700_000_000 - 75);
+        let stop = job.stop_handle();
+        let job = job.with_progress({
+            let etas = etas.clone();
+            move |update| {
+                lock(&etas).push(update.eta);
+                stop.stop();
+            }
+        });
+        let checkpoint = job.run(&sink).await.unwrap();
+        assert_eq!(checkpoint.pages_completed, 1);
+        assert!(!checkpoint.done);
+        assert!(lock(&etas)[0].is_some());
+
+        // Resumed from the file, the job ends at the first transaction older than `since`
+        let job = BackfillJob::new(history, market, Arc::new(JsonFileCheckpointer::new(&path)))
+            .with_page_size(3)
+            .with_since(1_700_000_000 - 75);
+        let checkpoint = job.run(&sink).await.unwrap();
+        assert!(checkpoint.done);
+        assert_eq!(checkpoint.signatures_processed, 8);
+        assert_eq!(checkpoint.earliest_block_time, Some(1_700_000_000 - 70));
+        assert_eq!(checkpointer.load(job.job_id()).unwrap(), Some(checkpoint));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_event_store_backfill_is_idempotent() {
+        use crate::event_store::EventStore;
+
+        let history = Arc::new(MockHistory::new(5));
+        let market = history.market;
+        let store = Arc::new(EventStore::open_in_memory().unwrap());
+        let crash_at = Signature::from_str(&history.statuses[7].signature).unwrap();
+        let crashed = AtomicBool::new(false);
+        let sink = FnSink({
+            let store = store.clone();
+            move |batch: Vec<SDKMarketEvent>| {
+                let event = *batch[0].as_phoenix_event().unwrap();
+                if event.signature == crash_at && !crashed.swap(true, Ordering::Relaxed) {
+                    anyhow::bail!("Crashed");
+                }
+                store.insert_events(&[event]).map(|_| ())
+            }
+        });
+
+        let job = BackfillJob::new(history.clone(), market, store.clone()).with_page_size(3);
+        assert!(job.run(&sink).await.is_err());
+        assert_eq!(
+            store.load(job.job_id()).unwrap().unwrap().pages_completed,
+            2
+        );
+        let job = BackfillJob::new(history.clone(), market, store.clone()).with_page_size(3);
+        assert!(job.run(&sink).await.unwrap().done);
+        assert_eq!(store.fills_between(&market, 0, i64::MAX).unwrap().len(), 14);
+
+        // Writing to the store directly also keeps each transaction's fee and compute units
+        let job = BackfillJob::new(history.clone(), market, store.clone()).with_job_id("costs");
+        assert!(job.run(store.as_ref()).await.unwrap().done);
+        assert_eq!(store.fills_between(&market, 0, i64::MAX).unwrap().len(), 14);
+        let stored = |index: usize| {
+            store
+                .transaction(&Signature::from_str(&history.statuses[index].signature).unwrap())
+                .unwrap()
+        };
+        let transaction = stored(0).unwrap();
+        assert_eq!(transaction.fee_lamports, 5_000);
+        assert_eq!(transaction.compute_units, Some(history.statuses[0].slot));
+        assert_eq!(transaction.block_time, history.statuses[0].block_time);
+        // Failed transactions are skipped
+        assert_eq!(stored(2), None);
+    }
+}
@@ -0,0 +1,588 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::path::Path;
+
+use anyhow::Result;
+use phoenix::quantities::WrapperU64;
+use phoenix::state::markets::FIFOOrderId;
+use phoenix::state::Side;
+use phoenix_sdk_core::market_event::{Fill, MarketEventDetails, PhoenixEvent};
+use phoenix_sdk_core::orderbook::Orderbook;
+use phoenix_sdk_core::position_tracker::{Position, PositionTracker};
+use phoenix_sdk_core::sdk_client_core::{MarketMetadata, PhoenixOrder};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::event_recorder::{EventReplayer, RecordedBatch};
+
+/// A change to the strategy's resting quotes. All simulated orders are post-only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteAction {
+    Place {
+        side: Side,
+        price_in_ticks: u64,
+        num_base_lots: u64,
+        client_order_id: u128,
+    },
+    Cancel {
+        client_order_id: u128,
+    },
+    CancelAll,
+}
+
+/// A fill of one of the strategy's simulated orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimulatedFill {
+    pub client_order_id: u128,
+    pub side: Side,
+    pub price_in_ticks: u64,
+    pub base_lots_filled: u64,
+    pub base_lots_remaining: u64,
+    pub fee_quote_atoms: u64,
+}
+
+pub trait Strategy {
+    /// Called for every replayed event on the backtested market, after it has been applied to
+    /// `book`. Returns the quote changes to make in response.
+    fn on_event(
+        &mut self,
+        event: &PhoenixEvent,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+    ) -> Vec<QuoteAction>;
+
+    /// Called when one of the strategy's simulated orders is filled.
+    fn on_fill(&mut self, _fill: &SimulatedFill) {}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BacktestConfig {
+    pub market: Pubkey,
+    pub meta: MarketMetadata,
+    /// Key the simulated position is tracked under.
+    pub trader: Pubkey,
+    /// Fee charged on simulated fills, in basis points of the filled notional.
+    pub maker_fee_bps: u64,
+    /// Delay between the event a strategy reacts to and its quote actions reaching the book,
+    /// measured against the batches' recorded timestamps.
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BacktestReport {
+    pub realized_pnl_quote_atoms: i128,
+    pub unrealized_pnl_quote_atoms: i128,
+    pub total_pnl_quote_atoms: i128,
+    pub fees_paid_quote_atoms: u64,
+    pub volume_base_lots: u64,
+    pub volume_quote_atoms: u64,
+    pub fill_count: u64,
+    pub orders_placed: u64,
+    pub orders_cancelled: u64,
+    /// Post-only orders that would have crossed the book when they arrived.
+    pub orders_rejected: u64,
+    pub final_position: Position,
+    /// Price the open position was marked at: the final mid, or the last trade if the book is one-sided.
+    pub mark_price_in_ticks: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct ShadowOrder {
+    client_order_id: u128,
+    side: Side,
+    price_in_ticks: u64,
+    base_lots_remaining: u64,
+    /// Arrival order among simulated orders.
+    priority: u64,
+    /// Sequence numbers of the real orders resting at the same price when this order arrived.
+    /// A fill against any other order at this price means the queue ahead of us has cleared.
+    queue_ahead: HashSet<u64>,
+}
+
+impl ShadowOrder {
+    fn is_reached_by(&self, fill: &Fill) -> bool {
+        if self.side != fill.maker_side {
+            return false;
+        }
+        let improves_on_fill = match self.side {
+            Side::Bid => self.price_in_ticks > fill.price_in_ticks,
+            Side::Ask => self.price_in_ticks < fill.price_in_ticks,
+        };
+        improves_on_fill
+            || self.price_in_ticks == fill.price_in_ticks
+                && !self.queue_ahead.contains(&fill.order_sequence_number)
+    }
+}
+
+/// Replays recorded events through a `Strategy`, simulating its post-only orders in a shadow book.
+///
+/// Simulated orders never interact with the real book. They are filled when replayed trade flow
+/// reaches them: a real fill at a worse price than a simulated order, or at the same price once
+/// every real order that was ahead of it in the queue has traded or left the book.
+pub struct Backtester<S: Strategy> {
+    config: BacktestConfig,
+    strategy: S,
+    book: Orderbook<FIFOOrderId, PhoenixOrder>,
+    shadow_orders: Vec<ShadowOrder>,
+    pending_actions: VecDeque<(u64, Vec<QuoteAction>)>,
+    tracker: PositionTracker,
+    next_priority: u64,
+    last_trade_price_in_ticks: Option<u64>,
+    orders_placed: u64,
+    orders_cancelled: u64,
+    orders_rejected: u64,
+}
+
+impl<S: Strategy> Backtester<S> {
+    pub fn new(config: BacktestConfig, strategy: S) -> Self {
+        let book = Orderbook {
+            raw_base_units_per_base_lot: config.meta.raw_base_units_per_base_lot_as_float(),
+            quote_units_per_raw_base_unit_per_tick: config
+                .meta
+                .quote_units_per_raw_base_unit_per_tick_as_float(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        };
+        Self::with_book(config, strategy, book)
+    }
+
+    /// Starts the backtest from a snapshot of the book, e.g. one taken when recording began.
+    pub fn with_book(
+        config: BacktestConfig,
+        strategy: S,
+        book: Orderbook<FIFOOrderId, PhoenixOrder>,
+    ) -> Self {
+        Self {
+            tracker: PositionTracker::new(config.trader),
+            config,
+            strategy,
+            book,
+            shadow_orders: vec![],
+            pending_actions: VecDeque::new(),
+            next_priority: 0,
+            last_trade_price_in_ticks: None,
+            orders_placed: 0,
+            orders_cancelled: 0,
+            orders_rejected: 0,
+        }
+    }
+
+    pub fn run_file<P: AsRef<Path>>(self, path: P) -> Result<BacktestReport> {
+        self.run(EventReplayer::open(path)?)
+    }
+
+    pub fn run<I: IntoIterator<Item = Result<RecordedBatch>>>(
+        mut self,
+        batches: I,
+    ) -> Result<BacktestReport> {
+        for batch in batches {
+            self.process_batch(&batch?);
+        }
+        // Actions still in flight would arrive after the recording ends
+        self.apply_pending_actions(u64::MAX);
+        Ok(self.report())
+    }
+
+    pub fn strategy(&self) -> &S {
+        &self.strategy
+    }
+
+    fn process_batch(&mut self, batch: &RecordedBatch) {
+        self.apply_pending_actions(batch.recorded_at_ms);
+        let market = self.config.market;
+        for event in batch.events.iter().filter(|event| event.market == market) {
+            if let MarketEventDetails::Fill(fill) = event.details {
+                self.match_shadow_orders(&fill);
+                self.last_trade_price_in_ticks = Some(fill.price_in_ticks);
+            }
+            self.book.apply_event(event);
+            let actions = self.strategy.on_event(event, &self.book);
+            if actions.is_empty() {
+                continue;
+            }
+            if self.config.latency_ms == 0 {
+                self.apply_actions(actions);
+            } else {
+                self.pending_actions
+                    .push_back((batch.recorded_at_ms + self.config.latency_ms, actions));
+            }
+        }
+    }
+
+    fn apply_pending_actions(&mut self, now_ms: u64) {
+        while let Some((arrival_ms, _)) = self.pending_actions.front() {
+            if *arrival_ms > now_ms {
+                break;
+            }
+            if let Some((_, actions)) = self.pending_actions.pop_front() {
+                self.apply_actions(actions);
+            }
+        }
+    }
+
+    fn apply_actions(&mut self, actions: Vec<QuoteAction>) {
+        for action in actions {
+            match action {
+                QuoteAction::Place {
+                    side,
+                    price_in_ticks,
+                    num_base_lots,
+                    client_order_id,
+                } => self.place(side, price_in_ticks, num_base_lots, client_order_id),
+                QuoteAction::Cancel { client_order_id } => {
+                    let before = self.shadow_orders.len();
+                    self.shadow_orders
+                        .retain(|order| order.client_order_id != client_order_id);
+                    self.orders_cancelled += (before - self.shadow_orders.len()) as u64;
+                }
+                QuoteAction::CancelAll => {
+                    self.orders_cancelled += self.shadow_orders.len() as u64;
+                    self.shadow_orders.clear();
+                }
+            }
+        }
+    }
+
+    fn place(
+        &mut self,
+        side: Side,
+        price_in_ticks: u64,
+        num_base_lots: u64,
+        client_order_id: u128,
+    ) {
+        let crosses = match side {
+            Side::Bid => self
+                .book
+                .asks
+                .keys()
+                .next()
+                .map(|ask| price_in_ticks >= ask.price_in_ticks.as_u64()),
+            Side::Ask => self
+                .book
+                .bids
+                .keys()
+                .next()
+                .map(|bid| price_in_ticks <= bid.price_in_ticks.as_u64()),
+        }
+        .unwrap_or(false);
+        if crosses || num_base_lots == 0 {
+            self.orders_rejected += 1;
+            return;
+        }
+        let resting = match side {
+            Side::Bid => &self.book.bids,
+            Side::Ask => &self.book.asks,
+        };
+        let queue_ahead = resting
+            .keys()
+            .filter(|order_id| order_id.price_in_ticks.as_u64() == price_in_ticks)
+            .map(|order_id| order_id.order_sequence_number)
+            .collect();
+        self.shadow_orders.push(ShadowOrder {
+            client_order_id,
+            side,
+            price_in_ticks,
+            base_lots_remaining: num_base_lots,
+            priority: self.next_priority,
+            queue_ahead,
+        });
+        self.next_priority += 1;
+        self.orders_placed += 1;
+    }
+
+    fn match_shadow_orders(&mut self, fill: &Fill) {
+        let side = fill.maker_side;
+        let mut matched = self
+            .shadow_orders
+            .iter()
+            .enumerate()
+            .filter(|(_, order)| order.is_reached_by(fill))
+            .map(|(i, order)| (i, order.price_in_ticks, order.priority))
+            .collect::<Vec<_>>();
+        // Best price first, then arrival order
+        matched.sort_by_key(|&(_, price_in_ticks, priority)| match side {
+            Side::Bid => (u64::MAX - price_in_ticks, priority),
+            Side::Ask => (price_in_ticks, priority),
+        });
+
+        let mut base_lots_available = fill.base_lots_filled;
+        for (i, _, _) in matched {
+            if base_lots_available == 0 {
+                break;
+            }
+            let order = &mut self.shadow_orders[i];
+            let base_lots_filled = order.base_lots_remaining.min(base_lots_available);
+            base_lots_available -= base_lots_filled;
+            order.base_lots_remaining -= base_lots_filled;
+
+            let quote_atoms = self
+                .config
+                .meta
+                .base_lots_and_price_to_quote_atoms(base_lots_filled, order.price_in_ticks);
+            let fee_quote_atoms = quote_atoms * self.config.maker_fee_bps / 10_000;
+            self.tracker.apply_trade(
+                &self.config.market,
+                side,
+                base_lots_filled,
+                quote_atoms,
+                fee_quote_atoms,
+            );
+            self.strategy.on_fill(&SimulatedFill {
+                client_order_id: order.client_order_id,
+                side,
+                price_in_ticks: order.price_in_ticks,
+                base_lots_filled,
+                base_lots_remaining: order.base_lots_remaining,
+                fee_quote_atoms,
+            });
+        }
+        self.shadow_orders
+            .retain(|order| order.base_lots_remaining > 0);
+    }
+
+    fn mark_price_in_ticks(&self) -> Option<u64> {
+        let best_bid = self.book.bids.keys().next();
+        let best_ask = self.book.asks.keys().next();
+        match (best_bid, best_ask) {
+            (Some(bid), Some(ask)) => {
+                Some((bid.price_in_ticks.as_u64() + ask.price_in_ticks.as_u64()) / 2)
+            }
+            _ => self.last_trade_price_in_ticks,
+        }
+    }
+
+    fn report(&self) -> BacktestReport {
+        let position = self.tracker.position(&self.config.market);
+        let mark_price_in_ticks = self.mark_price_in_ticks();
+        let unrealized_pnl_quote_atoms = match mark_price_in_ticks {
+            Some(mark) => position.unrealized_pnl(mark, &self.config.meta),
+            None => 0,
+        };
+        BacktestReport {
+            realized_pnl_quote_atoms: position.realized_pnl_quote_atoms,
+            unrealized_pnl_quote_atoms,
+            total_pnl_quote_atoms: position.realized_pnl_quote_atoms + unrealized_pnl_quote_atoms,
+            fees_paid_quote_atoms: position.fees_paid_quote_atoms,
+            volume_base_lots: position.volume_base_lots,
+            volume_quote_atoms: position.volume_quote_atoms,
+            fill_count: position.fill_count,
+            orders_placed: self.orders_placed,
+            orders_cancelled: self.orders_cancelled,
+            orders_rejected: self.orders_rejected,
+            final_position: position,
+            mark_price_in_ticks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use phoenix_sdk_core::market_event::Place;
+    use phoenix_sdk_core::test_utils::event;
+
+    fn meta() -> MarketMetadata {
+        MarketMetadata {
+            tick_size_in_quote_atoms_per_base_unit: 1000,
+            num_base_lots_per_base_unit: 100,
+            ..Default::default()
+        }
+    }
+
+    fn place(market: Pubkey, seq: u64, price_in_ticks: u64, lots: u64) -> PhoenixEvent {
+        event(
+            market,
+            MarketEventDetails::Place(Place {
+                order_sequence_number: seq,
+                client_order_id: 0,
+                maker: Pubkey::default(),
+                price_in_ticks,
+                base_lots_placed: lots,
+            }),
+        )
+    }
+
+    fn fill(
+        market: Pubkey,
+        seq: u64,
+        price_in_ticks: u64,
+        lots: u64,
+        remaining: u64,
+    ) -> PhoenixEvent {
+        event(
+            market,
+            MarketEventDetails::Fill(Fill {
+                order_sequence_number: seq,
+                maker: Pubkey::default(),
+                taker: Pubkey::default(),
+                price_in_ticks,
+                base_lots_filled: lots,
+                base_lots_remaining: remaining,
+                side_filled: Side::from_order_sequence_number(seq),
+                maker_side: Side::from_order_sequence_number(seq),
+                taker_side: Side::from_order_sequence_number(seq).opposite(),
+                is_full_fill: remaining == 0,
+            }),
+        )
+    }
+
+    fn batch(recorded_at_ms: u64, events: Vec<PhoenixEvent>) -> Result<RecordedBatch> {
+        Ok(RecordedBatch {
+            recorded_at_ms,
+            events,
+        })
+    }
+
+    /// Quotes once, on the first event it sees.
+    struct QuoteOnce {
+        quotes: Vec<QuoteAction>,
+        fills: Vec<SimulatedFill>,
+    }
+
+    impl QuoteOnce {
+        fn new(quotes: Vec<QuoteAction>) -> Self {
+            Self {
+                quotes,
+                fills: vec![],
+            }
+        }
+    }
+
+    impl Strategy for QuoteOnce {
+        fn on_event(
+            &mut self,
+            _event: &PhoenixEvent,
+            _book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        ) -> Vec<QuoteAction> {
+            std::mem::take(&mut self.quotes)
+        }
+
+        fn on_fill(&mut self, fill: &SimulatedFill) {
+            self.fills.push(*fill);
+        }
+    }
+
+    fn quote(side: Side, price_in_ticks: u64, num_base_lots: u64) -> QuoteAction {
+        QuoteAction::Place {
+            side,
+            price_in_ticks,
+            num_base_lots,
+            client_order_id: price_in_ticks as u128,
+        }
+    }
+
+    fn config(market: Pubkey, latency_ms: u64) -> BacktestConfig {
+        BacktestConfig {
+            market,
+            meta: meta(),
+            trader: Pubkey::new_unique(),
+            maker_fee_bps: 10,
+            latency_ms,
+        }
+    }
+
+    #[test]
+    fn test_queue_priority_and_pnl() {
+        let market = Pubkey::new_unique();
+        let strategy = QuoteOnce::new(vec![quote(Side::Bid, 99, 5), quote(Side::Ask, 101, 5)]);
+        let batches = vec![
+            // A real bid is already resting at 99 when we quote
+            batch(0, vec![place(market, !1, 99, 3)]),
+            batch(
+                10,
+                vec![place(market, 2, 102, 10), place(market, !3, 98, 10)],
+            ),
+            // Fills the order ahead of us at 99
+            batch(20, vec![fill(market, !1, 99, 3, 0)]),
+            // Trades through us to 98: we are filled at 99
+            batch(30, vec![fill(market, !3, 98, 4, 6)]),
+            batch(40, vec![fill(market, 2, 102, 10, 0)]),
+            batch(
+                50,
+                vec![place(market, !4, 100, 1), place(market, 5, 103, 1)],
+            ),
+        ];
+        let backtester = Backtester::new(config(market, 0), strategy);
+        let report = backtester.run(batches).unwrap();
+
+        assert_eq!(report.orders_placed, 2);
+        assert_eq!(report.orders_rejected, 0);
+        assert_eq!(report.fill_count, 2);
+        assert_eq!(report.volume_base_lots, 9);
+        // Bought 4 @ 99, sold 5 @ 101 (10 quote atoms per lot per tick)
+        assert_eq!(report.final_position.base_lots, -1);
+        assert_eq!(report.volume_quote_atoms, 3960 + 5050);
+        assert_eq!(report.fees_paid_quote_atoms, 3 + 5);
+        assert_eq!(report.realized_pnl_quote_atoms, 4 * 20 - 8);
+        assert_eq!(report.mark_price_in_ticks, Some(101));
+        assert_eq!(report.unrealized_pnl_quote_atoms, 0);
+    }
+
+    #[test]
+    fn test_latency_delays_quotes() {
+        let market = Pubkey::new_unique();
+        let batches = || {
+            vec![
+                batch(0, vec![place(market, !1, 98, 10)]),
+                batch(50, vec![fill(market, !1, 98, 2, 8)]),
+                batch(150, vec![fill(market, !1, 98, 2, 6)]),
+            ]
+        };
+        let instant = Backtester::new(
+            config(market, 0),
+            QuoteOnce::new(vec![quote(Side::Bid, 99, 5)]),
+        )
+        .run(batches())
+        .unwrap();
+        assert_eq!(instant.volume_base_lots, 4);
+
+        let delayed = Backtester::new(
+            config(market, 100),
+            QuoteOnce::new(vec![quote(Side::Bid, 99, 5)]),
+        )
+        .run(batches())
+        .unwrap();
+        assert_eq!(delayed.volume_base_lots, 2);
+        assert_eq!(delayed.final_position.base_lots, 2);
+    }
+
+    #[test]
+    fn test_crossing_post_only_is_rejected() {
+        let market = Pubkey::new_unique();
+        let strategy = QuoteOnce::new(vec![
+            quote(Side::Bid, 100, 5),
+            quote(Side::Bid, 99, 5),
+            QuoteAction::CancelAll,
+        ]);
+        let batches = vec![batch(0, vec![place(market, 1, 100, 10)])];
+        let report = Backtester::new(config(market, 0), strategy)
+            .run(batches)
+            .unwrap();
+        assert_eq!(report.orders_rejected, 1);
+        assert_eq!(report.orders_placed, 1);
+        assert_eq!(report.orders_cancelled, 1);
+        assert_eq!(report.fill_count, 0);
+    }
+
+    #[test]
+    fn test_run_file() {
+        use crate::event_recorder::EventRecorder;
+
+        let market = Pubkey::new_unique();
+        let path =
+            std::env::temp_dir().join(format!("phoenix-backtest-{}.bin", Pubkey::new_unique()));
+        let mut recorder = EventRecorder::create(&path).unwrap();
+        for (t, events) in [
+            (0, vec![place(market, !1, 98, 10)]),
+            (10, vec![fill(market, !1, 98, 3, 7)]),
+        ] {
+            let events = events.into_iter().map(Into::into).collect::<Vec<_>>();
+            recorder.record_with_timestamp(&events, t).unwrap();
+        }
+        drop(recorder);
+
+        let strategy = QuoteOnce::new(vec![quote(Side::Bid, 99, 5)]);
+        let backtester = Backtester::new(config(market, 0), strategy);
+        let report = backtester.run_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.fill_count, 1);
+        assert_eq!(report.final_position.base_lots, 3);
+    }
+}
@@ -0,0 +1,16 @@
+//! Writes the SDK's test vectors, see `phoenix_sdk::test_vectors`, to the path given as the only
+//! argument.
+
+use anyhow::{anyhow, Result};
+use phoenix_sdk::test_vectors::generate;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| anyhow!("Usage: phoenix-test-vectors <path>"))?;
+    let vectors = generate().await?;
+    std::fs::write(&path, serde_json::to_string_pretty(&vectors)? + "\n")?;
+    println!("Wrote test vectors to {}", path);
+    Ok(())
+}
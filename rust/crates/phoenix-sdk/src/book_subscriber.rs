@@ -0,0 +1,469 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use phoenix::quantities::WrapperU64;
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk_core::orderbook::Orderbook;
+use phoenix_sdk_core::sdk_client_core::{MarketState, PhoenixOrder};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::broadcast;
+
+use crate::error::PhoenixSdkError;
+use crate::health::{
+    ComponentDetails, ComponentHealth, HealthProvider, HealthStatus, HealthThresholds,
+};
+use crate::rate_limiter::{RateLimiter, RpcCategory};
+
+/// A change in the total size resting at one price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelChange {
+    pub price_in_ticks: u64,
+    pub old_base_lots: u64,
+    pub new_base_lots: u64,
+}
+
+/// Per-level changes between two states of a book, each side ordered best price first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookDiff {
+    /// Set when the diff is against an empty book rather than the previous update, e.g. for the
+    /// first update after (re)subscribing. Every level of the new book is then reported.
+    pub is_full: bool,
+    pub bids: Vec<LevelChange>,
+    pub asks: Vec<LevelChange>,
+}
+
+impl BookDiff {
+    /// Diffs `current` against `previous`, or against an empty book if there is none.
+    pub fn between(
+        previous: Option<&Orderbook<FIFOOrderId, PhoenixOrder>>,
+        current: &Orderbook<FIFOOrderId, PhoenixOrder>,
+    ) -> Self {
+        let mut bids = diff_levels(
+            &previous.map(|book| levels(&book.bids)).unwrap_or_default(),
+            &levels(&current.bids),
+        );
+        bids.reverse();
+        let asks = diff_levels(
+            &previous.map(|book| levels(&book.asks)).unwrap_or_default(),
+            &levels(&current.asks),
+        );
+        BookDiff {
+            is_full: previous.is_none(),
+            bids,
+            asks,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+}
+
+/// Aggregates one side of a book into total base lots per price.
+fn levels(orders: &BTreeMap<FIFOOrderId, PhoenixOrder>) -> BTreeMap<u64, u64> {
+    let mut levels = BTreeMap::new();
+    for (order_id, order) in orders.iter() {
+        *levels.entry(order_id.price_in_ticks.as_u64()).or_default() += order.num_base_lots;
+    }
+    levels
+}
+
+/// Returns the changed levels in ascending price order.
+fn diff_levels(previous: &BTreeMap<u64, u64>, current: &BTreeMap<u64, u64>) -> Vec<LevelChange> {
+    let mut prices = previous
+        .keys()
+        .chain(current.keys())
+        .copied()
+        .collect::<Vec<_>>();
+    prices.sort_unstable();
+    prices.dedup();
+    prices
+        .into_iter()
+        .filter_map(|price_in_ticks| {
+            let old_base_lots = previous.get(&price_in_ticks).copied().unwrap_or(0);
+            let new_base_lots = current.get(&price_in_ticks).copied().unwrap_or(0);
+            (old_base_lots != new_base_lots).then_some(LevelChange {
+                price_in_ticks,
+                old_base_lots,
+                new_base_lots,
+            })
+        })
+        .collect()
+}
+
+/// A new state of the market, with its changes since the previous update.
+#[derive(Clone)]
+pub struct BookUpdate {
+    pub market_state: Arc<MarketState>,
+    pub diff: BookDiff,
+    /// The context slot of the notification: the slot `market_state` was read at.
+    pub slot: u64,
+}
+
+impl BookUpdate {
+    /// Fails with `PhoenixSdkError::StaleData` if the update was read before `min_slot`, like
+    /// `SDKClient::get_market_state_with_min_slot` does for fetched state.
+    pub fn check_min_slot(&self, min_slot: u64) -> Result<()> {
+        if self.slot < min_slot {
+            return Err(PhoenixSdkError::StaleData {
+                got: self.slot,
+                required: min_slot,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Orders account updates into `BookUpdate`s: updates from slots older than the latest one seen are
+/// dropped, and the first update after a reset is diffed against an empty book.
+#[derive(Clone, Default)]
+struct BookTracker {
+    latest_slot: Option<u64>,
+    previous: Option<Arc<MarketState>>,
+}
+
+impl BookTracker {
+    fn apply(&mut self, slot: u64, market_state: MarketState) -> Option<BookUpdate> {
+        if self.latest_slot.is_some_and(|latest| slot < latest) {
+            return None;
+        }
+        self.latest_slot = Some(slot);
+        let diff = BookDiff::between(
+            self.previous.as_ref().map(|state| &state.orderbook),
+            &market_state.orderbook,
+        );
+        let market_state = Arc::new(market_state);
+        self.previous = Some(market_state.clone());
+        Some(BookUpdate {
+            market_state,
+            diff,
+            slot,
+        })
+    }
+
+    /// Makes the next update a full diff, since changes may have been missed.
+    fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// Maintains a live `MarketState` from websocket account notifications on the market account and
+/// broadcasts a `BookUpdate` for every change.
+///
+/// Notifications with no change to the book (e.g. only trader state changed) are still broadcast,
+/// with an empty diff. When the websocket disconnects, the subscriber reconnects after
+/// `reconnect_delay` and the first update after resubscribing is a full diff. If the connection
+/// fails, the reconnect goes to the next of the fallback URLs, if any were given.
+#[derive(Clone)]
+pub struct BookSubscriber {
+    ws_urls: Vec<String>,
+    current_url: usize,
+    market_key: Pubkey,
+    commitment: CommitmentConfig,
+    reconnect_delay: Duration,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    sender: broadcast::Sender<BookUpdate>,
+    tracker: BookTracker,
+    health: Arc<BookSubscriberHealth>,
+}
+
+impl BookSubscriber {
+    /// `capacity` is the number of updates a slow receiver may fall behind by before it lags.
+    pub fn new(ws_url: &str, market_key: Pubkey, capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            ws_urls: vec![ws_url.to_string()],
+            current_url: 0,
+            market_key,
+            commitment: CommitmentConfig::confirmed(),
+            reconnect_delay: Duration::from_secs(1),
+            rate_limiter: None,
+            sender,
+            tracker: BookTracker::default(),
+            health: Arc::new(BookSubscriberHealth::new(market_key)),
+        }
+    }
+
+    pub fn with_commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.commitment
+    }
+
+    pub fn with_reconnect_delay(mut self, reconnect_delay: Duration) -> Self {
+        self.reconnect_delay = reconnect_delay;
+        self
+    }
+
+    /// Each connection attempt waits for budget from `rate_limiter`, counted under the
+    /// `"book_subscriber"` subsystem, so reconnect storms are paced with the rest of the SDK.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Websocket endpoints to fail over to, in order, after the one given to `new`.
+    pub fn with_fallback_urls(mut self, ws_urls: &[String]) -> Self {
+        self.ws_urls.extend_from_slice(ws_urls);
+        self
+    }
+
+    pub fn market_key(&self) -> &Pubkey {
+        &self.market_key
+    }
+
+    /// The endpoint the subscriber is connected to, or will connect to next.
+    pub fn current_url(&self) -> &str {
+        &self.ws_urls[self.current_url]
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<BookUpdate> {
+        self.sender.subscribe()
+    }
+
+    /// When the subscriber last heard from its websocket, for health checks. Stays current after
+    /// the subscriber is moved into `run`.
+    pub fn health(&self) -> Arc<BookSubscriberHealth> {
+        self.health.clone()
+    }
+
+    /// Streams updates until every receiver has been dropped, reconnecting as needed.
+    pub async fn run(mut self) -> Result<()> {
+        loop {
+            // Errors are treated like disconnects: the subscription is retried on the next endpoint.
+            if self.run_subscription().await.is_err() {
+                self.current_url = (self.current_url + 1) % self.ws_urls.len();
+            }
+            if self.sender.receiver_count() == 0 {
+                return Ok(());
+            }
+            self.tracker.reset();
+            tokio::time::sleep(self.reconnect_delay).await;
+        }
+    }
+
+    async fn run_subscription(&mut self) -> Result<()> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter
+                .acquire("book_subscriber", RpcCategory::Subscribe)
+                .await;
+        }
+        let client = PubsubClient::new(&self.ws_urls[self.current_url]).await?;
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(self.commitment),
+            ..RpcAccountInfoConfig::default()
+        };
+        let (mut stream, unsubscribe) = client
+            .account_subscribe(&self.market_key, Some(config))
+            .await?;
+        while let Some(response) = stream.next().await {
+            let account = response
+                .value
+                .decode::<Account>()
+                .ok_or_else(|| anyhow!("Failed to decode market account"))?;
+            self.health.record_update(response.context.slot);
+            let market_state = MarketState::from_account_data(&account.data)?;
+            if let Some(update) = self.tracker.apply(response.context.slot, market_state) {
+                if self.sender.send(update).is_err() {
+                    break;
+                }
+            }
+        }
+        unsubscribe().await;
+        Ok(())
+    }
+}
+
+/// When a `BookSubscriber` last received a notification, see `BookSubscriber::health`.
+///
+/// Notifications only arrive when the market account changes, so a quiet market looks stale too.
+#[derive(Debug)]
+pub struct BookSubscriberHealth {
+    market_key: Pubkey,
+    created_at: Instant,
+    last_update: Mutex<Option<(u64, Instant)>>,
+}
+
+impl BookSubscriberHealth {
+    fn new(market_key: Pubkey) -> Self {
+        Self {
+            market_key,
+            created_at: Instant::now(),
+            last_update: Mutex::new(None),
+        }
+    }
+
+    fn record_update(&self, slot: u64) {
+        *self.last_update() = Some((slot, Instant::now()));
+    }
+
+    /// The slot of the last notification.
+    pub fn last_update_slot(&self) -> Option<u64> {
+        self.last_update().map(|(slot, _)| slot)
+    }
+
+    /// Time since the last notification, or since the subscriber was created if none arrived.
+    pub fn staleness(&self) -> Duration {
+        self.last_update()
+            .map_or(self.created_at, |(_, at)| at)
+            .elapsed()
+    }
+
+    fn last_update(&self) -> std::sync::MutexGuard<'_, Option<(u64, Instant)>> {
+        self.last_update
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Degraded once the subscriber has gone `max_book_staleness` without a notification.
+impl HealthProvider for BookSubscriberHealth {
+    fn health_check(&self, thresholds: &HealthThresholds) -> ComponentHealth {
+        let staleness = self.staleness();
+        ComponentHealth {
+            status: if staleness > thresholds.max_book_staleness {
+                HealthStatus::Degraded
+            } else {
+                HealthStatus::Healthy
+            },
+            details: ComponentDetails::BookSubscriber {
+                market: self.market_key,
+                last_update_slot: self.last_update_slot(),
+                staleness,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn market_state(bids: &[(u64, u64)], asks: &[(u64, u64)]) -> MarketState {
+        let mut orderbook = Orderbook {
+            raw_base_units_per_base_lot: 1.0,
+            quote_units_per_raw_base_unit_per_tick: 1.0,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        };
+        for (sequence_number, &(price_in_ticks, num_base_lots)) in bids.iter().enumerate() {
+            orderbook.bids.insert(
+                FIFOOrderId::new_from_untyped(price_in_ticks, !(sequence_number as u64)),
+                PhoenixOrder {
+                    num_base_lots,
+                    maker_id: Pubkey::default(),
+                },
+            );
+        }
+        for (sequence_number, &(price_in_ticks, num_base_lots)) in asks.iter().enumerate() {
+            orderbook.asks.insert(
+                FIFOOrderId::new_from_untyped(price_in_ticks, sequence_number as u64),
+                PhoenixOrder {
+                    num_base_lots,
+                    maker_id: Pubkey::default(),
+                },
+            );
+        }
+        MarketState {
+            orderbook,
+            traders: BTreeMap::new(),
+        }
+    }
+
+    fn change(price_in_ticks: u64, old_base_lots: u64, new_base_lots: u64) -> LevelChange {
+        LevelChange {
+            price_in_ticks,
+            old_base_lots,
+            new_base_lots,
+        }
+    }
+
+    #[test]
+    fn test_diffs_and_stale_slots() {
+        let mut tracker = BookTracker::default();
+        // Two orders at 99 are aggregated into one level
+        let first = tracker
+            .apply(10, market_state(&[(99, 1), (99, 2), (98, 5)], &[(101, 4)]))
+            .unwrap();
+        assert!(first.diff.is_full);
+        assert_eq!(first.diff.bids, vec![change(99, 0, 3), change(98, 0, 5)]);
+        assert_eq!(first.diff.asks, vec![change(101, 0, 4)]);
+
+        let second = tracker
+            .apply(12, market_state(&[(99, 3), (98, 5)], &[(101, 1), (102, 7)]))
+            .unwrap();
+        assert!(!second.diff.is_full);
+        assert!(second.diff.bids.is_empty());
+        assert_eq!(second.diff.asks, vec![change(101, 4, 1), change(102, 0, 7)]);
+
+        // An older slot arriving late is dropped; the same slot is not
+        assert!(tracker.apply(11, market_state(&[], &[])).is_none());
+        let same_slot = tracker
+            .apply(12, market_state(&[(98, 5)], &[(102, 7)]))
+            .unwrap();
+        assert_eq!(same_slot.diff.bids, vec![change(99, 3, 0)]);
+        assert_eq!(same_slot.diff.asks, vec![change(101, 1, 0)]);
+        assert!(same_slot.check_min_slot(12).is_ok());
+        let stale = same_slot.check_min_slot(13).unwrap_err();
+        assert_eq!(
+            stale.downcast_ref::<PhoenixSdkError>(),
+            Some(&PhoenixSdkError::StaleData {
+                got: 12,
+                required: 13
+            })
+        );
+
+        // After a reconnect, the next update reports the whole book
+        tracker.reset();
+        let resubscribed = tracker
+            .apply(13, market_state(&[(98, 5)], &[(102, 7)]))
+            .unwrap();
+        assert!(resubscribed.diff.is_full);
+        assert_eq!(resubscribed.diff.bids, vec![change(98, 0, 5)]);
+        assert_eq!(resubscribed.market_state.orderbook.asks.len(), 1);
+    }
+    #[test]
+    fn test_health() {
+        let market_key = Pubkey::new_unique();
+        let subscriber = BookSubscriber::new("ws://localhost:8900", market_key, 4);
+        let health = subscriber.health();
+        let fresh = HealthThresholds {
+            max_book_staleness: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let stale = HealthThresholds {
+            max_book_staleness: Duration::ZERO,
+            ..Default::default()
+        };
+        assert_eq!(health.last_update_slot(), None);
+        assert_eq!(health.health_check(&fresh).status, HealthStatus::Healthy);
+
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(health.health_check(&stale).status, HealthStatus::Degraded);
+        // Updates are seen through handles taken before the subscriber runs
+        subscriber.health.record_update(42);
+        let report = health.health_check(&fresh);
+        assert_eq!(report.status, HealthStatus::Healthy);
+        assert!(matches!(
+            report.details,
+            ComponentDetails::BookSubscriber {
+                market,
+                last_update_slot: Some(42),
+                ..
+            } if market == market_key
+        ));
+    }
+}
@@ -0,0 +1,268 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use serde_json::{json, Value};
+use solana_client::client_error::reqwest;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+
+/// The block engine accepts at most this many transactions in one bundle.
+pub const MAX_BUNDLE_TRANSACTIONS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleConfig {
+    /// Base URL of the block engine, e.g. `https://mainnet.block-engine.jito.wtf`.
+    pub block_engine_url: String,
+    /// Account the tip is transferred to. Must be one of the block engine's tip accounts.
+    pub tip_account: Pubkey,
+    /// Resend the bundle's transactions through RPC if the bundle does not land.
+    pub fallback_to_rpc: bool,
+    pub status_poll_interval: Duration,
+    /// How long to wait for the bundle to land before giving up on it.
+    pub landing_timeout: Duration,
+}
+
+impl BundleConfig {
+    pub fn new(block_engine_url: &str, tip_account: Pubkey) -> Self {
+        Self {
+            block_engine_url: block_engine_url.trim_end_matches('/').to_string(),
+            tip_account,
+            fallback_to_rpc: true,
+            status_poll_interval: Duration::from_millis(500),
+            landing_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Status of a recently submitted bundle, as reported by `getInflightBundleStatuses`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleStatus {
+    Pending,
+    Landed {
+        slot: u64,
+    },
+    Failed,
+    /// The block engine does not know the bundle, e.g. it was rejected or is too old.
+    Invalid,
+}
+
+impl BundleStatus {
+    /// Reads the status of the first bundle in a `getInflightBundleStatuses` response.
+    pub fn from_response(response: &Value) -> Result<Self> {
+        if let Some(error) = response.get("error") {
+            return Err(anyhow!("Block engine error: {}", error));
+        }
+        let Some(entry) = response["result"]["value"].get(0) else {
+            return Ok(BundleStatus::Invalid);
+        };
+        match entry["status"].as_str() {
+            Some("Pending") => Ok(BundleStatus::Pending),
+            Some("Landed") => Ok(BundleStatus::Landed {
+                slot: entry["landed_slot"].as_u64().unwrap_or_default(),
+            }),
+            Some("Failed") => Ok(BundleStatus::Failed),
+            Some("Invalid") => Ok(BundleStatus::Invalid),
+            status => Err(anyhow!("Unknown bundle status: {:?}", status)),
+        }
+    }
+
+    pub fn is_final(&self) -> bool {
+        !matches!(self, BundleStatus::Pending)
+    }
+}
+
+/// Submits transactions as Jito bundles through a block engine's JSON-RPC API.
+pub struct BundleSender {
+    pub config: BundleConfig,
+    http: reqwest::Client,
+}
+
+impl BundleSender {
+    pub fn new(config: BundleConfig) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Signs one transaction per instruction list with `payer`, appending a transfer of
+    /// `tip_lamports` to the tip account in the last transaction.
+    pub fn build_transactions(
+        &self,
+        txs: Vec<Vec<Instruction>>,
+        tip_lamports: u64,
+        payer: &Keypair,
+        recent_blockhash: Hash,
+    ) -> Result<Vec<Transaction>> {
+        if txs.is_empty() || txs.len() > MAX_BUNDLE_TRANSACTIONS {
+            return Err(anyhow!(
+                "A bundle must contain between 1 and {} transactions, got {}",
+                MAX_BUNDLE_TRANSACTIONS,
+                txs.len()
+            ));
+        }
+        let last = txs.len() - 1;
+        Ok(txs
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut ixs)| {
+                if i == last {
+                    ixs.push(system_instruction::transfer(
+                        &payer.pubkey(),
+                        &self.config.tip_account,
+                        tip_lamports,
+                    ));
+                }
+                Transaction::new_signed_with_payer(
+                    &ixs,
+                    Some(&payer.pubkey()),
+                    &[payer],
+                    recent_blockhash,
+                )
+            })
+            .collect())
+    }
+
+    /// Submits the signed transactions as one bundle and returns its id.
+    pub async fn send_bundle(&self, transactions: &[Transaction]) -> Result<String> {
+        let encoded = transactions
+            .iter()
+            .map(|tx| {
+                bincode::serialize(tx)
+                    .map(|bytes| base64::engine::general_purpose::STANDARD.encode(bytes))
+                    .map_err(|e| anyhow!("Failed to serialize transaction: {}", e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let response = self
+            .request("sendBundle", json!([encoded, { "encoding": "base64" }]))
+            .await?;
+        response["result"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow!("Bundle rejected: {}", response))
+    }
+
+    pub async fn get_bundle_status(&self, bundle_id: &str) -> Result<BundleStatus> {
+        let response = self
+            .request("getInflightBundleStatuses", json!([[bundle_id]]))
+            .await?;
+        BundleStatus::from_response(&response)
+    }
+
+    /// Polls the bundle's status until it is final or `landing_timeout` passes, in which case the
+    /// last status seen is returned.
+    pub async fn wait_for_bundle(&self, bundle_id: &str) -> Result<BundleStatus> {
+        let deadline = Instant::now() + self.config.landing_timeout;
+        let mut status = BundleStatus::Pending;
+        while Instant::now() < deadline {
+            // The block engine may not report a bundle until shortly after it is sent
+            if let Ok(latest) = self.get_bundle_status(bundle_id).await {
+                status = latest;
+                if status.is_final() && status != BundleStatus::Invalid {
+                    return Ok(status);
+                }
+            }
+            tokio::time::sleep(self.config.status_poll_interval).await;
+        }
+        Ok(status)
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let response = self
+            .http
+            .post(format!("{}/api/v1/bundles", self.config.block_engine_url))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to reach block engine: {}", e))?;
+        let response: Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Invalid block engine response: {}", e))?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow!("Block engine error on {}: {}", method, error));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_transactions_tips_last() {
+        let tip_account = Pubkey::new_unique();
+        let sender = BundleSender::new(BundleConfig::new("http://localhost:1", tip_account));
+        let payer = Keypair::new();
+        let ix = |_| system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+
+        let txs = sender
+            .build_transactions(
+                vec![vec![ix(0)], vec![ix(1)]],
+                10_000,
+                &payer,
+                Hash::default(),
+            )
+            .unwrap();
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].message.instructions.len(), 1);
+        assert_eq!(txs[1].message.instructions.len(), 2);
+        assert!(txs[1].message.account_keys.contains(&tip_account));
+        assert!(!txs[0].message.account_keys.contains(&tip_account));
+        assert!(txs.iter().all(|tx| tx.is_signed()));
+
+        assert!(sender
+            .build_transactions(vec![], 10_000, &payer, Hash::default())
+            .is_err());
+        assert!(sender
+            .build_transactions(vec![vec![ix(0)]; 6], 10_000, &payer, Hash::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_status_from_response() {
+        let response = |status: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "result": {
+                    "context": { "slot": 280999028 },
+                    "value": [{ "bundle_id": "abc", "status": status, "landed_slot": 280999025 }]
+                },
+                "id": 1
+            })
+        };
+        assert_eq!(
+            BundleStatus::from_response(&response("Landed")).unwrap(),
+            BundleStatus::Landed { slot: 280999025 }
+        );
+        assert_eq!(
+            BundleStatus::from_response(&response("Pending")).unwrap(),
+            BundleStatus::Pending
+        );
+        assert_eq!(
+            BundleStatus::from_response(&response("Failed")).unwrap(),
+            BundleStatus::Failed
+        );
+        assert!(BundleStatus::from_response(&response("Unknown")).is_err());
+
+        let empty = json!({ "result": { "context": { "slot": 1 }, "value": [] } });
+        assert_eq!(
+            BundleStatus::from_response(&empty).unwrap(),
+            BundleStatus::Invalid
+        );
+        let error = json!({ "error": { "code": -32602, "message": "bad params" } });
+        assert!(BundleStatus::from_response(&error).is_err());
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use phoenix_sdk_core::market_event::{Fill, MarketEventDetails, PhoenixEvent};
+use phoenix_sdk_core::sdk_client_core::MarketMetadata;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::{mpsc, watch};
+
+use crate::sdk_market_event::SDKMarketEvent;
+
+/// An OHLCV bar of one market's fills. Prices are in quote units per raw base unit and volumes
+/// in raw base units, except `quote_volume`.
+///
+/// Serializes to JSON as
+///
+/// ```json
+/// {"market":"<base58>","interval":60,"time":1700000040,"open":25.1,"high":25.3,"low":25.0,
+///  "close":25.2,"volume":12.5,"quote_volume":314.2,"buy_volume":8.0,"sell_volume":4.5,"trades":4}
+/// ```
+///
+/// where `time` is the start of the bar in Unix seconds and `interval` its length in seconds.
+/// `time`, `open`, `high`, `low` and `close` are a lightweight-charts candlestick point as is,
+/// and `time` with `volume` a histogram point.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    #[serde(with = "pubkey_string")]
+    pub market: Pubkey,
+    #[serde(rename = "interval")]
+    pub interval_seconds: u64,
+    #[serde(rename = "time")]
+    pub start_time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub quote_volume: f64,
+    /// Volume of fills where the taker bought.
+    pub buy_volume: f64,
+    /// Volume of fills where the taker sold.
+    pub sell_volume: f64,
+    #[serde(rename = "trades")]
+    pub trade_count: u64,
+}
+
+impl Candle {
+    /// A bar without fills, flat at `price`, for intervals in which nothing traded.
+    pub fn empty(market: Pubkey, interval_seconds: u64, start_time: i64, price: f64) -> Self {
+        Self {
+            market,
+            interval_seconds,
+            start_time,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: 0.0,
+            quote_volume: 0.0,
+            buy_volume: 0.0,
+            sell_volume: 0.0,
+            trade_count: 0,
+        }
+    }
+
+    fn add_fill(&mut self, price: f64, size: f64, trade_direction: i8) {
+        if self.trade_count == 0 {
+            self.open = price;
+            self.high = price;
+            self.low = price;
+        }
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += size;
+        self.quote_volume += price * size;
+        if trade_direction > 0 {
+            self.buy_volume += size;
+        } else {
+            self.sell_volume += size;
+        }
+        self.trade_count += 1;
+    }
+}
+
+pub(crate) mod pubkey_string {
+    use std::str::FromStr;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use solana_sdk::pubkey::Pubkey;
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(pubkey)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let pubkey = String::deserialize(deserializer)?;
+        Pubkey::from_str(&pubkey).map_err(D::Error::custom)
+    }
+}
+
+struct MarketCandles {
+    metadata: MarketMetadata,
+    current: Option<Candle>,
+    /// The start of the first bar that has not been closed yet.
+    next_start: Option<i64>,
+    last_close: Option<f64>,
+    sender: watch::Sender<Option<Candle>>,
+}
+
+/// Builds candles from fills and pushes them to subscribers: the in-progress candle of each
+/// market over a watch channel after every fill, and closed candles over an mpsc channel.
+///
+/// A bar is closed by the first fill of a later interval, or by `on_time` once its interval
+/// has passed, so a timer should call `on_time` for bars to close while a market is quiet. With
+/// `with_empty_candles`, the intervals without fills between two bars are closed as flat
+/// candles at the last price. Bars are only closed after a market's first fill.
+///
+/// Fills are placed by their block time, and fills without one are skipped, see
+/// `estimate_missing_timestamps`. Fills older than the in-progress bar arrive too late to be
+/// included and are skipped too. Closed bars are not revised on rollbacks.
+pub struct CandleAggregator {
+    interval_seconds: u64,
+    emit_empty_candles: bool,
+    markets: BTreeMap<Pubkey, MarketCandles>,
+    closed: mpsc::UnboundedSender<Candle>,
+}
+
+impl CandleAggregator {
+    /// Returns an aggregator of `interval` bars, rounded down to whole seconds and at least one,
+    /// and the receiver of its closed candles.
+    pub fn new(interval: Duration) -> (Self, mpsc::UnboundedReceiver<Candle>) {
+        let (closed, receiver) = mpsc::unbounded_channel();
+        let aggregator = Self {
+            interval_seconds: interval.as_secs().max(1),
+            emit_empty_candles: false,
+            markets: BTreeMap::new(),
+            closed,
+        };
+        (aggregator, receiver)
+    }
+
+    /// Aggregates the fills of `market`. Fills of other markets are ignored.
+    pub fn with_market(mut self, market: Pubkey, metadata: MarketMetadata) -> Self {
+        let (sender, _) = watch::channel(None);
+        self.markets.insert(
+            market,
+            MarketCandles {
+                metadata,
+                current: None,
+                next_start: None,
+                last_close: None,
+                sender,
+            },
+        );
+        self
+    }
+
+    /// Closes intervals without fills as flat candles instead of skipping them.
+    pub fn with_empty_candles(mut self, emit_empty_candles: bool) -> Self {
+        self.emit_empty_candles = emit_empty_candles;
+        self
+    }
+
+    pub fn interval_seconds(&self) -> u64 {
+        self.interval_seconds
+    }
+
+    /// The in-progress candle of `market`, `None` until its first fill and between a bar
+    /// closing and the next fill. `None` if the market was not added.
+    pub fn subscribe(&self, market: &Pubkey) -> Option<watch::Receiver<Option<Candle>>> {
+        Some(self.markets.get(market)?.sender.subscribe())
+    }
+
+    /// The start of the interval containing `unix_seconds`.
+    pub fn bar_start(&self, unix_seconds: i64) -> i64 {
+        unix_seconds - unix_seconds.rem_euclid(self.interval_seconds as i64)
+    }
+
+    pub fn on_event(&mut self, event: &PhoenixEvent) {
+        let MarketEventDetails::Fill(fill) = &event.details else {
+            return;
+        };
+        if !event.time().is_known() {
+            return;
+        }
+        let start = self.bar_start(event.timestamp);
+        self.close_bars_before(&event.market, start);
+        let interval_seconds = self.interval_seconds;
+        let Some(candles) = self.markets.get_mut(&event.market) else {
+            return;
+        };
+        if candles
+            .next_start
+            .is_some_and(|next_start| start < next_start)
+        {
+            return;
+        }
+        let candle = candles
+            .current
+            .get_or_insert_with(|| Candle::empty(event.market, interval_seconds, start, 0.0));
+        add_fill(candle, &candles.metadata, fill);
+        candles.next_start = Some(start);
+        candles.sender.send_replace(Some(*candle));
+    }
+
+    /// Closes the bars of every market whose interval ended by `unix_seconds`.
+    pub fn on_time(&mut self, unix_seconds: i64) {
+        let start = self.bar_start(unix_seconds);
+        let markets = self.markets.keys().copied().collect::<Vec<_>>();
+        for market in markets {
+            self.close_bars_before(&market, start);
+        }
+    }
+
+    /// Aggregates the fills of event batches, e.g. from an `EventRouter` subscription, until the
+    /// sender is dropped.
+    pub async fn run(&mut self, mut receiver: mpsc::Receiver<Vec<SDKMarketEvent>>) {
+        while let Some(batch) = receiver.recv().await {
+            for event in batch {
+                if let SDKMarketEvent::PhoenixEvent { event } = event {
+                    self.on_event(&event);
+                }
+            }
+        }
+    }
+
+    fn close_bars_before(&mut self, market: &Pubkey, start: i64) {
+        let interval_seconds = self.interval_seconds as i64;
+        let Some(candles) = self.markets.get_mut(market) else {
+            return;
+        };
+        if let Some(current) = candles.current.filter(|current| current.start_time < start) {
+            // A closed receiver only means nobody wants closed candles
+            self.closed.send(current).ok();
+            candles.current = None;
+            candles.next_start = Some(current.start_time + interval_seconds);
+            candles.last_close = Some(current.close);
+            candles.sender.send_replace(None);
+        }
+        let (Some(mut next_start), Some(last_close)) = (candles.next_start, candles.last_close)
+        else {
+            return;
+        };
+        if candles.current.is_some() || next_start >= start {
+            return;
+        }
+        while next_start < start {
+            if self.emit_empty_candles {
+                self.closed
+                    .send(Candle::empty(
+                        *market,
+                        interval_seconds as u64,
+                        next_start,
+                        last_close,
+                    ))
+                    .ok();
+            }
+            next_start += interval_seconds;
+        }
+        candles.next_start = Some(next_start);
+    }
+}
+
+fn add_fill(candle: &mut Candle, metadata: &MarketMetadata, fill: &Fill) {
+    let price = metadata.ticks_to_float_price(fill.price_in_ticks);
+    let size = fill.base_lots_filled as f64 * metadata.raw_base_units_per_base_lot_as_float();
+    candle.add_fill(price, size, fill.trade_direction());
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix::state::enums::Side;
+
+    use super::*;
+    use phoenix_sdk_core::test_utils::event;
+
+    fn fill_event(
+        market: Pubkey,
+        timestamp: i64,
+        price_in_ticks: u64,
+        taker_side: Side,
+    ) -> PhoenixEvent {
+        // Makers' ask sequence numbers have the top bit clear, bids have it set
+        let order_sequence_number = match taker_side {
+            Side::Bid => 1,
+            Side::Ask => !1,
+        };
+        PhoenixEvent {
+            sequence_number: 1,
+            slot: 1,
+            timestamp,
+            ..event(
+                market,
+                MarketEventDetails::Fill(Fill::new(
+                    order_sequence_number,
+                    Pubkey::new_unique(),
+                    Pubkey::new_unique(),
+                    price_in_ticks,
+                    1000,
+                    0,
+                )),
+            )
+        }
+    }
+
+    #[test]
+    fn test_current_and_closed_candles() {
+        let market = Pubkey::new_unique();
+        let metadata = crate::paper_trading::test_utils::test_market_metadata();
+        let (aggregator, mut closed) = CandleAggregator::new(Duration::from_secs(60));
+        let mut aggregator = aggregator
+            .with_market(market, metadata)
+            .with_empty_candles(true);
+        let mut current = aggregator.subscribe(&market).unwrap();
+
+        aggregator.on_event(&fill_event(market, 1_000_000_020, 100, Side::Bid));
+        aggregator.on_event(&fill_event(market, 1_000_000_030, 120, Side::Ask));
+        aggregator.on_event(&fill_event(market, 1_000_000_059, 90, Side::Bid));
+        // Fills without a block time and of other markets are skipped
+        aggregator.on_event(&fill_event(market, 0, 500, Side::Bid));
+        aggregator.on_event(&fill_event(
+            Pubkey::new_unique(),
+            1_000_000_059,
+            500,
+            Side::Bid,
+        ));
+        assert!(current.has_changed().unwrap());
+        let in_progress = current.borrow_and_update().unwrap();
+        assert_eq!(in_progress.start_time, 1_000_000_020);
+        assert_eq!(
+            (
+                in_progress.open,
+                in_progress.high,
+                in_progress.low,
+                in_progress.close
+            ),
+            (0.1, 0.12, 0.09, 0.09)
+        );
+        assert_eq!(
+            (in_progress.buy_volume, in_progress.sell_volume),
+            (2.0, 1.0)
+        );
+        assert_eq!(in_progress.trade_count, 3);
+        assert!(closed.try_recv().is_err());
+
+        // A fill two intervals later closes the bar and the empty one in between
+        aggregator.on_event(&fill_event(market, 1_000_000_140, 110, Side::Bid));
+        assert_eq!(closed.try_recv().unwrap(), in_progress);
+        let gap = closed.try_recv().unwrap();
+        assert_eq!(gap, Candle::empty(market, 60, 1_000_000_080, 0.09));
+        assert!(closed.try_recv().is_err());
+        assert_eq!(current.borrow().unwrap().start_time, 1_000_000_140);
+
+        // Late fills are skipped, and time alone closes bars
+        aggregator.on_event(&fill_event(market, 1_000_000_079, 1, Side::Bid));
+        aggregator.on_time(1_000_000_260);
+        let last = closed.try_recv().unwrap();
+        assert_eq!(
+            (last.start_time, last.trade_count, last.close),
+            (1_000_000_140, 1, 0.11)
+        );
+        assert_eq!(closed.try_recv().unwrap().start_time, 1_000_000_200);
+        assert!(closed.try_recv().is_err());
+        assert_eq!(*current.borrow(), None);
+        aggregator.on_time(1_000_000_260);
+        assert!(closed.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_candle_json() {
+        let market = Pubkey::new_unique();
+        let mut candle = Candle::empty(market, 60, 1_700_000_040, 0.0);
+        candle.add_fill(25.0, 2.0, 1);
+        let json = serde_json::to_value(candle).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "market": market.to_string(),
+                "interval": 60,
+                "time": 1_700_000_040,
+                "open": 25.0,
+                "high": 25.0,
+                "low": 25.0,
+                "close": 25.0,
+                "volume": 2.0,
+                "quote_volume": 50.0,
+                "buy_volume": 2.0,
+                "sell_volume": 0.0,
+                "trades": 1,
+            })
+        );
+        assert_eq!(serde_json::from_value::<Candle>(json).unwrap(), candle);
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use phoenix_sdk_core::market_event::PhoenixEvent;
+use serde::Serialize;
+use solana_sdk::signature::Signature;
+use tokio::sync::mpsc;
+
+use crate::health::{
+    serialize_display, ComponentDetails, ComponentHealth, HealthProvider, HealthStatus,
+    HealthThresholds,
+};
+use crate::latency::ReceiveStamp;
+use crate::sdk_market_event::SDKMarketEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockMonitorConfig {
+    /// Offsets kept for the estimate.
+    pub window: usize,
+    /// The percentile (0 to 100) of the offsets taken as the skew. A low one picks the events
+    /// that were delivered fastest, whose offset is mostly skew rather than latency.
+    pub skew_percentile: f64,
+    /// Offsets needed before there is an estimate.
+    pub min_samples: usize,
+    /// How far the estimate may move with one sample before it counts as a jump.
+    pub jump_threshold: Duration,
+}
+
+impl Default for ClockMonitorConfig {
+    fn default() -> Self {
+        Self {
+            window: 64,
+            skew_percentile: 10.0,
+            min_samples: 8,
+            jump_threshold: Duration::from_secs(2),
+        }
+    }
+}
+
+/// A sudden change of the estimated skew, usually because the RPC node streaming the events
+/// changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SkewJump {
+    pub previous_skew_millis: i64,
+    pub skew_millis: i64,
+    /// The transaction whose event moved the estimate.
+    pub slot: u64,
+    #[serde(serialize_with = "serialize_display")]
+    pub signature: Signature,
+}
+
+/// Point-in-time copy of a `ClockMonitor`'s estimates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClockSnapshot {
+    /// See `ClockMonitor::skew_millis`.
+    pub skew_millis: Option<i64>,
+    /// Offsets recorded since the monitor was created.
+    pub samples: u64,
+    pub skew_jumps: u64,
+    pub last_jump: Option<SkewJump>,
+    /// Seconds the latest header timestamp checked with `record_block_time` was ahead of the
+    /// slot's block time.
+    pub block_time_offset_secs: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+struct ClockState {
+    offsets_millis: VecDeque<i64>,
+    /// Header timestamps of recent slots, to check against their block time.
+    header_times: VecDeque<(u64, i64)>,
+    skew_millis: Option<i64>,
+    samples: u64,
+    skew_jumps: u64,
+    last_jump: Option<SkewJump>,
+    block_time_offset_secs: Option<i64>,
+}
+
+/// Estimates the skew between the timestamps in event headers and the local clock.
+///
+/// Each transaction's first event is an offset sample: the local time it was received less its
+/// header timestamp. An offset is latency plus skew, and header timestamps are whole seconds, so
+/// the estimate is a low percentile of the recent offsets rather than their average. Use
+/// `normalize_timestamp` to read event times on the local clock, e.g. for age alerts. Attach
+/// the monitor to a `LatencyTracker` to do the same for the chain-to-receive leg.
+///
+/// When one sample moves the estimate by more than `jump_threshold`, the jump is counted, kept
+/// as `last_jump` and sent to the jump channel if one is set. With the default percentile, a
+/// clock that moves ahead shows once most of the window has been replaced, and one that falls
+/// behind after a few samples.
+#[derive(Debug, Default)]
+pub struct ClockMonitor {
+    config: ClockMonitorConfig,
+    jumps: Option<mpsc::UnboundedSender<SkewJump>>,
+    state: Mutex<ClockState>,
+}
+
+impl ClockMonitor {
+    pub fn new(config: ClockMonitorConfig) -> Self {
+        Self {
+            config,
+            jumps: None,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Sends each skew jump to `jumps`.
+    pub fn with_jump_sender(mut self, jumps: mpsc::UnboundedSender<SkewJump>) -> Self {
+        self.jumps = Some(jumps);
+        self
+    }
+
+    pub fn config(&self) -> &ClockMonitorConfig {
+        &self.config
+    }
+
+    /// Records the events of a batch received at `received`.
+    pub fn on_events(&self, events: &[SDKMarketEvent], received: ReceiveStamp) {
+        for message in events {
+            if let SDKMarketEvent::PhoenixEvent { event } = message {
+                self.on_event(event, received.system_time);
+            }
+        }
+    }
+
+    /// Records an event received at `received`. Each transaction counts once, on its first
+    /// event.
+    pub fn on_event(&self, event: &PhoenixEvent, received: SystemTime) {
+        if event.event_index != 0 || event.timestamp <= 0 {
+            return;
+        }
+        let mut state = self.state();
+        let window = self.config.window.max(1);
+        let offset_millis = system_time_millis(received) - event.timestamp * 1000;
+        if state.offsets_millis.len() == window {
+            state.offsets_millis.pop_front();
+        }
+        state.offsets_millis.push_back(offset_millis);
+        state.samples += 1;
+        if state.header_times.len() == window {
+            state.header_times.pop_front();
+        }
+        state.header_times.push_back((event.slot, event.timestamp));
+
+        if state.offsets_millis.len() < self.config.min_samples.max(1) {
+            return;
+        }
+        let mut sorted = state.offsets_millis.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        let rank = (self.config.skew_percentile.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64)
+            .ceil() as usize;
+        let skew_millis = sorted[rank.clamp(1, sorted.len()) - 1];
+        let previous = state.skew_millis.replace(skew_millis);
+        let Some(previous_skew_millis) = previous else {
+            return;
+        };
+        if previous_skew_millis.abs_diff(skew_millis) as u128
+            <= self.config.jump_threshold.as_millis()
+        {
+            return;
+        }
+        let jump = SkewJump {
+            previous_skew_millis,
+            skew_millis,
+            slot: event.slot,
+            signature: event.signature,
+        };
+        state.skew_jumps += 1;
+        state.last_jump = Some(jump);
+        if let Some(jumps) = &self.jumps {
+            // The receiver going away doesn't stop the monitor
+            let _ = jumps.send(jump);
+        }
+    }
+
+    /// Checks the header timestamp of `slot`, if an event of it was recorded recently, against
+    /// the slot's block time, e.g. from `RpcClient::get_block_time`. Returns how many seconds
+    /// the header was ahead.
+    pub fn record_block_time(&self, slot: u64, block_time: i64) -> Option<i64> {
+        let mut state = self.state();
+        let (_, timestamp) = state
+            .header_times
+            .iter()
+            .rev()
+            .find(|(header_slot, _)| *header_slot == slot)
+            .copied()?;
+        let offset_secs = timestamp - block_time;
+        state.block_time_offset_secs = Some(offset_secs);
+        Some(offset_secs)
+    }
+
+    /// How far the local clock is estimated to be ahead of the event timestamps, in
+    /// milliseconds, or `None` until `min_samples` offsets are recorded. Includes the latency of
+    /// the fastest deliveries.
+    pub fn skew_millis(&self) -> Option<i64> {
+        self.state().skew_millis
+    }
+
+    /// The event's header timestamp on the local clock. Without an estimate yet, the header
+    /// timestamp as is.
+    pub fn normalize_timestamp(&self, event: &PhoenixEvent) -> SystemTime {
+        let timestamp = UNIX_EPOCH + Duration::from_secs(event.timestamp.max(0) as u64);
+        let skew_millis = self.skew_millis().unwrap_or_default();
+        let skew = Duration::from_millis(skew_millis.unsigned_abs());
+        if skew_millis >= 0 {
+            timestamp + skew
+        } else {
+            timestamp.checked_sub(skew).unwrap_or(UNIX_EPOCH)
+        }
+    }
+
+    pub fn snapshot(&self) -> ClockSnapshot {
+        let state = self.state();
+        ClockSnapshot {
+            skew_millis: state.skew_millis,
+            samples: state.samples,
+            skew_jumps: state.skew_jumps,
+            last_jump: state.last_jump,
+            block_time_offset_secs: state.block_time_offset_secs,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, ClockState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Degraded while the estimated skew is larger than `max_clock_skew`.
+impl HealthProvider for ClockMonitor {
+    fn health_check(&self, thresholds: &HealthThresholds) -> ComponentHealth {
+        let snapshot = self.snapshot();
+        let skewed = snapshot.skew_millis.is_some_and(|skew_millis| {
+            skew_millis.unsigned_abs() as u128 > thresholds.max_clock_skew.as_millis()
+        });
+        ComponentHealth {
+            status: if skewed {
+                HealthStatus::Degraded
+            } else {
+                HealthStatus::Healthy
+            },
+            details: ComponentDetails::Clock {
+                skew_millis: snapshot.skew_millis,
+                skew_jumps: snapshot.skew_jumps,
+                last_jump: snapshot.last_jump,
+            },
+        }
+    }
+}
+
+fn system_time_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use phoenix_sdk_core::market_event::MarketEventDetails;
+    use phoenix_sdk_core::test_utils::event;
+    use solana_sdk::pubkey::Pubkey;
+
+    const BLOCK_TIME: i64 = 1_700_000_000;
+
+    fn stamped(slot: u64, timestamp: i64) -> PhoenixEvent {
+        PhoenixEvent {
+            sequence_number: slot,
+            slot,
+            timestamp,
+            signature: Signature::new_unique(),
+            ..event(Pubkey::default(), MarketEventDetails::Fee(0))
+        }
+    }
+
+    /// Receives an event stamped `BLOCK_TIME + i` at `offset_millis` after that.
+    fn receive(monitor: &ClockMonitor, i: u64, offset_millis: i64) {
+        let timestamp = BLOCK_TIME + i as i64;
+        let received =
+            UNIX_EPOCH + Duration::from_millis((timestamp * 1000 + offset_millis) as u64);
+        monitor.on_event(&stamped(i, timestamp), received);
+    }
+
+    #[test]
+    fn test_skew_estimate() {
+        let monitor = ClockMonitor::new(ClockMonitorConfig {
+            window: 10,
+            min_samples: 4,
+            ..Default::default()
+        });
+        for (i, offset_millis) in [4_300, 4_100, 5_200].into_iter().enumerate() {
+            receive(&monitor, i as u64, offset_millis);
+        }
+        assert_eq!(monitor.skew_millis(), None);
+        let late = stamped(3, BLOCK_TIME);
+        assert_eq!(
+            monitor.normalize_timestamp(&late),
+            UNIX_EPOCH + Duration::from_secs(BLOCK_TIME as u64)
+        );
+
+        // The fastest deliveries set the estimate, not the slow ones
+        for (i, offset_millis) in [4_200, 9_000, 4_400, 4_500].into_iter().enumerate() {
+            receive(&monitor, 3 + i as u64, offset_millis);
+        }
+        assert_eq!(monitor.skew_millis(), Some(4_100));
+        assert_eq!(
+            monitor.normalize_timestamp(&late),
+            UNIX_EPOCH + Duration::from_millis(BLOCK_TIME as u64 * 1000 + 4_100)
+        );
+
+        // Later events of a transaction are not samples
+        let mut second = stamped(10, BLOCK_TIME);
+        second.event_index = 1;
+        monitor.on_event(&second, UNIX_EPOCH);
+        assert_eq!(monitor.snapshot().samples, 7);
+
+        // A local clock behind the chain's
+        let behind = ClockMonitor::new(ClockMonitorConfig {
+            min_samples: 1,
+            ..Default::default()
+        });
+        receive(&behind, 0, -3_000);
+        assert_eq!(behind.skew_millis(), Some(-3_000));
+        assert_eq!(
+            behind.normalize_timestamp(&late),
+            UNIX_EPOCH + Duration::from_secs(BLOCK_TIME as u64 - 3)
+        );
+    }
+
+    #[test]
+    fn test_skew_jump() {
+        let (sender, mut jumps) = mpsc::unbounded_channel();
+        let monitor = ClockMonitor::new(ClockMonitorConfig {
+            window: 8,
+            min_samples: 4,
+            ..Default::default()
+        })
+        .with_jump_sender(sender);
+        for i in 0..8 {
+            receive(&monitor, i, 500 + i as i64 * 10);
+        }
+        assert_eq!(monitor.skew_millis(), Some(500));
+        assert!(jumps.try_recv().is_err());
+
+        // The stream switches to a node whose clock is 6 seconds behind
+        for i in 8..10 {
+            receive(&monitor, i, -5_500);
+        }
+        let jump = jumps.try_recv().unwrap();
+        assert_eq!(
+            (jump.previous_skew_millis, jump.skew_millis, jump.slot),
+            (500, -5_500, 8)
+        );
+        assert!(jumps.try_recv().is_err());
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.skew_jumps, 1);
+        assert_eq!(snapshot.last_jump, Some(jump));
+
+        let health = monitor.health_check(&HealthThresholds::default());
+        assert_eq!(health.status, HealthStatus::Degraded);
+        assert!(matches!(
+            health.details,
+            ComponentDetails::Clock {
+                skew_millis: Some(-5_500),
+                skew_jumps: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_block_time() {
+        let monitor = ClockMonitor::default();
+        receive(&monitor, 5, 800);
+        assert_eq!(monitor.record_block_time(5, BLOCK_TIME + 3), Some(2));
+        assert_eq!(monitor.snapshot().block_time_offset_secs, Some(2));
+        // Slots without a recorded event can't be checked
+        assert_eq!(monitor.record_block_time(6, BLOCK_TIME), None);
+        assert_eq!(
+            monitor.health_check(&HealthThresholds::default()).status,
+            HealthStatus::Healthy
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use solana_sdk::commitment_config::CommitmentConfig;
+
+/// The commitment `SDKClient` uses for each kind of RPC request. By default every kind uses the
+/// commitment of the client's `RpcClient`, `confirmed` for the clients the SDK creates.
+///
+/// A bot might confirm sends at `confirmed`, read the book at `processed` for freshness and
+/// backfill history at `finalized` so that it never has to be undone:
+///
+/// ```
+/// # use phoenix_sdk::commitment_policy::CommitmentPolicy;
+/// # use solana_sdk::commitment_config::CommitmentConfig;
+/// let policy = CommitmentPolicy {
+///     snapshot: CommitmentConfig::processed(),
+///     history: CommitmentConfig::finalized(),
+///     ..CommitmentPolicy::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitmentPolicy {
+    /// What a sent transaction is confirmed at, see `SDKClient::confirm_signature`.
+    pub send: CommitmentConfig,
+    /// Reads of market and account state, e.g. `get_market_state`.
+    pub snapshot: CommitmentConfig,
+    /// Fetches of transactions and of a market's signatures, e.g. to parse the events of a
+    /// transaction. RPC nodes serve these at `confirmed` at the earliest, so `processed` is
+    /// treated as `confirmed`.
+    pub history: CommitmentConfig,
+    /// Websocket subscriptions, e.g. a `BookSubscriber` from `SDKClient::book_subscriber`.
+    pub subscription: CommitmentConfig,
+}
+
+impl Default for CommitmentPolicy {
+    fn default() -> Self {
+        Self::uniform(CommitmentConfig::confirmed())
+    }
+}
+
+impl CommitmentPolicy {
+    /// A policy that uses `commitment` for everything.
+    pub fn uniform(commitment: CommitmentConfig) -> Self {
+        Self {
+            send: commitment,
+            snapshot: commitment,
+            history: commitment,
+            subscription: commitment,
+        }
+    }
+
+    /// The commitment for history requests, raised to `confirmed` if it is below it.
+    pub fn history_commitment(&self) -> CommitmentConfig {
+        if self.history.is_at_least_confirmed() {
+            self.history
+        } else {
+            CommitmentConfig::confirmed()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_history_is_at_least_confirmed() {
+        let policy = CommitmentPolicy::uniform(CommitmentConfig::processed());
+        assert_eq!(policy.snapshot, CommitmentConfig::processed());
+        assert_eq!(policy.history_commitment(), CommitmentConfig::confirmed());
+
+        let policy = CommitmentPolicy {
+            history: CommitmentConfig::finalized(),
+            ..Default::default()
+        };
+        assert_eq!(policy.history_commitment(), CommitmentConfig::finalized());
+    }
+}
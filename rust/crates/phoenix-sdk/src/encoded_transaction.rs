@@ -0,0 +1,430 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use solana_sdk::bs58;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiInstruction, UiMessage,
+    UiParsedInstruction,
+};
+
+/// Whether the transaction failed, in which case it has no events.
+pub fn is_failed(tx: &EncodedConfirmedTransactionWithStatusMeta) -> bool {
+    tx.transaction
+        .meta
+        .as_ref()
+        .is_some_and(|meta| meta.err.is_some())
+}
+
+/// The fee the transaction paid in lamports, and the compute units it consumed if the RPC node
+/// reports them. A failed transaction still pays its fee.
+pub fn fee_and_compute_units(
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<(u64, Option<u64>)> {
+    let meta = tx
+        .transaction
+        .meta
+        .as_ref()
+        .ok_or_else(|| anyhow!("Transaction has no status meta"))?;
+    let compute_units = match meta.compute_units_consumed {
+        OptionSerializer::Some(compute_units) => Some(compute_units),
+        _ => None,
+    };
+    Ok((meta.fee, compute_units))
+}
+
+/// The instructions a transaction invoked from its top-level instructions, as
+/// `(program_id, data)` in order. Phoenix logs its events this way, so the result can be passed
+/// to `SDKClientCore::parse_events_from_instruction_data`.
+///
+/// Works with every encoding `getTransaction` returns (base58, base64, json and jsonParsed), and
+/// with legacy and v0 messages. For v0 messages, program ids are resolved against the account
+/// keys extended with the addresses loaded from lookup tables.
+pub fn inner_instructions(
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+    let meta = tx
+        .transaction
+        .meta
+        .as_ref()
+        .ok_or_else(|| anyhow!("Transaction has no status meta"))?;
+    let loaded_addresses = match &meta.loaded_addresses {
+        OptionSerializer::Some(loaded) => loaded
+            .writable
+            .iter()
+            .chain(loaded.readonly.iter())
+            .map(|key| parse_pubkey(key))
+            .collect::<Result<Vec<_>>>()?,
+        _ => vec![],
+    };
+    let account_keys = match &tx.transaction.transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Raw(message) => message
+                .account_keys
+                .iter()
+                .map(|key| parse_pubkey(key))
+                .chain(loaded_addresses.into_iter().map(Ok))
+                .collect::<Result<Vec<_>>>()?,
+            // Parsed messages already list the loaded addresses
+            UiMessage::Parsed(message) => message
+                .account_keys
+                .iter()
+                .map(|key| parse_pubkey(&key.pubkey))
+                .collect::<Result<Vec<_>>>()?,
+        },
+        encoded => {
+            let transaction = encoded
+                .decode()
+                .ok_or_else(|| anyhow!("Failed to decode transaction"))?;
+            let mut keys = transaction.message.static_account_keys().to_vec();
+            keys.extend(loaded_addresses);
+            keys
+        }
+    };
+
+    let OptionSerializer::Some(inner_instructions) = &meta.inner_instructions else {
+        return Ok(vec![]);
+    };
+    let mut instructions = vec![];
+    for instruction in inner_instructions
+        .iter()
+        .flat_map(|inner| &inner.instructions)
+    {
+        match instruction {
+            UiInstruction::Compiled(compiled) => {
+                let program_id = account_keys
+                    .get(compiled.program_id_index as usize)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Program id index {} is out of bounds",
+                            compiled.program_id_index
+                        )
+                    })?;
+                instructions.push((*program_id, decode_data(&compiled.data)?));
+            }
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(partial)) => {
+                instructions.push((
+                    parse_pubkey(&partial.program_id)?,
+                    decode_data(&partial.data)?,
+                ));
+            }
+            // Fully parsed instructions belong to programs the RPC node knows, such as the
+            // token program, and are never Phoenix instructions
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(_)) => {}
+        }
+    }
+    Ok(instructions)
+}
+
+fn parse_pubkey(key: &str) -> Result<Pubkey> {
+    Pubkey::from_str(key).map_err(|e| anyhow!("Invalid account key {}: {}", key, e))
+}
+
+fn decode_data(data: &str) -> Result<Vec<u8>> {
+    bs58::decode(data)
+        .into_vec()
+        .map_err(|e| anyhow!("Invalid instruction data: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use borsh::BorshSerialize;
+    use ellipsis_client::transaction_utils;
+    use phoenix::program::{AuditLogHeader, FillEvent, PhoenixInstruction, PhoenixMarketEvent};
+    use phoenix_sdk_core::event_iter::AUDIT_LOG_HEADER_LEN;
+    use phoenix_sdk_core::sdk_client_core::{
+        phoenix_inner_instructions, RawPhoenixEvent, SDKClientCore,
+    };
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::message::v0::{self, LoadedAddresses, MessageAddressTableLookup};
+    use solana_sdk::message::{Message, MessageHeader, VersionedMessage};
+    use solana_sdk::signature::Signature;
+    use solana_sdk::transaction::VersionedTransaction;
+    use solana_transaction_status::{
+        InnerInstruction, InnerInstructions, TransactionStatusMeta, UiTransactionEncoding,
+        VersionedTransactionWithStatusMeta,
+    };
+
+    use super::*;
+
+    fn fill(maker_id: Pubkey, price_in_ticks: u64, base_lots_filled: u64) -> FillEvent {
+        FillEvent {
+            index: 0,
+            maker_id,
+            order_sequence_number: 3,
+            price_in_ticks,
+            base_lots_filled,
+            base_lots_remaining: 0,
+        }
+    }
+
+    /// The data of a Phoenix `Log` instruction carrying `fills`.
+    fn log_data(market: Pubkey, signer: Pubkey, fills: &[FillEvent]) -> Vec<u8> {
+        let mut data = vec![PhoenixInstruction::Log as u8];
+        PhoenixMarketEvent::Header(AuditLogHeader {
+            instruction: PhoenixInstruction::Swap as u8,
+            sequence_number: 7,
+            timestamp: 1_700_000_000,
+            slot: 250_000_000,
+            market,
+            signer,
+            total_events: fills.len() as u16,
+        })
+        .serialize(&mut data)
+        .unwrap();
+        for fill in fills {
+            PhoenixMarketEvent::Fill(*fill)
+                .serialize(&mut data)
+                .unwrap();
+        }
+        data
+    }
+
+    fn encode(
+        message: VersionedMessage,
+        meta: TransactionStatusMeta,
+        encoding: UiTransactionEncoding,
+    ) -> EncodedConfirmedTransactionWithStatusMeta {
+        let transaction = VersionedTransactionWithStatusMeta {
+            transaction: VersionedTransaction {
+                signatures: vec![Signature::default()],
+                message,
+            },
+            meta,
+        };
+        EncodedConfirmedTransactionWithStatusMeta {
+            slot: 250_000_000,
+            transaction: transaction.encode(encoding, Some(0), false).unwrap(),
+            block_time: Some(1_700_000_000),
+        }
+    }
+
+    #[test]
+    fn test_inner_instructions() {
+        let payer = Pubkey::new_unique();
+        let market = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+        let log = log_data(market, payer, &[fill(Pubkey::new_unique(), 1_000, 5)]);
+        let header = MessageHeader {
+            num_required_signatures: 1,
+            num_readonly_signed_accounts: 0,
+            num_readonly_unsigned_accounts: 1,
+        };
+        let static_keys = vec![payer, phoenix::id()];
+        let top_level = vec![CompiledInstruction::new_from_raw_parts(
+            1,
+            vec![0],
+            vec![0, 2],
+        )];
+        // Index 2 is the market and 3 the other program, both loaded from a lookup table
+        let meta = TransactionStatusMeta {
+            inner_instructions: Some(vec![InnerInstructions {
+                index: 0,
+                instructions: vec![
+                    InnerInstruction {
+                        instruction: CompiledInstruction::new_from_raw_parts(3, vec![9], vec![]),
+                        stack_height: Some(2),
+                    },
+                    InnerInstruction {
+                        instruction: CompiledInstruction::new_from_raw_parts(
+                            1,
+                            log.clone(),
+                            vec![],
+                        ),
+                        stack_height: Some(2),
+                    },
+                ],
+            }]),
+            loaded_addresses: LoadedAddresses {
+                writable: vec![market],
+                readonly: vec![other_program],
+            },
+            fee: 5_000,
+            compute_units_consumed: Some(21_000),
+            ..Default::default()
+        };
+        let v0_message = VersionedMessage::V0(v0::Message {
+            header,
+            account_keys: static_keys.clone(),
+            recent_blockhash: Hash::default(),
+            instructions: top_level.clone(),
+            address_table_lookups: vec![MessageAddressTableLookup {
+                account_key: Pubkey::new_unique(),
+                writable_indexes: vec![0],
+                readonly_indexes: vec![0],
+            }],
+        });
+        let expected = vec![(other_program, vec![9]), (phoenix::id(), log.clone())];
+        for encoding in [
+            UiTransactionEncoding::Base58,
+            UiTransactionEncoding::Base64,
+            UiTransactionEncoding::Json,
+            UiTransactionEncoding::JsonParsed,
+        ] {
+            let tx = encode(v0_message.clone(), meta.clone(), encoding);
+            assert_eq!(inner_instructions(&tx).unwrap(), expected, "{:?}", encoding);
+            assert!(!is_failed(&tx));
+            assert_eq!(fee_and_compute_units(&tx).unwrap(), (5_000, Some(21_000)));
+        }
+
+        // A legacy message has only static keys
+        let legacy_message = VersionedMessage::Legacy(Message {
+            header,
+            account_keys: vec![payer, phoenix::id(), market, other_program],
+            recent_blockhash: Hash::default(),
+            instructions: top_level,
+        });
+        let legacy_meta = TransactionStatusMeta {
+            loaded_addresses: LoadedAddresses::default(),
+            compute_units_consumed: None,
+            ..meta
+        };
+        let tx = encode(legacy_message, legacy_meta, UiTransactionEncoding::Base64);
+        let instructions = inner_instructions(&tx).unwrap();
+        assert_eq!(instructions, expected);
+        // Nodes that don't report compute units
+        assert_eq!(fee_and_compute_units(&tx).unwrap(), (5_000, None));
+
+        // The extracted data parses into the logged events
+        let core = SDKClientCore {
+            markets: Default::default(),
+            trader: payer,
+            default_self_trade_behavior: Default::default(),
+        };
+        let sig = Signature::new_unique();
+        let events = core
+            .parse_events_from_instruction_data(
+                &sig,
+                instructions
+                    .iter()
+                    .map(|(program_id, data)| (*program_id, data.as_slice())),
+            )
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].header.signature, sig);
+        assert_eq!(events[0].header.market, market);
+        assert_eq!(events[0].header.sequence_number, 7);
+        assert_eq!(events[0].batch.len(), 1);
+    }
+
+    #[test]
+    fn test_aggregator_route() {
+        // Lays out a swap routed through an aggregator, as Jupiter does: the user calls the
+        // aggregator, which transfers tokens and calls Phoenix, which logs its fills with a CPI
+        // to itself. Phoenix, its log authority and the market come from a lookup table.
+        let user = Pubkey::new_unique();
+        let aggregator = Pubkey::from_str("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4").unwrap();
+        let market = Pubkey::new_unique();
+        let (maker_a, maker_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let fills = [fill(maker_a, 20_000, 30), fill(maker_b, 20_001, 12)];
+        let log = log_data(market, user, &fills);
+
+        // 0: user, 1: user token account, 2: aggregator, then loaded 3: market,
+        // 4: phoenix, 5: log authority, 6: token program
+        let inner = |program_id_index, data: Vec<u8>, accounts, stack_height| InnerInstruction {
+            instruction: CompiledInstruction::new_from_raw_parts(program_id_index, data, accounts),
+            stack_height: Some(stack_height),
+        };
+        let meta = TransactionStatusMeta {
+            inner_instructions: Some(vec![InnerInstructions {
+                index: 1,
+                instructions: vec![
+                    inner(6, vec![3, 1, 0, 0, 0, 0, 0, 0, 0], vec![1, 3, 0], 2),
+                    inner(4, vec![PhoenixInstruction::Swap as u8], vec![4, 5, 3, 0], 2),
+                    inner(4, log.clone(), vec![5], 3),
+                    inner(6, vec![3, 2, 0, 0, 0, 0, 0, 0, 0], vec![3, 1, 0], 3),
+                ],
+            }]),
+            loaded_addresses: LoadedAddresses {
+                writable: vec![market],
+                readonly: vec![
+                    phoenix::id(),
+                    phoenix::phoenix_log_authority::id(),
+                    spl_token::id(),
+                ],
+            },
+            ..Default::default()
+        };
+        let message = VersionedMessage::V0(v0::Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: vec![user, Pubkey::new_unique(), aggregator],
+            recent_blockhash: Hash::default(),
+            instructions: vec![
+                CompiledInstruction::new_from_raw_parts(2, vec![0], vec![]),
+                CompiledInstruction::new_from_raw_parts(2, vec![1], vec![0, 1, 3, 4, 5, 6]),
+            ],
+            address_table_lookups: vec![MessageAddressTableLookup {
+                account_key: Pubkey::new_unique(),
+                writable_indexes: vec![0],
+                readonly_indexes: vec![1, 2, 3],
+            }],
+        });
+
+        let core = SDKClientCore {
+            markets: Default::default(),
+            trader: user,
+            default_self_trade_behavior: Default::default(),
+        };
+        let sig = Signature::new_unique();
+        let recovered_fills = |events: Vec<RawPhoenixEvent>| {
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].header.market, market);
+            events[0]
+                .batch
+                .iter()
+                .map(|event| match event {
+                    PhoenixMarketEvent::Fill(fill) => {
+                        (fill.maker_id, fill.price_in_ticks, fill.base_lots_filled)
+                    }
+                    other => panic!("Unexpected event {:?}", other),
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![(maker_a, 20_000, 30), (maker_b, 20_001, 12)];
+        for encoding in [
+            UiTransactionEncoding::Base64,
+            UiTransactionEncoding::JsonParsed,
+        ] {
+            let tx = encode(message.clone(), meta.clone(), encoding);
+            let instructions = inner_instructions(&tx).unwrap();
+            let events = core
+                .parse_events_from_instruction_data(
+                    &sig,
+                    instructions
+                        .iter()
+                        .map(|(program_id, data)| (*program_id, data.as_slice())),
+                )
+                .unwrap();
+            assert_eq!(recovered_fills(events), expected, "{:?}", encoding);
+        }
+
+        // `SDKClient::parse_events_from_transaction` fetches base58 and parses it like this
+        let tx = encode(message.clone(), meta.clone(), UiTransactionEncoding::Base58);
+        let mut parsed = transaction_utils::parse_transaction(tx);
+        parsed.signature = sig.to_string();
+        let events = core.parse_events_from_transaction(&parsed).unwrap();
+        assert_eq!(recovered_fills(events), expected);
+
+        // A log whose last fill was truncated still yields the fill before it
+        let mut truncated_meta = meta;
+        let instructions = &mut truncated_meta.inner_instructions.as_mut().unwrap()[0].instructions;
+        instructions[2].instruction.data.truncate(log.len() - 10);
+        let tx = encode(message, truncated_meta, UiTransactionEncoding::Base58);
+        let mut parsed = transaction_utils::parse_transaction(tx);
+        parsed.signature = sig.to_string();
+        assert!(core.parse_events_from_transaction(&parsed).is_none());
+        let partial = core
+            .parse_events_from_instruction_data_lenient(&sig, phoenix_inner_instructions(&parsed))
+            .unwrap_err();
+        assert_eq!(recovered_fills(partial.events), expected[..1]);
+        assert_eq!(partial.error.log_index, 0);
+        // The header and the first fill, a tag and 66 bytes
+        assert_eq!(partial.bytes_consumed, AUDIT_LOG_HEADER_LEN + 67);
+    }
+}
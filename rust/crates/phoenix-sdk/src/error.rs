@@ -0,0 +1,250 @@
+use std::fmt;
+
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk_core::order_id::CompactOrderId;
+use phoenix_sdk_core::program_error::{decode_phoenix_error, PhoenixProgramError};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::TransactionError;
+
+pub use phoenix_sdk_core::sdk_client_core::{BelowMinNotional, MarketMetadataError, SizeTooSmall};
+
+/// Typed errors returned inside the `anyhow::Error`s of `SDKClient`, for callers that need to
+/// act on them: `error.downcast_ref::<PhoenixSdkError>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PhoenixSdkError {
+    /// A transaction was rejected in simulation or failed on-chain.
+    TransactionFailed {
+        /// Unknown when the transaction failed preflight simulation.
+        signature: Option<Signature>,
+        error: TransactionError,
+        /// Set when the failure was raised by the Phoenix program.
+        program_error: Option<PhoenixProgramError>,
+        /// Program logs from the simulation or the confirmed transaction, if they could be
+        /// fetched.
+        logs: Vec<String>,
+    },
+    /// The order `SDKClient::move_order` was moving had already left the book, e.g. because it
+    /// filled, so the move's cancel removed nothing and its replacement was placed regardless.
+    OrderNotOnBook {
+        order_id: FIFOOrderId,
+        /// The move's transaction.
+        signature: Signature,
+        replacement: FIFOOrderId,
+        /// Whether the replacement was cancelled again. If not, it is still on the book.
+        replacement_cancelled: bool,
+    },
+    /// No RPC response was read at `required` or a later slot before the deadline, see
+    /// `SDKClient::get_market_state_with_min_slot`.
+    StaleData {
+        /// The newest slot a response was read at, or 0 if every node reported not having
+        /// reached `required` yet.
+        got: u64,
+        required: u64,
+    },
+    /// A transaction's Phoenix events could not be parsed under strict event parsing. The parse
+    /// error itself goes to the event parse error sender; see
+    /// `SDKClient::set_event_parse_error_sender`.
+    MalformedEvents { signature: Signature },
+}
+
+impl PhoenixSdkError {
+    pub fn transaction_failed(
+        signature: Option<Signature>,
+        error: TransactionError,
+        logs: Vec<String>,
+    ) -> Self {
+        PhoenixSdkError::TransactionFailed {
+            signature,
+            program_error: decode_phoenix_error(&error, &logs),
+            error,
+            logs,
+        }
+    }
+
+    /// The failure of a transaction rejected by preflight simulation, with the simulation's logs.
+    /// `None` for other client errors, e.g. a failed connection.
+    pub fn from_client_error(client_error: &ClientError) -> Option<Self> {
+        let logs = match &client_error.kind {
+            ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                data: RpcResponseErrorData::SendTransactionPreflightFailure(simulation),
+                ..
+            }) => simulation.logs.clone().unwrap_or_default(),
+            _ => vec![],
+        };
+        let error = client_error.get_transaction_error()?;
+        Some(Self::transaction_failed(None, error, logs))
+    }
+
+    /// Whether sending the same instructions again could succeed. Program errors are
+    /// deterministic for a given book and balance, so they are not retried; transient failures
+    /// such as an expired blockhash or a busy account are.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            PhoenixSdkError::TransactionFailed { error, .. } => matches!(
+                error,
+                TransactionError::BlockhashNotFound
+                    | TransactionError::AccountInUse
+                    | TransactionError::ClusterMaintenance
+                    | TransactionError::WouldExceedMaxBlockCostLimit
+                    | TransactionError::WouldExceedMaxAccountCostLimit
+                    | TransactionError::WouldExceedMaxVoteCostLimit
+                    | TransactionError::WouldExceedAccountDataBlockLimit
+                    | TransactionError::TooManyAccountLocks
+            ),
+            PhoenixSdkError::OrderNotOnBook { .. } => false,
+            PhoenixSdkError::StaleData { .. } => true,
+            PhoenixSdkError::MalformedEvents { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for PhoenixSdkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PhoenixSdkError::TransactionFailed {
+                signature,
+                error,
+                program_error,
+                ..
+            } => {
+                write!(f, "Transaction ")?;
+                if let Some(signature) = signature {
+                    write!(f, "{} ", signature)?;
+                }
+                write!(f, "failed: {}", error)?;
+                if let Some(program_error) = program_error {
+                    write!(f, " ({})", program_error)?;
+                }
+                Ok(())
+            }
+            PhoenixSdkError::OrderNotOnBook {
+                order_id,
+                signature,
+                replacement,
+                replacement_cancelled,
+            } => {
+                write!(
+                    f,
+                    "Order {} was no longer on the book when transaction {} moved it. Its \
+                     replacement {} ",
+                    CompactOrderId(*order_id),
+                    signature,
+                    CompactOrderId(*replacement)
+                )?;
+                if *replacement_cancelled {
+                    write!(f, "was cancelled")
+                } else {
+                    write!(f, "could not be cancelled and is still on the book")
+                }
+            }
+            PhoenixSdkError::StaleData { got, required } => write!(
+                f,
+                "RPC data is stale: read at slot {}, but slot {} or later is required",
+                got, required
+            ),
+            PhoenixSdkError::MalformedEvents { signature } => {
+                write!(f, "Failed to parse events of transaction {}", signature)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PhoenixSdkError {}
+
+/// Whether `error` is worth retrying: transient transaction failures and errors that are not
+/// transaction failures at all, such as timeouts and connection errors.
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<PhoenixSdkError>()
+        .is_none_or(PhoenixSdkError::is_retryable)
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix::program::error::PhoenixError;
+    use solana_client::rpc_response::RpcSimulateTransactionResult;
+    use solana_sdk::instruction::InstructionError;
+
+    use super::*;
+
+    #[test]
+    fn test_preflight_failure() {
+        let simulation = RpcSimulateTransactionResult {
+            err: Some(TransactionError::InstructionError(
+                1,
+                InstructionError::Custom(15),
+            )),
+            logs: Some(vec![
+                "Program log: PostOnly order crosses the book - order rejected".to_string(),
+                format!(
+                    "Program {} failed: custom program error: 0xf",
+                    phoenix::id()
+                ),
+            ]),
+            accounts: None,
+            units_consumed: None,
+            return_data: None,
+        };
+        let client_error =
+            ClientError::from(ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                code: -32002,
+                message: "Transaction simulation failed".to_string(),
+                data: RpcResponseErrorData::SendTransactionPreflightFailure(simulation),
+            }));
+        let failed = PhoenixSdkError::from_client_error(&client_error).unwrap();
+        let PhoenixSdkError::TransactionFailed {
+            signature,
+            program_error,
+            logs,
+            ..
+        } = &failed
+        else {
+            panic!("Expected a failed transaction, got {}", failed);
+        };
+        assert_eq!(*signature, None);
+        assert_eq!(*program_error, Some(PhoenixProgramError::PostOnlyCrosses));
+        assert_eq!(logs.len(), 2);
+        assert!(!failed.is_retryable());
+        assert!(failed
+            .to_string()
+            .contains("Post-only order crosses the book"));
+
+        let io_error = ClientError::from(ClientErrorKind::Custom("connection reset".to_string()));
+        assert!(PhoenixSdkError::from_client_error(&io_error).is_none());
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let expired =
+            PhoenixSdkError::transaction_failed(None, TransactionError::BlockhashNotFound, vec![]);
+        assert!(expired.is_retryable());
+        assert!(is_retryable(&anyhow::Error::new(expired)));
+        assert!(is_retryable(&anyhow::anyhow!("request timed out")));
+
+        let no_seat = PhoenixSdkError::transaction_failed(
+            Some(Signature::default()),
+            TransactionError::InstructionError(0, InstructionError::Custom(20)),
+            vec![],
+        );
+        let PhoenixSdkError::TransactionFailed { program_error, .. } = &no_seat else {
+            panic!("Expected a failed transaction, got {}", no_seat);
+        };
+        assert_eq!(
+            *program_error,
+            Some(PhoenixProgramError::Program(PhoenixError::TraderNotFound))
+        );
+        assert!(!is_retryable(&anyhow::Error::new(no_seat)));
+
+        let stale = PhoenixSdkError::StaleData {
+            got: 99,
+            required: 100,
+        };
+        assert!(stale.is_retryable());
+        assert_eq!(
+            stale.to_string(),
+            "RPC data is stale: read at slot 99, but slot 100 or later is required"
+        );
+    }
+}
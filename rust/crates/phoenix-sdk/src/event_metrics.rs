@@ -0,0 +1,505 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::clock_monitor::ClockMonitor;
+use crate::event_sink::EventSink;
+use crate::health::{
+    ComponentDetails, ComponentHealth, HealthProvider, HealthStatus, HealthThresholds,
+};
+use crate::latency::{LatencyTracker, ReceiveStamp};
+use crate::sdk_market_event::SDKMarketEvent;
+
+/// Point-in-time copy of `EventMetrics`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventMetricsSnapshot {
+    /// Highest slot of any event delivered so far.
+    pub last_processed_slot: u64,
+    /// Latest slot reported by the chain slot tracker.
+    pub chain_slot: u64,
+    /// How far `last_processed_slot` trails `chain_slot`.
+    pub slot_lag: u64,
+    /// Batches delivered, i.e. transactions processed.
+    pub transactions_processed: u64,
+    /// Transactions processed during the last complete tracker interval.
+    pub transactions_last_interval: u64,
+    pub events_processed: u64,
+    /// Batches the wrapped sink failed to accept.
+    pub send_failures: u64,
+    /// How long the wrapped sink took to accept the latest batch.
+    pub last_send_latency: Duration,
+    /// Failed attempts by the tracker to fetch the chain slot.
+    pub chain_slot_errors: u64,
+    /// Transactions whose events were cut short or dropped by a malformed event record.
+    pub parse_failures: u64,
+    /// The wait the poller chose before its next poll. In adaptive mode it grows while polls
+    /// come back empty.
+    pub poll_interval: Duration,
+}
+
+/// Counters describing how an event stream is keeping up with the chain, shared as an `Arc` between
+/// the `MetricsSink` that feeds it, the chain slot tracker and whatever reads it.
+///
+/// `track_chain_slot` keeps `chain_slot` moving while no events arrive, so a stalled stream shows
+/// up as a growing `slot_lag`. A producer that scans slots without finding events should report
+/// its progress with `record_processed_slot`, so that a quiet market is not mistaken for a stall.
+/// An `EventPoller` given the metrics with `EventPoller::with_metrics` also records its parse
+/// failures and the wait before its next poll.
+/// Register the metrics with `SDKClient::register_health_provider` to report the lag in
+/// `SDKClient::health`.
+#[derive(Debug, Default)]
+pub struct EventMetrics {
+    last_processed_slot: AtomicU64,
+    chain_slot: AtomicU64,
+    transactions_processed: AtomicU64,
+    transactions_in_interval: AtomicU64,
+    transactions_last_interval: AtomicU64,
+    events_processed: AtomicU64,
+    send_failures: AtomicU64,
+    last_send_latency_micros: AtomicU64,
+    chain_slot_errors: AtomicU64,
+    parse_failures: AtomicU64,
+    poll_interval_micros: AtomicU64,
+}
+
+impl EventMetrics {
+    pub fn snapshot(&self) -> EventMetricsSnapshot {
+        let last_processed_slot = self.last_processed_slot.load(Ordering::Relaxed);
+        let chain_slot = self.chain_slot.load(Ordering::Relaxed);
+        EventMetricsSnapshot {
+            last_processed_slot,
+            chain_slot,
+            slot_lag: chain_slot.saturating_sub(last_processed_slot),
+            transactions_processed: self.transactions_processed.load(Ordering::Relaxed),
+            transactions_last_interval: self.transactions_last_interval.load(Ordering::Relaxed),
+            events_processed: self.events_processed.load(Ordering::Relaxed),
+            send_failures: self.send_failures.load(Ordering::Relaxed),
+            last_send_latency: Duration::from_micros(
+                self.last_send_latency_micros.load(Ordering::Relaxed),
+            ),
+            chain_slot_errors: self.chain_slot_errors.load(Ordering::Relaxed),
+            parse_failures: self.parse_failures.load(Ordering::Relaxed),
+            poll_interval: Duration::from_micros(self.poll_interval_micros.load(Ordering::Relaxed)),
+        }
+    }
+
+    pub fn slot_lag(&self) -> u64 {
+        self.snapshot().slot_lag
+    }
+
+    /// Records a delivered batch and how long the sink took to accept it. A batch of only
+    /// heartbeats is not counted as a transaction.
+    pub fn record_batch(&self, events: &[SDKMarketEvent], latency: Duration, delivered: bool) {
+        self.last_send_latency_micros
+            .store(latency.as_micros() as u64, Ordering::Relaxed);
+        if !delivered {
+            self.send_failures.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        if events.iter().all(SDKMarketEvent::is_heartbeat) {
+            return;
+        }
+        let max_slot = events
+            .iter()
+            .filter_map(|event| event.as_phoenix_event().map(|event| event.slot))
+            .max();
+        if let Some(slot) = max_slot {
+            self.record_processed_slot(slot);
+        }
+        self.transactions_processed.fetch_add(1, Ordering::Relaxed);
+        self.transactions_in_interval
+            .fetch_add(1, Ordering::Relaxed);
+        self.events_processed
+            .fetch_add(events.len() as u64, Ordering::Relaxed);
+    }
+
+    /// Marks every slot up to `slot` as processed.
+    pub fn record_processed_slot(&self, slot: u64) {
+        self.last_processed_slot.fetch_max(slot, Ordering::Relaxed);
+    }
+
+    pub fn record_chain_slot(&self, slot: u64) {
+        self.chain_slot.fetch_max(slot, Ordering::Relaxed);
+    }
+
+    pub fn record_parse_failure(&self) {
+        self.parse_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_poll_interval(&self, interval: Duration) {
+        self.poll_interval_micros
+            .store(interval.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Closes the current interval, making its transaction count `transactions_last_interval`.
+    pub fn roll_interval(&self) {
+        let count = self.transactions_in_interval.swap(0, Ordering::Relaxed);
+        self.transactions_last_interval
+            .store(count, Ordering::Relaxed);
+    }
+
+    /// Fetches the chain slot with `get_slot` every `interval`, forever, rolling the interval
+    /// counters on each tick. Typically `|| client.get_slot()` with an `RpcClient`.
+    pub async fn track_chain_slot<F, Fut, E>(&self, get_slot: F, interval: Duration)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = std::result::Result<u64, E>>,
+        E: Display,
+    {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately, so the first interval starts now
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match get_slot().await {
+                Ok(slot) => self.record_chain_slot(slot),
+                Err(_) => {
+                    self.chain_slot_errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            self.roll_interval();
+        }
+    }
+
+    /// Sends a `Heartbeat` for each of `markets` to `sink` every `interval`, starting now, with
+    /// the chain slot and lag of these metrics, so consumers can tell a quiet market from a
+    /// stalled producer. Returns once the sink fails.
+    pub async fn send_heartbeats<S: EventSink>(
+        &self,
+        markets: &[Pubkey],
+        sink: &S,
+        interval: Duration,
+    ) -> Result<()> {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let snapshot = self.snapshot();
+            let heartbeats = markets
+                .iter()
+                .map(|&market| SDKMarketEvent::Heartbeat {
+                    market,
+                    chain_slot: snapshot.chain_slot,
+                    poller_lag: snapshot.slot_lag,
+                })
+                .collect();
+            sink.send_events(heartbeats).await?;
+        }
+    }
+}
+
+/// Forwards batches to another sink, recording them in `metrics`.
+pub struct MetricsSink<S: EventSink> {
+    inner: S,
+    metrics: Arc<EventMetrics>,
+    latency_tracker: Option<Arc<LatencyTracker>>,
+    clock_monitor: Option<Arc<ClockMonitor>>,
+}
+
+impl<S: EventSink> MetricsSink<S> {
+    pub fn new(inner: S, metrics: Arc<EventMetrics>) -> Self {
+        Self {
+            inner,
+            metrics,
+            latency_tracker: None,
+            clock_monitor: None,
+        }
+    }
+
+    /// Also stamps each batch's arrival in `latency_tracker`, before forwarding it.
+    pub fn with_latency_tracker(mut self, latency_tracker: Arc<LatencyTracker>) -> Self {
+        self.latency_tracker = Some(latency_tracker);
+        self
+    }
+
+    /// Also records each batch's arrival in `clock_monitor`, ahead of the latency tracker.
+    pub fn with_clock_monitor(mut self, clock_monitor: Arc<ClockMonitor>) -> Self {
+        self.clock_monitor = Some(clock_monitor);
+        self
+    }
+
+    pub fn metrics(&self) -> &Arc<EventMetrics> {
+        &self.metrics
+    }
+}
+
+#[async_trait]
+impl<S: EventSink> EventSink for MetricsSink<S> {
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()> {
+        let received = ReceiveStamp::now();
+        if let Some(clock_monitor) = &self.clock_monitor {
+            clock_monitor.on_events(&events, received);
+        }
+        if let Some(latency_tracker) = &self.latency_tracker {
+            latency_tracker.on_events(&events, received);
+        }
+        let recorded = events.clone();
+        let start = Instant::now();
+        let result = self.inner.send_events(events).await;
+        self.metrics
+            .record_batch(&recorded, start.elapsed(), result.is_ok());
+        result
+    }
+}
+
+/// Exposes `EventMetrics` to a Prometheus registry. Values are read from the shared metrics on
+/// every scrape.
+#[cfg(feature = "prometheus")]
+pub struct EventMetricsCollector {
+    metrics: Arc<EventMetrics>,
+    gauges: Vec<(prometheus::IntGauge, SnapshotValue)>,
+    counters: Vec<(prometheus::IntCounter, SnapshotValue)>,
+}
+
+#[cfg(feature = "prometheus")]
+type SnapshotValue = fn(&EventMetricsSnapshot) -> u64;
+
+#[cfg(feature = "prometheus")]
+impl EventMetricsCollector {
+    /// Metric names are prefixed with `prefix`, e.g. `phoenix_events` gives
+    /// `phoenix_events_slot_lag`.
+    pub fn new(metrics: Arc<EventMetrics>, prefix: &str) -> prometheus::Result<Self> {
+        let gauges: [(&str, &str, SnapshotValue); 6] = [
+            (
+                "last_processed_slot",
+                "Highest slot of any delivered event",
+                |s| s.last_processed_slot,
+            ),
+            ("chain_slot", "Latest slot reported by the chain", |s| {
+                s.chain_slot
+            }),
+            (
+                "slot_lag",
+                "Slots the event stream trails the chain by",
+                |s| s.slot_lag,
+            ),
+            (
+                "transactions_last_interval",
+                "Transactions processed in the last interval",
+                |s| s.transactions_last_interval,
+            ),
+            (
+                "send_latency_micros",
+                "Time the sink took to accept the latest batch",
+                |s| s.last_send_latency.as_micros() as u64,
+            ),
+            (
+                "poll_interval_micros",
+                "Wait before the poller's next poll",
+                |s| s.poll_interval.as_micros() as u64,
+            ),
+        ];
+        let counters: [(&str, &str, SnapshotValue); 5] = [
+            ("transactions_processed", "Transactions processed", |s| {
+                s.transactions_processed
+            }),
+            ("events_processed", "Events processed", |s| {
+                s.events_processed
+            }),
+            ("send_failures", "Batches the sink failed to accept", |s| {
+                s.send_failures
+            }),
+            (
+                "chain_slot_errors",
+                "Failed attempts to fetch the chain slot",
+                |s| s.chain_slot_errors,
+            ),
+            (
+                "parse_failures",
+                "Transactions with malformed event records",
+                |s| s.parse_failures,
+            ),
+        ];
+        Ok(Self {
+            metrics,
+            gauges: gauges
+                .into_iter()
+                .map(|(name, help, get)| {
+                    prometheus::IntGauge::new(format!("{}_{}", prefix, name), help)
+                        .map(|gauge| (gauge, get))
+                })
+                .collect::<prometheus::Result<_>>()?,
+            counters: counters
+                .into_iter()
+                .map(|(name, help, get)| {
+                    prometheus::IntCounter::new(format!("{}_{}", prefix, name), help)
+                        .map(|counter| (counter, get))
+                })
+                .collect::<prometheus::Result<_>>()?,
+        })
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl prometheus::core::Collector for EventMetricsCollector {
+    fn desc(&self) -> Vec<&prometheus::core::Desc> {
+        self.gauges
+            .iter()
+            .flat_map(|(gauge, _)| gauge.desc())
+            .chain(self.counters.iter().flat_map(|(counter, _)| counter.desc()))
+            .collect()
+    }
+
+    fn collect(&self) -> Vec<prometheus::proto::MetricFamily> {
+        let snapshot = self.metrics.snapshot();
+        for (gauge, get) in self.gauges.iter() {
+            gauge.set(get(&snapshot) as i64);
+        }
+        for (counter, get) in self.counters.iter() {
+            let value = get(&snapshot);
+            counter.inc_by(value.saturating_sub(counter.get()));
+        }
+        self.gauges
+            .iter()
+            .flat_map(|(gauge, _)| gauge.collect())
+            .chain(
+                self.counters
+                    .iter()
+                    .flat_map(|(counter, _)| counter.collect()),
+            )
+            .collect()
+    }
+}
+
+/// Degraded while the stream trails the chain by more than `max_slot_lag`.
+impl HealthProvider for EventMetrics {
+    fn health_check(&self, thresholds: &HealthThresholds) -> ComponentHealth {
+        let snapshot = self.snapshot();
+        ComponentHealth {
+            status: if snapshot.slot_lag > thresholds.max_slot_lag {
+                HealthStatus::Degraded
+            } else {
+                HealthStatus::Healthy
+            },
+            details: ComponentDetails::EventStream {
+                chain_slot: snapshot.chain_slot,
+                last_processed_slot: snapshot.last_processed_slot,
+                slot_lag: snapshot.slot_lag,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent};
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use phoenix_sdk_core::test_utils::event;
+
+    fn batch(slot: u64) -> Vec<SDKMarketEvent> {
+        vec![PhoenixEvent {
+            sequence_number: slot,
+            slot,
+            ..event(Pubkey::default(), MarketEventDetails::Fee(0))
+        }
+        .into()]
+    }
+
+    #[tokio::test]
+    async fn test_lag_grows_when_events_stop() {
+        let metrics = Arc::new(EventMetrics::default());
+        let (sender, mut receiver) = mpsc::channel(16);
+        let sink = MetricsSink::new(sender, metrics.clone());
+
+        // A mocked chain that advances one slot per fetch
+        let chain_slot = AtomicU64::new(100);
+        let get_slot = || {
+            let slot = chain_slot.fetch_add(1, Ordering::SeqCst);
+            async move { Ok::<_, String>(slot) }
+        };
+        let tracker = metrics.track_chain_slot(get_slot, Duration::from_millis(5));
+        tokio::pin!(tracker);
+
+        // While events keep up with the chain the lag stays small
+        for _ in 0..5 {
+            tokio::select! {
+                _ = &mut tracker => unreachable!(),
+                _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+            }
+            let slot = metrics.snapshot().chain_slot;
+            sink.send_events(batch(slot)).await.unwrap();
+            receiver.recv().await.unwrap();
+        }
+        let before = metrics.snapshot();
+        assert!(before.slot_lag <= 2, "{:?}", before);
+        assert_eq!(before.transactions_processed, 5);
+        assert_eq!(before.events_processed, 5);
+
+        // No new events arrive, but the chain keeps moving
+        tokio::select! {
+            _ = &mut tracker => unreachable!(),
+            _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+        }
+        let after = metrics.snapshot();
+        assert_eq!(after.last_processed_slot, before.last_processed_slot);
+        assert!(after.slot_lag >= before.slot_lag + 5, "{:?}", after);
+        assert_eq!(after.transactions_last_interval, 0);
+
+        // A closed sink counts as a send failure
+        drop(receiver);
+        assert!(sink.send_events(batch(1)).await.is_err());
+        assert_eq!(metrics.snapshot().send_failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_cadence() {
+        let metrics = Arc::new(EventMetrics::default());
+        metrics.record_processed_slot(100);
+        metrics.record_chain_slot(120);
+        let (sender, mut receiver) = mpsc::channel(64);
+        let sink = MetricsSink::new(sender, metrics.clone());
+        let (sol, eth) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        // With no events at all, a heartbeat per market arrives every interval
+        let interval = Duration::from_millis(20);
+        let markets = [sol, eth];
+        let start = Instant::now();
+        tokio::select! {
+            _ = metrics.send_heartbeats(&markets, &sink, interval) => unreachable!(),
+            _ = tokio::time::sleep(Duration::from_millis(110)) => {}
+        }
+        let mut arrivals = vec![];
+        while let Ok(batch) = receiver.try_recv() {
+            assert_eq!(
+                batch,
+                vec![
+                    SDKMarketEvent::Heartbeat {
+                        market: sol,
+                        chain_slot: 120,
+                        poller_lag: 20,
+                    },
+                    SDKMarketEvent::Heartbeat {
+                        market: eth,
+                        chain_slot: 120,
+                        poller_lag: 20,
+                    },
+                ]
+            );
+            arrivals.push(batch);
+        }
+        // One at once, then one per interval
+        let expected = start.elapsed().as_millis() / interval.as_millis() + 1;
+        assert!(
+            (arrivals.len() as u128).abs_diff(expected) <= 1,
+            "{} heartbeats after {:?}",
+            arrivals.len(),
+            start.elapsed()
+        );
+        // Heartbeats are not transactions
+        assert_eq!(metrics.snapshot().transactions_processed, 0);
+
+        // A closed sink stops the heartbeats
+        drop(receiver);
+        assert!(metrics
+            .send_heartbeats(&[sol], &sink, interval)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,516 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::error::PhoenixSdkError;
+use crate::event_metrics::EventMetrics;
+use crate::event_sink::EventSink;
+use crate::poll_schedule::{PollSchedule, PollScheduleConfig};
+use crate::sdk_client::SDKClient;
+use crate::sdk_market_event::{ControlMsg, SDKMarketEvent};
+
+/// Follows markets' new transactions over RPC and turns them into event batches, one per
+/// transaction with events on the polled market, oldest first.
+///
+/// The first poll of a market only records its newest transaction, so history is not replayed;
+/// use a `BackfillJob` for that. If more than a page of transactions landed between two polls,
+/// the ones that did not fit are skipped and a `ControlMsg::ResyncRequired` for the market is
+/// sent before the rest. A transaction that cannot be fetched fails the poll, and the next poll
+/// starts again from the same point on every market, so no batch is lost. A transaction whose
+/// events cannot be parsed under strict event parsing would fail every retry the same way, so it
+/// is skipped with a `ControlMsg::ResyncRequired` for the market in place of its batch.
+pub struct EventPoller {
+    sdk: Arc<SDKClient>,
+    markets: Vec<Pubkey>,
+    schedule: PollSchedule,
+    page_size: usize,
+    /// The newest transaction seen on each market.
+    last_seen: BTreeMap<Pubkey, Signature>,
+    metrics: Option<Arc<EventMetrics>>,
+}
+
+impl EventPoller {
+    /// Polls every second, up to 100 signatures per market.
+    pub fn new(sdk: Arc<SDKClient>, markets: Vec<Pubkey>) -> Self {
+        Self {
+            sdk,
+            markets,
+            schedule: PollSchedule::new(PollScheduleConfig::default()),
+            page_size: 100,
+            last_seen: BTreeMap::new(),
+            metrics: None,
+        }
+    }
+
+    pub fn with_schedule(mut self, config: PollScheduleConfig) -> Self {
+        self.schedule = PollSchedule::new(config);
+        self
+    }
+
+    /// Signatures fetched per market and poll, at most 1000, the RPC's limit.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.clamp(1, 1000);
+        self
+    }
+
+    /// Records parse failures and the schedule's wait between polls in `metrics`, e.g. the
+    /// metrics of the `MetricsSink` the poller sends to.
+    pub fn with_metrics(mut self, metrics: Arc<EventMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn markets(&self) -> &[Pubkey] {
+        &self.markets
+    }
+
+    /// Polls every market once, returning the batches of their new transactions.
+    pub async fn poll(&mut self) -> Result<Vec<Vec<SDKMarketEvent>>> {
+        let mut batches = vec![];
+        // Only committed once every market is polled, so a failure does not skip any batches
+        let mut cursors = BTreeMap::new();
+        for market in self.markets.clone() {
+            let page = self
+                .sdk
+                .get_market_signatures(&market, None, self.page_size)
+                .await?;
+            let Some(newest) = page.first() else {
+                continue;
+            };
+            let newest = Signature::from_str(&newest.signature)?;
+            let Some(last_seen) = self.last_seen.get(&market).copied() else {
+                cursors.insert(market, newest);
+                continue;
+            };
+            let last_seen = last_seen.to_string();
+            let new = page
+                .iter()
+                .take_while(|status| status.signature != last_seen)
+                .collect::<Vec<_>>();
+            let mut market_batches = vec![];
+            if new.len() == page.len() {
+                market_batches.push(vec![SDKMarketEvent::Control(ControlMsg::ResyncRequired {
+                    market: Some(market),
+                })]);
+            }
+            for status in new.into_iter().rev() {
+                if status.err.is_some() {
+                    continue;
+                }
+                let events = match self
+                    .sdk
+                    .get_transaction_events_salvaged(&Signature::from_str(&status.signature)?)
+                    .await
+                {
+                    Ok((events, salvaged)) => {
+                        if salvaged {
+                            self.record_parse_failure();
+                        }
+                        events
+                    }
+                    Err(e) if is_malformed(&e) => {
+                        self.record_parse_failure();
+                        market_batches.push(vec![SDKMarketEvent::Control(
+                            ControlMsg::ResyncRequired {
+                                market: Some(market),
+                            },
+                        )]);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                let events = events
+                    .into_iter()
+                    .filter(|event| event.market == market)
+                    .map(SDKMarketEvent::from)
+                    .collect::<Vec<_>>();
+                if !events.is_empty() {
+                    market_batches.push(events);
+                }
+            }
+            cursors.insert(market, newest);
+            batches.extend(market_batches);
+        }
+        self.last_seen.extend(cursors);
+        Ok(batches)
+    }
+
+    /// Polls on the schedule and sends the batches to `sink`, until a poll fails or the sink is
+    /// closed.
+    pub async fn run<K: EventSink + ?Sized>(&mut self, sink: &K) -> Result<()> {
+        loop {
+            let batches = self.poll().await?;
+            let found_new = !batches.is_empty();
+            for batch in batches {
+                sink.send_events(batch).await?;
+            }
+            let delay = self.schedule.next_delay(found_new);
+            // Recorded before the wait, so that a backed-off poller shows it while waiting
+            if let Some(metrics) = &self.metrics {
+                metrics.record_poll_interval(delay);
+            }
+            PollSchedule::sleep(delay).await;
+        }
+    }
+
+    fn record_parse_failure(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_parse_failure();
+        }
+    }
+}
+
+fn is_malformed(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<PhoenixSdkError>(),
+        Some(PhoenixSdkError::MalformedEvents { .. })
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use borsh::BorshSerialize;
+    use ellipsis_client::EllipsisClient;
+    use phoenix::program::{AuditLogHeader, FillEvent, PhoenixInstruction, PhoenixMarketEvent};
+    use phoenix_sdk_core::market_event::MarketEventDetails;
+    use solana_client::client_error::{ClientErrorKind, Result as ClientResult};
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_client::rpc_client::RpcClientConfig;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::message::{Message, MessageHeader, VersionedMessage};
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::transaction::VersionedTransaction;
+    use solana_transaction_status::{
+        EncodedConfirmedTransactionWithStatusMeta, InnerInstruction, InnerInstructions,
+        TransactionStatusMeta, UiTransactionEncoding, VersionedTransactionWithStatusMeta,
+    };
+
+    use super::*;
+    use crate::paper_trading::test_utils::test_market_metadata;
+
+    #[tokio::test]
+    async fn test_starts_from_newest_and_resyncs_on_gap() {
+        // The first page is mocked, later ones get the mock sender's single default signature
+        let first = Signature::new_unique();
+        let mocks = HashMap::from([(
+            RpcRequest::GetSignaturesForAddress,
+            serde_json::json!([{
+                "signature": first.to_string(),
+                "slot": 100,
+                "err": null,
+                "memo": null,
+                "blockTime": null,
+                "confirmationStatus": "finalized",
+            }]),
+        )]);
+        let client = EllipsisClient::from_rpc(
+            RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks),
+            &Keypair::new(),
+        )
+        .unwrap();
+        let sdk = Arc::new(SDKClient::new_from_ellipsis_client(client).await.unwrap());
+        let market = Pubkey::new_unique();
+        let mut poller = EventPoller::new(sdk, vec![market]);
+
+        assert!(poller.poll().await.unwrap().is_empty());
+        assert_eq!(poller.last_seen[&market], first);
+
+        // `first` is no longer on the page, so transactions may have been missed
+        let batches = poller.poll().await.unwrap();
+        assert_eq!(
+            batches,
+            vec![vec![SDKMarketEvent::Control(ControlMsg::ResyncRequired {
+                market: Some(market)
+            })]]
+        );
+        assert_ne!(poller.last_seen[&market], first);
+
+        // Nothing new since
+        assert!(poller.poll().await.unwrap().is_empty());
+    }
+
+    /// Serves each market's signature page from `pages` and transactions from `transactions`.
+    /// Other `getTransaction`s fail.
+    #[derive(Clone, Default)]
+    struct PageSender {
+        pages: Arc<Mutex<HashMap<String, serde_json::Value>>>,
+        transactions: Arc<Mutex<HashMap<String, serde_json::Value>>>,
+    }
+
+    impl PageSender {
+        /// Sets `market`'s page, newest first, with whether each transaction failed.
+        fn set_page(&self, market: &Pubkey, page: &[(Signature, bool)]) {
+            let page = page
+                .iter()
+                .map(|(signature, failed)| {
+                    let err = failed
+                        .then(|| serde_json::json!({ "InstructionError": [0, "InvalidArgument"] }));
+                    serde_json::json!({
+                        "signature": signature.to_string(),
+                        "slot": 100,
+                        "err": err,
+                        "memo": null,
+                        "blockTime": null,
+                        "confirmationStatus": "finalized",
+                    })
+                })
+                .collect();
+            self.pages
+                .lock()
+                .unwrap()
+                .insert(market.to_string(), serde_json::Value::Array(page));
+        }
+
+        /// Serves a transaction whose only Phoenix event is a fill on `market`, with its log
+        /// cut short by `truncate` bytes.
+        fn set_fill_transaction(&self, signature: &Signature, market: &Pubkey, truncate: usize) {
+            let payer = Pubkey::new_unique();
+            let mut log = vec![PhoenixInstruction::Log as u8];
+            PhoenixMarketEvent::Header(AuditLogHeader {
+                instruction: PhoenixInstruction::Swap as u8,
+                sequence_number: 7,
+                timestamp: 0,
+                slot: 100,
+                market: *market,
+                signer: payer,
+                total_events: 1,
+            })
+            .serialize(&mut log)
+            .unwrap();
+            PhoenixMarketEvent::Fill(FillEvent {
+                index: 0,
+                maker_id: Pubkey::new_unique(),
+                order_sequence_number: 3,
+                price_in_ticks: 100,
+                base_lots_filled: 5,
+                base_lots_remaining: 0,
+            })
+            .serialize(&mut log)
+            .unwrap();
+            log.truncate(log.len() - truncate);
+
+            let transaction = VersionedTransactionWithStatusMeta {
+                transaction: VersionedTransaction {
+                    signatures: vec![*signature],
+                    message: VersionedMessage::Legacy(Message {
+                        header: MessageHeader {
+                            num_required_signatures: 1,
+                            num_readonly_signed_accounts: 0,
+                            num_readonly_unsigned_accounts: 1,
+                        },
+                        account_keys: vec![payer, phoenix::id(), *market],
+                        recent_blockhash: Hash::default(),
+                        instructions: vec![CompiledInstruction::new_from_raw_parts(
+                            1,
+                            vec![PhoenixInstruction::Swap as u8],
+                            vec![2, 0],
+                        )],
+                    }),
+                },
+                meta: TransactionStatusMeta {
+                    inner_instructions: Some(vec![InnerInstructions {
+                        index: 0,
+                        instructions: vec![InnerInstruction {
+                            instruction: CompiledInstruction::new_from_raw_parts(1, log, vec![]),
+                            stack_height: Some(2),
+                        }],
+                    }]),
+                    ..Default::default()
+                },
+            };
+            let encoded = EncodedConfirmedTransactionWithStatusMeta {
+                slot: 100,
+                transaction: transaction
+                    .encode(UiTransactionEncoding::Base58, Some(0), false)
+                    .unwrap(),
+                block_time: None,
+            };
+            self.transactions.lock().unwrap().insert(
+                signature.to_string(),
+                serde_json::to_value(encoded).unwrap(),
+            );
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl RpcSender for PageSender {
+        async fn send(
+            &self,
+            request: RpcRequest,
+            params: serde_json::Value,
+        ) -> ClientResult<serde_json::Value> {
+            match request.to_string().as_str() {
+                "getVersion" => Ok(serde_json::json!({ "solana-core": "1.17.31" })),
+                "getSignaturesForAddress" => Ok(params[0]
+                    .as_str()
+                    .and_then(|market| self.pages.lock().unwrap().get(market).cloned())
+                    .unwrap_or_else(|| serde_json::json!([]))),
+                "getTransaction" => params[0]
+                    .as_str()
+                    .and_then(|signature| self.transactions.lock().unwrap().get(signature).cloned())
+                    .ok_or_else(|| {
+                        ClientErrorKind::Custom("getTransaction is unavailable".to_string()).into()
+                    }),
+                method => Err(ClientErrorKind::Custom(format!("{} is unavailable", method)).into()),
+            }
+        }
+
+        fn get_transport_stats(&self) -> RpcTransportStats {
+            Default::default()
+        }
+
+        fn url(&self) -> String {
+            "pages".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_poll_keeps_every_cursor() {
+        let sender = PageSender::default();
+        let client = EllipsisClient::from_rpc(
+            RpcClient::new_sender(sender.clone(), RpcClientConfig::default()),
+            &Keypair::new(),
+        )
+        .unwrap();
+        let sdk = Arc::new(SDKClient::new_from_ellipsis_client(client).await.unwrap());
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut poller = EventPoller::new(sdk, vec![first, second]);
+
+        let (first_seen, second_seen) = (Signature::new_unique(), Signature::new_unique());
+        sender.set_page(&first, &[(first_seen, false)]);
+        sender.set_page(&second, &[(second_seen, false)]);
+        assert!(poller.poll().await.unwrap().is_empty());
+
+        // The first market's cursor fell off its page, and the second market's new transaction
+        // cannot be fetched
+        sender.set_page(
+            &first,
+            &[
+                (Signature::new_unique(), true),
+                (Signature::new_unique(), true),
+            ],
+        );
+        let unavailable = Signature::new_unique();
+        sender.set_page(&second, &[(unavailable, false), (second_seen, false)]);
+        assert!(poller.poll().await.is_err());
+        assert_eq!(poller.last_seen[&first], first_seen);
+        assert_eq!(poller.last_seen[&second], second_seen);
+
+        // The retry still reports the first market's gap
+        sender.set_page(&second, &[(unavailable, true), (second_seen, false)]);
+        assert_eq!(
+            poller.poll().await.unwrap(),
+            vec![vec![SDKMarketEvent::Control(ControlMsg::ResyncRequired {
+                market: Some(first)
+            })]]
+        );
+        assert_eq!(poller.last_seen[&second], unavailable);
+    }
+
+    /// A poller of one market, whose transactions on top of the first seen one are a malformed
+    /// one and then a parsed one, with the metrics it records parse failures in.
+    async fn poller_past_malformed_transaction(
+        strict: bool,
+    ) -> (EventPoller, Pubkey, Signature, Arc<EventMetrics>) {
+        let sender = PageSender::default();
+        let client = EllipsisClient::from_rpc(
+            RpcClient::new_sender(sender.clone(), RpcClientConfig::default()),
+            &Keypair::new(),
+        )
+        .unwrap();
+        let mut sdk = SDKClient::new_from_ellipsis_client(client).await.unwrap();
+        sdk.set_strict_event_parsing(strict);
+        let market = Pubkey::new_unique();
+        sdk.markets.insert(market, test_market_metadata());
+        let metrics = Arc::new(EventMetrics::default());
+        let mut poller =
+            EventPoller::new(Arc::new(sdk), vec![market]).with_metrics(metrics.clone());
+
+        let first_seen = Signature::new_unique();
+        sender.set_page(&market, &[(first_seen, false)]);
+        assert!(poller.poll().await.unwrap().is_empty());
+
+        // The older transaction's log is cut off mid-fill, which no retry fixes
+        let (malformed, parsed) = (Signature::new_unique(), Signature::new_unique());
+        sender.set_fill_transaction(&malformed, &market, 10);
+        sender.set_fill_transaction(&parsed, &market, 0);
+        sender.set_page(
+            &market,
+            &[(parsed, false), (malformed, false), (first_seen, false)],
+        );
+        (poller, market, parsed, metrics)
+    }
+
+    fn is_fill_batch(batch: &[SDKMarketEvent]) -> bool {
+        matches!(
+            batch,
+            [SDKMarketEvent::PhoenixEvent { event }]
+                if matches!(event.details, MarketEventDetails::Fill(_))
+        )
+    }
+
+    #[tokio::test]
+    async fn test_malformed_transaction_is_skipped_with_resync() {
+        let (mut poller, market, parsed, metrics) = poller_past_malformed_transaction(true).await;
+        let batches = poller.poll().await.unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(
+            batches[0],
+            vec![SDKMarketEvent::Control(ControlMsg::ResyncRequired {
+                market: Some(market)
+            })]
+        );
+        assert!(is_fill_batch(&batches[1]));
+        assert_eq!(poller.last_seen[&market], parsed);
+        assert_eq!(metrics.snapshot().parse_failures, 1);
+        assert!(poller.poll().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_salvaged_transaction_counts_as_parse_failure() {
+        // Nothing precedes the broken fill, so only the parsed transaction has a batch
+        let (mut poller, market, parsed, metrics) = poller_past_malformed_transaction(false).await;
+        let batches = poller.poll().await.unwrap();
+        assert_eq!(batches.len(), 1);
+        assert!(is_fill_batch(&batches[0]));
+        assert_eq!(poller.last_seen[&market], parsed);
+        assert_eq!(metrics.snapshot().parse_failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_records_backoff() {
+        let client = EllipsisClient::from_rpc(
+            RpcClient::new_sender(PageSender::default(), RpcClientConfig::default()),
+            &Keypair::new(),
+        )
+        .unwrap();
+        let sdk = Arc::new(SDKClient::new_from_ellipsis_client(client).await.unwrap());
+        let metrics = Arc::new(EventMetrics::default());
+        let config = PollScheduleConfig {
+            jitter: 0.0,
+            ..PollScheduleConfig::adaptive(Duration::from_millis(1), Duration::from_millis(8))
+        };
+        let mut poller = EventPoller::new(sdk, vec![Pubkey::new_unique()])
+            .with_schedule(config)
+            .with_metrics(metrics.clone());
+        let (sender, _receiver) = tokio::sync::mpsc::channel(16);
+
+        // No transactions ever land, so every poll is empty and the wait backs off to the cap
+        tokio::select! {
+            _ = poller.run(&sender) => unreachable!(),
+            _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+        }
+        assert_eq!(metrics.snapshot().poll_interval, Duration::from_millis(8));
+    }
+}
@@ -0,0 +1,367 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use phoenix_sdk_core::market_event::PhoenixEvent;
+use tokio::sync::mpsc::Receiver;
+
+use crate::event_sink::EventSink;
+use crate::sdk_market_event::SDKMarketEvent;
+
+/// A batch of events as it was received from the event channel, stamped with the local
+/// wall-clock time (unix milliseconds) at which it was recorded.
+///
+/// Each batch is stored on disk as a little-endian `u32` length prefix followed by the
+/// borsh encoding of this struct.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct RecordedBatch {
+    pub recorded_at_ms: u64,
+    pub events: Vec<PhoenixEvent>,
+}
+
+impl RecordedBatch {
+    pub fn to_sdk_market_events(&self) -> Vec<SDKMarketEvent> {
+        self.events
+            .iter()
+            .map(|&e| SDKMarketEvent::from(e))
+            .collect()
+    }
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Appends batches from an `SDKMarketEvent` channel to a record file.
+pub struct EventRecorder<W: Write> {
+    writer: W,
+}
+
+impl EventRecorder<BufWriter<File>> {
+    /// Opens `path` for appending, creating the file if it does not exist.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> EventRecorder<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Records a batch stamped with the current time. Heartbeats and control messages are not
+    /// recorded.
+    pub fn record(&mut self, batch: &[SDKMarketEvent]) -> Result<()> {
+        self.record_with_timestamp(batch, now_ms())
+    }
+
+    pub fn record_with_timestamp(
+        &mut self,
+        batch: &[SDKMarketEvent],
+        recorded_at_ms: u64,
+    ) -> Result<()> {
+        let events = batch
+            .iter()
+            .filter_map(|event| match event {
+                SDKMarketEvent::PhoenixEvent { event } => Some(**event),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let bytes = RecordedBatch {
+            recorded_at_ms,
+            events,
+        }
+        .try_to_vec()?;
+        let len = u32::try_from(bytes.len()).map_err(|_| anyhow!("Batch too large to record"))?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(&bytes)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Records every batch received on `receiver` until the channel closes. If `forward_to` is
+    /// provided, each batch is passed along after it is written so the recorder can sit in front
+    /// of an existing consumer. Forwarding stops if the downstream receiver is dropped.
+    pub async fn run<S: EventSink>(
+        mut self,
+        mut receiver: Receiver<Vec<SDKMarketEvent>>,
+        mut forward_to: Option<S>,
+    ) -> Result<()> {
+        while let Some(batch) = receiver.recv().await {
+            self.record(&batch)?;
+            if let Some(sender) = forward_to.as_ref() {
+                if sender.send_events(batch).await.is_err() {
+                    forward_to = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Controls how quickly an `EventReplayer` sends batches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Send batches back-to-back without waiting.
+    AsFastAsPossible,
+    /// Wait the original gap between batches divided by `speed_multiplier`
+    /// (e.g. 2.0 replays twice as fast as real time).
+    Throttled { speed_multiplier: f64 },
+}
+
+/// Reads batches written by an `EventRecorder`.
+pub struct EventReplayer<R: Read> {
+    reader: R,
+}
+
+impl EventReplayer<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read> EventReplayer<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Returns the next recorded batch, or `None` at a clean end of file.
+    pub fn next_batch(&mut self) -> Result<Option<RecordedBatch>> {
+        let mut len_bytes = [0u8; 4];
+        match self.reader.read_exact(&mut len_bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut bytes = vec![0u8; u32::from_le_bytes(len_bytes) as usize];
+        self.reader
+            .read_exact(&mut bytes)
+            .map_err(|e| anyhow!("Truncated record: {}", e))?;
+        Ok(Some(RecordedBatch::try_from_slice(&bytes)?))
+    }
+
+    /// Sends every remaining batch into `sender`, returning the number of batches replayed.
+    pub async fn replay<S: EventSink>(mut self, sender: S, speed: ReplaySpeed) -> Result<usize> {
+        let mut previous_recorded_at_ms = None;
+        let mut count = 0;
+        while let Some(batch) = self.next_batch()? {
+            if let (ReplaySpeed::Throttled { speed_multiplier }, Some(previous)) =
+                (speed, previous_recorded_at_ms)
+            {
+                let gap_ms = batch.recorded_at_ms.saturating_sub(previous) as f64;
+                if speed_multiplier > 0.0 && gap_ms > 0.0 {
+                    tokio::time::sleep(Duration::from_secs_f64(gap_ms / speed_multiplier / 1000.0))
+                        .await;
+                }
+            }
+            previous_recorded_at_ms = Some(batch.recorded_at_ms);
+            sender
+                .send_events(batch.to_sdk_market_events())
+                .await
+                .map_err(|e| anyhow!("Replay sink closed: {}", e))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl<R: Read> Iterator for EventReplayer<R> {
+    type Item = Result<RecordedBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_batch().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use phoenix::state::Side;
+    use phoenix_sdk_core::market_event::{Fill, FillSummary, MarketEventDetails, Place};
+    use phoenix_sdk_core::test_utils::event;
+    use solana_sdk::{pubkey::Pubkey, signature::Signature};
+    use std::io::Cursor;
+    use std::time::Instant;
+    use tokio::sync::mpsc::channel;
+
+    /// An event with every field set, so the round trips cover them all
+    fn recorded(
+        sequence_number: u64,
+        event_index: u64,
+        details: MarketEventDetails,
+    ) -> PhoenixEvent {
+        PhoenixEvent {
+            sequence_number,
+            slot: 200 + sequence_number,
+            timestamp: 1_700_000_000 + sequence_number as i64,
+            signature: Signature::new_unique(),
+            signer: Pubkey::new_unique(),
+            event_index,
+            ..event(Pubkey::new_unique(), details)
+        }
+    }
+
+    fn sample_batches() -> Vec<Vec<SDKMarketEvent>> {
+        let place = MarketEventDetails::Place(Place {
+            order_sequence_number: 7,
+            client_order_id: 42,
+            maker: Pubkey::new_unique(),
+            price_in_ticks: 1000,
+            base_lots_placed: 10,
+        });
+        let fill = MarketEventDetails::Fill(Fill {
+            order_sequence_number: 7,
+            maker: Pubkey::new_unique(),
+            taker: Pubkey::new_unique(),
+            price_in_ticks: 1000,
+            base_lots_filled: 4,
+            base_lots_remaining: 6,
+            side_filled: Side::Ask,
+            maker_side: Side::Ask,
+            taker_side: Side::Ask.opposite(),
+            is_full_fill: false,
+        });
+        let summary = MarketEventDetails::FillSummary(FillSummary {
+            client_order_id: 1,
+            total_base_filled: 400,
+            total_quote_filled_including_fees: 4000,
+            total_quote_fees: 2,
+            trade_direction: 1,
+        });
+        vec![
+            vec![recorded(1, 0, place).into()],
+            vec![recorded(2, 0, fill).into(), recorded(2, 1, summary).into()],
+            vec![],
+            vec![recorded(3, 0, MarketEventDetails::Fee(17)).into()],
+        ]
+    }
+
+    fn to_bytes(batches: &[Vec<SDKMarketEvent>]) -> Vec<Vec<u8>> {
+        batches
+            .iter()
+            .map(|batch| {
+                batch
+                    .iter()
+                    .flat_map(|message| match message {
+                        SDKMarketEvent::PhoenixEvent { event } => event.try_to_vec().unwrap(),
+                        _ => unreachable!(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_record_from_channel_to_file() {
+        let batches = sample_batches();
+        let path =
+            std::env::temp_dir().join(format!("phoenix-events-{}.bin", Pubkey::new_unique()));
+        let (in_sender, in_receiver) = channel(16);
+        let (fwd_sender, mut fwd_receiver) = channel(16);
+        for batch in batches.iter() {
+            in_sender.send(batch.clone()).await.unwrap();
+        }
+        drop(in_sender);
+
+        EventRecorder::create(&path)
+            .unwrap()
+            .run(in_receiver, Some(fwd_sender))
+            .await
+            .unwrap();
+
+        let mut forwarded = vec![];
+        while let Some(batch) = fwd_receiver.recv().await {
+            forwarded.push(batch);
+        }
+        assert_eq!(forwarded, batches);
+
+        let recorded = EventReplayer::open(&path)
+            .unwrap()
+            .map(|batch| batch.unwrap().to_sdk_market_events())
+            .collect::<Vec<_>>();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(recorded, batches);
+    }
+
+    #[tokio::test]
+    async fn test_replayed_stream_is_identical() {
+        let batches = sample_batches();
+        let mut recorder = EventRecorder::new(Vec::new());
+        for (i, batch) in batches.iter().enumerate() {
+            recorder
+                .record_with_timestamp(batch, 1_000 + i as u64 * 10)
+                .unwrap();
+        }
+        let bytes = recorder.into_inner();
+
+        let (sender, mut receiver) = channel(16);
+        let replayed_count = EventReplayer::new(Cursor::new(bytes))
+            .replay(sender, ReplaySpeed::AsFastAsPossible)
+            .await
+            .unwrap();
+        assert_eq!(replayed_count, batches.len());
+
+        let mut replayed = vec![];
+        while let Some(batch) = receiver.recv().await {
+            replayed.push(batch);
+        }
+        assert_eq!(to_bytes(&replayed), to_bytes(&batches));
+        assert_eq!(replayed, batches);
+    }
+
+    #[tokio::test]
+    async fn test_throttled_replay_respects_gaps() {
+        let batches = sample_batches();
+        let mut recorder = EventRecorder::new(Vec::new());
+        for (i, batch) in batches.iter().enumerate() {
+            recorder
+                .record_with_timestamp(batch, i as u64 * 200)
+                .unwrap();
+        }
+        let (sender, mut receiver) = channel(16);
+        let start = Instant::now();
+        let handle = tokio::spawn(
+            EventReplayer::new(Cursor::new(recorder.into_inner())).replay(
+                sender,
+                ReplaySpeed::Throttled {
+                    speed_multiplier: 10.0,
+                },
+            ),
+        );
+        let mut replayed = vec![];
+        while let Some(batch) = receiver.recv().await {
+            replayed.push(batch);
+        }
+        handle.await.unwrap().unwrap();
+        // 3 gaps of 200ms at 10x speed
+        assert!(start.elapsed() >= Duration::from_millis(60));
+        assert_eq!(replayed, batches);
+    }
+
+    #[test]
+    fn test_truncated_record_is_an_error() {
+        let mut recorder = EventRecorder::new(Vec::new());
+        recorder
+            .record_with_timestamp(&sample_batches()[1], 0)
+            .unwrap();
+        let mut bytes = recorder.into_inner();
+        bytes.truncate(bytes.len() - 3);
+        let mut replayer = EventReplayer::new(Cursor::new(bytes));
+        assert!(replayer.next_batch().is_err());
+        assert!(EventReplayer::new(Cursor::new(vec![]))
+            .next_batch()
+            .unwrap()
+            .is_none());
+    }
+}
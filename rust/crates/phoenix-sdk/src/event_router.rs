@@ -0,0 +1,511 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+use crate::event_sink::EventSink;
+use crate::sdk_market_event::{ControlMsg, SDKMarketEvent};
+
+/// What the router does when a subscriber's buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait for the subscriber to make room. A slow subscriber delays every other subscriber.
+    Wait,
+    /// Drop the subscriber's oldest undelivered batch. The receiver counts what it missed in
+    /// `dropped_batches`.
+    DropOldest,
+}
+
+/// Fans one stream of event batches out to per-market subscribers.
+///
+/// A subscriber to a market receives, for each inbound batch with events on that market, a batch
+/// of just those events in their original order. Heartbeats go to the subscribers of their market,
+/// a `ControlMsg::Rollback` is split by market like events, and other control messages go to the
+/// subscribers of their market, or to every subscriber if they name none. A wildcard subscriber
+/// receives every batch unchanged.
+///
+/// If routing is restarted with `route_from`, e.g. by a supervisor after a panic, a batch may have
+/// reached only some subscribers, so every subscriber is sent a `ControlMsg::ResyncRequired` first.
+///
+/// The router is a cheap handle: clone it to subscribe from elsewhere after `spawn`. Subscribers
+/// receive batches routed after they subscribe, and dropping a `RoutedReceiver` unsubscribes it.
+///
+/// Evictions of a trader's orders also go out on their own channels, see `subscribe_evictions`.
+#[derive(Clone)]
+pub struct EventRouter {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+    eviction_subscribers: Arc<Mutex<Vec<EvictionSubscriber>>>,
+    capacity: usize,
+    started: Arc<AtomicBool>,
+}
+
+struct EvictionSubscriber {
+    trader: Pubkey,
+    sender: mpsc::UnboundedSender<PhoenixEvent>,
+}
+
+struct Subscriber {
+    market: Option<Pubkey>,
+    sender: SubscriberSender,
+}
+
+#[derive(Clone)]
+enum SubscriberSender {
+    Wait(mpsc::Sender<Vec<SDKMarketEvent>>),
+    DropOldest(broadcast::Sender<Vec<SDKMarketEvent>>),
+}
+
+impl SubscriberSender {
+    fn is_closed(&self) -> bool {
+        match self {
+            SubscriberSender::Wait(sender) => sender.is_closed(),
+            SubscriberSender::DropOldest(sender) => sender.receiver_count() == 0,
+        }
+    }
+}
+
+impl EventRouter {
+    /// Each subscriber buffers up to `capacity` batches.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            subscribers: Default::default(),
+            eviction_subscribers: Default::default(),
+            capacity: capacity.max(1),
+            started: Default::default(),
+        }
+    }
+
+    /// Receives the events on `market`.
+    pub fn subscribe(&self, market: Pubkey, backpressure: Backpressure) -> RoutedReceiver {
+        self.add_subscriber(Some(market), backpressure)
+    }
+
+    /// Receives every batch, whatever its markets.
+    pub fn subscribe_all(&self, backpressure: Backpressure) -> RoutedReceiver {
+        self.add_subscriber(None, backpressure)
+    }
+
+    /// Receives the `Evict` events that remove `trader`'s orders, on any market. They are sent
+    /// before the batch that carries them reaches any other subscriber, and the channel is
+    /// unbounded, so an eviction is never delayed or dropped by a slow subscriber. An eviction
+    /// means the trader's seat is gone, so quoting on the market should stop.
+    pub fn subscribe_evictions(&self, trader: Pubkey) -> mpsc::UnboundedReceiver<PhoenixEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.eviction_subscribers()
+            .push(EvictionSubscriber { trader, sender });
+        receiver
+    }
+
+    /// Subscribers whose receivers are still alive.
+    pub fn subscriber_count(&self) -> usize {
+        let mut subscribers = self.subscribers();
+        subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+        subscribers.len()
+    }
+
+    /// Routes one batch to the subscribers.
+    pub async fn route(&self, batch: Vec<SDKMarketEvent>) {
+        self.route_evictions(&batch);
+        let targets = {
+            let mut subscribers = self.subscribers();
+            subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+            subscribers
+                .iter()
+                .map(|subscriber| (subscriber.market, subscriber.sender.clone()))
+                .collect::<Vec<_>>()
+        };
+        for (market, sender) in targets {
+            let events = match market {
+                Some(market) => {
+                    let events = events_on_market(&batch, market);
+                    if events.is_empty() {
+                        continue;
+                    }
+                    events
+                }
+                None => batch.clone(),
+            };
+            // A closed subscriber is removed on the next batch
+            match sender {
+                SubscriberSender::Wait(sender) => {
+                    let _ = sender.send(events).await;
+                }
+                SubscriberSender::DropOldest(sender) => {
+                    let _ = sender.send(events);
+                }
+            }
+        }
+    }
+
+    /// Routes every batch from `receiver` until it closes, then closes all subscriptions.
+    pub async fn run(&self, mut receiver: mpsc::Receiver<Vec<SDKMarketEvent>>) {
+        self.route_from(&mut receiver).await
+    }
+
+    /// Like `run`, but borrows the receiver, so a restarted router can resume reading from it.
+    pub async fn route_from(&self, receiver: &mut mpsc::Receiver<Vec<SDKMarketEvent>>) {
+        if self.started.swap(true, Ordering::Relaxed) {
+            self.route(vec![ControlMsg::ResyncRequired { market: None }.into()])
+                .await;
+        }
+        while let Some(batch) = receiver.recv().await {
+            self.route(batch).await;
+        }
+        self.subscribers().clear();
+        self.eviction_subscribers().clear();
+    }
+
+    /// Spawns `run` on the current tokio runtime.
+    pub fn spawn(&self, receiver: mpsc::Receiver<Vec<SDKMarketEvent>>) -> JoinHandle<()> {
+        let router = self.clone();
+        tokio::spawn(async move { router.run(receiver).await })
+    }
+
+    fn route_evictions(&self, batch: &[SDKMarketEvent]) {
+        let mut subscribers = self.eviction_subscribers();
+        subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+        if subscribers.is_empty() {
+            return;
+        }
+        for message in batch {
+            let SDKMarketEvent::PhoenixEvent { event } = message else {
+                continue;
+            };
+            let MarketEventDetails::Evict(evict) = &event.details else {
+                continue;
+            };
+            for subscriber in subscribers.iter() {
+                if subscriber.trader == evict.maker {
+                    let _ = subscriber.sender.send(**event);
+                }
+            }
+        }
+    }
+
+    fn add_subscriber(&self, market: Option<Pubkey>, backpressure: Backpressure) -> RoutedReceiver {
+        let (sender, receiver) = match backpressure {
+            Backpressure::Wait => {
+                let (sender, receiver) = mpsc::channel(self.capacity);
+                (SubscriberSender::Wait(sender), ReceiverKind::Wait(receiver))
+            }
+            Backpressure::DropOldest => {
+                let (sender, receiver) = broadcast::channel(self.capacity);
+                (
+                    SubscriberSender::DropOldest(sender),
+                    ReceiverKind::DropOldest(receiver),
+                )
+            }
+        };
+        self.subscribers().push(Subscriber { market, sender });
+        RoutedReceiver {
+            market,
+            receiver,
+            dropped_batches: 0,
+        }
+    }
+
+    fn subscribers(&self) -> MutexGuard<'_, Vec<Subscriber>> {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn eviction_subscribers(&self) -> MutexGuard<'_, Vec<EvictionSubscriber>> {
+        self.eviction_subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl EventSink for EventRouter {
+    /// Never fails, since subscribers may come and go at any time.
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()> {
+        self.route(events).await;
+        Ok(())
+    }
+}
+
+/// A subscription created by `EventRouter::subscribe` or `subscribe_all`.
+pub struct RoutedReceiver {
+    market: Option<Pubkey>,
+    receiver: ReceiverKind,
+    dropped_batches: u64,
+}
+
+enum ReceiverKind {
+    Wait(mpsc::Receiver<Vec<SDKMarketEvent>>),
+    DropOldest(broadcast::Receiver<Vec<SDKMarketEvent>>),
+}
+
+impl RoutedReceiver {
+    /// The next batch, or `None` once the router has stopped. After falling behind, the next
+    /// batch is a `ControlMsg::Gap` with the number of batches dropped.
+    pub async fn recv(&mut self) -> Option<Vec<SDKMarketEvent>> {
+        match &mut self.receiver {
+            ReceiverKind::Wait(receiver) => receiver.recv().await,
+            ReceiverKind::DropOldest(receiver) => match receiver.recv().await {
+                Ok(batch) => Some(batch),
+                Err(RecvError::Lagged(missed)) => {
+                    self.dropped_batches += missed;
+                    Some(vec![ControlMsg::Gap {
+                        market: self.market,
+                        missed_batches: missed,
+                    }
+                    .into()])
+                }
+                Err(RecvError::Closed) => None,
+            },
+        }
+    }
+
+    /// Batches dropped because this subscriber fell behind. Always 0 with `Backpressure::Wait`.
+    pub fn dropped_batches(&self) -> u64 {
+        self.dropped_batches
+    }
+}
+
+/// The messages of `batch` for the subscribers of `market`, in order.
+fn events_on_market(batch: &[SDKMarketEvent], market: Pubkey) -> Vec<SDKMarketEvent> {
+    batch
+        .iter()
+        .filter_map(|message| match message {
+            SDKMarketEvent::PhoenixEvent { event } => {
+                (event.market == market).then(|| message.clone())
+            }
+            SDKMarketEvent::Heartbeat {
+                market: heartbeat_market,
+                ..
+            } => (*heartbeat_market == market).then(|| message.clone()),
+            SDKMarketEvent::Control(ControlMsg::Rollback { signature, events }) => {
+                let events = events
+                    .iter()
+                    .filter(|event| event.market == market)
+                    .copied()
+                    .collect::<Vec<_>>();
+                (!events.is_empty()).then(|| SDKMarketEvent::rollback(*signature, events))
+            }
+            SDKMarketEvent::Control(control) => control
+                .market()
+                .is_none_or(|control_market| control_market == market)
+                .then(|| message.clone()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix_sdk_core::market_event::{Evict, Fill};
+    use phoenix_sdk_core::test_utils::event;
+    use solana_sdk::signature::Signature;
+
+    use super::*;
+    use crate::event_recorder::{EventRecorder, EventReplayer, ReplaySpeed};
+
+    fn fill(market: Pubkey, sequence_number: u64) -> PhoenixEvent {
+        let fill = Fill::new(
+            sequence_number,
+            Pubkey::default(),
+            Pubkey::default(),
+            1000,
+            1,
+            0,
+        );
+        PhoenixEvent {
+            sequence_number,
+            slot: 100 + sequence_number,
+            ..event(market, MarketEventDetails::Fill(fill))
+        }
+    }
+
+    fn sequence_numbers(batch: &[SDKMarketEvent]) -> Vec<u64> {
+        batch
+            .iter()
+            .map(|message| match message {
+                SDKMarketEvent::PhoenixEvent { event } => event.sequence_number,
+                other => panic!("Unexpected {:?}", other),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_routes_recorded_stream() {
+        let (sol, eth) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut recorder = EventRecorder::new(vec![]);
+        for batch in [
+            vec![fill(sol, 1), fill(eth, 2), fill(sol, 3)],
+            vec![fill(eth, 4)],
+            vec![fill(sol, 5)],
+        ] {
+            let batch = batch.into_iter().map(Into::into).collect::<Vec<_>>();
+            recorder.record(&batch).unwrap();
+        }
+        let recording = recorder.into_inner();
+
+        let router = EventRouter::new(16);
+        let mut sol_events = router.subscribe(sol, Backpressure::Wait);
+        let mut eth_events = router.subscribe(eth, Backpressure::DropOldest);
+        let mut all_events = router.subscribe_all(Backpressure::Wait);
+        let dropped = router.subscribe(sol, Backpressure::Wait);
+        drop(dropped);
+        assert_eq!(router.subscriber_count(), 3);
+
+        let (sender, receiver) = mpsc::channel(16);
+        let task = router.spawn(receiver);
+        EventReplayer::new(recording.as_slice())
+            .replay(sender, ReplaySpeed::AsFastAsPossible)
+            .await
+            .unwrap();
+        task.await.unwrap();
+
+        let mut received = vec![];
+        while let Some(batch) = sol_events.recv().await {
+            received.push(sequence_numbers(&batch));
+        }
+        assert_eq!(received, vec![vec![1, 3], vec![5]]);
+        let mut received = vec![];
+        while let Some(batch) = eth_events.recv().await {
+            received.push(sequence_numbers(&batch));
+        }
+        assert_eq!(received, vec![vec![2], vec![4]]);
+        let mut received = vec![];
+        while let Some(batch) = all_events.recv().await {
+            received.push(sequence_numbers(&batch));
+        }
+        assert_eq!(received, vec![vec![1, 2, 3], vec![4], vec![5]]);
+        assert_eq!(router.subscriber_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_rollbacks_and_backpressure() {
+        let (sol, eth) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let router = EventRouter::new(2);
+        let mut sol_events = router.subscribe(sol, Backpressure::Wait);
+        let mut eth_events = router.subscribe(eth, Backpressure::DropOldest);
+
+        // A rollback spanning both markets is split between them
+        let signature = Signature::new_unique();
+        let rolled_back = [fill(sol, 1), fill(eth, 2), fill(sol, 3)];
+        router
+            .route(vec![SDKMarketEvent::rollback(
+                signature,
+                rolled_back.to_vec(),
+            )])
+            .await;
+        assert_eq!(
+            sol_events.recv().await.unwrap(),
+            vec![SDKMarketEvent::rollback(
+                signature,
+                vec![rolled_back[0], rolled_back[2]]
+            )]
+        );
+
+        // A slow drop-oldest subscriber loses its oldest batches without holding up the router
+        let _ = eth_events.recv().await.unwrap();
+        for sequence_number in 10..14 {
+            router.route(vec![fill(eth, sequence_number).into()]).await;
+        }
+        assert_eq!(
+            eth_events.recv().await.unwrap(),
+            vec![SDKMarketEvent::Control(ControlMsg::Gap {
+                market: Some(eth),
+                missed_batches: 2,
+            })]
+        );
+        assert_eq!(
+            sequence_numbers(&eth_events.recv().await.unwrap()),
+            vec![12]
+        );
+        assert_eq!(eth_events.dropped_batches(), 2);
+        assert_eq!(
+            sequence_numbers(&eth_events.recv().await.unwrap()),
+            vec![13]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_evictions_skip_the_queue() {
+        let (sol, trader) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let evict = |sequence_number, maker| PhoenixEvent {
+            details: MarketEventDetails::Evict(Evict {
+                order_sequence_number: sequence_number,
+                maker,
+                price_in_ticks: 1000,
+                base_lots_evicted: 5,
+            }),
+            ..fill(sol, sequence_number)
+        };
+        let router = EventRouter::new(1);
+        let mut stalled = router.subscribe(sol, Backpressure::Wait);
+        let mut evictions = router.subscribe_evictions(trader);
+
+        // The stalled subscriber's buffer is full, so the next batch waits for it
+        router.route(vec![fill(sol, 1).into()]).await;
+        let batch = vec![
+            fill(sol, 2).into(),
+            evict(3, Pubkey::new_unique()).into(),
+            evict(4, trader).into(),
+        ];
+        let routing = tokio::spawn({
+            let router = router.clone();
+            async move { router.route(batch).await }
+        });
+        let eviction = evictions.recv().await.unwrap();
+        assert_eq!(eviction.sequence_number, 4);
+        assert!(!routing.is_finished());
+
+        assert_eq!(sequence_numbers(&stalled.recv().await.unwrap()), vec![1]);
+        assert_eq!(
+            sequence_numbers(&stalled.recv().await.unwrap()),
+            vec![2, 3, 4]
+        );
+        routing.await.unwrap();
+        assert!(evictions.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_forwards_heartbeats_and_control() {
+        let (sol, eth) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let router = EventRouter::new(16);
+        let mut sol_events = router.subscribe(sol, Backpressure::Wait);
+        let mut all_events = router.subscribe_all(Backpressure::Wait);
+
+        let heartbeat = |market| SDKMarketEvent::Heartbeat {
+            market,
+            chain_slot: 200,
+            poller_lag: 3,
+        };
+        let eth_gap: SDKMarketEvent = ControlMsg::Gap {
+            market: Some(eth),
+            missed_batches: 1,
+        }
+        .into();
+        let resync: SDKMarketEvent = ControlMsg::ResyncRequired { market: None }.into();
+        let batch = vec![heartbeat(sol), heartbeat(eth), eth_gap, resync.clone()];
+        router.route(batch.clone()).await;
+        assert_eq!(
+            sol_events.recv().await.unwrap(),
+            vec![heartbeat(sol), resync.clone()]
+        );
+        assert_eq!(all_events.recv().await.unwrap(), batch);
+
+        // A restarted router tells every subscriber to resync before routing again
+        let (sender, mut receiver) = mpsc::channel(16);
+        sender.send(vec![fill(sol, 1).into()]).await.unwrap();
+        drop(sender);
+        let restarted = router.clone();
+        restarted.started.store(true, Ordering::Relaxed);
+        let mut replacement = router.subscribe(eth, Backpressure::Wait);
+        restarted.route_from(&mut receiver).await;
+        assert_eq!(sol_events.recv().await.unwrap(), vec![resync.clone()]);
+        assert_eq!(sequence_numbers(&sol_events.recv().await.unwrap()), vec![1]);
+        assert_eq!(replacement.recv().await.unwrap(), vec![resync]);
+        assert!(replacement.recv().await.is_none());
+    }
+}
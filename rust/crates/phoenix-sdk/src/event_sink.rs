@@ -0,0 +1,226 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::sdk_client::ParsedPhoenixTransaction;
+use crate::sdk_market_event::SDKMarketEvent;
+
+/// A destination for batches of market events.
+///
+/// Producers (the paper exchange, `EventRecorder` forwarding and `EventReplayer`) accept any sink,
+/// so one stream can feed several consumers without a separate fan-out task:
+/// - `mpsc::Sender` delivers to a single consumer and applies backpressure when its buffer is full.
+/// - `broadcast::Sender` delivers every batch to every subscribed receiver. A receiver that falls
+///   more than the channel capacity behind gets `RecvError::Lagged` with the number of batches it
+///   missed and continues from the oldest retained batch; the producer is never blocked or stopped.
+///   `BroadcastSink` does the same and also counts the batches sent while a receiver was full.
+/// - `FnSink` calls a closure with each batch.
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    /// Delivers a batch. An error means the sink is closed and the producer should stop using it.
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()>;
+
+    /// Records a transaction's fee and compute units, sent by `BackfillJob` after the
+    /// transaction's events. Ignored by default; `EventStore` keeps them.
+    async fn record_transaction(&self, _transaction: &ParsedPhoenixTransaction) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for dyn EventSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventSink")
+    }
+}
+
+#[async_trait]
+impl<S: EventSink + ?Sized> EventSink for &S {
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()> {
+        (**self).send_events(events).await
+    }
+
+    async fn record_transaction(&self, transaction: &ParsedPhoenixTransaction) -> Result<()> {
+        (**self).record_transaction(transaction).await
+    }
+}
+
+#[async_trait]
+impl<S: EventSink + ?Sized> EventSink for Arc<S> {
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()> {
+        (**self).send_events(events).await
+    }
+
+    async fn record_transaction(&self, transaction: &ParsedPhoenixTransaction) -> Result<()> {
+        (**self).record_transaction(transaction).await
+    }
+}
+
+#[async_trait]
+impl EventSink for mpsc::Sender<Vec<SDKMarketEvent>> {
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()> {
+        self.send(events)
+            .await
+            .map_err(|_| anyhow!("Event receiver dropped"))
+    }
+}
+
+#[async_trait]
+impl EventSink for broadcast::Sender<Vec<SDKMarketEvent>> {
+    /// Succeeds even when nobody is subscribed, since receivers may subscribe at any time. Use
+    /// `BroadcastSink` to find out when a slow receiver loses batches.
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()> {
+        let _ = self.send(events);
+        Ok(())
+    }
+}
+
+/// A broadcast channel that counts the batches overwritten before the slowest receiver read them.
+pub struct BroadcastSink {
+    sender: broadcast::Sender<Vec<SDKMarketEvent>>,
+    capacity: usize,
+    overwritten_batches: AtomicU64,
+    lag_sender: Option<mpsc::UnboundedSender<u64>>,
+}
+
+impl BroadcastSink {
+    /// Each receiver buffers up to `capacity` batches.
+    pub fn new(capacity: usize) -> (Self, broadcast::Receiver<Vec<SDKMarketEvent>>) {
+        let (sender, receiver) = broadcast::channel(capacity);
+        let sink = Self {
+            sender,
+            // The channel rounds its buffer up to a power of two
+            capacity: capacity.next_power_of_two(),
+            overwritten_batches: AtomicU64::new(0),
+            lag_sender: None,
+        };
+        (sink, receiver)
+    }
+
+    /// Sends the running count of overwritten batches each time a send overwrites one.
+    pub fn with_lag_sender(mut self, lag_sender: mpsc::UnboundedSender<u64>) -> Self {
+        self.lag_sender = Some(lag_sender);
+        self
+    }
+
+    /// Adds a receiver that gets the batches sent from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Vec<SDKMarketEvent>> {
+        self.sender.subscribe()
+    }
+
+    /// Batches overwritten so far; some receiver got `RecvError::Lagged` for each of them.
+    pub fn overwritten_batches(&self) -> u64 {
+        self.overwritten_batches.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl EventSink for BroadcastSink {
+    /// Succeeds even when nobody is subscribed or a receiver is full, like `broadcast::Sender`.
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()> {
+        let full = self.sender.len() >= self.capacity;
+        if self.sender.send(events).is_ok() && full {
+            let overwritten = self.overwritten_batches.fetch_add(1, Ordering::Relaxed) + 1;
+            if let Some(lag_sender) = &self.lag_sender {
+                let _ = lag_sender.send(overwritten);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Calls a closure with each batch. Returning an error closes the sink.
+pub struct FnSink<F>(pub F);
+
+#[async_trait]
+impl<F> EventSink for FnSink<F>
+where
+    F: Fn(Vec<SDKMarketEvent>) -> Result<()> + Send + Sync,
+{
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()> {
+        (self.0)(events)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use phoenix::state::enums::Side;
+    use tokio::sync::broadcast::error::RecvError;
+
+    use super::*;
+    use crate::paper_trading::test_utils::paper_client;
+
+    #[tokio::test]
+    async fn test_broadcast_subscribers_receive_identical_batches() {
+        let (sdk, market) = paper_client(&[(Side::Ask, 100, 1000)]).await;
+        let (sender, mut first) = broadcast::channel(16);
+        let mut second = sender.subscribe();
+        sdk.paper_exchange().set_event_sender(Some(sender));
+
+        for _ in 0..2 {
+            let ix = sdk
+                .get_ioc_from_tick_price_generic_ix(&market, 100, Side::Bid, 100, None, None, None)
+                .unwrap();
+            sdk.send_ixs(vec![ix]).await.unwrap();
+        }
+        for _ in 0..2 {
+            let batch = first.recv().await.unwrap();
+            assert!(!batch.is_empty());
+            assert_eq!(batch, second.recv().await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_does_not_stop_producer() {
+        let (sender, mut receiver) = broadcast::channel(1);
+        let sink: Box<dyn EventSink> = Box::new(sender.clone());
+        for _ in 0..3 {
+            sink.send_events(vec![]).await.unwrap();
+        }
+        assert!(matches!(receiver.recv().await, Err(RecvError::Lagged(2))));
+        assert!(receiver.recv().await.is_ok());
+
+        let received = Arc::new(Mutex::new(0));
+        let counter = received.clone();
+        let sink = FnSink(move |events: Vec<SDKMarketEvent>| {
+            *counter.lock().unwrap() += 1 + events.len();
+            Ok(())
+        });
+        sink.send_events(vec![]).await.unwrap();
+        assert_eq!(*received.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_sink_reports_overwritten_batches() {
+        let (lag_sender, mut lags) = mpsc::unbounded_channel();
+        let (sink, mut receiver) = BroadcastSink::new(1);
+        let sink = sink.with_lag_sender(lag_sender);
+        sink.send_events(vec![]).await.unwrap();
+        assert_eq!(sink.overwritten_batches(), 0);
+
+        sink.send_events(vec![]).await.unwrap();
+        sink.send_events(vec![]).await.unwrap();
+        assert_eq!(sink.overwritten_batches(), 2);
+        assert_eq!(lags.try_recv().unwrap(), 1);
+        assert_eq!(lags.try_recv().unwrap(), 2);
+        assert!(matches!(receiver.recv().await, Err(RecvError::Lagged(2))));
+
+        // Once the receiver catches up, sends stop overwriting
+        assert!(receiver.recv().await.is_ok());
+        sink.send_events(vec![]).await.unwrap();
+        assert_eq!(sink.overwritten_batches(), 2);
+        assert!(lags.try_recv().is_err());
+
+        // Nothing is overwritten without receivers
+        drop(receiver);
+        for _ in 0..3 {
+            sink.send_events(vec![]).await.unwrap();
+        }
+        assert_eq!(sink.overwritten_batches(), 2);
+    }
+}
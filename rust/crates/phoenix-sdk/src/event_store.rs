@@ -0,0 +1,583 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use phoenix::state::enums::Side;
+use phoenix_sdk_core::market_event::{Fill, MarketEventDetails, PhoenixEvent};
+use rusqlite::{params, Connection, Row};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::backfill::{BackfillCheckpoint, Checkpointer};
+use crate::event_sink::EventSink;
+use crate::sdk_client::ParsedPhoenixTransaction;
+use crate::sdk_market_event::{ControlMsg, SDKMarketEvent};
+
+/// Schema changes, applied in order. `PRAGMA user_version` records how many have been applied, so
+/// new migrations must only ever be appended.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE fills (
+        market TEXT NOT NULL,
+        sequence_number INTEGER NOT NULL,
+        event_index INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        signature TEXT NOT NULL,
+        signer TEXT NOT NULL,
+        order_sequence_number INTEGER NOT NULL,
+        maker TEXT NOT NULL,
+        taker TEXT NOT NULL,
+        price_in_ticks INTEGER NOT NULL,
+        base_lots_filled INTEGER NOT NULL,
+        base_lots_remaining INTEGER NOT NULL,
+        side_filled INTEGER NOT NULL,
+        is_full_fill INTEGER NOT NULL,
+        PRIMARY KEY (market, sequence_number, event_index)
+    );
+    CREATE INDEX fills_by_time ON fills (market, timestamp);
+    CREATE INDEX fills_by_maker ON fills (maker);
+    CREATE INDEX fills_by_taker ON fills (taker);
+    CREATE TABLE places (
+        market TEXT NOT NULL,
+        sequence_number INTEGER NOT NULL,
+        event_index INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        signature TEXT NOT NULL,
+        signer TEXT NOT NULL,
+        order_sequence_number INTEGER NOT NULL,
+        client_order_id TEXT NOT NULL,
+        maker TEXT NOT NULL,
+        price_in_ticks INTEGER NOT NULL,
+        base_lots_placed INTEGER NOT NULL,
+        PRIMARY KEY (market, sequence_number, event_index)
+    );
+    CREATE TABLE reduces (
+        market TEXT NOT NULL,
+        sequence_number INTEGER NOT NULL,
+        event_index INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        signature TEXT NOT NULL,
+        signer TEXT NOT NULL,
+        order_sequence_number INTEGER NOT NULL,
+        maker TEXT NOT NULL,
+        price_in_ticks INTEGER NOT NULL,
+        base_lots_removed INTEGER NOT NULL,
+        base_lots_remaining INTEGER NOT NULL,
+        is_full_cancel INTEGER NOT NULL,
+        PRIMARY KEY (market, sequence_number, event_index)
+    );
+    CREATE TABLE evictions (
+        market TEXT NOT NULL,
+        sequence_number INTEGER NOT NULL,
+        event_index INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        signature TEXT NOT NULL,
+        signer TEXT NOT NULL,
+        order_sequence_number INTEGER NOT NULL,
+        maker TEXT NOT NULL,
+        price_in_ticks INTEGER NOT NULL,
+        base_lots_evicted INTEGER NOT NULL,
+        PRIMARY KEY (market, sequence_number, event_index)
+    );",
+    "CREATE TABLE backfill_checkpoints (
+        job_id TEXT PRIMARY KEY,
+        checkpoint TEXT NOT NULL
+    );",
+    "CREATE TABLE transactions (
+        signature TEXT PRIMARY KEY,
+        slot INTEGER NOT NULL,
+        block_time INTEGER,
+        signer TEXT NOT NULL,
+        fee_lamports INTEGER NOT NULL,
+        compute_units INTEGER,
+        is_err INTEGER NOT NULL
+    );
+    CREATE INDEX transactions_by_signer ON transactions (signer, slot);",
+];
+
+const FILL_COLUMNS: &str = "market, sequence_number, event_index, slot, timestamp, signature, \
+    signer, order_sequence_number, maker, taker, price_in_ticks, base_lots_filled, \
+    base_lots_remaining, side_filled, is_full_fill";
+
+/// Archives fills, places, reduces and evictions in a SQLite database, one table per event type,
+/// and the fees and compute units of the transactions recorded with them.
+///
+/// Rows are keyed by (market, sequence_number, event_index) and duplicates are ignored, so the same
+/// events can safely be written more than once, e.g. when replaying a recording over a live
+/// archive. Other event types are not stored. As an `EventSink`, rollback messages delete the
+/// rolled-back transaction's rows. u64 columns are stored bit-for-bit as SQLite's
+/// signed 64-bit integers, so bid order sequence numbers appear negative when queried directly.
+pub struct EventStore {
+    connection: Mutex<Connection>,
+}
+
+impl EventStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self> {
+        let version: usize =
+            connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > MIGRATIONS.len() {
+            return Err(anyhow!(
+                "Event store schema version {} is newer than this SDK supports ({})",
+                version,
+                MIGRATIONS.len()
+            ));
+        }
+        let transaction = connection.transaction()?;
+        for migration in &MIGRATIONS[version..] {
+            transaction.execute_batch(migration)?;
+        }
+        transaction.pragma_update(None, "user_version", MIGRATIONS.len())?;
+        transaction.commit()?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Writes the supported events in one transaction, returning how many were not already stored.
+    pub fn insert_events<E: AsRef<PhoenixEvent>>(&self, events: &[E]) -> Result<usize> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let mut inserted = 0;
+        for event in events.iter().map(AsRef::as_ref) {
+            let market = event.market.to_string();
+            let sequence_number = event.sequence_number as i64;
+            let event_index = event.event_index as i64;
+            let slot = event.slot as i64;
+            let signature = event.signature.to_string();
+            let signer = event.signer.to_string();
+            inserted += match event.details {
+                MarketEventDetails::Fill(fill) => transaction.execute(
+                    &format!(
+                        "INSERT OR IGNORE INTO fills ({}) VALUES \
+                         (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                        FILL_COLUMNS
+                    ),
+                    params![
+                        market,
+                        sequence_number,
+                        event_index,
+                        slot,
+                        event.timestamp_seconds(),
+                        signature,
+                        signer,
+                        fill.order_sequence_number as i64,
+                        fill.maker.to_string(),
+                        fill.taker.to_string(),
+                        fill.price_in_ticks as i64,
+                        fill.base_lots_filled as i64,
+                        fill.base_lots_remaining as i64,
+                        fill.side_filled as u8,
+                        fill.is_full_fill,
+                    ],
+                )?,
+                MarketEventDetails::Place(place) => transaction.execute(
+                    "INSERT OR IGNORE INTO places (market, sequence_number, event_index, slot, \
+                     timestamp, signature, signer, order_sequence_number, client_order_id, maker, \
+                     price_in_ticks, base_lots_placed) VALUES \
+                     (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                    params![
+                        market,
+                        sequence_number,
+                        event_index,
+                        slot,
+                        event.timestamp_seconds(),
+                        signature,
+                        signer,
+                        place.order_sequence_number as i64,
+                        place.client_order_id.to_string(),
+                        place.maker.to_string(),
+                        place.price_in_ticks as i64,
+                        place.base_lots_placed as i64,
+                    ],
+                )?,
+                MarketEventDetails::Reduce(reduce) => transaction.execute(
+                    "INSERT OR IGNORE INTO reduces (market, sequence_number, event_index, slot, \
+                     timestamp, signature, signer, order_sequence_number, maker, price_in_ticks, \
+                     base_lots_removed, base_lots_remaining, is_full_cancel) VALUES \
+                     (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                    params![
+                        market,
+                        sequence_number,
+                        event_index,
+                        slot,
+                        event.timestamp_seconds(),
+                        signature,
+                        signer,
+                        reduce.order_sequence_number as i64,
+                        reduce.maker.to_string(),
+                        reduce.price_in_ticks as i64,
+                        reduce.base_lots_removed as i64,
+                        reduce.base_lots_remaining as i64,
+                        reduce.is_full_cancel,
+                    ],
+                )?,
+                MarketEventDetails::Evict(evict) => transaction.execute(
+                    "INSERT OR IGNORE INTO evictions (market, sequence_number, event_index, slot, \
+                     timestamp, signature, signer, order_sequence_number, maker, price_in_ticks, \
+                     base_lots_evicted) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    params![
+                        market,
+                        sequence_number,
+                        event_index,
+                        slot,
+                        event.timestamp_seconds(),
+                        signature,
+                        signer,
+                        evict.order_sequence_number as i64,
+                        evict.maker.to_string(),
+                        evict.price_in_ticks as i64,
+                        evict.base_lots_evicted as i64,
+                    ],
+                )?,
+                _ => 0,
+            };
+        }
+        transaction.commit()?;
+        Ok(inserted)
+    }
+
+    /// Writes a transaction's fee and compute units, returning whether it was not already stored.
+    /// Its events are not written; see `insert_events`.
+    pub fn insert_transaction(&self, transaction: &ParsedPhoenixTransaction) -> Result<bool> {
+        let connection = self.connection.lock().unwrap();
+        let inserted = connection.execute(
+            "INSERT OR IGNORE INTO transactions (signature, slot, block_time, signer, \
+             fee_lamports, compute_units, is_err) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                transaction.signature.to_string(),
+                transaction.slot as i64,
+                transaction.block_time,
+                transaction.signer.to_string(),
+                transaction.fee_lamports as i64,
+                transaction.compute_units.map(|units| units as i64),
+                transaction.is_err,
+            ],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// A stored transaction, without its events.
+    pub fn transaction(&self, signature: &Signature) -> Result<Option<ParsedPhoenixTransaction>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT signature, slot, block_time, signer, fee_lamports, compute_units, is_err \
+             FROM transactions WHERE signature = ?1",
+        )?;
+        let mut rows = statement.query(params![signature.to_string()])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(ParsedPhoenixTransaction {
+                signature: parse_column(row, 0)?,
+                slot: row.get::<_, i64>(1)? as u64,
+                block_time: row.get(2)?,
+                signer: parse_column(row, 3)?,
+                fee_lamports: row.get::<_, i64>(4)? as u64,
+                compute_units: row.get::<_, Option<i64>>(5)?.map(|units| units as u64),
+                is_err: row.get(6)?,
+                events: vec![],
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Deletes every stored event of a transaction, e.g. one that was rolled back by a fork, and
+    /// its fee and compute units. Returns the number of events removed.
+    pub fn remove_transaction(&self, signature: &Signature) -> Result<usize> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let mut removed = 0;
+        for table in ["fills", "places", "reduces", "evictions"] {
+            removed += transaction.execute(
+                &format!("DELETE FROM {} WHERE signature = ?1", table),
+                params![signature.to_string()],
+            )?;
+        }
+        transaction.execute(
+            "DELETE FROM transactions WHERE signature = ?1",
+            params![signature.to_string()],
+        )?;
+        transaction.commit()?;
+        Ok(removed)
+    }
+
+    /// Fills on `market` with `start_timestamp <= timestamp < end_timestamp`, oldest first.
+    /// Timestamps are in seconds since the Unix epoch, as in `PhoenixEvent::timestamp_seconds`.
+    pub fn fills_between(
+        &self,
+        market: &Pubkey,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> Result<Vec<PhoenixEvent>> {
+        self.query_fills(
+            "market = ?1 AND timestamp >= ?2 AND timestamp < ?3",
+            params![market.to_string(), start_timestamp, end_timestamp],
+        )
+    }
+
+    /// Fills where `trader` was either the maker or the taker, oldest first.
+    pub fn fills_for_trader(&self, trader: &Pubkey) -> Result<Vec<PhoenixEvent>> {
+        self.query_fills("maker = ?1 OR taker = ?1", params![trader.to_string()])
+    }
+
+    fn query_fills(
+        &self,
+        filter: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<PhoenixEvent>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM fills WHERE {} ORDER BY slot, sequence_number, event_index",
+            FILL_COLUMNS, filter
+        ))?;
+        let fills = statement
+            .query_map(params, fill_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(fills)
+    }
+}
+
+fn parse_column<T: FromStr>(row: &Row, index: usize) -> rusqlite::Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let value: String = row.get(index)?;
+    value.parse().map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn fill_from_row(row: &Row) -> rusqlite::Result<PhoenixEvent> {
+    let maker_side = match row.get::<_, u8>(13)? {
+        0 => Side::Bid,
+        _ => Side::Ask,
+    };
+    Ok(PhoenixEvent {
+        market: parse_column(row, 0)?,
+        sequence_number: row.get::<_, i64>(1)? as u64,
+        event_index: row.get::<_, i64>(2)? as u64,
+        slot: row.get::<_, i64>(3)? as u64,
+        timestamp: row.get(4)?,
+        signature: parse_column(row, 5)?,
+        signer: parse_column(row, 6)?,
+        details: MarketEventDetails::Fill(Fill {
+            order_sequence_number: row.get::<_, i64>(7)? as u64,
+            maker: parse_column(row, 8)?,
+            taker: parse_column(row, 9)?,
+            price_in_ticks: row.get::<_, i64>(10)? as u64,
+            base_lots_filled: row.get::<_, i64>(11)? as u64,
+            base_lots_remaining: row.get::<_, i64>(12)? as u64,
+            side_filled: maker_side,
+            is_full_fill: row.get(14)?,
+            maker_side,
+            taker_side: maker_side.opposite(),
+        }),
+    })
+}
+
+/// Writes each batch synchronously, which briefly blocks the producer's task.
+#[async_trait]
+impl EventSink for EventStore {
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()> {
+        let mut phoenix_events = vec![];
+        for message in events {
+            match message {
+                SDKMarketEvent::PhoenixEvent { event } => phoenix_events.push(event),
+                SDKMarketEvent::Control(ControlMsg::Rollback { signature, .. }) => {
+                    self.insert_events(&phoenix_events)?;
+                    phoenix_events.clear();
+                    self.remove_transaction(&signature)?;
+                }
+                _ => {}
+            }
+        }
+        self.insert_events(&phoenix_events).map(|_| ())
+    }
+
+    async fn record_transaction(&self, transaction: &ParsedPhoenixTransaction) -> Result<()> {
+        self.insert_transaction(transaction).map(|_| ())
+    }
+}
+
+/// Keeps backfill checkpoints next to the events, as JSON in the `backfill_checkpoints` table.
+impl Checkpointer for EventStore {
+    fn load(&self, job_id: &str) -> Result<Option<BackfillCheckpoint>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT checkpoint FROM backfill_checkpoints WHERE job_id = ?1")?;
+        let mut rows = statement.query(params![job_id])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(serde_json::from_str(&row.get::<_, String>(0)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, job_id: &str, checkpoint: &BackfillCheckpoint) -> Result<()> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT OR REPLACE INTO backfill_checkpoints (job_id, checkpoint) VALUES (?1, ?2)",
+            params![job_id, serde_json::to_string(checkpoint)?],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use phoenix_sdk_core::market_event::{Evict, Place, Reduce};
+
+    use super::*;
+    use crate::event_recorder::{EventRecorder, EventReplayer, ReplaySpeed};
+    use phoenix_sdk_core::test_utils::event;
+
+    #[tokio::test]
+    async fn test_store_replayed_events() {
+        let market = Pubkey::new_unique();
+        let maker = Pubkey::new_unique();
+        let taker = Pubkey::new_unique();
+        let sequenced = |sequence_number: u64, event_index, details| PhoenixEvent {
+            sequence_number,
+            slot: 200 + sequence_number,
+            timestamp: 1_700_000_000 + sequence_number as i64,
+            signature: Signature::new_unique(),
+            signer: taker,
+            event_index,
+            ..event(market, details)
+        };
+        let fill = |base_lots_filled| {
+            MarketEventDetails::Fill(Fill {
+                order_sequence_number: !7,
+                maker,
+                taker,
+                price_in_ticks: 1000,
+                base_lots_filled,
+                base_lots_remaining: 10 - base_lots_filled,
+                side_filled: Side::Bid,
+                maker_side: Side::Bid,
+                taker_side: Side::Bid.opposite(),
+                is_full_fill: false,
+            })
+        };
+        let batches: Vec<Vec<SDKMarketEvent>> = vec![
+            vec![sequenced(
+                1,
+                0,
+                MarketEventDetails::Place(Place {
+                    order_sequence_number: !7,
+                    client_order_id: u128::MAX,
+                    maker,
+                    price_in_ticks: 1000,
+                    base_lots_placed: 10,
+                }),
+            )
+            .into()],
+            vec![
+                sequenced(2, 0, fill(4)).into(),
+                sequenced(2, 1, fill(5)).into(),
+                sequenced(2, 2, MarketEventDetails::Fee(3)).into(),
+            ],
+            vec![sequenced(
+                3,
+                0,
+                MarketEventDetails::Evict(Evict {
+                    order_sequence_number: !7,
+                    maker,
+                    price_in_ticks: 1000,
+                    base_lots_evicted: 1,
+                }),
+            )
+            .into()],
+            vec![sequenced(
+                4,
+                0,
+                MarketEventDetails::Reduce(Reduce {
+                    order_sequence_number: !8,
+                    maker,
+                    price_in_ticks: 999,
+                    base_lots_removed: 1,
+                    base_lots_remaining: 0,
+                    is_full_cancel: true,
+                }),
+            )
+            .into()],
+        ];
+        let mut recorder = EventRecorder::new(Vec::new());
+        for batch in batches.iter() {
+            recorder.record(batch).unwrap();
+        }
+        let bytes = recorder.into_inner();
+
+        let path = std::env::temp_dir().join(format!("phoenix-events-{}.db", Pubkey::new_unique()));
+        let store = EventStore::open(&path).unwrap();
+        // Replaying the same recording twice stores each event once
+        for _ in 0..2 {
+            EventReplayer::new(Cursor::new(bytes.clone()))
+                .replay(&store, ReplaySpeed::AsFastAsPossible)
+                .await
+                .unwrap();
+        }
+        drop(store);
+
+        // Reopening an up-to-date database leaves it unchanged
+        let store = EventStore::open(&path).unwrap();
+        let expected_fills = batches[1][..2]
+            .iter()
+            .map(|message| match message {
+                SDKMarketEvent::PhoenixEvent { event } => **event,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(store.fills_for_trader(&maker).unwrap(), expected_fills);
+        assert_eq!(store.fills_for_trader(&taker).unwrap(), expected_fills);
+        assert!(store
+            .fills_for_trader(&Pubkey::new_unique())
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store
+                .fills_between(&market, 1_700_000_002, 1_700_000_003)
+                .unwrap(),
+            expected_fills
+        );
+        assert!(store
+            .fills_between(&market, 1_700_000_000, 1_700_000_002)
+            .unwrap()
+            .is_empty());
+
+        // Rolling back a transaction removes its events
+        store
+            .send_events(vec![SDKMarketEvent::rollback(
+                expected_fills[0].signature,
+                vec![expected_fills[0]],
+            )])
+            .await
+            .unwrap();
+        assert_eq!(store.fills_for_trader(&maker).unwrap(), expected_fills[1..]);
+
+        let connection = store.connection.lock().unwrap();
+        for (table, count) in [("places", 1), ("reduces", 1), ("evictions", 1)] {
+            let stored: i64 = connection
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(stored, count);
+        }
+        drop(connection);
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
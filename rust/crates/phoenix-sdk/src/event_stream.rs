@@ -0,0 +1,291 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::Stream;
+use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::mpsc;
+
+use crate::sdk_market_event::SDKMarketEvent;
+
+/// The variant of a `MarketEventDetails`, for filtering without matching on its fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EventKind {
+    Fill,
+    Place,
+    Evict,
+    Reduce,
+    FillSummary,
+    Fee,
+    TimeInForce,
+}
+
+impl EventKind {
+    pub fn of(details: &MarketEventDetails) -> Self {
+        match details {
+            MarketEventDetails::Fill(_) => EventKind::Fill,
+            MarketEventDetails::Place(_) => EventKind::Place,
+            MarketEventDetails::Evict(_) => EventKind::Evict,
+            MarketEventDetails::Reduce(_) => EventKind::Reduce,
+            MarketEventDetails::FillSummary(_) => EventKind::FillSummary,
+            MarketEventDetails::Fee(_) => EventKind::Fee,
+            MarketEventDetails::TimeInForce(_) => EventKind::TimeInForce,
+        }
+    }
+}
+
+/// The batches from an event channel as a `Stream`, one item per batch the producer sent.
+///
+/// The stream ends once every sender is dropped and the buffered batches are consumed. Polling
+/// it is cancel-safe: if `next()` loses a `select!`, no batch is lost.
+pub struct EventBatchStream {
+    receiver: mpsc::Receiver<Vec<SDKMarketEvent>>,
+}
+
+impl EventBatchStream {
+    pub fn new(receiver: mpsc::Receiver<Vec<SDKMarketEvent>>) -> Self {
+        Self { receiver }
+    }
+
+    /// Flattens the batches into single events.
+    pub fn into_events(self) -> EventStream {
+        EventStream::new(self.receiver)
+    }
+
+    pub fn into_inner(self) -> mpsc::Receiver<Vec<SDKMarketEvent>> {
+        self.receiver
+    }
+}
+
+impl From<mpsc::Receiver<Vec<SDKMarketEvent>>> for EventBatchStream {
+    fn from(receiver: mpsc::Receiver<Vec<SDKMarketEvent>>) -> Self {
+        Self::new(receiver)
+    }
+}
+
+impl Stream for EventBatchStream {
+    type Item = Vec<SDKMarketEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// The events from an event channel as a `Stream` of single events, in the order they were sent.
+///
+/// Heartbeats and control messages are skipped, since undoing an event already yielded needs the
+/// batch context; use `EventBatchStream` to see them. The stream ends once every sender is dropped
+/// and the buffered events are consumed.
+///
+/// Polling is cancel-safe: events from a batch that are not yet yielded stay buffered in the
+/// stream, so it can be polled from a `select!` loop and resumed after another branch wins.
+///
+/// ```
+/// use futures::StreamExt;
+/// use phoenix_sdk::event_stream::{EventKind, EventStream};
+/// use phoenix_sdk::sdk_client::{Fill, MarketEventDetails, PhoenixEvent};
+/// use solana_sdk::pubkey::Pubkey;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let (sender, receiver) = tokio::sync::mpsc::channel(16);
+/// let market = Pubkey::new_unique();
+/// # let fill = PhoenixEvent {
+/// #     market,
+/// #     sequence_number: 1,
+/// #     slot: 100,
+/// #     timestamp: 1_700_000_000,
+/// #     signature: Default::default(),
+/// #     signer: Pubkey::default(),
+/// #     event_index: 0,
+/// #     details: MarketEventDetails::Fill(Fill::new(1, Pubkey::default(), Pubkey::default(), 1000, 10, 0)),
+/// # };
+/// sender.send(vec![fill.into()]).await.unwrap();
+/// drop(sender);
+///
+/// let mut stream = EventStream::new(receiver)
+///     .with_market(market)
+///     .with_kinds([EventKind::Fill]);
+/// while let Some(event) = stream.next().await {
+///     if let MarketEventDetails::Fill(fill) = event.details {
+///         println!("Filled {} lots at {}", fill.base_lots_filled, fill.price_in_ticks);
+///     }
+/// }
+/// # }
+/// ```
+pub struct EventStream {
+    receiver: mpsc::Receiver<Vec<SDKMarketEvent>>,
+    buffered: VecDeque<PhoenixEvent>,
+    market: Option<Pubkey>,
+    kinds: Option<Vec<EventKind>>,
+}
+
+impl EventStream {
+    pub fn new(receiver: mpsc::Receiver<Vec<SDKMarketEvent>>) -> Self {
+        Self {
+            receiver,
+            buffered: VecDeque::new(),
+            market: None,
+            kinds: None,
+        }
+    }
+
+    /// Only yields events on `market`.
+    pub fn with_market(mut self, market: Pubkey) -> Self {
+        self.market = Some(market);
+        self
+    }
+
+    /// Only yields events of one of `kinds`.
+    pub fn with_kinds(mut self, kinds: impl IntoIterator<Item = EventKind>) -> Self {
+        self.kinds = Some(kinds.into_iter().collect());
+        self
+    }
+
+    fn is_wanted(&self, event: &PhoenixEvent) -> bool {
+        self.market.is_none_or(|market| event.market == market)
+            && self
+                .kinds
+                .as_ref()
+                .is_none_or(|kinds| kinds.contains(&EventKind::of(&event.details)))
+    }
+}
+
+impl From<mpsc::Receiver<Vec<SDKMarketEvent>>> for EventStream {
+    fn from(receiver: mpsc::Receiver<Vec<SDKMarketEvent>>) -> Self {
+        Self::new(receiver)
+    }
+}
+
+impl Stream for EventStream {
+    type Item = PhoenixEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.buffered.pop_front() {
+                return Poll::Ready(Some(event));
+            }
+            let batch = match self.receiver.poll_recv(cx) {
+                Poll::Ready(Some(batch)) => batch,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            for message in batch {
+                if let SDKMarketEvent::PhoenixEvent { event } = message {
+                    if self.is_wanted(&event) {
+                        self.buffered.push_back(*event);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+    use phoenix_sdk_core::market_event::{Fill, Place};
+    use phoenix_sdk_core::test_utils::event;
+    use solana_sdk::signature::Signature;
+
+    use super::*;
+
+    fn fill(market: Pubkey, sequence_number: u64) -> SDKMarketEvent {
+        let fill = Fill::new(1, Pubkey::default(), Pubkey::default(), 100, 1, 0);
+        PhoenixEvent {
+            sequence_number,
+            ..event(market, MarketEventDetails::Fill(fill))
+        }
+        .into()
+    }
+
+    fn place(market: Pubkey, sequence_number: u64) -> SDKMarketEvent {
+        let place = Place {
+            order_sequence_number: 1,
+            client_order_id: 0,
+            maker: Pubkey::default(),
+            price_in_ticks: 100,
+            base_lots_placed: 1,
+        };
+        PhoenixEvent {
+            sequence_number,
+            ..event(market, MarketEventDetails::Place(place))
+        }
+        .into()
+    }
+
+    #[tokio::test]
+    async fn test_flattens_and_filters() {
+        let (sol, eth) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let batches = vec![
+            vec![fill(sol, 1), place(sol, 2), fill(eth, 3)],
+            vec![SDKMarketEvent::rollback(Signature::default(), vec![])],
+            vec![],
+            vec![place(eth, 4), fill(sol, 5)],
+        ];
+        let sequence_numbers = |stream: EventStream| async {
+            stream
+                .map(|event| event.sequence_number)
+                .collect::<Vec<_>>()
+                .await
+        };
+        let channel = || {
+            let (sender, receiver) = mpsc::channel(8);
+            for batch in &batches {
+                sender.try_send(batch.clone()).unwrap();
+            }
+            receiver
+        };
+
+        assert_eq!(
+            sequence_numbers(EventStream::new(channel())).await,
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            sequence_numbers(EventStream::new(channel()).with_market(sol)).await,
+            vec![1, 2, 5]
+        );
+        assert_eq!(
+            sequence_numbers(
+                EventStream::new(channel())
+                    .with_market(sol)
+                    .with_kinds([EventKind::Fill])
+            )
+            .await,
+            vec![1, 5]
+        );
+        let batch_sizes = EventBatchStream::new(channel())
+            .map(|batch| batch.len())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(batch_sizes, vec![3, 1, 0, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_safe_in_select() {
+        let sol = Pubkey::new_unique();
+        let (sender, receiver) = mpsc::channel(8);
+        let mut stream = EventStream::new(receiver);
+        sender
+            .send(vec![fill(sol, 1), fill(sol, 2), fill(sol, 3)])
+            .await
+            .unwrap();
+
+        // Take one event, then let another branch win while the rest are buffered
+        assert_eq!(stream.next().await.unwrap().sequence_number, 1);
+        let (mut ticks, mut received) = (0, vec![]);
+        while received.len() < 2 {
+            tokio::select! {
+                biased;
+                _ = async {}, if ticks < 3 => ticks += 1,
+                event = stream.next() => received.push(event.unwrap().sequence_number),
+            }
+        }
+        assert_eq!((ticks, received), (3, vec![2, 3]));
+
+        // Ends cleanly once the producer is gone
+        drop(sender);
+        assert!(stream.next().await.is_none());
+    }
+}
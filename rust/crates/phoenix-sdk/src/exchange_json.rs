@@ -0,0 +1,138 @@
+use anyhow::{anyhow, Result};
+use phoenix::state::markets::{Ladder, LadderOrder};
+use phoenix_sdk_core::atoms::Rounding;
+use phoenix_sdk_core::sdk_client_core::MarketMetadata;
+use serde_json::{json, Value};
+
+/// Depth snapshots in the JSON shape common to centralized exchange APIs:
+///
+/// ```json
+/// {"bids": [["24.95", "1.5"], ...], "asks": [["25.01", "1"], ...], "lastUpdateId": 1234}
+/// ```
+///
+/// Prices are in quote units per raw base unit and sizes in raw base units, formatted as exact
+/// decimal strings, best level first.
+pub trait ExchangeJsonLadder: Sized {
+    /// The best `depth` levels of each side, with `last_update_id` as the `lastUpdateId`,
+    /// usually the market's sequence number. Fails if the market's prices have no exact decimal
+    /// form, see `MarketMetadata::ticks_to_decimal_str`.
+    fn to_exchange_json(
+        &self,
+        meta: &MarketMetadata,
+        depth: usize,
+        last_update_id: u64,
+    ) -> Result<Value>;
+
+    /// Parses `to_exchange_json`'s output back into a ladder and its `lastUpdateId`. Fails on
+    /// any price or size that is not a whole number of ticks or base lots.
+    fn from_exchange_json(value: &Value, meta: &MarketMetadata) -> Result<(Self, u64)>;
+}
+
+impl ExchangeJsonLadder for Ladder {
+    fn to_exchange_json(
+        &self,
+        meta: &MarketMetadata,
+        depth: usize,
+        last_update_id: u64,
+    ) -> Result<Value> {
+        let levels = |levels: &[LadderOrder]| {
+            levels
+                .iter()
+                .take(depth)
+                .map(|level| {
+                    Ok(json!([
+                        meta.ticks_to_decimal_str(level.price_in_ticks)?,
+                        meta.base_lots_to_decimal_str(level.size_in_base_lots),
+                    ]))
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(json!({
+            "bids": levels(&self.bids)?,
+            "asks": levels(&self.asks)?,
+            "lastUpdateId": last_update_id,
+        }))
+    }
+
+    fn from_exchange_json(value: &Value, meta: &MarketMetadata) -> Result<(Self, u64)> {
+        let levels = |side: &str| {
+            value[side]
+                .as_array()
+                .ok_or_else(|| anyhow!("Missing {}", side))?
+                .iter()
+                .map(|level| {
+                    let (Some(price), Some(size)) = (level[0].as_str(), level[1].as_str()) else {
+                        return Err(anyhow!("Invalid {} level: {}", side, level));
+                    };
+                    Ok(LadderOrder {
+                        price_in_ticks: meta.decimal_str_to_ticks(price, Rounding::Exact)?,
+                        size_in_base_lots: meta.decimal_str_to_base_lots(size, Rounding::Exact)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        let last_update_id = value["lastUpdateId"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("Missing lastUpdateId"))?;
+        Ok((
+            Ladder {
+                bids: levels("bids")?,
+                asks: levels("asks")?,
+            },
+            last_update_id,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use phoenix_sdk_core::test_utils::sol_usdc_metadata;
+
+    fn level(price_in_ticks: u64, size_in_base_lots: u64) -> LadderOrder {
+        LadderOrder {
+            price_in_ticks,
+            size_in_base_lots,
+        }
+    }
+
+    #[test]
+    fn test_exchange_json_round_trip() {
+        let meta = sol_usdc_metadata();
+        let ladder = Ladder {
+            bids: vec![level(24_950, 1500), level(24_900, 20), level(24_000, 1)],
+            asks: vec![level(25_010, 1000)],
+        };
+        let value = ladder.to_exchange_json(&meta, 2, 1234).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "bids": [["24.95", "1.5"], ["24.9", "0.02"]],
+                "asks": [["25.01", "1"]],
+                "lastUpdateId": 1234,
+            })
+        );
+
+        let (parsed, last_update_id) = Ladder::from_exchange_json(&value, &meta).unwrap();
+        assert_eq!(last_update_id, 1234);
+        assert_eq!(parsed.bids, ladder.bids[..2]);
+        assert_eq!(parsed.asks, ladder.asks);
+
+        // Prices between ticks are rejected rather than rounded
+        let off_tick = json!({ "bids": [["24.9505", "1"]], "asks": [], "lastUpdateId": 1 });
+        assert!(Ladder::from_exchange_json(&off_tick, &meta).is_err());
+    }
+
+    #[test]
+    fn test_inexact_prices_fail() {
+        let meta = MarketMetadata {
+            raw_base_units_per_base_unit: 3,
+            ..sol_usdc_metadata()
+        };
+        let ladder = Ladder {
+            bids: vec![level(100, 1)],
+            asks: vec![],
+        };
+        assert!(ladder.to_exchange_json(&meta, 10, 0).is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk_core::order_manager::OrderManager;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::Notify;
+
+use crate::sdk_client::{CancelReport, CancelledOrder, SDKClient};
+use crate::sdk_market_event::SDKMarketEvent;
+
+/// How many orders an `ExpirySweeper` cancels per transaction by default. Each cancel adds 17
+/// bytes to the instruction, so this stays well inside the transaction size limit.
+pub const DEFAULT_CANCEL_BATCH_SIZE: usize = 20;
+
+/// A cancel transaction of an `ExpirySweeper` that failed. Its orders are tracked again, so the
+/// next sweep retries them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedCancel {
+    pub market: Pubkey,
+    pub order_ids: Vec<FIFOOrderId>,
+    pub error: String,
+}
+
+/// What one sweep of an `ExpirySweeper` cancelled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpirySweep {
+    /// The unix timestamp the orders' ages were measured at.
+    pub swept_at: i64,
+    /// One report per cancel transaction, each for up to the batch size of one market's orders.
+    pub cancels: Vec<CancelReport>,
+    pub failures: Vec<FailedCancel>,
+}
+
+impl ExpirySweep {
+    /// The orders the sweep removed from the book.
+    pub fn cancelled(&self) -> impl Iterator<Item = &CancelledOrder> {
+        self.cancels.iter().flat_map(|report| report.orders.iter())
+    }
+
+    /// Whether the sweep found no expired orders.
+    pub fn is_empty(&self) -> bool {
+        self.cancels.is_empty() && self.failures.is_empty()
+    }
+}
+
+#[derive(Debug, Default)]
+struct SweeperShared {
+    /// TTLs that replace the default for orders with these client order ids.
+    ttls: HashMap<u128, Duration>,
+    /// Client order ids whose orders are never swept.
+    excluded: BTreeSet<u128>,
+    stopped: bool,
+}
+
+/// Changes the TTLs of a running `ExpirySweeper` and stops it.
+#[derive(Debug, Clone, Default)]
+pub struct ExpirySweeperHandle {
+    shared: Arc<Mutex<SweeperShared>>,
+    wake: Arc<Notify>,
+}
+
+impl ExpirySweeperHandle {
+    fn lock(&self) -> MutexGuard<'_, SweeperShared> {
+        self.shared
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Gives the orders placed with `client_order_id` their own TTL, e.g. right before placing
+    /// one that should rest for longer than the default.
+    pub fn set_ttl(&self, client_order_id: u128, ttl: Duration) {
+        self.lock().ttls.insert(client_order_id, ttl);
+    }
+
+    /// Never sweeps the orders placed with `client_order_id`.
+    pub fn exclude(&self, client_order_id: u128) {
+        self.lock().excluded.insert(client_order_id);
+    }
+
+    /// Sweeps the orders placed with `client_order_id` again after `exclude`.
+    pub fn include(&self, client_order_id: u128) {
+        self.lock().excluded.remove(&client_order_id);
+    }
+
+    /// Stops the sweeper after its current sweep. Orders it has not cancelled stay on the book.
+    pub fn stop(&self) {
+        self.lock().stopped = true;
+        self.wake.notify_one();
+    }
+}
+
+type SweepCallback = Box<dyn Fn(&ExpirySweep) + Send + Sync>;
+
+/// Cancels the trader's orders that have rested for longer than a TTL, for markets or order
+/// types where the on-chain time in force is not used.
+///
+/// The sweeper follows the trader's open orders with an `OrderManager` fed from an event
+/// channel, and ages them by the timestamps of their place events. Those are in whole seconds,
+/// as is the age an order is compared with its TTL at. Orders placed before the sweeper
+/// started are not on the channel and are left alone. Each sweep cancels the expired orders of
+/// a market with `get_cancel_ids_ix`, in batches of `DEFAULT_CANCEL_BATCH_SIZE`, and waits for
+/// every batch to confirm.
+///
+/// Orders use the default TTL unless the client order id they were placed with has its own,
+/// set with `with_ttl` or `ExpirySweeperHandle::set_ttl`. Excluded client order ids are never
+/// swept.
+pub struct ExpirySweeper {
+    default_ttl: Duration,
+    interval: Duration,
+    batch_size: usize,
+    orders: OrderManager,
+    handle: ExpirySweeperHandle,
+    on_sweep: Option<SweepCallback>,
+}
+
+impl ExpirySweeper {
+    pub fn new(client: &SDKClient, default_ttl: Duration) -> (Self, ExpirySweeperHandle) {
+        let handle = ExpirySweeperHandle::default();
+        (
+            Self {
+                default_ttl,
+                interval: Duration::from_secs(1),
+                batch_size: DEFAULT_CANCEL_BATCH_SIZE,
+                orders: OrderManager::new(client.trader),
+                handle: handle.clone(),
+                on_sweep: None,
+            },
+            handle,
+        )
+    }
+
+    /// How often `run` sweeps. Defaults to once a second.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The most orders cancelled per transaction, at least one.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// See `ExpirySweeperHandle::set_ttl`.
+    pub fn with_ttl(self, client_order_id: u128, ttl: Duration) -> Self {
+        self.handle.set_ttl(client_order_id, ttl);
+        self
+    }
+
+    /// See `ExpirySweeperHandle::exclude`.
+    pub fn with_excluded(self, client_order_ids: impl IntoIterator<Item = u128>) -> Self {
+        self.handle.lock().excluded.extend(client_order_ids);
+        self
+    }
+
+    /// Called by `run` with every sweep that cancelled or failed to cancel something.
+    pub fn with_on_sweep(
+        mut self,
+        on_sweep: impl Fn(&ExpirySweep) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_sweep = Some(Box::new(on_sweep));
+        self
+    }
+
+    /// The trader's open orders, as far as the sweeper has seen them.
+    pub fn orders(&self) -> &OrderManager {
+        &self.orders
+    }
+
+    /// Applies a batch of events, e.g. from the channel `run` reads.
+    pub fn apply_events(&mut self, batch: &[SDKMarketEvent]) {
+        for message in batch {
+            if let SDKMarketEvent::PhoenixEvent { event } = message {
+                self.orders.apply_event(event);
+            }
+        }
+    }
+
+    /// The open orders that are at least as old as their TTL at the unix timestamp `now`, by
+    /// market, in the order of `OrderManager::open_orders`.
+    pub fn expired(&self, now: i64) -> BTreeMap<Pubkey, Vec<FIFOOrderId>> {
+        let shared = self.handle.lock();
+        let mut expired = BTreeMap::<Pubkey, Vec<FIFOOrderId>>::new();
+        for market in self.orders.markets() {
+            for order in self.orders.open_orders(market) {
+                if shared.excluded.contains(&order.client_order_id) {
+                    continue;
+                }
+                let Some(placed_at) = self.orders.placed_at(market, &order.order_id) else {
+                    continue;
+                };
+                let ttl = shared
+                    .ttls
+                    .get(&order.client_order_id)
+                    .copied()
+                    .unwrap_or(self.default_ttl);
+                let age = Duration::from_secs(now.saturating_sub(placed_at).max(0) as u64);
+                if age >= ttl {
+                    expired.entry(*market).or_default().push(order.order_id);
+                }
+            }
+        }
+        expired
+    }
+
+    /// Cancels the orders expired at the unix timestamp `now`. Cancelled orders are removed
+    /// from the sweeper's open orders right away, so a later sweep does not cancel them again
+    /// before their events arrive.
+    pub async fn sweep(&mut self, client: &SDKClient, now: i64) -> ExpirySweep {
+        let mut sweep = ExpirySweep {
+            swept_at: now,
+            ..Default::default()
+        };
+        for (market, order_ids) in self.expired(now) {
+            for batch in order_ids.chunks(self.batch_size) {
+                for order_id in batch {
+                    self.orders.reduce_pending(&market, *order_id, u64::MAX);
+                }
+                let result = match client.get_cancel_ids_ix(&market, batch.to_vec()) {
+                    Ok(ix) => client.send_cancel_and_report(ix).await,
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(report) => sweep.cancels.push(report),
+                    Err(e) => {
+                        for order_id in batch {
+                            self.orders.revert_pending_reduce(&market, *order_id);
+                        }
+                        sweep.failures.push(FailedCancel {
+                            market,
+                            order_ids: batch.to_vec(),
+                            error: e.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        sweep
+    }
+
+    /// Sweeps every interval until stopped through the handle or until the event channel
+    /// closes. Events are applied as they arrive, and sweeps are reported to the callback set
+    /// with `with_on_sweep`.
+    pub async fn run(mut self, client: &SDKClient, events: &mut Receiver<Vec<SDKMarketEvent>>) {
+        let wake = self.handle.wake.clone();
+        loop {
+            while let Ok(batch) = events.try_recv() {
+                self.apply_events(&batch);
+            }
+            if self.handle.lock().stopped {
+                return;
+            }
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_secs() as i64);
+            let sweep = self.sweep(client, now).await;
+            if let Some(on_sweep) = self.on_sweep.as_ref().filter(|_| !sweep.is_empty()) {
+                on_sweep(&sweep);
+            }
+
+            let next_sweep = tokio::time::sleep(self.interval);
+            tokio::pin!(next_sweep);
+            loop {
+                tokio::select! {
+                    batch = events.recv() => match batch {
+                        Some(batch) => self.apply_events(&batch),
+                        None => return,
+                    },
+                    _ = wake.notified() => break,
+                    _ = &mut next_sweep => break,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix::quantities::WrapperU64;
+    use phoenix::state::enums::Side;
+    use tokio::sync::mpsc::channel;
+
+    use super::*;
+    use crate::paper_trading::test_utils::paper_client;
+
+    async fn place(sdk: &SDKClient, market: &Pubkey, price_in_ticks: u64, client_order_id: u128) {
+        let ix = sdk
+            .get_post_only_ix_from_tick_price(
+                market,
+                price_in_ticks,
+                Side::Bid,
+                10,
+                client_order_id,
+                false,
+            )
+            .unwrap();
+        sdk.send_ixs(vec![ix]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sweeps_in_batches() {
+        let (sdk, market) = paper_client(&[]).await;
+        let (tx, mut rx) = channel(64);
+        sdk.paper_exchange().set_event_sender(Some(tx));
+        for price_in_ticks in 50..55 {
+            place(&sdk, &market, price_in_ticks, 1).await;
+        }
+        // Excluded, and with a longer TTL
+        place(&sdk, &market, 60, 2).await;
+        place(&sdk, &market, 61, 3).await;
+
+        let (sweeper, _handle) = ExpirySweeper::new(&sdk, Duration::from_secs(10));
+        let mut sweeper = sweeper
+            .with_batch_size(2)
+            .with_excluded([2])
+            .with_ttl(3, Duration::from_secs(60));
+        while let Ok(batch) = rx.try_recv() {
+            sweeper.apply_events(&batch);
+        }
+        assert_eq!(sweeper.orders().open_order_count(), 7);
+
+        // Freshly placed orders are never swept. The paper exchange stamps them with the
+        // current time, so they may straddle a second
+        let placed_at = sweeper
+            .orders()
+            .open_orders(&market)
+            .filter_map(|order| sweeper.orders().placed_at(&market, &order.order_id))
+            .min()
+            .unwrap();
+        assert!(sweeper.sweep(&sdk, placed_at).await.is_empty());
+        assert!(sweeper.expired(placed_at + 9).is_empty());
+
+        let sweep = sweeper.sweep(&sdk, placed_at + 11).await;
+        assert!(sweep.failures.is_empty());
+        let batch_sizes = sweep
+            .cancels
+            .iter()
+            .map(|report| report.orders.len())
+            .collect::<Vec<_>>();
+        assert_eq!(batch_sizes, vec![2, 2, 1]);
+        assert_eq!(sweep.cancelled().count(), 5);
+        let resting = sdk.paper_exchange().open_orders(&market);
+        assert_eq!(resting.len(), 2);
+
+        // The cancels' own events do not bring the orders back, and nothing is cancelled twice
+        while let Ok(batch) = rx.try_recv() {
+            sweeper.apply_events(&batch);
+        }
+        assert_eq!(sweeper.orders().open_order_count(), 2);
+        assert!(sweeper.sweep(&sdk, placed_at + 30).await.is_empty());
+        let sweep = sweeper.sweep(&sdk, placed_at + 61).await;
+        assert_eq!(sweep.cancelled().count(), 1);
+        assert_eq!(sdk.paper_exchange().open_orders(&market).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_sweeps() {
+        let (sdk, market) = paper_client(&[]).await;
+        let (tx, mut rx) = channel(64);
+        sdk.paper_exchange().set_event_sender(Some(tx));
+
+        let sweeps = Arc::new(Mutex::new(vec![]));
+        let recorded = sweeps.clone();
+        let (sweeper, handle) = ExpirySweeper::new(&sdk, Duration::ZERO);
+        let sweeper = sweeper
+            .with_interval(Duration::from_millis(10))
+            .with_on_sweep(move |sweep| recorded.lock().unwrap().push(sweep.clone()));
+        // Placed with a TTL set through the handle while the sweeper runs
+        handle.set_ttl(9, Duration::from_secs(3600));
+        let driver = async {
+            place(&sdk, &market, 50, 9).await;
+            place(&sdk, &market, 51, 1).await;
+            while sdk.paper_exchange().open_orders(&market).len() > 1 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            handle.stop();
+        };
+        tokio::join!(sweeper.run(&sdk, &mut rx), driver);
+
+        let sweeps = sweeps.lock().unwrap().clone();
+        assert_eq!(sweeps.len(), 1);
+        let cancelled = sweeps[0].cancelled().collect::<Vec<_>>();
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(cancelled[0].order_id.price_in_ticks.as_u64(), 51);
+        assert_eq!(sdk.paper_exchange().open_orders(&market).len(), 1);
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Read, Write};
+
+use anyhow::{anyhow, Result};
+use phoenix::state::enums::Side;
+use phoenix_sdk_core::atoms::AmountFormat;
+use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent};
+use phoenix_sdk_core::sdk_client_core::MarketMetadata;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+/// One fill in exportable form. Amounts are exact decimal strings in quote units per raw base
+/// unit (price), raw base units (base_size) and quote units (quote_size, fee), as written by
+/// `MarketMetadata::format_price`, `format_base` and `format_quote` without separators.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FillRecord {
+    /// Block time in seconds since the Unix epoch, or 0 if unknown.
+    pub timestamp: i64,
+    pub slot: u64,
+    pub signature: String,
+    pub market: String,
+    pub maker: String,
+    pub taker: String,
+    /// The taker's side, "buy" or "sell".
+    pub side: String,
+    pub price: String,
+    pub base_size: String,
+    pub quote_size: String,
+    /// The taker fee of the order, from its `FillSummary`. It is reported on the order's first
+    /// fill only, so that summing the column gives total fees, and is empty when the summary is
+    /// not among the exported events.
+    pub fee: Option<String>,
+}
+
+/// One fill with every amount an integer, for consumers that must not round-trip through
+/// decimals: the price in ticks and sizes and fee in atoms.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FillAtomsRecord {
+    /// Block time in seconds since the Unix epoch, or 0 if unknown.
+    pub timestamp: i64,
+    pub slot: u64,
+    pub signature: String,
+    pub market: String,
+    pub maker: String,
+    pub taker: String,
+    /// The taker's side, "buy" or "sell".
+    pub side: String,
+    pub price_in_ticks: u64,
+    pub base_atoms: u64,
+    pub quote_atoms: u64,
+    /// Reported like `FillRecord::fee`.
+    pub fee_atoms: Option<u64>,
+}
+
+/// Converts the fills among `events` into integer records, in the order they appear.
+pub fn fill_records_in_atoms<E: AsRef<PhoenixEvent>>(
+    events: &[E],
+    meta: &BTreeMap<Pubkey, MarketMetadata>,
+) -> Result<Vec<FillAtomsRecord>> {
+    // Fees are keyed by instruction: the fills of an order share its header with its summary.
+    let mut fees: HashMap<(Signature, Pubkey, u64), u64> = events
+        .iter()
+        .map(AsRef::as_ref)
+        .filter_map(|event| match event.details {
+            MarketEventDetails::FillSummary(summary) => Some((
+                (event.signature, event.market, event.sequence_number),
+                summary.total_quote_fees,
+            )),
+            _ => None,
+        })
+        .collect();
+
+    let mut records = vec![];
+    for event in events.iter().map(AsRef::as_ref) {
+        let MarketEventDetails::Fill(fill) = event.details else {
+            continue;
+        };
+        let meta = meta
+            .get(&event.market)
+            .ok_or_else(|| anyhow!("Market metadata not found for {}", event.market))?;
+        let too_large = || {
+            anyhow!(
+                "Fill of {} base lots at {} ticks in {} is too large for u64 atoms",
+                fill.base_lots_filled,
+                fill.price_in_ticks,
+                event.signature
+            )
+        };
+        records.push(FillAtomsRecord {
+            timestamp: event.timestamp_seconds(),
+            slot: event.slot,
+            signature: event.signature.to_string(),
+            market: event.market.to_string(),
+            maker: fill.maker.to_string(),
+            taker: fill.taker.to_string(),
+            side: match fill.taker_side {
+                Side::Bid => "buy",
+                Side::Ask => "sell",
+            }
+            .to_string(),
+            price_in_ticks: fill.price_in_ticks,
+            base_atoms: meta
+                .checked_base_lots_to_base_atoms(fill.base_lots_filled)
+                .ok_or_else(too_large)?,
+            quote_atoms: meta
+                .checked_base_lots_and_price_to_quote_atoms(
+                    fill.base_lots_filled,
+                    fill.price_in_ticks,
+                )
+                .ok_or_else(too_large)?,
+            fee_atoms: fees.remove(&(event.signature, event.market, event.sequence_number)),
+        });
+    }
+    Ok(records)
+}
+
+/// Converts the fills among `events` into records, in the order they appear.
+pub fn fill_records<E: AsRef<PhoenixEvent>>(
+    events: &[E],
+    meta: &BTreeMap<Pubkey, MarketMetadata>,
+) -> Result<Vec<FillRecord>> {
+    fill_records_in_atoms(events, meta)?
+        .into_iter()
+        .zip(
+            events
+                .iter()
+                .map(AsRef::as_ref)
+                .filter(|event| matches!(event.details, MarketEventDetails::Fill(_))),
+        )
+        .map(|(record, event)| {
+            // Present, or `fill_records_in_atoms` would have failed
+            let meta = &meta[&event.market];
+            let format = AmountFormat::default().with_thousands_separator(false);
+            Ok(FillRecord {
+                price: meta.format_price_with(record.price_in_ticks, format),
+                base_size: meta.format_base_with(record.base_atoms, format),
+                quote_size: meta.format_quote_with(record.quote_atoms, format),
+                fee: record
+                    .fee_atoms
+                    .map(|fee_atoms| meta.format_quote_with(fee_atoms, format)),
+                timestamp: record.timestamp,
+                slot: record.slot,
+                signature: record.signature,
+                market: record.market,
+                maker: record.maker,
+                taker: record.taker,
+                side: record.side,
+            })
+        })
+        .collect()
+}
+
+/// Writes one CSV row per fill, with a header row.
+pub fn fills_to_csv<E: AsRef<PhoenixEvent>, W: Write>(
+    events: &[E],
+    meta: &BTreeMap<Pubkey, MarketMetadata>,
+    w: W,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(w);
+    for record in fill_records(events, meta)? {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes one CSV row per fill with integer amounts, with a header row.
+pub fn fills_to_csv_in_atoms<E: AsRef<PhoenixEvent>, W: Write>(
+    events: &[E],
+    meta: &BTreeMap<Pubkey, MarketMetadata>,
+    w: W,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(w);
+    for record in fill_records_in_atoms(events, meta)? {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes one JSON object per line per fill.
+pub fn fills_to_json_lines<E: AsRef<PhoenixEvent>, W: Write>(
+    events: &[E],
+    meta: &BTreeMap<Pubkey, MarketMetadata>,
+    mut w: W,
+) -> Result<()> {
+    for record in fill_records(events, meta)? {
+        serde_json::to_writer(&mut w, &record)?;
+        w.write_all(b"\n")?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Reads back records written by `fills_to_csv`.
+pub fn fill_records_from_csv<R: Read>(r: R) -> Result<Vec<FillRecord>> {
+    csv::Reader::from_reader(r)
+        .deserialize()
+        .map(|record| record.map_err(Into::into))
+        .collect()
+}
+
+/// Reads back records written by `fills_to_json_lines`, skipping blank lines.
+pub fn fill_records_from_json_lines<R: BufRead>(r: R) -> Result<Vec<FillRecord>> {
+    let mut records = vec![];
+    for line in r.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            records.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix_sdk_core::market_event::{Fill, FillSummary};
+
+    use super::*;
+    use phoenix_sdk_core::test_utils::event;
+
+    fn events() -> (Vec<PhoenixEvent>, BTreeMap<Pubkey, MarketMetadata>) {
+        let market = Pubkey::new_from_array([1; 32]);
+        let maker = Pubkey::new_from_array([2; 32]);
+        let taker = Pubkey::new_from_array([3; 32]);
+        let meta = MarketMetadata {
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_atoms_per_raw_base_unit: 1_000_000_000,
+            quote_atoms_per_quote_unit: 1_000_000,
+            quote_atoms_per_quote_lot: 1,
+            base_atoms_per_base_lot: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 100,
+            num_base_lots_per_base_unit: 1000,
+            raw_base_units_per_base_unit: 1,
+            ..Default::default()
+        };
+        let sequenced = |sequence_number, signature_byte, event_index, details| PhoenixEvent {
+            sequence_number,
+            slot: 250_000_000 + sequence_number,
+            timestamp: 1_700_000_000,
+            signature: Signature::from([signature_byte; 64]),
+            signer: taker,
+            event_index,
+            ..event(market, details)
+        };
+        let summary = |total_quote_fees| {
+            MarketEventDetails::FillSummary(FillSummary {
+                client_order_id: 0,
+                total_base_filled: 0,
+                total_quote_filled_including_fees: 0,
+                total_quote_fees,
+                trade_direction: 1,
+            })
+        };
+        let events = vec![
+            // A buy taking two asks: 0.1 @ 0.3 and 0.2 @ 0.3001
+            sequenced(
+                1,
+                4,
+                0,
+                MarketEventDetails::Fill(Fill::new(5, maker, taker, 3000, 100, 0)),
+            ),
+            sequenced(
+                1,
+                4,
+                1,
+                MarketEventDetails::Fill(Fill::new(6, maker, taker, 3001, 200, 50)),
+            ),
+            sequenced(1, 4, 2, summary(25)),
+            // A sell hitting a bid, with no summary
+            sequenced(
+                2,
+                5,
+                0,
+                MarketEventDetails::Fill(Fill::new(!7, maker, taker, 2999, 1, 0)),
+            ),
+            sequenced(2, 5, 1, MarketEventDetails::Fee(3)),
+        ];
+        (events, BTreeMap::from([(market, meta)]))
+    }
+
+    #[test]
+    fn test_csv_golden_and_round_trip() {
+        let (events, meta) = events();
+        let mut bytes = vec![];
+        fills_to_csv(&events, &meta, &mut bytes).unwrap();
+        let csv = String::from_utf8(bytes).unwrap();
+
+        let market = Pubkey::new_from_array([1; 32]);
+        let maker = Pubkey::new_from_array([2; 32]);
+        let taker = Pubkey::new_from_array([3; 32]);
+        let row = |slot, signature_byte, side, price, base_size, quote_size, fee| {
+            format!(
+                "1700000000,{},{},{},{},{},{},{},{},{},{}\n",
+                slot,
+                Signature::from([signature_byte; 64]),
+                market,
+                maker,
+                taker,
+                side,
+                price,
+                base_size,
+                quote_size,
+                fee
+            )
+        };
+        let expected = [
+            "timestamp,slot,signature,market,maker,taker,side,price,base_size,quote_size,fee\n"
+                .to_string(),
+            row(250000001, 4, "buy", "0.3", "0.1", "0.03", "0.000025"),
+            row(250000001, 4, "buy", "0.3001", "0.2", "0.06002", ""),
+            row(250000002, 5, "sell", "0.2999", "0.001", "0.000299", ""),
+        ]
+        .concat();
+        assert_eq!(csv, expected);
+
+        let records = fill_records_from_csv(csv.as_bytes()).unwrap();
+        assert_eq!(records, fill_records(&events, &meta).unwrap());
+    }
+
+    #[test]
+    fn test_json_lines_round_trip() {
+        let (events, meta) = events();
+        let mut bytes = vec![];
+        fills_to_json_lines(&events, &meta, &mut bytes).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        assert!(text
+            .lines()
+            .next()
+            .unwrap()
+            .contains(r#""price":"0.3","base_size":"0.1","quote_size":"0.03","fee":"0.000025""#));
+
+        let records = fill_records_from_json_lines(text.as_bytes()).unwrap();
+        assert_eq!(records, fill_records(&events, &meta).unwrap());
+        assert_eq!(records[2].fee, None);
+
+        assert!(fill_records(&events, &BTreeMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_csv_in_atoms() {
+        let (events, meta) = events();
+        let mut bytes = vec![];
+        fills_to_csv_in_atoms(&events, &meta, &mut bytes).unwrap();
+        let csv = String::from_utf8(bytes).unwrap();
+        let rows = csv
+            .lines()
+            .map(|line| line.split(',').skip(7).collect::<Vec<_>>().join(","))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            [
+                "price_in_ticks,base_atoms,quote_atoms,fee_atoms",
+                "3000,100000000,30000,25",
+                "3001,200000000,60020,",
+                "2999,1000000,299,",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prices_beyond_96_bits() {
+        let (events, mut meta) = events();
+        for meta in meta.values_mut() {
+            meta.tick_size_in_quote_atoms_per_base_unit = u64::MAX;
+            // So that the fill's quote atoms still fit in a u64
+            meta.num_base_lots_per_base_unit = u64::MAX;
+        }
+        let fill = Fill::new(5, Pubkey::default(), Pubkey::default(), u64::MAX, 1, 0);
+        let event = PhoenixEvent {
+            details: MarketEventDetails::Fill(fill),
+            ..events[0]
+        };
+        let records = fill_records(&[event], &meta).unwrap();
+        // (2^64 - 1)^2 quote atoms per base unit, at 10^6 quote atoms per quote unit
+        assert_eq!(records[0].price, "340282366920938463426481119284349.108225");
+    }
+
+    #[test]
+    fn test_amounts_beyond_u64_atoms() {
+        let (events, meta) = events();
+        let fill = |price_in_ticks, base_lots| PhoenixEvent {
+            details: MarketEventDetails::Fill(Fill::new(
+                5,
+                Pubkey::default(),
+                Pubkey::default(),
+                price_in_ticks,
+                base_lots,
+                0,
+            )),
+            ..events[0]
+        };
+        // Too many quote atoms, then too many base atoms
+        assert!(fill_records_in_atoms(&[fill(u64::MAX, 1 << 40)], &meta).is_err());
+        assert!(fill_records_in_atoms(&[fill(1, u64::MAX)], &meta).is_err());
+        assert!(fill_records(&[fill(u64::MAX, 1 << 40)], &meta).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::clock_monitor::SkewJump;
+use crate::rpc_pool::EndpointHealth;
+use crate::task_supervisor::TaskHealth;
+
+/// Ordered from best to worst, so the status of a report is the `max` of its components'.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    #[default]
+    Healthy,
+    /// Working, but slower or less redundant than it should be.
+    Degraded,
+    Unhealthy,
+}
+
+/// Limits past which components report themselves `Degraded`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthThresholds {
+    /// Slots an event stream may trail the chain by.
+    pub max_slot_lag: u64,
+    /// Time a book subscriber may go without a notification.
+    pub max_book_staleness: Duration,
+    /// Share of a rate limiter's burst that may be spent.
+    pub max_rate_limiter_saturation: f64,
+    /// Skew a clock monitor may estimate between event timestamps and the local clock.
+    pub max_clock_skew: Duration,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            // About a minute
+            max_slot_lag: 150,
+            max_book_staleness: Duration::from_secs(30),
+            max_rate_limiter_saturation: 0.9,
+            // Header timestamps are whole seconds and the skew includes some latency
+            max_clock_skew: Duration::from_secs(5),
+        }
+    }
+}
+
+/// What a component reports about itself, by kind of component.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ComponentDetails {
+    RpcPool {
+        endpoints: Vec<EndpointHealth>,
+    },
+    RateLimiter {
+        /// Share of the burst that is spent, above 1 while callers are held back.
+        saturation: f64,
+    },
+    EventStream {
+        chain_slot: u64,
+        last_processed_slot: u64,
+        slot_lag: u64,
+    },
+    BookSubscriber {
+        #[serde(serialize_with = "serialize_display")]
+        market: Pubkey,
+        last_update_slot: Option<u64>,
+        /// Time since the last notification, or since the subscriber was created.
+        #[serde(serialize_with = "serialize_millis")]
+        staleness: Duration,
+    },
+    Tasks {
+        tasks: Vec<TaskHealth>,
+    },
+    Clock {
+        skew_millis: Option<i64>,
+        skew_jumps: u64,
+        last_jump: Option<SkewJump>,
+    },
+    /// For providers outside the SDK.
+    Other {
+        details: serde_json::Value,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    pub details: ComponentDetails,
+}
+
+/// A component that can report its health without making requests, e.g. from counters it
+/// already keeps. Register one with `SDKClient::register_health_provider` to include it in
+/// `SDKClient::health`. `PhoenixRuntime` registers the components it runs.
+pub trait HealthProvider: Send + Sync {
+    fn health_check(&self, thresholds: &HealthThresholds) -> ComponentHealth;
+}
+
+/// The most recent transaction the client sent successfully.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LastTransaction {
+    #[serde(serialize_with = "serialize_display")]
+    pub signature: Signature,
+    /// Time since it was sent.
+    #[serde(serialize_with = "serialize_millis")]
+    pub age: Duration,
+}
+
+/// The state of an `SDKClient` and the components registered with it, see `SDKClient::health`.
+/// Durations serialize as milliseconds and keys and signatures as base58 strings.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthReport {
+    /// The worst status of any component.
+    pub status: HealthStatus,
+    /// By the name they were registered under.
+    pub components: BTreeMap<String, ComponentHealth>,
+    pub last_transaction: Option<LastTransaction>,
+}
+
+impl HealthReport {
+    pub fn new(
+        components: BTreeMap<String, ComponentHealth>,
+        last_transaction: Option<LastTransaction>,
+    ) -> Self {
+        Self {
+            status: components
+                .values()
+                .map(|component| component.status)
+                .max()
+                .unwrap_or_default(),
+            components,
+            last_transaction,
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.status == HealthStatus::Healthy
+    }
+}
+
+pub(crate) fn serialize_millis<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+pub(crate) fn serialize_optional_millis<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    duration
+        .map(|duration| duration.as_millis() as u64)
+        .serialize(serializer)
+}
+
+pub(crate) fn serialize_display<T: Display, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_metrics::EventMetrics;
+
+    #[test]
+    fn test_report_status_and_json() {
+        let thresholds = HealthThresholds::default();
+        let metrics = EventMetrics::default();
+        metrics.record_chain_slot(1_000);
+        metrics.record_processed_slot(990);
+        let stream = metrics.health_check(&thresholds);
+        assert_eq!(stream.status, HealthStatus::Healthy);
+
+        let report = HealthReport::new(
+            BTreeMap::from([("events".to_string(), stream.clone())]),
+            None,
+        );
+        assert!(report.is_healthy());
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "status": "healthy",
+                "components": {
+                    "events": {
+                        "status": "healthy",
+                        "details": {
+                            "kind": "event_stream",
+                            "chain_slot": 1_000,
+                            "last_processed_slot": 990,
+                            "slot_lag": 10,
+                        },
+                    },
+                },
+                "last_transaction": null,
+            })
+        );
+
+        // The worst component sets the report's status
+        metrics.record_chain_slot(2_000);
+        let lagging = metrics.health_check(&thresholds);
+        assert_eq!(lagging.status, HealthStatus::Degraded);
+        let custom = ComponentHealth {
+            status: HealthStatus::Unhealthy,
+            details: ComponentDetails::Other {
+                details: serde_json::json!({ "feed": "stale" }),
+            },
+        };
+        let components = [
+            ("events", stream),
+            ("lagging", lagging),
+            ("pricing", custom),
+        ]
+        .map(|(name, health)| (name.to_string(), health));
+        let report = HealthReport::new(BTreeMap::from(components), None);
+        assert_eq!(report.status, HealthStatus::Unhealthy);
+        let empty = HealthReport::new(BTreeMap::new(), None);
+        assert_eq!(empty.status, HealthStatus::Healthy);
+    }
+}
@@ -0,0 +1,608 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk_core::market_event::{Fill, MarketEventDetails, PhoenixEvent, Reduce};
+use phoenix_sdk_core::order_manager::{OpenOrder, OrderManager};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::Notify;
+use tokio::time::{sleep_until, Instant};
+
+use crate::sdk_client::SDKClient;
+use crate::sdk_market_event::{ControlMsg, SDKMarketEvent};
+
+/// The parent order an `IcebergExecutor` works.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcebergSpec {
+    pub market: Pubkey,
+    pub side: Side,
+    pub price_in_ticks: u64,
+    pub total_base_lots: u64,
+    /// Size of each child order, before randomization.
+    pub display_base_lots: u64,
+    /// Each child's size is drawn uniformly from `display_base_lots ± display_variance_base_lots`,
+    /// so the replenished size does not give the parent away. Must be less than
+    /// `display_base_lots`.
+    pub display_variance_base_lots: u64,
+    /// A child filled down to this many lots or fewer is cancelled and replaced by a full-size
+    /// one. With 0, children are only replaced once fully filled.
+    pub replenish_threshold_base_lots: u64,
+    /// Shortest time between two child orders.
+    pub min_replenish_delay: Duration,
+    pub client_order_id: u128,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IcebergStatus {
+    Working,
+    /// The full size was filled.
+    Completed,
+    Cancelled,
+    /// A child order could not be placed or cancelled, or the event channel closed. Fills from
+    /// earlier children are kept; a child may still be resting.
+    Failed(String),
+}
+
+impl IcebergStatus {
+    pub fn is_finished(&self) -> bool {
+        *self != IcebergStatus::Working
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IcebergProgress {
+    pub status: IcebergStatus,
+    pub total_base_lots: u64,
+    pub filled_base_lots: u64,
+    pub children_placed: u32,
+    /// The child order currently resting, with its unfilled size.
+    pub working_child: Option<OpenOrder>,
+}
+
+impl IcebergProgress {
+    /// Base lots neither filled nor resting in the working child.
+    pub fn unworked_base_lots(&self) -> u64 {
+        self.total_base_lots
+            - self.filled_base_lots
+            - self.working_child.map_or(0, |child| child.num_base_lots)
+    }
+}
+
+#[derive(Debug)]
+struct IcebergShared {
+    progress: IcebergProgress,
+    cancelled: bool,
+}
+
+/// Controls a running `IcebergExecutor` and reads its progress.
+#[derive(Debug, Clone)]
+pub struct IcebergHandle {
+    shared: Arc<Mutex<IcebergShared>>,
+    wake: Arc<Notify>,
+}
+
+impl IcebergHandle {
+    fn lock(&self) -> MutexGuard<'_, IcebergShared> {
+        self.shared
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn progress(&self) -> IcebergProgress {
+        self.lock().progress.clone()
+    }
+
+    pub fn status(&self) -> IcebergStatus {
+        self.lock().progress.status.clone()
+    }
+
+    /// Base lots filled so far.
+    pub fn filled(&self) -> u64 {
+        self.lock().progress.filled_base_lots
+    }
+
+    /// Cancels the parent. The executor cancels the working child and places no further ones,
+    /// including when a child is in flight: it is cancelled as soon as it lands.
+    pub fn cancel(&self) {
+        self.lock().cancelled = true;
+        self.wake.notify_one();
+    }
+}
+
+/// The child order currently working.
+#[derive(Debug, Clone, Copy)]
+struct Child {
+    order_id: FIFOOrderId,
+    placed_base_lots: u64,
+    /// Fills of this child seen on the event channel.
+    observed_fills: u64,
+}
+
+/// Works a large post-only order by showing only a small child order at a time, replenishing it
+/// at the same price as it fills.
+///
+/// The executor is driven by the event channel: an `OrderManager` follows the working child
+/// through its fill and reduce events. Once the child is gone, or filled down to the replenish
+/// threshold and cancelled, it is settled and the next child is placed after
+/// `min_replenish_delay`. The trader must already have a seat on the market.
+///
+/// A child's fills are settled from the chain's view where possible: when a child is cancelled,
+/// its fills are its placed size minus the lots the cancel removed, which also covers fills that
+/// landed just before the cancel but were not yet seen on the channel. Fill events of settled
+/// children are ignored, so nothing is counted twice.
+pub struct IcebergExecutor {
+    spec: IcebergSpec,
+    handle: IcebergHandle,
+    orders: OrderManager,
+    child: Option<Child>,
+    last_placed_at: Option<Instant>,
+    rng: StdRng,
+}
+
+impl IcebergExecutor {
+    pub fn new(client: &SDKClient, spec: IcebergSpec) -> Result<(Self, IcebergHandle)> {
+        client.get_market_metadata_from_cache(&spec.market)?;
+        if spec.total_base_lots == 0 || spec.display_base_lots == 0 {
+            bail!("An iceberg needs a non-zero total and display size");
+        }
+        if spec.display_variance_base_lots >= spec.display_base_lots {
+            bail!(
+                "Display variance of {} lots must be less than the display size of {} lots",
+                spec.display_variance_base_lots,
+                spec.display_base_lots
+            );
+        }
+        if spec.replenish_threshold_base_lots >= spec.display_base_lots {
+            bail!(
+                "Replenish threshold of {} lots must be less than the display size of {} lots",
+                spec.replenish_threshold_base_lots,
+                spec.display_base_lots
+            );
+        }
+
+        let handle = IcebergHandle {
+            shared: Arc::new(Mutex::new(IcebergShared {
+                progress: IcebergProgress {
+                    status: IcebergStatus::Working,
+                    total_base_lots: spec.total_base_lots,
+                    filled_base_lots: 0,
+                    children_placed: 0,
+                    working_child: None,
+                },
+                cancelled: false,
+            })),
+            wake: Arc::new(Notify::new()),
+        };
+        Ok((
+            Self {
+                spec,
+                handle: handle.clone(),
+                orders: OrderManager::new(client.trader),
+                child: None,
+                last_placed_at: None,
+                rng: StdRng::from_entropy(),
+            },
+            handle,
+        ))
+    }
+
+    /// Uses `rng` to randomize child sizes, e.g. a seeded one for reproducible sizes.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Works the parent until it is filled, cancelled or fails, and returns the final progress.
+    pub async fn run(
+        mut self,
+        client: &SDKClient,
+        events: &mut Receiver<Vec<SDKMarketEvent>>,
+    ) -> IcebergProgress {
+        let mut channel_open = true;
+        loop {
+            while let Ok(batch) = events.try_recv() {
+                self.on_events(batch);
+            }
+            if self.handle.lock().cancelled {
+                return match self.cancel_child(client).await {
+                    Ok(()) => self.finish(IcebergStatus::Cancelled),
+                    Err(e) => self.finish(IcebergStatus::Failed(e.to_string())),
+                };
+            }
+            if let Err(e) = self.replenish_if_due(client).await {
+                return self.finish(IcebergStatus::Failed(e.to_string()));
+            }
+            let progress = self.handle.progress();
+            if progress.filled_base_lots == progress.total_base_lots {
+                return self.finish(IcebergStatus::Completed);
+            }
+            if !channel_open && self.child.is_some() {
+                return self.finish(IcebergStatus::Failed(
+                    "Event channel closed with a child order working".to_string(),
+                ));
+            }
+
+            // Without a child, the next one waits on the replenish delay
+            let replenish_at = match self.child {
+                Some(_) => None,
+                None => self
+                    .last_placed_at
+                    .map(|placed_at| placed_at + self.spec.min_replenish_delay),
+            };
+            let wake = self.handle.wake.clone();
+            tokio::select! {
+                batch = events.recv(), if channel_open => match batch {
+                    Some(batch) => self.on_events(batch),
+                    None => channel_open = false,
+                },
+                _ = wake.notified() => {}
+                _ = sleep_until(replenish_at.unwrap_or_else(Instant::now)), if replenish_at.is_some() => {}
+            }
+        }
+    }
+
+    /// Settles the working child if it is done, then places the next one if the delay allows.
+    async fn replenish_if_due(&mut self, client: &SDKClient) -> Result<()> {
+        if let Some(child) = self.child {
+            match self.resting_base_lots(&child) {
+                None => self.settle_child(child.placed_base_lots),
+                Some(resting) if resting <= self.spec.replenish_threshold_base_lots => {
+                    self.cancel_child(client).await?
+                }
+                Some(_) => return Ok(()),
+            }
+        }
+        let unworked = self.handle.progress().unworked_base_lots();
+        if unworked == 0 {
+            return Ok(());
+        }
+        if self
+            .last_placed_at
+            .is_some_and(|placed_at| placed_at.elapsed() < self.spec.min_replenish_delay)
+        {
+            return Ok(());
+        }
+        let size = self.next_child_size(unworked);
+        self.place_child(client, size).await
+    }
+
+    fn next_child_size(&mut self, unworked_base_lots: u64) -> u64 {
+        let display = self.spec.display_base_lots;
+        let variance = self.spec.display_variance_base_lots;
+        let size = if variance == 0 {
+            display
+        } else {
+            self.rng
+                .gen_range(display - variance, display + variance + 1)
+        };
+        size.min(unworked_base_lots)
+    }
+
+    async fn place_child(&mut self, client: &SDKClient, num_base_lots: u64) -> Result<()> {
+        let ix = client.get_post_only_ix_from_tick_price(
+            &self.spec.market,
+            self.spec.price_in_ticks,
+            self.spec.side,
+            num_base_lots,
+            self.spec.client_order_id,
+            false,
+        )?;
+        let signature = client.send_ixs(vec![ix]).await?;
+        self.last_placed_at = Some(Instant::now());
+        let place = client
+            .parse_places(&signature)
+            .await
+            .into_iter()
+            .find(|event| match event.details {
+                MarketEventDetails::Place(place) => {
+                    event.market == self.spec.market && place.maker == client.trader
+                }
+                _ => false,
+            })
+            .ok_or_else(|| anyhow!("Child order in {} did not rest on the book", signature))?;
+        self.orders.apply_event(&place);
+        let MarketEventDetails::Place(place_details) = place.details else {
+            unreachable!()
+        };
+        let child = Child {
+            order_id: FIFOOrderId::new_from_untyped(
+                place_details.price_in_ticks,
+                place_details.order_sequence_number,
+            ),
+            placed_base_lots: place_details.base_lots_placed,
+            observed_fills: 0,
+        };
+        self.child = Some(child);
+
+        let mut shared = self.handle.lock();
+        shared.progress.children_placed += 1;
+        shared.progress.working_child = self.working_child(&child);
+        Ok(())
+    }
+
+    /// Cancels the working child, if any, and settles it from the lots the cancel removed.
+    async fn cancel_child(&mut self, client: &SDKClient) -> Result<()> {
+        let Some(child) = self.child else {
+            return Ok(());
+        };
+        let ix = client.get_cancel_ids_ix(&self.spec.market, [child.order_id])?;
+        let signature = client.send_ixs(vec![ix]).await?;
+        let mut removed = 0;
+        for event in client.parse_cancels(&signature).await {
+            if let MarketEventDetails::Reduce(Reduce {
+                order_sequence_number,
+                base_lots_removed,
+                ..
+            }) = event.details
+            {
+                if order_sequence_number == child.order_id.order_sequence_number {
+                    removed += base_lots_removed;
+                }
+            }
+            self.orders.apply_event(&event);
+        }
+        self.settle_child(child.placed_base_lots.saturating_sub(removed));
+        Ok(())
+    }
+
+    /// Books the final fills of the working child and clears it. Fills already seen on the
+    /// channel are a lower bound, since the chain's own account may run ahead of them.
+    fn settle_child(&mut self, chain_fills: u64) {
+        let Some(child) = self.child.take() else {
+            return;
+        };
+        let fills = chain_fills
+            .max(child.observed_fills)
+            .min(child.placed_base_lots);
+        let mut shared = self.handle.lock();
+        shared.progress.filled_base_lots += fills;
+        shared.progress.working_child = None;
+    }
+
+    fn on_events(&mut self, batch: Vec<SDKMarketEvent>) {
+        for message in batch {
+            match message {
+                SDKMarketEvent::PhoenixEvent { event } => {
+                    // The child's place event was already applied from its transaction, and
+                    // applying it again would undo fills seen since
+                    if matches!(event.details, MarketEventDetails::Place(_)) {
+                        continue;
+                    }
+                    self.orders.apply_event(&event);
+                    self.observe_fill(&event, false);
+                }
+                SDKMarketEvent::Control(ControlMsg::Rollback { events, .. }) => {
+                    for event in events.iter() {
+                        self.observe_fill(event, true);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(child) = self.child {
+            self.handle.lock().progress.working_child = self.working_child(&child);
+        }
+    }
+
+    fn observe_fill(&mut self, event: &PhoenixEvent, rolled_back: bool) {
+        let (
+            Some(child),
+            MarketEventDetails::Fill(Fill {
+                order_sequence_number,
+                base_lots_filled,
+                ..
+            }),
+        ) = (self.child.as_mut(), event.details)
+        else {
+            return;
+        };
+        if event.market != self.spec.market
+            || order_sequence_number != child.order_id.order_sequence_number
+        {
+            return;
+        }
+        child.observed_fills = if rolled_back {
+            child.observed_fills.saturating_sub(base_lots_filled)
+        } else {
+            child.observed_fills + base_lots_filled
+        };
+    }
+
+    /// The child's unfilled size according to the order manager, or `None` once it left the book.
+    fn resting_base_lots(&self, child: &Child) -> Option<u64> {
+        self.orders
+            .open_orders(&self.spec.market)
+            .find(|order| order.order_id == child.order_id)
+            .map(|order| order.num_base_lots)
+    }
+
+    fn working_child(&self, child: &Child) -> Option<OpenOrder> {
+        self.orders
+            .open_orders(&self.spec.market)
+            .find(|order| order.order_id == child.order_id)
+            .copied()
+    }
+
+    fn finish(self, status: IcebergStatus) -> IcebergProgress {
+        let mut shared = self.handle.lock();
+        shared.progress.status = status;
+        shared.progress.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::paper_trading::test_utils::paper_client;
+    use phoenix_sdk_core::test_utils::event;
+    use solana_sdk::signature::Signature;
+    use tokio::sync::mpsc::{channel, Sender};
+
+    fn spec(market: Pubkey) -> IcebergSpec {
+        IcebergSpec {
+            market,
+            side: Side::Bid,
+            price_in_ticks: 100,
+            total_base_lots: 120,
+            display_base_lots: 50,
+            display_variance_base_lots: 0,
+            replenish_threshold_base_lots: 0,
+            min_replenish_delay: Duration::from_millis(20),
+            client_order_id: 7,
+        }
+    }
+
+    /// Paper orders never trade, so fills of the resting child are sent on the channel by hand.
+    async fn fill_child(
+        tx: &Sender<Vec<SDKMarketEvent>>,
+        market: Pubkey,
+        maker: Pubkey,
+        child: OpenOrder,
+        base_lots_filled: u64,
+    ) {
+        let base_lots_remaining = child.num_base_lots - base_lots_filled;
+        let event = PhoenixEvent {
+            signature: Signature::new_unique(),
+            signer: Pubkey::new_unique(),
+            ..event(
+                market,
+                MarketEventDetails::Fill(Fill {
+                    order_sequence_number: child.order_id.order_sequence_number,
+                    maker,
+                    taker: Pubkey::new_unique(),
+                    price_in_ticks: child.price_in_ticks(),
+                    base_lots_filled,
+                    base_lots_remaining,
+                    side_filled: child.side,
+                    maker_side: child.side,
+                    taker_side: child.side.opposite(),
+                    is_full_fill: base_lots_remaining == 0,
+                }),
+            )
+        };
+        tx.send(vec![event.into()]).await.unwrap();
+    }
+
+    /// Waits for a child other than `previous` to be working.
+    async fn next_child(handle: &IcebergHandle, previous: Option<OpenOrder>) -> OpenOrder {
+        loop {
+            let working = handle.progress().working_child;
+            if let Some(child) = working
+                .filter(|child| previous.is_none_or(|previous| previous.order_id != child.order_id))
+            {
+                return child;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replenish_sequence() {
+        let (sdk, market) = paper_client(&[]).await;
+        let (tx, mut rx) = channel(16);
+        sdk.paper_exchange().set_event_sender(Some(tx.clone()));
+        let trader = sdk.trader;
+
+        let (executor, handle) = IcebergExecutor::new(&sdk, spec(market)).unwrap();
+        let start = Instant::now();
+        let driver = async {
+            let mut sizes = vec![];
+            let first = next_child(&handle, None).await;
+            sizes.push(first.num_base_lots);
+            fill_child(&tx, market, trader, first, 50).await;
+
+            // The second child is filled in two steps; the first step does not replenish
+            let second = next_child(&handle, Some(first)).await;
+            sizes.push(second.num_base_lots);
+            fill_child(&tx, market, trader, second, 30).await;
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            assert_eq!(handle.progress().children_placed, 2);
+            let partially_filled = handle.progress().working_child.unwrap();
+            assert_eq!(partially_filled.num_base_lots, 20);
+            fill_child(&tx, market, trader, partially_filled, 20).await;
+
+            let third = next_child(&handle, Some(second)).await;
+            sizes.push(third.num_base_lots);
+            fill_child(&tx, market, trader, third, 20).await;
+            sizes
+        };
+        let (progress, sizes) = tokio::join!(executor.run(&sdk, &mut rx), driver);
+
+        assert_eq!(sizes, vec![50, 50, 20]);
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(progress.status, IcebergStatus::Completed);
+        assert_eq!(progress.children_placed, 3);
+        assert_eq!(progress.filled_base_lots, 120);
+        assert_eq!(progress.working_child, None);
+    }
+
+    #[tokio::test]
+    async fn test_threshold_replace_and_cancel() {
+        let (sdk, market) = paper_client(&[]).await;
+        let (tx, mut rx) = channel(16);
+        sdk.paper_exchange().set_event_sender(Some(tx.clone()));
+        let trader = sdk.trader;
+
+        let (executor, handle) = IcebergExecutor::new(
+            &sdk,
+            IcebergSpec {
+                total_base_lots: 100,
+                display_base_lots: 40,
+                replenish_threshold_base_lots: 10,
+                ..spec(market)
+            },
+        )
+        .unwrap();
+        let driver = async {
+            // 35 of 40 lots fill, leaving 5 lots, under the threshold
+            let first = next_child(&handle, None).await;
+            fill_child(&tx, market, trader, first, 35).await;
+            let second = next_child(&handle, Some(first)).await;
+            assert_eq!(second.num_base_lots, 40);
+            assert_eq!(handle.filled(), 35);
+            assert_eq!(handle.progress().unworked_base_lots(), 25);
+            handle.cancel();
+        };
+        let (progress, ()) = tokio::join!(executor.run(&sdk, &mut rx), driver);
+
+        assert_eq!(progress.status, IcebergStatus::Cancelled);
+        assert_eq!(progress.children_placed, 2);
+        assert_eq!(progress.filled_base_lots, 35);
+        assert_eq!(progress.working_child, None);
+        assert!(sdk.paper_exchange().open_orders(&market).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_randomized_display_size() {
+        let (sdk, market) = paper_client(&[]).await;
+        let (executor, _) = IcebergExecutor::new(
+            &sdk,
+            IcebergSpec {
+                display_variance_base_lots: 10,
+                ..spec(market)
+            },
+        )
+        .unwrap();
+        let mut executor = executor.with_rng(StdRng::seed_from_u64(3));
+        let sizes = (0..100)
+            .map(|_| executor.next_child_size(1000))
+            .collect::<Vec<_>>();
+        assert!(sizes.iter().all(|size| (40..=60).contains(size)));
+        assert!(sizes.windows(2).any(|pair| pair[0] != pair[1]));
+        assert_eq!(executor.next_child_size(5), 5);
+
+        assert!(IcebergExecutor::new(
+            &sdk,
+            IcebergSpec {
+                display_variance_base_lots: 50,
+                ..spec(market)
+            }
+        )
+        .is_err());
+    }
+}
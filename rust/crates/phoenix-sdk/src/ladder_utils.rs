@@ -109,20 +109,18 @@ mod test {
                 },
             ],
         };
-        let fixture = Fixture {
+        Fixture {
             ladder,
             atoms_in_base_lot: 1e6,
             atoms_in_quote_lot: 1.,
             atoms_in_base_unit: 1e9,
             atoms_in_quote_unit: 1e6,
-        };
-        fixture
+        }
     }
 
     fn lots_to_unit_amount(lots: u64, lots_to_atoms: f64, atoms_to_unit: f64) -> f64 {
         let atoms = lots_to_atoms * lots as f64;
-        let unit = atoms / atoms_to_unit;
-        unit
+        atoms / atoms_to_unit
     }
 
     #[test]
@@ -0,0 +1,594 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent, Place};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::clock_monitor::ClockMonitor;
+use crate::sdk_market_event::SDKMarketEvent;
+
+/// Samples kept per leg for percentiles.
+const DEFAULT_WINDOW: usize = 4096;
+
+/// A measured span of an order's or event's path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LatencyLeg {
+    /// From handing the transaction to the send helper to receiving its first `Place` event.
+    /// Measured on the monotonic clock.
+    SubmitToPlace,
+    /// From handing the transaction to the send helper to the send helper returning it confirmed.
+    /// Measured on the monotonic clock.
+    SubmitToConfirmation,
+    /// From the event's block time to its local receipt. Compares the chain's clock with the
+    /// local wall clock, so it includes their skew, and block times are whole seconds. With a
+    /// clock monitor attached, the block time is normalized to the local clock first.
+    ChainToReceive,
+}
+
+impl LatencyLeg {
+    pub const ALL: [LatencyLeg; 3] = [
+        LatencyLeg::SubmitToPlace,
+        LatencyLeg::SubmitToConfirmation,
+        LatencyLeg::ChainToReceive,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LatencyLeg::SubmitToPlace => "submit_to_place",
+            LatencyLeg::SubmitToConfirmation => "submit_to_confirmation",
+            LatencyLeg::ChainToReceive => "chain_to_receive",
+        }
+    }
+
+    /// Whether the leg compares the chain's clock with the local one.
+    pub fn includes_clock_skew(&self) -> bool {
+        matches!(self, LatencyLeg::ChainToReceive)
+    }
+}
+
+/// When an event was received, on both clocks: `instant` to compare with submit times, and
+/// `system_time` to compare with the chain's block time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceiveStamp {
+    pub instant: Instant,
+    pub system_time: SystemTime,
+}
+
+impl ReceiveStamp {
+    pub fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            system_time: SystemTime::now(),
+        }
+    }
+}
+
+/// Percentiles over the most recent samples of a leg.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    /// Samples recorded since the tracker was created, including those no longer in the window.
+    pub count: u64,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// The most recent `window` samples of one leg.
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    samples: VecDeque<Duration>,
+    window: usize,
+    count: u64,
+}
+
+impl LatencyHistogram {
+    pub fn new(window: usize) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            window: window.max(1),
+            count: 0,
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The `percentile` (0 to 100) of the samples in the window, by nearest rank.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut sorted = self.samples.iter().copied().collect::<Vec<_>>();
+        sorted.sort();
+        nearest_rank(&sorted, percentile)
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        let mut sorted = self.samples.iter().copied().collect::<Vec<_>>();
+        sorted.sort();
+        let at = |percentile| nearest_rank(&sorted, percentile).unwrap_or_default();
+        LatencySummary {
+            count: self.count,
+            p50: at(50.0),
+            p90: at(90.0),
+            p99: at(99.0),
+            max: sorted.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+fn nearest_rank(sorted: &[Duration], percentile: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Submission {
+    submitted_at: Instant,
+    placed: bool,
+}
+
+#[derive(Debug)]
+struct TrackerState {
+    histograms: HashMap<LatencyLeg, LatencyHistogram>,
+    submissions: HashMap<Signature, Submission>,
+    by_client_order_id: HashMap<u128, Signature>,
+    /// Submissions and unmatched events in the order they were recorded, for expiry.
+    expiry: VecDeque<(Instant, Expiring)>,
+    /// First `Place` receipt of transactions whose submission is not recorded yet, since events
+    /// can arrive before the send helper returns.
+    early_places: HashMap<Signature, Instant>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Expiring {
+    Submission(Signature),
+    EarlyPlace(Signature),
+}
+
+/// Correlates the trader's submissions with their events to measure latency.
+///
+/// Submissions are recorded by the send helpers of an `SDKClient` with the tracker set (see
+/// `SDKClient::set_latency_tracker`), and events are stamped on receipt by a `MetricsSink` with
+/// the tracker attached. A `Place` event is matched to its submission by transaction signature,
+/// or else by client order id among the trader's orders. Submissions and unmatched events are
+/// forgotten after `match_timeout`.
+#[derive(Debug)]
+pub struct LatencyTracker {
+    pub trader: Pubkey,
+    match_timeout: Duration,
+    window: usize,
+    clock_monitor: Option<Arc<ClockMonitor>>,
+    state: Mutex<TrackerState>,
+}
+
+impl LatencyTracker {
+    pub fn new(trader: Pubkey) -> Self {
+        Self::with_window(trader, DEFAULT_WINDOW, Duration::from_secs(120))
+    }
+
+    /// Keeps the last `window` samples per leg for percentiles.
+    pub fn with_window(trader: Pubkey, window: usize, match_timeout: Duration) -> Self {
+        Self {
+            trader,
+            match_timeout,
+            window,
+            clock_monitor: None,
+            state: Mutex::new(TrackerState {
+                histograms: HashMap::new(),
+                submissions: HashMap::new(),
+                by_client_order_id: HashMap::new(),
+                expiry: VecDeque::new(),
+                early_places: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Measures the chain-to-receive leg from event timestamps normalized by `clock_monitor`,
+    /// leaving out the skew it estimates. The monitor is fed separately, e.g. by a `MetricsSink`.
+    pub fn with_clock_monitor(mut self, clock_monitor: Arc<ClockMonitor>) -> Self {
+        self.clock_monitor = Some(clock_monitor);
+        self
+    }
+
+    /// Records a transaction handed to the network at `submitted_at`, with the client order ids of
+    /// the orders it places.
+    pub fn record_submit(
+        &self,
+        signature: Signature,
+        client_order_ids: &[u128],
+        submitted_at: Instant,
+    ) {
+        let mut state = self.state();
+        self.expire(&mut state, Instant::now());
+        let mut submission = Submission {
+            submitted_at,
+            placed: false,
+        };
+        if let Some(received_at) = state.early_places.remove(&signature) {
+            submission.placed = true;
+            self.record(
+                &mut state,
+                LatencyLeg::SubmitToPlace,
+                received_at.saturating_duration_since(submitted_at),
+            );
+        }
+        state.submissions.insert(signature, submission);
+        for client_order_id in client_order_ids {
+            state.by_client_order_id.insert(*client_order_id, signature);
+        }
+        state
+            .expiry
+            .push_back((Instant::now(), Expiring::Submission(signature)));
+    }
+
+    /// Records that the transaction submitted at `submitted_at` was confirmed at `confirmed_at`.
+    pub fn record_confirmation(&self, submitted_at: Instant, confirmed_at: Instant) {
+        let mut state = self.state();
+        self.record(
+            &mut state,
+            LatencyLeg::SubmitToConfirmation,
+            confirmed_at.saturating_duration_since(submitted_at),
+        );
+    }
+
+    /// Records the events of a batch received at `received`.
+    pub fn on_events(&self, events: &[SDKMarketEvent], received: ReceiveStamp) {
+        for message in events {
+            if let SDKMarketEvent::PhoenixEvent { event } = message {
+                self.on_event(event, received);
+            }
+        }
+    }
+
+    /// Records an event received at `received`. Each transaction counts once towards the
+    /// chain-to-receive leg, on its first event.
+    pub fn on_event(&self, event: &PhoenixEvent, received: ReceiveStamp) {
+        let mut state = self.state();
+        if event.event_index == 0 && event.timestamp > 0 {
+            let block_time = match &self.clock_monitor {
+                Some(clock_monitor) => clock_monitor.normalize_timestamp(event),
+                None => UNIX_EPOCH + Duration::from_secs(event.timestamp as u64),
+            };
+            let latency = received
+                .system_time
+                .duration_since(block_time)
+                .unwrap_or_default();
+            self.record(&mut state, LatencyLeg::ChainToReceive, latency);
+        }
+        let MarketEventDetails::Place(Place {
+            client_order_id,
+            maker,
+            ..
+        }) = event.details
+        else {
+            return;
+        };
+        if maker != self.trader {
+            return;
+        }
+        let signature = if state.submissions.contains_key(&event.signature) {
+            Some(event.signature)
+        } else {
+            state.by_client_order_id.get(&client_order_id).copied()
+        };
+        let Some(submission) =
+            signature.and_then(|signature| state.submissions.get_mut(&signature))
+        else {
+            if let Entry::Vacant(entry) = state.early_places.entry(event.signature) {
+                entry.insert(received.instant);
+                state
+                    .expiry
+                    .push_back((Instant::now(), Expiring::EarlyPlace(event.signature)));
+            }
+            return;
+        };
+        if submission.placed {
+            return;
+        }
+        submission.placed = true;
+        let latency = received
+            .instant
+            .saturating_duration_since(submission.submitted_at);
+        self.record(&mut state, LatencyLeg::SubmitToPlace, latency);
+    }
+
+    pub fn summary(&self, leg: LatencyLeg) -> LatencySummary {
+        self.state()
+            .histograms
+            .get(&leg)
+            .map(LatencyHistogram::summary)
+            .unwrap_or_default()
+    }
+
+    /// The `percentile` (0 to 100) of a leg's recent samples, or `None` if it has none.
+    pub fn percentile(&self, leg: LatencyLeg, percentile: f64) -> Option<Duration> {
+        self.state()
+            .histograms
+            .get(&leg)
+            .and_then(|histogram| histogram.percentile(percentile))
+    }
+
+    fn record(&self, state: &mut TrackerState, leg: LatencyLeg, latency: Duration) {
+        state
+            .histograms
+            .entry(leg)
+            .or_insert_with(|| LatencyHistogram::new(self.window))
+            .record(latency);
+    }
+
+    fn expire(&self, state: &mut TrackerState, now: Instant) {
+        while let Some(&(recorded_at, expiring)) = state.expiry.front() {
+            if now.saturating_duration_since(recorded_at) < self.match_timeout {
+                break;
+            }
+            state.expiry.pop_front();
+            match expiring {
+                Expiring::Submission(signature) => {
+                    state.submissions.remove(&signature);
+                    state
+                        .by_client_order_id
+                        .retain(|_, submitted| *submitted != signature);
+                }
+                Expiring::EarlyPlace(signature) => {
+                    state.early_places.remove(&signature);
+                }
+            }
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, TrackerState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Exposes a `LatencyTracker`'s percentiles to a Prometheus registry, as gauges in microseconds
+/// per leg and a sample counter. Values are read from the tracker on every scrape.
+#[cfg(feature = "prometheus")]
+pub struct LatencyCollector {
+    tracker: std::sync::Arc<LatencyTracker>,
+    gauges: Vec<(prometheus::IntGauge, LatencyLeg, SummaryValue)>,
+    counters: Vec<(prometheus::IntCounter, LatencyLeg)>,
+}
+
+#[cfg(feature = "prometheus")]
+type SummaryValue = fn(&LatencySummary) -> Duration;
+
+#[cfg(feature = "prometheus")]
+impl LatencyCollector {
+    /// Metric names are prefixed with `prefix` and the leg, e.g. `phoenix_latency` gives
+    /// `phoenix_latency_submit_to_place_p99_micros`.
+    pub fn new(tracker: std::sync::Arc<LatencyTracker>, prefix: &str) -> prometheus::Result<Self> {
+        let values: [(&str, SummaryValue); 4] = [
+            ("p50", |s| s.p50),
+            ("p90", |s| s.p90),
+            ("p99", |s| s.p99),
+            ("max", |s| s.max),
+        ];
+        let mut gauges = vec![];
+        let mut counters = vec![];
+        for leg in LatencyLeg::ALL {
+            let skew = if leg.includes_clock_skew() {
+                ", including chain clock skew"
+            } else {
+                ""
+            };
+            for (value_name, get) in values {
+                let gauge = prometheus::IntGauge::new(
+                    format!("{}_{}_{}_micros", prefix, leg.name(), value_name),
+                    format!("{} latency of {}{}", value_name, leg.name(), skew),
+                )?;
+                gauges.push((gauge, leg, get));
+            }
+            let counter = prometheus::IntCounter::new(
+                format!("{}_{}_samples", prefix, leg.name()),
+                format!("Latency samples of {}", leg.name()),
+            )?;
+            counters.push((counter, leg));
+        }
+        Ok(Self {
+            tracker,
+            gauges,
+            counters,
+        })
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl prometheus::core::Collector for LatencyCollector {
+    fn desc(&self) -> Vec<&prometheus::core::Desc> {
+        self.gauges
+            .iter()
+            .flat_map(|(gauge, _, _)| gauge.desc())
+            .chain(self.counters.iter().flat_map(|(counter, _)| counter.desc()))
+            .collect()
+    }
+
+    fn collect(&self) -> Vec<prometheus::proto::MetricFamily> {
+        let summaries = LatencyLeg::ALL
+            .iter()
+            .map(|leg| (*leg, self.tracker.summary(*leg)))
+            .collect::<HashMap<_, _>>();
+        for (gauge, leg, get) in self.gauges.iter() {
+            gauge.set(get(&summaries[leg]).as_micros() as i64);
+        }
+        for (counter, leg) in self.counters.iter() {
+            let value = summaries[leg].count;
+            counter.inc_by(value.saturating_sub(counter.get()));
+        }
+        self.gauges
+            .iter()
+            .flat_map(|(gauge, _, _)| gauge.collect())
+            .chain(
+                self.counters
+                    .iter()
+                    .flat_map(|(counter, _)| counter.collect()),
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use phoenix_sdk_core::test_utils::event;
+
+    fn place(trader: Pubkey, signature: Signature, client_order_id: u128) -> PhoenixEvent {
+        PhoenixEvent {
+            signature,
+            signer: trader,
+            event_index: 1,
+            ..event(
+                Pubkey::new_unique(),
+                MarketEventDetails::Place(Place {
+                    order_sequence_number: 1,
+                    client_order_id,
+                    maker: trader,
+                    price_in_ticks: 100,
+                    base_lots_placed: 10,
+                }),
+            )
+        }
+    }
+
+    fn stamp(instant: Instant) -> ReceiveStamp {
+        ReceiveStamp {
+            instant,
+            system_time: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut histogram = LatencyHistogram::new(100);
+        assert_eq!(histogram.percentile(50.0), None);
+        for millis in (1..=200).rev() {
+            histogram.record(Duration::from_millis(millis));
+        }
+        // Only the last 100 samples, 1ms to 100ms, are in the window
+        assert_eq!(histogram.count(), 200);
+        assert_eq!(histogram.percentile(50.0), Some(Duration::from_millis(50)));
+        assert_eq!(histogram.percentile(0.0), Some(Duration::from_millis(1)));
+        let summary = histogram.summary();
+        assert_eq!(summary.p90, Duration::from_millis(90));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+        assert_eq!(summary.max, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_correlates_submissions_and_events() {
+        let trader = Pubkey::new_unique();
+        let tracker = LatencyTracker::new(trader);
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+
+        // Matched by signature, once per transaction
+        let signature = Signature::new_unique();
+        tracker.record_submit(signature, &[], t0);
+        tracker.on_event(&place(trader, signature, 0), stamp(t0 + ms(40)));
+        tracker.on_event(&place(trader, signature, 0), stamp(t0 + ms(90)));
+        tracker.record_confirmation(t0, t0 + ms(400));
+
+        // Matched by client order id when the event's signature is unknown
+        tracker.record_submit(Signature::new_unique(), &[7], t0);
+        tracker.on_event(
+            &place(trader, Signature::new_unique(), 7),
+            stamp(t0 + ms(60)),
+        );
+
+        // The event arrives before the send helper returns
+        let early = Signature::new_unique();
+        tracker.on_event(&place(trader, early, 0), stamp(t0 + ms(20)));
+        tracker.record_submit(early, &[], t0);
+
+        // Another trader's orders are not ours
+        let other = Pubkey::new_unique();
+        tracker.on_event(&place(other, signature, 0), stamp(t0 + ms(1)));
+
+        let summary = tracker.summary(LatencyLeg::SubmitToPlace);
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.p50, ms(40));
+        assert_eq!(summary.max, ms(60));
+        assert_eq!(
+            tracker.percentile(LatencyLeg::SubmitToConfirmation, 50.0),
+            Some(ms(400))
+        );
+        assert_eq!(tracker.summary(LatencyLeg::ChainToReceive).count, 0);
+    }
+
+    #[test]
+    fn test_chain_to_receive() {
+        let tracker = LatencyTracker::new(Pubkey::new_unique());
+        let block_time = 1_700_000_000;
+        let mut event = place(Pubkey::new_unique(), Signature::new_unique(), 0);
+        event.timestamp = block_time;
+        event.event_index = 0;
+        let received = ReceiveStamp {
+            instant: Instant::now(),
+            system_time: UNIX_EPOCH + Duration::from_millis(block_time as u64 * 1000 + 1500),
+        };
+        tracker.on_events(&[event.into()], received);
+        assert_eq!(
+            tracker.percentile(LatencyLeg::ChainToReceive, 50.0),
+            Some(Duration::from_millis(1500))
+        );
+
+        // A local clock behind the chain's records zero rather than failing
+        let mut event = place(Pubkey::new_unique(), Signature::new_unique(), 0);
+        event.timestamp = block_time;
+        event.event_index = 0;
+        let behind = ReceiveStamp {
+            instant: Instant::now(),
+            system_time: UNIX_EPOCH + Duration::from_secs(block_time as u64 - 1),
+        };
+        tracker.on_event(&event, behind);
+        assert_eq!(
+            tracker.percentile(LatencyLeg::ChainToReceive, 0.0),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_chain_to_receive_normalized() {
+        use crate::clock_monitor::ClockMonitorConfig;
+
+        let clock_monitor = Arc::new(ClockMonitor::new(ClockMonitorConfig {
+            min_samples: 1,
+            ..Default::default()
+        }));
+        let tracker =
+            LatencyTracker::new(Pubkey::new_unique()).with_clock_monitor(clock_monitor.clone());
+        let block_time = 1_700_000_000;
+        let received_after = |millis| ReceiveStamp {
+            instant: Instant::now(),
+            system_time: UNIX_EPOCH + Duration::from_millis(block_time as u64 * 1000 + millis),
+        };
+        let mut event = place(Pubkey::new_unique(), Signature::new_unique(), 0);
+        event.timestamp = block_time;
+        event.event_index = 0;
+
+        // The local clock runs 4 seconds ahead of the chain's
+        clock_monitor.on_event(&event, received_after(4_000).system_time);
+        event.signature = Signature::new_unique();
+        tracker.on_event(&event, received_after(4_300));
+        assert_eq!(
+            tracker.percentile(LatencyLeg::ChainToReceive, 50.0),
+            Some(Duration::from_millis(300))
+        );
+    }
+}
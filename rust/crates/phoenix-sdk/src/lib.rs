@@ -1,5 +1,60 @@
 pub use phoenix_sdk_core::orderbook;
+pub mod analytics;
+pub mod backfill;
+pub mod backtest;
+pub mod book_subscriber;
+#[cfg(feature = "jito")]
+pub mod bundle_sender;
+pub mod candles;
+pub mod clock_monitor;
+pub mod commitment_policy;
+pub mod encoded_transaction;
+pub mod error;
+pub mod event_metrics;
+pub mod event_poller;
+pub mod event_recorder;
+pub mod event_router;
+pub mod event_sink;
+#[cfg(feature = "sqlite")]
+pub mod event_store;
+pub mod event_stream;
+pub mod exchange_json;
+pub mod expiry_sweeper;
+pub mod export;
+pub mod health;
+pub mod iceberg;
 pub mod ladder_utils;
+pub mod latency;
+pub mod market_quality;
+pub mod market_registry;
+pub mod metadata_cache;
 pub mod order_packet_template;
+pub mod order_preview;
+pub mod paper_trading;
+pub mod poll_schedule;
+pub mod portfolio;
+pub mod prelude;
+#[cfg(feature = "pyth")]
+pub mod pyth;
+pub mod quote_guard;
+pub mod rate_limiter;
+pub mod reorg_guard;
+pub mod risk_manager;
+pub mod rpc_pool;
+pub mod runtime;
 pub mod sdk_client;
+pub mod sdk_market_event;
+pub mod task_supervisor;
+#[cfg(any(test, feature = "test-vectors"))]
+pub mod test_vectors;
+pub mod top_of_book;
+pub mod tracked_order;
+pub mod trade_tape;
+pub mod trading_stack;
+pub mod trigger_engine;
+pub mod twap;
 pub mod utils;
+pub mod warm_up;
+#[cfg(feature = "reqwest")]
+pub mod webhook_sink;
+pub mod withdrawal;
@@ -0,0 +1,309 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use phoenix::quantities::WrapperU64;
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk_core::orderbook::Orderbook;
+use phoenix_sdk_core::sdk_client_core::{MarketState, PhoenixOrder};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::book_subscriber::BookUpdate;
+use crate::event_recorder::now_ms;
+
+/// The top of a book and of one maker's quotes at a point in time. Prices are in ticks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QualitySample {
+    pub timestamp_ms: u64,
+    pub best_bid: Option<u64>,
+    pub best_ask: Option<u64>,
+    pub maker_bid: Option<u64>,
+    pub maker_ask: Option<u64>,
+}
+
+impl QualitySample {
+    pub fn from_book(
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        maker: &Pubkey,
+        timestamp_ms: u64,
+    ) -> Self {
+        let best = |side| {
+            book.iter_side(side)
+                .next()
+                .map(|(order_id, _)| order_id.price_in_ticks.as_u64())
+        };
+        let maker_best = |side| {
+            book.iter_side(side)
+                .find(|(_, order)| order.maker_id == *maker)
+                .map(|(order_id, _)| order_id.price_in_ticks.as_u64())
+        };
+        Self {
+            timestamp_ms,
+            best_bid: best(Side::Bid),
+            best_ask: best(Side::Ask),
+            maker_bid: maker_best(Side::Bid),
+            maker_ask: maker_best(Side::Ask),
+        }
+    }
+
+    /// Midpoint in ticks, if the book is two-sided.
+    pub fn mid(&self) -> Option<f64> {
+        Some((self.best_bid? as f64 + self.best_ask? as f64) / 2.0)
+    }
+
+    pub fn spread_bps(&self) -> Option<f64> {
+        let mid = self.mid()?;
+        let spread = self.best_ask? as f64 - self.best_bid? as f64;
+        Some(spread / mid * 10_000.0)
+    }
+
+    /// Whether the maker quotes both sides, each within `bps` of the mid.
+    pub fn maker_within_bps(&self, bps: f64) -> bool {
+        let (Some(mid), Some(bid), Some(ask)) = (self.mid(), self.maker_bid, self.maker_ask) else {
+            return false;
+        };
+        let max_distance = mid * bps / 10_000.0;
+        mid - bid as f64 <= max_distance && ask as f64 - mid <= max_distance
+    }
+}
+
+/// Time-weighted statistics over a run of samples. Each sample is weighted by the time until the
+/// next one, and the last by the sampling interval. Fractions are between 0 and 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MarketQualitySummary {
+    pub duration: Duration,
+    /// Fraction of the time the book had both bids and asks.
+    pub two_sided: f64,
+    /// Average spread while the book was two-sided.
+    pub time_weighted_spread_bps: Option<f64>,
+    /// Fraction of the time the spread was at most `within_bps`.
+    pub spread_within_bps: f64,
+    /// Fraction of the time the maker quoted both sides.
+    pub maker_two_sided_uptime: f64,
+    /// Fraction of the time the maker quoted both sides within `within_bps` of the mid.
+    pub maker_within_bps: f64,
+}
+
+impl MarketQualitySummary {
+    pub fn from_samples<'a>(
+        samples: impl IntoIterator<Item = &'a QualitySample>,
+        sample_interval: Duration,
+        within_bps: f64,
+    ) -> Self {
+        let samples = samples.into_iter().collect::<Vec<_>>();
+        let mut total_ms = 0.0;
+        let mut two_sided_ms = 0.0;
+        let mut spread_bps_ms = 0.0;
+        let mut spread_within_ms = 0.0;
+        let mut maker_two_sided_ms = 0.0;
+        let mut maker_within_ms = 0.0;
+        for (i, sample) in samples.iter().enumerate() {
+            let weight = match samples.get(i + 1) {
+                Some(next) => next.timestamp_ms.saturating_sub(sample.timestamp_ms) as f64,
+                None => sample_interval.as_millis() as f64,
+            };
+            total_ms += weight;
+            if let Some(spread_bps) = sample.spread_bps() {
+                two_sided_ms += weight;
+                spread_bps_ms += spread_bps * weight;
+                if spread_bps <= within_bps {
+                    spread_within_ms += weight;
+                }
+            }
+            if sample.maker_bid.is_some() && sample.maker_ask.is_some() {
+                maker_two_sided_ms += weight;
+            }
+            if sample.maker_within_bps(within_bps) {
+                maker_within_ms += weight;
+            }
+        }
+        let fraction = |ms: f64| if total_ms > 0.0 { ms / total_ms } else { 0.0 };
+        Self {
+            duration: Duration::from_millis(total_ms as u64),
+            two_sided: fraction(two_sided_ms),
+            time_weighted_spread_bps: (two_sided_ms > 0.0).then(|| spread_bps_ms / two_sided_ms),
+            spread_within_bps: fraction(spread_within_ms),
+            maker_two_sided_uptime: fraction(maker_two_sided_ms),
+            maker_within_bps: fraction(maker_within_ms),
+        }
+    }
+}
+
+/// Samples a market's top of book and one maker's quotes at a fixed interval, keeping the most
+/// recent `capacity` samples in memory and optionally appending every sample to a CSV.
+pub struct MarketQualityRecorder {
+    pub maker: Pubkey,
+    pub interval: Duration,
+    capacity: usize,
+    samples: VecDeque<QualitySample>,
+    csv: Option<csv::Writer<Box<dyn Write + Send>>>,
+}
+
+impl MarketQualityRecorder {
+    pub fn new(maker: Pubkey, interval: Duration, capacity: usize) -> Self {
+        Self {
+            maker,
+            interval,
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+            csv: None,
+        }
+    }
+
+    /// Also appends every sample to `writer` as CSV, with a header row.
+    pub fn with_csv<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.csv = Some(csv::Writer::from_writer(Box::new(writer)));
+        self
+    }
+
+    pub fn samples(&self) -> &VecDeque<QualitySample> {
+        &self.samples
+    }
+
+    pub fn record(&mut self, sample: QualitySample) -> Result<()> {
+        if let Some(csv) = self.csv.as_mut() {
+            csv.serialize(sample)?;
+            csv.flush()?;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        if self.capacity > 0 {
+            self.samples.push_back(sample);
+        }
+        Ok(())
+    }
+
+    pub fn sample_book(
+        &mut self,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        timestamp_ms: u64,
+    ) -> Result<QualitySample> {
+        let sample = QualitySample::from_book(book, &self.maker, timestamp_ms);
+        self.record(sample)?;
+        Ok(sample)
+    }
+
+    /// Summarizes the samples in memory.
+    pub fn summary(&self, within_bps: f64) -> MarketQualitySummary {
+        MarketQualitySummary::from_samples(&self.samples, self.interval, within_bps)
+    }
+
+    /// Keeps the latest book from a `BookSubscriber` and samples it every `interval` until the
+    /// subscriber stops. Nothing is sampled before the first update.
+    pub async fn run(&mut self, mut updates: broadcast::Receiver<BookUpdate>) -> Result<()> {
+        let mut latest: Option<Arc<MarketState>> = None;
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            tokio::select! {
+                update = updates.recv() => match update {
+                    Ok(update) => latest = Some(update.market_state),
+                    // Only the latest book matters, so missed updates are harmless
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                _ = ticker.tick() => {
+                    if let Some(state) = latest.as_ref() {
+                        self.sample_book(&state.orderbook, now_ms())?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Reads back samples written by a `MarketQualityRecorder`.
+pub fn quality_samples_from_csv<R: Read>(r: R) -> Result<Vec<QualitySample>> {
+    csv::Reader::from_reader(r)
+        .deserialize()
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// A `Write` whose contents can be read after the recorder takes ownership of it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn book(orders: &[(u64, u64, Pubkey)]) -> Orderbook<FIFOOrderId, PhoenixOrder> {
+        let mut book = Orderbook::default();
+        for &(sequence_number, price, maker_id) in orders {
+            let order_id = FIFOOrderId::new_from_untyped(price, sequence_number);
+            let order = PhoenixOrder {
+                num_base_lots: 1,
+                maker_id,
+            };
+            match sequence_number >> 63 {
+                1 => book.bids.insert(order_id, order),
+                _ => book.asks.insert(order_id, order),
+            };
+        }
+        book
+    }
+
+    #[test]
+    fn test_summary() {
+        let me = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let buffer = SharedBuffer::default();
+        let mut recorder =
+            MarketQualityRecorder::new(me, Duration::from_secs(1), 3).with_csv(buffer.clone());
+
+        // 1s one-sided, 1s with my quotes 20 bps wide, 2s with me only bidding 2% wide
+        recorder
+            .sample_book(&book(&[(!1, 9990, other)]), 0)
+            .unwrap();
+        recorder
+            .sample_book(&book(&[(!1, 9990, me), (2, 10010, me)]), 1000)
+            .unwrap();
+        let sample = recorder
+            .sample_book(&book(&[(!1, 9900, me), (2, 10100, other)]), 2000)
+            .unwrap();
+        assert_eq!(sample.maker_ask, None);
+        assert_eq!(sample.spread_bps(), Some(200.0));
+
+        let summary = recorder.summary(25.0);
+        assert_eq!(summary.duration, Duration::from_secs(3));
+        assert!((summary.two_sided - 2.0 / 3.0).abs() < 1e-9);
+        assert!((summary.time_weighted_spread_bps.unwrap() - 110.0).abs() < 1e-9);
+        assert!((summary.spread_within_bps - 1.0 / 3.0).abs() < 1e-9);
+        assert!((summary.maker_two_sided_uptime - 1.0 / 3.0).abs() < 1e-9);
+        assert!((summary.maker_within_bps - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(recorder.summary(5.0).maker_within_bps, 0.0);
+
+        // The ring keeps the newest samples, while the CSV keeps everything
+        recorder.sample_book(&book(&[]), 3000).unwrap();
+        assert_eq!(recorder.samples().len(), 3);
+        assert_eq!(recorder.samples()[0].timestamp_ms, 1000);
+        let written = quality_samples_from_csv(buffer.0.lock().unwrap().as_slice()).unwrap();
+        assert_eq!(written.len(), 4);
+        assert_eq!(written[1], recorder.samples()[0]);
+        assert_eq!(
+            written[3],
+            QualitySample {
+                timestamp_ms: 3000,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,590 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use phoenix_sdk_core::sdk_client_core::MarketMetadata;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+/// A market named either by its address or by a symbol in a `MarketRegistry`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarketRef {
+    Pubkey(Pubkey),
+    Symbol(String),
+}
+
+impl From<Pubkey> for MarketRef {
+    fn from(market: Pubkey) -> Self {
+        MarketRef::Pubkey(market)
+    }
+}
+
+impl From<&Pubkey> for MarketRef {
+    fn from(market: &Pubkey) -> Self {
+        MarketRef::Pubkey(*market)
+    }
+}
+
+/// A string that parses as a pubkey is taken as an address, and anything else as a symbol.
+impl From<&str> for MarketRef {
+    fn from(market: &str) -> Self {
+        match Pubkey::from_str(market) {
+            Ok(market) => MarketRef::Pubkey(market),
+            Err(_) => MarketRef::Symbol(market.to_string()),
+        }
+    }
+}
+
+impl From<String> for MarketRef {
+    fn from(market: String) -> Self {
+        market.as_str().into()
+    }
+}
+
+impl fmt::Display for MarketRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketRef::Pubkey(market) => write!(f, "{}", market),
+            MarketRef::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+/// A market and the mints it is expected to trade.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketEntry {
+    /// `BASE/QUOTE`, e.g. `SOL/USDC`.
+    pub symbol: String,
+    pub market: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+}
+
+impl MarketEntry {
+    /// Checks that `meta`, read from the market account, trades the mints this entry expects.
+    pub fn validate(&self, meta: &MarketMetadata) -> Result<()> {
+        if meta.base_mint != self.base_mint || meta.quote_mint != self.quote_mint {
+            return Err(anyhow!(
+                "Market {} for {} trades {}/{}, but the registry expects {}/{}",
+                self.market,
+                self.symbol,
+                meta.base_mint,
+                meta.quote_mint,
+                self.base_mint,
+                self.quote_mint
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Maps market symbols such as `SOL/USDC` to market addresses and back. Symbols are matched
+/// case-insensitively.
+///
+/// Registry files list markets in JSON or, with the `toml` feature, TOML:
+///
+/// ```toml
+/// [[markets]]
+/// symbol = "SOL/USDC"
+/// market = "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg"
+/// base_mint = "So11111111111111111111111111111111111111112"
+/// quote_mint = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
+/// ```
+///
+/// See `SDKClient::set_market_registry` to use symbols with the client.
+#[derive(Debug, Clone, Default)]
+pub struct MarketRegistry {
+    /// Keyed by upper-cased symbol.
+    by_symbol: BTreeMap<String, MarketEntry>,
+    by_market: BTreeMap<Pubkey, String>,
+}
+
+impl MarketRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The Phoenix markets on mainnet-beta listed in `master_config.json`.
+    #[cfg(feature = "mainnet-markets")]
+    pub fn mainnet() -> Self {
+        let mut registry = Self::new();
+        for (symbol, market, base_mint, quote_mint) in MAINNET_MARKETS {
+            registry
+                .insert(MarketEntry {
+                    symbol: symbol.to_string(),
+                    market: Pubkey::from_str(market).unwrap(),
+                    base_mint: Pubkey::from_str(base_mint).unwrap(),
+                    quote_mint: Pubkey::from_str(quote_mint).unwrap(),
+                })
+                .unwrap();
+        }
+        registry
+    }
+
+    /// Adds a market. Fails if its symbol or address is already registered.
+    pub fn insert(&mut self, entry: MarketEntry) -> Result<()> {
+        let key = entry.symbol.to_uppercase();
+        if self.by_symbol.contains_key(&key) {
+            return Err(anyhow!("Symbol {} is already registered", entry.symbol));
+        }
+        if let Some(symbol) = self.by_market.get(&entry.market) {
+            return Err(anyhow!(
+                "Market {} is already registered as {}",
+                entry.market,
+                symbol
+            ));
+        }
+        self.by_market.insert(entry.market, entry.symbol.clone());
+        self.by_symbol.insert(key, entry);
+        Ok(())
+    }
+
+    pub fn from_json_str(json: &str) -> Result<Self> {
+        Self::from_file(serde_json::from_str(json)?)
+    }
+
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json_str(&std::fs::read_to_string(path)?)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(toml: &str) -> Result<Self> {
+        Self::from_file(toml::from_str(toml)?)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_toml_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Reads one cluster of a config in the format of `master_config.json`, naming each market
+    /// by the symbols of its tokens.
+    pub fn from_master_config(json: &str, cluster: &str) -> Result<Self> {
+        let config: BTreeMap<String, MasterConfigCluster> = serde_json::from_str(json)?;
+        let cluster_config = config
+            .get(cluster)
+            .ok_or_else(|| anyhow!("Failed to find cluster {} in config file", cluster))?;
+        let symbols = cluster_config
+            .tokens
+            .iter()
+            .map(|token| (token.mint.as_str(), token.symbol.as_str()))
+            .collect::<BTreeMap<_, _>>();
+        let mut registry = Self::new();
+        for market in &cluster_config.markets {
+            let symbol_of = |mint: &str| {
+                symbols
+                    .get(mint)
+                    .copied()
+                    .ok_or_else(|| anyhow!("No token with mint {} in config file", mint))
+            };
+            registry.insert(MarketEntry {
+                symbol: format!(
+                    "{}/{}",
+                    symbol_of(&market.baseMint)?,
+                    symbol_of(&market.quoteMint)?
+                ),
+                market: Pubkey::from_str(&market.market)?,
+                base_mint: Pubkey::from_str(&market.baseMint)?,
+                quote_mint: Pubkey::from_str(&market.quoteMint)?,
+            })?;
+        }
+        Ok(registry)
+    }
+
+    /// Writes the registry in the JSON format read by `from_json_str`.
+    pub fn to_json_string(&self) -> Result<String> {
+        let file = RegistryFile {
+            markets: self
+                .iter()
+                .map(|entry| RegistryFileEntry {
+                    symbol: entry.symbol.clone(),
+                    market: entry.market.to_string(),
+                    base_mint: entry.base_mint.to_string(),
+                    quote_mint: entry.quote_mint.to_string(),
+                })
+                .collect(),
+        };
+        Ok(serde_json::to_string_pretty(&file)?)
+    }
+
+    fn from_file(file: RegistryFile) -> Result<Self> {
+        let mut registry = Self::new();
+        for entry in file.markets {
+            registry.insert(MarketEntry {
+                market: Pubkey::from_str(&entry.market)?,
+                base_mint: Pubkey::from_str(&entry.base_mint)?,
+                quote_mint: Pubkey::from_str(&entry.quote_mint)?,
+                symbol: entry.symbol,
+            })?;
+        }
+        Ok(registry)
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&MarketEntry> {
+        self.by_symbol.get(&symbol.to_uppercase())
+    }
+
+    pub fn get_by_market(&self, market: &Pubkey) -> Option<&MarketEntry> {
+        self.get(self.by_market.get(market)?)
+    }
+
+    /// The address of `market`. Addresses are returned as they are, registered or not.
+    pub fn resolve(&self, market: impl Into<MarketRef>) -> Result<Pubkey> {
+        match market.into() {
+            MarketRef::Pubkey(market) => Ok(market),
+            MarketRef::Symbol(symbol) => self
+                .get(&symbol)
+                .map(|entry| entry.market)
+                .ok_or_else(|| anyhow!("Unknown market symbol {}", symbol)),
+        }
+    }
+
+    pub fn symbol(&self, market: &Pubkey) -> Option<&str> {
+        self.by_market.get(market).map(String::as_str)
+    }
+
+    /// Entries ordered by upper-cased symbol.
+    pub fn iter(&self) -> impl Iterator<Item = &MarketEntry> {
+        self.by_symbol.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_symbol.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_symbol.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RegistryFile {
+    markets: Vec<RegistryFileEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RegistryFileEntry {
+    symbol: String,
+    market: String,
+    base_mint: String,
+    quote_mint: String,
+}
+
+#[derive(Deserialize)]
+struct MasterConfigCluster {
+    tokens: Vec<MasterConfigToken>,
+    markets: Vec<MasterConfigMarket>,
+}
+
+#[derive(Deserialize)]
+struct MasterConfigToken {
+    symbol: String,
+    mint: String,
+}
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct MasterConfigMarket {
+    market: String,
+    baseMint: String,
+    quoteMint: String,
+}
+
+/// `(symbol, market, base mint, quote mint)` for the mainnet-beta markets in `master_config.json`.
+#[cfg(feature = "mainnet-markets")]
+const MAINNET_MARKETS: &[(&str, &str, &str, &str)] = &[
+    (
+        "SOL/USDC",
+        "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg",
+        "So11111111111111111111111111111111111111112",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "SOL/USDT",
+        "3J9LfemPBLowAJgpG3YdYPB9n6pUk7HEjwgS6Y5ToSFg",
+        "So11111111111111111111111111111111111111112",
+        "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB",
+    ),
+    (
+        "WETH/USDC",
+        "Ew3vFDdtdGrknJAVVfraxCA37uNJtimXYPY4QjnfhFHH",
+        "7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "JUP/USDC",
+        "2pspvjWWaf3dNgt3jsgSzFCNvMGPb7t8FrEYvLGjvcCe",
+        "JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "JUP/SOL",
+        "Ge1Vb599LquMJziLbLTF5aR4icq8MZQxpmgNywvdPqjL",
+        "JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN",
+        "So11111111111111111111111111111111111111112",
+    ),
+    (
+        "PYTH/USDC",
+        "2sTMN9A1D1qeZLF95XQgJCUPiKe5DiV52jLfZGqMP46m",
+        "HZ1JovNiVvGrGNiiYvEozEVgZ58xaU3RKwX8eACQBCt3",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "JTO/USDC",
+        "BRLLmdtPGuuFn3BU6orYw4KHaohAEptBToi3dwRUnHQZ",
+        "jtojtomepa8beP8AuQc6eXt5FriJwfFMwQx2v2f9mCL",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "Bonk/USDC",
+        "GBMoNx84HsFdVK63t8BZuDgyZhSBaeKWB4pHHpoeRM9z",
+        "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "Bonk/SOL",
+        "FicF181nDsEcasznMTPp9aLa5Rbpdtd11GtSEa1UUWzx",
+        "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263",
+        "So11111111111111111111111111111111111111112",
+    ),
+    (
+        "Bonk/USDT",
+        "2jxpfobdZDU3z9MsDCjAz8psSaTb5HPoDEtusFLGrPnD",
+        "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263",
+        "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB",
+    ),
+    (
+        "EURC/USDC",
+        "5x91Aaegvx1JmW7g8gDfWqwb6kPF7CdNunqNoYCdLjk1",
+        "HzwqbKZw8HxMN6bF2yFZNrht3c2iXXzpKcFu7uBEDKtr",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "$WIF/USDC",
+        "6ojSigXF7nDPyhFRgmn3V9ywhYseKF9J32ZrranMGVSX",
+        "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "$WIF/SOL",
+        "BKLhZ5NrFhCjViC4wyAMXBNsJFHbFfYujo3TtUmBxTH3",
+        "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm",
+        "So11111111111111111111111111111111111111112",
+    ),
+    (
+        "JitoSOL/USDC",
+        "5LQLfGtqcC5rm2WuGxJf4tjqYmDjsQAbKo2AMLQ8KB7p",
+        "J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "HNT/USDC",
+        "Dw1QomczWKpqCvJufRSinrqYj5jQTF2ct9DfHoiLPUpi",
+        "hntyVP6YFm1Hg25TN9WGLqM12b8TQmcknKrdu1oxWux",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "$MYRO/USDC",
+        "4ahqRcJkAepQAm2QBCwmQEYATm5nWuiSKMCBehz584yH",
+        "HhJpBhRRn4g56VsyLuT8DL5Bv31HkXqsrahTTUCZeZg4",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "WEN/USDC",
+        "EPZsr3WEzYzmRjQhs4E7rsAu1bvXpvX7vMqJPRReFxwD",
+        "WENWENvqqNya429ubCdR81ZmD69brwQaaBYY6p3LCpk",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "BOME/USDC",
+        "2usAgRgkwqqqtMrkkM6ktaxfLWjA4nm7xS8bmKw1hvPw",
+        "ukHH6c7mMyiWCf1b9pnWe25TSpkDDt3H5pQZgZ74J82",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "SLERF/USDC",
+        "6moHUcknpJ7vuVtoJzMBu7DEiuqevNLvXuf31d2PucJB",
+        "7BgBvyjrZX1YKz4oh9mjb8ZScatkkwb8DzFx7LoiVkM3",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "MOBILE/USDC",
+        "BSW9otw4SSv3JfTmB1n1FsUFuGV3vGboKbs2gBrAgJ54",
+        "mb1eu7TzEc71KxDpsmsKoucSSuuoGLv1drys1oP2jh6",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "RENDER/USDC",
+        "7zbKWvcNKTU9WcYAP1ZCNQaSHfxNkLzwWaRgfMTzMTby",
+        "rndrizKT3MK1iimdxRdWabcF7Zg7AR5T4nud4EkHBof",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "W/USDC",
+        "8dFTCTAbtGuHsdDL8WEPrTU6pXFDrU1QSjBTutw8fwZk",
+        "85VBFQZC9TZkfaptBWjvUw7YbZjy52A6mjtPGjstQAmQ",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "W/SOL",
+        "5WyJr7R3aYpHTjSKXFmrgnVaRU987FoxgCkcELeAJqcm",
+        "85VBFQZC9TZkfaptBWjvUw7YbZjy52A6mjtPGjstQAmQ",
+        "So11111111111111111111111111111111111111112",
+    ),
+    (
+        "JitoSOL/SOL",
+        "2t9TBYyUyovhHQq434uAiBxW6DmJCg7w4xdDoSK6LRjP",
+        "J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn",
+        "So11111111111111111111111111111111111111112",
+    ),
+    (
+        "mSOL/SOL",
+        "FZRgpfpvicJ3p23DfmZuvUgcQZBHJsWScTf2N2jK8dy6",
+        "mSoLzYCxHdYgdzU16g5QSh3i5K3z3KZK7ytfqcJm7So",
+        "So11111111111111111111111111111111111111112",
+    ),
+    (
+        "TNSR/USDC",
+        "AbJCZ9TAJiby5AY3cHcXS2gUdENC6mtsm6m7XpC2ZMvE",
+        "TNSRxcUxoT9xBG3de7PiJyTDYu7kskLqcpddxnEJAS6",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "TNSR/SOL",
+        "B1Tkeva9jZVvkGW9QK3WqEMpWFRZkTMouvqTgQCHeZGq",
+        "TNSRxcUxoT9xBG3de7PiJyTDYu7kskLqcpddxnEJAS6",
+        "So11111111111111111111111111111111111111112",
+    ),
+    (
+        "PRCL/SOL",
+        "9JXE9RZskL63ZySYo3xDPnqbhCbXHkLQH4E5Xh7UDekk",
+        "4LLbsb5ReP3yEtYzmXewyGjcir5uXtKFURtaEUVC2AHs",
+        "So11111111111111111111111111111111111111112",
+    ),
+    (
+        "WBTC/USDC",
+        "6BVnCYbEQZXkDZuQyaKTQMPs4HjpKcd7poMxKnRNEX32",
+        "3NZ9JMVBmGAqocybic2c7LQCJScmgsAZ6vQqTDzcqmJh",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "KMNO/USDC",
+        "6U5LibaXfAo84orySzuok3xchfaMipV7iiZjLDYfrbxb",
+        "KMNo3nJsBXfcpJTVhZcXLW7RmTwTt4GVFE7suUBo9sS",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "DRIFT/USDC",
+        "8BV6rrWsUabnTDA3dE6A69oUDJAj3hMhtBHTJyXB7czp",
+        "DriFtupJYLTosbwoN8koMbEYSx54aFAVLddWsbksjwg7",
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    ),
+    (
+        "DRIFT/SOL",
+        "7gpjEGSznykiEY62tkiikX3eMH9e2FiVpgFrF9nAM1A3",
+        "DriFtupJYLTosbwoN8koMbEYSx54aFAVLddWsbksjwg7",
+        "So11111111111111111111111111111111111111112",
+    ),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOL_USDC: &str = "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg";
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+    fn sol_usdc() -> MarketEntry {
+        MarketEntry {
+            symbol: "SOL/USDC".to_string(),
+            market: Pubkey::from_str(SOL_USDC).unwrap(),
+            base_mint: Pubkey::from_str(SOL).unwrap(),
+            quote_mint: Pubkey::from_str(USDC).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_and_validate() {
+        let mut registry = MarketRegistry::new();
+        registry.insert(sol_usdc()).unwrap();
+        let market = Pubkey::from_str(SOL_USDC).unwrap();
+
+        assert_eq!(registry.resolve("SOL/USDC").unwrap(), market);
+        assert_eq!(registry.resolve("sol/usdc").unwrap(), market);
+        assert_eq!(registry.resolve(SOL_USDC).unwrap(), market);
+        let unregistered = Pubkey::new_unique();
+        assert_eq!(registry.resolve(unregistered).unwrap(), unregistered);
+        assert!(registry.resolve("SOL/USDT").is_err());
+        assert_eq!(registry.symbol(&market), Some("SOL/USDC"));
+        assert_eq!(registry.get_by_market(&market), Some(&sol_usdc()));
+
+        // Neither the symbol nor the market can be registered twice
+        let mut duplicate = sol_usdc();
+        duplicate.symbol = "sol/usdc".to_string();
+        duplicate.market = Pubkey::new_unique();
+        assert!(registry.insert(duplicate).is_err());
+        let mut duplicate = sol_usdc();
+        duplicate.symbol = "SOL/USDC2".to_string();
+        assert!(registry.insert(duplicate).is_err());
+        assert_eq!(registry.len(), 1);
+
+        // A market pasted under the wrong symbol trades other mints than expected
+        let meta = MarketMetadata {
+            base_mint: Pubkey::from_str(SOL).unwrap(),
+            quote_mint: Pubkey::from_str(USDC).unwrap(),
+            ..Default::default()
+        };
+        sol_usdc().validate(&meta).unwrap();
+        let swapped = MarketMetadata {
+            base_mint: meta.quote_mint,
+            quote_mint: meta.base_mint,
+            ..meta
+        };
+        assert!(sol_usdc().validate(&swapped).is_err());
+    }
+
+    #[test]
+    fn test_registry_files() {
+        let mut registry = MarketRegistry::new();
+        registry.insert(sol_usdc()).unwrap();
+        let json = registry.to_json_string().unwrap();
+        let parsed = MarketRegistry::from_json_str(&json).unwrap();
+        assert_eq!(parsed.iter().collect::<Vec<_>>(), vec![&sol_usdc()]);
+        assert!(MarketRegistry::from_json_str(r#"{"markets": [{"symbol": "X/Y"}]}"#).is_err());
+
+        let master_config = format!(
+            r#"{{"mainnet-beta": {{
+                "tokens": [
+                    {{"name": "Solana", "symbol": "SOL", "mint": "{SOL}", "logoUri": ""}},
+                    {{"name": "USD Coin", "symbol": "USDC", "mint": "{USDC}", "logoUri": ""}}
+                ],
+                "markets": [{{"market": "{SOL_USDC}", "baseMint": "{SOL}", "quoteMint": "{USDC}"}}]
+            }}}}"#
+        );
+        let parsed = MarketRegistry::from_master_config(&master_config, "mainnet-beta").unwrap();
+        assert_eq!(parsed.get("SOL/USDC"), Some(&sol_usdc()));
+        assert!(MarketRegistry::from_master_config(&master_config, "devnet").is_err());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_file() {
+        let toml = format!(
+            "[[markets]]\nsymbol = \"SOL/USDC\"\nmarket = \"{SOL_USDC}\"\n\
+             base_mint = \"{SOL}\"\nquote_mint = \"{USDC}\"\n"
+        );
+        let registry = MarketRegistry::from_toml_str(&toml).unwrap();
+        assert_eq!(registry.get("SOL/USDC"), Some(&sol_usdc()));
+    }
+
+    #[cfg(feature = "mainnet-markets")]
+    #[test]
+    fn test_mainnet_table() {
+        let registry = MarketRegistry::mainnet();
+        assert_eq!(registry.get("SOL/USDC"), Some(&sol_usdc()));
+        assert_eq!(registry.len(), MAINNET_MARKETS.len());
+    }
+}
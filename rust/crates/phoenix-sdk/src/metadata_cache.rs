@@ -0,0 +1,372 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use phoenix::program::MarketSizeParams;
+use phoenix_sdk_core::sdk_client_core::MarketMetadata;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+/// Version of the cached metadata format. Bump it whenever `MarketMetadata` gains or changes a
+/// field, so that caches written by older versions are discarded instead of misread.
+pub const METADATA_CACHE_VERSION: u32 = 2;
+
+/// Stores market metadata across `SDKClient`s and processes, so that only markets not seen before
+/// are fetched from RPC. See `SDKClient::set_metadata_cache`.
+pub trait MetadataCache: Send + Sync {
+    fn get(&self, market: &Pubkey) -> Option<MarketMetadata>;
+    fn insert(&self, market: &Pubkey, metadata: MarketMetadata) -> Result<()>;
+    fn remove(&self, market: &Pubkey) -> Result<()>;
+}
+
+/// Metadata with the time it was fetched, in seconds since the Unix epoch.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    metadata: MarketMetadata,
+    fetched_at: u64,
+}
+
+/// Entries that expire after `ttl`, if set. Metadata never changes once a market is created, so
+/// a TTL is only useful to eventually pick up fields added by newer program versions.
+#[derive(Debug, Default)]
+struct Entries {
+    entries: BTreeMap<Pubkey, Entry>,
+    ttl: Option<Duration>,
+}
+
+impl Entries {
+    fn get(&self, market: &Pubkey) -> Option<MarketMetadata> {
+        let entry = self.entries.get(market)?;
+        let expired = self
+            .ttl
+            .is_some_and(|ttl| now_secs().saturating_sub(entry.fetched_at) >= ttl.as_secs());
+        (!expired).then_some(entry.metadata)
+    }
+
+    fn insert(&mut self, market: &Pubkey, metadata: MarketMetadata) {
+        self.entries.insert(
+            *market,
+            Entry {
+                metadata,
+                fetched_at: now_secs(),
+            },
+        );
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryMetadataCache {
+    entries: Mutex<Entries>,
+}
+
+impl InMemoryMetadataCache {
+    pub fn new(ttl: Option<Duration>) -> Self {
+        Self {
+            entries: Mutex::new(Entries {
+                entries: BTreeMap::new(),
+                ttl,
+            }),
+        }
+    }
+}
+
+impl MetadataCache for InMemoryMetadataCache {
+    fn get(&self, market: &Pubkey) -> Option<MarketMetadata> {
+        lock(&self.entries).get(market)
+    }
+
+    fn insert(&self, market: &Pubkey, metadata: MarketMetadata) -> Result<()> {
+        lock(&self.entries).insert(market, metadata);
+        Ok(())
+    }
+
+    fn remove(&self, market: &Pubkey) -> Result<()> {
+        lock(&self.entries).entries.remove(market);
+        Ok(())
+    }
+}
+
+/// A cache kept in a JSON file, rewritten on every change. A missing, unreadable or corrupted
+/// file, or one written with a different `METADATA_CACHE_VERSION`, is treated as empty, so the
+/// metadata is fetched from RPC again and the file is replaced.
+#[derive(Debug)]
+pub struct JsonFileMetadataCache {
+    path: PathBuf,
+    entries: Mutex<Entries>,
+}
+
+impl JsonFileMetadataCache {
+    pub fn open(path: impl AsRef<Path>, ttl: Option<Duration>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let entries = match std::fs::read(&path) {
+            Ok(bytes) => match parse_cache_file(&bytes) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    println!("Ignoring metadata cache {}: {}", path.display(), e);
+                    BTreeMap::new()
+                }
+            },
+            Err(_) => BTreeMap::new(),
+        };
+        Self {
+            path,
+            entries: Mutex::new(Entries { entries, ttl }),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes to a temporary file first so that a crash mid-write leaves the previous file intact.
+    fn save(&self, entries: &Entries) -> Result<()> {
+        let file = CacheFile {
+            version: METADATA_CACHE_VERSION,
+            markets: entries
+                .entries
+                .iter()
+                .map(|(market, entry)| (market.to_string(), CachedMetadata::from(*entry)))
+                .collect(),
+        };
+        let tmp_path = self.path.with_extension("tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&serde_json::to_vec_pretty(&file)?)?;
+        tmp.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl MetadataCache for JsonFileMetadataCache {
+    fn get(&self, market: &Pubkey) -> Option<MarketMetadata> {
+        lock(&self.entries).get(market)
+    }
+
+    fn insert(&self, market: &Pubkey, metadata: MarketMetadata) -> Result<()> {
+        let mut entries = lock(&self.entries);
+        entries.insert(market, metadata);
+        self.save(&entries)
+    }
+
+    fn remove(&self, market: &Pubkey) -> Result<()> {
+        let mut entries = lock(&self.entries);
+        if entries.entries.remove(market).is_some() {
+            self.save(&entries)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    markets: BTreeMap<String, CachedMetadata>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedMetadata {
+    fetched_at: u64,
+    base_mint: String,
+    quote_mint: String,
+    base_decimals: u32,
+    quote_decimals: u32,
+    base_atoms_per_raw_base_unit: u64,
+    quote_atoms_per_quote_unit: u64,
+    quote_atoms_per_quote_lot: u64,
+    base_atoms_per_base_lot: u64,
+    tick_size_in_quote_atoms_per_base_unit: u64,
+    num_base_lots_per_base_unit: u64,
+    raw_base_units_per_base_unit: u32,
+    bids_size: u64,
+    asks_size: u64,
+    num_seats: u64,
+    taker_fee_bps: u64,
+}
+
+impl From<Entry> for CachedMetadata {
+    fn from(entry: Entry) -> Self {
+        let metadata = entry.metadata;
+        Self {
+            fetched_at: entry.fetched_at,
+            base_mint: metadata.base_mint.to_string(),
+            quote_mint: metadata.quote_mint.to_string(),
+            base_decimals: metadata.base_decimals,
+            quote_decimals: metadata.quote_decimals,
+            base_atoms_per_raw_base_unit: metadata.base_atoms_per_raw_base_unit,
+            quote_atoms_per_quote_unit: metadata.quote_atoms_per_quote_unit,
+            quote_atoms_per_quote_lot: metadata.quote_atoms_per_quote_lot,
+            base_atoms_per_base_lot: metadata.base_atoms_per_base_lot,
+            tick_size_in_quote_atoms_per_base_unit: metadata.tick_size_in_quote_atoms_per_base_unit,
+            num_base_lots_per_base_unit: metadata.num_base_lots_per_base_unit,
+            raw_base_units_per_base_unit: metadata.raw_base_units_per_base_unit,
+            bids_size: metadata.market_size_params.bids_size,
+            asks_size: metadata.market_size_params.asks_size,
+            num_seats: metadata.market_size_params.num_seats,
+            taker_fee_bps: metadata.taker_fee_bps,
+        }
+    }
+}
+
+impl TryFrom<CachedMetadata> for Entry {
+    type Error = anyhow::Error;
+
+    fn try_from(cached: CachedMetadata) -> Result<Self> {
+        Ok(Self {
+            fetched_at: cached.fetched_at,
+            metadata: MarketMetadata {
+                base_mint: Pubkey::from_str(&cached.base_mint)?,
+                quote_mint: Pubkey::from_str(&cached.quote_mint)?,
+                base_decimals: cached.base_decimals,
+                quote_decimals: cached.quote_decimals,
+                base_atoms_per_raw_base_unit: cached.base_atoms_per_raw_base_unit,
+                quote_atoms_per_quote_unit: cached.quote_atoms_per_quote_unit,
+                quote_atoms_per_quote_lot: cached.quote_atoms_per_quote_lot,
+                base_atoms_per_base_lot: cached.base_atoms_per_base_lot,
+                tick_size_in_quote_atoms_per_base_unit: cached
+                    .tick_size_in_quote_atoms_per_base_unit,
+                num_base_lots_per_base_unit: cached.num_base_lots_per_base_unit,
+                raw_base_units_per_base_unit: cached.raw_base_units_per_base_unit,
+                market_size_params: MarketSizeParams {
+                    bids_size: cached.bids_size,
+                    asks_size: cached.asks_size,
+                    num_seats: cached.num_seats,
+                },
+                taker_fee_bps: cached.taker_fee_bps,
+            },
+        })
+    }
+}
+
+/// Parses a cache file, checking the version before the entries so that a file from another
+/// version is rejected even if its entries happen to parse.
+fn parse_cache_file(bytes: &[u8]) -> Result<BTreeMap<Pubkey, Entry>> {
+    #[derive(Deserialize)]
+    struct Version {
+        version: u32,
+    }
+    let Version { version } = serde_json::from_slice(bytes)?;
+    if version != METADATA_CACHE_VERSION {
+        return Err(anyhow!(
+            "version {} does not match {}",
+            version,
+            METADATA_CACHE_VERSION
+        ));
+    }
+    let file: CacheFile = serde_json::from_slice(bytes)?;
+    file.markets
+        .into_iter()
+        .map(|(market, cached)| Ok((Pubkey::from_str(&market)?, Entry::try_from(cached)?)))
+        .collect()
+}
+
+fn lock(entries: &Mutex<Entries>) -> MutexGuard<'_, Entries> {
+    entries
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn metadata() -> MarketMetadata {
+        MarketMetadata {
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_atoms_per_raw_base_unit: 1_000_000_000,
+            quote_atoms_per_quote_unit: 1_000_000,
+            quote_atoms_per_quote_lot: 1,
+            base_atoms_per_base_lot: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 100,
+            num_base_lots_per_base_unit: 1000,
+            raw_base_units_per_base_unit: 1,
+            market_size_params: MarketSizeParams {
+                bids_size: 4096,
+                asks_size: 4096,
+                num_seats: 8193,
+            },
+            taker_fee_bps: 5,
+        }
+    }
+
+    fn assert_same(a: MarketMetadata, b: MarketMetadata) {
+        assert_eq!(format!("{:?}", a), format!("{:?}", b));
+    }
+
+    #[test]
+    fn test_json_file_cache() {
+        let path =
+            std::env::temp_dir().join(format!("phoenix-metadata-{}.json", Pubkey::new_unique()));
+        let (market, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let meta = metadata();
+
+        // A missing file is an empty cache, and entries survive reopening
+        let cache = JsonFileMetadataCache::open(&path, None);
+        assert!(cache.get(&market).is_none());
+        cache.insert(&market, meta).unwrap();
+        cache.insert(&other, meta).unwrap();
+        cache.remove(&other).unwrap();
+        let reopened = JsonFileMetadataCache::open(&path, None);
+        assert_same(reopened.get(&market).unwrap(), meta);
+        assert!(reopened.get(&other).is_none());
+
+        // A zero TTL expires everything
+        assert!(JsonFileMetadataCache::open(&path, Some(Duration::ZERO))
+            .get(&market)
+            .is_none());
+
+        // Another version invalidates the file, and so does corruption
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let bumped = contents.replacen(
+            &format!("\"version\": {}", METADATA_CACHE_VERSION),
+            &format!("\"version\": {}", METADATA_CACHE_VERSION + 1),
+            1,
+        );
+        assert_ne!(bumped, contents);
+        std::fs::write(&path, bumped).unwrap();
+        assert!(JsonFileMetadataCache::open(&path, None)
+            .get(&market)
+            .is_none());
+        std::fs::write(&path, &contents[..contents.len() / 2]).unwrap();
+        let cache = JsonFileMetadataCache::open(&path, None);
+        assert!(cache.get(&market).is_none());
+
+        // and the next insert replaces the bad file
+        cache.insert(&market, meta).unwrap();
+        assert_same(
+            JsonFileMetadataCache::open(&path, None)
+                .get(&market)
+                .unwrap(),
+            meta,
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_in_memory_cache() {
+        let market = Pubkey::new_unique();
+        let cache = InMemoryMetadataCache::new(None);
+        cache.insert(&market, metadata()).unwrap();
+        assert!(cache.get(&market).is_some());
+        cache.remove(&market).unwrap();
+        assert!(cache.get(&market).is_none());
+
+        let expiring = InMemoryMetadataCache::new(Some(Duration::ZERO));
+        expiring.insert(&market, metadata()).unwrap();
+        assert!(expiring.get(&market).is_none());
+    }
+}
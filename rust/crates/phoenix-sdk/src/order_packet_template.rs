@@ -1,17 +1,20 @@
+use phoenix::state::markets::FIFOOrderId;
 use phoenix::state::{SelfTradeBehavior, Side};
+use phoenix_sdk_core::orderbook::Orderbook;
+use phoenix_sdk_core::sdk_client_core::PhoenixOrder;
 
 /// LimitOrderTemplate is a helper type for creating a limit order.
 /// The template allows you to specify the price and size in commonly understood units:
-/// price is the floating point price (units of USDC per unit of SOL for the SOL/USDC market), and size is in whole base units (units of SOL for the SOL/USDC market).
+/// price is the floating point price (units of USDC per unit of SOL for the SOL/USDC market), and size is in raw base units (units of SOL for the SOL/USDC market).
 /// The SDK can then convert this to a limit order instruction, ready to be sent.
 pub struct LimitOrderTemplate {
     // The side for the order, a Side::Bid or a Side::Ask.
     pub side: Side,
 
-    /// The price of the order, as the commonly understood exchange price (the number of quote units to exchange for one base unit), as a floating point number.
+    /// The price of the order, as the commonly understood exchange price (the number of quote units to exchange for one raw base unit), as a floating point number.
     pub price_as_float: f64,
 
-    /// Total number of base units, as a floating point number, to place on the book or fill at a better price.
+    /// Total number of raw base units, as a floating point number, to place on the book or fill at a better price.
     pub size_in_base_units: f64,
 
     /// How the matching engine should handle a self trade.
@@ -41,16 +44,16 @@ pub struct LimitOrderTemplate {
 
 /// PostOnlyOrderTemplate is a helper type for creating a post-only order, which will never be matched against existing orders.
 /// The template allows you to specify the price and size in commonly understood units:
-/// price is the floating point price (units of USDC per unit of SOL for the SOL/USDC market), and size is in whole base units (units of SOL for the SOL/USDC market).
+/// price is the floating point price (units of USDC per unit of SOL for the SOL/USDC market), and size is in raw base units (units of SOL for the SOL/USDC market).
 /// The SDK can then convert this to a post-only order instruction, ready to be sent.
 pub struct PostOnlyOrderTemplate {
     // The side for the order, a Side::Bid or a Side::Ask.
     pub side: Side,
 
-    /// The price of the order, as the commonly understood exchange price (the number of quote units to exchange for one base unit), as a floating point number.
+    /// The price of the order, as the commonly understood exchange price (the number of quote units to exchange for one raw base unit), as a floating point number.
     pub price_as_float: f64,
 
-    /// Total number of base units, as a floating point number, to place on the book or fill at a better price.
+    /// Total number of raw base units, as a floating point number, to place on the book or fill at a better price.
     pub size_in_base_units: f64,
 
     /// Client order id used to identify the order in the response to the client.
@@ -78,7 +81,7 @@ pub struct PostOnlyOrderTemplate {
 
 /// ImmediateOrCancelOrderTemplate is a helper type for creating an immediate or cancel order.
 /// The template allows you to specify the price and size in commonly understood units:
-/// price is the floating point price (units of USDC per unit of SOL for the SOL/USDC market), and size is in whole base units (units of SOL for the SOL/USDC market).
+/// price is the floating point price (units of USDC per unit of SOL for the SOL/USDC market), and size is in raw base units (units of SOL for the SOL/USDC market).
 /// The SDK can then convert this to a limit order instruction, ready to be sent.
 ///
 /// Immediate-or-cancel orders will be matched against existing resting orders.
@@ -95,7 +98,7 @@ pub struct ImmediateOrCancelOrderTemplate {
     /// is treated as a market order.
     pub price_as_float: Option<f64>,
 
-    /// The number of base units to fill against the order book. Either this parameter or the `num_quote_units`
+    /// The number of raw base units to fill against the order book. Either this parameter or the `num_quote_units`
     /// parameter must be set to a nonzero value.
     pub size_in_base_units: f64,
 
@@ -103,7 +106,7 @@ pub struct ImmediateOrCancelOrderTemplate {
     /// parameter must be set to a nonzero value.
     pub size_in_quote_units: f64,
 
-    /// The minimum number of base units to fill against the order book. If the order does not fill
+    /// The minimum number of raw base units to fill against the order book. If the order does not fill
     /// this many base lots, it will be voided.
     pub min_base_units_to_fill: f64,
 
@@ -114,7 +117,9 @@ pub struct ImmediateOrCancelOrderTemplate {
     /// How the matching engine should handle a self trade.
     pub self_trade_behavior: SelfTradeBehavior,
 
-    /// Number of orders to match against. If set to `None`, there is no limit.
+    /// Number of orders to match against. If set to `None`, there is no limit. Once the order has
+    /// matched this many resting orders, the rest of it is cancelled, and the transaction fails
+    /// only if less than the minimum to fill was filled. See `with_match_limit_from_book`.
     pub match_limit: Option<u64>,
 
     /// Client order id used to identify the order in the response to the client.
@@ -131,3 +136,127 @@ pub struct ImmediateOrCancelOrderTemplate {
     /// If this is set, the order will be invalid after the specified unix timestamp.
     pub last_valid_unix_timestamp_in_seconds: Option<u64>,
 }
+
+impl LimitOrderTemplate {
+    /// Sets `match_limit` to the number of orders in `book` that the order would match, plus
+    /// `safety_margin` for orders placed before it lands. See `Orderbook::count_orders_to_fill`.
+    pub fn with_match_limit_from_book(
+        mut self,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        safety_margin: u64,
+    ) -> Self {
+        self.match_limit = Some(match_limit_from_book(
+            book,
+            self.side,
+            self.size_in_base_units,
+            Some(self.price_as_float),
+            safety_margin,
+        ));
+        self
+    }
+}
+
+impl ImmediateOrCancelOrderTemplate {
+    /// Sets `match_limit` to the number of orders in `book` that the order would match, plus
+    /// `safety_margin` for orders placed before it lands. See `Orderbook::count_orders_to_fill`.
+    /// An order sized in quote units only is counted against every order that crosses.
+    pub fn with_match_limit_from_book(
+        mut self,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        safety_margin: u64,
+    ) -> Self {
+        self.match_limit = Some(match_limit_from_book(
+            book,
+            self.side,
+            self.size_in_base_units,
+            self.price_as_float,
+            safety_margin,
+        ));
+        self
+    }
+}
+
+/// Rounds the size up, and the price towards crossing more of the book, so that floating point
+/// error can only add orders to the count. Never returns 0, which would not match at all.
+fn match_limit_from_book(
+    book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+    side: Side,
+    size_in_base_units: f64,
+    price_as_float: Option<f64>,
+    safety_margin: u64,
+) -> u64 {
+    let size_lots = if size_in_base_units > 0.0 {
+        (size_in_base_units / book.raw_base_units_per_base_lot).ceil() as u64
+    } else {
+        u64::MAX
+    };
+    let limit_ticks = price_as_float.map(|price| {
+        let ticks = price / book.quote_units_per_raw_base_unit_per_tick;
+        match side {
+            Side::Bid => ticks.ceil() as u64,
+            Side::Ask => ticks.floor() as u64,
+        }
+    });
+    let orders = book.count_orders_to_fill(side, size_lots, limit_ticks) as u64;
+    orders.saturating_add(safety_margin).max(1)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+
+    #[test]
+    fn test_match_limit_from_book() {
+        // 1000 lots per base unit and 1000 ticks per quote unit
+        let mut book = Orderbook {
+            raw_base_units_per_base_lot: 0.001,
+            quote_units_per_raw_base_unit_per_tick: 0.001,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        };
+        for (sequence_number, price_in_ticks) in [(1, 1000), (2, 1000), (3, 1001)] {
+            book.asks.insert(
+                FIFOOrderId::new_from_untyped(price_in_ticks, sequence_number),
+                PhoenixOrder {
+                    num_base_lots: 10,
+                    maker_id: Pubkey::new_unique(),
+                },
+            );
+        }
+        let ioc = |price_as_float, size_in_base_units, size_in_quote_units| {
+            ImmediateOrCancelOrderTemplate {
+                side: Side::Bid,
+                price_as_float,
+                size_in_base_units,
+                size_in_quote_units,
+                min_base_units_to_fill: 0.0,
+                min_quote_units_to_fill: 0.0,
+                self_trade_behavior: SelfTradeBehavior::CancelProvide,
+                match_limit: None,
+                client_order_id: 0,
+                use_only_deposited_funds: false,
+                last_valid_slot: None,
+                last_valid_unix_timestamp_in_seconds: None,
+            }
+        };
+
+        // 15 lots take both orders at 1000
+        let sweep = ioc(None, 0.015, 0.0).with_match_limit_from_book(&book, 0);
+        assert_eq!(sweep.match_limit, Some(2));
+        let sweep = ioc(None, 0.015, 0.0).with_match_limit_from_book(&book, 3);
+        assert_eq!(sweep.match_limit, Some(5));
+        // Only the orders at 1000 cross
+        let limited = ioc(Some(1.0), 1.0, 0.0).with_match_limit_from_book(&book, 0);
+        assert_eq!(limited.match_limit, Some(2));
+        // A quote budget counts every order that crosses
+        let budget = ioc(None, 0.0, 5.0).with_match_limit_from_book(&book, 0);
+        assert_eq!(budget.match_limit, Some(3));
+        // Nothing crosses, but a match limit of 0 would not match orders placed meanwhile
+        let below = ioc(Some(0.5), 1.0, 0.0).with_match_limit_from_book(&book, 0);
+        assert_eq!(below.match_limit, Some(1));
+    }
+}
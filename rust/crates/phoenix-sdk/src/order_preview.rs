@@ -0,0 +1,507 @@
+use std::fmt;
+
+use anyhow::{anyhow, bail, Result};
+use phoenix::program::PhoenixInstruction;
+use phoenix::quantities::WrapperU64;
+use phoenix::state::{OrderPacket, OrderPacketMetadata, SelfTradeBehavior, Side};
+use phoenix_sdk_core::packet_decoder::decode_order_packet;
+use phoenix_sdk_core::sdk_client_core::{MarketMetadata, SDKClientCore};
+use serde::{Deserialize, Serialize};
+use solana_sdk::instruction::Instruction;
+
+use crate::market_registry::MarketRegistry;
+
+/// The kind of order a new-order instruction places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewOrderType {
+    PostOnly,
+    Limit,
+    ImmediateOrCancel,
+    /// An immediate-or-cancel order whose minimum fill is its whole size.
+    FillOrKill,
+}
+
+impl fmt::Display for PreviewOrderType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreviewOrderType::PostOnly => write!(f, "Post-only"),
+            PreviewOrderType::Limit => write!(f, "Limit"),
+            PreviewOrderType::ImmediateOrCancel => write!(f, "Immediate-or-cancel"),
+            PreviewOrderType::FillOrKill => write!(f, "Fill-or-kill"),
+        }
+    }
+}
+
+/// An account of the instruction, named by its place in the instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreviewAccount {
+    pub name: String,
+    pub pubkey: String,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// What a Phoenix new-order instruction will do, decoded from its data and accounts, for review
+/// before it is sent or to audit a transaction that was. Amounts are exact decimal strings in
+/// raw base units (size) and quote units (notional), and the price is in quote units per raw
+/// base unit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderPreview {
+    pub market: String,
+    /// The market's symbol, if it is in the registry given to `with_symbol_from`.
+    pub symbol: Option<String>,
+    pub instruction: String,
+    pub order_type: PreviewOrderType,
+    /// "buy" or "sell".
+    pub side: String,
+    /// `None` for an immediate-or-cancel order without a limit price.
+    pub price_in_ticks: Option<u64>,
+    pub price: Option<f64>,
+    pub base_lots: u64,
+    pub size: String,
+    /// Immediate-or-cancel orders can be sized in quote lots instead of base lots.
+    pub quote_lots: u64,
+    pub min_base_lots_to_fill: u64,
+    pub min_quote_lots_to_fill: u64,
+    /// The most quote the order exchanges: the base size at the limit price, or the quote size.
+    /// `None` when the order has a base size and no limit price.
+    pub notional: Option<String>,
+    /// `None` for post-only orders, which never match.
+    pub self_trade_behavior: Option<String>,
+    pub match_limit: Option<u64>,
+    /// Only set for post-only orders.
+    pub reject_post_only: Option<bool>,
+    pub client_order_id: u128,
+    pub use_only_deposited_funds: bool,
+    pub last_valid_slot: Option<u64>,
+    pub last_valid_unix_timestamp_in_seconds: Option<u64>,
+    pub fail_silently_on_insufficient_funds: bool,
+    pub accounts: Vec<PreviewAccount>,
+}
+
+/// Names of the accounts of each new-order instruction, in order.
+fn account_names(instruction: PhoenixInstruction) -> &'static [&'static str] {
+    match instruction {
+        PhoenixInstruction::Swap => &[
+            "phoenix_program",
+            "log_authority",
+            "market",
+            "trader",
+            "base_account",
+            "quote_account",
+            "base_vault",
+            "quote_vault",
+            "token_program",
+        ],
+        PhoenixInstruction::PlaceLimitOrder => &[
+            "phoenix_program",
+            "log_authority",
+            "market",
+            "trader",
+            "seat",
+            "base_account",
+            "quote_account",
+            "base_vault",
+            "quote_vault",
+            "token_program",
+        ],
+        _ => &[
+            "phoenix_program",
+            "log_authority",
+            "market",
+            "trader",
+            "seat",
+        ],
+    }
+}
+
+impl OrderPreview {
+    /// Decodes `ix`, which must place a single order with `Swap`, `PlaceLimitOrder` or their
+    /// free-funds variants, on a market loaded into `core`.
+    pub fn from_instruction(core: &SDKClientCore, ix: &Instruction) -> Result<Self> {
+        if ix.program_id != phoenix::id() {
+            bail!("Instruction is for program {}, not Phoenix", ix.program_id);
+        }
+        let (tag, data) = ix
+            .data
+            .split_first()
+            .ok_or_else(|| anyhow!("Instruction has no data"))?;
+        let instruction = PhoenixInstruction::try_from(*tag)
+            .map_err(|_| anyhow!("Unknown Phoenix instruction tag {}", tag))?;
+        if !matches!(
+            instruction,
+            PhoenixInstruction::Swap
+                | PhoenixInstruction::SwapWithFreeFunds
+                | PhoenixInstruction::PlaceLimitOrder
+                | PhoenixInstruction::PlaceLimitOrderWithFreeFunds
+        ) {
+            bail!("{:?} does not place a single order", instruction);
+        }
+        let market = ix
+            .accounts
+            .get(2)
+            .ok_or_else(|| anyhow!("Instruction has no market account"))?
+            .pubkey;
+        let meta = core.markets.get(&market).ok_or_else(|| {
+            anyhow!(
+                "Market {} not found! Please load in the market first",
+                market
+            )
+        })?;
+        let packet = decode_order_packet(data)?;
+
+        let names = account_names(instruction);
+        let accounts = ix
+            .accounts
+            .iter()
+            .enumerate()
+            .map(|(i, account)| PreviewAccount {
+                name: names.get(i).copied().unwrap_or("unknown").to_string(),
+                pubkey: account.pubkey.to_string(),
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect();
+
+        let mut preview = Self::from_packet(&meta, &packet);
+        preview.use_only_deposited_funds |= matches!(
+            instruction,
+            PhoenixInstruction::SwapWithFreeFunds
+                | PhoenixInstruction::PlaceLimitOrderWithFreeFunds
+        );
+        preview.market = market.to_string();
+        preview.instruction = format!("{:?}", instruction);
+        preview.accounts = accounts;
+        Ok(preview)
+    }
+
+    fn from_packet(meta: &MarketMetadata, packet: &OrderPacket) -> Self {
+        let (order_type, self_trade_behavior, match_limit, reject_post_only) = match *packet {
+            OrderPacket::PostOnly {
+                reject_post_only, ..
+            } => (
+                PreviewOrderType::PostOnly,
+                None,
+                None,
+                Some(reject_post_only),
+            ),
+            OrderPacket::Limit {
+                self_trade_behavior,
+                match_limit,
+                ..
+            } => (
+                PreviewOrderType::Limit,
+                Some(self_trade_behavior),
+                match_limit,
+                None,
+            ),
+            OrderPacket::ImmediateOrCancel {
+                self_trade_behavior,
+                match_limit,
+                ..
+            } => {
+                let order_type = if packet.is_fok() {
+                    PreviewOrderType::FillOrKill
+                } else {
+                    PreviewOrderType::ImmediateOrCancel
+                };
+                (order_type, Some(self_trade_behavior), match_limit, None)
+            }
+        };
+        let (min_base_lots_to_fill, min_quote_lots_to_fill) = match *packet {
+            OrderPacket::ImmediateOrCancel {
+                min_base_lots_to_fill,
+                min_quote_lots_to_fill,
+                ..
+            } => (
+                min_base_lots_to_fill.as_u64(),
+                min_quote_lots_to_fill.as_u64(),
+            ),
+            _ => (0, 0),
+        };
+        let price_in_ticks = match *packet {
+            OrderPacket::ImmediateOrCancel { price_in_ticks, .. } => {
+                price_in_ticks.map(|price| price.as_u64())
+            }
+            _ => Some(packet.get_price_in_ticks().as_u64()),
+        };
+        let base_lots = packet.num_base_lots().as_u64();
+        let quote_lots = packet.num_quote_lots().as_u64();
+        let notional_atoms = if quote_lots > 0 {
+            Some(meta.quote_lots_to_quote_atoms(quote_lots))
+        } else {
+            price_in_ticks.map(|price| meta.base_lots_and_price_to_quote_atoms(base_lots, price))
+        };
+
+        OrderPreview {
+            market: String::new(),
+            symbol: None,
+            instruction: String::new(),
+            order_type,
+            side: match packet.side() {
+                Side::Bid => "buy",
+                Side::Ask => "sell",
+            }
+            .to_string(),
+            price_in_ticks,
+            price: price_in_ticks.map(|price| meta.ticks_to_float_price(price)),
+            base_lots,
+            size: meta.base_lots_to_decimal_str(base_lots),
+            quote_lots,
+            min_base_lots_to_fill,
+            min_quote_lots_to_fill,
+            notional: notional_atoms.map(|atoms| meta.quote_atoms_to_decimal_str(atoms as u128)),
+            self_trade_behavior: self_trade_behavior
+                .map(|behavior: SelfTradeBehavior| format!("{:?}", behavior)),
+            match_limit,
+            reject_post_only,
+            client_order_id: packet.client_order_id(),
+            use_only_deposited_funds: packet.no_deposit_or_withdrawal(),
+            last_valid_slot: packet.get_last_valid_slot(),
+            last_valid_unix_timestamp_in_seconds: packet.get_last_valid_unix_timestamp_in_seconds(),
+            fail_silently_on_insufficient_funds: packet.fail_silently_on_insufficient_funds(),
+            accounts: vec![],
+        }
+    }
+
+    /// Fills in the market's symbol from `registry`, if it lists the market.
+    pub fn with_symbol_from(mut self, registry: &MarketRegistry) -> Self {
+        self.symbol = self
+            .market
+            .parse()
+            .ok()
+            .and_then(|market| registry.symbol(&market))
+            .map(str::to_string);
+        self
+    }
+}
+
+impl fmt::Display for OrderPreview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.symbol {
+            Some(symbol) => writeln!(
+                f,
+                "{} order on {} ({})",
+                self.order_type, symbol, self.market
+            )?,
+            None => writeln!(f, "{} order on {}", self.order_type, self.market)?,
+        }
+        writeln!(f, "  Instruction:      {}", self.instruction)?;
+        writeln!(f, "  Side:             {}", self.side)?;
+        match (self.price, self.price_in_ticks) {
+            (Some(price), Some(ticks)) => {
+                writeln!(f, "  Price:            {} ({} ticks)", price, ticks)?
+            }
+            _ => writeln!(f, "  Price:            market")?,
+        }
+        if self.quote_lots > 0 {
+            writeln!(f, "  Size:             {} quote lots", self.quote_lots)?;
+        } else {
+            writeln!(
+                f,
+                "  Size:             {} ({} base lots)",
+                self.size, self.base_lots
+            )?;
+        }
+        if self.min_base_lots_to_fill > 0 || self.min_quote_lots_to_fill > 0 {
+            writeln!(
+                f,
+                "  Minimum fill:     {} base lots, {} quote lots",
+                self.min_base_lots_to_fill, self.min_quote_lots_to_fill
+            )?;
+        }
+        writeln!(
+            f,
+            "  Notional:         {}",
+            self.notional.as_deref().unwrap_or("unknown")
+        )?;
+        if let Some(behavior) = &self.self_trade_behavior {
+            writeln!(f, "  Self trade:       {}", behavior)?;
+        }
+        if let Some(match_limit) = self.match_limit {
+            writeln!(f, "  Match limit:      {}", match_limit)?;
+        }
+        if let Some(reject_post_only) = self.reject_post_only {
+            writeln!(f, "  Reject crossing:  {}", reject_post_only)?;
+        }
+        writeln!(f, "  Client order id:  {}", self.client_order_id)?;
+        match (
+            self.last_valid_slot,
+            self.last_valid_unix_timestamp_in_seconds,
+        ) {
+            (None, None) => writeln!(f, "  Expiry:           none")?,
+            (slot, timestamp) => {
+                let slot = slot.map(|slot| format!("slot {}", slot));
+                let timestamp = timestamp.map(|timestamp| format!("unix time {}", timestamp));
+                let expiry = slot.into_iter().chain(timestamp).collect::<Vec<_>>();
+                writeln!(f, "  Expiry:           after {}", expiry.join(" or "))?
+            }
+        }
+        writeln!(
+            f,
+            "  Funds:            {}",
+            if self.use_only_deposited_funds {
+                "deposited only"
+            } else {
+                "token accounts"
+            }
+        )?;
+        if self.fail_silently_on_insufficient_funds {
+            writeln!(f, "  Fails silently on insufficient funds")?;
+        }
+        writeln!(f, "  Accounts:")?;
+        for account in &self.accounts {
+            let mut flags = vec![];
+            if account.is_signer {
+                flags.push("signer");
+            }
+            if account.is_writable {
+                flags.push("writable");
+            }
+            write!(f, "    {:<16} {}", account.name, account.pubkey)?;
+            if !flags.is_empty() {
+                write!(f, " ({})", flags.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use phoenix::program::create_new_order_with_free_funds_instruction;
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::market_registry::MarketEntry;
+
+    fn setup() -> (SDKClientCore, Pubkey) {
+        let market = Pubkey::new_unique();
+        let meta = MarketMetadata {
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_atoms_per_raw_base_unit: 1_000_000_000,
+            quote_atoms_per_quote_unit: 1_000_000,
+            quote_atoms_per_quote_lot: 1,
+            base_atoms_per_base_lot: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 1000,
+            num_base_lots_per_base_unit: 1000,
+            raw_base_units_per_base_unit: 1,
+            ..Default::default()
+        };
+        let core = SDKClientCore {
+            markets: [(market, meta)].into_iter().collect(),
+            trader: Pubkey::new_unique(),
+            default_self_trade_behavior: Default::default(),
+        };
+        (core, market)
+    }
+
+    #[test]
+    fn test_limit_order_preview() {
+        let (core, market) = setup();
+        let ix = core
+            .get_limit_order_generic_ix(
+                &market,
+                // Prices are in quote atoms per base unit
+                22_500_000,
+                Side::Bid,
+                1500,
+                Some(SelfTradeBehavior::CancelProvide),
+                Some(3),
+                Some(7),
+                None,
+                Some(1000),
+                None,
+                None,
+            )
+            .unwrap();
+        let mut registry = MarketRegistry::new();
+        registry
+            .insert(MarketEntry {
+                symbol: "SOL/USDC".to_string(),
+                market,
+                base_mint: core.markets.get(&market).unwrap().base_mint,
+                quote_mint: core.markets.get(&market).unwrap().quote_mint,
+            })
+            .unwrap();
+        let preview = OrderPreview::from_instruction(&core, &ix)
+            .unwrap()
+            .with_symbol_from(&registry);
+
+        assert_eq!(preview.symbol.as_deref(), Some("SOL/USDC"));
+        assert_eq!(preview.instruction, "PlaceLimitOrder");
+        assert_eq!(preview.order_type, PreviewOrderType::Limit);
+        assert_eq!(preview.side, "buy");
+        assert_eq!(preview.price_in_ticks, Some(22_500));
+        assert_eq!(preview.price, Some(22.5));
+        assert_eq!((preview.base_lots, preview.size.as_str()), (1500, "1.5"));
+        assert_eq!(preview.notional.as_deref(), Some("33.75"));
+        assert_eq!(
+            preview.self_trade_behavior.as_deref(),
+            Some("CancelProvide")
+        );
+        assert_eq!(preview.match_limit, Some(3));
+        assert_eq!(preview.client_order_id, 7);
+        assert_eq!(preview.last_valid_slot, Some(1000));
+        assert_eq!(preview.accounts.len(), 10);
+        let trader = &preview.accounts[3];
+        assert_eq!(trader.name, "trader");
+        assert_eq!(Pubkey::from_str(&trader.pubkey).unwrap(), core.trader);
+        assert!(trader.is_signer);
+
+        let text = preview.to_string();
+        assert!(text.starts_with(&format!("Limit order on SOL/USDC ({})", market)));
+        assert!(text.contains("22.5 (22500 ticks)"));
+        assert!(text.contains("Expiry:           after slot 1000"));
+
+        let json = serde_json::to_string(&preview).unwrap();
+        assert_eq!(
+            serde_json::from_str::<OrderPreview>(&json).unwrap(),
+            preview
+        );
+    }
+
+    #[test]
+    fn test_ioc_and_post_only_previews() {
+        let (core, market) = setup();
+        let ix = core
+            .get_fok_generic_ix(
+                &market,
+                22_000_000,
+                Side::Ask,
+                2_000_000_000,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let preview = OrderPreview::from_instruction(&core, &ix).unwrap();
+        assert_eq!(preview.order_type, PreviewOrderType::FillOrKill);
+        assert_eq!(preview.side, "sell");
+
+        let ix = create_new_order_with_free_funds_instruction(
+            &market,
+            &core.trader,
+            &OrderPacket::new_post_only_default(Side::Ask, 21_000, 10),
+        );
+        let preview = OrderPreview::from_instruction(&core, &ix).unwrap();
+        assert_eq!(preview.instruction, "PlaceLimitOrderWithFreeFunds");
+        assert_eq!(preview.order_type, PreviewOrderType::PostOnly);
+        assert_eq!(preview.reject_post_only, Some(true));
+        assert_eq!(preview.self_trade_behavior, None);
+        assert!(preview.use_only_deposited_funds);
+        assert_eq!(preview.accounts.len(), 5);
+        assert_eq!(preview.accounts[4].name, "seat");
+
+        let cancel = core.get_cancel_all_ix(&market).unwrap();
+        assert!(OrderPreview::from_instruction(&core, &cancel).is_err());
+    }
+}
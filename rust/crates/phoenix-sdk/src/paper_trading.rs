@@ -0,0 +1,1104 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Result};
+use borsh::BorshDeserialize;
+use phoenix::program::new_order::MultipleOrderPacket;
+use phoenix::program::{
+    CancelMultipleOrdersByIdParams, CancelUpToParams, PhoenixInstruction, ReduceOrderParams,
+};
+use phoenix::quantities::{Ticks, WrapperU64};
+use phoenix::state::markets::FIFOOrderId;
+use phoenix::state::{
+    decode_order_packet, OrderPacket, OrderPacketMetadata, SelfTradeBehavior, Side,
+};
+use phoenix_sdk_core::market_event::{
+    Fill, FillSummary, MarketEventDetails, PhoenixEvent, Place, Reduce,
+};
+use phoenix_sdk_core::orderbook::Orderbook;
+use phoenix_sdk_core::sdk_client_core::{MarketMetadata, MarketState, PhoenixOrder};
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::event_sink::EventSink;
+
+const FEE_DIVISOR: u64 = 10000;
+
+/// Sequence numbers handed out to paper orders start here so they never collide with real orders.
+const FIRST_PAPER_ORDER_SEQUENCE_NUMBER: u64 = 1 << 62;
+
+/// Controls whether `SDKClient` submits transactions to the cluster.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TradingMode {
+    #[default]
+    Live,
+    /// Transactions are simulated locally by the client's `PaperExchange` and never sent.
+    Paper,
+}
+
+/// `FIFOOrderId` orders bids and asks in opposite directions, so open orders are keyed by side
+/// first (bids, then asks) to keep the map's ordering consistent.
+type OpenOrderKey = (bool, FIFOOrderId);
+
+fn open_order_key(order_id: FIFOOrderId) -> OpenOrderKey {
+    (
+        Side::from_order_sequence_number(order_id.order_sequence_number) == Side::Ask,
+        order_id,
+    )
+}
+
+/// The state a paper transaction mutates. It is cloned before each transaction so that a failing
+/// instruction leaves no trace, matching on-chain atomicity.
+#[derive(Debug, Clone, Default)]
+struct PaperState {
+    books: BTreeMap<Pubkey, Orderbook<FIFOOrderId, PhoenixOrder>>,
+    open_orders: BTreeMap<Pubkey, BTreeMap<OpenOrderKey, u64>>,
+    balances: BTreeMap<Pubkey, i128>,
+    next_order_sequence_number: u64,
+    market_sequence_numbers: BTreeMap<Pubkey, u64>,
+}
+
+/// Simulates Phoenix instructions against local snapshots of market state.
+///
+/// Taking orders walk the snapshot book in price-time priority and consume the liquidity they
+/// match, so repeated sends see the book they left behind until the snapshot is refreshed. Resting
+/// paper orders are kept separately from the snapshot and never trade; they exist so that cancels
+/// and reduces behave as they would on chain.
+///
+/// Balances are free (unlocked) balances per mint in atoms. They start at zero unless seeded with
+/// `set_balance` and are not enforced, so they may go negative.
+#[derive(Debug, Default)]
+pub struct PaperExchange {
+    state: PaperState,
+    transactions: HashMap<Signature, Vec<PhoenixEvent>>,
+    event_sender: Option<Arc<dyn EventSink>>,
+    /// Taker fee applied to simulated fills, in basis points.
+    pub taker_fee_bps: u64,
+    slot: u64,
+}
+
+impl PaperExchange {
+    pub fn new(taker_fee_bps: u64) -> Self {
+        Self {
+            taker_fee_bps,
+            ..Default::default()
+        }
+    }
+
+    /// Replaces the snapshot that paper orders on `market_key` are matched against.
+    pub fn set_market_state(&mut self, market_key: &Pubkey, market_state: &MarketState) {
+        self.state
+            .books
+            .insert(*market_key, market_state.orderbook.clone());
+    }
+
+    pub fn has_market_state(&self, market_key: &Pubkey) -> bool {
+        self.state.books.contains_key(market_key)
+    }
+
+    pub fn set_balance(&mut self, mint: &Pubkey, atoms: i128) {
+        self.state.balances.insert(*mint, atoms);
+    }
+
+    pub fn balance(&self, mint: &Pubkey) -> i128 {
+        self.state.balances.get(mint).copied().unwrap_or(0)
+    }
+
+    /// Returns the open paper orders on a market with their remaining size in base lots.
+    pub fn open_orders(&self, market_key: &Pubkey) -> Vec<(FIFOOrderId, u64)> {
+        self.state
+            .open_orders
+            .get(market_key)
+            .map(|orders| orders.iter().map(|(&(_, id), &lots)| (id, lots)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Synthesized events are also pushed into this sink, one batch per paper transaction.
+    pub fn set_event_sender<S: EventSink + 'static>(&mut self, sender: Option<S>) {
+        self.event_sender = sender.map(|sender| Arc::new(sender) as Arc<dyn EventSink>);
+    }
+
+    pub fn event_sender(&self) -> Option<Arc<dyn EventSink>> {
+        self.event_sender.clone()
+    }
+
+    /// Returns the events of a paper transaction, as `parse_events_from_transaction` would.
+    pub fn get_transaction_events(&self, signature: &Signature) -> Option<Vec<PhoenixEvent>> {
+        self.transactions.get(signature).cloned()
+    }
+
+    /// Returns the markets the Phoenix instructions in `instructions` touch.
+    pub fn markets_for_instructions(instructions: &[Instruction]) -> Vec<Pubkey> {
+        let mut markets = instructions
+            .iter()
+            .filter(|ix| ix.program_id == phoenix::id())
+            .filter_map(|ix| ix.accounts.get(2).map(|meta| meta.pubkey))
+            .collect::<Vec<_>>();
+        markets.sort();
+        markets.dedup();
+        markets
+    }
+
+    /// Simulates a transaction signed by `signer`. Instructions for other programs (e.g. token
+    /// account creation) and Phoenix instructions that do not affect orders are accepted as no-ops.
+    /// If any instruction fails, the whole transaction fails and no state changes.
+    pub fn process_transaction(
+        &mut self,
+        signer: &Pubkey,
+        instructions: &[Instruction],
+        markets: &BTreeMap<Pubkey, MarketMetadata>,
+    ) -> Result<(Signature, Vec<PhoenixEvent>)> {
+        let signature = Signature::new_unique();
+        self.slot += 1;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        let mut state = self.state.clone();
+        let mut events = vec![];
+        for ix in instructions
+            .iter()
+            .filter(|ix| ix.program_id == phoenix::id())
+        {
+            let (tag, data) = ix
+                .data
+                .split_first()
+                .ok_or_else(|| anyhow!("Empty Phoenix instruction"))?;
+            let market_key = match ix.accounts.get(2) {
+                Some(meta) => meta.pubkey,
+                None => continue,
+            };
+            let sequence_number = state.market_sequence_numbers.entry(market_key).or_default();
+            *sequence_number += 1;
+            let mut context = InstructionContext {
+                template: PhoenixEvent {
+                    market: market_key,
+                    sequence_number: *sequence_number,
+                    slot: self.slot,
+                    timestamp,
+                    signature,
+                    signer: *signer,
+                    event_index: 0,
+                    details: MarketEventDetails::Fee(0),
+                },
+                events: vec![],
+            };
+            let ix_enum = PhoenixInstruction::try_from(*tag)
+                .map_err(|_| anyhow!("Unknown Phoenix instruction tag {}", tag))?;
+            match ix_enum {
+                PhoenixInstruction::Swap
+                | PhoenixInstruction::SwapWithFreeFunds
+                | PhoenixInstruction::PlaceLimitOrder
+                | PhoenixInstruction::PlaceLimitOrderWithFreeFunds => {
+                    let meta = get_meta(markets, &market_key)?;
+                    let order_packet = decode_order_packet(data)
+                        .ok_or_else(|| anyhow!("Failed to decode order packet"))?;
+                    state.process_order_packet(
+                        &mut context,
+                        meta,
+                        order_packet,
+                        self.taker_fee_bps,
+                    )?;
+                }
+                PhoenixInstruction::PlaceMultiplePostOnlyOrders
+                | PhoenixInstruction::PlaceMultiplePostOnlyOrdersWithFreeFunds => {
+                    let meta = get_meta(markets, &market_key)?;
+                    let packet = MultipleOrderPacket::try_from_slice(data)?;
+                    let reject_post_only = packet
+                        .failed_multiple_limit_order_behavior
+                        .should_fail_on_cross();
+                    let client_order_id = packet.client_order_id.unwrap_or(0);
+                    for (side, orders) in [(Side::Bid, &packet.bids), (Side::Ask, &packet.asks)] {
+                        for order in orders.iter() {
+                            state.process_order_packet(
+                                &mut context,
+                                meta,
+                                OrderPacket::new_post_only(
+                                    side,
+                                    order.price_in_ticks,
+                                    order.size_in_base_lots,
+                                    client_order_id,
+                                    reject_post_only,
+                                    false,
+                                ),
+                                self.taker_fee_bps,
+                            )?;
+                        }
+                    }
+                }
+                PhoenixInstruction::CancelAllOrders
+                | PhoenixInstruction::CancelAllOrdersWithFreeFunds => {
+                    let meta = get_meta(markets, &market_key)?;
+                    let order_ids = state.open_order_ids(&market_key, |_| true);
+                    state.cancel_orders(&mut context, meta, order_ids);
+                }
+                PhoenixInstruction::CancelUpTo | PhoenixInstruction::CancelUpToWithFreeFunds => {
+                    let meta = get_meta(markets, &market_key)?;
+                    let params = CancelUpToParams::try_from_slice(data)?;
+                    let last_tick = params.tick_limit.unwrap_or(match params.side {
+                        Side::Ask => u64::MAX,
+                        Side::Bid => 0,
+                    });
+                    let mut order_ids = state.open_order_ids(&market_key, |order_id| {
+                        Side::from_order_sequence_number(order_id.order_sequence_number)
+                            == params.side
+                            && match params.side {
+                                Side::Bid => order_id.price_in_ticks.as_u64() >= last_tick,
+                                Side::Ask => order_id.price_in_ticks.as_u64() <= last_tick,
+                            }
+                    });
+                    if let Some(num_orders_to_cancel) = params.num_orders_to_cancel {
+                        order_ids.truncate(num_orders_to_cancel as usize);
+                    }
+                    state.cancel_orders(&mut context, meta, order_ids);
+                }
+                PhoenixInstruction::CancelMultipleOrdersById
+                | PhoenixInstruction::CancelMultipleOrdersByIdWithFreeFunds => {
+                    let meta = get_meta(markets, &market_key)?;
+                    let params = CancelMultipleOrdersByIdParams::try_from_slice(data)?;
+                    let order_ids = params
+                        .orders
+                        .iter()
+                        .map(|order| {
+                            FIFOOrderId::new_from_untyped(
+                                order.price_in_ticks,
+                                order.order_sequence_number,
+                            )
+                        })
+                        .collect();
+                    state.cancel_orders(&mut context, meta, order_ids);
+                }
+                PhoenixInstruction::ReduceOrder | PhoenixInstruction::ReduceOrderWithFreeFunds => {
+                    let meta = get_meta(markets, &market_key)?;
+                    let params = ReduceOrderParams::try_from_slice(data)?;
+                    let order_id = FIFOOrderId::new_from_untyped(
+                        params.base_params.price_in_ticks,
+                        params.base_params.order_sequence_number,
+                    );
+                    state.reduce_order(&mut context, meta, order_id, params.size);
+                }
+                // Deposits, withdrawals and seat management do not change simulated orders
+                _ => {}
+            }
+            events.extend(context.events);
+        }
+
+        self.state = state;
+        self.transactions.insert(signature, events.clone());
+        Ok((signature, events))
+    }
+}
+
+fn get_meta<'a>(
+    markets: &'a BTreeMap<Pubkey, MarketMetadata>,
+    market_key: &Pubkey,
+) -> Result<&'a MarketMetadata> {
+    markets
+        .get(market_key)
+        .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))
+}
+
+fn quote_lots_for(meta: &MarketMetadata, base_lots: u64, price_in_ticks: u64) -> u64 {
+    meta.base_lots_and_price_to_quote_atoms(base_lots, price_in_ticks)
+        / meta.quote_atoms_per_quote_lot
+}
+
+/// Accumulates the events of a single instruction, stamped from a shared header.
+struct InstructionContext {
+    template: PhoenixEvent,
+    events: Vec<PhoenixEvent>,
+}
+
+impl InstructionContext {
+    fn push(&mut self, details: MarketEventDetails) {
+        self.events.push(PhoenixEvent {
+            event_index: self.events.len() as u64,
+            details,
+            ..self.template
+        });
+    }
+
+    fn market(&self) -> Pubkey {
+        self.template.market
+    }
+
+    fn signer(&self) -> Pubkey {
+        self.template.signer
+    }
+}
+
+impl PaperState {
+    fn adjust_balance(&mut self, mint: &Pubkey, atoms: i128) {
+        *self.balances.entry(*mint).or_default() += atoms;
+    }
+
+    /// Adjusts balances for funds moving into (`lock`) or out of a resting order.
+    fn lock_funds(
+        &mut self,
+        meta: &MarketMetadata,
+        side: Side,
+        price_in_ticks: u64,
+        base_lots: u64,
+        lock: bool,
+    ) {
+        let sign = if lock { -1 } else { 1 };
+        match side {
+            Side::Bid => self.adjust_balance(
+                &meta.quote_mint,
+                sign * meta.base_lots_and_price_to_quote_atoms(base_lots, price_in_ticks) as i128,
+            ),
+            Side::Ask => self.adjust_balance(
+                &meta.base_mint,
+                sign * meta.base_lots_to_base_atoms(base_lots) as i128,
+            ),
+        }
+    }
+
+    fn open_order_ids(
+        &self,
+        market_key: &Pubkey,
+        filter: impl Fn(&FIFOOrderId) -> bool,
+    ) -> Vec<FIFOOrderId> {
+        self.open_orders
+            .get(market_key)
+            .map(|orders| {
+                orders
+                    .keys()
+                    .map(|&(_, id)| id)
+                    .filter(|id| filter(id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn process_order_packet(
+        &mut self,
+        context: &mut InstructionContext,
+        meta: &MarketMetadata,
+        mut order_packet: OrderPacket,
+        taker_fee_bps: u64,
+    ) -> Result<()> {
+        let market_key = context.market();
+        let side = order_packet.side();
+        let book = self
+            .books
+            .get(&market_key)
+            .ok_or_else(|| anyhow!("No market state snapshot for market {}", market_key))?;
+        let best_opposite_price = match side {
+            Side::Bid => book.asks.keys().next(),
+            Side::Ask => book.bids.keys().next(),
+        }
+        .map(|order_id| order_id.price_in_ticks.as_u64());
+
+        if let OrderPacket::PostOnly {
+            reject_post_only, ..
+        } = order_packet
+        {
+            let price_in_ticks = order_packet.get_price_in_ticks().as_u64();
+            if let Some(best) = best_opposite_price {
+                let crosses = match side {
+                    Side::Bid => price_in_ticks >= best,
+                    Side::Ask => price_in_ticks <= best,
+                };
+                if crosses {
+                    if reject_post_only {
+                        bail!(
+                            "Post-only order at {} ticks would cross the book",
+                            price_in_ticks
+                        );
+                    }
+                    let amended = match side {
+                        Side::Bid => best.saturating_sub(1),
+                        Side::Ask => best + 1,
+                    };
+                    if amended == 0 {
+                        bail!("Post-only bid cannot be amended below one tick");
+                    }
+                    order_packet.set_price_in_ticks(Ticks::new(amended));
+                }
+            }
+        }
+        let base_lots_matched = if order_packet.is_post_only() {
+            0
+        } else {
+            self.match_order(context, meta, &order_packet, taker_fee_bps)?
+        };
+
+        if order_packet.is_take_only() {
+            return Ok(());
+        }
+        let base_lots_to_place = order_packet
+            .num_base_lots()
+            .as_u64()
+            .saturating_sub(base_lots_matched);
+        if base_lots_to_place == 0 {
+            return Ok(());
+        }
+        let price_in_ticks = order_packet.get_price_in_ticks().as_u64();
+        let order_sequence_number =
+            FIRST_PAPER_ORDER_SEQUENCE_NUMBER + self.next_order_sequence_number;
+        self.next_order_sequence_number += 1;
+        let order_sequence_number = match side {
+            Side::Bid => !order_sequence_number,
+            Side::Ask => order_sequence_number,
+        };
+        self.open_orders.entry(market_key).or_default().insert(
+            open_order_key(FIFOOrderId::new_from_untyped(
+                price_in_ticks,
+                order_sequence_number,
+            )),
+            base_lots_to_place,
+        );
+        self.lock_funds(meta, side, price_in_ticks, base_lots_to_place, true);
+        context.push(MarketEventDetails::Place(Place {
+            order_sequence_number,
+            client_order_id: order_packet.client_order_id(),
+            maker: context.signer(),
+            price_in_ticks,
+            base_lots_placed: base_lots_to_place,
+        }));
+        Ok(())
+    }
+
+    /// Walks the opposite side of the snapshot book, consuming liquidity up to the packet's limit
+    /// price, size budgets and match limit. Returns the base lots taken from the order's size,
+    /// including any decremented by self trades.
+    fn match_order(
+        &mut self,
+        context: &mut InstructionContext,
+        meta: &MarketMetadata,
+        order_packet: &OrderPacket,
+        taker_fee_bps: u64,
+    ) -> Result<u64> {
+        let market_key = context.market();
+        let side = order_packet.side();
+        let limit_price = order_packet.get_price_in_ticks().as_u64();
+        let mut base_lots_remaining = order_packet.base_lot_budget().as_u64();
+        // Buys pay fees out of the quote budget
+        let mut quote_lots_remaining = order_packet.quote_lot_budget().map(|budget| match side {
+            Side::Bid => budget.as_u64() * FEE_DIVISOR / (FEE_DIVISOR + taker_fee_bps),
+            Side::Ask => budget.as_u64(),
+        });
+        let mut matches_remaining = order_packet.match_limit();
+        let self_trade_behavior = order_packet.self_trade_behavior();
+        let book = self
+            .books
+            .get_mut(&market_key)
+            .ok_or_else(|| anyhow!("No market state snapshot for market {}", market_key))?;
+        let resting = match side {
+            Side::Bid => book.asks.iter(),
+            Side::Ask => book.bids.iter(),
+        }
+        .map(|(&order_id, &order)| (order_id, order))
+        .collect::<Vec<_>>();
+
+        let mut total_base_lots = 0;
+        let mut total_quote_lots = 0;
+        let mut base_lots_decremented = 0;
+        let mut maker_events = vec![];
+        for (order_id, order) in resting {
+            let price_in_ticks = order_id.price_in_ticks.as_u64();
+            let crosses = match side {
+                Side::Bid => price_in_ticks <= limit_price,
+                Side::Ask => price_in_ticks >= limit_price,
+            };
+            if !crosses || base_lots_remaining == 0 || matches_remaining == 0 {
+                break;
+            }
+            if order.maker_id == context.signer() {
+                match self_trade_behavior {
+                    SelfTradeBehavior::Abort => bail!("Order would self trade"),
+                    SelfTradeBehavior::CancelProvide => {
+                        maker_events.push(MarketEventDetails::Reduce(Reduce {
+                            order_sequence_number: order_id.order_sequence_number,
+                            maker: order.maker_id,
+                            price_in_ticks,
+                            base_lots_removed: order.num_base_lots,
+                            base_lots_remaining: 0,
+                            is_full_cancel: true,
+                        }));
+                    }
+                    SelfTradeBehavior::DecrementTake => {
+                        let decrement = order.num_base_lots.min(base_lots_remaining);
+                        base_lots_remaining -= decrement;
+                        base_lots_decremented += decrement;
+                        maker_events.push(MarketEventDetails::Reduce(Reduce {
+                            order_sequence_number: order_id.order_sequence_number,
+                            maker: order.maker_id,
+                            price_in_ticks,
+                            base_lots_removed: decrement,
+                            base_lots_remaining: order.num_base_lots - decrement,
+                            is_full_cancel: decrement == order.num_base_lots,
+                        }));
+                    }
+                }
+                continue;
+            }
+            let mut base_lots_filled = order.num_base_lots.min(base_lots_remaining);
+            if let Some(quote_lots) = quote_lots_remaining {
+                let quote_lots_per_base_lot = quote_lots_for(meta, 1, price_in_ticks).max(1);
+                base_lots_filled = base_lots_filled.min(quote_lots / quote_lots_per_base_lot);
+            }
+            if base_lots_filled == 0 {
+                break;
+            }
+            let quote_lots_filled = quote_lots_for(meta, base_lots_filled, price_in_ticks);
+            base_lots_remaining -= base_lots_filled;
+            if let Some(quote_lots) = quote_lots_remaining.as_mut() {
+                *quote_lots -= quote_lots_filled;
+            }
+            matches_remaining -= 1;
+            total_base_lots += base_lots_filled;
+            total_quote_lots += quote_lots_filled;
+            maker_events.push(MarketEventDetails::Fill(Fill::new(
+                order_id.order_sequence_number,
+                order.maker_id,
+                context.signer(),
+                price_in_ticks,
+                base_lots_filled,
+                order.num_base_lots - base_lots_filled,
+            )));
+        }
+
+        let fee_quote_lots = (total_quote_lots * taker_fee_bps).div_ceil(FEE_DIVISOR);
+        if let OrderPacket::ImmediateOrCancel {
+            min_base_lots_to_fill,
+            min_quote_lots_to_fill,
+            ..
+        } = order_packet
+        {
+            // Buyers are measured on quote spent and sellers on quote received
+            let quote_lots_for_min = match side {
+                Side::Bid => total_quote_lots + fee_quote_lots,
+                Side::Ask => total_quote_lots - fee_quote_lots,
+            };
+            if total_base_lots < min_base_lots_to_fill.as_u64()
+                || quote_lots_for_min < min_quote_lots_to_fill.as_u64()
+            {
+                bail!(
+                    "Order filled {} base lots and {} quote lots, below the required minimum",
+                    total_base_lots,
+                    total_quote_lots
+                );
+            }
+        }
+
+        for details in maker_events {
+            let event = PhoenixEvent {
+                details,
+                ..context.template
+            };
+            book.apply_event(&event);
+            context.push(details);
+        }
+        if total_base_lots == 0 {
+            return Ok(base_lots_decremented);
+        }
+
+        let base_atoms = meta.base_lots_to_base_atoms(total_base_lots) as i128;
+        let quote_atoms = meta.quote_lots_to_quote_atoms(total_quote_lots) as i128;
+        let fee_atoms = meta.quote_lots_to_quote_atoms(fee_quote_lots) as i128;
+        match side {
+            Side::Bid => {
+                self.adjust_balance(&meta.base_mint, base_atoms);
+                self.adjust_balance(&meta.quote_mint, -(quote_atoms + fee_atoms));
+            }
+            Side::Ask => {
+                self.adjust_balance(&meta.base_mint, -base_atoms);
+                self.adjust_balance(&meta.quote_mint, quote_atoms - fee_atoms);
+            }
+        }
+        if order_packet.is_take_only() {
+            context.push(MarketEventDetails::FillSummary(FillSummary {
+                client_order_id: order_packet.client_order_id(),
+                total_base_filled: base_atoms as u64,
+                total_quote_filled_including_fees: match side {
+                    Side::Bid => quote_atoms + fee_atoms,
+                    Side::Ask => quote_atoms - fee_atoms,
+                } as u64,
+                total_quote_fees: fee_atoms as u64,
+                trade_direction: match side {
+                    Side::Bid => 1,
+                    Side::Ask => -1,
+                },
+            }));
+        }
+        Ok(total_base_lots + base_lots_decremented)
+    }
+
+    fn cancel_orders(
+        &mut self,
+        context: &mut InstructionContext,
+        meta: &MarketMetadata,
+        order_ids: Vec<FIFOOrderId>,
+    ) {
+        for order_id in order_ids {
+            self.reduce_order(context, meta, order_id, u64::MAX);
+        }
+    }
+
+    fn reduce_order(
+        &mut self,
+        context: &mut InstructionContext,
+        meta: &MarketMetadata,
+        order_id: FIFOOrderId,
+        base_lots: u64,
+    ) {
+        let Some(orders) = self.open_orders.get_mut(&context.market()) else {
+            return;
+        };
+        let Some(remaining) = orders.get_mut(&open_order_key(order_id)) else {
+            return;
+        };
+        let base_lots_removed = base_lots.min(*remaining);
+        *remaining -= base_lots_removed;
+        let base_lots_remaining = *remaining;
+        if base_lots_remaining == 0 {
+            orders.remove(&open_order_key(order_id));
+        }
+        let side = Side::from_order_sequence_number(order_id.order_sequence_number);
+        let price_in_ticks = order_id.price_in_ticks.as_u64();
+        self.lock_funds(meta, side, price_in_ticks, base_lots_removed, false);
+        context.push(MarketEventDetails::Reduce(Reduce {
+            order_sequence_number: order_id.order_sequence_number,
+            maker: context.signer(),
+            price_in_ticks,
+            base_lots_removed,
+            base_lots_remaining,
+            is_full_cancel: base_lots_remaining == 0,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use phoenix_sdk_core::market_map::MarketMap;
+    use phoenix_sdk_core::sdk_client_core::{IocLeg, SDKClientCore};
+
+    fn setup() -> (SDKClientCore, Pubkey, PaperExchange) {
+        let market = Pubkey::new_unique();
+        let meta = MarketMetadata {
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_atoms_per_raw_base_unit: 1_000_000,
+            quote_atoms_per_quote_unit: 1_000_000,
+            quote_atoms_per_quote_lot: 1,
+            base_atoms_per_base_lot: 1000,
+            tick_size_in_quote_atoms_per_base_unit: 1000,
+            num_base_lots_per_base_unit: 1000,
+            raw_base_units_per_base_unit: 1,
+            ..Default::default()
+        };
+        let core = SDKClientCore {
+            markets: MarketMap::from_iter([(market, meta)]),
+            trader: Pubkey::new_unique(),
+            default_self_trade_behavior: Default::default(),
+        };
+        let maker = Pubkey::new_unique();
+        let mut orderbook = Orderbook {
+            raw_base_units_per_base_lot: meta.raw_base_units_per_base_lot_as_float(),
+            quote_units_per_raw_base_unit_per_tick: meta
+                .quote_units_per_raw_base_unit_per_tick_as_float(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        };
+        for (price_in_ticks, seq) in [(101, 1), (102, 2)] {
+            orderbook.asks.insert(
+                FIFOOrderId::new_from_untyped(price_in_ticks, seq),
+                PhoenixOrder {
+                    num_base_lots: 10,
+                    maker_id: maker,
+                },
+            );
+        }
+        orderbook.bids.insert(
+            FIFOOrderId::new_from_untyped(99, !3),
+            PhoenixOrder {
+                num_base_lots: 10,
+                maker_id: maker,
+            },
+        );
+        let mut exchange = PaperExchange::new(10);
+        exchange.set_market_state(
+            &market,
+            &MarketState {
+                orderbook,
+                traders: BTreeMap::new(),
+            },
+        );
+        (core, market, exchange)
+    }
+
+    #[test]
+    fn test_ioc_walks_the_book() {
+        let (core, market, mut exchange) = setup();
+        let meta = core.get_market_metadata(&market);
+        // Price is in quote atoms per base unit: 102 ticks
+        let ix = core.get_ioc_ix(&market, 102_000, Side::Bid, 15).unwrap();
+        let (signature, events) = exchange
+            .process_transaction(&core.trader, &[ix], &core.markets.load())
+            .unwrap();
+
+        let fills = events
+            .iter()
+            .filter_map(|event| match event.details {
+                MarketEventDetails::Fill(fill) => Some(fill),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(fills.len(), 2);
+        assert_eq!(
+            (fills[0].price_in_ticks, fills[0].base_lots_filled),
+            (101, 10)
+        );
+        assert_eq!(
+            (fills[1].price_in_ticks, fills[1].base_lots_filled),
+            (102, 5)
+        );
+        assert!(fills.iter().all(|fill| fill.side_filled == Side::Ask
+            && fill.taker_side == Side::Bid
+            && fill.trade_direction() == 1));
+
+        let quote_atoms = 10 * 101 + 5 * 102;
+        let fee_atoms = (quote_atoms * 10_u64).div_ceil(10_000);
+        match events.last().unwrap().details {
+            MarketEventDetails::FillSummary(summary) => {
+                assert_eq!(summary.total_base_filled, 15_000);
+                assert_eq!(
+                    summary.total_quote_filled_including_fees,
+                    quote_atoms + fee_atoms
+                );
+                assert_eq!(summary.total_quote_fees, fee_atoms);
+                assert_eq!(summary.trade_direction, 1);
+            }
+            _ => panic!("Expected a fill summary"),
+        }
+        assert_eq!(exchange.balance(&meta.base_mint), 15_000);
+        assert_eq!(
+            exchange.balance(&meta.quote_mint),
+            -((quote_atoms + fee_atoms) as i128)
+        );
+        assert_eq!(exchange.get_transaction_events(&signature).unwrap(), events);
+
+        // The consumed liquidity is gone from the snapshot
+        let ix = core.get_ioc_ix(&market, 102_000, Side::Bid, 15).unwrap();
+        let (_, events) = exchange
+            .process_transaction(&core.trader, &[ix], &core.markets.load())
+            .unwrap();
+        assert!(matches!(
+            events[0].details,
+            MarketEventDetails::Fill(Fill {
+                base_lots_filled: 5,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_ioc_sell_hits_bids() {
+        let (core, market, mut exchange) = setup();
+        let ix = core.get_ioc_ix(&market, 99_000, Side::Ask, 4).unwrap();
+        let (_, events) = exchange
+            .process_transaction(&core.trader, &[ix], &core.markets.load())
+            .unwrap();
+        match events[0].details {
+            MarketEventDetails::Fill(fill) => {
+                assert_eq!((fill.price_in_ticks, fill.base_lots_filled), (99, 4));
+                assert_eq!(fill.side_filled, Side::Bid);
+                assert_eq!(fill.maker_side, Side::Bid);
+                assert_eq!(fill.taker_side, Side::Ask);
+                assert_eq!(fill.trade_direction(), -1);
+            }
+            _ => panic!("Expected a fill"),
+        }
+        // Sellers receive the matched quote less the fee, as on chain
+        let quote_atoms = 4 * 99;
+        let fee_atoms = (quote_atoms * 10_u64).div_ceil(10_000);
+        match events.last().unwrap().details {
+            MarketEventDetails::FillSummary(summary) => {
+                assert_eq!(
+                    summary.total_quote_filled_including_fees,
+                    quote_atoms - fee_atoms
+                );
+                assert_eq!(summary.total_quote_fees, fee_atoms);
+                assert_eq!(summary.trade_direction, -1);
+            }
+            _ => panic!("Expected a fill summary"),
+        }
+    }
+
+    #[test]
+    fn test_post_only_rests_and_cancels() {
+        let (core, market, mut exchange) = setup();
+        let meta = core.get_market_metadata(&market);
+        let ix = core
+            .get_post_only_ix(&market, 100_000, Side::Bid, 4)
+            .unwrap();
+        let (_, events) = exchange
+            .process_transaction(&core.trader, &[ix], &core.markets.load())
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0].details,
+            MarketEventDetails::Place(Place {
+                price_in_ticks: 100,
+                base_lots_placed: 4,
+                ..
+            })
+        ));
+        assert_eq!(exchange.open_orders(&market).len(), 1);
+        assert_eq!(exchange.balance(&meta.quote_mint), -400);
+
+        let ix = core.get_cancel_all_ix(&market).unwrap();
+        let (_, events) = exchange
+            .process_transaction(&core.trader, &[ix], &core.markets.load())
+            .unwrap();
+        assert!(matches!(
+            events[0].details,
+            MarketEventDetails::Reduce(Reduce {
+                base_lots_removed: 4,
+                is_full_cancel: true,
+                ..
+            })
+        ));
+        assert!(exchange.open_orders(&market).is_empty());
+        assert_eq!(exchange.balance(&meta.quote_mint), 0);
+    }
+
+    #[test]
+    fn test_failed_transaction_is_atomic() {
+        let (core, market, mut exchange) = setup();
+        let place = core
+            .get_post_only_ix(&market, 100_000, Side::Bid, 4)
+            .unwrap();
+        // A crossing post-only order is rejected, which fails the whole transaction
+        let crossing = core
+            .get_post_only_generic_ix(
+                &market,
+                101_000,
+                Side::Bid,
+                4,
+                None,
+                Some(true),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        assert!(exchange
+            .process_transaction(&core.trader, &[place, crossing], &core.markets.load())
+            .is_err());
+        assert!(exchange.open_orders(&market).is_empty());
+
+        // Without rejection, the crossing order is amended to one tick inside the best ask
+        let amended = core
+            .get_post_only_ix(&market, 101_000, Side::Bid, 4)
+            .unwrap();
+        exchange
+            .process_transaction(&core.trader, &[amended], &core.markets.load())
+            .unwrap();
+        let open_orders = exchange.open_orders(&market);
+        assert_eq!(open_orders[0].0.price_in_ticks.as_u64(), 100);
+
+        // Fill-or-kill that cannot be filled
+        let fok = core.get_fok_buy_ix(&market, 101_000, 10_000).unwrap();
+        assert!(exchange
+            .process_transaction(&core.trader, &[fok], &core.markets.load())
+            .is_err());
+    }
+
+    #[test]
+    fn test_multi_market_ioc_reverts_together() {
+        let (core, market_a, mut exchange) = setup();
+        let meta_a = core.get_market_metadata(&market_a);
+        // A second market sharing the quote mint, with a bid above market A's best ask
+        let market_b = Pubkey::new_unique();
+        let meta_b = MarketMetadata {
+            base_mint: Pubkey::new_unique(),
+            ..meta_a
+        };
+        core.markets.insert(market_b, meta_b);
+        let mut orderbook = Orderbook {
+            raw_base_units_per_base_lot: meta_b.raw_base_units_per_base_lot_as_float(),
+            quote_units_per_raw_base_unit_per_tick: meta_b
+                .quote_units_per_raw_base_unit_per_tick_as_float(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        };
+        orderbook.bids.insert(
+            FIFOOrderId::new_from_untyped(105, !1),
+            PhoenixOrder {
+                num_base_lots: 10,
+                maker_id: Pubkey::new_unique(),
+            },
+        );
+        exchange.set_market_state(
+            &market_b,
+            &MarketState {
+                orderbook,
+                traders: BTreeMap::new(),
+            },
+        );
+
+        // Buy 10 lots on A at 101 with fees out of the budget, then sell them on B at 105
+        let legs = |min_quote_lots_out| {
+            core.get_multi_market_ioc_ixs(&[
+                IocLeg {
+                    market: market_a,
+                    side: Side::Bid,
+                    price_in_ticks: Some(101),
+                    size_lots: 1020,
+                    min_lots_out: 10,
+                },
+                IocLeg {
+                    market: market_b,
+                    side: Side::Ask,
+                    price_in_ticks: Some(105),
+                    size_lots: 10,
+                    min_lots_out: min_quote_lots_out,
+                },
+            ])
+        };
+
+        // The second leg cannot receive 1100 quote lots, so the first leg's fill is reverted
+        let ixs = legs(1100).unwrap();
+        assert_eq!(ixs.len(), 2);
+        assert!(exchange
+            .process_transaction(&core.trader, &ixs, &core.markets.load())
+            .is_err());
+        assert_eq!(exchange.balance(&meta_a.base_mint), 0);
+        assert_eq!(exchange.balance(&meta_a.quote_mint), 0);
+
+        let (_, events) = exchange
+            .process_transaction(&core.trader, &legs(1048).unwrap(), &core.markets.load())
+            .unwrap();
+        assert!(events.iter().any(|event| event.market == market_a));
+        assert!(events.iter().any(|event| event.market == market_b));
+        assert_eq!(exchange.balance(&meta_a.base_mint), 10_000);
+        assert_eq!(exchange.balance(&meta_b.base_mint), -10_000);
+        // 1010 spent plus a fee of 2, 1050 received less a fee of 2
+        assert_eq!(exchange.balance(&meta_a.quote_mint), 1048 - 1012);
+
+        // Every market must be loaded before any leg is built
+        let unknown = IocLeg {
+            market: Pubkey::new_unique(),
+            side: Side::Ask,
+            price_in_ticks: None,
+            size_lots: 10,
+            min_lots_out: 0,
+        };
+        assert!(core.get_multi_market_ioc_ixs(&[unknown]).is_err());
+        assert!(core.get_multi_market_ioc_ixs(&[]).is_err());
+    }
+
+    #[test]
+    fn test_cancel_by_id_with_orders_on_both_sides() {
+        let (core, market, mut exchange) = setup();
+        let bid = core
+            .get_post_only_ix(&market, 95_000, Side::Bid, 4)
+            .unwrap();
+        let ask = core
+            .get_post_only_ix(&market, 110_000, Side::Ask, 4)
+            .unwrap();
+        exchange
+            .process_transaction(&core.trader, &[bid, ask], &core.markets.load())
+            .unwrap();
+        let ids = exchange
+            .open_orders(&market)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids.len(), 2);
+
+        for id in ids {
+            let cancel = core.get_cancel_ids_ix(&market, vec![id]).unwrap();
+            let (_, events) = exchange
+                .process_transaction(&core.trader, &[cancel], &core.markets.load())
+                .unwrap();
+            assert_eq!(events.len(), 1);
+        }
+        assert!(exchange.open_orders(&market).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_refresh_keeps_the_snapshot() {
+        let (sdk, market) = super::test_utils::paper_client(&[(Side::Ask, 105, 100)]).await;
+        // Nothing listens on the client's RPC address
+        assert!(sdk.refresh_paper_market_state(&market).await.is_err());
+
+        let ix = sdk.get_ioc_ix(&market, 105_000, Side::Bid, 10).unwrap();
+        let (_, events) = sdk
+            .paper_exchange()
+            .process_transaction(&sdk.trader, &[ix], &sdk.markets.load())
+            .unwrap();
+        assert!(events
+            .iter()
+            .any(|event| matches!(event.details, MarketEventDetails::Fill(_))));
+    }
+}
+
+/// Helpers for tests elsewhere in the crate that need an `SDKClient` trading against a paper book.
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::*;
+    use crate::sdk_client::SDKClient;
+    use ellipsis_client::EllipsisClient;
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_sdk::signature::Keypair;
+
+    pub(crate) use phoenix_sdk_core::test_utils::test_market_metadata;
+
+    /// Returns a paper-mode client with a single market whose book has the given
+    /// `(side, price_in_ticks, num_base_lots)` levels, each resting from a distinct maker.
+    /// No RPC calls are made.
+    pub(crate) async fn paper_client(levels: &[(Side, u64, u64)]) -> (SDKClient, Pubkey) {
+        let market = Pubkey::new_unique();
+        let meta = test_market_metadata();
+        let mut orderbook = Orderbook {
+            raw_base_units_per_base_lot: meta.raw_base_units_per_base_lot_as_float(),
+            quote_units_per_raw_base_unit_per_tick: meta
+                .quote_units_per_raw_base_unit_per_tick_as_float(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        };
+        for (sequence_number, &(side, price_in_ticks, num_base_lots)) in levels.iter().enumerate() {
+            let order = PhoenixOrder {
+                num_base_lots,
+                maker_id: Pubkey::new_unique(),
+            };
+            let sequence_number = sequence_number as u64 + 1;
+            match side {
+                Side::Bid => orderbook.bids.insert(
+                    FIFOOrderId::new_from_untyped(price_in_ticks, !sequence_number),
+                    order,
+                ),
+                Side::Ask => orderbook.asks.insert(
+                    FIFOOrderId::new_from_untyped(price_in_ticks, sequence_number),
+                    order,
+                ),
+            };
+        }
+
+        let client = EllipsisClient::from_rpc(
+            RpcClient::new("http://127.0.0.1:8899".to_string()),
+            &Keypair::new(),
+        )
+        .unwrap();
+        let mut sdk = SDKClient::new_from_ellipsis_client(client).await.unwrap();
+        sdk.markets.insert(market, meta);
+        sdk.set_trading_mode(TradingMode::Paper);
+        sdk.paper_exchange().set_market_state(
+            &market,
+            &MarketState {
+                orderbook,
+                traders: BTreeMap::new(),
+            },
+        );
+        (sdk, market)
+    }
+}
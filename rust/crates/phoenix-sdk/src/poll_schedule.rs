@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollMode {
+    /// Waits `interval` between every poll.
+    Fixed,
+    /// Polls again immediately after finding something, up to `max_burst` times in a row, and
+    /// backs off geometrically from `interval` to `max_interval` while polls come back empty.
+    Adaptive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PollScheduleConfig {
+    pub mode: PollMode,
+    /// The wait of the fixed mode, and the shortest wait of the adaptive mode.
+    pub interval: Duration,
+    pub max_interval: Duration,
+    /// Each empty poll multiplies the next wait by this much.
+    pub backoff_factor: f64,
+    /// Immediate re-polls allowed after polls that found something, before waiting `interval`.
+    pub max_burst: u32,
+    /// Waits are scaled by a random factor within `1 ± jitter`, so pollers started together
+    /// drift apart instead of hitting the RPC at the same moment. Not applied in fixed mode.
+    pub jitter: f64,
+}
+
+impl PollScheduleConfig {
+    pub fn fixed(interval: Duration) -> Self {
+        Self {
+            mode: PollMode::Fixed,
+            interval,
+            ..Default::default()
+        }
+    }
+
+    pub fn adaptive(interval: Duration, max_interval: Duration) -> Self {
+        Self {
+            mode: PollMode::Adaptive,
+            interval,
+            max_interval,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for PollScheduleConfig {
+    fn default() -> Self {
+        Self {
+            mode: PollMode::Fixed,
+            interval: Duration::from_millis(1000),
+            max_interval: Duration::from_secs(10),
+            backoff_factor: 2.0,
+            max_burst: 5,
+            jitter: 0.15,
+        }
+    }
+}
+
+/// Decides how long a polling loop waits before its next poll, given whether the last one found
+/// anything new.
+///
+/// ```
+/// use std::time::Duration;
+/// use phoenix_sdk::poll_schedule::{PollSchedule, PollScheduleConfig};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let config = PollScheduleConfig::adaptive(Duration::from_millis(1), Duration::from_millis(8));
+/// let mut schedule = PollSchedule::new(config);
+/// for found_new in [true, false] {
+///     // ... poll for new signatures ...
+///     schedule.wait(found_new).await;
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PollSchedule {
+    config: PollScheduleConfig,
+    /// The wait after the next empty poll, before jitter.
+    idle_interval: Duration,
+    burst: u32,
+    rng: StdRng,
+}
+
+impl PollSchedule {
+    pub fn new(config: PollScheduleConfig) -> Self {
+        Self::with_rng(config, StdRng::from_entropy())
+    }
+
+    /// Uses `rng` for the jitter, e.g. a seeded one for reproducible waits.
+    pub fn with_rng(config: PollScheduleConfig, rng: StdRng) -> Self {
+        Self {
+            config,
+            idle_interval: config.interval,
+            burst: 0,
+            rng,
+        }
+    }
+
+    pub fn config(&self) -> &PollScheduleConfig {
+        &self.config
+    }
+
+    /// The wait before the next poll. Records the outcome of the last poll, so call it once per
+    /// poll.
+    pub fn next_delay(&mut self, found_new: bool) -> Duration {
+        let config = self.config;
+        if config.mode == PollMode::Fixed {
+            return config.interval;
+        }
+        if found_new {
+            self.idle_interval = config.interval;
+            if self.burst < config.max_burst {
+                self.burst += 1;
+                return Duration::ZERO;
+            }
+            self.burst = 0;
+            return self.jittered(config.interval);
+        }
+        self.burst = 0;
+        let delay = self.idle_interval;
+        self.idle_interval = delay
+            .mul_f64(config.backoff_factor.max(1.0))
+            .min(config.max_interval.max(config.interval));
+        self.jittered(delay)
+    }
+
+    /// Sleeps for `next_delay(found_new)`.
+    pub async fn wait(&mut self, found_new: bool) {
+        Self::sleep(self.next_delay(found_new)).await;
+    }
+
+    /// Sleeps for a delay from `next_delay`. A zero delay yields to other tasks instead.
+    pub async fn sleep(delay: Duration) {
+        if delay.is_zero() {
+            tokio::task::yield_now().await;
+        } else {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn jittered(&mut self, delay: Duration) -> Duration {
+        let jitter = self.config.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        delay.mul_f64(self.rng.gen_range(1.0 - jitter, 1.0 + jitter))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn schedule(config: PollScheduleConfig) -> PollSchedule {
+        PollSchedule::with_rng(config, StdRng::seed_from_u64(7))
+    }
+
+    #[test]
+    fn test_busy_then_quiet() {
+        let ms = Duration::from_millis;
+        let config = PollScheduleConfig {
+            max_burst: 2,
+            jitter: 0.0,
+            ..PollScheduleConfig::adaptive(ms(100), ms(1000))
+        };
+        let mut schedule = schedule(config);
+        // Four busy polls, then quiet
+        let pattern = [
+            true, true, true, true, false, false, false, false, false, false,
+        ];
+        let delays = pattern
+            .iter()
+            .map(|found_new| schedule.next_delay(*found_new).as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![0, 0, 100, 0, 100, 200, 400, 800, 1000, 1000]);
+
+        // Activity resets the backoff
+        assert_eq!(schedule.next_delay(true), Duration::ZERO);
+        assert_eq!(schedule.next_delay(false), ms(100));
+
+        let mut fixed = PollSchedule::new(PollScheduleConfig::fixed(ms(250)));
+        for found_new in pattern {
+            assert_eq!(fixed.next_delay(found_new), ms(250));
+        }
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        let ms = Duration::from_millis;
+        let mut schedule = schedule(PollScheduleConfig {
+            max_burst: 0,
+            ..PollScheduleConfig::adaptive(ms(1000), ms(1000))
+        });
+        let delays = (0..200)
+            .map(|i| schedule.next_delay(i % 2 == 0))
+            .collect::<Vec<_>>();
+        assert!(delays
+            .iter()
+            .all(|delay| *delay >= ms(850) && *delay <= ms(1150)));
+        // Waits vary from poll to poll
+        assert!(delays.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+}
@@ -0,0 +1,521 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent};
+use phoenix_sdk_core::order_manager::OrderManager;
+use phoenix_sdk_core::position_tracker::PositionTracker;
+use phoenix_sdk_core::sdk_client_core::MarketMetadata;
+use serde::{Serialize, Serializer};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::mpsc;
+
+use crate::event_recorder::now_ms;
+use crate::health::serialize_display;
+use crate::top_of_book::TopOfBookFeed;
+
+/// Where a mark price came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkSource {
+    /// The middle of a fresh best bid and ask.
+    Mid,
+    /// The price of the market's last fill.
+    LastTrade,
+}
+
+impl fmt::Display for MarkSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarkSource::Mid => write!(f, "mid"),
+            MarkSource::LastTrade => write!(f, "last"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mark {
+    pub price_in_ticks: u64,
+    pub source: MarkSource,
+}
+
+struct MarketMarks {
+    metadata: MarketMetadata,
+    feed: Option<Arc<TopOfBookFeed>>,
+    last_trade_in_ticks: Option<u64>,
+}
+
+/// Mark prices for `PortfolioSnapshot`, per market.
+///
+/// A market is marked at the mid of its `TopOfBookFeed` while the feed has seen a book within
+/// `max_book_age` and both sides are quoted. Otherwise it falls back to the price of the last
+/// fill passed to `on_event`, and is left unmarked if there was none.
+pub struct PortfolioMarks {
+    markets: BTreeMap<Pubkey, MarketMarks>,
+    max_book_age: Duration,
+}
+
+impl Default for PortfolioMarks {
+    fn default() -> Self {
+        Self {
+            markets: BTreeMap::new(),
+            max_book_age: Duration::from_secs(10),
+        }
+    }
+}
+
+impl PortfolioMarks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks `market` at its last trade only.
+    pub fn with_market(mut self, market: Pubkey, metadata: MarketMetadata) -> Self {
+        self.markets
+            .entry(market)
+            .and_modify(|marks| marks.metadata = metadata)
+            .or_insert(MarketMarks {
+                metadata,
+                feed: None,
+                last_trade_in_ticks: None,
+            });
+        self
+    }
+
+    /// Marks the feed's market at its mid while the feed is fresh.
+    pub fn with_top_of_book(mut self, feed: Arc<TopOfBookFeed>) -> Self {
+        let market = feed.market();
+        self = self.with_market(market, *feed.metadata());
+        if let Some(marks) = self.markets.get_mut(&market) {
+            marks.feed = Some(feed);
+        }
+        self
+    }
+
+    /// How long ago a feed must have seen a book for its mid to be used, 10 seconds by default.
+    pub fn with_max_book_age(mut self, max_book_age: Duration) -> Self {
+        self.max_book_age = max_book_age;
+        self
+    }
+
+    /// Records the price of a fill on a registered market. Other events are ignored.
+    pub fn on_event(&mut self, event: &PhoenixEvent) {
+        let MarketEventDetails::Fill(fill) = &event.details else {
+            return;
+        };
+        if let Some(marks) = self.markets.get_mut(&event.market) {
+            marks.last_trade_in_ticks = Some(fill.price_in_ticks);
+        }
+    }
+
+    pub fn metadata(&self, market: &Pubkey) -> Option<&MarketMetadata> {
+        self.markets.get(market).map(|marks| &marks.metadata)
+    }
+
+    pub fn mark(&self, market: &Pubkey) -> Option<Mark> {
+        let marks = self.markets.get(market)?;
+        let mid = marks
+            .feed
+            .as_ref()
+            .filter(|feed| {
+                feed.last_book_age()
+                    .is_some_and(|age| age <= self.max_book_age)
+            })
+            .and_then(|feed| feed.latest())
+            .and_then(|tob| Some((tob.best_bid_in_ticks?, tob.best_ask_in_ticks?)))
+            .map(|(bid, ask)| Mark {
+                price_in_ticks: (bid + ask) / 2,
+                source: MarkSource::Mid,
+            });
+        mid.or(marks.last_trade_in_ticks.map(|price_in_ticks| Mark {
+            price_in_ticks,
+            source: MarkSource::LastTrade,
+        }))
+    }
+}
+
+/// A market's position and open orders. Sizes are in raw base units and amounts in quote units.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MarketExposure {
+    #[serde(serialize_with = "serialize_display")]
+    pub market: Pubkey,
+    /// Signed net position, positive when long.
+    pub position: f64,
+    pub base_lots: i64,
+    pub mark_price: Option<f64>,
+    pub mark_price_in_ticks: Option<u64>,
+    pub mark_source: Option<MarkSource>,
+    /// Net of fees.
+    pub realized_pnl: f64,
+    /// `None` with an open position and no mark.
+    pub unrealized_pnl: Option<f64>,
+    pub fees_paid: f64,
+    pub open_orders: usize,
+    /// The position at the mark, or at its cost without one, plus the notional of the open
+    /// orders at their prices. Infinite if a notional does not fit in `u64` quote atoms.
+    pub notional_at_risk: f64,
+}
+
+impl fmt::Display for MarketExposure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} pos {}", self.market, self.position)?;
+        match (self.mark_price, self.mark_source) {
+            (Some(price), Some(source)) => write!(f, " mark {} ({})", price, source)?,
+            _ => write!(f, " mark none")?,
+        }
+        match self.unrealized_pnl {
+            Some(unrealized_pnl) => write!(f, " upnl {:.4}", unrealized_pnl)?,
+            None => write!(f, " upnl n/a")?,
+        }
+        write!(
+            f,
+            " rpnl {:.4} fees {:.4} orders {} at risk {:.4}",
+            self.realized_pnl, self.fees_paid, self.open_orders, self.notional_at_risk
+        )
+    }
+}
+
+/// A trader's exposure on every market with a position or open orders, for a periodic log line
+/// or a dashboard. Amounts are in each market's own quote units and are not summed across
+/// markets, which may quote in different mints; see `PositionTracker::portfolio_summary` for
+/// totals.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PortfolioSnapshot {
+    pub timestamp_ms: u64,
+    pub markets: Vec<MarketExposure>,
+    /// Markets with a position or open orders but no metadata in the `PortfolioMarks`, which
+    /// cannot be valued.
+    #[serde(serialize_with = "serialize_pubkeys")]
+    pub unregistered_markets: Vec<Pubkey>,
+}
+
+impl PortfolioSnapshot {
+    /// Values the trader's positions and open orders at the current marks. Markets the trader
+    /// only ever had orders on, and no longer does, are left out.
+    pub fn collect(
+        position_tracker: &PositionTracker,
+        order_manager: &OrderManager,
+        marks: &PortfolioMarks,
+    ) -> Self {
+        let mut snapshot = Self {
+            timestamp_ms: now_ms(),
+            markets: vec![],
+            unregistered_markets: vec![],
+        };
+        let markets = position_tracker
+            .positions()
+            .keys()
+            .chain(order_manager.markets())
+            .copied()
+            .collect::<BTreeSet<_>>();
+        for market in markets {
+            let Some(meta) = marks.metadata(&market) else {
+                snapshot.unregistered_markets.push(market);
+                continue;
+            };
+            let position = position_tracker.position(&market);
+            let mark = marks.mark(&market);
+            let quote_units =
+                |quote_atoms: i128| quote_atoms as f64 / meta.quote_atoms_per_quote_unit as f64;
+            let unrealized_pnl = match mark {
+                Some(mark) => Some(position.unrealized_pnl(mark.price_in_ticks, meta)),
+                None if position.base_lots == 0 => Some(0),
+                None => None,
+            };
+            let position_notional = match mark {
+                Some(mark) => meta
+                    .checked_base_lots_and_price_to_quote_atoms(
+                        position.base_lots.unsigned_abs(),
+                        mark.price_in_ticks,
+                    )
+                    .map(i128::from),
+                None => Some(position.cost_basis_quote_atoms.abs()),
+            };
+            let (open_orders, orders_notional) = order_manager.open_orders(&market).fold(
+                (0, Some(0)),
+                |(count, notional), order| {
+                    let order_notional = meta.checked_base_lots_and_price_to_quote_atoms(
+                        order.num_base_lots,
+                        order.price_in_ticks(),
+                    );
+                    let notional = notional
+                        .zip(order_notional)
+                        .map(|(notional, order_notional)| notional + order_notional as i128);
+                    (count + 1, notional)
+                },
+            );
+            snapshot.markets.push(MarketExposure {
+                market,
+                position: position.base_lots as f64 * meta.raw_base_units_per_base_lot_as_float(),
+                base_lots: position.base_lots,
+                mark_price: mark.map(|mark| meta.ticks_to_float_price(mark.price_in_ticks)),
+                mark_price_in_ticks: mark.map(|mark| mark.price_in_ticks),
+                mark_source: mark.map(|mark| mark.source),
+                realized_pnl: quote_units(position.realized_pnl_quote_atoms),
+                unrealized_pnl: unrealized_pnl.map(quote_units),
+                fees_paid: quote_units(position.fees_paid_quote_atoms as i128),
+                open_orders,
+                notional_at_risk: position_notional
+                    .zip(orders_notional)
+                    .map_or(f64::INFINITY, |(position, orders)| {
+                        quote_units(position + orders)
+                    }),
+            });
+        }
+        snapshot
+    }
+}
+
+impl fmt::Display for PortfolioSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.markets.is_empty() && self.unregistered_markets.is_empty() {
+            return write!(f, "no positions or open orders");
+        }
+        for (i, exposure) in self.markets.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", exposure)?;
+        }
+        if !self.unregistered_markets.is_empty() {
+            if !self.markets.is_empty() {
+                write!(f, "; ")?;
+            }
+            write!(f, "no metadata for")?;
+            for market in &self.unregistered_markets {
+                write!(f, " {}", market)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn serialize_pubkeys<S: Serializer>(pubkeys: &[Pubkey], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(pubkeys.iter().map(|pubkey| pubkey.to_string()))
+}
+
+/// Emits a `PortfolioSnapshot` every `interval`, as a log line and/or over a channel.
+///
+/// Lines are logged at info level with the `tracing` feature, and printed otherwise.
+pub struct PortfolioReporter {
+    pub interval: Duration,
+    log: bool,
+    sender: Option<mpsc::UnboundedSender<PortfolioSnapshot>>,
+}
+
+impl PortfolioReporter {
+    /// A reporter that logs every snapshot.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            log: true,
+            sender: None,
+        }
+    }
+
+    /// Also sends every snapshot to `sender`.
+    pub fn with_sender(mut self, sender: mpsc::UnboundedSender<PortfolioSnapshot>) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    /// Whether snapshots are logged, on by default.
+    pub fn with_logging(mut self, log: bool) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Logs and sends `snapshot`. Returns false once the receiver of the channel is dropped.
+    pub fn report(&self, snapshot: &PortfolioSnapshot) -> bool {
+        if self.log {
+            #[cfg(feature = "tracing")]
+            tracing::info!(target: "phoenix_sdk::portfolio", "{}", snapshot);
+            #[cfg(not(feature = "tracing"))]
+            println!("Portfolio: {}", snapshot);
+        }
+        match &self.sender {
+            Some(sender) => sender.send(snapshot.clone()).is_ok(),
+            None => true,
+        }
+    }
+
+    /// Reports a snapshot from `collect` every `interval`, starting right away, until the
+    /// receiver of the channel is dropped. Without a channel it runs until the task is aborted.
+    ///
+    /// `collect` typically locks wherever the trackers are kept, e.g.
+    /// `|| PortfolioSnapshot::collect(&risk.positions, &risk.orders, &marks)`.
+    pub async fn run(&self, mut collect: impl FnMut() -> PortfolioSnapshot) {
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            ticker.tick().await;
+            if !self.report(&collect()) {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix::state::enums::Side;
+    use phoenix::state::markets::FIFOOrderId;
+    use phoenix_sdk_core::market_event::{Fill, Place};
+    use phoenix_sdk_core::orderbook::Orderbook;
+    use phoenix_sdk_core::sdk_client_core::PhoenixOrder;
+    use phoenix_sdk_core::test_utils::event;
+
+    use super::*;
+    use crate::paper_trading::test_utils::test_market_metadata;
+    use crate::top_of_book::TobChangeFilter;
+
+    fn book(bid: Option<u64>, ask: Option<u64>) -> Orderbook<FIFOOrderId, PhoenixOrder> {
+        let mut orderbook = Orderbook::<FIFOOrderId, PhoenixOrder>::default();
+        let order = PhoenixOrder {
+            num_base_lots: 100,
+            maker_id: Pubkey::default(),
+        };
+        if let Some(bid) = bid {
+            orderbook
+                .bids
+                .insert(FIFOOrderId::new_from_untyped(bid, !1), order);
+        }
+        if let Some(ask) = ask {
+            orderbook
+                .asks
+                .insert(FIFOOrderId::new_from_untyped(ask, 1), order);
+        }
+        orderbook
+    }
+
+    /// A 2 unit long bought at 100 ticks with a fee of 100 quote atoms, and a bid for 0.5 units
+    /// at 90 ticks.
+    fn trackers(trader: Pubkey, market: Pubkey) -> (PositionTracker, OrderManager) {
+        let mut positions = PositionTracker::new(trader);
+        positions.apply_trade(&market, Side::Bid, 2_000, 200_000, 100);
+        let mut orders = OrderManager::new(trader);
+        orders.apply_event(&event(
+            market,
+            MarketEventDetails::Place(Place {
+                order_sequence_number: !7,
+                client_order_id: 7,
+                maker: trader,
+                price_in_ticks: 90,
+                base_lots_placed: 500,
+            }),
+        ));
+        (positions, orders)
+    }
+
+    #[test]
+    fn test_collect_marks_at_mid() {
+        let (trader, market) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (positions, orders) = trackers(trader, market);
+        let feed = Arc::new(TopOfBookFeed::new(
+            market,
+            test_market_metadata(),
+            TobChangeFilter::default(),
+        ));
+        feed.on_book(&book(Some(110), Some(120)), 1);
+        let marks = PortfolioMarks::new().with_top_of_book(feed);
+
+        let snapshot = PortfolioSnapshot::collect(&positions, &orders, &marks);
+        assert!(snapshot.unregistered_markets.is_empty());
+        let [exposure] = snapshot.markets.as_slice() else {
+            panic!("expected one market, got {:?}", snapshot.markets);
+        };
+        assert_eq!(exposure.position, 2.0);
+        assert_eq!(exposure.mark_price_in_ticks, Some(115));
+        assert_eq!(exposure.mark_source, Some(MarkSource::Mid));
+        // Worth 230_000 quote atoms against a cost of 200_000
+        assert_eq!(exposure.unrealized_pnl, Some(0.03));
+        assert_eq!(exposure.realized_pnl, -0.0001);
+        assert_eq!(exposure.fees_paid, 0.0001);
+        assert_eq!(exposure.open_orders, 1);
+        // The position at the mark plus 500 lots at 90 ticks
+        assert_eq!(exposure.notional_at_risk, 0.275);
+
+        assert_eq!(
+            snapshot.to_string(),
+            format!(
+                "{} pos 2 mark 0.115 (mid) upnl 0.0300 rpnl -0.0001 fees 0.0001 orders 1 \
+                 at risk 0.2750",
+                market
+            )
+        );
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["markets"][0]["market"], market.to_string());
+        assert_eq!(json["markets"][0]["mark_source"], "mid");
+        assert_eq!(json["unregistered_markets"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_stale_book_falls_back_to_last_trade() {
+        let (trader, market) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (positions, orders) = trackers(trader, market);
+        let feed = Arc::new(TopOfBookFeed::new(
+            market,
+            test_market_metadata(),
+            TobChangeFilter::default(),
+        ));
+        let mut marks = PortfolioMarks::new()
+            .with_top_of_book(feed.clone())
+            .with_max_book_age(Duration::from_millis(1));
+
+        // No book and no trade yet
+        let snapshot = PortfolioSnapshot::collect(&positions, &orders, &marks);
+        assert_eq!(snapshot.markets[0].unrealized_pnl, None);
+        // Valued at cost without a mark
+        assert_eq!(snapshot.markets[0].notional_at_risk, 0.245);
+        assert!(snapshot.to_string().contains("mark none upnl n/a"));
+
+        marks.on_event(&event(
+            market,
+            MarketEventDetails::Fill(Fill::new(!3, Pubkey::new_unique(), trader, 105, 10, 0)),
+        ));
+        // A one-sided book has no mid
+        feed.on_book(&book(Some(110), None), 2);
+        assert_eq!(
+            marks.mark(&market),
+            Some(Mark {
+                price_in_ticks: 105,
+                source: MarkSource::LastTrade
+            })
+        );
+
+        feed.on_book(&book(Some(110), Some(120)), 3);
+        std::thread::sleep(Duration::from_millis(5));
+        let snapshot = PortfolioSnapshot::collect(&positions, &orders, &marks);
+        assert_eq!(snapshot.markets[0].mark_source, Some(MarkSource::LastTrade));
+        assert_eq!(snapshot.markets[0].unrealized_pnl, Some(0.01));
+    }
+
+    #[tokio::test]
+    async fn test_reporter_sends_until_closed() {
+        let (trader, market) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (positions, orders) = trackers(trader, market);
+        let marks = PortfolioMarks::new();
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let reporter = PortfolioReporter::new(Duration::from_millis(1))
+            .with_sender(sender)
+            .with_logging(false);
+        let collected = std::sync::atomic::AtomicUsize::new(0);
+        let run = reporter.run(|| {
+            collected.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            PortfolioSnapshot::collect(&positions, &orders, &marks)
+        });
+        let consume = async {
+            for _ in 0..3 {
+                let snapshot = receiver.recv().await.unwrap();
+                assert_eq!(snapshot.unregistered_markets, vec![market]);
+                assert_eq!(snapshot.to_string(), format!("no metadata for {}", market));
+            }
+            drop(receiver);
+        };
+        tokio::time::timeout(Duration::from_secs(5), futures::future::join(run, consume))
+            .await
+            .unwrap();
+        assert!(collected.load(std::sync::atomic::Ordering::Relaxed) >= 3);
+    }
+}
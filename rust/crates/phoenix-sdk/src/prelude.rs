@@ -0,0 +1,26 @@
+//! The types most trading apps need, from this crate, `phoenix-sdk-core`, the Phoenix program
+//! and the Solana SDK, so that one `use phoenix_sdk::prelude::*;` covers them.
+
+pub use phoenix::quantities::WrapperU64;
+pub use phoenix::state::enums::Side;
+pub use phoenix_sdk_core::market_event::{
+    Evict, Fill, FillSummary, MarketEventDetails, PhoenixEvent, Place, Reduce,
+};
+pub use phoenix_sdk_core::order_manager::{OpenOrder, OrderManager};
+pub use phoenix_sdk_core::position_tracker::{Position, PositionTracker};
+pub use phoenix_sdk_core::sdk_client_core::{
+    MarketMetadata, MarketState, PhoenixOrder, SDKClientCore,
+};
+pub use solana_sdk::pubkey::Pubkey;
+pub use solana_sdk::signature::{Keypair, Signature, Signer};
+
+pub use crate::event_poller::EventPoller;
+pub use crate::event_router::{Backpressure, EventRouter, RoutedReceiver};
+pub use crate::paper_trading::TradingMode;
+pub use crate::poll_schedule::PollScheduleConfig;
+pub use crate::risk_manager::{RiskConfig, RiskManager};
+pub use crate::runtime::PhoenixRuntime;
+pub use crate::sdk_client::SDKClient;
+pub use crate::sdk_market_event::{ControlMsg, SDKMarketEvent};
+pub use crate::task_supervisor::{TaskOptions, TaskSupervisor};
+pub use crate::trading_stack::{TradingStack, TradingStackBuilder};
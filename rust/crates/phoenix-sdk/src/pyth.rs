@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use phoenix_sdk_core::reference_price::{
+    ReferencePrice, ReferencePriceSource, SharedReferencePrices,
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+const MAGIC: u32 = 0xa1b2c3d4;
+const VERSION: u32 = 2;
+const PRICE_ACCOUNT_TYPE: u32 = 3;
+const STATUS_TRADING: u32 = 1;
+
+// Offsets into a Pyth v2 price account
+const EXPONENT_OFFSET: usize = 20;
+const TIMESTAMP_OFFSET: usize = 96;
+const AGGREGATE_PRICE_OFFSET: usize = 208;
+const AGGREGATE_CONFIDENCE_OFFSET: usize = 216;
+const AGGREGATE_STATUS_OFFSET: usize = 224;
+const MIN_ACCOUNT_LEN: usize = 240;
+
+/// Reads reference prices from Pyth price accounts, one per Phoenix market.
+///
+/// Prices are only as fresh as the last `refresh` or `update`; clones share them, so one clone
+/// can be refreshed on a timer while another is handed to a `ReferencePriceFeed`. The Pyth
+/// feed must be quoted in the market's own units, e.g. SOL/USD for SOL/USDC, since no
+/// conversion is applied. The aggregate price is used, and only while its status is trading.
+#[derive(Debug, Clone, Default)]
+pub struct PythPriceSource {
+    price_accounts: BTreeMap<Pubkey, Pubkey>,
+    prices: SharedReferencePrices,
+}
+
+impl PythPriceSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads `market`'s reference price from the Pyth price account `price_account`.
+    pub fn with_feed(mut self, market: Pubkey, price_account: Pubkey) -> Self {
+        self.price_accounts.insert(market, price_account);
+        self
+    }
+
+    /// Fetches every configured price account in one request and updates the prices. Accounts
+    /// that are missing or cannot be used leave the previous price in place, to go stale.
+    pub async fn refresh(&self, client: &RpcClient) -> Result<()> {
+        let (markets, price_accounts): (Vec<Pubkey>, Vec<Pubkey>) =
+            self.price_accounts.iter().unzip();
+        let accounts = client.get_multiple_accounts(&price_accounts).await?;
+        let mut errors = vec![];
+        for (market, account) in markets.iter().zip(accounts) {
+            let result = account
+                .ok_or_else(|| anyhow!("Pyth price account for market {} not found", market))
+                .and_then(|account| self.update(market, &account.data));
+            if let Err(error) = result {
+                errors.push(error.to_string());
+            }
+        }
+        if !errors.is_empty() {
+            bail!("Failed to refresh Pyth prices: {}", errors.join("; "));
+        }
+        Ok(())
+    }
+
+    /// Updates `market`'s price from the data of its price account, e.g. from an account
+    /// subscription.
+    pub fn update(&self, market: &Pubkey, data: &[u8]) -> Result<()> {
+        self.prices.set(market, parse_price_account(data)?);
+        Ok(())
+    }
+}
+
+impl ReferencePriceSource for PythPriceSource {
+    fn price(&self, market: &Pubkey) -> Option<ReferencePrice> {
+        self.prices.price(market)
+    }
+}
+
+/// Decodes the aggregate price of a Pyth v2 price account.
+pub fn parse_price_account(data: &[u8]) -> Result<ReferencePrice> {
+    if data.len() < MIN_ACCOUNT_LEN {
+        bail!("Pyth price account is {} bytes, too short", data.len());
+    }
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let read_u64 = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    if read_u32(0) != MAGIC || read_u32(4) != VERSION || read_u32(8) != PRICE_ACCOUNT_TYPE {
+        bail!("Account is not a Pyth v2 price account");
+    }
+    let status = read_u32(AGGREGATE_STATUS_OFFSET);
+    if status != STATUS_TRADING {
+        bail!("Pyth price is not trading (status {})", status);
+    }
+    let exponent = read_u32(EXPONENT_OFFSET) as i32;
+    let scale = 10_f64.powi(exponent);
+    let timestamp = read_u64(TIMESTAMP_OFFSET) as i64;
+    Ok(ReferencePrice {
+        price: read_u64(AGGREGATE_PRICE_OFFSET) as i64 as f64 * scale,
+        confidence: read_u64(AGGREGATE_CONFIDENCE_OFFSET) as f64 * scale,
+        timestamp_ms: timestamp.max(0) as u64 * 1000,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn price_account(price: i64, confidence: u64, exponent: i32, status: u32) -> Vec<u8> {
+        let mut data = vec![0; 3312];
+        let mut write = |offset: usize, bytes: &[u8]| {
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        write(0, &MAGIC.to_le_bytes());
+        write(4, &VERSION.to_le_bytes());
+        write(8, &PRICE_ACCOUNT_TYPE.to_le_bytes());
+        write(EXPONENT_OFFSET, &exponent.to_le_bytes());
+        write(TIMESTAMP_OFFSET, &1_700_000_000_i64.to_le_bytes());
+        write(AGGREGATE_PRICE_OFFSET, &price.to_le_bytes());
+        write(AGGREGATE_CONFIDENCE_OFFSET, &confidence.to_le_bytes());
+        write(AGGREGATE_STATUS_OFFSET, &status.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_parse_price_account() {
+        let price = parse_price_account(&price_account(2_512_345_678, 1_500_000, -8, 1)).unwrap();
+        assert!((price.price - 25.12345678).abs() < 1e-9);
+        assert!((price.confidence - 0.015).abs() < 1e-12);
+        assert_eq!(price.timestamp_ms, 1_700_000_000_000);
+
+        // Halted prices and other accounts are rejected
+        assert!(parse_price_account(&price_account(2_512_345_678, 0, -8, 2)).is_err());
+        assert!(parse_price_account(&[0; 100]).is_err());
+        let mut product_account = price_account(1, 0, -8, 1);
+        product_account[8] = 2;
+        assert!(parse_price_account(&product_account).is_err());
+
+        let market = Pubkey::new_unique();
+        let source = PythPriceSource::new().with_feed(market, Pubkey::new_unique());
+        let shared = source.clone();
+        source
+            .update(&market, &price_account(100, 1, -2, 1))
+            .unwrap();
+        assert_eq!(shared.price(&market).unwrap().price, 1.0);
+    }
+}
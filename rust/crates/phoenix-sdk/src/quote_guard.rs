@@ -0,0 +1,449 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use phoenix::program::status::SeatApprovalStatus;
+use phoenix::state::markets::FIFOOrderId;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::paper_trading::TradingMode;
+use crate::rate_limiter::RpcCategory;
+use crate::sdk_client::SDKClient;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuoteGuardConfig {
+    /// Markets whose orders are cancelled when the guard trips.
+    pub markets: Vec<Pubkey>,
+    /// The guard trips if `heartbeat` is not called for this long.
+    pub timeout: Duration,
+    /// Also withdraw free funds from the markets after cancelling.
+    pub withdraw_funds: bool,
+    /// How often `QuoteGuard::run` checks the heartbeat and the RPC connection.
+    pub check_interval: Duration,
+    /// How long to wait between cancel attempts while orders remain on the book.
+    pub retry_interval: Duration,
+    /// How long the drop and panic hooks wait for their cancel to be sent.
+    pub hook_timeout: Duration,
+    /// How often `QuoteGuard::run` checks that the trader's seat on each guarded market is still
+    /// approved, or `None` not to check.
+    pub seat_check_interval: Option<Duration>,
+}
+
+impl QuoteGuardConfig {
+    pub fn new(markets: Vec<Pubkey>, timeout: Duration) -> Self {
+        Self {
+            markets,
+            timeout,
+            withdraw_funds: false,
+            check_interval: Duration::from_millis(100),
+            retry_interval: Duration::from_secs(1),
+            hook_timeout: Duration::from_secs(10),
+            seat_check_interval: None,
+        }
+    }
+}
+
+/// Why `QuoteGuard::run` cancelled the guarded orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TripReason {
+    HeartbeatTimeout,
+    /// The RPC connection was unreachable and came back, so quotes may have been resting
+    /// unattended in between.
+    RpcRecovered,
+    /// The trader's seat on the market is no longer approved, e.g. because it was evicted, so
+    /// new quotes would fail to post.
+    SeatNotApproved(Pubkey),
+}
+
+/// Sends the cancels for the guarded markets. Shared with the panic hook, which outlives any
+/// borrow of the guard.
+#[derive(Clone)]
+struct Canceller {
+    sdk: Arc<SDKClient>,
+    markets: Vec<Pubkey>,
+    withdraw_funds: bool,
+    armed: Arc<AtomicBool>,
+    hook_timeout: Duration,
+}
+
+impl Canceller {
+    fn instructions(&self) -> Result<Vec<Instruction>> {
+        let mut ixs = vec![];
+        for market_key in self.markets.iter() {
+            ixs.push(self.sdk.get_cancel_all_ix(market_key)?);
+            if self.withdraw_funds {
+                ixs.push(self.sdk.get_withdraw_ix(market_key)?);
+            }
+        }
+        Ok(ixs)
+    }
+
+    async fn send(&self) -> Result<()> {
+        // Cancels must go out even when the orders they remove would breach risk limits
+        self.sdk
+            .send_ixs_with_risk_override(self.instructions()?, true)
+            .await
+            .map(|_| ())
+    }
+
+    async fn is_clean(&self) -> Result<bool> {
+        for market_key in self.markets.iter() {
+            if !self.sdk.get_open_orders(market_key).await?.is_empty() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Sends the cancels once from a separate thread and runtime, so it works from synchronous
+    /// contexts. Does nothing if the guard was disarmed or the hook already fired.
+    fn fire_blocking(&self) {
+        if !self.armed.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        let canceller = self.clone();
+        let (done_sender, done_receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let result = tokio::runtime::Runtime::new()
+                .map_err(Into::into)
+                .and_then(|rt| rt.block_on(canceller.send()));
+            let _ = done_sender.send(result);
+        });
+        // Don't hang a crashing process on an unreachable RPC
+        let _ = done_receiver.recv_timeout(self.hook_timeout);
+    }
+}
+
+/// Cancels a market maker's quotes if the process stops heartbeating or loses connectivity.
+///
+/// The guard should be given its own `SDKClient`, e.g. from `SDKClient::new`, with the guarded
+/// markets added, so that cancels do not queue behind a stuck connection in the trading path.
+/// The application calls `heartbeat` at least every `timeout`, while `run` watches for a missed
+/// heartbeat, an RPC outage ending or, with `seat_check_interval` set, a seat that is no longer
+/// approved, and then cancels until `get_open_orders` shows the book is clean. Dropping an armed guard, or a panic after `install_panic_hook`, also fires a best-effort
+/// cancel. Call `disarm` on a deliberate shutdown that should leave the quotes resting.
+pub struct QuoteGuard {
+    pub config: QuoteGuardConfig,
+    canceller: Canceller,
+    last_heartbeat: Mutex<Instant>,
+    recorded_orders: BTreeMap<Pubkey, Vec<(FIFOOrderId, u64)>>,
+}
+
+impl QuoteGuard {
+    /// Records the trader's open orders on the guarded markets and arms the guard. Fails if any
+    /// market is missing from the client's cache or its orders cannot be fetched.
+    pub async fn start(sdk: Arc<SDKClient>, config: QuoteGuardConfig) -> Result<Self> {
+        let canceller = Canceller {
+            sdk,
+            markets: config.markets.clone(),
+            withdraw_funds: config.withdraw_funds,
+            armed: Arc::new(AtomicBool::new(false)),
+            hook_timeout: config.hook_timeout,
+        };
+        canceller.instructions()?;
+        let mut recorded_orders = BTreeMap::new();
+        for market_key in config.markets.iter() {
+            recorded_orders.insert(
+                *market_key,
+                canceller.sdk.get_open_orders(market_key).await?,
+            );
+        }
+        canceller.armed.store(true, Ordering::SeqCst);
+        Ok(Self {
+            config,
+            canceller,
+            last_heartbeat: Mutex::new(Instant::now()),
+            recorded_orders,
+        })
+    }
+
+    /// The open orders on each guarded market when the guard started.
+    pub fn recorded_orders(&self) -> &BTreeMap<Pubkey, Vec<(FIFOOrderId, u64)>> {
+        &self.recorded_orders
+    }
+
+    pub fn heartbeat(&self) {
+        *self
+            .last_heartbeat
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Instant::now();
+    }
+
+    pub fn time_since_heartbeat(&self) -> Duration {
+        self.last_heartbeat
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .elapsed()
+    }
+
+    /// Stops the drop and panic hooks from cancelling. `run` and `cancel_until_clean` still do.
+    pub fn disarm(&self) {
+        self.canceller.armed.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.canceller.armed.load(Ordering::SeqCst)
+    }
+
+    /// Chains a panic hook that fires a best-effort cancel before the previous hook runs. The
+    /// hook fires at most once, and not at all once the guard is disarmed.
+    pub fn install_panic_hook(&self) {
+        let canceller = self.canceller.clone();
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            canceller.fire_blocking();
+            previous(info);
+        }));
+    }
+
+    /// Sends cancel-all (and withdraw, if configured) for every guarded market, retrying every
+    /// `retry_interval` until none of the trader's orders remain. Send and fetch failures are
+    /// retried, so this only returns once the book is confirmed clean.
+    pub async fn cancel_until_clean(&self) {
+        loop {
+            let _ = self.canceller.send().await;
+            if let Ok(true) = self.canceller.is_clean().await {
+                return;
+            }
+            tokio::time::sleep(self.config.retry_interval).await;
+        }
+    }
+
+    /// Watches the heartbeat and the RPC connection every `check_interval`, and the seats every
+    /// `seat_check_interval`. When the heartbeat is missed, the RPC recovers from an outage or a
+    /// seat is not approved, cancels until the book is clean and returns the reason. The quoting
+    /// loop should stop on `SeatNotApproved` until the seat is approved again. Calling `run`
+    /// again resumes guarding.
+    pub async fn run(&self) -> TripReason {
+        let mut interval = tokio::time::interval(self.config.check_interval);
+        let mut rpc_was_down = false;
+        let mut last_seat_check = None;
+        loop {
+            interval.tick().await;
+            let reason = if self.time_since_heartbeat() > self.config.timeout {
+                Some(TripReason::HeartbeatTimeout)
+            } else if let Some(market) = self.unapproved_seat(&mut last_seat_check).await {
+                Some(TripReason::SeatNotApproved(market))
+            } else if self.canceller.sdk.trading_mode == TradingMode::Live {
+                self.canceller
+                    .sdk
+                    .throttle("quote_guard", RpcCategory::GetSlot)
+                    .await;
+                let is_up = self.canceller.sdk.client.get_slot().await.is_ok();
+                let recovered = is_up && rpc_was_down;
+                rpc_was_down = !is_up;
+                recovered.then_some(TripReason::RpcRecovered)
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                self.cancel_until_clean().await;
+                return reason;
+            }
+        }
+    }
+
+    /// The first guarded market whose seat `get_seat_status` reports as anything but approved,
+    /// if `seat_check_interval` has passed since `last_check`. Failed fetches are skipped, since
+    /// outages are caught by the RPC check.
+    async fn unapproved_seat(&self, last_check: &mut Option<Instant>) -> Option<Pubkey> {
+        let seat_check_interval = self.config.seat_check_interval?;
+        if last_check.is_some_and(|checked_at: Instant| checked_at.elapsed() < seat_check_interval)
+        {
+            return None;
+        }
+        *last_check = Some(Instant::now());
+        for market_key in self.config.markets.iter() {
+            match self.canceller.sdk.get_seat_status(market_key).await {
+                Ok(Some(SeatApprovalStatus::Approved)) | Err(_) => {}
+                Ok(_) => return Some(*market_key),
+            }
+        }
+        None
+    }
+}
+
+impl Drop for QuoteGuard {
+    fn drop(&mut self) {
+        self.canceller.fire_blocking();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix::state::enums::Side;
+    use phoenix_sdk_core::market_event::{Evict, MarketEventDetails, PhoenixEvent};
+    use phoenix_sdk_core::order_manager::OrderEnded;
+    use solana_sdk::signature::Signature;
+
+    use super::*;
+    use crate::event_router::{Backpressure, EventRouter};
+    use crate::paper_trading::test_utils::paper_client;
+    use crate::risk_manager::{RiskConfig, RiskManager};
+    use crate::sdk_market_event::SDKMarketEvent;
+    use phoenix_sdk_core::test_utils::event;
+
+    async fn quoting_client() -> (Arc<SDKClient>, Pubkey) {
+        let (sdk, market) = paper_client(&[(Side::Ask, 105, 100)]).await;
+        for (price, side) in [(100, Side::Bid), (110, Side::Ask)] {
+            let ix = sdk
+                .get_post_only_ix_from_tick_price(&market, price, side, 10, 0, false)
+                .unwrap();
+            sdk.send_ixs(vec![ix]).await.unwrap();
+        }
+        (Arc::new(sdk), market)
+    }
+
+    #[tokio::test]
+    async fn test_missed_heartbeat_cancels() {
+        let (sdk, market) = quoting_client().await;
+        let mut config = QuoteGuardConfig::new(vec![market], Duration::from_millis(50));
+        config.check_interval = Duration::from_millis(5);
+        let guard = QuoteGuard::start(sdk.clone(), config).await.unwrap();
+        assert_eq!(guard.recorded_orders()[&market].len(), 2);
+
+        // Heartbeats keep the quotes up
+        let watchdog = guard.run();
+        tokio::pin!(watchdog);
+        for _ in 0..10 {
+            guard.heartbeat();
+            tokio::select! {
+                reason = &mut watchdog => panic!("Tripped while heartbeating: {:?}", reason),
+                _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+            }
+        }
+        assert_eq!(sdk.get_open_orders(&market).await.unwrap().len(), 2);
+
+        assert_eq!(watchdog.await, TripReason::HeartbeatTimeout);
+        assert!(sdk.get_open_orders(&market).await.unwrap().is_empty());
+
+        assert!(QuoteGuard::start(
+            sdk,
+            QuoteGuardConfig::new(vec![Pubkey::new_unique()], Duration::from_secs(1))
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_drop_cancels_unless_disarmed() {
+        let (sdk, market) = quoting_client().await;
+        let config = QuoteGuardConfig::new(vec![market], Duration::from_secs(1));
+
+        let guard = QuoteGuard::start(sdk.clone(), config.clone())
+            .await
+            .unwrap();
+        guard.disarm();
+        drop(guard);
+        assert_eq!(sdk.get_open_orders(&market).await.unwrap().len(), 2);
+
+        let guard = QuoteGuard::start(sdk.clone(), config).await.unwrap();
+        assert!(guard.is_armed());
+        drop(guard);
+        assert!(sdk.get_open_orders(&market).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_eviction_halts_quoting() {
+        let (mut sdk, market) = paper_client(&[]).await;
+        let router = EventRouter::new(16);
+        sdk.set_event_router(Some(router.clone()));
+        let mut evictions = sdk.subscribe_evictions().unwrap();
+        let mut updates = router.subscribe_all(Backpressure::Wait);
+        let ended = Arc::new(Mutex::new(vec![]));
+        let mut risk_manager = RiskManager::new(sdk.trader, RiskConfig::default());
+        let recorded = ended.clone();
+        risk_manager.orders.set_on_order_ended(Some(
+            move |_: &PhoenixEvent, ended: &OrderEnded| recorded.lock().unwrap().push(*ended),
+        ));
+        sdk.set_risk_manager(Some(risk_manager));
+        for (price, side) in [(100, Side::Bid), (110, Side::Ask)] {
+            let ix = sdk
+                .get_post_only_ix_from_tick_price(&market, price, side, 10, 0, false)
+                .unwrap();
+            sdk.send_ixs(vec![ix]).await.unwrap();
+        }
+        let open_orders = sdk
+            .risk_manager()
+            .as_ref()
+            .unwrap()
+            .orders
+            .open_orders(&market)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(open_orders.len(), 2);
+
+        // Another trader's seat request evicts the trader, taking its orders with it
+        let batch = open_orders
+            .iter()
+            .enumerate()
+            .map(|(event_index, order)| {
+                SDKMarketEvent::from(PhoenixEvent {
+                    sequence_number: 10,
+                    slot: 1,
+                    signature: Signature::new_unique(),
+                    signer: Pubkey::new_unique(),
+                    event_index: event_index as u64,
+                    ..event(
+                        market,
+                        MarketEventDetails::Evict(Evict {
+                            order_sequence_number: order.order_id.order_sequence_number,
+                            maker: sdk.trader,
+                            price_in_ticks: order.price_in_ticks(),
+                            base_lots_evicted: order.num_base_lots,
+                        }),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        router.route(batch).await;
+        for order in open_orders.iter() {
+            let eviction = evictions.recv().await.unwrap();
+            assert!(matches!(
+                eviction.details,
+                MarketEventDetails::Evict(Evict { order_sequence_number, .. })
+                    if order_sequence_number == order.order_id.order_sequence_number
+            ));
+        }
+
+        // The order manager, fed from the router, ends the orders as evicted
+        let meta = sdk.markets.get(&market).unwrap();
+        for message in updates.recv().await.unwrap() {
+            if let SDKMarketEvent::PhoenixEvent { event } = message {
+                sdk.risk_manager()
+                    .as_mut()
+                    .unwrap()
+                    .apply_event(&event, &meta);
+            }
+        }
+        assert_eq!(
+            *ended.lock().unwrap(),
+            open_orders
+                .iter()
+                .map(|order| OrderEnded::Evicted(*order))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            sdk.risk_manager()
+                .as_ref()
+                .unwrap()
+                .orders
+                .open_order_count(),
+            0
+        );
+
+        // Polling the seat trips the guard, which stops the quoting loop
+        sdk.warmed()
+            .insert_seat_status(&market, Some(SeatApprovalStatus::NotApproved));
+        let sdk = Arc::new(sdk);
+        let mut config = QuoteGuardConfig::new(vec![market], Duration::from_secs(60));
+        config.check_interval = Duration::from_millis(5);
+        config.seat_check_interval = Some(Duration::from_millis(5));
+        let guard = QuoteGuard::start(sdk.clone(), config).await.unwrap();
+        assert_eq!(guard.run().await, TripReason::SeatNotApproved(market));
+        assert!(sdk.get_open_orders(&market).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::health::{
+    ComponentDetails, ComponentHealth, HealthProvider, HealthStatus, HealthThresholds,
+};
+
+/// Kinds of RPC request, weighted by how much of a provider's budget they use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RpcCategory {
+    GetSlot,
+    GetAccount,
+    GetTransaction,
+    GetSignatureStatuses,
+    SendTransaction,
+    /// Opening a websocket subscription.
+    Subscribe,
+    Other,
+}
+
+impl RpcCategory {
+    /// `getTransaction` is several times as expensive as a plain read at most providers.
+    pub fn default_weight(&self) -> f64 {
+        match self {
+            RpcCategory::GetTransaction => 5.0,
+            RpcCategory::SendTransaction => 2.0,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    /// Sustained rate, in weight units per second.
+    pub requests_per_second: f64,
+    /// Weight that can be spent at once after a quiet period.
+    pub burst: f64,
+    /// Overrides of `RpcCategory::default_weight`.
+    pub weights: BTreeMap<RpcCategory, f64>,
+}
+
+impl RateLimitConfig {
+    pub fn new(requests_per_second: f64, burst: f64) -> Self {
+        Self {
+            requests_per_second,
+            burst,
+            weights: BTreeMap::new(),
+        }
+    }
+
+    pub fn weight(&self, category: RpcCategory) -> f64 {
+        self.weights
+            .get(&category)
+            .copied()
+            .unwrap_or_else(|| category.default_weight())
+    }
+}
+
+/// Source of time for a `RateLimiter`, replaceable in tests.
+pub trait Clock: Send + Sync {
+    /// Time elapsed since an arbitrary fixed point.
+    fn now(&self) -> Duration;
+}
+
+pub struct MonotonicClock {
+    start: Instant,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Requests made through a `RateLimiter` by one subsystem.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SubsystemCounters {
+    pub requests: u64,
+    pub weight: f64,
+    /// Total time requests were held back for.
+    pub waited: Duration,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Duration,
+}
+
+/// Token bucket shared by everything that makes RPC requests on behalf of one provider account.
+///
+/// Callers wait for budget instead of failing. Requests are admitted in the order they reserve,
+/// and one heavier than `burst` is let through once the bucket has been drained for it. Usage is
+/// counted per subsystem, e.g. `"sdk_client"` or `"reorg_guard"`.
+pub struct RateLimiter {
+    pub config: RateLimitConfig,
+    clock: Box<dyn Clock>,
+    bucket: Mutex<Bucket>,
+    counters: Mutex<BTreeMap<&'static str, SubsystemCounters>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self::with_clock(config, MonotonicClock::default())
+    }
+
+    pub fn with_clock(config: RateLimitConfig, clock: impl Clock + 'static) -> Self {
+        let clock = Box::new(clock);
+        let bucket = Bucket {
+            tokens: config.burst,
+            updated_at: clock.now(),
+        };
+        Self {
+            config,
+            clock,
+            bucket: Mutex::new(bucket),
+            counters: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Takes the request's weight from the bucket and returns how long the caller must wait
+    /// before sending it. The budget stays reserved even if the caller gives up.
+    pub fn reserve(&self, subsystem: &'static str, category: RpcCategory) -> Duration {
+        let weight = self.config.weight(category);
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|p| p.into_inner());
+            let now = self.clock.now();
+            let elapsed = now.saturating_sub(bucket.updated_at).as_secs_f64();
+            bucket.tokens =
+                (bucket.tokens + elapsed * self.config.requests_per_second).min(self.config.burst);
+            bucket.updated_at = now;
+            bucket.tokens -= weight;
+            if bucket.tokens >= 0.0 || self.config.requests_per_second <= 0.0 {
+                Duration::ZERO
+            } else {
+                Duration::from_secs_f64(-bucket.tokens / self.config.requests_per_second)
+            }
+        };
+        let mut counters = self.counters.lock().unwrap_or_else(|p| p.into_inner());
+        let counter = counters.entry(subsystem).or_default();
+        counter.requests += 1;
+        counter.weight += weight;
+        counter.waited += wait;
+        wait
+    }
+
+    /// Waits until the request fits in the budget.
+    pub async fn acquire(&self, subsystem: &'static str, category: RpcCategory) {
+        let wait = self.reserve(subsystem, category);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    pub fn counters(&self) -> BTreeMap<&'static str, SubsystemCounters> {
+        self.counters
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .clone()
+    }
+
+    /// The share of `burst` that is spent, as of now: 0 after a quiet period, 1 once the bucket is
+    /// empty, and above 1 while reserved requests are waiting for budget.
+    pub fn saturation(&self) -> f64 {
+        if self.config.burst <= 0.0 {
+            return 0.0;
+        }
+        let bucket = self.bucket.lock().unwrap_or_else(|p| p.into_inner());
+        let elapsed = self
+            .clock
+            .now()
+            .saturating_sub(bucket.updated_at)
+            .as_secs_f64();
+        let tokens =
+            (bucket.tokens + elapsed * self.config.requests_per_second).min(self.config.burst);
+        1.0 - tokens / self.config.burst
+    }
+}
+
+/// Degraded while `saturation` is above `max_rate_limiter_saturation`.
+impl HealthProvider for RateLimiter {
+    fn health_check(&self, thresholds: &HealthThresholds) -> ComponentHealth {
+        let saturation = self.saturation();
+        ComponentHealth {
+            status: if saturation > thresholds.max_rate_limiter_saturation {
+                HealthStatus::Degraded
+            } else {
+                HealthStatus::Healthy
+            },
+            details: ComponentDetails::RateLimiter { saturation },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct MockClock(Arc<Mutex<Duration>>);
+
+    impl MockClock {
+        fn advance(&self, duration: Duration) {
+            *self.0.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Duration {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_pacing_and_weights() {
+        let clock = MockClock::default();
+        // 10 per second with a burst of 3
+        let limiter = RateLimiter::with_clock(RateLimitConfig::new(10.0, 3.0), clock.clone());
+        let millis = |wait: Duration| wait.as_millis();
+
+        // The burst goes straight through, then requests are spaced 100ms apart
+        for _ in 0..3 {
+            assert_eq!(millis(limiter.reserve("a", RpcCategory::GetSlot)), 0);
+        }
+        assert_eq!(millis(limiter.reserve("a", RpcCategory::GetSlot)), 100);
+        assert_eq!(millis(limiter.reserve("b", RpcCategory::GetSlot)), 200);
+
+        // A getTransaction costs five reads, queued behind the reads already reserved
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(
+            millis(limiter.reserve("b", RpcCategory::GetTransaction)),
+            500
+        );
+
+        // After a quiet period the bucket refills, but only up to the burst
+        clock.advance(Duration::from_secs(10));
+        for _ in 0..3 {
+            assert_eq!(millis(limiter.reserve("a", RpcCategory::GetAccount)), 0);
+        }
+        assert_eq!(millis(limiter.reserve("a", RpcCategory::GetAccount)), 100);
+
+        let counters = limiter.counters();
+        assert_eq!(counters["a"].requests, 8);
+        assert_eq!(counters["a"].weight, 8.0);
+        assert_eq!(counters["a"].waited, Duration::from_millis(200));
+        assert_eq!(counters["b"].requests, 2);
+        assert_eq!(counters["b"].weight, 6.0);
+    }
+
+    #[test]
+    fn test_weight_overrides() {
+        let mut config = RateLimitConfig::new(1.0, 1.0);
+        config.weights.insert(RpcCategory::GetSlot, 0.5);
+        assert_eq!(config.weight(RpcCategory::GetSlot), 0.5);
+        assert_eq!(config.weight(RpcCategory::GetTransaction), 5.0);
+
+        let limiter = RateLimiter::with_clock(config, MockClock::default());
+        assert!(limiter.reserve("a", RpcCategory::GetSlot).is_zero());
+        assert!(limiter.reserve("a", RpcCategory::GetSlot).is_zero());
+        assert_eq!(
+            limiter.reserve("a", RpcCategory::GetSlot),
+            Duration::from_millis(500)
+        );
+    }
+    #[test]
+    fn test_saturation() {
+        let clock = MockClock::default();
+        let limiter = RateLimiter::with_clock(RateLimitConfig::new(10.0, 4.0), clock.clone());
+        let thresholds = HealthThresholds::default();
+        assert_eq!(limiter.saturation(), 0.0);
+
+        limiter.reserve("a", RpcCategory::GetSlot);
+        assert_eq!(limiter.saturation(), 0.25);
+        assert_eq!(
+            limiter.health_check(&thresholds).status,
+            HealthStatus::Healthy
+        );
+
+        // A getTransaction overdraws the bucket
+        limiter.reserve("a", RpcCategory::GetTransaction);
+        assert_eq!(limiter.saturation(), 1.5);
+        assert_eq!(
+            limiter.health_check(&thresholds).status,
+            HealthStatus::Degraded
+        );
+
+        // Reading the saturation does not refill the bucket, time does
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(limiter.saturation(), 0.25);
+    }
+}
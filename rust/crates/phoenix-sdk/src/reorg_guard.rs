@@ -0,0 +1,398 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use phoenix_sdk_core::market_event::PhoenixEvent;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::event_sink::EventSink;
+use crate::rate_limiter::{RateLimiter, RpcCategory};
+use crate::sdk_market_event::SDKMarketEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReorgCheckConfig {
+    /// A transaction that is still not finalized once the newest event seen is this many slots
+    /// past it is treated as dropped by a fork.
+    pub window_slots: u64,
+    /// How long after a transaction is received before its status is first checked.
+    pub check_delay: Duration,
+    /// How often `ReorgGuard::run` checks pending transactions.
+    pub check_interval: Duration,
+    /// Hold batches back until every transaction in them is finalized, instead of forwarding them
+    /// immediately and sending a rollback later. Unfinalized transactions are then never delivered.
+    pub strict: bool,
+}
+
+impl Default for ReorgCheckConfig {
+    fn default() -> Self {
+        Self {
+            window_slots: 150,
+            check_delay: Duration::from_secs(20),
+            check_interval: Duration::from_secs(5),
+            strict: false,
+        }
+    }
+}
+
+/// Outcome of looking up a transaction at finalized commitment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinalizationStatus {
+    Finalized,
+    /// Known to the cluster but not yet rooted.
+    Pending,
+    NotFound,
+}
+
+struct PendingTransaction {
+    signature: Signature,
+    slot: u64,
+    received_at: Instant,
+    events: Vec<PhoenixEvent>,
+}
+
+#[derive(Default)]
+struct GuardState {
+    /// Transactions awaiting finalization, in the order they were received.
+    pending: VecDeque<PendingTransaction>,
+    /// Batches held back in strict mode, with the signatures they are waiting on.
+    held: VecDeque<(Vec<SDKMarketEvent>, Vec<Signature>)>,
+    latest_slot: u64,
+}
+
+/// Wraps an `EventSink` fed at confirmed commitment and re-verifies each transaction at finalized
+/// commitment, so events from transactions that a fork later drops can be undone.
+///
+/// By default batches are forwarded immediately and a `ControlMsg::Rollback` is sent for any
+/// transaction that fails verification. In strict mode batches are only forwarded, in order, once
+/// all of their transactions are finalized, and batches behind an unverified one wait for it.
+pub struct ReorgGuard<S: EventSink> {
+    pub config: ReorgCheckConfig,
+    sink: S,
+    state: Mutex<GuardState>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<S: EventSink> ReorgGuard<S> {
+    pub fn new(sink: S, config: ReorgCheckConfig) -> Self {
+        Self {
+            config,
+            sink,
+            state: Mutex::new(GuardState::default()),
+            rate_limiter: None,
+        }
+    }
+
+    /// Status lookups wait for budget from `rate_limiter`, counted under the `"reorg_guard"`
+    /// subsystem.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Number of transactions whose finalization has not been decided yet.
+    pub async fn pending_count(&self) -> usize {
+        self.state.lock().await.pending.len()
+    }
+
+    /// Checks every transaction received at least `check_delay` ago against `client`.
+    pub async fn check(&self, client: &RpcClient) -> Result<()> {
+        let statuses = self.finalization_statuses(client).await?;
+        self.apply_statuses(&statuses).await
+    }
+
+    async fn finalization_statuses(
+        &self,
+        client: &RpcClient,
+    ) -> Result<Vec<(Signature, FinalizationStatus)>> {
+        let now = Instant::now();
+        let due = {
+            let state = self.state.lock().await;
+            state
+                .pending
+                .iter()
+                .filter(|tx| now >= tx.received_at + self.config.check_delay)
+                .map(|tx| tx.signature)
+                .collect::<Vec<_>>()
+        };
+        let mut statuses = Vec::with_capacity(due.len());
+        // The RPC accepts at most 256 signatures per request
+        for signatures in due.chunks(256) {
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter
+                    .acquire("reorg_guard", RpcCategory::GetSignatureStatuses)
+                    .await;
+            }
+            let response = client
+                .get_signature_statuses_with_history(signatures)
+                .await?
+                .value;
+            statuses.extend(signatures.iter().zip(response).map(|(signature, status)| {
+                let status = match status {
+                    Some(status) if status.satisfies_commitment(CommitmentConfig::finalized()) => {
+                        FinalizationStatus::Finalized
+                    }
+                    Some(_) => FinalizationStatus::Pending,
+                    None => FinalizationStatus::NotFound,
+                };
+                (*signature, status)
+            }));
+        }
+        Ok(statuses)
+    }
+
+    /// Applies looked-up statuses, forwarding verified batches in strict mode and rollbacks
+    /// otherwise. A transaction is dropped once it is not found or is still not finalized after
+    /// `window_slots`.
+    pub async fn apply_statuses(&self, statuses: &[(Signature, FinalizationStatus)]) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let latest_slot = state.latest_slot;
+        let mut dropped = vec![];
+        for (signature, status) in statuses {
+            let Some(position) = state
+                .pending
+                .iter()
+                .position(|tx| tx.signature == *signature)
+            else {
+                continue;
+            };
+            let is_resolved = match status {
+                FinalizationStatus::Finalized | FinalizationStatus::NotFound => true,
+                FinalizationStatus::Pending => {
+                    latest_slot.saturating_sub(state.pending[position].slot)
+                        > self.config.window_slots
+                }
+            };
+            if !is_resolved {
+                continue;
+            }
+            if let Some(tx) = state.pending.remove(position) {
+                if *status != FinalizationStatus::Finalized {
+                    dropped.push(tx);
+                }
+            }
+        }
+
+        if self.config.strict {
+            // Batches containing a dropped transaction are discarded whole, since they were never
+            // delivered. The rest are released in order up to the first that is still waiting.
+            state.held.retain(|(_, signatures)| {
+                !signatures
+                    .iter()
+                    .any(|signature| dropped.iter().any(|tx| tx.signature == *signature))
+            });
+            while let Some((_, signatures)) = state.held.front() {
+                let is_verified = signatures
+                    .iter()
+                    .all(|signature| !state.pending.iter().any(|tx| tx.signature == *signature));
+                if !is_verified {
+                    break;
+                }
+                if let Some((batch, _)) = state.held.pop_front() {
+                    self.sink.send_events(batch).await?;
+                }
+            }
+        } else if !dropped.is_empty() {
+            let rollbacks = dropped
+                .into_iter()
+                .map(|tx| SDKMarketEvent::rollback(tx.signature, tx.events))
+                .collect();
+            self.sink.send_events(rollbacks).await?;
+        }
+        Ok(())
+    }
+
+    /// Checks pending transactions every `check_interval`, returning once forwarding to the
+    /// wrapped sink fails. Failed RPC lookups are retried on the next check.
+    pub async fn run(&self, client: &RpcClient) -> Result<()> {
+        let mut interval = tokio::time::interval(self.config.check_interval);
+        loop {
+            interval.tick().await;
+            if let Ok(statuses) = self.finalization_statuses(client).await {
+                self.apply_statuses(&statuses).await?;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S: EventSink> EventSink for ReorgGuard<S> {
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let received_at = Instant::now();
+        let mut signatures = vec![];
+        for message in events.iter() {
+            let SDKMarketEvent::PhoenixEvent { event } = message else {
+                continue;
+            };
+            state.latest_slot = state.latest_slot.max(event.slot);
+            if let Some(tx) = state
+                .pending
+                .iter_mut()
+                .find(|tx| tx.signature == event.signature)
+            {
+                tx.events.push(**event);
+            } else {
+                state.pending.push_back(PendingTransaction {
+                    signature: event.signature,
+                    slot: event.slot,
+                    received_at,
+                    events: vec![**event],
+                });
+            }
+            if !signatures.contains(&event.signature) {
+                signatures.push(event.signature);
+            }
+        }
+        if self.config.strict && !(signatures.is_empty() && state.held.is_empty()) {
+            state.held.push_back((events, signatures));
+            Ok(())
+        } else {
+            self.sink.send_events(events).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix::state::enums::Side;
+    use phoenix_sdk_core::market_event::{Fill, MarketEventDetails};
+    use solana_sdk::pubkey::Pubkey;
+    use tokio::sync::mpsc::{channel, Receiver, Sender};
+
+    use super::*;
+    use phoenix_sdk_core::test_utils::event;
+
+    fn fill_batch(slot: u64) -> Vec<SDKMarketEvent> {
+        let signature = Signature::new_unique();
+        (0..2)
+            .map(|event_index| {
+                PhoenixEvent {
+                    sequence_number: slot,
+                    slot,
+                    signature,
+                    event_index,
+                    ..event(
+                        Pubkey::default(),
+                        MarketEventDetails::Fill(Fill {
+                            order_sequence_number: 1,
+                            maker: Pubkey::default(),
+                            taker: Pubkey::default(),
+                            price_in_ticks: 100,
+                            base_lots_filled: 1,
+                            base_lots_remaining: 0,
+                            side_filled: Side::Ask,
+                            maker_side: Side::Ask,
+                            taker_side: Side::Ask.opposite(),
+                            is_full_fill: true,
+                        }),
+                    )
+                }
+                .into()
+            })
+            .collect()
+    }
+
+    fn signature_of(batch: &[SDKMarketEvent]) -> Signature {
+        match &batch[0] {
+            SDKMarketEvent::PhoenixEvent { event } => event.signature,
+            message => *message.as_rollback().unwrap().0,
+        }
+    }
+
+    fn guard(
+        strict: bool,
+    ) -> (
+        ReorgGuard<Sender<Vec<SDKMarketEvent>>>,
+        Receiver<Vec<SDKMarketEvent>>,
+    ) {
+        let (sender, receiver) = channel(16);
+        let config = ReorgCheckConfig {
+            window_slots: 10,
+            check_delay: Duration::ZERO,
+            strict,
+            ..ReorgCheckConfig::default()
+        };
+        (ReorgGuard::new(sender, config), receiver)
+    }
+
+    #[tokio::test]
+    async fn test_rollback_of_dropped_transaction() {
+        let (guard, mut receiver) = guard(false);
+        let (kept, dropped, slow) = (fill_batch(1), fill_batch(2), fill_batch(3));
+        for batch in [&kept, &dropped, &slow] {
+            guard.send_events(batch.clone()).await.unwrap();
+            assert_eq!(receiver.try_recv().unwrap(), *batch);
+        }
+
+        guard
+            .apply_statuses(&[
+                (signature_of(&kept), FinalizationStatus::Finalized),
+                (signature_of(&dropped), FinalizationStatus::NotFound),
+                (signature_of(&slow), FinalizationStatus::Pending),
+            ])
+            .await
+            .unwrap();
+        let expected_events = dropped
+            .iter()
+            .map(|message| match message {
+                SDKMarketEvent::PhoenixEvent { event } => **event,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            vec![SDKMarketEvent::rollback(
+                signature_of(&dropped),
+                expected_events
+            )]
+        );
+        assert_eq!(guard.pending_count().await, 1);
+
+        // Still unfinalized once the window has passed
+        guard.send_events(fill_batch(20)).await.unwrap();
+        receiver.try_recv().unwrap();
+        guard
+            .apply_statuses(&[(signature_of(&slow), FinalizationStatus::Pending)])
+            .await
+            .unwrap();
+        assert_eq!(
+            receiver.try_recv().unwrap()[0].as_rollback().unwrap().0,
+            &signature_of(&slow)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_strict_mode_holds_unverified_batches() {
+        let (guard, mut receiver) = guard(true);
+        let (first, dropped, third) = (fill_batch(1), fill_batch(2), fill_batch(3));
+        for batch in [&first, &dropped, &third] {
+            guard.send_events(batch.clone()).await.unwrap();
+        }
+        assert!(receiver.try_recv().is_err());
+
+        // The third batch cannot pass the unverified second one
+        guard
+            .apply_statuses(&[
+                (signature_of(&first), FinalizationStatus::Finalized),
+                (signature_of(&third), FinalizationStatus::Finalized),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(receiver.try_recv().unwrap(), first);
+        assert!(receiver.try_recv().is_err());
+
+        guard
+            .apply_statuses(&[(signature_of(&dropped), FinalizationStatus::NotFound)])
+            .await
+            .unwrap();
+        assert_eq!(receiver.try_recv().unwrap(), third);
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(guard.pending_count().await, 0);
+    }
+}
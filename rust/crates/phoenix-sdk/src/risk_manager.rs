@@ -0,0 +1,867 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt;
+use std::time::Duration;
+
+use borsh::BorshDeserialize;
+use phoenix::program::new_order::MultipleOrderPacket;
+use phoenix::program::PhoenixInstruction;
+use phoenix::quantities::WrapperU64;
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+use phoenix::state::{decode_order_packet, OrderPacket, OrderPacketMetadata};
+use phoenix_sdk_core::market_event::PhoenixEvent;
+use phoenix_sdk_core::order_manager::OrderManager;
+use phoenix_sdk_core::orderbook::Orderbook;
+use phoenix_sdk_core::position_tracker::PositionTracker;
+use phoenix_sdk_core::reference_price::{ReferencePriceError, ReferencePriceFeed};
+use phoenix_sdk_core::sdk_client_core::{MarketMetadata, PhoenixOrder};
+use phoenix_sdk_core::tick_math::clamp_to_band;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::sdk_client::WalletBalances;
+
+/// Limits enforced by a `RiskManager`. Limits left as `None` are not checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RiskConfig {
+    /// Maximum notional of a single order, in quote units.
+    pub max_order_notional_quote_units: Option<f64>,
+    /// Maximum position per market in Phoenix base units, long or short.
+    pub max_position_base_units: Option<f64>,
+    /// Maximum number of resting orders across all markets.
+    pub max_open_orders: Option<usize>,
+    /// Orders priced more than this percentage away from the market's mid price are rejected.
+    pub price_band_pct: Option<f64>,
+    /// Orders are rejected while the wallet holds fewer lamports than this, to keep SOL for fees.
+    /// Checked against the balances from `RiskManager::set_wallet_balances`.
+    pub min_lamports: Option<u64>,
+}
+
+/// The limit an order would breach and the value computed for it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RiskViolation {
+    MaxOrderNotional {
+        market: Pubkey,
+        limit_quote_units: f64,
+        order_notional_quote_units: f64,
+    },
+    MaxPosition {
+        market: Pubkey,
+        limit_base_units: f64,
+        projected_position_base_units: f64,
+    },
+    MaxOpenOrders {
+        limit: usize,
+        projected_open_orders: usize,
+    },
+    PriceBand {
+        market: Pubkey,
+        limit_pct: f64,
+        deviation_pct: f64,
+    },
+    /// A check needs the market's mid price, for the price band or to value a market order, but
+    /// none is set.
+    MissingMidPrice {
+        market: Pubkey,
+    },
+    /// A check needs the market's mid price, but the reference feed's price is older than the
+    /// feed allows.
+    StaleReferencePrice {
+        market: Pubkey,
+        age: Duration,
+        max_age: Duration,
+    },
+    /// The order is on a market whose metadata is not loaded.
+    UnknownMarket {
+        market: Pubkey,
+    },
+    MinLamports {
+        limit: u64,
+        lamports: u64,
+    },
+    /// A check needs the wallet's balances, but none are set for the market.
+    MissingWalletBalances {
+        market: Pubkey,
+    },
+}
+
+impl fmt::Display for RiskViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RiskViolation::MaxOrderNotional {
+                market,
+                limit_quote_units,
+                order_notional_quote_units,
+            } => write!(
+                f,
+                "Order notional of {} quote units on market {} exceeds the limit of {}",
+                order_notional_quote_units, market, limit_quote_units
+            ),
+            RiskViolation::MaxPosition {
+                market,
+                limit_base_units,
+                projected_position_base_units,
+            } => write!(
+                f,
+                "Projected position of {} base units on market {} exceeds the limit of {}",
+                projected_position_base_units, market, limit_base_units
+            ),
+            RiskViolation::MaxOpenOrders {
+                limit,
+                projected_open_orders,
+            } => write!(
+                f,
+                "Projected open order count of {} exceeds the limit of {}",
+                projected_open_orders, limit
+            ),
+            RiskViolation::PriceBand {
+                market,
+                limit_pct,
+                deviation_pct,
+            } => write!(
+                f,
+                "Order price is {}% from the mid on market {}, outside the band of {}%",
+                deviation_pct, market, limit_pct
+            ),
+            RiskViolation::MissingMidPrice { market } => {
+                write!(f, "No mid price set for risk checks on market {}", market)
+            }
+            RiskViolation::StaleReferencePrice {
+                market,
+                age,
+                max_age,
+            } => write!(
+                f,
+                "Reference price for market {} is {:?} old, more than {:?}",
+                market, age, max_age
+            ),
+            RiskViolation::UnknownMarket { market } => {
+                write!(f, "Market {} not found for risk checks", market)
+            }
+            RiskViolation::MinLamports { limit, lamports } => write!(
+                f,
+                "Wallet holds {} lamports, below the minimum of {}",
+                lamports, limit
+            ),
+            RiskViolation::MissingWalletBalances { market } => write!(
+                f,
+                "No wallet balances set for risk checks on market {}",
+                market
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RiskViolation {}
+
+/// An order that was sent but whose events have not been applied yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingOrder {
+    pub market: Pubkey,
+    pub side: Side,
+    pub num_base_lots: u64,
+    /// Whether any unfilled size rests on the book (i.e. the order is not IOC).
+    pub rests: bool,
+}
+
+/// Number of applied events remembered to drop duplicates.
+const APPLIED_EVENT_HISTORY: usize = 1 << 16;
+
+/// Identifies an event: indices restart with each instruction's event batch.
+type EventKey = (Signature, Pubkey, u64, u64);
+
+#[derive(Debug, Clone)]
+struct PendingTransaction {
+    signature: Option<Signature>,
+    orders: Vec<PendingOrder>,
+}
+
+/// Pre-trade checks on Phoenix instructions before they are sent.
+///
+/// Positions and open orders come from a `PositionTracker` and `OrderManager` fed with the
+/// trader's events through `apply_event`. Orders are reserved as pending from the moment they pass
+/// the checks until the events of their transaction are applied, so concurrent sends cannot each
+/// pass against the same stale state. Events are deduplicated, so the same transaction may be
+/// applied both from the sender and from an event stream.
+///
+/// Position checks are worst-case: an order is checked as if it, every pending order and every
+/// resting order on the same side filled completely.
+#[derive(Debug, Clone)]
+pub struct RiskManager {
+    pub config: RiskConfig,
+    pub positions: PositionTracker,
+    pub orders: OrderManager,
+    pending: BTreeMap<u64, PendingTransaction>,
+    next_reservation: u64,
+    mid_prices: BTreeMap<Pubkey, u64>,
+    reference_prices: Option<ReferencePriceFeed>,
+    wallet_balances: BTreeMap<Pubkey, WalletBalances>,
+    applied_events: HashSet<EventKey>,
+    applied_event_order: VecDeque<EventKey>,
+}
+
+impl RiskManager {
+    pub fn new(trader: Pubkey, config: RiskConfig) -> Self {
+        Self {
+            config,
+            positions: PositionTracker::new(trader),
+            orders: OrderManager::new(trader),
+            pending: BTreeMap::new(),
+            next_reservation: 0,
+            mid_prices: BTreeMap::new(),
+            reference_prices: None,
+            wallet_balances: BTreeMap::new(),
+            applied_events: HashSet::new(),
+            applied_event_order: VecDeque::new(),
+        }
+    }
+
+    /// Sets the reference price the price band is measured from.
+    pub fn set_mid_price(&mut self, market: &Pubkey, mid_price_in_ticks: u64) {
+        self.mid_prices.insert(*market, mid_price_in_ticks);
+    }
+
+    /// Measures the price band from `feed` instead of the mids set with `set_mid_price`. Orders
+    /// that need a mid fail with `StaleReferencePrice` while the feed's price is too old, rather
+    /// than falling back to the book.
+    pub fn set_reference_price_feed(&mut self, feed: Option<ReferencePriceFeed>) {
+        self.reference_prices = feed;
+    }
+
+    /// The price the band is measured from and market orders are valued at, in ticks.
+    fn mid_price(&self, market: &Pubkey, meta: &MarketMetadata) -> Result<u64, RiskViolation> {
+        let market = *market;
+        match &self.reference_prices {
+            Some(feed) => match feed.price(&market) {
+                Ok(reference) => Ok(reference.price_in_ticks(meta)),
+                Err(ReferencePriceError::Missing { .. }) => {
+                    Err(RiskViolation::MissingMidPrice { market })
+                }
+                Err(ReferencePriceError::Stale { age, max_age, .. }) => {
+                    Err(RiskViolation::StaleReferencePrice {
+                        market,
+                        age,
+                        max_age,
+                    })
+                }
+            },
+            None => self
+                .mid_prices
+                .get(&market)
+                .copied()
+                .ok_or(RiskViolation::MissingMidPrice { market }),
+        }
+    }
+
+    /// `price_in_ticks` clamped to the price band around the market's mid, for quoting at the edge
+    /// of the band rather than having the order rejected. Unchanged without a band.
+    pub fn clamp_to_price_band(
+        &self,
+        market: &Pubkey,
+        meta: &MarketMetadata,
+        price_in_ticks: u64,
+    ) -> Result<u64, RiskViolation> {
+        let Some(limit_pct) = self.config.price_band_pct else {
+            return Ok(price_in_ticks);
+        };
+        let mid = self.mid_price(market, meta)?;
+        // Rounded down so that the edge passes `check`
+        let max_deviation_bps = (limit_pct * 100.0).max(0.0).floor() as u64;
+        Ok(clamp_to_band(price_in_ticks, mid, max_deviation_bps, 1))
+    }
+
+    /// Sets the reference price from the midpoint of the book's best bid and ask. Does nothing if
+    /// either side is empty.
+    pub fn update_mid_price(
+        &mut self,
+        market: &Pubkey,
+        orderbook: &Orderbook<FIFOOrderId, PhoenixOrder>,
+    ) {
+        if let (Some(best_bid), Some(best_ask)) =
+            (orderbook.bids.keys().next(), orderbook.asks.keys().next())
+        {
+            let mid = (best_bid.price_in_ticks.as_u64() + best_ask.price_in_ticks.as_u64()) / 2;
+            self.set_mid_price(market, mid);
+        }
+    }
+
+    /// Sets the wallet's balances on a market, e.g. from `SDKClient::get_wallet_balances`.
+    pub fn set_wallet_balances(&mut self, market: &Pubkey, balances: WalletBalances) {
+        self.wallet_balances.insert(*market, balances);
+    }
+
+    pub fn wallet_balances(&self, market: &Pubkey) -> Option<&WalletBalances> {
+        self.wallet_balances.get(market)
+    }
+
+    pub fn pending_orders(&self) -> impl Iterator<Item = &PendingOrder> {
+        self.pending
+            .values()
+            .flat_map(|pending| pending.orders.iter())
+    }
+
+    /// Applies one of the trader's events (events of other traders are ignored). Returns false if
+    /// the event was already applied.
+    pub fn apply_event(&mut self, event: &PhoenixEvent, meta: &MarketMetadata) -> bool {
+        let key = (
+            event.signature,
+            event.market,
+            event.sequence_number,
+            event.event_index,
+        );
+        if !self.applied_events.insert(key) {
+            return false;
+        }
+        self.applied_event_order.push_back(key);
+        if self.applied_event_order.len() > APPLIED_EVENT_HISTORY {
+            if let Some(oldest) = self.applied_event_order.pop_front() {
+                self.applied_events.remove(&oldest);
+            }
+        }
+        self.pending
+            .retain(|_, pending| pending.signature != Some(event.signature));
+        self.positions.apply_event(event, meta);
+        self.orders.apply_event(event);
+        true
+    }
+
+    /// Checks `ixs` and reserves their orders as pending, returning a reservation to pass to
+    /// `confirm` or `release` once the send completes. If `enforce_limits` is false the orders are
+    /// reserved without being checked.
+    pub fn reserve(
+        &mut self,
+        ixs: &[Instruction],
+        markets: &BTreeMap<Pubkey, MarketMetadata>,
+        enforce_limits: bool,
+    ) -> Result<u64, RiskViolation> {
+        let orders = self.evaluate(ixs, markets, enforce_limits)?;
+        let reservation = self.next_reservation;
+        self.next_reservation += 1;
+        self.pending.insert(
+            reservation,
+            PendingTransaction {
+                signature: None,
+                orders,
+            },
+        );
+        Ok(reservation)
+    }
+
+    /// Drops a reservation whose transaction failed to send.
+    pub fn release(&mut self, reservation: u64) {
+        self.pending.remove(&reservation);
+    }
+
+    /// Marks a reservation as sent. If the transaction's events are known they are applied and the
+    /// reservation is dropped; otherwise it stays pending until an event with `signature` is
+    /// applied.
+    pub fn confirm(
+        &mut self,
+        reservation: u64,
+        signature: Signature,
+        events: Option<&[PhoenixEvent]>,
+        markets: &BTreeMap<Pubkey, MarketMetadata>,
+    ) {
+        let Some(events) = events else {
+            if let Some(pending) = self.pending.get_mut(&reservation) {
+                pending.signature = Some(signature);
+            }
+            return;
+        };
+        self.pending.remove(&reservation);
+        for event in events {
+            if let Some(meta) = markets.get(&event.market) {
+                self.apply_event(event, meta);
+            }
+        }
+    }
+
+    /// Checks every order placed by `ixs` against the limits, in order, as if the earlier ones had
+    /// already been sent. Returns the orders that would be reserved.
+    pub fn check(
+        &self,
+        ixs: &[Instruction],
+        markets: &BTreeMap<Pubkey, MarketMetadata>,
+    ) -> Result<Vec<PendingOrder>, RiskViolation> {
+        self.evaluate(ixs, markets, true)
+    }
+
+    fn evaluate(
+        &self,
+        ixs: &[Instruction],
+        markets: &BTreeMap<Pubkey, MarketMetadata>,
+        enforce_limits: bool,
+    ) -> Result<Vec<PendingOrder>, RiskViolation> {
+        let config = if enforce_limits {
+            self.config
+        } else {
+            RiskConfig::default()
+        };
+        let mut checked: Vec<PendingOrder> = vec![];
+        for (market, order_packet) in ixs.iter().flat_map(order_packets) {
+            let Some(meta) = markets.get(&market) else {
+                if enforce_limits {
+                    return Err(RiskViolation::UnknownMarket { market });
+                }
+                continue;
+            };
+            if let Some(limit) = config.min_lamports {
+                let lamports = self
+                    .wallet_balances
+                    .get(&market)
+                    .ok_or(RiskViolation::MissingWalletBalances { market })?
+                    .lamports;
+                if lamports < limit {
+                    return Err(RiskViolation::MinLamports { limit, lamports });
+                }
+            }
+
+            let mid = self.mid_price(&market, meta);
+            let side = order_packet.side();
+            let price_in_ticks = match order_packet.get_price_in_ticks().as_u64() {
+                // A market order has no limit price; assume it trades at the mid.
+                0 | u64::MAX => mid.ok(),
+                price => Some(price),
+            };
+
+            if let Some(limit_pct) = config.price_band_pct {
+                let mid = mid?;
+                let price = price_in_ticks.unwrap_or(mid);
+                let deviation_pct = (price as f64 - mid as f64).abs() / mid as f64 * 100.0;
+                if deviation_pct > limit_pct {
+                    return Err(RiskViolation::PriceBand {
+                        market,
+                        limit_pct,
+                        deviation_pct,
+                    });
+                }
+            }
+
+            let num_base_lots = order_base_lots(&order_packet, meta, price_in_ticks);
+            if let Some(limit_quote_units) = config.max_order_notional_quote_units {
+                let notional_quote_atoms =
+                    match (order_packet.num_quote_lots().as_u64(), price_in_ticks) {
+                        (0, Some(price)) => {
+                            meta.checked_base_lots_and_price_to_quote_atoms(num_base_lots, price)
+                        }
+                        (0, None) => {
+                            return Err(mid
+                                .err()
+                                .unwrap_or(RiskViolation::MissingMidPrice { market }))
+                        }
+                        (quote_lots, _) => quote_lots.checked_mul(meta.quote_atoms_per_quote_lot),
+                    };
+                // A notional too large for u64 quote atoms is over any limit
+                let order_notional_quote_units = notional_quote_atoms
+                    .map(|quote_atoms| meta.quote_atoms_to_quote_units_as_float(quote_atoms))
+                    .unwrap_or(f64::INFINITY);
+                if order_notional_quote_units > limit_quote_units {
+                    return Err(RiskViolation::MaxOrderNotional {
+                        market,
+                        limit_quote_units,
+                        order_notional_quote_units,
+                    });
+                }
+            }
+
+            let order = PendingOrder {
+                market,
+                side,
+                num_base_lots,
+                rests: !order_packet.is_take_only(),
+            };
+            if let Some(limit_base_units) = config.max_position_base_units {
+                let projected_base_lots = self.worst_case_exposure(&order, &checked);
+                let projected_position_base_units =
+                    projected_base_lots as f64 / meta.num_base_lots_per_base_unit as f64;
+                if projected_position_base_units > limit_base_units {
+                    return Err(RiskViolation::MaxPosition {
+                        market,
+                        limit_base_units,
+                        projected_position_base_units,
+                    });
+                }
+            }
+            if let Some(limit) = config.max_open_orders {
+                if order.rests {
+                    let projected_open_orders = self.orders.open_order_count()
+                        + self
+                            .pending_orders()
+                            .chain(checked.iter())
+                            .filter(|order| order.rests)
+                            .count()
+                        + 1;
+                    if projected_open_orders > limit {
+                        return Err(RiskViolation::MaxOpenOrders {
+                            limit,
+                            projected_open_orders,
+                        });
+                    }
+                }
+            }
+            checked.push(order);
+        }
+        Ok(checked)
+    }
+
+    /// Position in base lots, in the direction of `order`, if everything on that side filled.
+    fn worst_case_exposure(&self, order: &PendingOrder, checked: &[PendingOrder]) -> u64 {
+        let position = self.positions.position(&order.market).base_lots;
+        let directional_position = match order.side {
+            Side::Bid => position,
+            Side::Ask => -position,
+        };
+        let in_flight: u64 = self
+            .pending_orders()
+            .chain(checked.iter())
+            .filter(|pending| pending.market == order.market && pending.side == order.side)
+            .map(|pending| pending.num_base_lots)
+            .sum();
+        let exposure = directional_position
+            + (self.orders.resting_base_lots(&order.market, order.side)
+                + in_flight
+                + order.num_base_lots) as i64;
+        exposure.max(0) as u64
+    }
+}
+
+/// Decodes the orders an instruction places, with their market. Non-Phoenix and non-order
+/// instructions yield nothing.
+pub(crate) fn order_packets(ix: &Instruction) -> Vec<(Pubkey, OrderPacket)> {
+    if ix.program_id != phoenix::id() {
+        return vec![];
+    }
+    let (Some((tag, data)), Some(market)) = (ix.data.split_first(), ix.accounts.get(2)) else {
+        return vec![];
+    };
+    match PhoenixInstruction::try_from(*tag) {
+        Ok(
+            PhoenixInstruction::Swap
+            | PhoenixInstruction::SwapWithFreeFunds
+            | PhoenixInstruction::PlaceLimitOrder
+            | PhoenixInstruction::PlaceLimitOrderWithFreeFunds,
+        ) => decode_order_packet(data)
+            .map(|packet| vec![(market.pubkey, packet)])
+            .unwrap_or_default(),
+        Ok(
+            PhoenixInstruction::PlaceMultiplePostOnlyOrders
+            | PhoenixInstruction::PlaceMultiplePostOnlyOrdersWithFreeFunds,
+        ) => MultipleOrderPacket::try_from_slice(data)
+            .map(|packet| {
+                let bids = packet.bids.iter().map(|order| (Side::Bid, order));
+                let asks = packet.asks.iter().map(|order| (Side::Ask, order));
+                bids.chain(asks)
+                    .map(|(side, order)| {
+                        (
+                            market.pubkey,
+                            OrderPacket::new_post_only_default(
+                                side,
+                                order.price_in_ticks,
+                                order.size_in_base_lots,
+                            ),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        _ => vec![],
+    }
+}
+
+/// Size of an order in base lots. Orders sized in quote lots are converted at their limit price.
+fn order_base_lots(
+    order_packet: &OrderPacket,
+    meta: &MarketMetadata,
+    price_in_ticks: Option<u64>,
+) -> u64 {
+    let num_base_lots = order_packet.num_base_lots().as_u64();
+    if num_base_lots > 0 {
+        return num_base_lots;
+    }
+    let quote_atoms =
+        order_packet.num_quote_lots().as_u64() as u128 * meta.quote_atoms_per_quote_lot as u128;
+    match price_in_ticks {
+        Some(price) if price > 0 => {
+            (quote_atoms * meta.num_base_lots_per_base_unit as u128
+                / (price as u128 * meta.tick_size_in_quote_atoms_per_base_unit as u128))
+                as u64
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::paper_trading::test_utils::{paper_client, test_market_metadata};
+    use phoenix_sdk_core::market_map::MarketMap;
+    use phoenix_sdk_core::sdk_client_core::SDKClientCore;
+
+    fn setup(config: RiskConfig) -> (SDKClientCore, Pubkey, RiskManager) {
+        let market = Pubkey::new_unique();
+        let core = SDKClientCore {
+            markets: MarketMap::from_iter([(market, test_market_metadata())]),
+            trader: Pubkey::new_unique(),
+            default_self_trade_behavior: Default::default(),
+        };
+        let risk_manager = RiskManager::new(core.trader, config);
+        (core, market, risk_manager)
+    }
+
+    fn post_only(
+        core: &SDKClientCore,
+        market: &Pubkey,
+        side: Side,
+        price: u64,
+        lots: u64,
+    ) -> Instruction {
+        core.get_post_only_ix_from_tick_price(market, price, side, lots, 0, false)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_notional_and_price_band() {
+        let (core, market, mut risk_manager) = setup(RiskConfig {
+            max_order_notional_quote_units: Some(0.05),
+            price_band_pct: Some(5.0),
+            ..Default::default()
+        });
+        // 1000 lots (one base unit) at 100 ticks of 0.001 quote units each
+        let ix = post_only(&core, &market, Side::Bid, 100, 1000);
+        assert_eq!(
+            risk_manager.check(std::slice::from_ref(&ix), &core.markets.load()),
+            Err(RiskViolation::MissingMidPrice { market })
+        );
+
+        risk_manager.set_mid_price(&market, 100);
+        assert_eq!(
+            risk_manager.check(&[ix], &core.markets.load()),
+            Err(RiskViolation::MaxOrderNotional {
+                market,
+                limit_quote_units: 0.05,
+                order_notional_quote_units: 0.1,
+            })
+        );
+        let ix = post_only(&core, &market, Side::Bid, 94, 100);
+        match risk_manager.check(&[ix], &core.markets.load()) {
+            Err(RiskViolation::PriceBand { deviation_pct, .. }) => {
+                assert!((deviation_pct - 6.0).abs() < 1e-9)
+            }
+            result => panic!("Expected a price band violation, got {:?}", result),
+        }
+        let ix = post_only(&core, &market, Side::Bid, 96, 100);
+        assert!(risk_manager.check(&[ix], &core.markets.load()).is_ok());
+
+        let meta = *core.markets.load().get(&market).unwrap();
+        let price = risk_manager
+            .clamp_to_price_band(&market, &meta, 94)
+            .unwrap();
+        assert_eq!(price, 95);
+        let ix = post_only(&core, &market, Side::Bid, price, 100);
+        assert!(risk_manager.check(&[ix], &core.markets.load()).is_ok());
+
+        // A notional beyond u64 quote atoms does not saturate under the limit
+        let (core, market, mut risk_manager) = setup(RiskConfig {
+            max_order_notional_quote_units: Some(f64::MAX),
+            ..Default::default()
+        });
+        risk_manager.set_mid_price(&market, 100);
+        let ix = post_only(&core, &market, Side::Ask, u64::MAX, u64::MAX);
+        assert_eq!(
+            risk_manager.check(&[ix], &core.markets.load()),
+            Err(RiskViolation::MaxOrderNotional {
+                market,
+                limit_quote_units: f64::MAX,
+                order_notional_quote_units: f64::INFINITY,
+            })
+        );
+    }
+
+    #[test]
+    fn test_price_band_from_reference_feed() {
+        use phoenix_sdk_core::reference_price::{ReferencePrice, SharedReferencePrices};
+
+        use crate::event_recorder::now_ms;
+
+        let (core, market, mut risk_manager) = setup(RiskConfig {
+            price_band_pct: Some(5.0),
+            ..Default::default()
+        });
+        let prices = SharedReferencePrices::new();
+        risk_manager.set_reference_price_feed(Some(ReferencePriceFeed::new(
+            prices.clone(),
+            Duration::from_secs(10),
+        )));
+        // The feed replaces the mids set by hand
+        risk_manager.set_mid_price(&market, 100);
+        let ix = post_only(&core, &market, Side::Bid, 100, 100);
+        assert_eq!(
+            risk_manager.check(std::slice::from_ref(&ix), &core.markets.load()),
+            Err(RiskViolation::MissingMidPrice { market })
+        );
+
+        // 0.094 quote units is 94 ticks, so an order at 100 ticks is 6.4% away
+        let reference = |price, timestamp_ms| ReferencePrice {
+            price,
+            confidence: 0.0,
+            timestamp_ms,
+        };
+        prices.set(&market, reference(0.094, now_ms()));
+        assert!(matches!(
+            risk_manager.check(std::slice::from_ref(&ix), &core.markets.load()),
+            Err(RiskViolation::PriceBand { .. })
+        ));
+        prices.set(&market, reference(0.098, now_ms()));
+        assert!(risk_manager
+            .check(std::slice::from_ref(&ix), &core.markets.load())
+            .is_ok());
+
+        prices.set(&market, reference(0.098, now_ms() - 20_000));
+        assert!(matches!(
+            risk_manager.check(&[ix], &core.markets.load()),
+            Err(RiskViolation::StaleReferencePrice { max_age, .. }) if max_age == Duration::from_secs(10)
+        ));
+    }
+
+    #[test]
+    fn test_min_lamports() {
+        let (core, market, mut risk_manager) = setup(RiskConfig {
+            min_lamports: Some(5_000_000),
+            ..Default::default()
+        });
+        let ix = post_only(&core, &market, Side::Bid, 100, 1000);
+        assert_eq!(
+            risk_manager.check(std::slice::from_ref(&ix), &core.markets.load()),
+            Err(RiskViolation::MissingWalletBalances { market })
+        );
+
+        let meta = core.markets.get(&market).unwrap();
+        risk_manager.set_wallet_balances(&market, WalletBalances::new(&meta, 0, 0, 1_000_000));
+        assert_eq!(
+            risk_manager.check(std::slice::from_ref(&ix), &core.markets.load()),
+            Err(RiskViolation::MinLamports {
+                limit: 5_000_000,
+                lamports: 1_000_000,
+            })
+        );
+        risk_manager.set_wallet_balances(&market, WalletBalances::new(&meta, 0, 0, 5_000_000));
+        assert!(risk_manager.check(&[ix], &core.markets.load()).is_ok());
+    }
+
+    #[test]
+    fn test_reservations_count_towards_limits() {
+        let (core, market, mut risk_manager) = setup(RiskConfig {
+            max_position_base_units: Some(1.5),
+            max_open_orders: Some(2),
+            ..Default::default()
+        });
+        let bid = post_only(&core, &market, Side::Bid, 100, 1000);
+        let first = risk_manager
+            .reserve(std::slice::from_ref(&bid), &core.markets.load(), true)
+            .unwrap();
+        assert_eq!(
+            risk_manager.reserve(std::slice::from_ref(&bid), &core.markets.load(), true),
+            Err(RiskViolation::MaxPosition {
+                market,
+                limit_base_units: 1.5,
+                projected_position_base_units: 2.0,
+            })
+        );
+        // Selling reduces the worst-case long, so only the short side is checked
+        let ask = post_only(&core, &market, Side::Ask, 110, 1000);
+        risk_manager
+            .reserve(std::slice::from_ref(&ask), &core.markets.load(), true)
+            .unwrap();
+        let small_ask = post_only(&core, &market, Side::Ask, 110, 100);
+        assert_eq!(
+            risk_manager.check(&[small_ask], &core.markets.load()),
+            Err(RiskViolation::MaxOpenOrders {
+                limit: 2,
+                projected_open_orders: 3,
+            })
+        );
+        // IOC orders never rest
+        let ioc = core
+            .get_ioc_from_tick_price_generic_ix(&market, 110, Side::Ask, 100, None, None, None)
+            .unwrap();
+        assert!(risk_manager.check(&[ioc], &core.markets.load()).is_ok());
+
+        risk_manager.release(first);
+        assert!(risk_manager.check(&[bid], &core.markets.load()).is_ok());
+        // Overrides are still reserved
+        risk_manager
+            .reserve(&[ask], &core.markets.load(), false)
+            .unwrap();
+        assert_eq!(risk_manager.pending_orders().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_ixs_enforces_limits() {
+        let (sdk, market) = paper_client(&[(Side::Ask, 101, 500)]).await;
+        sdk.set_risk_manager(Some(RiskManager::new(
+            sdk.trader,
+            RiskConfig {
+                max_position_base_units: Some(0.6),
+                max_open_orders: Some(1),
+                ..Default::default()
+            },
+        )));
+
+        let ioc = sdk
+            .get_ioc_from_tick_price_generic_ix(&market, 101, Side::Bid, 500, None, None, None)
+            .unwrap();
+        sdk.send_ixs(vec![ioc]).await.unwrap();
+        assert_eq!(
+            sdk.risk_manager()
+                .as_ref()
+                .unwrap()
+                .positions
+                .position(&market)
+                .base_lots,
+            500
+        );
+
+        // The filled 500 lots count towards the position limit
+        let error = sdk
+            .send_ixs(vec![post_only(&sdk, &market, Side::Bid, 95, 200)])
+            .await
+            .unwrap_err();
+        match error.downcast_ref::<RiskViolation>() {
+            Some(RiskViolation::MaxPosition {
+                projected_position_base_units,
+                ..
+            }) => assert!((projected_position_base_units - 0.7).abs() < 1e-9),
+            _ => panic!("Expected a position violation, got {}", error),
+        }
+        sdk.send_ixs(vec![post_only(&sdk, &market, Side::Bid, 95, 100)])
+            .await
+            .unwrap();
+        assert_eq!(
+            sdk.risk_manager()
+                .as_ref()
+                .unwrap()
+                .orders
+                .open_order_count(),
+            1
+        );
+
+        let ask = post_only(&sdk, &market, Side::Ask, 110, 100);
+        let error = sdk.send_ixs(vec![ask.clone()]).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<RiskViolation>(),
+            Some(&RiskViolation::MaxOpenOrders {
+                limit: 1,
+                projected_open_orders: 2,
+            })
+        );
+        assert!(sdk.paper_exchange().open_orders(&market).len() == 1);
+        sdk.send_ixs_with_risk_override(vec![ask], true)
+            .await
+            .unwrap();
+        let risk_manager = sdk.risk_manager();
+        let risk_manager = risk_manager.as_ref().unwrap();
+        assert_eq!(risk_manager.orders.open_order_count(), 2);
+        assert_eq!(risk_manager.pending_orders().count(), 0);
+    }
+}
@@ -0,0 +1,493 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use ellipsis_client::EllipsisClient;
+use futures::future::join_all;
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::transaction::Transaction;
+
+use crate::error::PhoenixSdkError;
+use crate::health::{
+    serialize_optional_millis, ComponentDetails, ComponentHealth, HealthProvider, HealthStatus,
+    HealthThresholds,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RpcPoolConfig {
+    /// A request that takes longer than this counts as a failure of the endpoint.
+    pub request_timeout: Duration,
+    /// Consecutive failures after which an endpoint is skipped until it recovers.
+    pub failure_threshold: u32,
+    /// How long an unhealthy endpoint is left alone before `probe` checks it again.
+    pub probe_interval: Duration,
+    /// Send each transaction to every endpoint at once instead of only the first that accepts it.
+    pub broadcast_transactions: bool,
+    /// How long `sign_send_instructions` waits for a sent transaction to confirm.
+    pub confirmation_timeout: Duration,
+}
+
+impl Default for RpcPoolConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(10),
+            failure_threshold: 3,
+            probe_interval: Duration::from_secs(30),
+            broadcast_transactions: false,
+            confirmation_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// What the pool knows about one endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EndpointHealth {
+    pub url: String,
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    /// Requests this endpoint answered successfully.
+    pub requests_served: u64,
+    pub failures: u64,
+    pub last_error: Option<String>,
+    /// How long the most recent successful request took.
+    #[serde(serialize_with = "serialize_optional_millis")]
+    pub last_latency: Option<Duration>,
+}
+
+struct Endpoint {
+    client: EllipsisClient,
+    health: Mutex<EndpointHealth>,
+    last_failure_at: Mutex<Option<Instant>>,
+}
+
+/// An ordered list of RPC endpoints used as one. Reads go to the first healthy endpoint and fail
+/// over down the list on errors and timeouts. An endpoint is marked unhealthy after
+/// `failure_threshold` consecutive failures and is then only tried once every healthy endpoint
+/// has failed, until a successful request or `probe` brings it back.
+pub struct RpcPool {
+    pub config: RpcPoolConfig,
+    endpoints: Vec<Endpoint>,
+    last_served_by: AtomicUsize,
+}
+
+impl RpcPool {
+    pub fn new(
+        urls: &[String],
+        payer: &Keypair,
+        commitment: CommitmentConfig,
+        config: RpcPoolConfig,
+    ) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(anyhow!("An RPC pool needs at least one endpoint"));
+        }
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                let rpc = RpcClient::new_with_commitment(url.clone(), commitment);
+                let client = EllipsisClient::from_rpc(rpc, payer)
+                    .map_err(|e| anyhow!("Failed to create client for {}: {}", url, e))?;
+                Ok(Endpoint {
+                    client,
+                    health: Mutex::new(EndpointHealth {
+                        url: url.clone(),
+                        healthy: true,
+                        consecutive_failures: 0,
+                        requests_served: 0,
+                        failures: 0,
+                        last_error: None,
+                        last_latency: None,
+                    }),
+                    last_failure_at: Mutex::new(None),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            config,
+            endpoints,
+            last_served_by: AtomicUsize::new(usize::MAX),
+        })
+    }
+
+    /// The client of the first endpoint, for calls that do not need failover.
+    pub fn primary(&self) -> &EllipsisClient {
+        &self.endpoints[0].client
+    }
+
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| lock(&endpoint.health).clone())
+            .collect()
+    }
+
+    /// The URL of the endpoint that answered the most recent successful request.
+    pub fn last_served_by(&self) -> Option<String> {
+        let index = self.last_served_by.load(Ordering::SeqCst);
+        self.endpoints
+            .get(index)
+            .map(|endpoint| lock(&endpoint.health).url.clone())
+    }
+
+    /// Runs `request` against each endpoint in turn until one succeeds.
+    pub async fn read<'a, T, E, F, Fut>(&'a self, request: F) -> Result<T>
+    where
+        F: Fn(&'a EllipsisClient) -> Fut,
+        Fut: Future<Output = std::result::Result<T, E>>,
+        E: Display,
+    {
+        self.read_with_endpoint(request)
+            .await
+            .map(|(value, _)| value)
+    }
+
+    /// Like `read`, also returning the URL of the endpoint that served the request.
+    pub async fn read_with_endpoint<'a, T, E, F, Fut>(&'a self, request: F) -> Result<(T, String)>
+    where
+        F: Fn(&'a EllipsisClient) -> Fut,
+        Fut: Future<Output = std::result::Result<T, E>>,
+        E: Display,
+    {
+        let mut errors = vec![];
+        for index in self.candidates() {
+            let endpoint = &self.endpoints[index];
+            let started_at = Instant::now();
+            let error =
+                match tokio::time::timeout(self.config.request_timeout, request(&endpoint.client))
+                    .await
+                {
+                    Ok(Ok(value)) => {
+                        self.record_success(index, started_at.elapsed());
+                        return Ok((value, lock(&endpoint.health).url.clone()));
+                    }
+                    Ok(Err(e)) => e.to_string(),
+                    Err(_) => "request timed out".to_string(),
+                };
+            self.record_failure(index, &error);
+            errors.push(format!("{}: {}", lock(&endpoint.health).url, error));
+        }
+        Err(anyhow!("All RPC endpoints failed: {}", errors.join("; ")))
+    }
+
+    /// Sends a signed transaction. With `broadcast_transactions` it goes to every endpoint
+    /// concurrently and succeeds if any accepts it; duplicates of one signed transaction are
+    /// harmless. Otherwise it fails over like a read. A transaction rejected in preflight
+    /// simulation is returned as a `PhoenixSdkError::TransactionFailed` with the simulation's logs.
+    pub async fn send_transaction(&self, transaction: &Transaction) -> Result<Signature> {
+        if !self.config.broadcast_transactions {
+            // A transaction rejected in simulation would be rejected by every endpoint, so it is
+            // returned as is rather than failed over
+            return self
+                .read(|client| async move {
+                    match client.send_transaction(transaction).await {
+                        Ok(signature) => Ok(Ok(signature)),
+                        Err(e) => PhoenixSdkError::from_client_error(&e).map(Err).ok_or(e),
+                    }
+                })
+                .await?
+                .map_err(Into::into);
+        }
+        let results = join_all(self.endpoints.iter().map(|endpoint| async {
+            let started_at = Instant::now();
+            let result = tokio::time::timeout(
+                self.config.request_timeout,
+                endpoint.client.send_transaction(transaction),
+            )
+            .await;
+            (result, started_at.elapsed())
+        }))
+        .await;
+        let mut signature = None;
+        let mut rejected = None;
+        let mut errors = vec![];
+        for (index, (result, latency)) in results.into_iter().enumerate() {
+            match result {
+                Ok(Ok(sent)) => {
+                    self.record_success(index, latency);
+                    signature.get_or_insert(sent);
+                }
+                Ok(Err(e)) if rejected.is_none() => match PhoenixSdkError::from_client_error(&e) {
+                    Some(failed) => rejected = Some(failed),
+                    None => {
+                        self.record_failure(index, &e.to_string());
+                        errors.push(e.to_string());
+                    }
+                },
+                Ok(Err(e)) => {
+                    self.record_failure(index, &e.to_string());
+                    errors.push(e.to_string());
+                }
+                Err(_) => {
+                    self.record_failure(index, "request timed out");
+                    errors.push("request timed out".to_string());
+                }
+            }
+        }
+        if let (None, Some(rejected)) = (signature, rejected) {
+            return Err(rejected.into());
+        }
+        signature.ok_or_else(|| {
+            anyhow!(
+                "Transaction rejected by every endpoint: {}",
+                errors.join("; ")
+            )
+        })
+    }
+
+    /// Signs `instructions` with `payer`, sends them with `send_transaction` and waits for
+    /// confirmation, failing if the transaction errors or does not confirm in time.
+    pub async fn sign_send_instructions(
+        &self,
+        instructions: Vec<Instruction>,
+        payer: &Keypair,
+    ) -> Result<Signature> {
+        self.sign_send_instructions_with_commitment(
+            instructions,
+            payer,
+            CommitmentConfig::confirmed(),
+        )
+        .await
+    }
+
+    /// Like `sign_send_instructions`, waiting for the transaction to reach `commitment`.
+    pub async fn sign_send_instructions_with_commitment(
+        &self,
+        instructions: Vec<Instruction>,
+        payer: &Keypair,
+        commitment: CommitmentConfig,
+    ) -> Result<Signature> {
+        let blockhash = self.read(|client| client.get_latest_blockhash()).await?;
+        let transaction = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&payer.pubkey()),
+            &[payer],
+            blockhash,
+        );
+        let signature = self.send_transaction(&transaction).await?;
+        self.confirm_with_commitment(signature, commitment).await
+    }
+
+    /// Waits up to `confirmation_timeout` for a sent transaction to confirm, failing if it errors.
+    /// An on-chain failure is returned as a `PhoenixSdkError::TransactionFailed` without logs.
+    pub async fn confirm(&self, signature: Signature) -> Result<Signature> {
+        self.confirm_with_commitment(signature, CommitmentConfig::confirmed())
+            .await
+    }
+
+    /// Like `confirm`, waiting for the transaction to reach `commitment`.
+    pub async fn confirm_with_commitment(
+        &self,
+        signature: Signature,
+        commitment: CommitmentConfig,
+    ) -> Result<Signature> {
+        let deadline = Instant::now() + self.config.confirmation_timeout;
+        while Instant::now() < deadline {
+            let statuses = self
+                .read(|client| client.get_signature_statuses(std::slice::from_ref(&signature)))
+                .await;
+            if let Ok(response) = statuses {
+                if let Some(Some(status)) = response.value.first() {
+                    if let Some(e) = &status.err {
+                        return Err(PhoenixSdkError::transaction_failed(
+                            Some(signature),
+                            e.clone(),
+                            vec![],
+                        )
+                        .into());
+                    }
+                    if status.satisfies_commitment(commitment) {
+                        return Ok(signature);
+                    }
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        Err(anyhow!(
+            "Transaction {} was not confirmed in time",
+            signature
+        ))
+    }
+
+    /// Checks unhealthy endpoints that have been left alone for `probe_interval`, marking those
+    /// that answer as healthy again.
+    pub async fn probe(&self) {
+        let due = (0..self.endpoints.len())
+            .filter(|&index| {
+                !lock(&self.endpoints[index].health).healthy
+                    && lock(&self.endpoints[index].last_failure_at)
+                        .is_none_or(|at| at.elapsed() >= self.config.probe_interval)
+            })
+            .collect::<Vec<_>>();
+        for index in due {
+            let endpoint = &self.endpoints[index];
+            match tokio::time::timeout(self.config.request_timeout, endpoint.client.get_slot())
+                .await
+            {
+                Ok(Ok(_)) => self.record_recovery(index),
+                Ok(Err(e)) => self.record_failure(index, &e.to_string()),
+                Err(_) => self.record_failure(index, "request timed out"),
+            }
+        }
+    }
+
+    /// Probes unhealthy endpoints every `probe_interval`, forever.
+    pub async fn run_probes(&self) {
+        let mut interval = tokio::time::interval(self.config.probe_interval);
+        loop {
+            interval.tick().await;
+            self.probe().await;
+        }
+    }
+
+    /// Healthy endpoints in order, then unhealthy ones as a last resort.
+    fn candidates(&self) -> Vec<usize> {
+        let (mut healthy, unhealthy): (Vec<usize>, Vec<usize>) = (0..self.endpoints.len())
+            .partition(|&index| lock(&self.endpoints[index].health).healthy);
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    fn record_success(&self, index: usize, latency: Duration) {
+        self.record_recovery(index);
+        let mut health = lock(&self.endpoints[index].health);
+        health.requests_served += 1;
+        health.last_latency = Some(latency);
+        self.last_served_by.store(index, Ordering::SeqCst);
+    }
+
+    fn record_recovery(&self, index: usize) {
+        let mut health = lock(&self.endpoints[index].health);
+        health.healthy = true;
+        health.consecutive_failures = 0;
+    }
+
+    fn record_failure(&self, index: usize, error: &str) {
+        let endpoint = &self.endpoints[index];
+        let mut health = lock(&endpoint.health);
+        health.failures += 1;
+        health.consecutive_failures += 1;
+        health.last_error = Some(error.to_string());
+        if health.consecutive_failures >= self.config.failure_threshold {
+            health.healthy = false;
+        }
+        *lock(&endpoint.last_failure_at) = Some(Instant::now());
+    }
+}
+
+/// Degraded while any endpoint is unhealthy, and unhealthy once all are.
+impl HealthProvider for RpcPool {
+    fn health_check(&self, _thresholds: &HealthThresholds) -> ComponentHealth {
+        let endpoints = self.health();
+        let unhealthy = endpoints
+            .iter()
+            .filter(|endpoint| !endpoint.healthy)
+            .count();
+        ComponentHealth {
+            status: match unhealthy {
+                0 => HealthStatus::Healthy,
+                _ if unhealthy == endpoints.len() => HealthStatus::Unhealthy,
+                _ => HealthStatus::Degraded,
+            },
+            details: ComponentDetails::RpcPool { endpoints },
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pool(failure_threshold: u32) -> RpcPool {
+        let urls = ["http://primary:8899", "http://backup:8899"].map(String::from);
+        RpcPool::new(
+            &urls,
+            &Keypair::new(),
+            CommitmentConfig::confirmed(),
+            RpcPoolConfig {
+                failure_threshold,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_failover_and_recovery() {
+        let pool = pool(2);
+        let primary_up = Mutex::new(false);
+        // Stands in for an RPC call: the primary fails while it is down
+        let request = |client: &EllipsisClient| {
+            let url = client.url();
+            let is_up = !url.contains("primary") || *primary_up.lock().unwrap();
+            async move {
+                match is_up {
+                    true => Ok(url),
+                    false => Err("connection refused"),
+                }
+            }
+        };
+
+        let (value, served_by) = pool.read_with_endpoint(request).await.unwrap();
+        assert_eq!(value, "http://backup:8899");
+        assert_eq!(served_by, "http://backup:8899");
+        assert!(pool.health()[0].healthy);
+
+        // The second consecutive failure marks the primary unhealthy
+        pool.read(request).await.unwrap();
+        let health = pool.health();
+        assert!(!health[0].healthy);
+        assert_eq!(health[0].failures, 2);
+        assert_eq!(health[0].last_error.as_deref(), Some("connection refused"));
+        assert_eq!(health[1].requests_served, 2);
+        assert!(health[1].last_latency.is_some());
+        assert_eq!(health[0].last_latency, None);
+        let status = pool.health_check(&HealthThresholds::default()).status;
+        assert_eq!(status, HealthStatus::Degraded);
+
+        // Unhealthy endpoints are skipped while another is healthy, then used once it recovers
+        *primary_up.lock().unwrap() = true;
+        pool.read(request).await.unwrap();
+        assert_eq!(pool.last_served_by().as_deref(), Some("http://backup:8899"));
+        pool.record_recovery(0);
+        assert_eq!(pool.read(request).await.unwrap(), "http://primary:8899");
+    }
+
+    #[tokio::test]
+    async fn test_all_endpoints_failing() {
+        let pool = pool(1);
+        let result = pool
+            .read(|client: &EllipsisClient| {
+                let url = client.url();
+                async move { Err::<(), _>(format!("{} is down", url)) }
+            })
+            .await;
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("http://primary:8899 is down"));
+        assert!(error.contains("http://backup:8899 is down"));
+        assert!(pool.health().iter().all(|health| !health.healthy));
+        let status = pool.health_check(&HealthThresholds::default()).status;
+        assert_eq!(status, HealthStatus::Unhealthy);
+        assert_eq!(pool.last_served_by(), None);
+
+        // With everything unhealthy, requests are still attempted in order
+        let served_by = pool
+            .read_with_endpoint(|_| async { Ok::<_, String>(()) })
+            .await
+            .unwrap()
+            .1;
+        assert_eq!(served_by, "http://primary:8899");
+        assert!(pool.health()[0].healthy);
+    }
+}
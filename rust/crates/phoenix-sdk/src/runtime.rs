@@ -0,0 +1,262 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::{broadcast, mpsc, Mutex};
+
+use crate::book_subscriber::{BookSubscriber, BookUpdate};
+use crate::event_router::EventRouter;
+use crate::quote_guard::{QuoteGuard, QuoteGuardConfig};
+use crate::sdk_client::SDKClient;
+use crate::sdk_market_event::SDKMarketEvent;
+use crate::task_supervisor::{TaskHealth, TaskOptions, TaskSupervisor};
+use crate::trigger_engine::{TriggerEngine, TriggerExecution};
+
+pub const RPC_PROBES_TASK: &str = "rpc_probes";
+pub const EVENT_ROUTER_TASK: &str = "event_router";
+pub const TRIGGER_ENGINE_TASK: &str = "trigger_engine";
+pub const QUOTE_GUARD_TASK: &str = "quote_guard";
+pub const TASKS_HEALTH: &str = "tasks";
+
+/// Starts the SDK's background components under one `TaskSupervisor`, so they share a lifecycle.
+///
+/// Each `spawn_*` method registers its component under a fixed task name (the book subscriber's
+/// includes its market), which other tasks can list in `TaskOptions::depends_on` to be stopped
+/// after it. Channels a component reads from are kept by the runtime between runs, so a restarted
+/// component resumes where the failed run stopped; the batch being processed when it failed is
+/// lost.
+pub struct PhoenixRuntime {
+    sdk: Arc<SDKClient>,
+    supervisor: Arc<TaskSupervisor>,
+}
+
+impl PhoenixRuntime {
+    /// If the client has an `RpcPool`, its probes run as the `"rpc_probes"` task. The supervisor
+    /// is registered with the client's `health` as `"tasks"`.
+    pub fn new(sdk: Arc<SDKClient>) -> Result<Self> {
+        let runtime = Self {
+            sdk,
+            supervisor: Arc::new(TaskSupervisor::new()),
+        };
+        runtime
+            .sdk
+            .register_health_provider(TASKS_HEALTH, runtime.supervisor.clone());
+        if let Some(rpc_pool) = runtime.sdk.rpc_pool().cloned() {
+            runtime
+                .supervisor
+                .spawn_supervised(RPC_PROBES_TASK, move || {
+                    let rpc_pool = rpc_pool.clone();
+                    async move {
+                        rpc_pool.run_probes().await;
+                        Ok(())
+                    }
+                })?;
+        }
+        Ok(runtime)
+    }
+
+    pub fn sdk(&self) -> &Arc<SDKClient> {
+        &self.sdk
+    }
+
+    /// The supervisor, to run application tasks alongside the SDK's.
+    pub fn supervisor(&self) -> &TaskSupervisor {
+        &self.supervisor
+    }
+
+    pub fn health(&self) -> Vec<TaskHealth> {
+        self.supervisor.health()
+    }
+
+    /// Runs `subscriber` as the `"book_subscriber:<market>"` task and returns a receiver of its
+    /// updates. A restart resubscribes from scratch, so its first update is a full diff. Its
+    /// staleness is registered with the client's `health` under the task's name.
+    pub fn spawn_book_subscriber(
+        &self,
+        subscriber: BookSubscriber,
+        options: TaskOptions,
+    ) -> Result<broadcast::Receiver<BookUpdate>> {
+        let updates = subscriber.subscribe();
+        let name = format!("book_subscriber:{}", subscriber.market_key());
+        let health = subscriber.health();
+        self.supervisor
+            .spawn_supervised_with(name.clone(), options, move || subscriber.clone().run())?;
+        self.sdk.register_health_provider(name, health);
+        Ok(updates)
+    }
+
+    /// Routes `events` through `router` as the `"event_router"` task. Subscribe through clones of
+    /// `router`.
+    pub fn spawn_event_router(
+        &self,
+        router: EventRouter,
+        events: mpsc::Receiver<Vec<SDKMarketEvent>>,
+        options: TaskOptions,
+    ) -> Result<()> {
+        let events = Arc::new(Mutex::new(events));
+        self.supervisor
+            .spawn_supervised_with(EVENT_ROUTER_TASK, options, move || {
+                let (router, events) = (router.clone(), events.clone());
+                async move {
+                    router.route_from(&mut *events.lock_owned().await).await;
+                    Ok(())
+                }
+            })
+    }
+
+    /// Runs `engine` on `events` as the `"trigger_engine"` task, sending fired orders through the
+    /// runtime's client. Returns the engine, to add triggers while it runs; it is locked while a
+    /// batch is being evaluated.
+    pub fn spawn_trigger_engine(
+        &self,
+        engine: TriggerEngine,
+        events: mpsc::Receiver<Vec<SDKMarketEvent>>,
+        executions: Option<mpsc::Sender<TriggerExecution>>,
+        options: TaskOptions,
+    ) -> Result<Arc<Mutex<TriggerEngine>>> {
+        let engine = Arc::new(Mutex::new(engine));
+        let events = Arc::new(Mutex::new(events));
+        let (sdk, task_engine) = (self.sdk.clone(), engine.clone());
+        self.supervisor
+            .spawn_supervised_with(TRIGGER_ENGINE_TASK, options, move || {
+                let (sdk, engine, events) = (sdk.clone(), task_engine.clone(), events.clone());
+                let executions = executions.clone();
+                async move {
+                    let mut events = events.lock_owned().await;
+                    while let Some(batch) = events.recv().await {
+                        engine
+                            .lock()
+                            .await
+                            .execute(&sdk, &batch, executions.as_ref())
+                            .await?;
+                    }
+                    Ok(())
+                }
+            })?;
+        Ok(engine)
+    }
+
+    /// Starts a `QuoteGuard` on `sdk`, which should be a client of its own (see `QuoteGuard`), and
+    /// runs it as the `"quote_guard"` task. The task finishes once the guard trips. The guard's
+    /// drop hook still fires when the last handle to it is dropped, so `disarm` it before
+    /// shutting down to leave the quotes resting.
+    pub async fn start_quote_guard(
+        &self,
+        sdk: Arc<SDKClient>,
+        config: QuoteGuardConfig,
+        options: TaskOptions,
+    ) -> Result<Arc<QuoteGuard>> {
+        let guard = Arc::new(QuoteGuard::start(sdk, config).await?);
+        let task_guard = guard.clone();
+        self.supervisor
+            .spawn_supervised_with(QUOTE_GUARD_TASK, options, move || {
+                let guard = task_guard.clone();
+                async move {
+                    let reason = guard.run().await;
+                    println!("Quote guard tripped: {:?}", reason);
+                    Ok(())
+                }
+            })?;
+        Ok(guard)
+    }
+
+    /// Stops every task, producers before consumers. See `TaskSupervisor::shutdown`.
+    pub async fn shutdown(&self) {
+        self.supervisor.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use phoenix::state::enums::Side;
+    use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent, Place};
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::event_router::Backpressure;
+    use crate::paper_trading::test_utils::paper_client;
+    use crate::task_supervisor::TaskState;
+    use crate::trigger_engine::{TriggerAction, TriggerCondition};
+    use phoenix_sdk_core::test_utils::event;
+
+    #[tokio::test]
+    async fn test_runtime_wires_components() {
+        let (sdk, market) = paper_client(&[(Side::Ask, 105, 100)]).await;
+        let runtime = PhoenixRuntime::new(Arc::new(sdk)).unwrap();
+        let (router_sender, router_events) = mpsc::channel(8);
+        let router = EventRouter::new(8);
+        let mut routed = router.subscribe(market, Backpressure::Wait);
+        runtime
+            .spawn_event_router(router, router_events, TaskOptions::new())
+            .unwrap();
+        let (engine_sender, engine_events) = mpsc::channel(8);
+        let (executions, mut executed) = mpsc::channel(8);
+        let engine = runtime
+            .spawn_trigger_engine(
+                TriggerEngine::new(),
+                engine_events,
+                Some(executions),
+                TaskOptions::new(),
+            )
+            .unwrap();
+        engine
+            .lock()
+            .await
+            .add_trigger(
+                &market,
+                TriggerCondition::BestBidAtOrAbove {
+                    price_in_ticks: 100,
+                    consecutive_events: 1,
+                },
+                TriggerAction::ImmediateOrCancel {
+                    side: Side::Bid,
+                    price_in_ticks: 105,
+                    num_base_lots: 10,
+                },
+            )
+            .unwrap();
+
+        let place = PhoenixEvent {
+            sequence_number: 1,
+            slot: 1,
+            signer: Pubkey::new_unique(),
+            ..event(
+                market,
+                MarketEventDetails::Place(Place {
+                    order_sequence_number: !1,
+                    client_order_id: 0,
+                    maker: Pubkey::new_unique(),
+                    price_in_ticks: 100,
+                    base_lots_placed: 10,
+                }),
+            )
+        };
+        router_sender.send(vec![place.into()]).await.unwrap();
+        let routed_batch = tokio::time::timeout(Duration::from_secs(5), routed.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        engine_sender.send(routed_batch).await.unwrap();
+        let execution = tokio::time::timeout(Duration::from_secs(5), executed.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(execution.result.is_ok());
+        assert!(runtime
+            .health()
+            .iter()
+            .all(|health| health.state == TaskState::Running));
+        // The supervisor reports through the client, including the trigger engine's order
+        let report = runtime.sdk().health();
+        assert!(report.is_healthy());
+        assert!(report.components.contains_key(TASKS_HEALTH));
+        assert!(report.last_transaction.is_some());
+
+        runtime.shutdown().await;
+        assert!(runtime
+            .health()
+            .iter()
+            .all(|health| health.state == TaskState::Stopped));
+    }
+}
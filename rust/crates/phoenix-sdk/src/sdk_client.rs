@@ -1,15 +1,40 @@
+use crate::book_subscriber::BookSubscriber;
+#[cfg(feature = "jito")]
+use crate::bundle_sender::{BundleSender, BundleStatus};
+use crate::commitment_policy::CommitmentPolicy;
+use crate::encoded_transaction;
+use crate::error::{self, PhoenixSdkError, SizeTooSmall};
+use crate::event_router::EventRouter;
+use crate::exchange_json::ExchangeJsonLadder;
+use crate::health::{
+    ComponentHealth, HealthProvider, HealthReport, HealthThresholds, LastTransaction,
+};
 use crate::ladder_utils::{MarketSimulator, SimulationSummaryInLots};
+use crate::latency::LatencyTracker;
+use crate::market_registry::{MarketRef, MarketRegistry};
+use crate::metadata_cache::MetadataCache;
 use crate::order_packet_template::ImmediateOrCancelOrderTemplate;
 use crate::order_packet_template::LimitOrderTemplate;
 use crate::order_packet_template::PostOnlyOrderTemplate;
+use crate::order_preview::OrderPreview;
+use crate::paper_trading::{PaperExchange, TradingMode};
+use crate::rate_limiter::{RateLimiter, RpcCategory};
+use crate::risk_manager::{order_packets, RiskManager};
+use crate::rpc_pool::RpcPool;
+use crate::sdk_market_event::SDKMarketEvent;
+use crate::tracked_order::TrackedOrder;
 use crate::utils::create_ata_ix_if_needed;
 use crate::utils::create_claim_seat_ix_if_needed;
+use crate::warm_up::{WarmedState, WarmupReport, WarmupTarget};
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Result;
-use ellipsis_client::EllipsisClient;
+use ellipsis_client::transaction_utils::{parse_transaction, ParsedTransaction};
+use ellipsis_client::{EllipsisClient, EllipsisClientError};
+use itertools::Itertools;
 use phoenix::program::create_new_order_instruction;
-use phoenix::program::dispatch_market::*;
+use phoenix::program::get_seat_address;
+use phoenix::program::status::SeatApprovalStatus;
 use phoenix::program::EvictEvent;
 use phoenix::program::ExpiredOrderEvent;
 use phoenix::program::FeeEvent;
@@ -19,6 +44,7 @@ use phoenix::program::MarketHeader;
 use phoenix::program::PhoenixMarketEvent;
 use phoenix::program::PlaceEvent;
 use phoenix::program::ReduceEvent;
+use phoenix::program::Seat;
 use phoenix::program::TimeInForceEvent;
 use phoenix::quantities::BaseLots;
 use phoenix::quantities::QuoteLots;
@@ -28,49 +54,742 @@ use phoenix::state::enums::*;
 use phoenix::state::markets::*;
 use phoenix::state::OrderPacket;
 use phoenix::state::TraderState;
+use phoenix_sdk_core::ata_utils::{
+    get_associated_token_address_with_program_id, TOKEN_2022_PROGRAM_ID,
+};
+use phoenix_sdk_core::compute_units::{
+    has_compute_budget_instruction, instruction_shape, with_compute_unit_limit, ComputeUnitCache,
+    ComputeUnitMargin, MAX_COMPUTE_UNIT_LIMIT,
+};
+use phoenix_sdk_core::execution_report::ExecutionReport;
+use phoenix_sdk_core::instruction_parser::{self, TransactionInstruction};
 use phoenix_sdk_core::market_event::TimeInForce;
+use phoenix_sdk_core::order_id::IntentKey;
+use phoenix_sdk_core::order_manager::OpenOrder;
+use phoenix_sdk_core::packet_decoder::decode_order_packet;
+use phoenix_sdk_core::quote_ladder::QuoteLadder;
+use phoenix_sdk_core::sdk_client_core::load_market;
 use phoenix_sdk_core::sdk_client_core::MarketState;
 use phoenix_sdk_core::sdk_client_core::RawPhoenixEvent;
+use phoenix_sdk_core::sdk_client_core::{
+    phoenix_inner_instructions, EventParseError, PartialParse,
+};
 pub use phoenix_sdk_core::{
     market_event::{Evict, Fill, FillSummary, MarketEventDetails, PhoenixEvent, Place, Reduce},
-    sdk_client_core::{get_decimal_string, MarketMetadata, PhoenixOrder, SDKClientCore},
+    market_map::MarketMap,
+    sdk_client_core::{
+        get_decimal_string, AmendPath, MarketMetadata, MarketSnapshot, NormalizedSize,
+        PhoenixOrder, SDKClientCore, SizeRounding,
+    },
 };
 use serde::{Deserialize, Serialize};
-use solana_client::client_error::reqwest;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::client_error::{reqwest, ClientError, ClientErrorKind};
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcSimulateTransactionConfig, RpcTransactionConfig,
+};
+use solana_client::rpc_custom_error::JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED;
+use solana_client::rpc_request::RpcError;
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_sdk::account::Account;
 use solana_sdk::instruction::Instruction;
+use solana_sdk::program_pack::Pack;
 use solana_sdk::{
     commitment_config::CommitmentConfig,
+    compute_budget,
     pubkey::Pubkey,
     signature::{Signature, Signer},
     signer::keypair::Keypair,
+    transaction::Transaction,
 };
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{collections::BTreeMap, mem::size_of, ops::DerefMut};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
 use crate::orderbook::Orderbook;
 
 const FEE_DIVISOR: u64 = 10000;
 
+/// The most accounts a single `getMultipleAccounts` request may ask for.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// How long the `_with_min_slot` getters retry by default, see `SDKClient::set_min_slot_timeout`.
+pub const DEFAULT_MIN_SLOT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The pause between attempts of the `_with_min_slot` getters.
+const MIN_SLOT_RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
 #[derive(Debug, Default)]
 pub struct LadderExpiration {
     pub last_valid_slot: Option<u64>,
     pub last_valid_unix_timestamp_in_seconds: Option<u64>,
 }
 
+/// A market's aggregated book as of `slot`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LadderSnapshot {
+    pub slot: u64,
+    /// The market header's sequence number, which increases with every instruction that changes
+    /// the market.
+    pub sequence_number: u64,
+    pub ladder: Ladder,
+}
+
+impl LadderSnapshot {
+    /// The best `depth` levels of each side in the common exchange depth shape, with the
+    /// sequence number as the `lastUpdateId`. See `ExchangeJsonLadder`.
+    pub fn to_exchange_json(
+        &self,
+        meta: &MarketMetadata,
+        depth: usize,
+    ) -> Result<serde_json::Value> {
+        self.ladder
+            .to_exchange_json(meta, depth, self.sequence_number)
+    }
+}
+
+/// A ladder as `(price, size)` pairs, best level first, with prices in quote units per raw base
+/// unit and sizes in raw base units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UiLadder {
+    pub slot: u64,
+    pub bids: Vec<(f64, f64)>,
+    pub asks: Vec<(f64, f64)>,
+}
+
+impl UiLadder {
+    pub fn from_snapshot(snapshot: &LadderSnapshot, meta: &MarketMetadata) -> Self {
+        let to_floats = |levels: &[LadderOrder]| {
+            levels
+                .iter()
+                .map(|level| {
+                    (
+                        meta.ticks_to_float_price(level.price_in_ticks),
+                        level.size_in_base_lots as f64
+                            * meta.raw_base_units_per_base_lot_as_float(),
+                    )
+                })
+                .collect()
+        };
+        Self {
+            slot: snapshot.slot,
+            bids: to_floats(&snapshot.ladder.bids),
+            asks: to_floats(&snapshot.ladder.asks),
+        }
+    }
+}
+
+/// The most signatures `SDKClient::get_market_summary` scans for fills. Each one costs a
+/// `getTransaction` request, so on busy markets the volume window is not fully covered and the
+/// summary's `volume_is_partial` is set.
+pub const MARKET_SUMMARY_MAX_SIGNATURES: usize = 1_000;
+
+/// A market's book, seats and recent trading, for screening markets. Prices are in quote units
+/// per raw base unit and sizes in raw base units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketSummary {
+    pub market: Pubkey,
+    /// The slot the market account was read at.
+    pub slot: u64,
+    /// Price of the most recent fill in the volume window.
+    pub last_price: Option<f64>,
+    pub volume_window: Duration,
+    pub base_volume: f64,
+    pub quote_volume: f64,
+    /// Set if the scan stopped at `MARKET_SUMMARY_MAX_SIGNATURES` before reaching the start of
+    /// the volume window, in which case the volumes only cover the most recent part of it.
+    pub volume_is_partial: bool,
+    pub signatures_scanned: usize,
+    pub best_bid: Option<f64>,
+    pub best_ask: Option<f64>,
+    pub bid_depth: f64,
+    pub ask_depth: f64,
+    pub resting_orders: usize,
+    pub seated_traders: usize,
+}
+
+impl MarketSummary {
+    fn new(
+        market: Pubkey,
+        snapshot: &MarketSnapshot,
+        meta: &MarketMetadata,
+        volume_window: Duration,
+        recent_fills: &RecentFills,
+    ) -> Self {
+        let book = &snapshot.state.orderbook;
+        let best_price = |side| {
+            book.iter_levels(side)
+                .next()
+                .map(|level| meta.ticks_to_float_price(level.price_in_ticks))
+        };
+        let depth =
+            |side| book.total_base_lots(side) as f64 * meta.raw_base_units_per_base_lot_as_float();
+        let (mut base_lots, mut quote_atoms) = (0u64, 0u64);
+        let mut last_fill: Option<(&PhoenixEvent, &Fill)> = None;
+        for event in &recent_fills.fills {
+            if let MarketEventDetails::Fill(fill) = &event.details {
+                base_lots += fill.base_lots_filled;
+                quote_atoms += meta
+                    .base_lots_and_price_to_quote_atoms(fill.base_lots_filled, fill.price_in_ticks);
+                let key = |event: &PhoenixEvent| (event.sequence_number, event.event_index);
+                if last_fill.is_none_or(|(last, _)| key(event) > key(last)) {
+                    last_fill = Some((event, fill));
+                }
+            }
+        }
+        Self {
+            market,
+            slot: snapshot.slot,
+            last_price: last_fill.map(|(_, fill)| meta.ticks_to_float_price(fill.price_in_ticks)),
+            volume_window,
+            base_volume: base_lots as f64 * meta.raw_base_units_per_base_lot_as_float(),
+            quote_volume: meta.quote_atoms_to_quote_units_as_float(quote_atoms),
+            volume_is_partial: recent_fills.is_partial,
+            signatures_scanned: recent_fills.signatures_scanned,
+            best_bid: best_price(Side::Bid),
+            best_ask: best_price(Side::Ask),
+            bid_depth: depth(Side::Bid),
+            ask_depth: depth(Side::Ask),
+            resting_orders: book.len(Side::Bid) + book.len(Side::Ask),
+            seated_traders: snapshot.state.traders.len(),
+        }
+    }
+}
+
+/// The fills found by `SDKClient::scan_recent_fills`.
+#[derive(Debug, Default)]
+struct RecentFills {
+    fills: Vec<PhoenixEvent>,
+    signatures_scanned: usize,
+    /// The scan hit its limit before reaching the start of the window.
+    is_partial: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct SimulationSummaryInAtoms {
     pub base_atoms_filled: u64,
     pub quote_atoms_filled: u64,
 }
 
+/// What `SDKClient::parse_raw_phoenix_events_with_options` does with events of a market whose
+/// metadata is not loaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownMarketPolicy {
+    /// Fetch the market's metadata.
+    #[default]
+    Fetch,
+    /// Drop the market's events.
+    Skip,
+    /// Fail the whole parse.
+    Error,
+}
+
+/// Options for `SDKClient::parse_raw_phoenix_events_with_options`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventParseOptions {
+    /// Only keep events of this market, e.g. when indexing one market from transactions that
+    /// also trade on others.
+    pub expected_market: Option<Pubkey>,
+    pub unknown_markets: UnknownMarketPolicy,
+}
+
+/// Options for `SDKClient::flatten_market`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlattenOptions {
+    /// Rounds of cancel and withdraw to try before giving up.
+    pub max_attempts: usize,
+    /// Wait between rounds, for fills or cancels from other transactions to land.
+    pub retry_delay: Duration,
+    /// If set, free base of at most this many base lots is market-sold from the seat before
+    /// withdrawing, instead of being withdrawn as dust.
+    pub sell_residual_base_lots_below: Option<u64>,
+}
+
+impl Default for FlattenOptions {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            retry_delay: Duration::from_secs(2),
+            sell_residual_base_lots_below: None,
+        }
+    }
+}
+
+/// What `SDKClient::flatten_market` sent and the seat it left behind.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlattenReport {
+    pub cancel_signatures: Vec<Signature>,
+    pub sell_signatures: Vec<Signature>,
+    pub withdraw_signatures: Vec<Signature>,
+    pub attempts: usize,
+    /// The trader's balances on the market after the last attempt.
+    pub trader_state: TraderState,
+    pub open_orders: usize,
+}
+
+impl FlattenReport {
+    /// Whether the trader ended with no orders and nothing deposited on the market.
+    pub fn is_flat(&self) -> bool {
+        self.open_orders == 0
+            && self.trader_state.base_lots_locked.as_u64() == 0
+            && self.trader_state.base_lots_free.as_u64() == 0
+            && self.trader_state.quote_lots_locked.as_u64() == 0
+            && self.trader_state.quote_lots_free.as_u64() == 0
+    }
+}
+
+/// Slippage allowed on the sells of `SDKClient::sweep_residuals`, in basis points of the
+/// proceeds expected from the book.
+pub const SWEEP_SLIPPAGE_BPS: u64 = 100;
+
+/// One thing `SDKClient::sweep_residuals` did on a market.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SweepAction {
+    /// Sold free base from the seat for quote with an IOC order.
+    Sold { base_atoms: u64, quote_atoms: u64 },
+    /// Withdrew free funds from the seat to the wallet's token accounts.
+    Withdrawn { base_atoms: u64, quote_atoms: u64 },
+}
+
+/// What `SDKClient::sweep_residuals` did on one market.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SweptMarket {
+    pub market: Pubkey,
+    /// The sweep transaction, or `None` if the seat held nothing or the sweep failed.
+    pub signature: Option<Signature>,
+    pub actions: Vec<SweepAction>,
+    /// Free base withdrawn rather than sold, because it was worth less than the threshold or the
+    /// bids could not absorb it.
+    pub unsold_base_atoms: u64,
+    /// Why the market could not be swept.
+    pub error: Option<String>,
+}
+
+/// What `SDKClient::sweep_residuals` did, market by market.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SweepReport {
+    pub markets: Vec<SweptMarket>,
+}
+
+impl SweepReport {
+    pub fn signatures(&self) -> impl Iterator<Item = &Signature> {
+        self.markets
+            .iter()
+            .filter_map(|market| market.signature.as_ref())
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &SweptMarket> {
+        self.markets.iter().filter(|market| market.error.is_some())
+    }
+
+    /// Quote received for the base sold, net of fees.
+    pub fn quote_atoms_from_sales(&self) -> u64 {
+        self.markets
+            .iter()
+            .flat_map(|market| &market.actions)
+            .map(|action| match action {
+                SweepAction::Sold { quote_atoms, .. } => *quote_atoms,
+                SweepAction::Withdrawn { .. } => 0,
+            })
+            .sum()
+    }
+}
+
+/// A sell of free base that `sweep_residuals` found worth sending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ResidualSale {
+    base_lots: u64,
+    min_quote_lots_out: u64,
+}
+
+/// Sells `base_lots_free` if the bids can absorb all of it for at least `min_quote_value_atoms`,
+/// accepting `SWEEP_SLIPPAGE_BPS` less than the book's proceeds.
+fn plan_residual_sale(
+    meta: &MarketMetadata,
+    bids: impl Iterator<Item = LadderOrder>,
+    base_lots_free: u64,
+    min_quote_value_atoms: u64,
+) -> Option<ResidualSale> {
+    if base_lots_free == 0 {
+        return None;
+    }
+    let mut base_lots_left = base_lots_free;
+    let mut quote_atoms = 0;
+    for level in bids {
+        let base_lots = level.size_in_base_lots.min(base_lots_left);
+        quote_atoms += meta.base_lots_and_price_to_quote_atoms(base_lots, level.price_in_ticks);
+        base_lots_left -= base_lots;
+        if base_lots_left == 0 {
+            break;
+        }
+    }
+    if base_lots_left > 0 || quote_atoms < min_quote_value_atoms.max(1) {
+        return None;
+    }
+    let min_quote_atoms_out = quote_atoms * (FEE_DIVISOR - SWEEP_SLIPPAGE_BPS) / FEE_DIVISOR;
+    Some(ResidualSale {
+        base_lots: base_lots_free,
+        min_quote_lots_out: meta.quote_atoms_to_quote_lots_rounded_down(min_quote_atoms_out),
+    })
+}
+
+/// An order removed by a cancel transaction, from its `Reduce` event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CancelledOrder {
+    pub market: Pubkey,
+    pub order_id: FIFOOrderId,
+    pub side: Side,
+    pub base_lots_removed: u64,
+    pub base_lots_remaining: u64,
+    pub is_full_cancel: bool,
+}
+
+/// An order moved by `SDKClient::move_order`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovedOrder {
+    pub signature: Signature,
+    /// The replacement's id.
+    pub order_id: FIFOOrderId,
+    /// Carried over from the moved order.
+    pub client_order_id: u128,
+    /// What was left of the moved order when the cancel removed it.
+    pub base_lots_cancelled: u64,
+}
+
+/// What a cancel transaction sent by `SDKClient::send_cancel_and_report` removed from the book.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CancelReport {
+    pub signature: Signature,
+    pub orders: Vec<CancelledOrder>,
+    /// Base atoms that were locked in the cancelled asks.
+    pub freed_base_atoms: u64,
+    /// Quote atoms that were locked in the cancelled bids.
+    pub freed_quote_atoms: u64,
+}
+
+impl CancelReport {
+    pub fn base_lots_removed(&self) -> u64 {
+        self.orders
+            .iter()
+            .map(|order| order.base_lots_removed)
+            .sum()
+    }
+}
+
+/// What a fill-or-kill order sent with `SDKClient::send_fok_and_verify` should fill. Unset
+/// amounts are not checked, e.g. the base lots of a buy with a quote budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FokExpectation {
+    /// The side of the order.
+    pub side: Side,
+    pub base_lots: Option<u64>,
+    /// Compared against the fill's quote lots including fees.
+    pub quote_lots: Option<u64>,
+    pub base_lots_tolerance: u64,
+    pub quote_lots_tolerance: u64,
+    /// On a mismatch, sell back what was bought, or buy back what was sold, with a market IOC.
+    pub unwind_on_mismatch: bool,
+}
+
+impl FokExpectation {
+    /// Expects `base_lots` to fill, give or take a lot.
+    pub fn base_lots(side: Side, base_lots: u64) -> Self {
+        Self {
+            side,
+            base_lots: Some(base_lots),
+            quote_lots: None,
+            base_lots_tolerance: 1,
+            quote_lots_tolerance: 1,
+            unwind_on_mismatch: false,
+        }
+    }
+
+    /// Expects `quote_lots` to fill, give or take a lot.
+    pub fn quote_lots(side: Side, quote_lots: u64) -> Self {
+        Self {
+            base_lots: None,
+            quote_lots: Some(quote_lots),
+            ..Self::base_lots(side, 0)
+        }
+    }
+
+    /// Classifies a fill. A fill of nothing is `NotFilled` whatever was expected.
+    pub fn check(&self, fill: FokFill) -> FokOutcome {
+        if fill.base_lots_filled == 0 {
+            return FokOutcome::NotFilled(fill.signature);
+        }
+        let deviations = [
+            (
+                self.base_lots,
+                fill.base_lots_filled,
+                self.base_lots_tolerance,
+            ),
+            (
+                self.quote_lots,
+                fill.quote_lots_filled,
+                self.quote_lots_tolerance,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(expected, filled, tolerance)| {
+            expected.map(|expected| (expected.abs_diff(filled), tolerance))
+        })
+        .collect::<Vec<_>>();
+        if deviations.iter().all(|&(deviation, _)| deviation == 0) {
+            FokOutcome::FilledExactly(fill)
+        } else if deviations
+            .iter()
+            .all(|&(deviation, tolerance)| deviation <= tolerance)
+        {
+            FokOutcome::FilledWithinTolerance(fill)
+        } else {
+            FokOutcome::Mismatch { fill, unwind: None }
+        }
+    }
+}
+
+/// What a fill-or-kill order filled, from its transaction's `FillSummary`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FokFill {
+    pub signature: Signature,
+    pub market: Pubkey,
+    pub base_lots_filled: u64,
+    /// Including fees.
+    pub quote_lots_filled: u64,
+    pub fee_in_quote_lots: u64,
+}
+
+/// How a fill-or-kill order sent with `SDKClient::send_fok_and_verify` compares to its
+/// `FokExpectation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FokOutcome {
+    FilledExactly(FokFill),
+    FilledWithinTolerance(FokFill),
+    /// The transaction landed without filling anything. A killed order fails its transaction
+    /// instead, which `send_fok_and_verify` returns as an error.
+    NotFilled(Signature),
+    /// The fill is off by more than the tolerance. `unwind` is the signature of the IOC that
+    /// reversed it, if `unwind_on_mismatch` was set.
+    Mismatch {
+        fill: FokFill,
+        unwind: Option<Signature>,
+    },
+    /// A mismatch where the order matched as many resting orders as its `match_limit`, so the
+    /// program stopped matching before the expected size filled. Unwound like a `Mismatch`.
+    MatchLimitReached {
+        fill: FokFill,
+        match_limit: u64,
+        unwind: Option<Signature>,
+    },
+}
+
+/// What a transaction asked Phoenix to do and, if it succeeded, what happened. See
+/// `SDKClient::parse_instructions_from_transaction`.
+#[derive(Debug, Clone)]
+pub struct TransactionIntents {
+    pub signature: Signature,
+    pub slot: u64,
+    pub is_err: bool,
+    /// The decoded Phoenix instructions, outer and inner, in the order they were invoked.
+    pub instructions: Vec<TransactionInstruction>,
+    /// Empty if the transaction failed.
+    pub events: Vec<PhoenixEvent>,
+}
+
+/// A transaction's Phoenix events and what it cost to land. See
+/// `SDKClient::parse_transaction_full`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedPhoenixTransaction {
+    pub signature: Signature,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    /// The fee payer.
+    pub signer: Pubkey,
+    pub fee_lamports: u64,
+    /// `None` if the RPC node does not report compute units consumed.
+    pub compute_units: Option<u64>,
+    pub is_err: bool,
+    /// Empty if the transaction failed.
+    pub events: Vec<PhoenixEvent>,
+}
+
+/// A wallet's holdings of a market's tokens, and its SOL for transaction fees.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WalletBalances {
+    pub base_atoms: u64,
+    pub quote_atoms: u64,
+    /// `base_atoms` in whole base tokens (raw base units).
+    pub base_units: f64,
+    pub quote_units: f64,
+    pub lamports: u64,
+}
+
+impl WalletBalances {
+    pub fn new(meta: &MarketMetadata, base_atoms: u64, quote_atoms: u64, lamports: u64) -> Self {
+        Self {
+            base_atoms,
+            quote_atoms,
+            base_units: meta.base_atoms_to_raw_base_units_as_float(base_atoms),
+            quote_units: meta.quote_atoms_to_quote_units_as_float(quote_atoms),
+            lamports,
+        }
+    }
+}
+
+/// A consistent view of everything a pre-trade check needs on one market: the market, the
+/// trader's seat and the trader's wallet, all read at the same slot. See
+/// `SDKClient::get_trading_context`.
+pub struct TradingContext {
+    pub market: Pubkey,
+    pub trader: Pubkey,
+    /// The slot all accounts were read at.
+    pub slot: u64,
+    pub metadata: MarketMetadata,
+    pub snapshot: MarketSnapshot,
+    /// `None` if the trader has no seat on the market.
+    pub seat_status: Option<SeatApprovalStatus>,
+    /// Balance of the trader's base token account, or `None` if it does not exist.
+    pub base_atoms: Option<u64>,
+    /// Balance of the trader's quote token account, or `None` if it does not exist.
+    pub quote_atoms: Option<u64>,
+    pub lamports: u64,
+}
+
+impl TradingContext {
+    /// Whether the trader's seat is approved, which placing orders requires.
+    pub fn has_approved_seat(&self) -> bool {
+        self.seat_status == Some(SeatApprovalStatus::Approved)
+    }
+
+    /// The trader's deposits on the market, or `None` if the trader is not registered on it.
+    pub fn trader_state(&self) -> Option<&TraderState> {
+        self.snapshot.state.traders.get(&self.trader)
+    }
+
+    /// The trader's wallet balances, with missing token accounts as zero, e.g. for
+    /// `RiskManager::set_wallet_balances`.
+    pub fn wallet_balances(&self) -> WalletBalances {
+        WalletBalances::new(
+            &self.metadata,
+            self.base_atoms.unwrap_or(0),
+            self.quote_atoms.unwrap_or(0),
+            self.lamports,
+        )
+    }
+}
+
+/// The client order ids of the orders `ixs` place, for the latency tracker. Orders placed by
+/// `PlaceMultiplePostOnlyOrders` do not carry theirs and are matched by signature only.
+fn client_order_ids(ixs: &[Instruction]) -> Vec<u128> {
+    ixs.iter()
+        .flat_map(order_packets)
+        .map(|(_, order_packet)| order_packet.client_order_id())
+        .filter(|client_order_id| *client_order_id != 0)
+        .collect()
+}
+
+/// The balance of the first of `accounts` that is an initialized token account for `mint`, owned
+/// by the token program at the same index of `token_programs`. Zero if there is none.
+fn token_account_balance(
+    mint: &Pubkey,
+    token_programs: &[Pubkey],
+    accounts: &[Option<Account>],
+) -> u64 {
+    token_account_amount(mint, token_programs, accounts).unwrap_or(0)
+}
+
+/// Like `token_account_balance`, but `None` if there is no such token account.
+fn token_account_amount(
+    mint: &Pubkey,
+    token_programs: &[Pubkey],
+    accounts: &[Option<Account>],
+) -> Option<u64> {
+    token_programs
+        .iter()
+        .zip(accounts)
+        .find_map(|(token_program, account)| {
+            let account = account
+                .as_ref()
+                .filter(|account| account.owner == *token_program)?;
+            // Token-2022 accounts may have extensions after the base layout
+            let token_account = spl_token::state::Account::unpack(
+                account.data.get(..spl_token::state::Account::LEN)?,
+            )
+            .ok()?;
+            (token_account.mint == *mint).then_some(token_account.amount)
+        })
+}
+
+/// `owner`'s associated token accounts for a market's base and quote mints, under both the SPL
+/// Token and Token-2022 programs.
+fn wallet_token_accounts(owner: &Pubkey, meta: &MarketMetadata) -> Vec<Pubkey> {
+    [&meta.base_mint, &meta.quote_mint]
+        .into_iter()
+        .flat_map(|mint| {
+            [spl_token::id(), TOKEN_2022_PROGRAM_ID]
+                .into_iter()
+                .map(move |token_program| {
+                    get_associated_token_address_with_program_id(owner, mint, &token_program)
+                })
+        })
+        .collect()
+}
+
+/// The approval status of a seat account, or `None` if it does not exist or is not a seat.
+fn seat_status(account: Option<&Account>) -> Option<SeatApprovalStatus> {
+    let account = account.filter(|account| account.owner == phoenix::id())?;
+    let seat = bytemuck::try_from_bytes::<Seat>(account.data.get(..size_of::<Seat>())?).ok()?;
+    // `SeatApprovalStatus::from` panics on unknown values
+    (seat.approval_status <= SeatApprovalStatus::Retired as u64)
+        .then(|| SeatApprovalStatus::from(seat.approval_status))
+}
+
+/// Whether a request failed because the node has not reached its `minContextSlot` yet. An
+/// `RpcPool` flattens the errors of its endpoints into a message, so that is checked too.
+fn is_min_context_slot_not_reached(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<ClientError>().map(|e| &e.kind) {
+        Some(ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. })) => {
+            *code == JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED
+        }
+        _ => error
+            .to_string()
+            .contains("Minimum context slot has not been reached"),
+    }
+}
+
+/// `error` with the accounts that failed to be fetched, unless it is a `PhoenixSdkError` that
+/// callers may want to downcast, like `StaleData`.
+fn fetch_error(error: anyhow::Error, accounts: &str) -> anyhow::Error {
+    if error.is::<PhoenixSdkError>() {
+        error
+    } else {
+        anyhow!("Failed to get {}: {}", accounts, error)
+    }
+}
+
+/// Uses the commitment of `client`'s `RpcClient` for everything. A `BanksClient` has no RPC
+/// commitment, so it gets the default policy.
+fn default_commitment_policy(client: &EllipsisClient) -> CommitmentPolicy {
+    if client.is_bank_client {
+        CommitmentPolicy::default()
+    } else {
+        CommitmentPolicy::uniform(client.commitment())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JsonMarketConfig {
     pub markets: Vec<MarketInfoConfig>,
 }
 #[derive(Debug, Serialize, Deserialize)]
+#[allow(non_snake_case)]
 pub struct MarketInfoConfig {
     pub market: String,
     pub baseMint: String,
@@ -80,6 +799,27 @@ pub struct MarketInfoConfig {
 pub struct SDKClient {
     pub client: EllipsisClient,
     pub core: SDKClientCore,
+    pub trading_mode: TradingMode,
+    paper_exchange: Arc<Mutex<PaperExchange>>,
+    risk_manager: Arc<Mutex<Option<RiskManager>>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    rpc_pool: Option<Arc<RpcPool>>,
+    metadata_cache: Option<Arc<dyn MetadataCache>>,
+    market_registry: Option<Arc<MarketRegistry>>,
+    latency_tracker: Option<Arc<LatencyTracker>>,
+    event_router: Option<EventRouter>,
+    warmed: Arc<Mutex<WarmedState>>,
+    health_providers: Arc<Mutex<BTreeMap<String, Arc<dyn HealthProvider>>>>,
+    health_thresholds: HealthThresholds,
+    last_transaction: Arc<Mutex<Option<(Signature, Instant)>>>,
+    compute_unit_cache: Arc<Mutex<ComputeUnitCache>>,
+    compute_unit_margin: Option<ComputeUnitMargin>,
+    commitment_policy: CommitmentPolicy,
+    min_slot_timeout: Duration,
+    strict_event_parsing: bool,
+    event_parse_errors: Option<UnboundedSender<EventParseError>>,
+    #[cfg(feature = "jito")]
+    bundle_sender: Option<Arc<BundleSender>>,
 }
 
 impl Deref for SDKClient {
@@ -102,13 +842,39 @@ impl SDKClient {
     /// This does not have any markets added to it. You must call `add_market` or `add_all_markets` to
     /// add markets to the SDKClient.
     pub async fn new_from_ellipsis_client(client: EllipsisClient) -> Result<Self> {
-        let markets = BTreeMap::new();
+        let markets = MarketMap::default();
 
         let core = SDKClientCore {
             markets,
             trader: client.payer.pubkey(),
+            default_self_trade_behavior: Default::default(),
         };
-        Ok(SDKClient { client, core })
+        let commitment_policy = default_commitment_policy(&client);
+        Ok(SDKClient {
+            client,
+            core,
+            trading_mode: TradingMode::Live,
+            paper_exchange: Default::default(),
+            risk_manager: Default::default(),
+            rate_limiter: None,
+            rpc_pool: None,
+            metadata_cache: None,
+            market_registry: None,
+            latency_tracker: None,
+            event_router: None,
+            warmed: Default::default(),
+            health_providers: Default::default(),
+            health_thresholds: Default::default(),
+            last_transaction: Default::default(),
+            compute_unit_cache: Default::default(),
+            compute_unit_margin: None,
+            commitment_policy,
+            min_slot_timeout: DEFAULT_MIN_SLOT_TIMEOUT,
+            strict_event_parsing: false,
+            event_parse_errors: None,
+            #[cfg(feature = "jito")]
+            bundle_sender: None,
+        })
     }
 
     /// Create a new SDKClient from an EllipsisClient.
@@ -122,14 +888,40 @@ impl SDKClient {
     /// Recommended way to create a new SDKClient from an EllipsisClient.
     /// This will use a list of markets from a pre-defined config file to add all known markets to the SDKClient.
     pub async fn new_from_ellipsis_client_with_all_markets(client: EllipsisClient) -> Result<Self> {
-        let markets = BTreeMap::new();
+        let markets = MarketMap::default();
 
         let core = SDKClientCore {
             markets,
             trader: client.payer.pubkey(),
+            default_self_trade_behavior: Default::default(),
         };
+        let commitment_policy = default_commitment_policy(&client);
         println!("Creating SDKClient with all markets");
-        let mut sdk = SDKClient { client, core };
+        let sdk = SDKClient {
+            client,
+            core,
+            trading_mode: TradingMode::Live,
+            paper_exchange: Default::default(),
+            risk_manager: Default::default(),
+            rate_limiter: None,
+            rpc_pool: None,
+            metadata_cache: None,
+            market_registry: None,
+            latency_tracker: None,
+            event_router: None,
+            warmed: Default::default(),
+            health_providers: Default::default(),
+            health_thresholds: Default::default(),
+            last_transaction: Default::default(),
+            compute_unit_cache: Default::default(),
+            compute_unit_margin: None,
+            commitment_policy,
+            min_slot_timeout: DEFAULT_MIN_SLOT_TIMEOUT,
+            strict_event_parsing: false,
+            event_parse_errors: None,
+            #[cfg(feature = "jito")]
+            bundle_sender: None,
+        };
         sdk.add_all_markets().await?;
         println!("Added all markets");
         Ok(sdk)
@@ -149,10 +941,36 @@ impl SDKClient {
         client: EllipsisClient,
     ) -> Result<Self> {
         let core = SDKClientCore {
-            markets: BTreeMap::new(),
+            markets: MarketMap::default(),
             trader: client.payer.pubkey(),
+            default_self_trade_behavior: Default::default(),
+        };
+        let commitment_policy = default_commitment_policy(&client);
+        let sdk = SDKClient {
+            client,
+            core,
+            trading_mode: TradingMode::Live,
+            paper_exchange: Default::default(),
+            risk_manager: Default::default(),
+            rate_limiter: None,
+            rpc_pool: None,
+            metadata_cache: None,
+            market_registry: None,
+            latency_tracker: None,
+            event_router: None,
+            warmed: Default::default(),
+            health_providers: Default::default(),
+            health_thresholds: Default::default(),
+            last_transaction: Default::default(),
+            compute_unit_cache: Default::default(),
+            compute_unit_margin: None,
+            commitment_policy,
+            min_slot_timeout: DEFAULT_MIN_SLOT_TIMEOUT,
+            strict_event_parsing: false,
+            event_parse_errors: None,
+            #[cfg(feature = "jito")]
+            bundle_sender: None,
         };
-        let mut sdk = SDKClient { client, core };
         for market_key in market_keys {
             sdk.add_market(market_key).await?;
         }
@@ -219,6 +1037,18 @@ impl SDKClient {
         SDKClient::new_from_ellipsis_client_with_market_keys(market_keys, client).await
     }
 
+    /// Create a new SDKClient that reads market metadata from `metadata_cache`, only fetching
+    /// markets the cache does not have. See `set_metadata_cache`.
+    pub async fn new_with_metadata_cache(
+        payer: &Keypair,
+        url: &str,
+        metadata_cache: Arc<dyn MetadataCache>,
+    ) -> Result<Self> {
+        let mut sdk = SDKClient::new(payer, url).await?;
+        sdk.set_metadata_cache(Some(metadata_cache));
+        Ok(sdk)
+    }
+
     /// Create a new SDKClient.
     /// Pass in a list of market keys to add to the SDKClient.
     pub fn new_with_market_keys_sync(
@@ -234,10 +1064,15 @@ impl SDKClient {
 /// Mutable functions that modify the internal state of the SDKClient
 impl SDKClient {
     /// Load in all known markets from a pre-defined config file located in the SDK github.
-    pub async fn add_all_markets(&mut self) -> Result<()> {
+    ///
+    /// Markets that fail to load, e.g. because their account is malformed, are skipped instead of
+    /// failing the whole scan. They are returned with the reason, and can be retried with
+    /// `add_market`.
+    pub async fn add_all_markets(&self) -> Result<Vec<(Pubkey, anyhow::Error)>> {
         let config_url =
             "https://raw.githubusercontent.com/Ellipsis-Labs/phoenix-sdk/master/master_config.json";
 
+        self.throttle("sdk_client", RpcCategory::Other).await;
         let genesis = self.client.get_genesis_hash().await?;
 
         //hardcoded in the genesis hashes for mainnet and devnet
@@ -258,30 +1093,43 @@ impl SDKClient {
             .get(cluster)
             .ok_or_else(|| anyhow!("Failed to find cluster {} in config file", cluster))?;
 
+        let mut skipped = vec![];
         for market in market_details.markets.iter() {
             let market_key = Pubkey::from_str(&market.market).map_err(|e| anyhow!(e))?;
-            if self.markets.get(&market_key).is_some() {
+            if self.markets.contains_key(&market_key) {
                 continue;
             }
-            self.add_market(&market_key).await.map_err(|e| anyhow!(e))?;
+            if let Err(e) = self.add_market(&market_key).await {
+                println!("Skipping market {}: {}", market_key, e);
+                skipped.push((market_key, e));
+            }
         }
 
-        Ok(())
+        Ok(skipped)
     }
 
-    pub fn add_all_markets_sync(&mut self) -> Result<()> {
+    pub fn add_all_markets_sync(&self) -> Result<Vec<(Pubkey, anyhow::Error)>> {
         let rt = tokio::runtime::Runtime::new()?;
         rt.block_on(self.add_all_markets())
     }
 
     /// This function adds the metadata for a market to the SDKClient's market cache.
-    pub async fn add_market(&mut self, market_key: &Pubkey) -> anyhow::Result<()> {
+    /// The market can be given by address or by a symbol in the market registry.
+    ///
+    /// The market cache can be changed while other tasks convert with it, see `MarketMap`.
+    pub async fn add_market(&self, market: impl Into<MarketRef>) -> anyhow::Result<()> {
+        let market_key = &self.resolve_market(market).await?;
         let market_metadata = self.get_market_metadata(market_key).await?;
         self.markets.insert(*market_key, market_metadata);
 
         Ok(())
     }
 
+    /// Removes a market from the SDKClient's market cache, returning its metadata.
+    pub fn remove_market(&self, market_key: &Pubkey) -> Option<MarketMetadata> {
+        self.markets.remove(market_key)
+    }
+
     pub fn set_payer(&mut self, payer: Keypair) {
         self.trader = payer.pubkey();
         self.client.payer = payer;
@@ -294,116 +1142,617 @@ impl SDKClient {
     pub fn get_trader(&self) -> Pubkey {
         self.trader
     }
-}
 
-/// Getter functions that make asynchronous calls via a Solana RPC connection to fetch state and events from Phoenix.
-impl SDKClient {
-    pub async fn get_market_metadata(&self, market_key: &Pubkey) -> Result<MarketMetadata> {
-        match self.markets.get(market_key) {
-            Some(metadata) => Ok(*metadata),
-            None => {
-                let market_account_data = (self.client.get_account_data(market_key))
-                    .await
-                    .map_err(|_| anyhow!("Failed to find market account"))?;
-                self.get_market_metadata_from_header_bytes(
-                    &market_account_data[..size_of::<MarketHeader>()],
-                )
-            }
-        }
+    /// In `TradingMode::Paper`, transactions are simulated against local market snapshots
+    /// instead of being sent. See `PaperExchange` for what is simulated.
+    pub fn set_trading_mode(&mut self, trading_mode: TradingMode) {
+        self.trading_mode = trading_mode;
     }
 
-    fn get_market_metadata_from_header_bytes(&self, header_bytes: &[u8]) -> Result<MarketMetadata> {
-        bytemuck::try_from_bytes(header_bytes)
-            .map_err(|_| anyhow!("Failed to deserialize market header"))
-            .and_then(MarketMetadata::from_header)
+    /// Returns the simulated exchange used in paper mode, e.g. to seed balances or inspect
+    /// open paper orders.
+    pub fn paper_exchange(&self) -> MutexGuard<'_, PaperExchange> {
+        self.paper_exchange
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
     }
 
-    /// Fetches a market's metadata from the SDKClient's market cache. This cache must be
-    /// explicitly populated by calling `add_market` or `add_all_markets`.
-    ///
-    /// Because the market metadata is static, it is recommended to call `add_market` and
-    /// use this function when repeatedly looking up metadata to avoid making
-    /// additional RPC calls.
-    pub fn get_market_metadata_from_cache(
-        &self,
-        market_key: &Pubkey,
-    ) -> anyhow::Result<&MarketMetadata> {
-        match self.markets.get(market_key) {
-            Some(metadata) => Ok(metadata),
-            None => Err(anyhow!(
-                "Market metadata not found in cache. Try calling add_market first."
-            )),
+    /// Enables pre-trade risk checks in `send_ixs` and the order-send helpers, or disables them
+    /// with `None`.
+    pub fn set_risk_manager(&self, risk_manager: Option<RiskManager>) {
+        *self.risk_manager() = risk_manager;
+    }
+
+    /// Returns the risk manager, e.g. to feed it events from a poller or update mid prices.
+    pub fn risk_manager(&self) -> MutexGuard<'_, Option<RiskManager>> {
+        self.risk_manager
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Makes every RPC request the client sends wait for budget from `rate_limiter`, counted under
+    /// the `"sdk_client"` subsystem. Share the limiter with other components using the same RPC
+    /// provider so that they draw from one budget.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Option<Arc<RateLimiter>>) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    pub fn rate_limiter(&self) -> Option<&Arc<RateLimiter>> {
+        self.rate_limiter.as_ref()
+    }
+
+    pub(crate) async fn throttle(&self, subsystem: &'static str, category: RpcCategory) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire(subsystem, category).await;
         }
     }
 
-    pub async fn get_market_ladder(&self, market_key: &Pubkey, levels: u64) -> Result<Ladder> {
-        let market_account_data = (self.client.get_account_data(market_key))
-            .await
-            .map_err(|_| anyhow!("Failed to get market account data"))?;
-        let (header_bytes, bytes) = market_account_data.split_at(size_of::<MarketHeader>());
-        let meta = self.get_market_metadata_from_header_bytes(header_bytes)?;
-        let market = load_with_dispatch(&meta.market_size_params, bytes)
-            .map_err(|_| anyhow!("Market configuration not found"))?
-            .inner;
+    /// Sends account reads, transaction fetches and transactions through `rpc_pool`, failing over
+    /// between its endpoints. Instruction helpers that take an `EllipsisClient`, such as the maker
+    /// setup instructions, still use `client`.
+    pub fn set_rpc_pool(&mut self, rpc_pool: Option<Arc<RpcPool>>) {
+        self.rpc_pool = rpc_pool;
+    }
 
-        Ok(market.get_ladder(levels))
+    pub fn rpc_pool(&self) -> Option<&Arc<RpcPool>> {
+        self.rpc_pool.as_ref()
     }
 
-    pub fn get_market_ladder_sync(&self, market_key: &Pubkey, levels: u64) -> Result<Ladder> {
-        let rt = tokio::runtime::Runtime::new()?; //fix error handling instead of panic
-        rt.block_on(self.get_market_ladder(market_key, levels))
+    /// Sets the commitment of sends, state reads, history fetches and subscriptions. See
+    /// `CommitmentPolicy`.
+    pub fn set_commitment_policy(&mut self, commitment_policy: CommitmentPolicy) {
+        self.commitment_policy = commitment_policy;
     }
 
-    pub async fn get_market_orderbook(
+    pub fn commitment_policy(&self) -> CommitmentPolicy {
+        self.commitment_policy
+    }
+
+    /// How long `get_market_state_with_min_slot` and the other `_with_min_slot` getters wait for
+    /// a node to reach the slot before failing with `PhoenixSdkError::StaleData`. Defaults to
+    /// `DEFAULT_MIN_SLOT_TIMEOUT`.
+    pub fn set_min_slot_timeout(&mut self, timeout: Duration) {
+        self.min_slot_timeout = timeout;
+    }
+
+    /// Whether the events of a transaction with a malformed event record are dropped entirely.
+    /// By default the events before the record are kept and the error is reported, see
+    /// `set_event_parse_error_sender`.
+    pub fn set_strict_event_parsing(&mut self, strict: bool) {
+        self.strict_event_parsing = strict;
+    }
+
+    pub fn strict_event_parsing(&self) -> bool {
+        self.strict_event_parsing
+    }
+
+    /// Receives the errors of transactions whose events were only partly parsed, instead of
+    /// them being printed.
+    pub fn set_event_parse_error_sender(
+        &mut self,
+        sender: Option<UnboundedSender<EventParseError>>,
+    ) {
+        self.event_parse_errors = sender;
+    }
+
+    /// A `BookSubscriber` for `market_key` at the subscription commitment of the commitment
+    /// policy, paced by the client's rate limiter if it has one.
+    pub fn book_subscriber(
         &self,
-        market_key: &Pubkey,
-    ) -> Result<Orderbook<FIFOOrderId, PhoenixOrder>> {
-        let market_account_data = (self.client.get_account_data(market_key))
-            .await
-            .unwrap_or_default();
-        let default_orderbook = Orderbook::<FIFOOrderId, PhoenixOrder> {
-            raw_base_units_per_base_lot: 0.0,
-            quote_units_per_raw_base_unit_per_tick: 0.0,
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-        };
-        if market_account_data.is_empty() {
-            return Ok(default_orderbook);
+        ws_url: &str,
+        market_key: Pubkey,
+        capacity: usize,
+    ) -> BookSubscriber {
+        let subscriber = BookSubscriber::new(ws_url, market_key, capacity)
+            .with_commitment(self.commitment_policy.subscription);
+        match &self.rate_limiter {
+            Some(rate_limiter) => subscriber.with_rate_limiter(rate_limiter.clone()),
+            None => subscriber,
         }
-        let (header_bytes, bytes) = market_account_data.split_at(size_of::<MarketHeader>());
-        let meta = self.get_market_metadata_from_header_bytes(header_bytes)?;
-        let raw_base_units_per_base_lot = meta.raw_base_units_per_base_lot();
-        let quote_units_per_raw_base_unit_per_tick = meta.quote_units_per_raw_base_unit_per_tick();
-        Ok(load_with_dispatch(&meta.market_size_params, bytes)
-            .map(|market| {
-                Orderbook::from_market(
-                    market.inner,
-                    raw_base_units_per_base_lot,
-                    quote_units_per_raw_base_unit_per_tick,
-                )
-            })
-            .unwrap_or_else(|_| default_orderbook.clone()))
     }
 
-    pub async fn get_market_orderbook_sync(
-        &self,
-        market_key: &Pubkey,
-    ) -> Result<Orderbook<FIFOOrderId, PhoenixOrder>> {
-        let rt = tokio::runtime::Runtime::new()?;
-        rt.block_on(self.get_market_orderbook(market_key))
+    /// Looks up market metadata in `metadata_cache` before fetching it, and stores what is
+    /// fetched. Share one cache between clients, or use a `JsonFileMetadataCache` to keep it
+    /// across restarts.
+    pub fn set_metadata_cache(&mut self, metadata_cache: Option<Arc<dyn MetadataCache>>) {
+        self.metadata_cache = metadata_cache;
+    }
+
+    pub fn metadata_cache(&self) -> Option<&Arc<dyn MetadataCache>> {
+        self.metadata_cache.as_ref()
+    }
+
+    /// Lets `add_market`, `get_market_ladder`, `get_market_ladder_as_floats`,
+    /// `get_market_orderbook` and `get_market_state` take market symbols such as `"SOL/USDC"`,
+    /// resolved with `resolve_market`.
+    pub fn set_market_registry(&mut self, market_registry: Option<Arc<MarketRegistry>>) {
+        self.market_registry = market_registry;
+    }
+
+    pub fn market_registry(&self) -> Option<&Arc<MarketRegistry>> {
+        self.market_registry.as_ref()
+    }
+
+    /// Records the submit and confirmation times of transactions sent by `send_ixs` and
+    /// `send_ixs_as_bundle` in `latency_tracker`. Attach the same tracker to the `MetricsSink` of
+    /// the event channel to measure submit-to-place latency.
+    pub fn set_latency_tracker(&mut self, latency_tracker: Option<Arc<LatencyTracker>>) {
+        self.latency_tracker = latency_tracker;
+    }
+
+    pub fn latency_tracker(&self) -> Option<&Arc<LatencyTracker>> {
+        self.latency_tracker.as_ref()
+    }
+
+    /// The router that `place_limit_order_tracked` follows orders through. It should route the
+    /// events of the markets traded, e.g. from `PhoenixRuntime` or an `EventRouter::spawn`.
+    pub fn set_event_router(&mut self, event_router: Option<EventRouter>) {
+        self.event_router = event_router;
+    }
+
+    pub fn event_router(&self) -> Option<&EventRouter> {
+        self.event_router.as_ref()
+    }
+
+    /// Receives the evictions of the trader's orders from the event router, ahead of its other
+    /// subscribers. See `EventRouter::subscribe_evictions`.
+    pub fn subscribe_evictions(&self) -> Result<UnboundedReceiver<PhoenixEvent>> {
+        let event_router = self
+            .event_router
+            .as_ref()
+            .ok_or_else(|| anyhow!("No event router set. Call set_event_router first"))?;
+        Ok(event_router.subscribe_evictions(self.trader))
+    }
+
+    /// Decodes a new-order instruction for review, with the market's symbol if the market
+    /// registry lists it. See `OrderPreview::from_instruction`.
+    pub fn preview_order(&self, ix: &Instruction) -> Result<OrderPreview> {
+        let preview = OrderPreview::from_instruction(&self.core, ix)?;
+        Ok(match &self.market_registry {
+            Some(registry) => preview.with_symbol_from(registry),
+            None => preview,
+        })
+    }
+
+    /// Includes `provider` in `health` under `name`, replacing any provider registered under it.
+    pub fn register_health_provider(
+        &self,
+        name: impl Into<String>,
+        provider: Arc<dyn HealthProvider>,
+    ) {
+        self.health_providers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(name.into(), provider);
+    }
+
+    /// The limits past which `health` reports components as degraded.
+    pub fn set_health_thresholds(&mut self, health_thresholds: HealthThresholds) {
+        self.health_thresholds = health_thresholds;
+    }
+
+    pub fn health_thresholds(&self) -> &HealthThresholds {
+        &self.health_thresholds
+    }
+
+    /// Summarizes the client's RPC pool and rate limiter, the components registered with
+    /// `register_health_provider` and the last transaction sent, e.g. for a health check endpoint.
+    /// Only what the components already track is read, so no requests are made.
+    pub fn health(&self) -> HealthReport {
+        let mut components: BTreeMap<String, ComponentHealth> = self
+            .health_providers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .map(|(name, provider)| (name.clone(), provider.health_check(&self.health_thresholds)))
+            .collect();
+        if let Some(rpc_pool) = &self.rpc_pool {
+            components.insert(
+                "rpc_pool".to_string(),
+                rpc_pool.health_check(&self.health_thresholds),
+            );
+        }
+        if let Some(rate_limiter) = &self.rate_limiter {
+            components.insert(
+                "rate_limiter".to_string(),
+                rate_limiter.health_check(&self.health_thresholds),
+            );
+        }
+        let last_transaction = self
+            .last_transaction
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .map(|(signature, sent_at)| LastTransaction {
+                signature,
+                age: sent_at.elapsed(),
+            });
+        HealthReport::new(components, last_transaction)
+    }
+
+    /// Sets the compute unit limit of transactions sent with `send_ixs` to their
+    /// `estimate_compute_units` plus `margin`. Transactions whose instructions already include a
+    /// compute budget instruction are sent as they are. Without a margin, the default, no limit
+    /// is set. Paper mode ignores it.
+    pub fn set_compute_unit_margin(&mut self, margin: Option<ComputeUnitMargin>) {
+        self.compute_unit_margin = margin;
+    }
+
+    pub fn compute_unit_margin(&self) -> Option<&ComputeUnitMargin> {
+        self.compute_unit_margin.as_ref()
+    }
+
+    /// The estimates of `estimate_compute_units`, e.g. to clear them after the books change
+    /// shape.
+    pub fn compute_unit_cache(&self) -> MutexGuard<'_, ComputeUnitCache> {
+        self.compute_unit_cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn record_last_transaction(&self, signature: Signature) {
+        *self
+            .last_transaction
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some((signature, Instant::now()));
+    }
+
+    pub(crate) fn warmed(&self) -> MutexGuard<'_, WarmedState> {
+        self.warmed
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns the address of `market`. A symbol is looked up in the market registry, and the
+    /// market's mints are checked against the ones the registry expects, to catch a market
+    /// pasted under the wrong symbol.
+    pub async fn resolve_market(&self, market: impl Into<MarketRef>) -> Result<Pubkey> {
+        let symbol = match market.into() {
+            MarketRef::Pubkey(market_key) => return Ok(market_key),
+            MarketRef::Symbol(symbol) => symbol,
+        };
+        let entry = self
+            .market_registry
+            .as_ref()
+            .ok_or_else(|| anyhow!("No market registry set to resolve {}", symbol))?
+            .get(&symbol)
+            .ok_or_else(|| anyhow!("Unknown market symbol {}", symbol))?;
+        let meta = self.get_market_metadata(&entry.market).await?;
+        entry.validate(&meta)?;
+        Ok(entry.market)
+    }
+
+    /// Fetches a market's metadata from RPC even if it is cached, replacing the cached copy and
+    /// the one in the SDKClient's market cache.
+    pub async fn refresh_market_metadata(&self, market_key: &Pubkey) -> Result<MarketMetadata> {
+        let metadata = self.fetch_market_metadata(market_key).await?;
+        self.markets.insert(*market_key, metadata);
+        Ok(metadata)
+    }
+
+    async fn fetch_account_data(&self, key: &Pubkey) -> Result<Vec<u8>> {
+        self.throttle("sdk_client", RpcCategory::GetAccount).await;
+        match &self.rpc_pool {
+            Some(rpc_pool) => rpc_pool.read(|client| client.get_account_data(key)).await,
+            None => Ok(self.client.get_account_data(key).await?),
+        }
+    }
+
+    /// Fetches several accounts in one `getMultipleAccounts` request. Missing accounts are `None`.
+    async fn fetch_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        Ok(self.fetch_multiple_accounts_with_slot(keys).await?.0)
+    }
+
+    /// Fetches accounts in a single request, so that they are all read at the returned slot.
+    async fn fetch_multiple_accounts_with_slot(
+        &self,
+        keys: &[Pubkey],
+    ) -> Result<(Vec<Option<Account>>, u64)> {
+        self.throttle("sdk_client", RpcCategory::GetAccount).await;
+        let commitment = self.commitment_policy.snapshot;
+        let response = match &self.rpc_pool {
+            Some(rpc_pool) => {
+                rpc_pool
+                    .read(|client| client.get_multiple_accounts_with_commitment(keys, commitment))
+                    .await?
+            }
+            None => {
+                self.client
+                    .get_multiple_accounts_with_commitment(keys, commitment)
+                    .await?
+            }
+        };
+        Ok((response.value, response.context.slot))
+    }
+
+    /// Like `fetch_multiple_accounts_with_slot`, for a response read at `min_slot` or later.
+    ///
+    /// The request sets `minContextSlot`, which nodes that support it reject until they reach the
+    /// slot, and the response's slot is checked as well for nodes that ignore it. Either way the
+    /// request is retried until `min_slot_timeout` has passed, and then fails with
+    /// `PhoenixSdkError::StaleData`.
+    async fn fetch_multiple_accounts_with_min_slot(
+        &self,
+        keys: &[Pubkey],
+        min_slot: u64,
+    ) -> Result<(Vec<Option<Account>>, u64)> {
+        let deadline = Instant::now() + self.min_slot_timeout;
+        let config = || RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(self.commitment_policy.snapshot),
+            data_slice: None,
+            min_context_slot: Some(min_slot),
+        };
+        let mut got = 0;
+        loop {
+            self.throttle("sdk_client", RpcCategory::GetAccount).await;
+            let response = match &self.rpc_pool {
+                Some(rpc_pool) => {
+                    rpc_pool
+                        .read(|client| client.get_multiple_accounts_with_config(keys, config()))
+                        .await
+                }
+                None => self
+                    .client
+                    .get_multiple_accounts_with_config(keys, config())
+                    .await
+                    .map_err(anyhow::Error::from),
+            };
+            match response {
+                Ok(response) if response.context.slot >= min_slot => {
+                    return Ok((response.value, response.context.slot))
+                }
+                Ok(response) => got = got.max(response.context.slot),
+                Err(e) if !is_min_context_slot_not_reached(&e) => return Err(e),
+                Err(_) => {}
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(PhoenixSdkError::StaleData {
+                    got,
+                    required: min_slot,
+                }
+                .into());
+            }
+            tokio::time::sleep(MIN_SLOT_RETRY_INTERVAL.min(deadline - now)).await;
+        }
+    }
+
+    /// `fetch_multiple_accounts_with_min_slot` if there is a minimum slot, otherwise
+    /// `fetch_multiple_accounts_with_slot`.
+    async fn fetch_multiple_accounts_at(
+        &self,
+        keys: &[Pubkey],
+        min_slot: Option<u64>,
+    ) -> Result<(Vec<Option<Account>>, u64)> {
+        match min_slot {
+            Some(min_slot) => {
+                self.fetch_multiple_accounts_with_min_slot(keys, min_slot)
+                    .await
+            }
+            None => self.fetch_multiple_accounts_with_slot(keys).await,
+        }
+    }
+
+    /// Fetches any number of accounts with `getMultipleAccounts`, in chunks of at most 100 keys
+    /// sent in parallel. The returned slot is the oldest any chunk was read at.
+    async fn fetch_accounts_in_chunks(
+        &self,
+        keys: &[Pubkey],
+    ) -> Result<(Vec<Option<Account>>, u64)> {
+        let chunks = futures::future::try_join_all(
+            keys.chunks(MAX_MULTIPLE_ACCOUNTS)
+                .map(|chunk| self.fetch_multiple_accounts_with_slot(chunk)),
+        )
+        .await?;
+        let slot = chunks.iter().map(|(_, slot)| *slot).min().unwrap_or(0);
+        let accounts = chunks
+            .into_iter()
+            .flat_map(|(accounts, _)| accounts)
+            .collect::<Vec<_>>();
+        if accounts.len() != keys.len() {
+            bail!("Expected {} accounts, got {}", keys.len(), accounts.len());
+        }
+        Ok((accounts, slot))
+    }
+
+    /// Fetches the current market state and makes it the snapshot paper orders match against.
+    /// A failed fetch is an error and leaves the previous snapshot in place.
+    pub async fn refresh_paper_market_state(&self, market_key: &Pubkey) -> Result<()> {
+        let market_state = self.fetch_market_state(market_key).await?;
+        self.paper_exchange()
+            .set_market_state(market_key, &market_state);
+        Ok(())
+    }
+}
+
+/// Getter functions that make asynchronous calls via a Solana RPC connection to fetch state and events from Phoenix.
+impl SDKClient {
+    pub async fn get_market_metadata(&self, market_key: &Pubkey) -> Result<MarketMetadata> {
+        if let Some(metadata) = self.markets.get(market_key) {
+            return Ok(metadata);
+        }
+        if let Some(metadata) = self.warmed().metadata(market_key) {
+            return Ok(metadata);
+        }
+        if let Some(metadata) = self
+            .metadata_cache
+            .as_ref()
+            .and_then(|cache| cache.get(market_key))
+        {
+            return Ok(metadata);
+        }
+        self.fetch_market_metadata(market_key).await
+    }
+
+    /// Fetches a market's metadata from RPC and stores it in the metadata cache, if there is one.
+    /// Failing to store it is not an error, since it can always be fetched again.
+    async fn fetch_market_metadata(&self, market_key: &Pubkey) -> Result<MarketMetadata> {
+        let market_account_data = (self.fetch_account_data(market_key))
+            .await
+            .map_err(|_| anyhow!("Failed to find market account"))?;
+        let metadata = MarketMetadata::from_account_data(&market_account_data)?;
+        if let Some(cache) = self.metadata_cache.as_ref() {
+            if let Err(e) = cache.insert(market_key, metadata) {
+                println!("Failed to cache metadata for {}: {}", market_key, e);
+            }
+        }
+        Ok(metadata)
+    }
+
+    fn get_market_metadata_from_header_bytes(&self, header_bytes: &[u8]) -> Result<MarketMetadata> {
+        bytemuck::try_from_bytes(header_bytes)
+            .map_err(|_| anyhow!("Failed to deserialize market header"))
+            .and_then(MarketMetadata::from_header)
+    }
+
+    /// Fetches a market's metadata from the SDKClient's market cache. This cache must be
+    /// explicitly populated by calling `add_market` or `add_all_markets`.
+    ///
+    /// Because the market metadata is static, it is recommended to call `add_market` and
+    /// use this function when repeatedly looking up metadata to avoid making
+    /// additional RPC calls.
+    pub fn get_market_metadata_from_cache(
+        &self,
+        market_key: &Pubkey,
+    ) -> anyhow::Result<MarketMetadata> {
+        match self.markets.get(market_key) {
+            Some(metadata) => Ok(metadata),
+            None => Err(anyhow!(
+                "Market metadata not found in cache. Try calling add_market first."
+            )),
+        }
+    }
+
+    pub async fn get_market_ladder(
+        &self,
+        market: impl Into<MarketRef>,
+        levels: u64,
+    ) -> Result<Ladder> {
+        let market_key = &self.resolve_market(market).await?;
+        self.get_market_ladder_snapshot(market_key, levels as usize)
+            .await
+            .map(|snapshot| snapshot.ladder)
+    }
+
+    /// Fetches a market's account and aggregates its book into at most `depth` levels per side,
+    /// along with the slot the account was read at. Only the book is read, so this is cheaper
+    /// than `get_market_state` for a one-off look at the market.
+    pub async fn get_market_ladder_snapshot(
+        &self,
+        market_key: &Pubkey,
+        depth: usize,
+    ) -> Result<LadderSnapshot> {
+        let (account, slot) = self.fetch_market_account_with_slot(market_key).await?;
+        if account.data.len() < size_of::<MarketHeader>() {
+            return Err(anyhow!("Market account data is too short"));
+        }
+        let (header_bytes, bytes) = account.data.split_at(size_of::<MarketHeader>());
+        let meta = self.get_market_metadata_from_header_bytes(header_bytes)?;
+        let header: &MarketHeader = bytemuck::try_from_bytes(header_bytes)
+            .map_err(|_| anyhow!("Failed to deserialize market header"))?;
+        let market = load_market(&meta.market_size_params, bytes)
+            .map_err(|_| anyhow!("Market configuration not found"))?
+            .inner;
+
+        Ok(LadderSnapshot {
+            slot,
+            sequence_number: header.market_sequence_number,
+            ladder: market.get_ladder(depth as u64),
+        })
+    }
+
+    /// Fetches a market account along with the slot it was read at.
+    async fn fetch_market_account_with_slot(&self, market_key: &Pubkey) -> Result<(Account, u64)> {
+        self.throttle("sdk_client", RpcCategory::GetAccount).await;
+        let commitment = self.commitment_policy.snapshot;
+        let response = match &self.rpc_pool {
+            Some(rpc_pool) => {
+                rpc_pool
+                    .read(|client| client.get_account_with_commitment(market_key, commitment))
+                    .await
+            }
+            None => Ok(self
+                .client
+                .get_account_with_commitment(market_key, commitment)
+                .await?),
+        }
+        .map_err(|e| anyhow!("Failed to get market account data: {}", e))?;
+        let account = response
+            .value
+            .ok_or_else(|| anyhow!("Market account {} not found", market_key))?;
+        Ok((account, response.context.slot))
+    }
+
+    /// Like `get_market_ladder_snapshot`, with prices in quote units per raw base unit and sizes
+    /// in raw base units.
+    pub async fn get_market_ladder_as_floats(
+        &self,
+        market: impl Into<MarketRef>,
+        depth: usize,
+    ) -> Result<UiLadder> {
+        let market_key = &self.resolve_market(market).await?;
+        let snapshot = self.get_market_ladder_snapshot(market_key, depth).await?;
+        let meta = self.get_market_metadata(market_key).await?;
+        Ok(UiLadder::from_snapshot(&snapshot, &meta))
+    }
+
+    pub fn get_market_ladder_sync(&self, market_key: &Pubkey, levels: u64) -> Result<Ladder> {
+        let rt = tokio::runtime::Runtime::new()?; //fix error handling instead of panic
+        rt.block_on(self.get_market_ladder(market_key, levels))
+    }
+
+    pub async fn get_market_orderbook(
+        &self,
+        market: impl Into<MarketRef>,
+    ) -> Result<Orderbook<FIFOOrderId, PhoenixOrder>> {
+        let market_key = &self.resolve_market(market).await?;
+        let market_account_data = (self.fetch_account_data(market_key))
+            .await
+            .unwrap_or_default();
+        let default_orderbook = Orderbook::<FIFOOrderId, PhoenixOrder> {
+            raw_base_units_per_base_lot: 0.0,
+            quote_units_per_raw_base_unit_per_tick: 0.0,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        };
+        if market_account_data.is_empty() {
+            return Ok(default_orderbook);
+        }
+        let (header_bytes, bytes) = market_account_data.split_at(size_of::<MarketHeader>());
+        let meta = self.get_market_metadata_from_header_bytes(header_bytes)?;
+        let raw_base_units_per_base_lot = meta.raw_base_units_per_base_lot_as_float();
+        let quote_units_per_raw_base_unit_per_tick =
+            meta.quote_units_per_raw_base_unit_per_tick_as_float();
+        Ok(load_market(&meta.market_size_params, bytes)
+            .map(|market| {
+                Orderbook::from_market(
+                    market.inner,
+                    raw_base_units_per_base_lot,
+                    quote_units_per_raw_base_unit_per_tick,
+                )
+            })
+            .unwrap_or_else(|_| default_orderbook.clone()))
+    }
+
+    pub async fn get_market_orderbook_sync(
+        &self,
+        market_key: &Pubkey,
+    ) -> Result<Orderbook<FIFOOrderId, PhoenixOrder>> {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(self.get_market_orderbook(market_key))
     }
 
     pub async fn get_traders_with_market_key(
         &self,
         market_key: &Pubkey,
     ) -> Result<BTreeMap<Pubkey, TraderState>> {
-        let market_account_data = match (self.client.get_account_data(market_key)).await {
+        let market_account_data = match (self.fetch_account_data(market_key)).await {
             Ok(data) => data,
             Err(_) => return Ok(BTreeMap::new()),
         };
         let (header_bytes, bytes) = market_account_data.split_at(size_of::<MarketHeader>());
         let meta = self.get_market_metadata_from_header_bytes(header_bytes)?;
-        let market = load_with_dispatch(&meta.market_size_params, bytes)
+        let market = load_market(&meta.market_size_params, bytes)
             .map_err(|_| anyhow!("Market configuration not found"))?
             .inner;
 
@@ -422,8 +1771,9 @@ impl SDKClient {
         rt.block_on(self.get_traders_with_market_key(market_key))
     }
 
-    pub async fn get_market_state(&self, market_key: &Pubkey) -> Result<MarketState> {
-        let market_account_data = match (self.client.get_account_data(market_key)).await {
+    pub async fn get_market_state(&self, market: impl Into<MarketRef>) -> Result<MarketState> {
+        let market_key = &self.resolve_market(market).await?;
+        let market_account_data = match (self.fetch_account_data(market_key)).await {
             Ok(data) => data,
             Err(_) => {
                 return Ok(MarketState {
@@ -437,82 +1787,643 @@ impl SDKClient {
                 })
             }
         };
-        let (header_bytes, bytes) = market_account_data.split_at(size_of::<MarketHeader>());
-        let meta = self.get_market_metadata_from_header_bytes(header_bytes)?;
-        let market = load_with_dispatch(&meta.market_size_params, bytes)
-            .map_err(|_| anyhow!("Market configuration not found"))?
-            .inner;
-        let orderbook = Orderbook::from_market(
-            market,
-            meta.raw_base_units_per_base_lot(),
-            meta.quote_units_per_raw_base_unit_per_tick(),
-        );
-
-        let traders = market
-            .get_registered_traders()
-            .iter()
-            .map(|(k, v)| (*k, *v))
-            .collect();
-
-        Ok(MarketState { orderbook, traders })
+        MarketState::from_account_data(&market_account_data)
     }
 
-    /// Simulates a market transaction based on provided parameters.
-    ///
-    /// This function simulates the market transaction for a given market key, input mint key,
-    /// number of atoms, and optional expiration details. It calculates the number of base and quote atoms
-    /// that would be filled based on the current market state and returns them as a summary.
-    ///
-    /// # Arguments
-    ///
-    /// * `market_key` - The public key of the Phoenix market.
-    /// * `input_mint_key` - The public key of the input Mint.
-    /// * `atoms_to_sell` - The amount in atoms to sell.
-    /// * `expiration` - Expiration details for the simulation (optional but recommended for real-time simulations).
+    /// Fetches a market's state along with its sequence number and the slot it was read at, so
+    /// that events from a poller or subscription can be spliced onto its book with
+    /// `Orderbook::sync_from_events`. Unlike `get_market_state`, a failed fetch is an error.
     ///
-    pub async fn simulate_market_transaction(
+    /// The first call after `warm_up` returns the prefetched snapshot, if it is recent enough.
+    pub async fn get_market_snapshot(
         &self,
-        market_key: &Pubkey,
-        input_mint_key: &Pubkey,
-        atoms_to_sell: u64,
-        expiration: Option<LadderExpiration>,
-    ) -> Result<SimulationSummaryInAtoms> {
-        let LadderExpiration {
-            last_valid_slot,
-            last_valid_unix_timestamp_in_seconds,
-        } = expiration.unwrap_or_default();
+        market: impl Into<MarketRef>,
+    ) -> Result<MarketSnapshot> {
+        let market_key = &self.resolve_market(market).await?;
+        if let Some(snapshot) = self.warmed().take_snapshot(market_key) {
+            return Ok(snapshot);
+        }
+        let (account, slot) = self.fetch_market_account_with_slot(market_key).await?;
+        MarketSnapshot::from_account_data(&account.data, slot)
+    }
 
-        let market_account_data = self.client.get_account_data(market_key).await?;
-        let (header_bytes, bytes) = market_account_data.split_at(size_of::<MarketHeader>());
-        let meta = self.get_market_metadata_from_header_bytes(header_bytes)?;
-        let market = load_with_dispatch(&meta.market_size_params, bytes)
-            .map_err(|_| {
-                anyhow!(
-                    "Market configuration not found for key {}",
-                    market_key.to_string()
-                )
-            })?
-            .inner;
+    /// Like `get_market_snapshot`, for a snapshot read at `min_slot` or later, e.g. so that a
+    /// strategy never acts on a book older than a price it observed elsewhere at `min_slot`.
+    /// Fails with `PhoenixSdkError::StaleData` if no node serves one within the timeout set by
+    /// `set_min_slot_timeout`.
+    pub async fn get_market_state_with_min_slot(
+        &self,
+        market: impl Into<MarketRef>,
+        min_slot: u64,
+    ) -> Result<MarketSnapshot> {
+        let market_key = self.resolve_market(market).await?;
+        let (accounts, slot) = self
+            .fetch_multiple_accounts_with_min_slot(&[market_key], min_slot)
+            .await?;
+        let account = accounts
+            .into_iter()
+            .next()
+            .flatten()
+            .ok_or_else(|| anyhow!("Market account {} not found", market_key))?;
+        MarketSnapshot::from_account_data(&account.data, slot)
+    }
 
-        let ladder = market.get_ladder_with_expiration(
-            u64::MAX,
-            last_valid_slot,
-            last_valid_unix_timestamp_in_seconds,
-        );
-        let metadata = self.get_market_metadata_from_cache(market_key)?;
-        let side = match input_mint_key {
-            _ if input_mint_key == &metadata.base_mint => Side::Ask,
-            _ if input_mint_key == &metadata.quote_mint => Side::Bid,
-            _ => bail!(
-                "Input mint key {} does not match market base or quote mint",
-                input_mint_key.to_string()
-            ),
-        };
+    /// Summarizes a market's book, seats and the fills of the last `volume_window`, e.g. for a
+    /// screener. The volume is computed from the market's transactions, newest first, and covers
+    /// at most `MARKET_SUMMARY_MAX_SIGNATURES` of them; see `MarketSummary::volume_is_partial`.
+    pub async fn get_market_summary(
+        &self,
+        market: &Pubkey,
+        volume_window: Duration,
+    ) -> Result<MarketSummary> {
+        let snapshot = self.get_market_snapshot(market).await?;
+        let meta = self.get_market_metadata(market).await?;
+        let since = SystemTime::now()
+            .checked_sub(volume_window)
+            .and_then(|since| since.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs() as i64);
+        let recent_fills = self
+            .scan_recent_fills(market, since, MARKET_SUMMARY_MAX_SIGNATURES)
+            .await?;
+        Ok(MarketSummary::new(
+            *market,
+            &snapshot,
+            &meta,
+            volume_window,
+            &recent_fills,
+        ))
+    }
 
-        // convert atoms to lots
-        let mut lots_to_sell = match side {
-            Side::Bid => metadata.quote_atoms_to_quote_lots_rounded_down(atoms_to_sell),
-            Side::Ask => metadata.base_atoms_to_base_lots_rounded_down(atoms_to_sell),
+    /// Up to `limit` (at most 1000) of the market's transaction signatures, newest first,
+    /// starting after `before` if set. Page backwards by passing the last signature of a page as
+    /// the next `before`.
+    pub async fn get_market_signatures(
+        &self,
+        market_key: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        // The config is not `Clone`, and the pool may send the request more than once
+        let config = || GetConfirmedSignaturesForAddress2Config {
+            before,
+            until: None,
+            limit: Some(limit),
+            commitment: Some(self.commitment_policy.history_commitment()),
+        };
+        self.throttle("sdk_client", RpcCategory::Other).await;
+        match &self.rpc_pool {
+            Some(rpc_pool) => {
+                rpc_pool
+                    .read(|client| {
+                        client.get_signatures_for_address_with_config(market_key, config())
+                    })
+                    .await
+            }
+            None => self
+                .client
+                .get_signatures_for_address_with_config(market_key, config())
+                .await
+                .map_err(|e| anyhow!("Failed to get signatures of {}: {}", market_key, e)),
+        }
+    }
+
+    /// Collects the market's fills with a block time of at least `since`, a unix timestamp in
+    /// seconds, scanning at most `max_signatures` of its transactions. Failed transactions are
+    /// skipped, as are transactions that cannot be fetched.
+    async fn scan_recent_fills(
+        &self,
+        market_key: &Pubkey,
+        since: i64,
+        max_signatures: usize,
+    ) -> Result<RecentFills> {
+        let mut recent_fills = RecentFills::default();
+        let mut before = None;
+        loop {
+            let remaining = max_signatures - recent_fills.signatures_scanned;
+            if remaining == 0 {
+                recent_fills.is_partial = true;
+                return Ok(recent_fills);
+            }
+            let page = self
+                .get_market_signatures(market_key, before, remaining.min(1_000))
+                .await?;
+            if page.is_empty() {
+                return Ok(recent_fills);
+            }
+            for status in page.into_iter().take(remaining) {
+                if status
+                    .block_time
+                    .is_some_and(|block_time| block_time < since)
+                {
+                    return Ok(recent_fills);
+                }
+                recent_fills.signatures_scanned += 1;
+                let signature = Signature::from_str(&status.signature)?;
+                before = Some(signature);
+                if status.err.is_some() {
+                    continue;
+                }
+                let events = self
+                    .parse_events_from_transaction(&signature)
+                    .await
+                    .unwrap_or_default();
+                recent_fills
+                    .fills
+                    .extend(events.into_iter().filter(|event| {
+                        event.market == *market_key
+                            && event.timestamp >= since
+                            && matches!(event.details, MarketEventDetails::Fill(_))
+                    }));
+            }
+        }
+    }
+
+    /// Returns the trader's resting orders on a market with their remaining size in base lots.
+    /// In paper mode these are the open paper orders.
+    ///
+    /// Unlike `get_market_state`, a failed fetch is an error rather than an empty book, so an
+    /// empty result means the trader has no orders.
+    pub async fn get_open_orders(&self, market_key: &Pubkey) -> Result<Vec<(FIFOOrderId, u64)>> {
+        if self.trading_mode == TradingMode::Paper {
+            return Ok(self.paper_exchange().open_orders(market_key));
+        }
+        let orderbook = self.fetch_market_state(market_key).await?.orderbook;
+        Ok(orderbook
+            .bids
+            .iter()
+            .chain(orderbook.asks.iter())
+            .filter(|(_, order)| order.maker_id == self.trader)
+            .map(|(order_id, order)| (*order_id, order.num_base_lots))
+            .collect())
+    }
+
+    /// Returns the trader's deposits on a market, or empty balances if the trader has no seat.
+    /// A failed fetch is an error.
+    pub async fn get_trader_state(&self, market_key: &Pubkey) -> Result<TraderState> {
+        Ok(self
+            .fetch_market_state(market_key)
+            .await?
+            .traders
+            .get(&self.trader)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    pub(crate) async fn fetch_market_state(&self, market_key: &Pubkey) -> Result<MarketState> {
+        let market_account_data = self
+            .fetch_account_data(market_key)
+            .await
+            .map_err(|e| anyhow!("Failed to get market account data: {}", e))?;
+        MarketState::from_account_data(&market_account_data)
+    }
+
+    /// Returns `owner`'s balances of a market's base and quote tokens, and its lamports.
+    ///
+    /// The balances are read from `owner`'s associated token accounts for the market's mints.
+    /// Both the SPL Token and Token-2022 addresses are fetched, since only the account of the
+    /// mint's own token program can exist; a missing account counts as zero. The wallet and its
+    /// token accounts are fetched in a single `getMultipleAccounts` request. The first call for
+    /// the trader after `warm_up` returns the prefetched balances, if they are recent enough.
+    pub async fn get_wallet_balances(
+        &self,
+        market: &Pubkey,
+        owner: &Pubkey,
+    ) -> Result<WalletBalances> {
+        if *owner == self.trader {
+            if let Some(balances) = self.warmed().take_wallet_balances(market) {
+                return Ok(balances);
+            }
+        }
+        self.fetch_wallet_balances(market, owner, None).await
+    }
+
+    /// Like `get_wallet_balances`, for balances read at `min_slot` or later. See
+    /// `get_market_state_with_min_slot`.
+    pub async fn get_wallet_balances_with_min_slot(
+        &self,
+        market: &Pubkey,
+        owner: &Pubkey,
+        min_slot: u64,
+    ) -> Result<WalletBalances> {
+        self.fetch_wallet_balances(market, owner, Some(min_slot))
+            .await
+    }
+
+    async fn fetch_wallet_balances(
+        &self,
+        market: &Pubkey,
+        owner: &Pubkey,
+        min_slot: Option<u64>,
+    ) -> Result<WalletBalances> {
+        let meta = self.get_market_metadata(market).await?;
+        let token_programs = [spl_token::id(), TOKEN_2022_PROGRAM_ID];
+        let mut keys = vec![*owner];
+        for mint in [&meta.base_mint, &meta.quote_mint] {
+            keys.extend(token_programs.iter().map(|token_program| {
+                get_associated_token_address_with_program_id(owner, mint, token_program)
+            }));
+        }
+        let (accounts, _) = self
+            .fetch_multiple_accounts_at(&keys, min_slot)
+            .await
+            .map_err(|e| fetch_error(e, "wallet accounts"))?;
+        if accounts.len() != keys.len() {
+            bail!(
+                "Expected {} wallet accounts, got {}",
+                keys.len(),
+                accounts.len()
+            );
+        }
+        let lamports = accounts[0].as_ref().map_or(0, |account| account.lamports);
+        let base_atoms = token_account_balance(&meta.base_mint, &token_programs, &accounts[1..3]);
+        let quote_atoms = token_account_balance(&meta.quote_mint, &token_programs, &accounts[3..5]);
+        Ok(WalletBalances::new(
+            &meta,
+            base_atoms,
+            quote_atoms,
+            lamports,
+        ))
+    }
+
+    /// Fetches a market, the trader's seat on it and the trader's wallet in a single
+    /// `getMultipleAccounts` request, so that all of them reflect the same slot. Separate
+    /// requests can land on different slots and e.g. show a deposit on the market without the
+    /// matching debit of the wallet.
+    ///
+    /// Token accounts are looked up like in `get_wallet_balances`. A missing seat or token
+    /// account is `None`; a missing market is an error.
+    pub async fn get_trading_context(&self, market: &Pubkey) -> Result<TradingContext> {
+        self.fetch_trading_context(market, None).await
+    }
+
+    /// Like `get_trading_context`, for a context read at `min_slot` or later. See
+    /// `get_market_state_with_min_slot`.
+    pub async fn get_trading_context_with_min_slot(
+        &self,
+        market: &Pubkey,
+        min_slot: u64,
+    ) -> Result<TradingContext> {
+        self.fetch_trading_context(market, Some(min_slot)).await
+    }
+
+    async fn fetch_trading_context(
+        &self,
+        market: &Pubkey,
+        min_slot: Option<u64>,
+    ) -> Result<TradingContext> {
+        let trader = self.trader;
+        let token_programs = [spl_token::id(), TOKEN_2022_PROGRAM_ID];
+        let mut keys = vec![*market, get_seat_address(market, &trader).0, trader];
+        // The mints are only known from the market account, so they come from its metadata
+        let meta = self.get_market_metadata(market).await?;
+        for mint in [&meta.base_mint, &meta.quote_mint] {
+            keys.extend(token_programs.iter().map(|token_program| {
+                get_associated_token_address_with_program_id(&trader, mint, token_program)
+            }));
+        }
+        let (accounts, slot) = self
+            .fetch_multiple_accounts_at(&keys, min_slot)
+            .await
+            .map_err(|e| fetch_error(e, "trading accounts"))?;
+        if accounts.len() != keys.len() {
+            bail!(
+                "Expected {} trading accounts, got {}",
+                keys.len(),
+                accounts.len()
+            );
+        }
+        let market_account = accounts[0]
+            .as_ref()
+            .ok_or_else(|| anyhow!("Market account {} not found", market))?;
+        if market_account.data.len() < size_of::<MarketHeader>() {
+            bail!("Market account data is too short");
+        }
+        let metadata = self.get_market_metadata_from_header_bytes(
+            &market_account.data[..size_of::<MarketHeader>()],
+        )?;
+        Ok(TradingContext {
+            market: *market,
+            trader,
+            slot,
+            metadata,
+            snapshot: MarketSnapshot::from_account_data(&market_account.data, slot)?,
+            seat_status: seat_status(accounts[1].as_ref()),
+            lamports: accounts[2].as_ref().map_or(0, |account| account.lamports),
+            base_atoms: token_account_amount(&meta.base_mint, &token_programs, &accounts[3..5]),
+            quote_atoms: token_account_amount(&meta.quote_mint, &token_programs, &accounts[5..7]),
+        })
+    }
+
+    /// Returns the approval status of the trader's seat on a market, or `None` if the trader has
+    /// no seat.
+    pub async fn get_seat_status(&self, market: &Pubkey) -> Result<Option<SeatApprovalStatus>> {
+        if let Some(seat_status) = self.warmed().take_seat_status(market) {
+            return Ok(seat_status);
+        }
+        let seat = get_seat_address(market, &self.trader).0;
+        let accounts = self
+            .fetch_multiple_accounts(&[seat])
+            .await
+            .map_err(|e| anyhow!("Failed to get seat account: {}", e))?;
+        Ok(seat_status(accounts.first().and_then(Option::as_ref)))
+    }
+
+    /// Prefetches everything the first orders on `markets` need, so that they do not wait on a
+    /// burst of serial requests: the markets' metadata and snapshots, the trader's seats and the
+    /// trader's wallet balances, along with the current slot and blockhash.
+    ///
+    /// Accounts are read with `getMultipleAccounts`, in chunks of up to 100 sent in parallel.
+    /// Markets whose metadata is not known yet take a second round, since their token accounts
+    /// depend on their mints. The metadata is kept for good; the rest is returned once by
+    /// `get_market_snapshot`, `get_seat_status` and `get_wallet_balances` for the trader, if
+    /// they are called within `WARMED_STATE_MAX_AGE`. In paper mode, markets without a paper
+    /// book are seeded with the fetched state.
+    ///
+    /// Items that fail are recorded in the report rather than failing the warm-up.
+    pub async fn warm_up(&self, markets: &[Pubkey]) -> Result<WarmupReport> {
+        let started = Instant::now();
+        let trader = self.trader;
+        let markets = markets.iter().copied().unique().collect::<Vec<_>>();
+        let known_metadata = markets
+            .iter()
+            .map(|market| {
+                self.markets
+                    .get(market)
+                    .or_else(|| self.warmed().metadata(market))
+                    .or_else(|| {
+                        self.metadata_cache
+                            .as_ref()
+                            .and_then(|cache| cache.get(market))
+                    })
+            })
+            .collect::<Option<Vec<_>>>();
+
+        let mut keys = markets.clone();
+        keys.extend(
+            markets
+                .iter()
+                .map(|market| get_seat_address(market, &trader).0),
+        );
+        keys.push(trader);
+        if let Some(metadata) = &known_metadata {
+            keys.extend(
+                metadata
+                    .iter()
+                    .flat_map(|meta| wallet_token_accounts(&trader, meta)),
+            );
+        }
+        let slot = async {
+            self.throttle("sdk_client", RpcCategory::GetSlot).await;
+            match &self.rpc_pool {
+                Some(rpc_pool) => rpc_pool.read(|client| client.get_slot()).await,
+                None => Ok(self.client.get_slot().await?),
+            }
+        };
+        let blockhash = async {
+            self.throttle("sdk_client", RpcCategory::Other).await;
+            match &self.rpc_pool {
+                Some(rpc_pool) => rpc_pool.read(|client| client.get_latest_blockhash()).await,
+                None => Ok(self.client.get_latest_blockhash().await?),
+            }
+        };
+        let (slot, blockhash, accounts) =
+            tokio::join!(slot, blockhash, self.fetch_accounts_in_chunks(&keys));
+
+        let mut report = WarmupReport::default();
+        report.record(WarmupTarget::Slot, &slot);
+        report.record(WarmupTarget::Blockhash, &blockhash);
+        report.slot = slot.ok();
+        report.blockhash = blockhash.ok();
+        let (accounts, accounts_slot) = match accounts {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                let error: Result<()> = Err(anyhow!("Failed to get accounts: {}", e));
+                for market in &markets {
+                    report.record(WarmupTarget::Metadata(*market), &error);
+                    report.record(WarmupTarget::Snapshot(*market), &error);
+                    report.record(WarmupTarget::SeatStatus(*market), &error);
+                    report.record(WarmupTarget::WalletBalances(*market), &error);
+                }
+                report.elapsed = started.elapsed();
+                return Ok(report);
+            }
+        };
+        let num_markets = markets.len();
+        let all_metadata_known = known_metadata.is_some();
+        let metadata: Vec<Result<MarketMetadata>> = match known_metadata {
+            Some(metadata) => metadata.into_iter().map(Ok).collect(),
+            None => accounts[..num_markets]
+                .iter()
+                .zip(&markets)
+                .map(|(account, market)| {
+                    let account = account
+                        .as_ref()
+                        .ok_or_else(|| anyhow!("Market account {} not found", market))?;
+                    MarketMetadata::from_account_data(&account.data)
+                })
+                .collect(),
+        };
+
+        // The token accounts of markets whose mints were not known come in a second round
+        let wallet_accounts = if all_metadata_known {
+            Ok(accounts[2 * num_markets + 1..].to_vec())
+        } else {
+            let wallet_keys = metadata
+                .iter()
+                .flatten()
+                .flat_map(|meta| wallet_token_accounts(&trader, meta))
+                .collect::<Vec<_>>();
+            self.fetch_accounts_in_chunks(&wallet_keys)
+                .await
+                .map(|(accounts, _)| accounts)
+        };
+        let lamports = accounts[2 * num_markets]
+            .as_ref()
+            .map_or(0, |account| account.lamports);
+        let token_programs = [spl_token::id(), TOKEN_2022_PROGRAM_ID];
+
+        let mut warmed = vec![];
+        let mut wallet_chunks = wallet_accounts.as_ref().map(|accounts| accounts.chunks(4));
+        for (i, market) in markets.iter().enumerate() {
+            let meta = match &metadata[i] {
+                Ok(meta) => *meta,
+                Err(e) => {
+                    let error: Result<()> = Err(anyhow!("{}", e));
+                    report.record(WarmupTarget::Metadata(*market), &error);
+                    report.record(WarmupTarget::Snapshot(*market), &error);
+                    report.record(WarmupTarget::SeatStatus(*market), &error);
+                    report.record(WarmupTarget::WalletBalances(*market), &error);
+                    continue;
+                }
+            };
+            let snapshot = accounts[i]
+                .as_ref()
+                .ok_or_else(|| anyhow!("Market account {} not found", market))
+                .and_then(|account| {
+                    MarketSnapshot::from_account_data(&account.data, accounts_slot)
+                });
+            let seat = Ok::<_, anyhow::Error>(seat_status(accounts[num_markets + i].as_ref()));
+            let balances = match &mut wallet_chunks {
+                Ok(chunks) => chunks
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing wallet accounts for market {}", market))
+                    .map(|accounts| {
+                        WalletBalances::new(
+                            &meta,
+                            token_account_balance(&meta.base_mint, &token_programs, &accounts[..2]),
+                            token_account_balance(
+                                &meta.quote_mint,
+                                &token_programs,
+                                &accounts[2..],
+                            ),
+                            lamports,
+                        )
+                    }),
+                Err(e) => Err(anyhow!("Failed to get wallet accounts: {}", e)),
+            };
+            report.record(WarmupTarget::Metadata(*market), &Ok::<_, String>(()));
+            report.record(WarmupTarget::Snapshot(*market), &snapshot);
+            report.record(WarmupTarget::SeatStatus(*market), &seat);
+            report.record(WarmupTarget::WalletBalances(*market), &balances);
+            warmed.push((*market, meta, snapshot.ok(), seat.ok(), balances.ok()));
+        }
+
+        let mut state = self.warmed();
+        for (market, meta, snapshot, seat, balances) in warmed {
+            state.insert_metadata(&market, meta);
+            if let Some(cache) = self.metadata_cache.as_ref() {
+                if cache.get(&market).is_none() {
+                    if let Err(e) = cache.insert(&market, meta) {
+                        println!("Failed to cache metadata for {}: {}", market, e);
+                    }
+                }
+            }
+            if let Some(snapshot) = snapshot {
+                if self.trading_mode == TradingMode::Paper
+                    && !self.paper_exchange().has_market_state(&market)
+                {
+                    self.paper_exchange()
+                        .set_market_state(&market, &snapshot.state);
+                }
+                state.insert_snapshot(&market, snapshot);
+            }
+            if let Some(seat) = seat {
+                state.insert_seat_status(&market, seat);
+            }
+            if let Some(balances) = balances {
+                state.insert_wallet_balances(&market, balances);
+            }
+        }
+        drop(state);
+        report.elapsed = started.elapsed();
+        Ok(report)
+    }
+
+    /// Fetches the trader's wallet balances on a market and passes them to the risk manager, if
+    /// one is set.
+    pub async fn update_risk_wallet_balances(&self, market: &Pubkey) -> Result<WalletBalances> {
+        let balances = self.get_wallet_balances(market, &self.trader).await?;
+        if let Some(risk_manager) = self.risk_manager().as_mut() {
+            risk_manager.set_wallet_balances(market, balances);
+        }
+        Ok(balances)
+    }
+
+    /// Returns the deposit instructions needed for `ladder` to rest with
+    /// `use_only_deposited_funds` set, given the trader's current deposits on the market. See
+    /// `SDKClientCore::get_top_up_deposits_ixs` for how excess deposits are withdrawn.
+    ///
+    /// Fails if the trader's wallet does not hold enough of either token to cover the deposit.
+    pub async fn top_up_deposits(
+        &self,
+        market_key: &Pubkey,
+        ladder: &QuoteLadder,
+        withdraw_buffer_bps: Option<u64>,
+    ) -> Result<Vec<Instruction>> {
+        let (trader_state, wallet) = tokio::try_join!(
+            self.get_trader_state(market_key),
+            self.get_wallet_balances(market_key, &self.trader)
+        )?;
+        let meta = self.get_market_metadata(market_key).await?;
+        let (base_lots, quote_lots) =
+            self.deposit_shortfall_in_lots(market_key, &trader_state, &ladder.bids, &ladder.asks)?;
+        let base_atoms = base_lots * meta.base_atoms_per_base_lot;
+        let quote_atoms = quote_lots * meta.quote_atoms_per_quote_lot;
+        if base_atoms > wallet.base_atoms || quote_atoms > wallet.quote_atoms {
+            bail!(
+                "Wallet holds {} base atoms and {} quote atoms, short of the {} and {} to deposit on market {}",
+                wallet.base_atoms,
+                wallet.quote_atoms,
+                base_atoms,
+                quote_atoms,
+                market_key
+            );
+        }
+        self.get_top_up_deposits_ixs(
+            market_key,
+            &trader_state,
+            &ladder.bids,
+            &ladder.asks,
+            withdraw_buffer_bps,
+        )
+    }
+
+    /// Simulates a market transaction based on provided parameters.
+    ///
+    /// This function simulates the market transaction for a given market key, input mint key,
+    /// number of atoms, and optional expiration details. It calculates the number of base and quote atoms
+    /// that would be filled based on the current market state and returns them as a summary.
+    ///
+    /// # Arguments
+    ///
+    /// * `market_key` - The public key of the Phoenix market.
+    /// * `input_mint_key` - The public key of the input Mint.
+    /// * `atoms_to_sell` - The amount in atoms to sell.
+    /// * `expiration` - Expiration details for the simulation (optional but recommended for real-time simulations).
+    ///
+    pub async fn simulate_market_transaction(
+        &self,
+        market_key: &Pubkey,
+        input_mint_key: &Pubkey,
+        atoms_to_sell: u64,
+        expiration: Option<LadderExpiration>,
+    ) -> Result<SimulationSummaryInAtoms> {
+        let LadderExpiration {
+            last_valid_slot,
+            last_valid_unix_timestamp_in_seconds,
+        } = expiration.unwrap_or_default();
+
+        let market_account_data = self.fetch_account_data(market_key).await?;
+        let (header_bytes, bytes) = market_account_data.split_at(size_of::<MarketHeader>());
+        let meta = self.get_market_metadata_from_header_bytes(header_bytes)?;
+        let market = load_market(&meta.market_size_params, bytes)
+            .map_err(|_| {
+                anyhow!(
+                    "Market configuration not found for key {}",
+                    market_key.to_string()
+                )
+            })?
+            .inner;
+
+        let ladder = market.get_ladder_with_expiration(
+            u64::MAX,
+            last_valid_slot,
+            last_valid_unix_timestamp_in_seconds,
+        );
+        let metadata = self.get_market_metadata_from_cache(market_key)?;
+        let side = match input_mint_key {
+            _ if input_mint_key == &metadata.base_mint => Side::Ask,
+            _ if input_mint_key == &metadata.quote_mint => Side::Bid,
+            _ => bail!(
+                "Input mint key {} does not match market base or quote mint",
+                input_mint_key.to_string()
+            ),
+        };
+
+        // convert atoms to lots
+        let mut lots_to_sell = match side {
+            Side::Bid => metadata.quote_atoms_to_quote_lots_rounded_down(atoms_to_sell),
+            Side::Ask => metadata.base_atoms_to_base_lots_rounded_down(atoms_to_sell),
         };
 
         // If the input is quote, apply the fee before the swap
@@ -548,23 +2459,54 @@ impl SDKClient {
         })
     }
 
+    /// Converts raw events to `PhoenixEvent`s, fetching the metadata of markets that are not
+    /// loaded. Returns `None` if that fails. See `parse_raw_phoenix_events_with_options`.
     pub async fn parse_raw_phoenix_events(
         &self,
         raw_phoenix_events: Vec<RawPhoenixEvent>,
     ) -> Option<Vec<PhoenixEvent>> {
-        let mut trade_direction = None;
+        self.parse_raw_phoenix_events_with_options(raw_phoenix_events, &Default::default())
+            .await
+            .ok()
+    }
+
+    /// Converts raw events to `PhoenixEvent`s. Amounts in lots are converted with the metadata
+    /// of the market named in each event's header, so a transaction touching several markets is
+    /// converted market by market.
+    pub async fn parse_raw_phoenix_events_with_options(
+        &self,
+        raw_phoenix_events: Vec<RawPhoenixEvent>,
+        options: &EventParseOptions,
+    ) -> Result<Vec<PhoenixEvent>> {
         let mut market_events = vec![];
-        let mut cached_metadata = self.markets.clone();
+        let mut cached_metadata = BTreeMap::clone(&self.markets.load());
         for raw_phoenix_event in raw_phoenix_events {
             let header = raw_phoenix_event.header;
+            if options
+                .expected_market
+                .is_some_and(|market| market != header.market)
+            {
+                continue;
+            }
             if let std::collections::btree_map::Entry::Vacant(e) =
                 cached_metadata.entry(header.market)
             {
-                let metadata = self.get_market_metadata(&header.market).await.ok()?;
-                e.insert(metadata);
+                match options.unknown_markets {
+                    UnknownMarketPolicy::Fetch => {
+                        e.insert(self.get_market_metadata(&header.market).await?);
+                    }
+                    UnknownMarketPolicy::Skip => continue,
+                    UnknownMarketPolicy::Error => bail!(
+                        "Events of market {} in {}, which is not loaded",
+                        header.market,
+                        header.signature
+                    ),
+                }
             }
-            let meta = cached_metadata.get(&header.market)?;
+            let meta = &cached_metadata[&header.market];
 
+            // A fill summary follows the fills of its own instruction
+            let mut trade_direction = None;
             for phoenix_event in raw_phoenix_event.batch {
                 match phoenix_event {
                     PhoenixMarketEvent::Fill(FillEvent {
@@ -575,7 +2517,14 @@ impl SDKClient {
                         base_lots_filled,
                         base_lots_remaining,
                     }) => {
-                        let side_filled = Side::from_order_sequence_number(order_sequence_number);
+                        let fill = Fill::new(
+                            order_sequence_number,
+                            maker_id,
+                            header.signer,
+                            price_in_ticks,
+                            base_lots_filled,
+                            base_lots_remaining,
+                        );
                         market_events.push(PhoenixEvent {
                             market: header.market,
                             sequence_number: header.sequence_number,
@@ -584,24 +2533,10 @@ impl SDKClient {
                             signature: header.signature,
                             signer: header.signer,
                             event_index: index as u64,
-                            details: MarketEventDetails::Fill(Fill {
-                                order_sequence_number,
-                                maker: maker_id,
-                                taker: header.signer,
-                                price_in_ticks,
-                                base_lots_filled,
-                                base_lots_remaining,
-                                side_filled: Side::from_order_sequence_number(
-                                    order_sequence_number,
-                                ),
-                                is_full_fill: base_lots_remaining == 0,
-                            }),
+                            details: MarketEventDetails::Fill(fill),
                         });
                         if trade_direction.is_none() {
-                            trade_direction = match side_filled {
-                                Side::Bid => Some(-1),
-                                Side::Ask => Some(1),
-                            }
+                            trade_direction = Some(fill.trade_direction());
                         }
                     }
                     PhoenixMarketEvent::Reduce(ReduceEvent {
@@ -762,21 +2697,254 @@ impl SDKClient {
                 }
             }
         }
-        Some(market_events)
+        Ok(market_events)
+    }
+
+    /// Fetches a transaction at the history commitment of the commitment policy. Without an
+    /// RPC pool to fail over, the request is tried up to three times.
+    async fn fetch_transaction(&self, signature: &Signature) -> Result<ParsedTransaction> {
+        Ok(parse_transaction(
+            self.fetch_encoded_transaction(signature).await?,
+        ))
+    }
+
+    /// Like `fetch_transaction`, keeping the status meta that `ParsedTransaction` drops.
+    async fn fetch_encoded_transaction(
+        &self,
+        signature: &Signature,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta> {
+        let config = || RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base58),
+            commitment: Some(self.commitment_policy.history_commitment()),
+            max_supported_transaction_version: Some(0),
+        };
+        let tx = match &self.rpc_pool {
+            Some(rpc_pool) => {
+                rpc_pool
+                    .read(|client| client.get_transaction_with_config(signature, config()))
+                    .await?
+            }
+            None => {
+                let mut attempts = 0;
+                loop {
+                    attempts += 1;
+                    match self
+                        .client
+                        .get_transaction_with_config(signature, config())
+                        .await
+                    {
+                        Ok(tx) => break tx,
+                        Err(e) if attempts == 3 => {
+                            return Err(anyhow!("Failed to fetch transaction {}: {}", signature, e))
+                        }
+                        Err(_) => {}
+                    }
+                }
+            }
+        };
+        Ok(tx)
     }
 
     pub async fn parse_events_from_transaction(
         &self,
         sig: &Signature,
     ) -> Option<Vec<PhoenixEvent>> {
-        let tx = self.client.get_transaction(sig).await.ok()?;
+        if self.trading_mode == TradingMode::Paper {
+            if let Some(events) = self.paper_exchange().get_transaction_events(sig) {
+                return Some(events);
+            }
+        }
+        self.throttle("sdk_client", RpcCategory::GetTransaction)
+            .await;
+        let tx = self.fetch_transaction(sig).await.ok()?;
         if tx.is_err {
             return None;
         }
-        let events = self.core.parse_events_from_transaction(&tx)?;
+        self.parse_fetched_transaction_events(sig, &tx).await
+    }
+
+    /// Like `parse_events_from_transaction`, but a transaction that cannot be fetched or parsed is
+    /// an error, so that callers that must not miss events, e.g. a backfill, can retry it. A
+    /// failed transaction has no events.
+    ///
+    /// Events that cannot be parsed under strict event parsing fail with
+    /// `PhoenixSdkError::MalformedEvents`, which retrying does not fix.
+    pub async fn get_transaction_events(&self, sig: &Signature) -> Result<Vec<PhoenixEvent>> {
+        Ok(self.get_transaction_events_salvaged(sig).await?.0)
+    }
+
+    /// Like `get_transaction_events`, also returning whether a malformed event record cut the
+    /// events short.
+    pub(crate) async fn get_transaction_events_salvaged(
+        &self,
+        sig: &Signature,
+    ) -> Result<(Vec<PhoenixEvent>, bool)> {
+        self.throttle("sdk_client", RpcCategory::GetTransaction)
+            .await;
+        let tx = self.fetch_transaction(sig).await?;
+        if tx.is_err {
+            return Ok((vec![], false));
+        }
+        let parsed = self
+            .core
+            .parse_events_from_instruction_data_lenient(sig, phoenix_inner_instructions(&tx));
+        let salvaged = parsed.is_err();
+        let events = self
+            .salvage_events(parsed)
+            .ok_or(PhoenixSdkError::MalformedEvents { signature: *sig })?;
+        let events = self
+            .parse_raw_phoenix_events_with_options(events, &Default::default())
+            .await?;
+        Ok((events, salvaged))
+    }
+
+    /// Like `get_transaction_events`, with the transaction's fee, compute units and fee payer,
+    /// e.g. to account for what a strategy spends on landing transactions. A failed transaction
+    /// has no events, but is still returned for its fee.
+    pub async fn parse_transaction_full(
+        &self,
+        sig: &Signature,
+    ) -> Result<ParsedPhoenixTransaction> {
+        if self.trading_mode == TradingMode::Paper {
+            bail!("Paper trades are not sent as transactions");
+        }
+        self.throttle("sdk_client", RpcCategory::GetTransaction)
+            .await;
+        let encoded = self.fetch_encoded_transaction(sig).await?;
+        let (fee_lamports, compute_units) = encoded_transaction::fee_and_compute_units(&encoded)?;
+        let tx = parse_transaction(encoded);
+        let events = if tx.is_err {
+            vec![]
+        } else {
+            self.parse_fetched_transaction_events(sig, &tx)
+                .await
+                .ok_or_else(|| anyhow!("Failed to parse events of transaction {}", sig))?
+        };
+        Ok(ParsedPhoenixTransaction {
+            signature: *sig,
+            slot: tx.slot,
+            block_time: tx.block_time,
+            signer: Pubkey::from_str(&tx.fee_payer)
+                .map_err(|e| anyhow!("Invalid fee payer {}: {}", tx.fee_payer, e))?,
+            fee_lamports,
+            compute_units,
+            is_err: tx.is_err,
+            events,
+        })
+    }
+
+    async fn parse_fetched_transaction_events(
+        &self,
+        sig: &Signature,
+        tx: &ParsedTransaction,
+    ) -> Option<Vec<PhoenixEvent>> {
+        let events = self.salvage_events(
+            self.core
+                .parse_events_from_instruction_data_lenient(sig, phoenix_inner_instructions(tx)),
+        )?;
         self.parse_raw_phoenix_events(events).await
     }
 
+    /// The events of a lenient parse under the client's event parsing mode: all of them, or if
+    /// a record was malformed, the ones before it unless parsing is strict. The error is sent
+    /// to the event parse error sender, or printed.
+    fn salvage_events(
+        &self,
+        parsed: std::result::Result<Vec<RawPhoenixEvent>, PartialParse>,
+    ) -> Option<Vec<RawPhoenixEvent>> {
+        let partial = match parsed {
+            Ok(events) => return Some(events),
+            Err(partial) => partial,
+        };
+        let events = (!self.strict_event_parsing).then_some(partial.events);
+        match &self.event_parse_errors {
+            Some(sender) => {
+                sender.send(partial.error).ok();
+            }
+            None => println!(
+                "Error parsing events after byte {}: {}",
+                partial.bytes_consumed, partial.error
+            ),
+        }
+        events
+    }
+
+    /// Fetches a transaction and decodes its Phoenix instructions alongside its events. Unlike
+    /// `parse_events_from_transaction`, a failed transaction is still decoded, so that orders and
+    /// cancels that never landed can be told apart from ones that did.
+    pub async fn parse_instructions_from_transaction(
+        &self,
+        sig: &Signature,
+    ) -> Result<TransactionIntents> {
+        if self.trading_mode == TradingMode::Paper {
+            bail!("Paper trades are not sent as transactions");
+        }
+        self.throttle("sdk_client", RpcCategory::GetTransaction)
+            .await;
+        let tx = self.fetch_transaction(sig).await?;
+        let instructions = instruction_parser::parse_transaction_instructions(&tx)?;
+        let events =
+            if tx.is_err {
+                vec![]
+            } else {
+                match self.salvage_events(self.core.parse_events_from_instruction_data_lenient(
+                    sig,
+                    phoenix_inner_instructions(&tx),
+                )) {
+                    Some(events) => self
+                        .parse_raw_phoenix_events(events)
+                        .await
+                        .ok_or_else(|| anyhow!("Failed to parse events of transaction {}", sig))?,
+                    None => vec![],
+                }
+            };
+        Ok(TransactionIntents {
+            signature: *sig,
+            slot: tx.slot,
+            is_err: tx.is_err,
+            instructions,
+            events,
+        })
+    }
+
+    /// Parses the events of a transaction that was already fetched, e.g. by an indexer, instead
+    /// of fetching it again. Any encoding and message version is accepted, see
+    /// `encoded_transaction::inner_instructions`. A failed transaction has no events.
+    pub async fn parse_events_from_encoded_transaction(
+        &self,
+        sig: &Signature,
+        tx: &EncodedConfirmedTransactionWithStatusMeta,
+    ) -> Result<Vec<PhoenixEvent>> {
+        self.parse_events_from_encoded_transaction_with_options(sig, tx, &Default::default())
+            .await
+    }
+
+    /// Like `parse_events_from_encoded_transaction`, with `options` applied as in
+    /// `parse_raw_phoenix_events_with_options`.
+    pub async fn parse_events_from_encoded_transaction_with_options(
+        &self,
+        sig: &Signature,
+        tx: &EncodedConfirmedTransactionWithStatusMeta,
+        options: &EventParseOptions,
+    ) -> Result<Vec<PhoenixEvent>> {
+        if encoded_transaction::is_failed(tx) {
+            return Ok(vec![]);
+        }
+        let instructions = encoded_transaction::inner_instructions(tx)?;
+        let events = self
+            .salvage_events(
+                self.core.parse_events_from_instruction_data_lenient(
+                    sig,
+                    instructions
+                        .iter()
+                        .map(|(program_id, data)| (*program_id, data.as_slice())),
+                ),
+            )
+            .ok_or_else(|| anyhow!("Failed to parse Phoenix events in {}", sig))?;
+        self.parse_raw_phoenix_events_with_options(events, options)
+            .await
+    }
+
     pub async fn parse_places(&self, signature: &Signature) -> Vec<PhoenixEvent> {
         let events = self
             .parse_events_from_transaction(signature)
@@ -846,26 +3014,431 @@ impl SDKClient {
     }
 }
 
-/// Functions for sending transactions that interact with the Phoenix program
+/// Sending transactions as Jito bundles
+#[cfg(feature = "jito")]
 impl SDKClient {
-    pub async fn send_ioc(
-        &self,
-        market_key: &Pubkey,
-        price: u64,
-        side: Side,
-        size: u64,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let new_order_ix = self.get_ioc_ix(market_key, price, side, size).ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
-            .await
-            .ok()?;
-        let fills = self.parse_fills(&signature).await;
-        Some((signature, fills))
+    pub fn set_bundle_sender(&mut self, bundle_sender: Option<Arc<BundleSender>>) {
+        self.bundle_sender = bundle_sender;
     }
 
-    pub async fn send_fok_buy(
+    pub fn bundle_sender(&self) -> Option<&Arc<BundleSender>> {
+        self.bundle_sender.as_ref()
+    }
+
+    /// Sends each instruction list as one transaction of a Jito bundle, tipping `tip_lamports`
+    /// in the last transaction, and waits for the bundle to land. Returns the transactions'
+    /// signatures in order.
+    ///
+    /// If the bundle does not land and `fallback_to_rpc` is set, the same signed transactions are
+    /// sent through RPC, so a bundle that lands late cannot execute them twice. In paper mode the
+    /// transactions are processed one after another without a tip.
+    pub async fn send_ixs_as_bundle(
+        &self,
+        txs: Vec<Vec<Instruction>>,
+        tip_lamports: u64,
+    ) -> Result<Vec<Signature>> {
+        if self.trading_mode == TradingMode::Paper {
+            let mut signatures = vec![];
+            for ixs in txs {
+                signatures.push(self.send_ixs(ixs).await?);
+            }
+            return Ok(signatures);
+        }
+
+        let mut reservations = vec![];
+        if let Some(risk_manager) = self.risk_manager().as_mut() {
+            for ixs in txs.iter() {
+                match risk_manager.reserve(ixs, &self.markets.load(), true) {
+                    Ok(reservation) => reservations.push(reservation),
+                    Err(e) => {
+                        for reservation in reservations {
+                            risk_manager.release(reservation);
+                        }
+                        return Err(e.into());
+                    }
+                }
+            }
+        }
+        let client_order_ids = self
+            .latency_tracker
+            .as_ref()
+            .map(|_| {
+                txs.iter()
+                    .map(|ixs| client_order_ids(ixs))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let submitted_at = Instant::now();
+        let result = self.submit_bundle(txs, tip_lamports).await;
+        if let (Some(latency_tracker), Ok(signatures)) = (&self.latency_tracker, &result) {
+            latency_tracker.record_confirmation(submitted_at, Instant::now());
+            for (signature, client_order_ids) in signatures.iter().zip(client_order_ids) {
+                latency_tracker.record_submit(*signature, &client_order_ids, submitted_at);
+            }
+        }
+        if let Some(signature) = result
+            .as_ref()
+            .ok()
+            .and_then(|signatures| signatures.last())
+        {
+            self.record_last_transaction(*signature);
+        }
+        match &result {
+            Ok(signatures) => {
+                for (reservation, signature) in reservations.into_iter().zip(signatures) {
+                    let events = self.parse_events_from_transaction(signature).await;
+                    if let Some(risk_manager) = self.risk_manager().as_mut() {
+                        risk_manager.confirm(
+                            reservation,
+                            *signature,
+                            events.as_deref(),
+                            &self.markets.load(),
+                        );
+                    }
+                }
+            }
+            Err(_) => {
+                if let Some(risk_manager) = self.risk_manager().as_mut() {
+                    for reservation in reservations {
+                        risk_manager.release(reservation);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    async fn submit_bundle(
+        &self,
+        txs: Vec<Vec<Instruction>>,
+        tip_lamports: u64,
+    ) -> Result<Vec<Signature>> {
+        let bundle_sender = self
+            .bundle_sender
+            .as_ref()
+            .ok_or_else(|| anyhow!("No bundle sender set. Call set_bundle_sender first"))?;
+        self.throttle("sdk_client", RpcCategory::Other).await;
+        let blockhash = match &self.rpc_pool {
+            Some(rpc_pool) => {
+                rpc_pool
+                    .read(|client| client.get_latest_blockhash())
+                    .await?
+            }
+            None => self.client.get_latest_blockhash().await?,
+        };
+        let transactions =
+            bundle_sender.build_transactions(txs, tip_lamports, &self.client.payer, blockhash)?;
+        let signatures = transactions
+            .iter()
+            .map(|tx| tx.signatures[0])
+            .collect::<Vec<_>>();
+
+        let status = match bundle_sender.send_bundle(&transactions).await {
+            Ok(bundle_id) => bundle_sender.wait_for_bundle(&bundle_id).await,
+            Err(e) => Err(e),
+        };
+        match status {
+            Ok(BundleStatus::Landed { .. }) => return Ok(signatures),
+            _ if bundle_sender.config.fallback_to_rpc => {}
+            Ok(status) => return Err(anyhow!("Bundle did not land: {:?}", status)),
+            Err(e) => return Err(anyhow!("Failed to send bundle: {}", e)),
+        }
+        for transaction in transactions.iter() {
+            self.throttle("sdk_client", RpcCategory::SendTransaction)
+                .await;
+            let signature = match &self.rpc_pool {
+                Some(rpc_pool) => rpc_pool.send_transaction(transaction).await?,
+                None => self
+                    .client
+                    .send_transaction(transaction)
+                    .await
+                    .map_err(|e| anyhow!("Failed to send transaction: {}", e))?,
+            };
+            self.confirm_signature(&signature).await?;
+        }
+        Ok(signatures)
+    }
+}
+
+/// Functions for sending transactions that interact with the Phoenix program
+impl SDKClient {
+    /// Signs and sends `ixs` in a single transaction with the payer as the only signer.
+    ///
+    /// In paper mode, nothing is sent: the instructions are simulated by the paper exchange, the
+    /// resulting events are pushed to its event channel (if set), and the returned signature can be
+    /// passed to `parse_events_from_transaction` like a real one. Markets without a snapshot are
+    /// fetched on first use.
+    ///
+    /// If a risk manager is set, orders that breach its limits are rejected with a `RiskViolation`
+    /// error before anything is sent, and the transaction's events are fetched after sending to
+    /// keep its positions and open orders current.
+    ///
+    /// A transaction that fails in simulation or on-chain is returned as a
+    /// `PhoenixSdkError::TransactionFailed`, with its logs and the decoded Phoenix error if the
+    /// program raised one. `error::is_retryable` tells whether sending again could succeed.
+    pub async fn send_ixs(&self, ixs: Vec<Instruction>) -> Result<Signature> {
+        self.send_ixs_with_risk_override(ixs, false).await
+    }
+
+    /// Like `send_ixs`, but with `override_risk_checks` set the orders are sent even if they breach
+    /// the risk limits, e.g. for manual intervention. They are still tracked by the risk manager.
+    pub async fn send_ixs_with_risk_override(
+        &self,
+        ixs: Vec<Instruction>,
+        override_risk_checks: bool,
+    ) -> Result<Signature> {
+        let reservation = match self.risk_manager().as_mut() {
+            Some(risk_manager) => {
+                Some(risk_manager.reserve(&ixs, &self.markets.load(), !override_risk_checks)?)
+            }
+            None => None,
+        };
+        let client_order_ids = self
+            .latency_tracker
+            .as_ref()
+            .map(|_| client_order_ids(&ixs))
+            .unwrap_or_default();
+        let submitted_at = Instant::now();
+        let result = self.sign_and_send_ixs(ixs).await;
+        if let Ok(signature) = &result {
+            self.record_last_transaction(*signature);
+        }
+        if let (Some(latency_tracker), Ok(signature)) = (&self.latency_tracker, &result) {
+            latency_tracker.record_submit(*signature, &client_order_ids, submitted_at);
+            latency_tracker.record_confirmation(submitted_at, Instant::now());
+        }
+        let Some(reservation) = reservation else {
+            return result;
+        };
+        match result {
+            Ok(signature) => {
+                let events = self.parse_events_from_transaction(&signature).await;
+                if let Some(risk_manager) = self.risk_manager().as_mut() {
+                    risk_manager.confirm(
+                        reservation,
+                        signature,
+                        events.as_deref(),
+                        &self.markets.load(),
+                    );
+                }
+                if let Err(e) = self.cancel_duplicate_orders().await {
+                    println!("Failed to cancel duplicate orders: {}", e);
+                }
+                Ok(signature)
+            }
+            Err(e) => {
+                if let Some(risk_manager) = self.risk_manager().as_mut() {
+                    risk_manager.release(reservation);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns a `PhoenixSdkError` as is, fetching the logs of a transaction that failed on-chain
+    /// to decode its Phoenix error. Other errors are wrapped as a send failure.
+    async fn with_transaction_logs(&self, error: anyhow::Error) -> anyhow::Error {
+        let failed = match error.downcast::<PhoenixSdkError>() {
+            Ok(failed) => failed,
+            Err(e) => return anyhow!("Failed to send transaction: {}", e),
+        };
+        let PhoenixSdkError::TransactionFailed {
+            signature: Some(signature),
+            error,
+            logs,
+            ..
+        } = &failed
+        else {
+            return failed.into();
+        };
+        if !logs.is_empty() {
+            return failed.into();
+        }
+        self.throttle("sdk_client", RpcCategory::GetTransaction)
+            .await;
+        let tx = self.fetch_transaction(signature).await.ok();
+        match tx {
+            Some(tx) => {
+                PhoenixSdkError::transaction_failed(Some(*signature), error.clone(), tx.logs).into()
+            }
+            None => failed.into(),
+        }
+    }
+
+    /// Simulates `ixs` with the maximum compute unit limit and returns the units they consume.
+    /// Estimates are cached by `instruction_shape`, so instructions that only differ in their
+    /// arguments, e.g. the price of an order, are simulated once. Compute budget instructions in
+    /// `ixs` are left out of the simulation.
+    ///
+    /// A simulation that fails is returned as a `PhoenixSdkError::TransactionFailed` with its
+    /// logs.
+    pub async fn estimate_compute_units(&self, ixs: &[Instruction]) -> Result<u64> {
+        let shape = instruction_shape(ixs);
+        if let Some(units) = self.compute_unit_cache().get(shape) {
+            return Ok(units);
+        }
+        let ixs = with_compute_unit_limit(
+            ixs.iter()
+                .filter(|ix| ix.program_id != compute_budget::id())
+                .cloned()
+                .collect(),
+            MAX_COMPUTE_UNIT_LIMIT,
+        );
+        // Simulated without signatures, against the latest blockhash
+        let transaction = Transaction::new_with_payer(&ixs, Some(&self.client.payer.pubkey()));
+        let config = || RpcSimulateTransactionConfig {
+            sig_verify: false,
+            replace_recent_blockhash: true,
+            ..Default::default()
+        };
+        self.throttle("sdk_client", RpcCategory::Other).await;
+        let simulation = match &self.rpc_pool {
+            Some(rpc_pool) => {
+                rpc_pool
+                    .read(|client| client.simulate_transaction_with_config(&transaction, config()))
+                    .await?
+            }
+            None => {
+                self.client
+                    .simulate_transaction_with_config(&transaction, config())
+                    .await?
+            }
+        }
+        .value;
+        if let Some(error) = simulation.err {
+            return Err(PhoenixSdkError::transaction_failed(
+                None,
+                error,
+                simulation.logs.unwrap_or_default(),
+            )
+            .into());
+        }
+        let units = simulation
+            .units_consumed
+            .ok_or_else(|| anyhow!("The simulation did not report the compute units consumed"))?;
+        self.compute_unit_cache().insert(shape, units);
+        Ok(units)
+    }
+
+    /// Sets the compute unit limit of `ixs` if a compute unit margin is set.
+    async fn with_estimated_compute_unit_limit(&self, ixs: Vec<Instruction>) -> Vec<Instruction> {
+        let Some(margin) = self.compute_unit_margin else {
+            return ixs;
+        };
+        if has_compute_budget_instruction(&ixs) {
+            return ixs;
+        }
+        match self.estimate_compute_units(&ixs).await {
+            Ok(units) => with_compute_unit_limit(ixs, margin.limit(units)),
+            Err(e) => {
+                // Sent without a limit, so that a failing transaction is reported with its logs
+                println!("Failed to estimate compute units: {}", e);
+                ixs
+            }
+        }
+    }
+
+    /// Waits for a sent transaction to reach the send commitment of the commitment policy,
+    /// failing if it errors or is not found in time. An on-chain failure is returned as a
+    /// `PhoenixSdkError::TransactionFailed` without logs.
+    ///
+    /// Sends through an RPC pool already wait for this. Sends without one return as soon as the
+    /// transaction is sent.
+    pub async fn confirm_signature(&self, signature: &Signature) -> Result<()> {
+        let commitment = self.commitment_policy.send;
+        if let Some(rpc_pool) = &self.rpc_pool {
+            rpc_pool
+                .confirm_with_commitment(*signature, commitment)
+                .await?;
+            return Ok(());
+        }
+        self.throttle("sdk_client", RpcCategory::Other).await;
+        self.client
+            .poll_for_signature_with_commitment(signature, commitment)
+            .await
+            .map_err(|e| anyhow!("Transaction {} was not confirmed: {}", signature, e))?;
+        match self
+            .client
+            .get_signature_status_with_commitment(signature, commitment)
+            .await?
+        {
+            Some(Err(e)) => {
+                Err(PhoenixSdkError::transaction_failed(Some(*signature), e, vec![]).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn sign_and_send_ixs(&self, ixs: Vec<Instruction>) -> Result<Signature> {
+        if self.trading_mode == TradingMode::Live {
+            let ixs = self.with_estimated_compute_unit_limit(ixs).await;
+            self.throttle("sdk_client", RpcCategory::SendTransaction)
+                .await;
+            let result = match &self.rpc_pool {
+                Some(rpc_pool) => {
+                    rpc_pool
+                        .sign_send_instructions_with_commitment(
+                            ixs,
+                            &self.client.payer,
+                            self.commitment_policy.send,
+                        )
+                        .await
+                }
+                None => self
+                    .client
+                    .sign_send_instructions(ixs, vec![])
+                    .await
+                    .map_err(|e| match &e {
+                        EllipsisClientError::SolanaClient(client_error) => {
+                            PhoenixSdkError::from_client_error(client_error)
+                                .map(Into::into)
+                                .unwrap_or_else(|| e.into())
+                        }
+                        _ => e.into(),
+                    }),
+            };
+            return match result {
+                Ok(signature) => Ok(signature),
+                Err(e) => Err(self.with_transaction_logs(e).await),
+            };
+        }
+
+        for market_key in PaperExchange::markets_for_instructions(&ixs) {
+            if !self.paper_exchange().has_market_state(&market_key) {
+                self.refresh_paper_market_state(&market_key).await?;
+            }
+        }
+        let (signature, events, sender) = {
+            let mut paper_exchange = self.paper_exchange();
+            let (signature, events) =
+                paper_exchange.process_transaction(&self.trader, &ixs, &self.markets.load())?;
+            (signature, events, paper_exchange.event_sender())
+        };
+        if let Some(sender) = sender {
+            if !events.is_empty() {
+                sender
+                    .send_events(events.into_iter().map(SDKMarketEvent::from).collect())
+                    .await
+                    .map_err(|e| anyhow!("Failed to publish paper events: {}", e))?;
+            }
+        }
+        Ok(signature)
+    }
+
+    pub async fn send_ioc(
+        &self,
+        market_key: &Pubkey,
+        price: u64,
+        side: Side,
+        size: u64,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        let new_order_ix = self.get_ioc_ix(market_key, price, side, size).ok()?;
+        let signature = self.send_ixs(vec![new_order_ix]).await.ok()?;
+        let fills = self.parse_fills(&signature).await;
+        Some((signature, fills))
+    }
+
+    pub async fn send_fok_buy(
         &self,
         market_key: &Pubkey,
         price: u64,
@@ -875,11 +3448,7 @@ impl SDKClient {
             .get_fok_buy_ix(market_key, price, size_in_quote_lots)
             .ok()?;
 
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
-            .await
-            .ok()?;
+        let signature = self.send_ixs(vec![new_order_ix]).await.ok()?;
         let fills = self.parse_fills(&signature).await;
         Some((signature, fills))
     }
@@ -894,15 +3463,105 @@ impl SDKClient {
             .get_fok_sell_ix(market_key, price, size_in_base_lots)
             .ok()?;
 
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
-            .await
-            .ok()?;
+        let signature = self.send_ixs(vec![new_order_ix]).await.ok()?;
         let fills = self.parse_fills(&signature).await;
         Some((signature, fills))
     }
 
+    /// Sends a fill-or-kill order, e.g. from `get_fok_buy_generic_ix`, and checks what its
+    /// `FillSummary` reports against `expected`. With `unwind_on_mismatch` set, a mismatched fill
+    /// is reversed with a market IOC on the other side, so that the order either filled as
+    /// expected or, up to the unwind's price impact, did nothing.
+    pub async fn send_fok_and_verify(
+        &self,
+        ix: Instruction,
+        expected: FokExpectation,
+    ) -> Result<FokOutcome> {
+        let match_limit = ix
+            .data
+            .get(1..)
+            .and_then(|data| decode_order_packet(data).ok())
+            .and_then(|packet| match packet {
+                OrderPacket::ImmediateOrCancel { match_limit, .. } => match_limit,
+                _ => None,
+            });
+        let signature = self.send_ixs(vec![ix]).await?;
+        let events = self
+            .parse_events_from_transaction(&signature)
+            .await
+            .ok_or_else(|| {
+                anyhow!(
+                    "Failed to parse the events of FOK transaction {}",
+                    signature
+                )
+            })?;
+        let Some((market, summary)) = events.iter().find_map(|event| match event.details {
+            MarketEventDetails::FillSummary(summary) => Some((event.market, summary)),
+            _ => None,
+        }) else {
+            return Ok(FokOutcome::NotFilled(signature));
+        };
+        let meta = self.get_market_metadata(&market).await?;
+        let fill = FokFill {
+            signature,
+            market,
+            base_lots_filled: summary.total_base_filled / meta.base_atoms_per_base_lot,
+            quote_lots_filled: summary.total_quote_filled_including_fees
+                / meta.quote_atoms_per_quote_lot,
+            fee_in_quote_lots: summary.total_quote_fees / meta.quote_atoms_per_quote_lot,
+        };
+        let orders_matched = events
+            .iter()
+            .filter(|event| matches!(event.details, MarketEventDetails::Fill(_)))
+            .count() as u64;
+        let outcome = match (expected.check(fill), match_limit) {
+            (FokOutcome::Mismatch { .. }, Some(match_limit)) if orders_matched >= match_limit => {
+                FokOutcome::MatchLimitReached {
+                    fill,
+                    match_limit,
+                    unwind: None,
+                }
+            }
+            (outcome, _) => outcome,
+        };
+        if !(expected.unwind_on_mismatch
+            && matches!(
+                outcome,
+                FokOutcome::Mismatch { .. } | FokOutcome::MatchLimitReached { .. }
+            ))
+        {
+            return Ok(outcome);
+        }
+        let unwind_ix = self.get_ioc_full_ix(
+            &market,
+            expected.side.opposite(),
+            None,
+            fill.base_lots_filled,
+            0,
+            0,
+            0,
+            SelfTradeBehavior::CancelProvide,
+            None,
+            0,
+            false,
+        )?;
+        let unwind = self
+            .send_ixs(vec![unwind_ix])
+            .await
+            .map_err(|e| anyhow!("Failed to unwind mismatched FOK fill {:?}: {}", fill, e))?;
+        Ok(match outcome {
+            FokOutcome::MatchLimitReached { match_limit, .. } => FokOutcome::MatchLimitReached {
+                fill,
+                match_limit,
+                unwind: Some(unwind),
+            },
+            _ => FokOutcome::Mismatch {
+                fill,
+                unwind: Some(unwind),
+            },
+        })
+    }
+
     pub async fn send_ioc_with_slippage(
         &self,
         market_key: &Pubkey,
@@ -913,11 +3572,7 @@ impl SDKClient {
         let new_order_ix = self
             .get_ioc_with_slippage_ix(market_key, lots_in, min_lots_out, side)
             .ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
-            .await
-            .ok()?;
+        let signature = self.send_ixs(vec![new_order_ix]).await.ok()?;
         let fills = self.parse_fills(&signature).await;
         Some((signature, fills))
     }
@@ -933,11 +3588,7 @@ impl SDKClient {
             .get_post_only_new_maker_ixs(market_key, price, side, size)
             .await
             .ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(new_order_ixs, vec![])
-            .await
-            .ok()?;
+        let signature = self.send_ixs(new_order_ixs).await.ok()?;
         let fills = self.parse_fills(&signature).await;
         Some((signature, fills))
     }
@@ -953,31 +3604,304 @@ impl SDKClient {
             .get_limit_order_new_maker_ixs(market_key, price, side, size)
             .await
             .ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(new_order_ixs, vec![])
-            .await
-            .ok()?;
+        let signature = self.send_ixs(new_order_ixs).await.ok()?;
         let (fills, places) = self.parse_fills_and_places(&signature).await;
         Some((signature, places, fills))
     }
 
+    /// Sends a limit order with a random client order id and returns a `TrackedOrder` that
+    /// follows it through the event router: await `acknowledged` for its order id, then
+    /// `next_update` or `closed` for its fills and cancels. Unlike `send_limit_order`, it sends
+    /// only the order instruction, so the trader needs a seat and token accounts already.
+    pub async fn place_limit_order_tracked(
+        &self,
+        market_key: &Pubkey,
+        price: u64,
+        side: Side,
+        size: u64,
+    ) -> Result<TrackedOrder> {
+        let event_router = self
+            .event_router
+            .as_ref()
+            .ok_or_else(|| anyhow!("No event router set. Call set_event_router first"))?;
+        let client_order_id = rand::random::<u128>().max(1);
+        let ix = self.get_limit_order_generic_ix(
+            market_key,
+            price,
+            side,
+            size,
+            None,
+            None,
+            Some(client_order_id),
+            None,
+            None,
+            None,
+            None,
+        )?;
+        // Subscribe before sending, so that events routed before `send_ixs` returns are kept
+        let order =
+            TrackedOrder::subscribe(event_router, *market_key, self.trader, client_order_id);
+        let signature = self.send_ixs(vec![ix]).await?;
+        Ok(order.with_signature(signature))
+    }
+
+    /// Sends a post-only order whose client order id is derived from `intent`, so that sending
+    /// the same intent again, e.g. on a network retry, places an order the risk manager's
+    /// `OrderManager` recognizes as a duplicate if both land. Returns the signature and the client
+    /// order id. Like `place_limit_order_tracked`, it sends only the order instruction.
+    pub async fn send_post_only_for_intent(
+        &self,
+        market_key: &Pubkey,
+        price: u64,
+        side: Side,
+        size: u64,
+        intent: &IntentKey,
+    ) -> Result<(Signature, u128)> {
+        let client_order_id = intent.client_order_id();
+        let ix = self.get_post_only_generic_ix(
+            market_key,
+            price,
+            side,
+            size,
+            Some(client_order_id),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )?;
+        let signature = self.send_ixs(vec![ix]).await?;
+        Ok((signature, client_order_id))
+    }
+
+    /// Like `send_post_only_for_intent`, for a limit order.
+    pub async fn send_limit_order_for_intent(
+        &self,
+        market_key: &Pubkey,
+        price: u64,
+        side: Side,
+        size: u64,
+        intent: &IntentKey,
+    ) -> Result<(Signature, u128)> {
+        let client_order_id = intent.client_order_id();
+        let ix = self.get_limit_order_generic_ix(
+            market_key,
+            price,
+            side,
+            size,
+            None,
+            None,
+            Some(client_order_id),
+            None,
+            None,
+            None,
+            None,
+        )?;
+        let signature = self.send_ixs(vec![ix]).await?;
+        Ok((signature, client_order_id))
+    }
+
+    /// Cancels the duplicate orders the risk manager's `OrderManager` has queued under
+    /// `DuplicateOrderPolicy::Cancel`, one transaction per market. `send_ixs` calls this after
+    /// every transaction it confirms; call it directly for duplicates found in events fed to the
+    /// risk manager from elsewhere. Returns the signatures of the cancels.
+    pub async fn cancel_duplicate_orders(&self) -> Result<Vec<Signature>> {
+        let duplicates = match self.risk_manager().as_mut() {
+            Some(risk_manager) => risk_manager.orders.take_duplicates_to_cancel(),
+            None => return Ok(vec![]),
+        };
+        let mut by_market = BTreeMap::<Pubkey, Vec<FIFOOrderId>>::new();
+        for duplicate in duplicates {
+            by_market
+                .entry(duplicate.market)
+                .or_default()
+                .push(duplicate.duplicate);
+        }
+        let mut signatures = vec![];
+        for (market_key, order_ids) in by_market {
+            let ix = self.get_cancel_ids_ix(&market_key, order_ids)?;
+            // Boxed, since `send_ixs` calls back into this function
+            signatures.push(Box::pin(self.send_ixs(vec![ix])).await?);
+        }
+        Ok(signatures)
+    }
+
     pub async fn send_cancel_ids(
         &self,
         market_key: &Pubkey,
         ids: Vec<FIFOOrderId>,
     ) -> Option<(Signature, Vec<PhoenixEvent>)> {
         let cancel_ix = self.get_cancel_ids_ix(market_key, ids).ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![cancel_ix], vec![])
-            .await
-            .ok()?;
+        let signature = self.send_ixs(vec![cancel_ix]).await.ok()?;
 
         let cancels = self.parse_cancels(&signature).await;
         Some((signature, cancels))
     }
 
+    /// Sends a cancel instruction, e.g. from `get_cancel_up_to_ix`, and reports the orders it
+    /// removed from the transaction's `Reduce` events. Freed amounts are what the orders had
+    /// locked: they are withdrawn to the wallet or left deposited, depending on the instruction.
+    pub async fn send_cancel_and_report(&self, ix: Instruction) -> Result<CancelReport> {
+        let signature = self.send_ixs(vec![ix]).await?;
+        let events = self
+            .parse_events_from_transaction(&signature)
+            .await
+            .ok_or_else(|| {
+                anyhow!(
+                    "Failed to parse the events of cancel transaction {}",
+                    signature
+                )
+            })?;
+        let mut report = CancelReport {
+            signature,
+            ..Default::default()
+        };
+        for event in events {
+            let MarketEventDetails::Reduce(Reduce {
+                order_sequence_number,
+                maker,
+                price_in_ticks,
+                base_lots_removed,
+                base_lots_remaining,
+                is_full_cancel,
+            }) = event.details
+            else {
+                continue;
+            };
+            if maker != self.trader {
+                continue;
+            }
+            let meta = self.get_market_metadata(&event.market).await?;
+            let side = Side::from_order_sequence_number(order_sequence_number);
+            match side {
+                Side::Bid => {
+                    report.freed_quote_atoms +=
+                        meta.base_lots_and_price_to_quote_atoms(base_lots_removed, price_in_ticks)
+                }
+                Side::Ask => {
+                    report.freed_base_atoms += meta.base_lots_to_base_atoms(base_lots_removed)
+                }
+            }
+            report.orders.push(CancelledOrder {
+                market: event.market,
+                order_id: FIFOOrderId::new_from_untyped(price_in_ticks, order_sequence_number),
+                side,
+                base_lots_removed,
+                base_lots_remaining,
+                is_full_cancel,
+            });
+        }
+        Ok(report)
+    }
+
+    /// Gives a resting order a new size and price with the cheapest instructions, see
+    /// `AmendPath::choose`, and returns the path taken. No transaction is sent if the order is
+    /// unchanged. A reduce is applied to the risk manager's open orders as soon as it is sent,
+    /// and reverted if the transaction fails.
+    pub async fn amend_order(
+        &self,
+        market_key: &Pubkey,
+        order: &OpenOrder,
+        new_size_lots: u64,
+        new_price_in_ticks: u64,
+    ) -> Result<(AmendPath, Option<Signature>)> {
+        let (path, ixs) =
+            self.get_amend_order_ixs(market_key, order, new_size_lots, new_price_in_ticks)?;
+        if ixs.is_empty() {
+            return Ok((path, None));
+        }
+        if let AmendPath::Reduce { lots_to_remove } = path {
+            if let Some(risk_manager) = self.risk_manager().as_mut() {
+                risk_manager
+                    .orders
+                    .reduce_pending(market_key, order.order_id, lots_to_remove);
+            }
+        }
+        let result = self.send_ixs(ixs).await;
+        if result.is_err() {
+            if let Some(risk_manager) = self.risk_manager().as_mut() {
+                risk_manager
+                    .orders
+                    .revert_pending_reduce(market_key, order.order_id);
+            }
+        }
+        Ok((path, Some(result?)))
+    }
+
+    /// Moves `order` to a new price and size with `get_move_order_ixs` and returns the
+    /// replacement's id, read from the transaction's events.
+    ///
+    /// If the order had already left the book, e.g. because it filled while the move was in
+    /// flight, nothing was cancelled and the replacement was placed anyway. It is then cancelled
+    /// again, and the move fails with a `PhoenixSdkError::OrderNotOnBook`.
+    pub async fn move_order(
+        &self,
+        market_key: &Pubkey,
+        order: &OpenOrder,
+        new_price_in_ticks: u64,
+        new_size_lots: u64,
+    ) -> Result<MovedOrder> {
+        let ixs = self.get_move_order_ixs(market_key, order, new_price_in_ticks, new_size_lots)?;
+        let signature = self.send_ixs(ixs).await?;
+        let events = self
+            .parse_events_from_transaction(&signature)
+            .await
+            .ok_or_else(|| {
+                anyhow!(
+                    "Failed to parse the events of move transaction {}",
+                    signature
+                )
+            })?;
+        let mut base_lots_cancelled = None;
+        let mut replacement = None;
+        for event in events.iter().filter(|event| event.market == *market_key) {
+            match event.details {
+                MarketEventDetails::Reduce(Reduce {
+                    order_sequence_number,
+                    maker,
+                    base_lots_removed,
+                    ..
+                }) if maker == self.trader
+                    && order_sequence_number == order.order_id.order_sequence_number =>
+                {
+                    base_lots_cancelled = Some(base_lots_removed);
+                }
+                MarketEventDetails::Place(Place {
+                    order_sequence_number,
+                    client_order_id,
+                    maker,
+                    price_in_ticks,
+                    ..
+                }) if maker == self.trader && client_order_id == order.client_order_id => {
+                    replacement = Some(FIFOOrderId::new_from_untyped(
+                        price_in_ticks,
+                        order_sequence_number,
+                    ));
+                }
+                _ => {}
+            }
+        }
+        let order_id = replacement
+            .ok_or_else(|| anyhow!("Move transaction {} placed no replacement", signature))?;
+        let Some(base_lots_cancelled) = base_lots_cancelled else {
+            let cancel_ix = self.get_cancel_ids_ix(market_key, [order_id])?;
+            let replacement_cancelled = self.send_ixs(vec![cancel_ix]).await.is_ok();
+            return Err(PhoenixSdkError::OrderNotOnBook {
+                order_id: order.order_id,
+                signature,
+                replacement: order_id,
+                replacement_cancelled,
+            }
+            .into());
+        };
+        Ok(MovedOrder {
+            signature,
+            order_id,
+            client_order_id: order.client_order_id,
+            base_lots_cancelled,
+        })
+    }
+
     pub async fn send_cancel_up_to(
         &self,
         market_key: &Pubkey,
@@ -987,11 +3911,7 @@ impl SDKClient {
         let cancel_ix = self
             .get_cancel_up_to_ix(market_key, tick_limit, side)
             .ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![cancel_ix], vec![])
-            .await
-            .ok()?;
+        let signature = self.send_ixs(vec![cancel_ix]).await.ok()?;
 
         let cancels = self.parse_cancels(&signature).await;
         Some((signature, cancels))
@@ -1002,36 +3922,231 @@ impl SDKClient {
         market_key: &Pubkey,
     ) -> Option<(Signature, Vec<PhoenixEvent>)> {
         let cancel_all_ix = self.get_cancel_all_ix(market_key).ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![cancel_all_ix], vec![])
-            .await
-            .ok()?;
+        let signature = self.send_ixs(vec![cancel_all_ix]).await.ok()?;
 
         let cancels = self.parse_cancels(&signature).await;
         Some((signature, cancels))
     }
 
-    /// Returns the instructions needed to set up a maker account for a market. Includes:
-    /// - Creation of associated token accounts for base and quote tokens, if needed.
-    /// - Claiming of the market's seat, if needed.
-    /// - Evicting a seat on the market if the market trader state is full.
-    pub async fn get_maker_setup_instructions_for_market(
+    /// Cancels all of the trader's orders on a market and withdraws everything deposited on it,
+    /// repeating until the seat is empty or `max_attempts` rounds have run. Each transaction is
+    /// confirmed before the next is sent, and orders that fill in the meantime are picked up by
+    /// the next round. Risk checks are overridden, since these orders only reduce exposure. Not
+    /// supported in paper mode, which does not simulate seats.
+    ///
+    /// Transient send failures, such as an expired blockhash, end the round early and are retried
+    /// in the next one; program errors are returned. Returns a report rather than an error when
+    /// the market is not flat after the last attempt; check `FlattenReport::is_flat`.
+    pub async fn flatten_market(
         &self,
         market_key: &Pubkey,
-    ) -> anyhow::Result<Vec<Instruction>> {
-        let metadata = self.get_market_metadata(market_key).await?;
-        let mut instructions = Vec::with_capacity(4);
-        instructions.extend_from_slice(
-            &create_ata_ix_if_needed(
-                &self.client,
-                &self.trader,
-                &self.trader,
-                &metadata.base_mint,
-            )
-            .await,
+        opts: FlattenOptions,
+    ) -> Result<FlattenReport> {
+        if self.trading_mode == TradingMode::Paper {
+            bail!("flatten_market is not supported in paper mode");
+        }
+        let mut report = FlattenReport::default();
+        while report.attempts < opts.max_attempts {
+            if report.attempts > 0 {
+                tokio::time::sleep(opts.retry_delay).await;
+            }
+            report.attempts += 1;
+
+            if !self.get_open_orders(market_key).await?.is_empty() {
+                let cancel_ix = self.get_cancel_all_ix(market_key)?;
+                match self.send_flatten_ix(cancel_ix).await? {
+                    Some(signature) => report.cancel_signatures.push(signature),
+                    None => continue,
+                }
+            }
+
+            let mut trader_state = self.get_trader_state(market_key).await?;
+            let base_lots_free = trader_state.base_lots_free.as_u64();
+            if opts
+                .sell_residual_base_lots_below
+                .is_some_and(|threshold| base_lots_free > 0 && base_lots_free <= threshold)
+            {
+                let sell_ix = self.get_ioc_full_ix(
+                    market_key,
+                    Side::Ask,
+                    None,
+                    base_lots_free,
+                    0,
+                    0,
+                    0,
+                    SelfTradeBehavior::CancelProvide,
+                    None,
+                    0,
+                    true,
+                )?;
+                match self.send_flatten_ix(sell_ix).await? {
+                    Some(signature) => report.sell_signatures.push(signature),
+                    None => continue,
+                }
+                trader_state = self.get_trader_state(market_key).await?;
+            }
+
+            if trader_state.base_lots_free.as_u64() > 0 || trader_state.quote_lots_free.as_u64() > 0
+            {
+                let withdraw_ix = self.get_withdraw_ix(market_key)?;
+                match self.send_flatten_ix(withdraw_ix).await? {
+                    Some(signature) => report.withdraw_signatures.push(signature),
+                    None => continue,
+                }
+            }
+
+            report.trader_state = self.get_trader_state(market_key).await?;
+            report.open_orders = self.get_open_orders(market_key).await?.len();
+            if report.is_flat() {
+                break;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Moves the free funds left on the trader's seats back to the wallet, e.g. the dust of
+    /// months of trading. On each market, free base worth at least `min_quote_value_atoms` at the
+    /// current bids is first sold into quote with an IOC order that fails if it gets more than
+    /// `SWEEP_SLIPPAGE_BPS` less than the book shows; smaller amounts are withdrawn as they are.
+    /// Each market is swept in one transaction, which creates the wallet's token accounts if
+    /// needed, with risk checks overridden since it only reduces exposure. Locked funds are left
+    /// alone; cancel the orders first, or use `flatten_market`. Not supported in paper mode.
+    ///
+    /// A market that cannot be swept is reported with its error and does not stop the others.
+    pub async fn sweep_residuals(
+        &self,
+        markets: &[Pubkey],
+        min_quote_value_atoms: u64,
+    ) -> Result<SweepReport> {
+        if self.trading_mode == TradingMode::Paper {
+            bail!("sweep_residuals is not supported in paper mode");
+        }
+        let mut report = SweepReport::default();
+        for market in markets {
+            let swept = match self.sweep_market(market, min_quote_value_atoms).await {
+                Ok(swept) => swept,
+                Err(e) => SweptMarket {
+                    market: *market,
+                    error: Some(e.to_string()),
+                    ..Default::default()
+                },
+            };
+            report.markets.push(swept);
+        }
+        Ok(report)
+    }
+
+    async fn sweep_market(
+        &self,
+        market: &Pubkey,
+        min_quote_value_atoms: u64,
+    ) -> Result<SweptMarket> {
+        let meta = self.get_market_metadata(market).await?;
+        let state = self.fetch_market_state(market).await?;
+        let trader_state = state.traders.get(&self.trader).copied().unwrap_or_default();
+        let base_lots_free = trader_state.base_lots_free.as_u64();
+        let quote_lots_free = trader_state.quote_lots_free.as_u64();
+        let mut swept = SweptMarket {
+            market: *market,
+            ..Default::default()
+        };
+        if base_lots_free == 0 && quote_lots_free == 0 {
+            return Ok(swept);
+        }
+
+        let mut ixs = vec![];
+        for mint in [&meta.base_mint, &meta.quote_mint] {
+            self.throttle("sdk_client", RpcCategory::GetAccount).await;
+            ixs.extend(
+                create_ata_ix_if_needed(&self.client, &self.trader, &self.trader, mint).await,
+            );
+        }
+        let sale = plan_residual_sale(
+            &meta,
+            state.orderbook.iter_levels(Side::Bid),
+            base_lots_free,
+            min_quote_value_atoms,
         );
+        let client_order_id = rand::random::<u128>().max(1);
+        if let Some(sale) = sale {
+            ixs.push(self.get_ioc_full_ix(
+                market,
+                Side::Ask,
+                None,
+                sale.base_lots,
+                0,
+                0,
+                sale.min_quote_lots_out,
+                SelfTradeBehavior::CancelProvide,
+                None,
+                client_order_id,
+                true,
+            )?);
+        }
+        ixs.push(self.get_withdraw_ix(market)?);
+        let signature = self.send_ixs_with_risk_override(ixs, true).await?;
+        swept.signature = Some(signature);
 
+        // The sale's proceeds land on the seat and are withdrawn with the rest
+        let mut base_atoms = meta.base_lots_to_base_atoms(base_lots_free);
+        let mut quote_atoms = meta.quote_lots_to_quote_atoms(quote_lots_free);
+        if sale.is_some() {
+            let events = self
+                .parse_events_from_transaction(&signature)
+                .await
+                .ok_or_else(|| anyhow!("Failed to parse the events of sweep {}", signature))?;
+            let execution = ExecutionReport::from_events(&events, client_order_id, None, &meta)
+                .ok_or_else(|| anyhow!("No fill summary for the sale in sweep {}", signature))?;
+            swept.actions.push(SweepAction::Sold {
+                base_atoms: execution.filled_base_atoms,
+                quote_atoms: execution.net_quote_atoms,
+            });
+            base_atoms -= execution.filled_base_atoms;
+            quote_atoms += execution.net_quote_atoms;
+        }
+        swept.unsold_base_atoms = base_atoms;
+        swept.actions.push(SweepAction::Withdrawn {
+            base_atoms,
+            quote_atoms,
+        });
+        Ok(swept)
+    }
+
+    /// Sends one of `flatten_market`'s instructions with risk checks overridden. Returns `None`
+    /// if it failed in a way worth retrying.
+    async fn send_flatten_ix(&self, ix: Instruction) -> Result<Option<Signature>> {
+        match self.send_ixs_with_risk_override(vec![ix], true).await {
+            Ok(signature) => Ok(Some(signature)),
+            Err(e) if error::is_retryable(&e) => {
+                println!("Retrying after failed flatten transaction: {}", e);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the instructions needed to set up a maker account for a market. Includes:
+    /// - Creation of associated token accounts for base and quote tokens, if needed.
+    /// - Claiming of the market's seat, if needed.
+    /// - Evicting a seat on the market if the market trader state is full.
+    pub async fn get_maker_setup_instructions_for_market(
+        &self,
+        market_key: &Pubkey,
+    ) -> anyhow::Result<Vec<Instruction>> {
+        let metadata = self.get_market_metadata(market_key).await?;
+        let mut instructions = Vec::with_capacity(4);
+        self.throttle("sdk_client", RpcCategory::GetAccount).await;
+        instructions.extend_from_slice(
+            &create_ata_ix_if_needed(
+                &self.client,
+                &self.trader,
+                &self.trader,
+                &metadata.base_mint,
+            )
+            .await,
+        );
+
+        self.throttle("sdk_client", RpcCategory::GetAccount).await;
         instructions.extend_from_slice(
             &create_ata_ix_if_needed(
                 &self.client,
@@ -1042,6 +4157,8 @@ impl SDKClient {
             .await,
         );
 
+        // Reads the seat, and the market and seat manager if the seat must be claimed
+        self.throttle("sdk_client", RpcCategory::GetAccount).await;
         instructions.extend_from_slice(
             &create_claim_seat_ix_if_needed(&self.client, market_key, &self.trader).await?,
         );
@@ -1111,8 +4228,16 @@ impl SDKClient {
         } = limit_order_template;
 
         let price_in_ticks = self.float_price_to_ticks_rounded_down(market_key, *price_as_float)?;
-        let size_in_num_base_lots =
-            self.raw_base_units_to_base_lots_rounded_down(market_key, *size_in_base_units)?;
+        let size_in_num_base_lots = self
+            .normalize_order_size(
+                market_key,
+                *side,
+                price_in_ticks,
+                *size_in_base_units,
+                0,
+                SizeRounding::Down,
+            )?
+            .base_lots;
 
         let limit_order_packet = OrderPacket::Limit {
             side: *side,
@@ -1157,8 +4282,16 @@ impl SDKClient {
         } = post_only_order_template;
 
         let price_in_ticks = self.float_price_to_ticks_rounded_down(market_key, *price_as_float)?;
-        let size_in_num_base_lots =
-            self.raw_base_units_to_base_lots_rounded_down(market_key, *size_in_base_units)?;
+        let size_in_num_base_lots = self
+            .normalize_order_size(
+                market_key,
+                *side,
+                price_in_ticks,
+                *size_in_base_units,
+                0,
+                SizeRounding::Down,
+            )?
+            .base_lots;
 
         let post_only_packet = OrderPacket::PostOnly {
             side: *side,
@@ -1214,14 +4347,25 @@ impl SDKClient {
             None => None,
         };
 
-        let size_in_num_base_lots =
-            self.raw_base_units_to_base_lots_rounded_down(market_key, *size_in_base_units)?;
+        let size_in_num_base_lots = self
+            .raw_base_units_to_base_lots_rounded_down_as_float(market_key, *size_in_base_units)?;
         let size_in_num_quote_lots =
-            self.quote_units_to_quote_lots(market_key, *size_in_quote_units)?;
-        let min_base_lots_to_fill =
-            self.raw_base_units_to_base_lots_rounded_down(market_key, *min_base_units_to_fill)?;
+            self.quote_units_to_quote_lots_as_float(market_key, *size_in_quote_units)?;
+        let min_base_lots_to_fill = self.raw_base_units_to_base_lots_rounded_down_as_float(
+            market_key,
+            *min_base_units_to_fill,
+        )?;
         let min_quote_lots_to_fill =
-            self.quote_units_to_quote_lots(market_key, *min_quote_units_to_fill)?;
+            self.quote_units_to_quote_lots_as_float(market_key, *min_quote_units_to_fill)?;
+        // Only one of the sizes is normally set, so the order is empty when neither has a lot
+        if size_in_num_base_lots == 0 && size_in_num_quote_lots == 0 {
+            let min_size = if *size_in_base_units > 0.0 {
+                market_metadata.base_atoms_per_base_lot
+            } else {
+                market_metadata.quote_atoms_per_quote_lot
+            };
+            return Err(SizeTooSmall { min_size }.into());
+        }
 
         let ioc_order_packet = OrderPacket::ImmediateOrCancel {
             side: *side,
@@ -1249,3 +4393,1332 @@ impl SDKClient {
         Ok(ioc_ix)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use phoenix_sdk_core::test_utils::event;
+
+    #[test]
+    fn test_ui_ladder_from_snapshot() {
+        let meta = MarketMetadata {
+            base_atoms_per_raw_base_unit: 1_000_000_000,
+            quote_atoms_per_quote_unit: 1_000_000,
+            quote_atoms_per_quote_lot: 1,
+            base_atoms_per_base_lot: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 1000,
+            num_base_lots_per_base_unit: 1000,
+            raw_base_units_per_base_unit: 1,
+            ..Default::default()
+        };
+        let level = |price_in_ticks, size_in_base_lots| LadderOrder {
+            price_in_ticks,
+            size_in_base_lots,
+        };
+        let snapshot = LadderSnapshot {
+            slot: 42,
+            sequence_number: 7,
+            ladder: Ladder {
+                bids: vec![level(24_950, 1500), level(24_900, 20)],
+                asks: vec![level(25_010, 1000)],
+            },
+        };
+        let ui_ladder = UiLadder::from_snapshot(&snapshot, &meta);
+        assert_eq!(ui_ladder.slot, 42);
+        assert_eq!(ui_ladder.bids, vec![(24.95, 1.5), (24.9, 0.02)]);
+        assert_eq!(ui_ladder.asks, vec![(25.01, 1.0)]);
+    }
+
+    #[test]
+    fn test_token_account_balance() {
+        let mint = Pubkey::new_unique();
+        let token_programs = [spl_token::id(), TOKEN_2022_PROGRAM_ID];
+        let token_account = |mint, amount, owner, extension_len| {
+            let mut data = vec![0; spl_token::state::Account::LEN];
+            spl_token::state::Account {
+                mint,
+                owner: Pubkey::new_unique(),
+                amount,
+                state: spl_token::state::AccountState::Initialized,
+                ..Default::default()
+            }
+            .pack_into_slice(&mut data);
+            data.extend(vec![1; extension_len]);
+            Some(Account {
+                lamports: 2_039_280,
+                data,
+                owner,
+                executable: false,
+                rent_epoch: 0,
+            })
+        };
+
+        assert_eq!(
+            token_account_balance(&mint, &token_programs, &[None, None]),
+            0
+        );
+        let spl = token_account(mint, 42, spl_token::id(), 0);
+        assert_eq!(
+            token_account_balance(&mint, &token_programs, &[spl, None]),
+            42
+        );
+        let token_2022 = token_account(mint, 7, TOKEN_2022_PROGRAM_ID, 83);
+        assert_eq!(
+            token_account_balance(&mint, &token_programs, &[None, token_2022]),
+            7
+        );
+        // Accounts of another mint or owned by the wrong program are ignored
+        let wrong_program = token_account(mint, 42, TOKEN_2022_PROGRAM_ID, 0);
+        let other_mint = token_account(Pubkey::new_unique(), 42, TOKEN_2022_PROGRAM_ID, 0);
+        assert_eq!(
+            token_account_balance(&mint, &token_programs, &[wrong_program, other_mint]),
+            0
+        );
+    }
+
+    /// Runs `flatten_market` against a local validator. Needs a Phoenix market and a trader
+    /// keypair funded with both of its tokens, given as `PHOENIX_LOCALNET_MARKET` and
+    /// `PHOENIX_LOCALNET_KEYPAIR`.
+    #[tokio::test]
+    #[ignore = "needs a local validator with a Phoenix market"]
+    async fn test_flatten_market_localnet() {
+        let market = Pubkey::from_str(&std::env::var("PHOENIX_LOCALNET_MARKET").unwrap()).unwrap();
+        let payer = solana_sdk::signature::read_keypair_file(
+            std::env::var("PHOENIX_LOCALNET_KEYPAIR").unwrap(),
+        )
+        .unwrap();
+        let client =
+            SDKClient::new_with_market_keys(vec![&market], &payer, "http://localhost:8899")
+                .await
+                .unwrap();
+        let meta = client.get_market_metadata_from_cache(&market).unwrap();
+
+        // Rest a bid and an ask far from each other, then leave funds on the seat
+        let tick = meta.tick_size_in_quote_atoms_per_base_unit;
+        client
+            .send_post_only(&market, 1000 * tick, Side::Bid, 10)
+            .await
+            .unwrap();
+        client
+            .send_post_only(&market, 3000 * tick, Side::Ask, 10)
+            .await
+            .unwrap();
+        let deposit_ix = client
+            .get_deposit_ix(&market, meta.num_base_lots_per_base_unit, 1000)
+            .unwrap();
+        client.send_ixs(vec![deposit_ix]).await.unwrap();
+        assert_eq!(client.get_open_orders(&market).await.unwrap().len(), 2);
+
+        let report = client
+            .flatten_market(&market, FlattenOptions::default())
+            .await
+            .unwrap();
+        assert!(report.is_flat(), "{:?}", report);
+        assert_eq!(report.cancel_signatures.len(), 1);
+        assert_eq!(report.withdraw_signatures.len(), 1);
+        assert_eq!(
+            client.get_trader_state(&market).await.unwrap(),
+            TraderState::default()
+        );
+    }
+
+    #[test]
+    fn test_plan_residual_sale() {
+        let meta = crate::paper_trading::test_utils::test_market_metadata();
+        let bids = || {
+            [(100, 5), (99, 10)]
+                .map(|(price_in_ticks, size_in_base_lots)| LadderOrder {
+                    price_in_ticks,
+                    size_in_base_lots,
+                })
+                .into_iter()
+        };
+        // 8 lots take 5 at 100 ticks and 3 at 99, one lot being worth a tick in quote atoms
+        assert_eq!(
+            plan_residual_sale(&meta, bids(), 8, 700),
+            Some(ResidualSale {
+                base_lots: 8,
+                min_quote_lots_out: 789,
+            })
+        );
+        // Worth less than the threshold, more than the bids hold, or nothing at all
+        assert_eq!(plan_residual_sale(&meta, bids(), 8, 800), None);
+        assert_eq!(plan_residual_sale(&meta, bids(), 20, 0), None);
+        assert_eq!(plan_residual_sale(&meta, bids(), 0, 0), None);
+    }
+
+    #[tokio::test]
+    async fn test_amend_order() {
+        use crate::risk_manager::{RiskConfig, RiskManager};
+
+        let (client, market) = crate::paper_trading::test_utils::paper_client(&[]).await;
+        client.set_risk_manager(Some(RiskManager::new(client.trader, RiskConfig::default())));
+        let ix = client
+            .get_post_only_ix_from_tick_price(&market, 100, Side::Bid, 10, 7, false)
+            .unwrap();
+        client.send_ixs(vec![ix]).await.unwrap();
+        let open_order = || {
+            let risk_manager = client.risk_manager();
+            let orders = &risk_manager.as_ref().unwrap().orders;
+            assert_eq!(orders.open_order_count(), 1);
+            let order = *orders.open_orders(&market).next().unwrap();
+            order
+        };
+        let order = open_order();
+
+        // Shrinking keeps the order, and with it the queue position
+        let (path, signature) = client.amend_order(&market, &order, 6, 100).await.unwrap();
+        assert_eq!(path, AmendPath::Reduce { lots_to_remove: 4 });
+        assert!(signature.is_some());
+        assert_eq!(
+            client.get_open_orders(&market).await.unwrap(),
+            vec![(order.order_id, 6)]
+        );
+        let shrunk = open_order();
+        assert_eq!((shrunk.order_id, shrunk.num_base_lots), (order.order_id, 6));
+        assert!(!client
+            .risk_manager()
+            .as_ref()
+            .unwrap()
+            .orders
+            .is_reduce_pending(&market, &order.order_id));
+        assert_eq!(
+            client.amend_order(&market, &shrunk, 6, 100).await.unwrap(),
+            (AmendPath::Unchanged, None)
+        );
+
+        // Growing replaces the order
+        let (path, _) = client.amend_order(&market, &shrunk, 12, 100).await.unwrap();
+        assert_eq!(path, AmendPath::CancelReplace);
+        let grown = open_order();
+        assert_ne!(grown.order_id, order.order_id);
+        assert_eq!((grown.num_base_lots, grown.client_order_id), (12, 7));
+
+        // So does a new price
+        let (path, _) = client.amend_order(&market, &grown, 12, 98).await.unwrap();
+        assert_eq!(path, AmendPath::CancelReplace);
+        let moved = open_order();
+        assert_eq!((moved.price_in_ticks(), moved.num_base_lots), (98, 12));
+        assert_eq!(
+            client.get_open_orders(&market).await.unwrap(),
+            vec![(moved.order_id, 12)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_move_order() {
+        let (client, market) = crate::paper_trading::test_utils::paper_client(&[]).await;
+        let ix = client
+            .get_post_only_ix_from_tick_price(&market, 100, Side::Bid, 10, 7, false)
+            .unwrap();
+        client.send_ixs(vec![ix]).await.unwrap();
+        let (order_id, num_base_lots) = client.get_open_orders(&market).await.unwrap()[0];
+        let order = OpenOrder {
+            order_id,
+            side: Side::Bid,
+            client_order_id: 7,
+            num_base_lots,
+        };
+
+        let moved = client.move_order(&market, &order, 98, 12).await.unwrap();
+        assert_eq!(moved.order_id.price_in_ticks.as_u64(), 98);
+        assert_eq!((moved.client_order_id, moved.base_lots_cancelled), (7, 10));
+        assert_eq!(
+            client.get_open_orders(&market).await.unwrap(),
+            vec![(moved.order_id, 12)]
+        );
+
+        // The order leaves the book before the move lands, as if it filled
+        let moved_order = OpenOrder {
+            order_id: moved.order_id,
+            num_base_lots: 12,
+            ..order
+        };
+        client
+            .send_ixs(vec![client
+                .get_cancel_ids_ix(&market, [moved.order_id])
+                .unwrap()])
+            .await
+            .unwrap();
+        let error = client
+            .move_order(&market, &moved_order, 99, 12)
+            .await
+            .unwrap_err();
+        match error.downcast_ref::<PhoenixSdkError>() {
+            Some(PhoenixSdkError::OrderNotOnBook {
+                order_id,
+                replacement,
+                replacement_cancelled,
+                ..
+            }) => {
+                assert_eq!(*order_id, moved.order_id);
+                assert_eq!(replacement.price_in_ticks.as_u64(), 99);
+                assert!(replacement_cancelled);
+            }
+            _ => panic!("Expected the order to be off the book, got {}", error),
+        }
+        assert!(client.get_open_orders(&market).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_intent_orders() {
+        use crate::risk_manager::{RiskConfig, RiskManager};
+        use phoenix_sdk_core::order_manager::DuplicateOrderPolicy;
+
+        let (client, market) = crate::paper_trading::test_utils::paper_client(&[]).await;
+        client.set_risk_manager(Some(RiskManager::new(client.trader, RiskConfig::default())));
+        let intent = IntentKey::new("quoter", 1);
+        // A retry of an order that also landed: both transactions place an order
+        let send = || client.send_post_only_for_intent(&market, 100_000, Side::Bid, 10, &intent);
+        let (first, client_order_id) = send().await.unwrap();
+        let (retry, retry_client_order_id) = send().await.unwrap();
+        assert_ne!(first, retry);
+        assert_eq!(client_order_id, retry_client_order_id);
+        let open_orders = || {
+            client
+                .risk_manager()
+                .as_ref()
+                .unwrap()
+                .orders
+                .open_orders(&market)
+                .count()
+        };
+
+        // Warn only flags the duplicate
+        assert_eq!(open_orders(), 2);
+        {
+            let risk_manager = client.risk_manager();
+            let duplicates = risk_manager.as_ref().unwrap().orders.duplicates();
+            assert_eq!(duplicates.len(), 1);
+            assert_eq!(duplicates[0].client_order_id, client_order_id);
+            assert_eq!(duplicates[0].signature, retry);
+        }
+        assert!(client.cancel_duplicate_orders().await.unwrap().is_empty());
+
+        // Auto-cancel takes the duplicate off the book
+        client
+            .risk_manager()
+            .as_mut()
+            .unwrap()
+            .orders
+            .set_duplicate_policy(DuplicateOrderPolicy::Cancel);
+        let intent = IntentKey::new("quoter", 2);
+        for _ in 0..2 {
+            client
+                .send_limit_order_for_intent(&market, 99_000, Side::Bid, 5, &intent)
+                .await
+                .unwrap();
+        }
+        assert_eq!(open_orders(), 3);
+        assert_eq!(client.paper_exchange().open_orders(&market).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_report() {
+        let (client, market) = crate::paper_trading::test_utils::paper_client(&[]).await;
+        let meta = client.markets.get(&market).unwrap();
+        for (price_in_ticks, side, size) in [
+            (100, Side::Bid, 10),
+            (99, Side::Bid, 5),
+            (105, Side::Ask, 7),
+        ] {
+            let ix = client
+                .get_post_only_ix_from_tick_price(&market, price_in_ticks, side, size, 0, false)
+                .unwrap();
+            client.send_ixs(vec![ix]).await.unwrap();
+        }
+
+        let ix = client
+            .get_cancel_up_to_ix(&market, None, Side::Bid)
+            .unwrap();
+        let report = client.send_cancel_and_report(ix).await.unwrap();
+        let cancelled = report
+            .orders
+            .iter()
+            .map(|order| {
+                (
+                    order.order_id.price_in_ticks.as_u64(),
+                    order.base_lots_removed,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(cancelled, vec![(100, 10), (99, 5)]);
+        assert!(report
+            .orders
+            .iter()
+            .all(|order| order.side == Side::Bid && order.is_full_cancel));
+        assert_eq!(report.base_lots_removed(), 15);
+        assert_eq!(
+            report.freed_quote_atoms,
+            meta.base_lots_and_price_to_quote_atoms(10, 100)
+                + meta.base_lots_and_price_to_quote_atoms(5, 99)
+        );
+        assert_eq!(report.freed_base_atoms, 0);
+
+        let ix = client.get_cancel_all_ix(&market).unwrap();
+        let report = client.send_cancel_and_report(ix).await.unwrap();
+        assert_eq!(report.orders.len(), 1);
+        assert_eq!(report.freed_base_atoms, meta.base_lots_to_base_atoms(7));
+    }
+
+    #[test]
+    fn test_seat_status() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let seat_account = |approval_status: u64, owner: Pubkey| {
+            let mut seat = Seat::new_init(market, trader).unwrap();
+            seat.approval_status = approval_status;
+            Account {
+                data: bytemuck::bytes_of(&seat).to_vec(),
+                owner,
+                ..Default::default()
+            }
+        };
+        assert_eq!(
+            seat_status(Some(&seat_account(1, phoenix::id()))),
+            Some(SeatApprovalStatus::Approved)
+        );
+        assert_eq!(
+            seat_status(Some(&seat_account(0, phoenix::id()))),
+            Some(SeatApprovalStatus::NotApproved)
+        );
+        assert_eq!(seat_status(Some(&seat_account(7, phoenix::id()))), None);
+        assert_eq!(
+            seat_status(Some(&seat_account(1, Pubkey::new_unique()))),
+            None
+        );
+        assert_eq!(seat_status(None), None);
+    }
+
+    #[tokio::test]
+    async fn test_events_use_their_market_metadata() {
+        use phoenix_sdk_core::sdk_client_core::RawPhoenixHeader;
+
+        let (client, market_a) = crate::paper_trading::test_utils::paper_client(&[]).await;
+        let market_b = Pubkey::new_unique();
+        client.markets.insert(
+            market_b,
+            MarketMetadata {
+                base_atoms_per_base_lot: 50,
+                quote_atoms_per_quote_lot: 7,
+                ..crate::paper_trading::test_utils::test_market_metadata()
+            },
+        );
+        let raw_event = |market: Pubkey, side: Side| RawPhoenixEvent {
+            header: RawPhoenixHeader {
+                market,
+                ..Default::default()
+            },
+            batch: vec![
+                PhoenixMarketEvent::Fill(FillEvent {
+                    index: 0,
+                    maker_id: Pubkey::new_unique(),
+                    order_sequence_number: match side {
+                        Side::Bid => !1,
+                        Side::Ask => 1,
+                    },
+                    price_in_ticks: 100,
+                    base_lots_filled: 10,
+                    base_lots_remaining: 0,
+                }),
+                PhoenixMarketEvent::FillSummary(FillSummaryEvent {
+                    index: 1,
+                    client_order_id: 0,
+                    total_base_lots_filled: 10,
+                    total_quote_lots_filled: 1000,
+                    total_fee_in_quote_lots: 2,
+                }),
+            ],
+        };
+        let summaries = |events: &[PhoenixEvent]| {
+            events
+                .iter()
+                .filter_map(|event| match event.details {
+                    MarketEventDetails::FillSummary(summary) => Some((event.market, summary)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let raw_events = vec![
+            raw_event(market_a, Side::Ask),
+            raw_event(market_b, Side::Bid),
+        ];
+
+        let events = client
+            .parse_raw_phoenix_events(raw_events.clone())
+            .await
+            .unwrap();
+        let summaries_by_market = summaries(&events);
+        assert_eq!(summaries_by_market.len(), 2);
+        let (market, summary) = summaries_by_market[0];
+        assert_eq!(market, market_a);
+        assert_eq!(summary.total_base_filled, 10 * 1000);
+        assert_eq!(summary.total_quote_filled_including_fees, 1000);
+        assert_eq!(summary.total_quote_fees, 2);
+        let (market, summary) = summaries_by_market[1];
+        assert_eq!(market, market_b);
+        assert_eq!(summary.total_base_filled, 10 * 50);
+        assert_eq!(summary.total_quote_filled_including_fees, 1000 * 7);
+        assert_eq!(summary.total_quote_fees, 2 * 7);
+        // The direction of one market's fills does not carry over to the next
+        assert_ne!(
+            summaries_by_market[0].1.trade_direction,
+            summary.trade_direction
+        );
+
+        let options = EventParseOptions {
+            expected_market: Some(market_b),
+            ..Default::default()
+        };
+        let events = client
+            .parse_raw_phoenix_events_with_options(raw_events.clone(), &options)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|event| event.market == market_b));
+
+        // Unknown markets are skipped or rejected without fetching their metadata
+        let unknown = raw_event(Pubkey::new_unique(), Side::Bid);
+        let mut raw_events = raw_events;
+        raw_events.push(unknown);
+        let options = EventParseOptions {
+            unknown_markets: UnknownMarketPolicy::Skip,
+            ..Default::default()
+        };
+        let events = client
+            .parse_raw_phoenix_events_with_options(raw_events.clone(), &options)
+            .await
+            .unwrap();
+        assert_eq!(summaries(&events).len(), 2);
+        let options = EventParseOptions {
+            unknown_markets: UnknownMarketPolicy::Error,
+            ..Default::default()
+        };
+        assert!(client
+            .parse_raw_phoenix_events_with_options(raw_events, &options)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_template_size_too_small() {
+        let (client, market) = crate::paper_trading::test_utils::paper_client(&[]).await;
+        let meta = client.markets.get(&market).unwrap();
+        let (min_base_atoms, min_quote_atoms) = meta.min_order_sizes();
+        // One base lot is 0.001 base units and one quote lot is 0.000001 quote units
+        let base_lot = min_base_atoms as f64 / meta.base_atoms_per_raw_base_unit as f64;
+        let quote_lot = min_quote_atoms as f64 / meta.quote_atoms_per_quote_unit as f64;
+        let size_error =
+            |result: Result<Instruction>| result.unwrap_err().downcast::<SizeTooSmall>().unwrap();
+
+        let limit = |size_in_base_units| {
+            client.get_limit_order_ix_from_template(
+                &market,
+                &meta,
+                &LimitOrderTemplate {
+                    side: Side::Bid,
+                    price_as_float: 1.0,
+                    size_in_base_units,
+                    self_trade_behavior: SelfTradeBehavior::CancelProvide,
+                    match_limit: None,
+                    client_order_id: 0,
+                    use_only_deposited_funds: false,
+                    last_valid_slot: None,
+                    last_valid_unix_timestamp_in_seconds: None,
+                    fail_silently_on_insufficient_funds: false,
+                },
+            )
+        };
+        assert_eq!(
+            size_error(limit(base_lot * 0.9)),
+            SizeTooSmall {
+                min_size: min_base_atoms
+            }
+        );
+        assert!(limit(base_lot).is_ok());
+
+        let post_only = |size_in_base_units| {
+            client.get_post_only_ix_from_template(
+                &market,
+                &meta,
+                &PostOnlyOrderTemplate {
+                    side: Side::Ask,
+                    price_as_float: 1.0,
+                    size_in_base_units,
+                    client_order_id: 0,
+                    reject_post_only: true,
+                    use_only_deposited_funds: false,
+                    last_valid_slot: None,
+                    last_valid_unix_timestamp_in_seconds: None,
+                    fail_silently_on_insufficient_funds: false,
+                },
+            )
+        };
+        assert_eq!(
+            size_error(post_only(base_lot * 0.9)),
+            SizeTooSmall {
+                min_size: min_base_atoms
+            }
+        );
+        assert!(post_only(base_lot).is_ok());
+
+        let ioc = |size_in_base_units, size_in_quote_units| {
+            client.get_ioc_ix_from_template(
+                &market,
+                &meta,
+                &ImmediateOrCancelOrderTemplate {
+                    side: Side::Bid,
+                    price_as_float: None,
+                    size_in_base_units,
+                    size_in_quote_units,
+                    min_base_units_to_fill: 0.0,
+                    min_quote_units_to_fill: 0.0,
+                    self_trade_behavior: SelfTradeBehavior::CancelProvide,
+                    match_limit: None,
+                    client_order_id: 0,
+                    use_only_deposited_funds: false,
+                    last_valid_slot: None,
+                    last_valid_unix_timestamp_in_seconds: None,
+                },
+            )
+        };
+        assert_eq!(
+            size_error(ioc(base_lot * 0.9, 0.0)),
+            SizeTooSmall {
+                min_size: min_base_atoms
+            }
+        );
+        assert!(ioc(base_lot, 0.0).is_ok());
+        assert_eq!(
+            size_error(ioc(0.0, quote_lot * 0.9)),
+            SizeTooSmall {
+                min_size: min_quote_atoms
+            }
+        );
+        assert!(ioc(0.0, quote_lot).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_warm_up() {
+        use phoenix::program::dispatch_market::get_market_size;
+        use phoenix::program::{MarketSizeParams, TokenParams};
+        use phoenix::quantities::{
+            BaseAtomsPerBaseLot, QuoteAtomsPerBaseUnitPerTick, QuoteAtomsPerQuoteLot,
+        };
+        use solana_account_decoder::{UiAccount, UiAccountEncoding};
+        use solana_client::rpc_request::RpcRequest;
+
+        let market = Pubkey::new_unique();
+        let size_params = MarketSizeParams {
+            bids_size: 512,
+            asks_size: 512,
+            num_seats: 128,
+        };
+        let token_params = |decimals| TokenParams {
+            decimals,
+            vault_bump: 0,
+            mint_key: Pubkey::new_unique(),
+            vault_key: Pubkey::new_unique(),
+        };
+        let header = MarketHeader::new(
+            size_params,
+            token_params(9),
+            BaseAtomsPerBaseLot::new(1_000_000),
+            token_params(6),
+            QuoteAtomsPerQuoteLot::new(1),
+            QuoteAtomsPerBaseUnitPerTick::new(1000),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            1,
+        );
+        let meta = MarketMetadata::from_header(&header).unwrap();
+        let mut market_data = bytemuck::bytes_of(&header).to_vec();
+        market_data.resize(
+            size_of::<MarketHeader>() + get_market_size(&size_params).unwrap(),
+            0,
+        );
+
+        let payer = Keypair::new();
+        let trader = payer.pubkey();
+        let mut seat = Seat::new_init(market, trader).unwrap();
+        seat.approval_status = SeatApprovalStatus::Approved as u64;
+        let token_account = |mint, amount| {
+            let mut data = vec![0; spl_token::state::Account::LEN];
+            spl_token::state::Account {
+                mint,
+                owner: trader,
+                amount,
+                state: spl_token::state::AccountState::Initialized,
+                ..Default::default()
+            }
+            .pack_into_slice(&mut data);
+            data
+        };
+        let account = |owner, data: Vec<u8>| Account {
+            lamports: 1_000_000,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        };
+        // The market, the seat, the wallet, then the base and quote token accounts under both
+        // token programs
+        let accounts = [
+            Some(account(phoenix::id(), market_data)),
+            Some(account(phoenix::id(), bytemuck::bytes_of(&seat).to_vec())),
+            Some(account(solana_sdk::system_program::id(), vec![])),
+            Some(account(
+                spl_token::id(),
+                token_account(meta.base_mint, 2_000_000_000),
+            )),
+            None,
+            None,
+            Some(account(
+                TOKEN_2022_PROGRAM_ID,
+                token_account(meta.quote_mint, 5_000_000),
+            )),
+        ];
+        let value = accounts
+            .iter()
+            .map(|account| {
+                account.as_ref().map(|account| {
+                    UiAccount::encode(
+                        &Pubkey::default(),
+                        account,
+                        UiAccountEncoding::Base64,
+                        None,
+                        None,
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        let mocks = HashMap::from([(
+            RpcRequest::GetMultipleAccounts,
+            serde_json::json!({ "context": { "slot": 7 }, "value": value }),
+        )]);
+        let client = EllipsisClient::from_rpc(
+            solana_client::nonblocking::rpc_client::RpcClient::new_mock_with_mocks(
+                "succeeds".to_string(),
+                mocks,
+            ),
+            &payer,
+        )
+        .unwrap();
+        let sdk = SDKClient::new_from_ellipsis_client(client).await.unwrap();
+        // With the metadata known, everything is read in a single request
+        sdk.markets.insert(market, meta);
+
+        let report = sdk.warm_up(&[market, market]).await.unwrap();
+        assert!(report.is_ready(), "{:?}", report);
+        assert_eq!(report.items.len(), 6);
+        assert_eq!(report.slot, Some(0));
+        assert!(report.blockhash.is_some());
+
+        // The getters are served from the warm-up once, without further requests
+        let snapshot = sdk.get_market_snapshot(market).await.unwrap();
+        assert_eq!(snapshot.slot, 7);
+        assert_eq!(
+            sdk.get_seat_status(&market).await.unwrap(),
+            Some(SeatApprovalStatus::Approved)
+        );
+        let balances = sdk.get_wallet_balances(&market, &trader).await.unwrap();
+        assert_eq!(
+            balances,
+            WalletBalances::new(&meta, 2_000_000_000, 5_000_000, 1_000_000)
+        );
+        assert_eq!(balances.base_units, 2.0);
+        assert!(sdk.warmed().take_snapshot(&market).is_none());
+        // The next fetch goes to RPC, whose default mock has no accounts
+        assert!(sdk.get_market_snapshot(market).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_min_slot_getters() {
+        // The default mock answers `getMultipleAccounts` at slot 1 with no accounts
+        let client = EllipsisClient::from_rpc(
+            solana_client::nonblocking::rpc_client::RpcClient::new_mock("succeeds".to_string()),
+            &Keypair::new(),
+        )
+        .unwrap();
+        let mut sdk = SDKClient::new_from_ellipsis_client(client).await.unwrap();
+        sdk.set_min_slot_timeout(Duration::from_millis(300));
+        let market = Pubkey::new_unique();
+        sdk.markets.insert(market, MarketMetadata::default());
+
+        // Read at a recent enough slot, so the missing account is the error
+        let error = sdk
+            .get_market_state_with_min_slot(market, 1)
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("not found"), "{}", error);
+
+        let started = Instant::now();
+        let error = sdk
+            .get_market_state_with_min_slot(market, 5)
+            .await
+            .err()
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert_eq!(
+            error.downcast_ref::<PhoenixSdkError>(),
+            Some(&PhoenixSdkError::StaleData {
+                got: 1,
+                required: 5
+            })
+        );
+        let error = sdk
+            .get_wallet_balances_with_min_slot(&market, &sdk.trader, 5)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PhoenixSdkError>(),
+            Some(PhoenixSdkError::StaleData { .. })
+        ));
+
+        let not_reached = anyhow::Error::from(ClientError::from(ClientErrorKind::RpcError(
+            RpcError::RpcResponseError {
+                code: JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED,
+                message: "Minimum context slot has not been reached".to_string(),
+                data: solana_client::rpc_request::RpcResponseErrorData::Empty,
+            },
+        )));
+        assert!(is_min_context_slot_not_reached(&not_reached));
+        // As flattened by an `RpcPool`
+        let pooled = anyhow!("All RPC endpoints failed: http://a: {}", not_reached);
+        assert!(is_min_context_slot_not_reached(&pooled));
+        assert!(!is_min_context_slot_not_reached(&anyhow!(
+            "request timed out"
+        )));
+    }
+
+    #[test]
+    fn test_market_summary() {
+        let meta = MarketMetadata {
+            base_atoms_per_raw_base_unit: 1_000_000_000,
+            quote_atoms_per_quote_unit: 1_000_000,
+            quote_atoms_per_quote_lot: 1,
+            base_atoms_per_base_lot: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 1000,
+            num_base_lots_per_base_unit: 1000,
+            raw_base_units_per_base_unit: 1,
+            ..Default::default()
+        };
+        let market = Pubkey::new_unique();
+        let sequenced = |sequence_number, event_index, details| PhoenixEvent {
+            sequence_number,
+            event_index,
+            ..event(market, details)
+        };
+        let place = |order_sequence_number, price_in_ticks, base_lots_placed| {
+            sequenced(
+                0,
+                0,
+                MarketEventDetails::Place(Place {
+                    order_sequence_number,
+                    client_order_id: 0,
+                    maker: Pubkey::default(),
+                    price_in_ticks,
+                    base_lots_placed,
+                }),
+            )
+        };
+        let fill = |sequence_number, event_index, price_in_ticks, base_lots_filled| {
+            sequenced(
+                sequence_number,
+                event_index,
+                MarketEventDetails::Fill(Fill::new(
+                    0,
+                    Pubkey::default(),
+                    Pubkey::default(),
+                    price_in_ticks,
+                    base_lots_filled,
+                    0,
+                )),
+            )
+        };
+
+        let mut orderbook = Orderbook::default();
+        // Bids have inverted sequence numbers
+        for (order_sequence_number, price, lots) in [(!1, 24, 10), (!2, 25, 5), (!3, 25, 5)] {
+            orderbook.apply_event(&place(order_sequence_number, price, lots));
+        }
+        orderbook.apply_event(&place(4, 27, 30));
+        let traders = (0..3)
+            .map(|_| (Pubkey::new_unique(), TraderState::default()))
+            .collect();
+        let snapshot = MarketSnapshot {
+            state: MarketState { orderbook, traders },
+            sequence_number: 10,
+            slot: 99,
+        };
+        // Fills arrive newest transaction first
+        let recent_fills = RecentFills {
+            fills: vec![
+                fill(9, 1, 26, 100),
+                fill(9, 0, 25, 100),
+                fill(8, 0, 24, 200),
+            ],
+            signatures_scanned: 2,
+            is_partial: true,
+        };
+
+        let window = Duration::from_secs(86_400);
+        let summary = MarketSummary::new(market, &snapshot, &meta, window, &recent_fills);
+        assert_eq!(summary.slot, 99);
+        assert_eq!(summary.last_price, Some(0.026));
+        assert_eq!(summary.base_volume, 0.4);
+        // 0.1 at 0.026, 0.1 at 0.025 and 0.2 at 0.024
+        assert_eq!(summary.quote_volume, 0.0099);
+        assert!(summary.volume_is_partial);
+        assert_eq!(summary.signatures_scanned, 2);
+        assert_eq!(summary.best_bid, Some(0.025));
+        assert_eq!(summary.best_ask, Some(0.027));
+        assert_eq!(summary.bid_depth, 0.02);
+        assert_eq!(summary.ask_depth, 0.03);
+        assert_eq!(summary.resting_orders, 4);
+        assert_eq!(summary.seated_traders, 3);
+
+        let empty = MarketSummary::new(
+            market,
+            &MarketSnapshot {
+                state: MarketState {
+                    orderbook: Orderbook::default(),
+                    traders: BTreeMap::new(),
+                },
+                sequence_number: 0,
+                slot: 0,
+            },
+            &meta,
+            window,
+            &RecentFills::default(),
+        );
+        assert_eq!(
+            (empty.last_price, empty.best_bid, empty.best_ask),
+            (None, None, None)
+        );
+        assert_eq!((empty.base_volume, empty.quote_volume), (0.0, 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_scan_recent_fills_limits() {
+        use solana_client::rpc_request::RpcRequest;
+
+        let market = Pubkey::new_unique();
+        let since = 1_700_000_000;
+        // Failed transactions are counted but not fetched
+        let page = |block_times: &[i64]| {
+            let statuses = block_times
+                .iter()
+                .map(|block_time| {
+                    serde_json::json!({
+                        "signature": Signature::new_unique().to_string(),
+                        "slot": 1,
+                        "err": "AccountInUse",
+                        "memo": null,
+                        "blockTime": block_time,
+                        "confirmationStatus": "finalized",
+                    })
+                })
+                .collect::<Vec<_>>();
+            HashMap::from([(RpcRequest::GetSignaturesForAddress, statuses.into())])
+        };
+        let sdk_with_page = |block_times: &[i64]| {
+            let client = EllipsisClient::from_rpc(
+                RpcClient::new_mock_with_mocks("succeeds".to_string(), page(block_times)),
+                &Keypair::new(),
+            )
+            .unwrap();
+            SDKClient::new_from_ellipsis_client(client)
+        };
+
+        // The cap is hit while still inside the window
+        let sdk = sdk_with_page(&[since + 2, since + 1, since]).await.unwrap();
+        let recent_fills = sdk.scan_recent_fills(&market, since, 2).await.unwrap();
+        assert_eq!(recent_fills.signatures_scanned, 2);
+        assert!(recent_fills.is_partial);
+        assert!(recent_fills.fills.is_empty());
+
+        // The window ends before the cap
+        let sdk = sdk_with_page(&[since + 1, since - 1, since - 2])
+            .await
+            .unwrap();
+        let recent_fills = sdk.scan_recent_fills(&market, since, 2).await.unwrap();
+        assert_eq!(recent_fills.signatures_scanned, 1);
+        assert!(!recent_fills.is_partial);
+    }
+
+    #[test]
+    fn test_fok_expectation_check() {
+        let fill = |base_lots_filled, quote_lots_filled| FokFill {
+            signature: Signature::default(),
+            market: Pubkey::default(),
+            base_lots_filled,
+            quote_lots_filled,
+            fee_in_quote_lots: 0,
+        };
+        let budget = FokExpectation::quote_lots(Side::Bid, 1000);
+        assert_eq!(
+            budget.check(fill(40, 1000)),
+            FokOutcome::FilledExactly(fill(40, 1000))
+        );
+        // The base lots of a quote budget are not checked
+        assert_eq!(
+            budget.check(fill(39, 999)),
+            FokOutcome::FilledWithinTolerance(fill(39, 999))
+        );
+        assert_eq!(
+            budget.check(fill(38, 950)),
+            FokOutcome::Mismatch {
+                fill: fill(38, 950),
+                unwind: None
+            }
+        );
+        assert_eq!(
+            budget.check(fill(0, 0)),
+            FokOutcome::NotFilled(Signature::default())
+        );
+
+        let size = FokExpectation {
+            quote_lots: Some(500),
+            base_lots_tolerance: 0,
+            ..FokExpectation::base_lots(Side::Ask, 20)
+        };
+        assert_eq!(
+            size.check(fill(20, 501)),
+            FokOutcome::FilledWithinTolerance(fill(20, 501))
+        );
+        assert!(matches!(
+            size.check(fill(21, 500)),
+            FokOutcome::Mismatch { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_fok_and_verify() {
+        let (sdk, market) = crate::paper_trading::test_utils::paper_client(&[
+            (Side::Ask, 1000, 50),
+            (Side::Bid, 900, 100),
+        ])
+        .await;
+        let fok_buy = |base_lots| {
+            sdk.get_ioc_full_ix(
+                &market,
+                Side::Bid,
+                Some(1000),
+                base_lots,
+                0,
+                base_lots,
+                0,
+                SelfTradeBehavior::CancelProvide,
+                None,
+                0,
+                false,
+            )
+            .unwrap()
+        };
+
+        let outcome = sdk
+            .send_fok_and_verify(fok_buy(20), FokExpectation::base_lots(Side::Bid, 20))
+            .await
+            .unwrap();
+        let FokOutcome::FilledExactly(fill) = outcome else {
+            panic!("Expected an exact fill, got {:?}", outcome);
+        };
+        assert_eq!((fill.market, fill.base_lots_filled), (market, 20));
+
+        // Filling twice the expected size is sold back into the bids
+        let expected = FokExpectation {
+            unwind_on_mismatch: true,
+            ..FokExpectation::base_lots(Side::Bid, 10)
+        };
+        let outcome = sdk
+            .send_fok_and_verify(fok_buy(20), expected)
+            .await
+            .unwrap();
+        let FokOutcome::Mismatch {
+            fill,
+            unwind: Some(unwind),
+        } = outcome
+        else {
+            panic!("Expected an unwound mismatch, got {:?}", outcome);
+        };
+        assert_eq!(fill.base_lots_filled, 20);
+        let unwound = sdk
+            .parse_fills(&unwind)
+            .await
+            .iter()
+            .map(|event| match event.details {
+                MarketEventDetails::Fill(fill) => {
+                    assert_eq!((fill.taker_side, fill.price_in_ticks), (Side::Ask, 900));
+                    fill.base_lots_filled
+                }
+                _ => 0,
+            })
+            .sum::<u64>();
+        assert_eq!(unwound, 20);
+
+        // A killed order fails its transaction
+        assert!(sdk
+            .send_fok_and_verify(fok_buy(20), FokExpectation::base_lots(Side::Bid, 20))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_fok_and_verify_match_limit() {
+        let (sdk, market) = crate::paper_trading::test_utils::paper_client(&[
+            (Side::Ask, 1000, 10),
+            (Side::Ask, 1000, 10),
+            (Side::Bid, 900, 100),
+        ])
+        .await;
+        // Needs both asks, but may only match one
+        let ix = sdk
+            .get_ioc_full_ix(
+                &market,
+                Side::Bid,
+                Some(1000),
+                20,
+                0,
+                5,
+                0,
+                SelfTradeBehavior::CancelProvide,
+                Some(1),
+                0,
+                false,
+            )
+            .unwrap();
+        let expected = FokExpectation {
+            unwind_on_mismatch: true,
+            ..FokExpectation::base_lots(Side::Bid, 20)
+        };
+        let outcome = sdk.send_fok_and_verify(ix, expected).await.unwrap();
+        let FokOutcome::MatchLimitReached {
+            fill,
+            match_limit: 1,
+            unwind: Some(_),
+        } = outcome
+        else {
+            panic!("Expected an unwound truncated fill, got {:?}", outcome);
+        };
+        assert_eq!(fill.base_lots_filled, 10);
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        use crate::event_metrics::EventMetrics;
+        use crate::health::{ComponentDetails, HealthStatus};
+        use crate::rate_limiter::RateLimitConfig;
+
+        let (mut sdk, market) =
+            crate::paper_trading::test_utils::paper_client(&[(Side::Ask, 1000, 50)]).await;
+        let report = sdk.health();
+        assert!(report.is_healthy());
+        assert!(report.components.is_empty());
+        assert_eq!(report.last_transaction, None);
+
+        sdk.set_rate_limiter(Some(Arc::new(RateLimiter::new(RateLimitConfig::new(
+            10.0, 100.0,
+        )))));
+        let metrics = Arc::new(EventMetrics::default());
+        metrics.record_chain_slot(1_000);
+        sdk.register_health_provider("events", metrics.clone());
+        let ix = sdk.get_ioc_ix(&market, 1_000_000, Side::Bid, 10).unwrap();
+        let signature = sdk.send_ixs(vec![ix]).await.unwrap();
+
+        // Nothing has been processed, so the stream trails the chain by every slot
+        let report = sdk.health();
+        assert_eq!(report.status, HealthStatus::Degraded);
+        assert_eq!(
+            report.components.keys().collect::<Vec<_>>(),
+            ["events", "rate_limiter"]
+        );
+        assert!(matches!(
+            report.components["events"].details,
+            ComponentDetails::EventStream {
+                slot_lag: 1_000,
+                ..
+            }
+        ));
+        assert_eq!(report.last_transaction.unwrap().signature, signature);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["last_transaction"]["signature"], signature.to_string());
+
+        metrics.record_processed_slot(1_000);
+        assert!(sdk.health().is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_estimate_compute_units() {
+        use solana_client::rpc_request::RpcRequest;
+        use solana_sdk::compute_budget::ComputeBudgetInstruction;
+        use solana_sdk::instruction::AccountMeta;
+
+        let simulation = |err: serde_json::Value, units_consumed| {
+            serde_json::json!({
+                "context": { "slot": 1 },
+                "value": {
+                    "err": err,
+                    "logs": ["Program log: simulated"],
+                    "accounts": null,
+                    "unitsConsumed": units_consumed,
+                    "returnData": null,
+                },
+            })
+        };
+        let sdk_with_simulation = |simulation| {
+            let client = EllipsisClient::from_rpc(
+                RpcClient::new_mock_with_mocks(
+                    "succeeds".to_string(),
+                    HashMap::from([(RpcRequest::SimulateTransaction, simulation)]),
+                ),
+                &Keypair::new(),
+            )
+            .unwrap();
+            SDKClient::new_from_ellipsis_client(client)
+        };
+        let trader = Pubkey::new_unique();
+        let order = |price: u64| {
+            let mut data = vec![2];
+            data.extend(price.to_le_bytes());
+            Instruction::new_with_bytes(phoenix::id(), &data, vec![AccountMeta::new(trader, true)])
+        };
+
+        let sdk = sdk_with_simulation(simulation(serde_json::Value::Null, 42_000))
+            .await
+            .unwrap();
+        assert_eq!(
+            sdk.estimate_compute_units(&[order(100)]).await.unwrap(),
+            42_000
+        );
+        // The simulation is mocked once, so the second estimate comes from the cache
+        let priced = [
+            ComputeBudgetInstruction::set_compute_unit_price(1_000),
+            order(250),
+        ];
+        assert_eq!(sdk.estimate_compute_units(&priced).await.unwrap(), 42_000);
+        assert_eq!(sdk.compute_unit_cache().len(), 1);
+        // Another shape is simulated again
+        assert!(sdk
+            .estimate_compute_units(&[order(100), order(250)])
+            .await
+            .is_err());
+
+        let sdk = sdk_with_simulation(simulation(
+            serde_json::json!({ "InstructionError": [0, { "Custom": 1 }] }),
+            1_000,
+        ))
+        .await
+        .unwrap();
+        let error = sdk.estimate_compute_units(&[order(100)]).await.unwrap_err();
+        match error.downcast_ref::<PhoenixSdkError>() {
+            Some(PhoenixSdkError::TransactionFailed { logs, .. }) => {
+                assert_eq!(logs, &["Program log: simulated"])
+            }
+            _ => panic!("Expected a failed transaction, got {}", error),
+        }
+        assert!(sdk.compute_unit_cache().is_empty());
+    }
+
+    /// Answers requests with empty results and records them, to check what was asked for.
+    #[derive(Clone, Default)]
+    struct RecordingSender {
+        requests: Arc<Mutex<Vec<(String, serde_json::Value)>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl solana_client::rpc_sender::RpcSender for RecordingSender {
+        async fn send(
+            &self,
+            request: solana_client::rpc_request::RpcRequest,
+            params: serde_json::Value,
+        ) -> solana_client::client_error::Result<serde_json::Value> {
+            let method = request.to_string();
+            self.requests.lock().unwrap().push((method.clone(), params));
+            let empty = |value| serde_json::json!({ "context": { "slot": 1 }, "value": value });
+            Ok(match method.as_str() {
+                "getVersion" => serde_json::json!({ "solana-core": "1.17.31" }),
+                "getAccountInfo" => empty(serde_json::Value::Null),
+                "getMultipleAccounts" => empty(serde_json::json!([null])),
+                "getSignaturesForAddress" => serde_json::json!([]),
+                _ => serde_json::Value::Null,
+            })
+        }
+
+        fn get_transport_stats(&self) -> solana_client::rpc_sender::RpcTransportStats {
+            Default::default()
+        }
+
+        fn url(&self) -> String {
+            "recording".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commitment_policy() {
+        use solana_client::rpc_client::RpcClientConfig;
+
+        let sender = RecordingSender::default();
+        let rpc = RpcClient::new_sender(
+            sender.clone(),
+            RpcClientConfig::with_commitment(CommitmentConfig::finalized()),
+        );
+        let client = EllipsisClient::from_rpc(rpc, &Keypair::new()).unwrap();
+        let mut sdk = SDKClient::new_from_ellipsis_client(client).await.unwrap();
+        // Defaults to the commitment of the client
+        assert_eq!(
+            sdk.commitment_policy(),
+            CommitmentPolicy::uniform(CommitmentConfig::finalized())
+        );
+        sdk.set_commitment_policy(CommitmentPolicy {
+            send: CommitmentConfig::confirmed(),
+            snapshot: CommitmentConfig::processed(),
+            history: CommitmentConfig::finalized(),
+            subscription: CommitmentConfig::processed(),
+        });
+
+        let market = Pubkey::new_unique();
+        assert!(sdk.fetch_market_account_with_slot(&market).await.is_err());
+        sdk.fetch_multiple_accounts(&[market]).await.unwrap();
+        sdk.scan_recent_fills(&market, 0, 10).await.unwrap();
+        assert!(sdk
+            .parse_instructions_from_transaction(&Signature::default())
+            .await
+            .is_err());
+
+        let commitments = sender
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(method, _)| method != "getVersion")
+            .map(|(method, params)| {
+                let commitment = params[1]["commitment"].as_str().unwrap_or_default();
+                (method.clone(), commitment.to_string())
+            })
+            .dedup()
+            .collect::<Vec<_>>();
+        let expected = [
+            ("getAccountInfo", "processed"),
+            ("getMultipleAccounts", "processed"),
+            ("getSignaturesForAddress", "finalized"),
+            ("getTransaction", "finalized"),
+        ]
+        .map(|(method, commitment)| (method.to_string(), commitment.to_string()));
+        assert_eq!(commitments, expected);
+
+        // History is never fetched below confirmed
+        sdk.set_commitment_policy(CommitmentPolicy::uniform(CommitmentConfig::processed()));
+        sender.requests.lock().unwrap().clear();
+        sdk.scan_recent_fills(&market, 0, 10).await.unwrap();
+        assert_eq!(
+            sender.requests.lock().unwrap()[0].1[1]["commitment"],
+            "confirmed"
+        );
+
+        let subscriber = sdk.book_subscriber("ws://localhost:8900", market, 16);
+        assert_eq!(subscriber.commitment(), CommitmentConfig::processed());
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use phoenix_sdk_core::market_event::PhoenixEvent;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+/// Message type sent over the SDK's market event channels.
+/// Producers send events in batches (`Vec<SDKMarketEvent>`), typically one batch per transaction.
+///
+/// New variants may be added, so matches outside the SDK need a wildcard arm. The `as_*` helpers
+/// pick out one variant without a match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SDKMarketEvent {
+    /// Shared, so that fanning a batch out to several consumers copies pointers, not events.
+    PhoenixEvent { event: Arc<PhoenixEvent> },
+    /// The producer is alive, sent every heartbeat interval whether or not the market traded.
+    /// `poller_lag` is how many slots the producer's processed events trail `chain_slot` by.
+    Heartbeat {
+        market: Pubkey,
+        chain_slot: u64,
+        poller_lag: u64,
+    },
+    /// A change to the stream itself rather than to a market, see `ControlMsg`.
+    Control(ControlMsg),
+}
+
+/// The stream notifications of `SDKMarketEvent::Control`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ControlMsg {
+    /// A transaction whose events were already delivered was dropped by a fork and never
+    /// finalized. `events` are the events previously delivered for it, which consumers should undo.
+    Rollback {
+        signature: Signature,
+        events: Vec<PhoenixEvent>,
+    },
+    /// The consumer fell behind and `missed_batches` batches were dropped before this one, e.g.
+    /// by an `EventRouter` subscription with `Backpressure::DropOldest`. `market` is the market
+    /// of the subscription, `None` for a wildcard one.
+    Gap {
+        market: Option<Pubkey>,
+        missed_batches: u64,
+    },
+    /// Batches may have been lost without a count, e.g. when a router restarted mid-batch. State
+    /// built from the stream for `market`, or for every market if `None`, should be rebuilt
+    /// from a snapshot.
+    ResyncRequired { market: Option<Pubkey> },
+}
+
+impl ControlMsg {
+    /// The market the notification is about. `None` for a rollback, whose events may span
+    /// markets, and for notifications about every market.
+    pub fn market(&self) -> Option<Pubkey> {
+        match self {
+            ControlMsg::Rollback { .. } => None,
+            ControlMsg::Gap { market, .. } | ControlMsg::ResyncRequired { market } => *market,
+        }
+    }
+}
+
+impl SDKMarketEvent {
+    pub fn rollback(signature: Signature, events: Vec<PhoenixEvent>) -> Self {
+        SDKMarketEvent::Control(ControlMsg::Rollback { signature, events })
+    }
+
+    pub fn as_phoenix_event(&self) -> Option<&PhoenixEvent> {
+        match self {
+            SDKMarketEvent::PhoenixEvent { event } => Some(event),
+            _ => None,
+        }
+    }
+
+    /// The event as a shared handle, to keep it without copying it.
+    pub fn as_shared_phoenix_event(&self) -> Option<&Arc<PhoenixEvent>> {
+        match self {
+            SDKMarketEvent::PhoenixEvent { event } => Some(event),
+            _ => None,
+        }
+    }
+
+    /// The signature and events of a rollback.
+    pub fn as_rollback(&self) -> Option<(&Signature, &[PhoenixEvent])> {
+        match self {
+            SDKMarketEvent::Control(ControlMsg::Rollback { signature, events }) => {
+                Some((signature, events))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_control(&self) -> Option<&ControlMsg> {
+        match self {
+            SDKMarketEvent::Control(control) => Some(control),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a `Heartbeat`, which carries no market data.
+    pub fn is_heartbeat(&self) -> bool {
+        matches!(self, SDKMarketEvent::Heartbeat { .. })
+    }
+}
+
+impl From<PhoenixEvent> for SDKMarketEvent {
+    fn from(event: PhoenixEvent) -> Self {
+        SDKMarketEvent::PhoenixEvent {
+            event: Arc::new(event),
+        }
+    }
+}
+
+impl From<Arc<PhoenixEvent>> for SDKMarketEvent {
+    fn from(event: Arc<PhoenixEvent>) -> Self {
+        SDKMarketEvent::PhoenixEvent { event }
+    }
+}
+
+impl From<ControlMsg> for SDKMarketEvent {
+    fn from(control: ControlMsg) -> Self {
+        SDKMarketEvent::Control(control)
+    }
+}
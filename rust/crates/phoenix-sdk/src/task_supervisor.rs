@@ -0,0 +1,542 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::task::{JoinError, JoinHandle};
+
+use crate::health::{
+    ComponentDetails, ComponentHealth, HealthProvider, HealthStatus, HealthThresholds,
+};
+
+/// How a supervised task is restarted after it fails or panics. A task that returns `Ok` is done
+/// and is not restarted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// Restarts allowed before the task is left failed. `None` restarts it indefinitely.
+    pub max_restarts: Option<u32>,
+    /// Wait before the first restart. Each consecutive failure doubles it, up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RestartPolicy {
+    pub fn never() -> Self {
+        Self {
+            max_restarts: Some(0),
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: None,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Options for `TaskSupervisor::spawn_supervised_with`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskOptions {
+    pub restart: RestartPolicy,
+    /// Tasks this one consumes from. On shutdown they are stopped before it is.
+    pub depends_on: Vec<String>,
+    /// On shutdown, how long the task may run on its own once its dependencies have stopped, e.g.
+    /// to drain a closed channel, before it is cancelled.
+    pub shutdown_grace: Duration,
+}
+
+impl TaskOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_restart(mut self, restart: RestartPolicy) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    pub fn depends_on(mut self, name: impl Into<String>) -> Self {
+        self.depends_on.push(name.into());
+        self
+    }
+
+    pub fn with_shutdown_grace(mut self, shutdown_grace: Duration) -> Self {
+        self.shutdown_grace = shutdown_grace;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    /// Failed and waiting out its backoff before the next restart.
+    Restarting,
+    /// Returned `Ok`.
+    Finished,
+    /// Failed with no restarts left.
+    Failed,
+    /// Stopped by `TaskSupervisor::shutdown`.
+    Stopped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaskHealth {
+    pub name: String,
+    pub state: TaskState,
+    pub restarts: u32,
+    /// The error or panic message of the most recent failure.
+    pub last_error: Option<String>,
+}
+
+struct SupervisedTask {
+    name: String,
+    depends_on: Vec<String>,
+    health: Arc<Mutex<TaskHealth>>,
+    /// Taken by `shutdown`.
+    control: Option<TaskControl>,
+}
+
+struct TaskControl {
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+/// Aborts a run when the future supervising it is dropped, so it cannot outlive its supervisor.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Owns background tasks, restarting them when they fail and stopping them in dependency order.
+///
+/// Each task is given as a function that starts a fresh run of it, since a crashed future cannot be
+/// resumed. A run that returns an error or panics is restarted after a backoff, as set by the
+/// task's `RestartPolicy`. `shutdown` stops producers before their consumers: a task is only
+/// stopped once every task it `depends_on` has stopped. Dropping the supervisor cancels every task
+/// without waiting.
+#[derive(Default)]
+pub struct TaskSupervisor {
+    tasks: Mutex<Vec<SupervisedTask>>,
+}
+
+impl TaskSupervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawns a task with the default `TaskOptions`. See `spawn_supervised_with`.
+    pub fn spawn_supervised<F, Fut>(&self, name: impl Into<String>, task: F) -> Result<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.spawn_supervised_with(name, TaskOptions::default(), task)
+    }
+
+    /// Spawns `task()` on the current tokio runtime and supervises it under `name`, calling `task`
+    /// again for every restart. Fails if a task with the same name is still running or restarting;
+    /// a finished, failed or stopped one is replaced.
+    pub fn spawn_supervised_with<F, Fut>(
+        &self,
+        name: impl Into<String>,
+        options: TaskOptions,
+        task: F,
+    ) -> Result<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let name = name.into();
+        let mut tasks = self.tasks();
+        if tasks.iter().any(|supervised| {
+            supervised.name == name
+                && matches!(
+                    lock(&supervised.health).state,
+                    TaskState::Running | TaskState::Restarting
+                )
+        }) {
+            return Err(anyhow!("A task named {} is already supervised", name));
+        }
+        tasks.retain(|supervised| supervised.name != name);
+        let health = Arc::new(Mutex::new(TaskHealth {
+            name: name.clone(),
+            state: TaskState::Running,
+            restarts: 0,
+            last_error: None,
+        }));
+        let (stop, stop_receiver) = watch::channel(false);
+        let handle = tokio::spawn(supervise(
+            task,
+            options.restart,
+            options.shutdown_grace,
+            health.clone(),
+            stop_receiver,
+        ));
+        tasks.push(SupervisedTask {
+            name,
+            depends_on: options.depends_on,
+            health,
+            control: Some(TaskControl { stop, handle }),
+        });
+        Ok(())
+    }
+
+    /// The health of every task, in the order they were spawned.
+    pub fn health(&self) -> Vec<TaskHealth> {
+        self.tasks()
+            .iter()
+            .map(|supervised| lock(&supervised.health).clone())
+            .collect()
+    }
+
+    pub fn task_health(&self, name: &str) -> Option<TaskHealth> {
+        self.tasks()
+            .iter()
+            .find(|supervised| supervised.name == name)
+            .map(|supervised| lock(&supervised.health).clone())
+    }
+
+    /// Stops every task, producers before consumers, and waits for them to exit. Tasks whose
+    /// dependencies form a cycle are stopped together at the end. Tasks spawned after this is
+    /// called are not stopped by it. Their health stays available afterwards.
+    pub async fn shutdown(&self) {
+        let mut remaining = self
+            .tasks()
+            .iter_mut()
+            .filter_map(|supervised| {
+                let control = supervised.control.take()?;
+                Some((
+                    supervised.name.clone(),
+                    supervised.depends_on.clone(),
+                    control,
+                ))
+            })
+            .collect::<Vec<_>>();
+        while !remaining.is_empty() {
+            let names = remaining
+                .iter()
+                .map(|(name, _, _)| name.clone())
+                .collect::<HashSet<_>>();
+            let (mut ready, blocked): (Vec<_>, Vec<_>) =
+                remaining.into_iter().partition(|(_, depends_on, _)| {
+                    depends_on
+                        .iter()
+                        .all(|dependency| !names.contains(dependency))
+                });
+            remaining = blocked;
+            if ready.is_empty() {
+                ready = std::mem::take(&mut remaining);
+            }
+            for (_, _, control) in ready.iter() {
+                let _ = control.stop.send(true);
+            }
+            for (_, _, control) in ready {
+                let _ = control.handle.await;
+            }
+        }
+    }
+
+    fn tasks(&self) -> MutexGuard<'_, Vec<SupervisedTask>> {
+        lock(&self.tasks)
+    }
+}
+
+impl Drop for TaskSupervisor {
+    fn drop(&mut self) {
+        for supervised in self.tasks().iter() {
+            if let Some(control) = supervised.control.as_ref() {
+                control.handle.abort();
+            }
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Resolves once a stop is requested or the supervisor is gone.
+async fn stopped(stop: &mut watch::Receiver<bool>) {
+    while !*stop.borrow() {
+        if stop.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+fn failure_message(result: Result<Result<()>, JoinError>) -> Option<String> {
+    match result {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(e) if e.is_panic() => {
+            let panic = e.into_panic();
+            Some(match panic.downcast_ref::<&str>() {
+                Some(message) => format!("panicked: {}", message),
+                None => match panic.downcast_ref::<String>() {
+                    Some(message) => format!("panicked: {}", message),
+                    None => "panicked".to_string(),
+                },
+            })
+        }
+        Err(e) => Some(e.to_string()),
+    }
+}
+
+async fn supervise<F, Fut>(
+    mut task: F,
+    policy: RestartPolicy,
+    shutdown_grace: Duration,
+    health: Arc<Mutex<TaskHealth>>,
+    mut stop: watch::Receiver<bool>,
+) where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    let set_state = |state| lock(&health).state = state;
+    let mut backoff = policy.initial_backoff;
+    loop {
+        set_state(TaskState::Running);
+        let started_at = Instant::now();
+        // Spawned separately so that a panic is caught as a `JoinError`
+        let mut run = AbortOnDrop(tokio::spawn(task()));
+        let result = tokio::select! {
+            result = &mut run.0 => result,
+            _ = stopped(&mut stop) => {
+                if tokio::time::timeout(shutdown_grace, &mut run.0).await.is_err() {
+                    run.0.abort();
+                    let _ = (&mut run.0).await;
+                }
+                set_state(TaskState::Stopped);
+                return;
+            }
+        };
+        let Some(error) = failure_message(result) else {
+            set_state(TaskState::Finished);
+            return;
+        };
+        {
+            let mut health = lock(&health);
+            println!("Task {} failed: {}", health.name, error);
+            health.last_error = Some(error);
+            if policy
+                .max_restarts
+                .is_some_and(|max_restarts| health.restarts >= max_restarts)
+            {
+                health.state = TaskState::Failed;
+                return;
+            }
+            health.restarts += 1;
+            health.state = TaskState::Restarting;
+        }
+        // A run that stayed up for a while starts the backoff over
+        if started_at.elapsed() > policy.max_backoff {
+            backoff = policy.initial_backoff;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = stopped(&mut stop) => {
+                set_state(TaskState::Stopped);
+                return;
+            }
+        }
+        backoff = (backoff * 2).min(policy.max_backoff);
+    }
+}
+
+/// Degraded while a task is restarting, and unhealthy once one has failed for good.
+impl HealthProvider for TaskSupervisor {
+    fn health_check(&self, _thresholds: &HealthThresholds) -> ComponentHealth {
+        let tasks = self.health();
+        let status = tasks
+            .iter()
+            .map(|task| match task.state {
+                TaskState::Restarting => HealthStatus::Degraded,
+                TaskState::Failed => HealthStatus::Unhealthy,
+                TaskState::Running | TaskState::Finished | TaskState::Stopped => {
+                    HealthStatus::Healthy
+                }
+            })
+            .max()
+            .unwrap_or_default();
+        ComponentHealth {
+            status,
+            details: ComponentDetails::Tasks { tasks },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    /// Records its name when dropped, i.e. when the run that owns it is cancelled.
+    struct DropRecorder(&'static str, Arc<Mutex<Vec<&'static str>>>);
+
+    impl Drop for DropRecorder {
+        fn drop(&mut self) {
+            lock(&self.1).push(self.0);
+        }
+    }
+
+    fn fast_restarts(max_restarts: Option<u32>) -> TaskOptions {
+        TaskOptions::new().with_restart(RestartPolicy {
+            max_restarts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+        })
+    }
+
+    async fn wait_for_state(supervisor: &TaskSupervisor, name: &str, state: TaskState) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while supervisor.task_health(name).unwrap().state != state {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restarts_killed_task_and_shuts_down() {
+        let supervisor = TaskSupervisor::new();
+        let runs = Arc::new(AtomicU32::new(0));
+        let dropped = Arc::new(Mutex::new(vec![]));
+        let (task_runs, task_dropped) = (runs.clone(), dropped.clone());
+        supervisor
+            .spawn_supervised_with("mock", fast_restarts(None), move || {
+                let run = task_runs.fetch_add(1, Ordering::SeqCst);
+                let recorder = DropRecorder("mock", task_dropped.clone());
+                async move {
+                    let _recorder = recorder;
+                    match run {
+                        0 => panic!("killed"),
+                        1 => Err(anyhow!("connection reset")),
+                        _ => std::future::pending().await,
+                    }
+                }
+            })
+            .unwrap();
+        assert!(supervisor
+            .spawn_supervised("mock", || async { Ok(()) })
+            .is_err());
+
+        while runs.load(Ordering::SeqCst) < 3 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        wait_for_state(&supervisor, "mock", TaskState::Running).await;
+        let health = supervisor.task_health("mock").unwrap();
+        assert_eq!(health.restarts, 2);
+        assert_eq!(health.last_error.as_deref(), Some("connection reset"));
+
+        supervisor.shutdown().await;
+        assert_eq!(lock(&dropped).len(), 3);
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        let health = supervisor.task_health("mock").unwrap();
+        assert_eq!((health.state, health.restarts), (TaskState::Stopped, 2));
+
+        // A stopped task's name can be reused
+        supervisor
+            .spawn_supervised("mock", || async { Ok(()) })
+            .unwrap();
+        wait_for_state(&supervisor, "mock", TaskState::Finished).await;
+        assert_eq!(supervisor.health().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_restart_limit_and_completion() {
+        let supervisor = TaskSupervisor::new();
+        supervisor
+            .spawn_supervised_with("failing", fast_restarts(Some(2)), || async {
+                Err(anyhow!("bad config"))
+            })
+            .unwrap();
+        supervisor
+            .spawn_supervised("done", || async { Ok(()) })
+            .unwrap();
+        wait_for_state(&supervisor, "failing", TaskState::Failed).await;
+        wait_for_state(&supervisor, "done", TaskState::Finished).await;
+        let failing = supervisor.task_health("failing").unwrap();
+        assert_eq!(failing.restarts, 2);
+        assert_eq!(failing.last_error.as_deref(), Some("bad config"));
+        supervisor.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_producers_first() {
+        let supervisor = TaskSupervisor::new();
+        let stopped = Arc::new(Mutex::new(vec![]));
+        let (sender, receiver) = tokio::sync::mpsc::channel::<u64>(16);
+        let drained = Arc::new(Mutex::new(vec![]));
+
+        let consumer_stopped = stopped.clone();
+        let consumer_drained = drained.clone();
+        let mut receiver = Some(receiver);
+        supervisor
+            .spawn_supervised_with(
+                "consumer",
+                TaskOptions::new()
+                    .depends_on("producer")
+                    .with_shutdown_grace(Duration::from_secs(5)),
+                move || {
+                    let mut receiver = receiver.take();
+                    let recorder = DropRecorder("consumer", consumer_stopped.clone());
+                    let drained = consumer_drained.clone();
+                    async move {
+                        let _recorder = recorder;
+                        let receiver = receiver.as_mut().ok_or_else(|| anyhow!("No receiver"))?;
+                        while let Some(value) = receiver.recv().await {
+                            lock(&drained).push(value);
+                        }
+                        Ok(())
+                    }
+                },
+            )
+            .unwrap();
+        let producer_stopped = stopped.clone();
+        supervisor
+            .spawn_supervised("producer", move || {
+                let sender = sender.clone();
+                let recorder = DropRecorder("producer", producer_stopped.clone());
+                async move {
+                    let _recorder = recorder;
+                    for value in 0..3 {
+                        sender.send(value).await?;
+                    }
+                    std::future::pending().await
+                }
+            })
+            .unwrap();
+
+        while lock(&drained).len() < 3 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        let supervisor = Arc::new(supervisor);
+        let shutdown = tokio::spawn({
+            let supervisor = supervisor.clone();
+            async move { supervisor.shutdown().await }
+        });
+        // The consumer finishes on its own once the producer's sender is gone
+        tokio::time::timeout(Duration::from_secs(1), shutdown)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(*lock(&stopped), vec!["producer", "consumer"]);
+        assert_eq!(*lock(&drained), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,964 @@
+//! Canonical cases for checking other implementations of the SDK, such as the TypeScript one,
+//! against this one: lot and tick conversions on a few market configurations, `Log` instruction
+//! data with the `PhoenixEvent`s it decodes to, and order instructions with their data.
+//!
+//! `generate` builds the cases and `verify` checks them against this SDK. The cases are checked
+//! in at `phoenix-sdk/test_vectors/vectors.json`, which the tests here verify and compare with
+//! `generate`; after changing the cases, rewrite it with
+//! `cargo run -p phoenix-sdk --features test-vectors --bin phoenix-test-vectors -- <path>`.
+//!
+//! In the inputs, outputs and events, integers are decimal strings, since JavaScript numbers
+//! cannot hold every `u64`, and floats are numbers. Sides are `"bid"` and `"ask"`, roundings
+//! `"down"`, `"up"` and `"exact"`, and byte strings are hex. A conversion that fails has a `null` output.
+
+use std::fmt::Write;
+
+use anyhow::{anyhow, bail, Result};
+use borsh::BorshSerialize;
+use ellipsis_client::EllipsisClient;
+use phoenix::program::{
+    AuditLogHeader, EvictEvent, ExpiredOrderEvent, FeeEvent, FillEvent, FillSummaryEvent,
+    PhoenixInstruction, PhoenixMarketEvent, PlaceEvent, ReduceEvent, TimeInForceEvent,
+};
+use phoenix::state::enums::{SelfTradeBehavior, Side};
+use phoenix_sdk_core::atoms::Rounding;
+use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent};
+use phoenix_sdk_core::sdk_client_core::MarketMetadata;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+
+use crate::sdk_client::{EventParseOptions, SDKClient, UnknownMarketPolicy};
+
+/// Bumped when the format changes, not when cases are added.
+pub const TEST_VECTORS_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestVectors {
+    pub version: u32,
+    pub conversions: Vec<ConversionVectors>,
+    pub events: Vec<EventVector>,
+    pub order_packets: Vec<OrderPacketVector>,
+}
+
+/// The market parameters the conversions depend on, as in `MarketMetadata`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetadataParams {
+    pub base_decimals: u32,
+    pub quote_decimals: u32,
+    pub base_atoms_per_raw_base_unit: u64,
+    pub quote_atoms_per_quote_unit: u64,
+    pub quote_atoms_per_quote_lot: u64,
+    pub base_atoms_per_base_lot: u64,
+    pub tick_size_in_quote_atoms_per_base_unit: u64,
+    pub num_base_lots_per_base_unit: u64,
+    pub raw_base_units_per_base_unit: u32,
+    pub taker_fee_bps: u64,
+}
+
+impl MetadataParams {
+    pub fn to_metadata(&self, base_mint: Pubkey, quote_mint: Pubkey) -> MarketMetadata {
+        MarketMetadata {
+            base_mint,
+            quote_mint,
+            base_decimals: self.base_decimals,
+            quote_decimals: self.quote_decimals,
+            base_atoms_per_raw_base_unit: self.base_atoms_per_raw_base_unit,
+            quote_atoms_per_quote_unit: self.quote_atoms_per_quote_unit,
+            quote_atoms_per_quote_lot: self.quote_atoms_per_quote_lot,
+            base_atoms_per_base_lot: self.base_atoms_per_base_lot,
+            tick_size_in_quote_atoms_per_base_unit: self.tick_size_in_quote_atoms_per_base_unit,
+            num_base_lots_per_base_unit: self.num_base_lots_per_base_unit,
+            raw_base_units_per_base_unit: self.raw_base_units_per_base_unit,
+            taker_fee_bps: self.taker_fee_bps,
+            ..Default::default()
+        }
+    }
+}
+
+/// A call of the function named `function` with `inputs`, and what it returned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Case {
+    pub function: String,
+    pub inputs: Vec<Value>,
+    pub output: Value,
+}
+
+/// Cases of the `MarketMetadata` conversions on one market configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversionVectors {
+    pub name: String,
+    pub metadata: MetadataParams,
+    pub cases: Vec<Case>,
+}
+
+/// A `Log` instruction's data and the events `SDKClient` decodes from it, in a transaction with
+/// `signature` on a market with `metadata`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventVector {
+    pub name: String,
+    pub signature: String,
+    pub metadata: MetadataParams,
+    pub instruction_data: String,
+    pub events: Vec<Value>,
+}
+
+/// An order instruction built by the `SDKClientCore` function in `case`, whose output is the
+/// instruction's data.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderPacketVector {
+    pub name: String,
+    pub metadata: MetadataParams,
+    pub case: Case,
+}
+
+/// The conversions covered, with the kind of inputs they take.
+const CONVERSIONS: &[(&str, Inputs)] = &[
+    ("base_atoms_to_base_lots_rounded_down", Inputs::Integer),
+    ("base_atoms_to_base_lots_rounded_up", Inputs::Integer),
+    ("base_lots_to_base_atoms", Inputs::Integer),
+    ("base_units_to_base_lots", Inputs::Integer),
+    ("raw_base_units_to_base_lots", Inputs::Integer),
+    ("quote_atoms_to_quote_lots_rounded_down", Inputs::Integer),
+    ("quote_atoms_to_quote_lots_rounded_up", Inputs::Integer),
+    ("quote_lots_to_quote_atoms", Inputs::Integer),
+    ("base_atoms_to_raw_base_units_as_float", Inputs::Integer),
+    ("quote_atoms_to_quote_units_as_float", Inputs::Integer),
+    ("ticks_to_float_price", Inputs::Integer),
+    ("base_lots_to_decimal_str", Inputs::Integer),
+    ("base_atoms_to_decimal_str", Inputs::Integer),
+    ("quote_atoms_to_decimal_str", Inputs::Integer),
+    ("ticks_to_decimal_str", Inputs::Integer),
+    (
+        "raw_base_units_to_base_lots_rounded_down_as_float",
+        Inputs::Float,
+    ),
+    (
+        "raw_base_units_to_base_lots_rounded_up_as_float",
+        Inputs::Float,
+    ),
+    ("quote_units_to_quote_lots_as_float", Inputs::Float),
+    ("float_price_to_ticks_rounded_down", Inputs::Float),
+    ("float_price_to_ticks_rounded_up", Inputs::Float),
+    ("decimal_str_to_base_lots", Inputs::DecimalStr),
+    ("decimal_str_to_quote_lots", Inputs::DecimalStr),
+    ("decimal_str_to_ticks", Inputs::DecimalStr),
+    ("base_lots_and_price_to_quote_atoms", Inputs::LotsAndPrice),
+    ("fee_for_notional", Inputs::NotionalAndMaker),
+    ("order_notional_quote_atoms", Inputs::SidePriceLots),
+    ("min_base_lots_for_notional", Inputs::SidePriceLots),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum Inputs {
+    Integer,
+    Float,
+    DecimalStr,
+    LotsAndPrice,
+    NotionalAndMaker,
+    SidePriceLots,
+}
+
+impl Inputs {
+    fn cases(self) -> Vec<Vec<Value>> {
+        let integers = [0u64, 1, 7, 1_000, 123_456_789, 4_000_000_000];
+        match self {
+            Inputs::Integer => integers.iter().map(|n| vec![integer(*n)]).collect(),
+            Inputs::Float => [0.0, 0.5, 1.0, 1.5, 23.456, 0.000001, 1e-9]
+                .iter()
+                .map(|x| vec![json!(x)])
+                .collect(),
+            Inputs::DecimalStr => {
+                let strs = [
+                    "0",
+                    "1",
+                    "1.5",
+                    "0.000001",
+                    "123.456789",
+                    "0.0000000001",
+                    "x",
+                ];
+                strs.iter()
+                    .flat_map(|s| {
+                        ["down", "up", "exact"]
+                            .iter()
+                            .map(move |rounding| vec![json!(s), json!(rounding)])
+                    })
+                    .collect()
+            }
+            Inputs::LotsAndPrice => [(0, 100), (1, 1), (1_000, 25_000), (7, 123_456_789)]
+                .iter()
+                .map(|(lots, price)| vec![integer(*lots), integer(*price)])
+                .collect(),
+            Inputs::NotionalAndMaker => [0u64, 1, 9_999, 10_000, 123_456_789]
+                .iter()
+                .flat_map(|n| [false, true].map(|is_maker| vec![integer(*n), json!(is_maker)]))
+                .collect(),
+            Inputs::SidePriceLots => [(1u64, 1u64), (25_000, 1_000), (123_456, 7), (0, 10)]
+                .iter()
+                .flat_map(|(price, amount)| {
+                    ["bid", "ask"].map(|side| vec![json!(side), integer(*price), integer(*amount)])
+                })
+                .collect(),
+        }
+    }
+}
+
+fn integer(n: impl ToString) -> Value {
+    Value::String(n.to_string())
+}
+
+fn optional_integer(n: Option<impl ToString>) -> Value {
+    n.map(integer).unwrap_or(Value::Null)
+}
+
+/// Calls the conversion `function` on `meta`. A conversion that fails returns `Value::Null`; an
+/// unknown function or malformed inputs are errors.
+pub fn evaluate_conversion(
+    meta: &MarketMetadata,
+    function: &str,
+    inputs: &[Value],
+) -> Result<Value> {
+    let args = Args(inputs);
+    Ok(match function {
+        "base_atoms_to_base_lots_rounded_down" => {
+            integer(meta.base_atoms_to_base_lots_rounded_down(args.u64(0)?))
+        }
+        "base_atoms_to_base_lots_rounded_up" => {
+            integer(meta.base_atoms_to_base_lots_rounded_up(args.u64(0)?))
+        }
+        "base_lots_to_base_atoms" => integer(meta.base_lots_to_base_atoms(args.u64(0)?)),
+        "base_units_to_base_lots" => integer(meta.base_units_to_base_lots(args.u64(0)?)),
+        "raw_base_units_to_base_lots" => integer(meta.raw_base_units_to_base_lots(args.u64(0)?)),
+        "quote_atoms_to_quote_lots_rounded_down" => {
+            integer(meta.quote_atoms_to_quote_lots_rounded_down(args.u64(0)?))
+        }
+        "quote_atoms_to_quote_lots_rounded_up" => {
+            integer(meta.quote_atoms_to_quote_lots_rounded_up(args.u64(0)?))
+        }
+        "quote_lots_to_quote_atoms" => integer(meta.quote_lots_to_quote_atoms(args.u64(0)?)),
+        "base_atoms_to_raw_base_units_as_float" => {
+            json!(meta.base_atoms_to_raw_base_units_as_float(args.u64(0)?))
+        }
+        "quote_atoms_to_quote_units_as_float" => {
+            json!(meta.quote_atoms_to_quote_units_as_float(args.u64(0)?))
+        }
+        "ticks_to_float_price" => json!(meta.ticks_to_float_price(args.u64(0)?)),
+        "base_lots_to_decimal_str" => json!(meta.base_lots_to_decimal_str(args.u64(0)?)),
+        "base_atoms_to_decimal_str" => {
+            json!(meta.base_atoms_to_decimal_str(args.u64(0)? as u128))
+        }
+        "quote_atoms_to_decimal_str" => {
+            json!(meta.quote_atoms_to_decimal_str(args.u64(0)? as u128))
+        }
+        "ticks_to_decimal_str" => json!(meta.ticks_to_decimal_str(args.u64(0)?).ok()),
+        "raw_base_units_to_base_lots_rounded_down_as_float" => {
+            integer(meta.raw_base_units_to_base_lots_rounded_down_as_float(args.f64(0)?))
+        }
+        "raw_base_units_to_base_lots_rounded_up_as_float" => {
+            integer(meta.raw_base_units_to_base_lots_rounded_up_as_float(args.f64(0)?))
+        }
+        "quote_units_to_quote_lots_as_float" => {
+            integer(meta.quote_units_to_quote_lots_as_float(args.f64(0)?))
+        }
+        "float_price_to_ticks_rounded_down" => {
+            integer(meta.float_price_to_ticks_rounded_down(args.f64(0)?))
+        }
+        "float_price_to_ticks_rounded_up" => {
+            integer(meta.float_price_to_ticks_rounded_up(args.f64(0)?))
+        }
+        "decimal_str_to_base_lots" => optional_integer(
+            meta.decimal_str_to_base_lots(args.str(0)?, args.rounding(1)?)
+                .ok(),
+        ),
+        "decimal_str_to_quote_lots" => optional_integer(
+            meta.decimal_str_to_quote_lots(args.str(0)?, args.rounding(1)?)
+                .ok(),
+        ),
+        "decimal_str_to_ticks" => optional_integer(
+            meta.decimal_str_to_ticks(args.str(0)?, args.rounding(1)?)
+                .ok(),
+        ),
+        "base_lots_and_price_to_quote_atoms" => {
+            integer(meta.base_lots_and_price_to_quote_atoms(args.u64(0)?, args.u64(1)?))
+        }
+        "fee_for_notional" => integer(meta.fee_for_notional(args.u64(0)?, args.bool(1)?)),
+        "order_notional_quote_atoms" => {
+            integer(meta.order_notional_quote_atoms(args.side(0)?, args.u64(1)?, args.u64(2)?))
+        }
+        "min_base_lots_for_notional" => optional_integer(meta.min_base_lots_for_notional(
+            args.side(0)?,
+            args.u64(1)?,
+            args.u64(2)?,
+        )),
+        _ => bail!("Unknown conversion {}", function),
+    })
+}
+
+/// Builds the instruction of an order case, from one of a post-only, a limit and an IOC order
+/// builder, on `market` and returns its data as hex.
+pub fn evaluate_order_packet(
+    client: &SDKClient,
+    market: &Pubkey,
+    function: &str,
+    inputs: &[Value],
+) -> Result<Value> {
+    let args = Args(inputs);
+    let ix = match function {
+        "get_post_only_ix_from_tick_price" => client.get_post_only_ix_from_tick_price(
+            market,
+            args.u64(0)?,
+            args.side(1)?,
+            args.u64(2)?,
+            args.u128(3)?,
+            args.bool(4)?,
+        )?,
+        "get_limit_order_ix_from_tick_price" => client.get_limit_order_ix_from_tick_price(
+            market,
+            args.u64(0)?,
+            args.side(1)?,
+            args.u64(2)?,
+            args.u128(3)?,
+        )?,
+        "get_ioc_full_ix" => client.get_ioc_full_ix(
+            market,
+            args.side(0)?,
+            args.optional_u64(1)?,
+            args.u64(2)?,
+            args.u64(3)?,
+            args.u64(4)?,
+            args.u64(5)?,
+            match args.str(6)? {
+                "abort" => SelfTradeBehavior::Abort,
+                "cancel_provide" => SelfTradeBehavior::CancelProvide,
+                "decrement_take" => SelfTradeBehavior::DecrementTake,
+                other => bail!("Unknown self trade behavior {}", other),
+            },
+            args.optional_u64(7)?,
+            args.u128(8)?,
+            args.bool(9)?,
+        )?,
+        _ => bail!("Unknown order function {}", function),
+    };
+    Ok(json!(to_hex(&ix.data)))
+}
+
+struct Args<'a>(&'a [Value]);
+
+impl Args<'_> {
+    fn get(&self, index: usize) -> Result<&Value> {
+        self.0
+            .get(index)
+            .ok_or_else(|| anyhow!("Missing input {}", index))
+    }
+
+    fn str(&self, index: usize) -> Result<&str> {
+        self.get(index)?
+            .as_str()
+            .ok_or_else(|| anyhow!("Input {} is not a string", index))
+    }
+
+    fn u64(&self, index: usize) -> Result<u64> {
+        Ok(self.str(index)?.parse()?)
+    }
+
+    fn u128(&self, index: usize) -> Result<u128> {
+        Ok(self.str(index)?.parse()?)
+    }
+
+    fn optional_u64(&self, index: usize) -> Result<Option<u64>> {
+        match self.get(index)? {
+            Value::Null => Ok(None),
+            _ => self.u64(index).map(Some),
+        }
+    }
+
+    fn f64(&self, index: usize) -> Result<f64> {
+        self.get(index)?
+            .as_f64()
+            .ok_or_else(|| anyhow!("Input {} is not a number", index))
+    }
+
+    fn bool(&self, index: usize) -> Result<bool> {
+        self.get(index)?
+            .as_bool()
+            .ok_or_else(|| anyhow!("Input {} is not a bool", index))
+    }
+
+    fn side(&self, index: usize) -> Result<Side> {
+        match self.str(index)? {
+            "bid" => Ok(Side::Bid),
+            "ask" => Ok(Side::Ask),
+            other => bail!("Unknown side {}", other),
+        }
+    }
+
+    fn rounding(&self, index: usize) -> Result<Rounding> {
+        match self.str(index)? {
+            "down" => Ok(Rounding::Down),
+            "up" => Ok(Rounding::Up),
+            "exact" => Ok(Rounding::Exact),
+            other => bail!("Unknown rounding {}", other),
+        }
+    }
+}
+
+fn side_str(side: Side) -> &'static str {
+    match side {
+        Side::Bid => "bid",
+        Side::Ask => "ask",
+    }
+}
+
+/// A `PhoenixEvent` as it appears in `EventVector::events`.
+pub fn phoenix_event_to_json(event: &PhoenixEvent) -> Value {
+    let details = match event.details {
+        MarketEventDetails::Fill(fill) => json!({
+            "type": "fill",
+            "order_sequence_number": integer(fill.order_sequence_number),
+            "maker": fill.maker.to_string(),
+            "taker": fill.taker.to_string(),
+            "price_in_ticks": integer(fill.price_in_ticks),
+            "base_lots_filled": integer(fill.base_lots_filled),
+            "base_lots_remaining": integer(fill.base_lots_remaining),
+            "side_filled": side_str(fill.side_filled),
+            "is_full_fill": fill.is_full_fill,
+            "maker_side": side_str(fill.maker_side),
+            "taker_side": side_str(fill.taker_side),
+        }),
+        MarketEventDetails::Place(place) => json!({
+            "type": "place",
+            "order_sequence_number": integer(place.order_sequence_number),
+            "client_order_id": integer(place.client_order_id),
+            "maker": place.maker.to_string(),
+            "price_in_ticks": integer(place.price_in_ticks),
+            "base_lots_placed": integer(place.base_lots_placed),
+        }),
+        MarketEventDetails::Evict(evict) => json!({
+            "type": "evict",
+            "order_sequence_number": integer(evict.order_sequence_number),
+            "maker": evict.maker.to_string(),
+            "price_in_ticks": integer(evict.price_in_ticks),
+            "base_lots_evicted": integer(evict.base_lots_evicted),
+        }),
+        MarketEventDetails::Reduce(reduce) => json!({
+            "type": "reduce",
+            "order_sequence_number": integer(reduce.order_sequence_number),
+            "maker": reduce.maker.to_string(),
+            "price_in_ticks": integer(reduce.price_in_ticks),
+            "base_lots_removed": integer(reduce.base_lots_removed),
+            "base_lots_remaining": integer(reduce.base_lots_remaining),
+            "is_full_cancel": reduce.is_full_cancel,
+        }),
+        MarketEventDetails::FillSummary(summary) => json!({
+            "type": "fill_summary",
+            "client_order_id": integer(summary.client_order_id),
+            "total_base_filled": integer(summary.total_base_filled),
+            "total_quote_filled_including_fees": integer(summary.total_quote_filled_including_fees),
+            "total_quote_fees": integer(summary.total_quote_fees),
+            "trade_direction": summary.trade_direction,
+        }),
+        MarketEventDetails::Fee(fee) => json!({
+            "type": "fee",
+            "fees_collected": integer(fee),
+        }),
+        MarketEventDetails::TimeInForce(time_in_force) => json!({
+            "type": "time_in_force",
+            "order_sequence_number": integer(time_in_force.order_sequence_number),
+            "last_valid_slot": integer(time_in_force.last_valid_slot),
+            "last_valid_unix_timestamp_in_seconds":
+                integer(time_in_force.last_valid_unix_timestamp_in_seconds),
+        }),
+    };
+    json!({
+        "market": event.market.to_string(),
+        "sequence_number": integer(event.sequence_number),
+        "slot": integer(event.slot),
+        "timestamp": integer(event.timestamp),
+        "signature": event.signature.to_string(),
+        "signer": event.signer.to_string(),
+        "event_index": integer(event.event_index),
+        "details": details,
+    })
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+pub fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        bail!("Odd-length hex string");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&hex[i..i + 2], 16)?))
+        .collect()
+}
+
+/// SOL/USDC-like: 9 and 6 decimals, 0.001 SOL lots and 0.001 USDC ticks.
+const SOL_USDC: MetadataParams = MetadataParams {
+    base_decimals: 9,
+    quote_decimals: 6,
+    base_atoms_per_raw_base_unit: 1_000_000_000,
+    quote_atoms_per_quote_unit: 1_000_000,
+    quote_atoms_per_quote_lot: 1,
+    base_atoms_per_base_lot: 1_000_000,
+    tick_size_in_quote_atoms_per_base_unit: 1_000,
+    num_base_lots_per_base_unit: 1_000,
+    raw_base_units_per_base_unit: 1,
+    taker_fee_bps: 2,
+};
+
+/// BONK/USDC-like: a base unit of a million raw base units, priced in quote atoms.
+const BONK_USDC: MetadataParams = MetadataParams {
+    base_decimals: 5,
+    quote_decimals: 6,
+    base_atoms_per_raw_base_unit: 100_000,
+    quote_atoms_per_quote_unit: 1_000_000,
+    quote_atoms_per_quote_lot: 1,
+    base_atoms_per_base_lot: 100_000_000,
+    tick_size_in_quote_atoms_per_base_unit: 1,
+    num_base_lots_per_base_unit: 1_000,
+    raw_base_units_per_base_unit: 1_000_000,
+    taker_fee_bps: 5,
+};
+
+/// BTC/USDC-like: quote lots of 10 atoms and 0.01 USDC ticks.
+const BTC_USDC: MetadataParams = MetadataParams {
+    base_decimals: 8,
+    quote_decimals: 6,
+    base_atoms_per_raw_base_unit: 100_000_000,
+    quote_atoms_per_quote_unit: 1_000_000,
+    quote_atoms_per_quote_lot: 10,
+    base_atoms_per_base_lot: 10_000,
+    tick_size_in_quote_atoms_per_base_unit: 10_000,
+    num_base_lots_per_base_unit: 10_000,
+    raw_base_units_per_base_unit: 1,
+    taker_fee_bps: 10,
+};
+
+/// Markets and mints are fixed keys, so that generated vectors do not change between runs.
+fn market_key() -> Pubkey {
+    Pubkey::new_from_array([1; 32])
+}
+
+/// A client that never reaches the RPC node: it knows the one market it needs, and nothing here
+/// sends or fetches.
+async fn offline_client(metadata: &MetadataParams) -> Result<SDKClient> {
+    let client = EllipsisClient::from_rpc(
+        RpcClient::new("http://127.0.0.1:8899".to_string()),
+        &Keypair::new(),
+    )?;
+    let client = SDKClient::new_from_ellipsis_client(client).await?;
+    client.markets.insert(
+        market_key(),
+        metadata.to_metadata(
+            Pubkey::new_from_array([2; 32]),
+            Pubkey::new_from_array([3; 32]),
+        ),
+    );
+    Ok(client)
+}
+
+fn log_instruction_data(events: &[PhoenixMarketEvent]) -> Result<Vec<u8>> {
+    let mut data = vec![PhoenixInstruction::Log as u8];
+    let header = PhoenixMarketEvent::Header(AuditLogHeader {
+        instruction: PhoenixInstruction::Swap as u8,
+        sequence_number: 4_242,
+        timestamp: 1_700_000_000,
+        slot: 250_000_000,
+        market: market_key(),
+        signer: Pubkey::new_from_array([4; 32]),
+        total_events: events.len() as u16,
+    });
+    data.extend(header.try_to_vec()?);
+    for event in events {
+        data.extend(event.try_to_vec()?);
+    }
+    Ok(data)
+}
+
+fn event_batches() -> Vec<(&'static str, Vec<PhoenixMarketEvent>)> {
+    let maker = Pubkey::new_from_array([5; 32]);
+    vec![
+        (
+            "taker_buy",
+            vec![
+                PhoenixMarketEvent::Fill(FillEvent {
+                    index: 0,
+                    maker_id: maker,
+                    order_sequence_number: 42,
+                    price_in_ticks: 25_000,
+                    base_lots_filled: 10,
+                    base_lots_remaining: 0,
+                }),
+                PhoenixMarketEvent::Fill(FillEvent {
+                    index: 1,
+                    maker_id: maker,
+                    order_sequence_number: 43,
+                    price_in_ticks: 25_001,
+                    base_lots_filled: 5,
+                    base_lots_remaining: 95,
+                }),
+                PhoenixMarketEvent::FillSummary(FillSummaryEvent {
+                    index: 2,
+                    client_order_id: 7,
+                    total_base_lots_filled: 15,
+                    total_quote_lots_filled: 375_005,
+                    total_fee_in_quote_lots: 75,
+                }),
+            ],
+        ),
+        (
+            "taker_sell",
+            vec![
+                PhoenixMarketEvent::Fill(FillEvent {
+                    index: 0,
+                    maker_id: maker,
+                    order_sequence_number: !17,
+                    price_in_ticks: 24_999,
+                    base_lots_filled: 3,
+                    base_lots_remaining: 2,
+                }),
+                PhoenixMarketEvent::FillSummary(FillSummaryEvent {
+                    index: 1,
+                    client_order_id: u128::MAX,
+                    total_base_lots_filled: 3,
+                    total_quote_lots_filled: 74_997,
+                    total_fee_in_quote_lots: 15,
+                }),
+            ],
+        ),
+        (
+            "maker_lifecycle",
+            vec![
+                PhoenixMarketEvent::Place(PlaceEvent {
+                    index: 0,
+                    order_sequence_number: !99,
+                    client_order_id: 123_456_789_012_345_678_901_234_567_890,
+                    price_in_ticks: 24_500,
+                    base_lots_placed: 1_000,
+                }),
+                PhoenixMarketEvent::TimeInForce(TimeInForceEvent {
+                    index: 1,
+                    order_sequence_number: !99,
+                    last_valid_slot: 250_000_100,
+                    last_valid_unix_timestamp_in_seconds: 1_700_000_060,
+                }),
+                PhoenixMarketEvent::Reduce(ReduceEvent {
+                    index: 2,
+                    order_sequence_number: !99,
+                    price_in_ticks: 24_500,
+                    base_lots_removed: 400,
+                    base_lots_remaining: 600,
+                }),
+                PhoenixMarketEvent::Reduce(ReduceEvent {
+                    index: 3,
+                    order_sequence_number: !99,
+                    price_in_ticks: 24_500,
+                    base_lots_removed: 600,
+                    base_lots_remaining: 0,
+                }),
+            ],
+        ),
+        (
+            "evict_expire_fee",
+            vec![
+                PhoenixMarketEvent::Evict(EvictEvent {
+                    index: 0,
+                    maker_id: maker,
+                    order_sequence_number: 7,
+                    price_in_ticks: 99_999,
+                    base_lots_evicted: 12,
+                }),
+                PhoenixMarketEvent::ExpiredOrder(ExpiredOrderEvent {
+                    index: 1,
+                    maker_id: maker,
+                    order_sequence_number: !8,
+                    price_in_ticks: 1,
+                    base_lots_removed: 3,
+                }),
+                PhoenixMarketEvent::Fee(FeeEvent {
+                    index: 2,
+                    fees_collected_in_quote_lots: 1_234,
+                }),
+            ],
+        ),
+    ]
+}
+
+fn order_cases() -> Vec<(&'static str, Case)> {
+    let case = |function: &str, inputs: Vec<Value>| Case {
+        function: function.to_string(),
+        inputs,
+        output: Value::Null,
+    };
+    vec![
+        (
+            "post_only_bid",
+            case(
+                "get_post_only_ix_from_tick_price",
+                vec![
+                    integer(25_000),
+                    json!("bid"),
+                    integer(100),
+                    integer(1),
+                    json!(false),
+                ],
+            ),
+        ),
+        (
+            "post_only_ask_improve_on_cross",
+            case(
+                "get_post_only_ix_from_tick_price",
+                vec![
+                    integer(25_001),
+                    json!("ask"),
+                    integer(7),
+                    integer(u128::MAX),
+                    json!(true),
+                ],
+            ),
+        ),
+        (
+            "limit_bid",
+            case(
+                "get_limit_order_ix_from_tick_price",
+                vec![integer(24_000), json!("bid"), integer(1_000), integer(42)],
+            ),
+        ),
+        (
+            "ioc_buy_quote_lots",
+            case(
+                "get_ioc_full_ix",
+                vec![
+                    json!("bid"),
+                    Value::Null,
+                    integer(0),
+                    integer(1_000_000),
+                    integer(39),
+                    integer(0),
+                    json!("cancel_provide"),
+                    integer(10),
+                    integer(9),
+                    json!(true),
+                ],
+            ),
+        ),
+        (
+            "ioc_sell_limit_price",
+            case(
+                "get_ioc_full_ix",
+                vec![
+                    json!("ask"),
+                    integer(24_000),
+                    integer(500),
+                    integer(0),
+                    integer(0),
+                    integer(11_000_000),
+                    json!("abort"),
+                    Value::Null,
+                    integer(0),
+                    json!(false),
+                ],
+            ),
+        ),
+    ]
+}
+
+/// Builds every case from this SDK.
+pub async fn generate() -> Result<TestVectors> {
+    let mut conversions = vec![];
+    for (name, metadata) in [
+        ("sol_usdc", SOL_USDC),
+        ("bonk_usdc", BONK_USDC),
+        ("btc_usdc", BTC_USDC),
+    ] {
+        let meta = metadata.to_metadata(Pubkey::default(), Pubkey::default());
+        let mut cases = vec![];
+        for (function, inputs) in CONVERSIONS {
+            for inputs in inputs.cases() {
+                cases.push(Case {
+                    function: function.to_string(),
+                    output: evaluate_conversion(&meta, function, &inputs)?,
+                    inputs,
+                });
+            }
+        }
+        conversions.push(ConversionVectors {
+            name: name.to_string(),
+            metadata,
+            cases,
+        });
+    }
+
+    let signature = Signature::from([6; 64]);
+    let client = offline_client(&SOL_USDC).await?;
+    let mut events = vec![];
+    for (name, batch) in event_batches() {
+        let instruction_data = log_instruction_data(&batch)?;
+        events.push(EventVector {
+            name: name.to_string(),
+            signature: signature.to_string(),
+            metadata: SOL_USDC,
+            events: decode_events(&client, &signature, &instruction_data).await?,
+            instruction_data: to_hex(&instruction_data),
+        });
+    }
+
+    let mut order_packets = vec![];
+    for (name, mut case) in order_cases() {
+        case.output = evaluate_order_packet(&client, &market_key(), &case.function, &case.inputs)?;
+        order_packets.push(OrderPacketVector {
+            name: name.to_string(),
+            metadata: SOL_USDC,
+            case,
+        });
+    }
+
+    Ok(TestVectors {
+        version: TEST_VECTORS_VERSION,
+        conversions,
+        events,
+        order_packets,
+    })
+}
+
+async fn decode_events(
+    client: &SDKClient,
+    signature: &Signature,
+    instruction_data: &[u8],
+) -> Result<Vec<Value>> {
+    let raw = client
+        .parse_events_from_instruction_data(signature, [(phoenix::id(), instruction_data)])
+        .ok_or_else(|| anyhow!("Malformed Log instruction data"))?;
+    let options = EventParseOptions {
+        unknown_markets: UnknownMarketPolicy::Error,
+        ..Default::default()
+    };
+    let events = client
+        .parse_raw_phoenix_events_with_options(raw, &options)
+        .await?;
+    Ok(events.iter().map(phoenix_event_to_json).collect())
+}
+
+/// Checks every case of `vectors` against this SDK, returning the first mismatch.
+pub async fn verify(vectors: &TestVectors) -> Result<()> {
+    if vectors.version != TEST_VECTORS_VERSION {
+        bail!(
+            "Test vectors are version {}, expected {}",
+            vectors.version,
+            TEST_VECTORS_VERSION
+        );
+    }
+    for set in &vectors.conversions {
+        let meta = set
+            .metadata
+            .to_metadata(Pubkey::default(), Pubkey::default());
+        for case in &set.cases {
+            let output = evaluate_conversion(&meta, &case.function, &case.inputs)?;
+            if !same_output(&output, &case.output) {
+                bail!(
+                    "{} {}({:?}) returned {}, expected {}",
+                    set.name,
+                    case.function,
+                    case.inputs,
+                    output,
+                    case.output
+                );
+            }
+        }
+    }
+    for vector in &vectors.events {
+        let client = offline_client(&vector.metadata).await?;
+        let signature = vector.signature.parse()?;
+        let events =
+            decode_events(&client, &signature, &from_hex(&vector.instruction_data)?).await?;
+        if events != vector.events {
+            bail!("Events of {} decoded differently", vector.name);
+        }
+    }
+    for vector in &vectors.order_packets {
+        let client = offline_client(&vector.metadata).await?;
+        let case = &vector.case;
+        let output = evaluate_order_packet(&client, &market_key(), &case.function, &case.inputs)?;
+        if output != case.output {
+            bail!(
+                "Order {} has data {}, expected {}",
+                vector.name,
+                output,
+                case.output
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Floats may be parsed back from JSON one ulp off, so they are compared with a tolerance.
+fn same_output(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) if a.is_f64() || b.is_f64() => {
+            let (a, b) = (
+                a.as_f64().unwrap_or(f64::NAN),
+                b.as_f64().unwrap_or(f64::NAN),
+            );
+            (a - b).abs() <= f64::EPSILON * a.abs().max(b.abs())
+        }
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const COMMITTED: &str = include_str!("../test_vectors/vectors.json");
+
+    #[tokio::test]
+    async fn test_committed_vectors_pass() {
+        let vectors: TestVectors = serde_json::from_str(COMMITTED).unwrap();
+        verify(&vectors).await.unwrap();
+
+        // Every conversion and order builder is covered
+        let functions = |cases: &mut dyn Iterator<Item = &Case>| {
+            cases
+                .map(|case| case.function.clone())
+                .collect::<std::collections::BTreeSet<_>>()
+        };
+        let covered = functions(&mut vectors.conversions.iter().flat_map(|set| &set.cases));
+        assert_eq!(covered.len(), CONVERSIONS.len());
+        let covered = functions(&mut vectors.order_packets.iter().map(|vector| &vector.case));
+        assert_eq!(covered.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_committed_vectors_are_current() {
+        let generated = serde_json::to_string_pretty(&generate().await.unwrap()).unwrap() + "\n";
+        assert!(
+            generated == COMMITTED,
+            "test_vectors/vectors.json is stale; regenerate it with \
+             `cargo run -p phoenix-sdk --features test-vectors --bin phoenix-test-vectors -- \
+             phoenix-sdk/test_vectors/vectors.json`"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mismatch_is_reported() {
+        let mut vectors = generate().await.unwrap();
+        vectors.conversions[0].cases[2].output = integer(8);
+        assert!(verify(&vectors).await.is_err());
+        let mut vectors = generate().await.unwrap();
+        vectors.events[0].instruction_data.replace_range(2..4, "ff");
+        assert!(verify(&vectors).await.is_err());
+        assert_eq!(
+            from_hex(&to_hex(&[0, 1, 254, 255])).unwrap(),
+            vec![0, 1, 254, 255]
+        );
+    }
+}
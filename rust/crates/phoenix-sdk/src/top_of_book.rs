@@ -0,0 +1,309 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk_core::orderbook::Orderbook;
+use phoenix_sdk_core::sdk_client_core::{MarketMetadata, PhoenixOrder};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+
+use crate::book_subscriber::BookUpdate;
+
+/// The best bid and ask of a market. Prices and sizes are converted with the market's metadata;
+/// the tick and lot values they came from are kept alongside. Sizes are the total resting at the
+/// best price, and zero for an empty side.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TobUpdate {
+    pub market: Pubkey,
+    pub best_bid: Option<f64>,
+    pub best_ask: Option<f64>,
+    pub bid_size: f64,
+    pub ask_size: f64,
+    pub best_bid_in_ticks: Option<u64>,
+    pub best_ask_in_ticks: Option<u64>,
+    pub bid_base_lots: u64,
+    pub ask_base_lots: u64,
+    pub slot: u64,
+}
+
+impl TobUpdate {
+    pub fn from_book(
+        market: Pubkey,
+        metadata: &MarketMetadata,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        slot: u64,
+    ) -> Self {
+        let best = |side| {
+            book.iter_levels(side)
+                .next()
+                .map(|level| (level.price_in_ticks, level.size_in_base_lots))
+        };
+        let (best_bid_in_ticks, bid_base_lots) = best(Side::Bid).unzip();
+        let (best_ask_in_ticks, ask_base_lots) = best(Side::Ask).unzip();
+        let bid_base_lots = bid_base_lots.unwrap_or(0);
+        let ask_base_lots = ask_base_lots.unwrap_or(0);
+        let raw_base_units_per_base_lot = metadata.raw_base_units_per_base_lot_as_float();
+        Self {
+            market,
+            best_bid: best_bid_in_ticks.map(|ticks| metadata.ticks_to_float_price(ticks)),
+            best_ask: best_ask_in_ticks.map(|ticks| metadata.ticks_to_float_price(ticks)),
+            bid_size: bid_base_lots as f64 * raw_base_units_per_base_lot,
+            ask_size: ask_base_lots as f64 * raw_base_units_per_base_lot,
+            best_bid_in_ticks,
+            best_ask_in_ticks,
+            bid_base_lots,
+            ask_base_lots,
+            slot,
+        }
+    }
+}
+
+/// Which changes to the top of book `TopOfBookFeed` publishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TobChangeFilter {
+    /// Only changes to the best bid or ask price.
+    PriceOnly,
+    /// Price changes, and size changes at an unchanged price of more than
+    /// `size_tolerance_base_lots`.
+    PriceOrSize { size_tolerance_base_lots: u64 },
+}
+
+impl Default for TobChangeFilter {
+    fn default() -> Self {
+        TobChangeFilter::PriceOrSize {
+            size_tolerance_base_lots: 0,
+        }
+    }
+}
+
+impl TobChangeFilter {
+    /// Whether `current` differs from the last published update enough to be published.
+    pub fn is_change(&self, previous: &TobUpdate, current: &TobUpdate) -> bool {
+        if previous.best_bid_in_ticks != current.best_bid_in_ticks
+            || previous.best_ask_in_ticks != current.best_ask_in_ticks
+        {
+            return true;
+        }
+        match *self {
+            TobChangeFilter::PriceOnly => false,
+            TobChangeFilter::PriceOrSize {
+                size_tolerance_base_lots,
+            } => {
+                previous.bid_base_lots.abs_diff(current.bid_base_lots) > size_tolerance_base_lots
+                    || previous.ask_base_lots.abs_diff(current.ask_base_lots)
+                        > size_tolerance_base_lots
+            }
+        }
+    }
+}
+
+/// Publishes a market's top of book over a watch channel, only when it changes according to the
+/// feed's `TobChangeFilter`. A receiver sees the latest published update as soon as it
+/// subscribes, and `None` before the first book arrives.
+///
+/// Size changes within the tolerance are measured against the last published update, so slow
+/// drift is still published once it adds up to more than the tolerance.
+pub struct TopOfBookFeed {
+    market: Pubkey,
+    metadata: MarketMetadata,
+    filter: TobChangeFilter,
+    sender: watch::Sender<Option<TobUpdate>>,
+    last_book_at: Mutex<Option<Instant>>,
+}
+
+impl TopOfBookFeed {
+    pub fn new(market: Pubkey, metadata: MarketMetadata, filter: TobChangeFilter) -> Self {
+        let (sender, _) = watch::channel(None);
+        Self {
+            market,
+            metadata,
+            filter,
+            sender,
+            last_book_at: Mutex::new(None),
+        }
+    }
+
+    pub fn market(&self) -> Pubkey {
+        self.market
+    }
+
+    pub fn metadata(&self) -> &MarketMetadata {
+        &self.metadata
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Option<TobUpdate>> {
+        self.sender.subscribe()
+    }
+
+    /// The last published update.
+    pub fn latest(&self) -> Option<TobUpdate> {
+        *self.sender.borrow()
+    }
+
+    /// How long ago the feed last saw a book, whether or not it was published. An unchanged book
+    /// is not published, so the age of `latest` says little about whether the feed is alive.
+    pub fn last_book_age(&self) -> Option<Duration> {
+        self.last_book_at
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .map(|at| at.elapsed())
+    }
+
+    /// Publishes the top of `book` if it changed, returning the published update.
+    pub fn on_book(
+        &self,
+        book: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        slot: u64,
+    ) -> Option<TobUpdate> {
+        *self
+            .last_book_at
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Instant::now());
+        let current = TobUpdate::from_book(self.market, &self.metadata, book, slot);
+        let changed = self.sender.send_if_modified(|latest| match latest {
+            Some(previous) if !self.filter.is_change(previous, &current) => false,
+            _ => {
+                *latest = Some(current);
+                true
+            }
+        });
+        changed.then_some(current)
+    }
+
+    /// Publishes from a `BookSubscriber`'s updates until the subscriber stops.
+    pub async fn run(&self, mut updates: broadcast::Receiver<BookUpdate>) -> Result<()> {
+        loop {
+            match updates.recv().await {
+                Ok(update) => {
+                    self.on_book(&update.market_state.orderbook, update.slot);
+                }
+                // Every update carries the whole book, so missed updates are harmless
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn book(bids: &[(u64, u64)], asks: &[(u64, u64)]) -> Orderbook<FIFOOrderId, PhoenixOrder> {
+        let mut orderbook = Orderbook::<FIFOOrderId, PhoenixOrder>::default();
+        for (sequence_number, &(price_in_ticks, num_base_lots)) in bids.iter().enumerate() {
+            orderbook.bids.insert(
+                FIFOOrderId::new_from_untyped(price_in_ticks, !(sequence_number as u64)),
+                PhoenixOrder {
+                    num_base_lots,
+                    maker_id: Pubkey::default(),
+                },
+            );
+        }
+        for (sequence_number, &(price_in_ticks, num_base_lots)) in asks.iter().enumerate() {
+            orderbook.asks.insert(
+                FIFOOrderId::new_from_untyped(price_in_ticks, sequence_number as u64),
+                PhoenixOrder {
+                    num_base_lots,
+                    maker_id: Pubkey::default(),
+                },
+            );
+        }
+        orderbook
+    }
+
+    fn feed(filter: TobChangeFilter) -> TopOfBookFeed {
+        let metadata = crate::paper_trading::test_utils::test_market_metadata();
+        TopOfBookFeed::new(Pubkey::new_unique(), metadata, filter)
+    }
+
+    #[test]
+    fn test_price_or_size() {
+        let feed = feed(TobChangeFilter::PriceOrSize {
+            size_tolerance_base_lots: 5,
+        });
+        let mut receiver = feed.subscribe();
+        assert!(receiver.borrow_and_update().is_none());
+        assert!(feed.last_book_age().is_none());
+
+        // Two orders at 99 make one level; deeper levels do not matter
+        let first = feed
+            .on_book(&book(&[(99, 1000), (99, 500), (98, 7)], &[(101, 2000)]), 1)
+            .unwrap();
+        assert_eq!(first.best_bid, Some(0.099));
+        assert_eq!(first.bid_size, 1.5);
+        assert_eq!(first.best_ask_in_ticks, Some(101));
+        assert_eq!(first.ask_size, 2.0);
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(*receiver.borrow_and_update(), Some(first));
+
+        // Changes below the top, and size changes within the tolerance, are not published
+        assert!(feed
+            .on_book(&book(&[(99, 1504), (98, 70)], &[(101, 2000)]), 2)
+            .is_none());
+        assert!(!receiver.has_changed().unwrap());
+        assert_eq!(feed.latest().unwrap().slot, 1);
+        // but were still seen
+        assert!(feed.last_book_age().is_some());
+
+        // The tolerance is against the last published size
+        let resized = feed
+            .on_book(&book(&[(99, 1506)], &[(101, 2000)]), 3)
+            .unwrap();
+        assert_eq!(resized.bid_base_lots, 1506);
+
+        // A side emptying out is a price change
+        let no_asks = feed.on_book(&book(&[(99, 1506)], &[]), 4).unwrap();
+        assert_eq!((no_asks.best_ask, no_asks.ask_size), (None, 0.0));
+
+        // Late subscribers get the latest update immediately
+        let late = feed.subscribe();
+        assert_eq!(*late.borrow(), Some(no_asks));
+    }
+
+    #[test]
+    fn test_price_only() {
+        let feed = feed(TobChangeFilter::PriceOnly);
+        feed.on_book(&book(&[(99, 10)], &[(101, 10)]), 1).unwrap();
+        assert!(feed.on_book(&book(&[(99, 500)], &[(101, 1)]), 2).is_none());
+        let moved = feed.on_book(&book(&[(100, 500)], &[(101, 1)]), 3).unwrap();
+        assert_eq!(moved.best_bid_in_ticks, Some(100));
+        assert_eq!(moved.bid_base_lots, 500);
+    }
+
+    #[tokio::test]
+    async fn test_run_from_book_updates() {
+        use std::sync::Arc;
+
+        use phoenix_sdk_core::sdk_client_core::MarketState;
+
+        use crate::book_subscriber::BookDiff;
+
+        let feed = feed(TobChangeFilter::default());
+        let mut receiver = feed.subscribe();
+        let (sender, updates) = broadcast::channel(4);
+        for (slot, bid_lots) in [(1, 10), (2, 11), (3, 11)] {
+            let update = BookUpdate {
+                market_state: Arc::new(MarketState {
+                    orderbook: book(&[(99, bid_lots)], &[(101, 10)]),
+                    traders: BTreeMap::new(),
+                }),
+                diff: BookDiff::default(),
+                slot,
+            };
+            assert!(sender.send(update).is_ok());
+        }
+        drop(sender);
+        feed.run(updates).await.unwrap();
+
+        // The unchanged book at slot 3 was not published
+        receiver.changed().await.unwrap();
+        let latest = receiver.borrow_and_update().unwrap();
+        assert_eq!((latest.slot, latest.bid_base_lots), (2, 11));
+    }
+}
@@ -0,0 +1,459 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk_core::market_event::{Evict, Fill, MarketEventDetails, PhoenixEvent, Reduce};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio::time::Instant;
+
+use crate::event_router::{Backpressure, EventRouter, RoutedReceiver};
+use crate::sdk_market_event::{ControlMsg, SDKMarketEvent};
+
+/// How long `TrackedOrder` waits for an event by default.
+pub const DEFAULT_TRACKING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Something that happened to a tracked order, in the order the events arrived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderUpdate {
+    /// The order rests on the book, with `base_lots` left after any fills on placement.
+    Placed {
+        order_id: FIFOOrderId,
+        base_lots: u64,
+    },
+    /// Part of the order filled: as the taker when it was placed, or as the maker afterwards.
+    Filled(Fill),
+    /// The order was reduced, or cancelled if `is_full_cancel`.
+    Reduced(Reduce),
+    Evicted(Evict),
+}
+
+/// How a tracked order left the book, or why it never rested on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderClose {
+    /// Filled in full, on placement or afterwards.
+    Filled,
+    Cancelled,
+    Evicted,
+    /// The placing transaction neither filled nor placed the order, e.g. because it ran out of
+    /// funds with `fail_silently_on_insufficient_funds` set.
+    NotPlaced,
+}
+
+/// Why a `TrackedOrder` could not report what was asked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackingError {
+    /// No event answered the call within the timeout.
+    Timeout(Duration),
+    /// The placing transaction did not leave the order on the book; it closed as given.
+    NotResting(OrderClose),
+    /// The placing transaction was rolled back by a fork.
+    RolledBack,
+    /// The subscription fell behind the router and dropped batches, which may have held events
+    /// on the order.
+    MissedEvents,
+    /// The router stopped.
+    RouterClosed,
+}
+
+impl fmt::Display for TrackingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackingError::Timeout(timeout) => {
+                write!(f, "No event on the order within {:?}", timeout)
+            }
+            TrackingError::NotResting(close) => {
+                write!(f, "The order did not rest on the book: {:?}", close)
+            }
+            TrackingError::RolledBack => write!(f, "The order's transaction was rolled back"),
+            TrackingError::MissedEvents => {
+                write!(f, "Events on the order may have been dropped")
+            }
+            TrackingError::RouterClosed => write!(f, "The event router stopped"),
+        }
+    }
+}
+
+impl std::error::Error for TrackingError {}
+
+/// An order placed with a known `client_order_id`, followed through an `EventRouter`.
+///
+/// The order is recognized by the `Place` event with its client order id in the placing
+/// transaction, and from then on by its order id. All events of the placing transaction are
+/// expected in one batch, as the event pollers deliver them: a batch with the transaction's
+/// events but no `Place` means the order did not rest.
+///
+/// Every wait is bounded by the timeout, counted from the start of each call. Events on the
+/// order are queued until read with `next_update`, so waiting with `acknowledged` or `closed`
+/// loses none of them. Fills and cancels rolled back after they were reported are not undone.
+pub struct TrackedOrder {
+    pub market: Pubkey,
+    pub signature: Signature,
+    pub client_order_id: u128,
+    trader: Pubkey,
+    timeout: Duration,
+    receiver: RoutedReceiver,
+    order_id: Option<FIFOOrderId>,
+    base_lots_filled: u64,
+    close: Option<OrderClose>,
+    failure: Option<TrackingError>,
+    updates: VecDeque<OrderUpdate>,
+}
+
+impl TrackedOrder {
+    /// Starts following `trader`'s order `client_order_id` on `market`. Subscribe before sending
+    /// the order so that none of its events are missed, then set the signature of the placing
+    /// transaction with `with_signature` before waiting on the order.
+    pub fn subscribe(
+        router: &EventRouter,
+        market: Pubkey,
+        trader: Pubkey,
+        client_order_id: u128,
+    ) -> Self {
+        Self {
+            market,
+            signature: Signature::default(),
+            client_order_id,
+            trader,
+            timeout: DEFAULT_TRACKING_TIMEOUT,
+            receiver: router.subscribe(market, Backpressure::DropOldest),
+            order_id: None,
+            base_lots_filled: 0,
+            close: None,
+            failure: None,
+            updates: VecDeque::new(),
+        }
+    }
+
+    pub fn with_signature(mut self, signature: Signature) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The order id, once the order was seen resting.
+    pub fn order_id(&self) -> Option<FIFOOrderId> {
+        self.order_id
+    }
+
+    /// Base lots filled so far, as the taker and as the maker.
+    pub fn base_lots_filled(&self) -> u64 {
+        self.base_lots_filled
+    }
+
+    /// Waits for the order to rest on the book and returns its order id.
+    pub async fn acknowledged(&mut self) -> Result<FIFOOrderId, TrackingError> {
+        self.wait_for(|order| order.order_id.is_some() || order.close.is_some())
+            .await?;
+        match (self.order_id, self.close) {
+            (Some(order_id), _) => Ok(order_id),
+            (None, Some(close)) => Err(TrackingError::NotResting(close)),
+            (None, None) => unreachable!("waited for an order id or a close"),
+        }
+    }
+
+    /// Waits for the next event on the order.
+    pub async fn next_update(&mut self) -> Result<OrderUpdate, TrackingError> {
+        self.wait_for(|order| !order.updates.is_empty()).await?;
+        Ok(self.updates.pop_front().expect("waited for an update"))
+    }
+
+    /// Waits for the order to leave the book, or to turn out never to have rested on it.
+    pub async fn closed(&mut self) -> Result<OrderClose, TrackingError> {
+        self.wait_for(|order| order.close.is_some()).await?;
+        Ok(self.close.expect("waited for a close"))
+    }
+
+    async fn wait_for(&mut self, done: impl Fn(&Self) -> bool) -> Result<(), TrackingError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if done(self) {
+                return Ok(());
+            }
+            if let Some(failure) = self.failure {
+                return Err(failure);
+            }
+            let batch = tokio::time::timeout_at(deadline, self.receiver.recv())
+                .await
+                .map_err(|_| TrackingError::Timeout(self.timeout))?
+                .ok_or(TrackingError::RouterClosed)?;
+            if self.receiver.dropped_batches() > 0 && self.close.is_none() {
+                self.failure = Some(TrackingError::MissedEvents);
+            }
+            self.apply_batch(&batch);
+        }
+    }
+
+    fn apply_batch(&mut self, batch: &[SDKMarketEvent]) {
+        let mut placement_seen = false;
+        for message in batch {
+            match message {
+                SDKMarketEvent::PhoenixEvent { event } => {
+                    placement_seen |= event.signature == self.signature;
+                    self.apply_event(event);
+                }
+                SDKMarketEvent::Control(ControlMsg::Rollback { signature, .. })
+                    if *signature == self.signature =>
+                {
+                    self.failure = Some(TrackingError::RolledBack);
+                }
+                _ => {}
+            }
+        }
+        if placement_seen && self.order_id.is_none() && self.close.is_none() {
+            self.close = Some(if self.base_lots_filled > 0 {
+                OrderClose::Filled
+            } else {
+                OrderClose::NotPlaced
+            });
+        }
+    }
+
+    fn apply_event(&mut self, event: &PhoenixEvent) {
+        let is_placement = event.signature == self.signature;
+        let is_ours = |maker: &Pubkey, order_sequence_number: u64| {
+            *maker == self.trader
+                && self.order_id.map(|order_id| order_id.order_sequence_number)
+                    == Some(order_sequence_number)
+        };
+        let update = match event.details {
+            MarketEventDetails::Place(place)
+                if is_placement
+                    && place.maker == self.trader
+                    && place.client_order_id == self.client_order_id =>
+            {
+                let order_id = FIFOOrderId::new_from_untyped(
+                    place.price_in_ticks,
+                    place.order_sequence_number,
+                );
+                self.order_id = Some(order_id);
+                OrderUpdate::Placed {
+                    order_id,
+                    base_lots: place.base_lots_placed,
+                }
+            }
+            MarketEventDetails::Fill(fill)
+                if is_placement && self.order_id.is_none() && fill.taker == self.trader =>
+            {
+                self.base_lots_filled += fill.base_lots_filled;
+                OrderUpdate::Filled(fill)
+            }
+            MarketEventDetails::Fill(fill) if is_ours(&fill.maker, fill.order_sequence_number) => {
+                self.base_lots_filled += fill.base_lots_filled;
+                if fill.base_lots_remaining == 0 {
+                    self.close = Some(OrderClose::Filled);
+                }
+                OrderUpdate::Filled(fill)
+            }
+            MarketEventDetails::Reduce(reduce)
+                if is_ours(&reduce.maker, reduce.order_sequence_number) =>
+            {
+                if reduce.base_lots_remaining == 0 {
+                    self.close = Some(OrderClose::Cancelled);
+                }
+                OrderUpdate::Reduced(reduce)
+            }
+            MarketEventDetails::Evict(evict)
+                if is_ours(&evict.maker, evict.order_sequence_number) =>
+            {
+                self.close = Some(OrderClose::Evicted);
+                OrderUpdate::Evicted(evict)
+            }
+            _ => return,
+        };
+        self.updates.push_back(update);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phoenix_sdk_core::market_event::Place;
+
+    use super::*;
+    use phoenix_sdk_core::test_utils::event;
+
+    struct Replay {
+        market: Pubkey,
+        trader: Pubkey,
+        router: EventRouter,
+    }
+
+    impl Replay {
+        fn new() -> Self {
+            Self {
+                market: Pubkey::new_unique(),
+                trader: Pubkey::new_unique(),
+                router: EventRouter::new(16),
+            }
+        }
+
+        fn event(&self, signature: Signature, details: MarketEventDetails) -> SDKMarketEvent {
+            PhoenixEvent {
+                sequence_number: 1,
+                slot: 1,
+                timestamp: 1_700_000_000,
+                signature,
+                signer: self.trader,
+                ..event(self.market, details)
+            }
+            .into()
+        }
+
+        fn place(
+            &self,
+            signature: Signature,
+            client_order_id: u128,
+            base_lots: u64,
+        ) -> SDKMarketEvent {
+            self.event(
+                signature,
+                MarketEventDetails::Place(Place {
+                    order_sequence_number: !7,
+                    client_order_id,
+                    maker: self.trader,
+                    price_in_ticks: 100,
+                    base_lots_placed: base_lots,
+                }),
+            )
+        }
+
+        fn fill(
+            &self,
+            signature: Signature,
+            maker: Pubkey,
+            taker: Pubkey,
+            remaining: u64,
+        ) -> SDKMarketEvent {
+            // The trader's bid has sequence number !7; its taker fills hit asks
+            let order_sequence_number = if maker == self.trader { !7 } else { 3 };
+            self.event(
+                signature,
+                MarketEventDetails::Fill(Fill::new(
+                    order_sequence_number,
+                    maker,
+                    taker,
+                    100,
+                    10,
+                    remaining,
+                )),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resting_order() {
+        let replay = Replay::new();
+        let signature = Signature::new_unique();
+        let mut order = TrackedOrder::subscribe(&replay.router, replay.market, replay.trader, 42)
+            .with_signature(signature);
+        let other = Pubkey::new_unique();
+
+        // Another order of the trader in the same market is not confused with this one
+        replay
+            .router
+            .route(vec![replay.place(Signature::new_unique(), 41, 50)])
+            .await;
+        // The order crosses for 10 lots on placement and rests with 30
+        replay
+            .router
+            .route(vec![
+                replay.fill(signature, other, replay.trader, 0),
+                replay.place(signature, 42, 30),
+            ])
+            .await;
+        let order_id = order.acknowledged().await.unwrap();
+        assert_eq!(order_id, FIFOOrderId::new_from_untyped(100, !7));
+        assert_eq!(order.base_lots_filled(), 10);
+
+        let later = Signature::new_unique();
+        replay
+            .router
+            .route(vec![replay.fill(later, replay.trader, other, 20)])
+            .await;
+        replay
+            .router
+            .route(vec![replay.event(
+                Signature::new_unique(),
+                MarketEventDetails::Reduce(Reduce {
+                    order_sequence_number: !7,
+                    maker: replay.trader,
+                    price_in_ticks: 100,
+                    base_lots_removed: 20,
+                    base_lots_remaining: 0,
+                    is_full_cancel: true,
+                }),
+            )])
+            .await;
+        assert_eq!(order.closed().await.unwrap(), OrderClose::Cancelled);
+        assert_eq!(order.base_lots_filled(), 20);
+
+        // Every event on the order is still readable, in order
+        let mut updates = vec![];
+        for _ in 0..4 {
+            updates.push(order.next_update().await.unwrap());
+        }
+        assert!(matches!(updates[0], OrderUpdate::Filled(fill) if fill.taker == replay.trader));
+        assert_eq!(
+            updates[1],
+            OrderUpdate::Placed {
+                order_id,
+                base_lots: 30
+            }
+        );
+        assert!(matches!(updates[2], OrderUpdate::Filled(fill) if fill.base_lots_remaining == 20));
+        assert!(matches!(updates[3], OrderUpdate::Reduced(reduce) if reduce.is_full_cancel));
+    }
+
+    #[tokio::test]
+    async fn test_order_that_did_not_rest() {
+        let replay = Replay::new();
+        let signature = Signature::new_unique();
+        let mut order = TrackedOrder::subscribe(&replay.router, replay.market, replay.trader, 42)
+            .with_signature(signature);
+        replay
+            .router
+            .route(vec![replay.fill(
+                signature,
+                Pubkey::new_unique(),
+                replay.trader,
+                0,
+            )])
+            .await;
+        assert_eq!(
+            order.acknowledged().await,
+            Err(TrackingError::NotResting(OrderClose::Filled))
+        );
+        assert_eq!(order.closed().await, Ok(OrderClose::Filled));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_and_rollback() {
+        let replay = Replay::new();
+        let signature = Signature::new_unique();
+        let mut order = TrackedOrder::subscribe(&replay.router, replay.market, replay.trader, 42)
+            .with_signature(signature)
+            .with_timeout(Duration::from_millis(20));
+        assert_eq!(
+            order.acknowledged().await,
+            Err(TrackingError::Timeout(Duration::from_millis(20)))
+        );
+
+        // The order rests, then its transaction is dropped by a fork
+        let place = replay.place(signature, 42, 30);
+        replay.router.route(vec![place.clone()]).await;
+        assert!(order.acknowledged().await.is_ok());
+        let SDKMarketEvent::PhoenixEvent { event } = place else {
+            unreachable!()
+        };
+        replay
+            .router
+            .route(vec![SDKMarketEvent::rollback(signature, vec![*event])])
+            .await;
+        assert_eq!(order.closed().await, Err(TrackingError::RolledBack));
+    }
+}
@@ -0,0 +1,329 @@
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+
+use phoenix::state::enums::Side;
+use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent};
+use phoenix_sdk_core::sdk_client_core::MarketMetadata;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio::sync::mpsc;
+
+use crate::sdk_market_event::SDKMarketEvent;
+
+/// A print on the tape: what one taker order traded at one price. The price is in quote units
+/// per raw base unit and the size in raw base units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trade {
+    pub market: Pubkey,
+    pub price: f64,
+    pub base_size: f64,
+    /// The side of the order that crossed the book.
+    pub taker_side: Side,
+    pub timestamp: i64,
+    pub slot: u64,
+    pub signature: Signature,
+}
+
+/// A print that may still grow with the next fill of the same taker order.
+#[derive(Debug, Clone, Copy)]
+struct PendingTrade {
+    event: PhoenixEvent,
+    price_in_ticks: u64,
+    taker_side: Side,
+    base_lots: u64,
+}
+
+impl PendingTrade {
+    /// Whether `event` is a fill of the same taker order at the same price. The fills of an
+    /// order share their instruction's sequence number.
+    fn continues_with(&self, event: &PhoenixEvent, price_in_ticks: u64, taker_side: Side) -> bool {
+        event.market == self.event.market
+            && event.signature == self.event.signature
+            && event.sequence_number == self.event.sequence_number
+            && price_in_ticks == self.price_in_ticks
+            && taker_side == self.taker_side
+    }
+}
+
+/// Turns `PhoenixEvent`s into a tape of trades for charts and recent-trades views.
+///
+/// Each `Fill` is one maker order filled, so a taker sweeping a level with several resting
+/// orders produces several fills at the same price. By default these are merged into one
+/// `Trade` per taker order and price, see `with_merge_same_price`. `FillSummary` events repeat
+/// the fills' volume and are not printed.
+///
+/// A merged print is only complete once an event that does not continue it arrives, so
+/// `on_event` returns the previous print, and `flush` the last one. `trades` and `run` flush
+/// for you.
+pub struct TradeTape {
+    markets: BTreeMap<Pubkey, MarketMetadata>,
+    merge_same_price: bool,
+    pending: Option<PendingTrade>,
+}
+
+impl Default for TradeTape {
+    fn default() -> Self {
+        Self {
+            markets: BTreeMap::new(),
+            merge_same_price: true,
+            pending: None,
+        }
+    }
+}
+
+impl TradeTape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prints the fills of `market`. Fills of other markets are ignored.
+    pub fn with_market(mut self, market: Pubkey, metadata: MarketMetadata) -> Self {
+        self.markets.insert(market, metadata);
+        self
+    }
+
+    /// Merges the fills of one taker order at one price into a single print, the default.
+    /// Without merging, every fill is its own print.
+    pub fn with_merge_same_price(mut self, merge_same_price: bool) -> Self {
+        self.merge_same_price = merge_same_price;
+        self
+    }
+
+    /// Returns the print completed by `event`, if any.
+    pub fn on_event(&mut self, event: &PhoenixEvent) -> Option<Trade> {
+        let MarketEventDetails::Fill(fill) = &event.details else {
+            return self.flush();
+        };
+        if !self.markets.contains_key(&event.market) {
+            return self.flush();
+        }
+        let merge_same_price = self.merge_same_price;
+        if let Some(pending) = self.pending.as_mut().filter(|pending| {
+            merge_same_price && pending.continues_with(event, fill.price_in_ticks, fill.taker_side)
+        }) {
+            pending.base_lots += fill.base_lots_filled;
+            return None;
+        }
+        let completed = self.flush();
+        self.pending = Some(PendingTrade {
+            event: *event,
+            price_in_ticks: fill.price_in_ticks,
+            taker_side: fill.taker_side,
+            base_lots: fill.base_lots_filled,
+        });
+        if !self.merge_same_price {
+            // Nothing can be merged, so the fill is printed right away
+            return self.flush();
+        }
+        completed
+    }
+
+    /// Returns the print in progress, e.g. at the end of a batch of events.
+    pub fn flush(&mut self) -> Option<Trade> {
+        let pending = self.pending.take()?;
+        let meta = self.markets.get(&pending.event.market)?;
+        Some(Trade {
+            market: pending.event.market,
+            price: meta.ticks_to_float_price(pending.price_in_ticks),
+            base_size: pending.base_lots as f64 * meta.raw_base_units_per_base_lot_as_float(),
+            taker_side: pending.taker_side,
+            timestamp: pending.event.timestamp,
+            slot: pending.event.slot,
+            signature: pending.event.signature,
+        })
+    }
+
+    /// The prints of recorded events, e.g. from an `EventStore` or a backfill.
+    pub fn trades<I>(self, events: I) -> Trades<I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<PhoenixEvent>,
+    {
+        Trades {
+            tape: self,
+            events: events.into_iter(),
+        }
+    }
+
+    /// Prints the fills of event batches, e.g. from an `EventRouter` subscription, until the
+    /// sender or the receiver of `trades` is dropped. Each batch is flushed when it ends.
+    pub async fn run(
+        &mut self,
+        mut receiver: mpsc::Receiver<Vec<SDKMarketEvent>>,
+        trades: mpsc::UnboundedSender<Trade>,
+    ) {
+        while let Some(batch) = receiver.recv().await {
+            let events = batch.into_iter().filter_map(|event| match event {
+                SDKMarketEvent::PhoenixEvent { event } => Some(*event),
+                _ => None,
+            });
+            for event in events {
+                if let Some(trade) = self.on_event(&event) {
+                    if trades.send(trade).is_err() {
+                        return;
+                    }
+                }
+            }
+            if let Some(trade) = self.flush() {
+                if trades.send(trade).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// The prints of a sequence of events, see `TradeTape::trades`.
+pub struct Trades<I> {
+    tape: TradeTape,
+    events: I,
+}
+
+impl<I> Iterator for Trades<I>
+where
+    I: Iterator,
+    I::Item: Borrow<PhoenixEvent>,
+{
+    type Item = Trade;
+
+    fn next(&mut self) -> Option<Trade> {
+        for event in self.events.by_ref() {
+            if let Some(trade) = self.tape.on_event(event.borrow()) {
+                return Some(trade);
+            }
+        }
+        self.tape.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use phoenix_sdk_core::market_event::{Fill, FillSummary, Place};
+    use phoenix_sdk_core::test_utils::{event, sol_usdc_metadata};
+
+    /// Two transactions: a taker buy sweeping three ask levels, the first with two makers,
+    /// then a limit sell that takes one bid and rests the remainder.
+    fn sweep() -> Vec<PhoenixEvent> {
+        let market = Pubkey::new_from_array([1; 32]);
+        let (maker_a, maker_b) = (
+            Pubkey::new_from_array([2; 32]),
+            Pubkey::new_from_array([3; 32]),
+        );
+        let taker = Pubkey::new_from_array([4; 32]);
+        let sequenced = |sequence_number, event_index, signature_byte, details| PhoenixEvent {
+            sequence_number,
+            slot: 250_000_000 + sequence_number,
+            timestamp: 1_700_000_000 + sequence_number as i64,
+            signature: Signature::from([signature_byte; 64]),
+            signer: taker,
+            event_index,
+            ..event(market, details)
+        };
+        let fill = |order_sequence_number, maker, price_in_ticks, base_lots| {
+            MarketEventDetails::Fill(Fill::new(
+                order_sequence_number,
+                maker,
+                taker,
+                price_in_ticks,
+                base_lots,
+                0,
+            ))
+        };
+        vec![
+            sequenced(10, 0, 5, fill(1, maker_a, 25_000, 100)),
+            sequenced(10, 1, 5, fill(2, maker_b, 25_000, 50)),
+            sequenced(10, 2, 5, fill(3, maker_a, 25_001, 200)),
+            sequenced(10, 3, 5, fill(4, maker_b, 25_003, 25)),
+            sequenced(
+                10,
+                4,
+                5,
+                MarketEventDetails::FillSummary(FillSummary {
+                    client_order_id: 0,
+                    total_base_filled: 375_000_000,
+                    total_quote_filled_including_fees: 9_375_425,
+                    total_quote_fees: 0,
+                    trade_direction: 1,
+                }),
+            ),
+            sequenced(11, 0, 6, fill(!5, maker_b, 24_990, 40)),
+            sequenced(
+                11,
+                1,
+                6,
+                MarketEventDetails::Place(Place {
+                    order_sequence_number: 6,
+                    client_order_id: 0,
+                    maker: taker,
+                    price_in_ticks: 24_990,
+                    base_lots_placed: 60,
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_sweep_golden() {
+        let events = sweep();
+        let market = events[0].market;
+        let trades = TradeTape::new()
+            .with_market(market, sol_usdc_metadata())
+            .trades(&events)
+            .map(|trade| {
+                (
+                    trade.price,
+                    trade.base_size,
+                    trade.taker_side,
+                    trade.timestamp,
+                    trade.slot,
+                    trade.signature.as_ref()[0],
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            trades,
+            [
+                (25.0, 0.15, Side::Bid, 1_700_000_010, 250_000_010, 5),
+                (25.001, 0.2, Side::Bid, 1_700_000_010, 250_000_010, 5),
+                (25.003, 0.025, Side::Bid, 1_700_000_010, 250_000_010, 5),
+                (24.99, 0.04, Side::Ask, 1_700_000_011, 250_000_011, 6),
+            ]
+        );
+
+        // Unmerged, the first level prints once per maker
+        let sizes = TradeTape::new()
+            .with_market(market, sol_usdc_metadata())
+            .with_merge_same_price(false)
+            .trades(&events)
+            .map(|trade| trade.base_size)
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [0.1, 0.05, 0.2, 0.025, 0.04]);
+
+        // Fills of markets that were not added are not printed
+        assert_eq!(TradeTape::new().trades(&events).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let events = sweep();
+        let mut tape = TradeTape::new().with_market(events[0].market, sol_usdc_metadata());
+        let (sender, receiver) = mpsc::channel(4);
+        let (trades, mut received) = mpsc::unbounded_channel();
+        // The batch ends mid-transaction, which flushes the print in progress
+        for batch in [&events[..1], &events[1..]] {
+            sender
+                .send(batch.iter().copied().map(SDKMarketEvent::from).collect())
+                .await
+                .unwrap();
+        }
+        drop(sender);
+        tape.run(receiver, trades).await;
+
+        let mut sizes = vec![];
+        while let Ok(trade) = received.try_recv() {
+            sizes.push(trade.base_size);
+        }
+        assert_eq!(sizes, [0.1, 0.05, 0.2, 0.025, 0.04]);
+    }
+}
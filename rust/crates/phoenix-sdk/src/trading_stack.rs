@@ -0,0 +1,481 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use phoenix_sdk_core::order_manager::OpenOrder;
+use phoenix_sdk_core::position_tracker::Position;
+use phoenix_sdk_core::sdk_client_core::MarketState;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::event_metrics::{EventMetrics, MetricsSink};
+use crate::event_poller::EventPoller;
+use crate::event_router::{Backpressure, EventRouter, RoutedReceiver};
+use crate::paper_trading::TradingMode;
+use crate::poll_schedule::PollScheduleConfig;
+use crate::risk_manager::{RiskConfig, RiskManager};
+use crate::runtime::PhoenixRuntime;
+use crate::sdk_client::SDKClient;
+use crate::sdk_market_event::SDKMarketEvent;
+use crate::task_supervisor::{TaskHealth, TaskOptions};
+
+pub const EVENT_POLLER_TASK: &str = "event_poller";
+pub const HEARTBEAT_TASK: &str = "heartbeat";
+pub const TRADING_STATE_TASK: &str = "trading_state";
+
+enum ClientSource {
+    Rpc { url: String, payer: Box<Keypair> },
+    Client(Box<SDKClient>),
+}
+
+/// Configures a `TradingStack`. See `TradingStack::builder`.
+pub struct TradingStackBuilder {
+    client: ClientSource,
+    markets: Vec<Pubkey>,
+    risk: RiskConfig,
+    paper: bool,
+    poll_schedule: PollScheduleConfig,
+    heartbeat_interval: Duration,
+    event_capacity: usize,
+    cancel_on_shutdown: bool,
+}
+
+impl TradingStackBuilder {
+    /// Adds a market to trade. At least one is required.
+    pub fn market(mut self, market: Pubkey) -> Self {
+        if !self.markets.contains(&market) {
+            self.markets.push(market);
+        }
+        self
+    }
+
+    /// Limits checked before every order is sent. Defaults to no limits, in which case the risk
+    /// manager only tracks orders and positions.
+    pub fn with_risk(mut self, config: RiskConfig) -> Self {
+        self.risk = config;
+        self
+    }
+
+    /// Simulates orders against local snapshots of the markets instead of sending them. See
+    /// `PaperExchange`.
+    pub fn with_paper_mode(mut self) -> Self {
+        self.paper = true;
+        self
+    }
+
+    /// How often the markets are polled for new transactions outside paper mode. Defaults to
+    /// every second.
+    pub fn with_poll_schedule(mut self, config: PollScheduleConfig) -> Self {
+        self.poll_schedule = config;
+        self
+    }
+
+    /// How often a `Heartbeat` is sent to the subscribers of each market. Defaults to every five
+    /// seconds.
+    pub fn with_heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    /// The buffer, in batches, of each event channel. Defaults to 1024.
+    pub fn with_event_capacity(mut self, capacity: usize) -> Self {
+        self.event_capacity = capacity.max(1);
+        self
+    }
+
+    /// Whether `TradingStack::shutdown` cancels the trader's orders on every market. Defaults to
+    /// true.
+    pub fn with_cancel_on_shutdown(mut self, cancel_on_shutdown: bool) -> Self {
+        self.cancel_on_shutdown = cancel_on_shutdown;
+        self
+    }
+
+    /// Connects, loads the markets' metadata and starts the components. In paper mode, markets
+    /// without a snapshot are fetched to trade against.
+    pub async fn build(self) -> Result<TradingStack> {
+        if self.markets.is_empty() {
+            bail!("A trading stack needs at least one market");
+        }
+        let mut sdk = match self.client {
+            ClientSource::Rpc { url, payer } => {
+                SDKClient::new_with_market_keys(self.markets.iter().collect(), &payer, &url).await?
+            }
+            ClientSource::Client(sdk) => *sdk,
+        };
+        for market in &self.markets {
+            sdk.add_market(*market).await?;
+        }
+        if self.paper {
+            sdk.set_trading_mode(TradingMode::Paper);
+        }
+        sdk.set_risk_manager(Some(RiskManager::new(sdk.get_trader(), self.risk)));
+        let sdk = Arc::new(sdk);
+        let runtime = PhoenixRuntime::new(sdk.clone())?;
+
+        let (event_sender, events) = mpsc::channel(self.event_capacity);
+        let metrics = Arc::new(EventMetrics::default());
+        let event_sink = Arc::new(MetricsSink::new(event_sender, metrics.clone()));
+        let paper = sdk.trading_mode == TradingMode::Paper;
+        if paper {
+            for market in &self.markets {
+                if !sdk.paper_exchange().has_market_state(market) {
+                    sdk.refresh_paper_market_state(market).await?;
+                }
+            }
+            sdk.paper_exchange()
+                .set_event_sender(Some(event_sink.clone()));
+        } else {
+            let poller = EventPoller::new(sdk.clone(), self.markets.clone())
+                .with_schedule(self.poll_schedule)
+                .with_metrics(metrics.clone());
+            let poller = Arc::new(Mutex::new(poller));
+            let poller_sink = event_sink.clone();
+            runtime
+                .supervisor()
+                .spawn_supervised(EVENT_POLLER_TASK, move || {
+                    let (poller, event_sink) = (poller.clone(), poller_sink.clone());
+                    async move { poller.lock().await.run(&event_sink).await }
+                })?;
+        }
+
+        let (task_sdk, task_metrics) = (sdk.clone(), metrics.clone());
+        let (markets, interval) = (self.markets.clone(), self.heartbeat_interval);
+        runtime
+            .supervisor()
+            .spawn_supervised(HEARTBEAT_TASK, move || {
+                let (sdk, metrics) = (task_sdk.clone(), task_metrics.clone());
+                let (markets, event_sink) = (markets.clone(), event_sink.clone());
+                async move {
+                    let heartbeats = metrics.send_heartbeats(&markets, &event_sink, interval);
+                    if paper {
+                        return heartbeats.await;
+                    }
+                    let client = &sdk.client;
+                    tokio::select! {
+                        result = heartbeats => result,
+                        _ = metrics.track_chain_slot(|| client.get_slot(), interval) => {
+                            unreachable!("Tracking the chain slot never finishes")
+                        }
+                    }
+                }
+            })?;
+
+        let (router_sender, router_events) = mpsc::channel(self.event_capacity);
+        let events = Arc::new(Mutex::new(events));
+        let task_sdk = sdk.clone();
+        runtime.supervisor().spawn_supervised_with(
+            TRADING_STATE_TASK,
+            TaskOptions::new()
+                .depends_on(EVENT_POLLER_TASK)
+                .depends_on(HEARTBEAT_TASK),
+            move || {
+                let (sdk, events) = (task_sdk.clone(), events.clone());
+                let router_sender = router_sender.clone();
+                async move {
+                    let mut events = events.lock_owned().await;
+                    while let Some(batch) = events.recv().await {
+                        apply_events(&sdk, &batch).await?;
+                        router_sender
+                            .send(batch)
+                            .await
+                            .map_err(|_| anyhow!("Event router stopped"))?;
+                    }
+                    Ok(())
+                }
+            },
+        )?;
+        let router = EventRouter::new(self.event_capacity);
+        runtime.spawn_event_router(
+            router.clone(),
+            router_events,
+            TaskOptions::new().depends_on(TRADING_STATE_TASK),
+        )?;
+
+        Ok(TradingStack {
+            runtime,
+            router,
+            metrics,
+            markets: self.markets,
+            cancel_on_shutdown: self.cancel_on_shutdown,
+        })
+    }
+}
+
+/// Applies the trader's events to the client's risk manager, which tracks their orders and
+/// positions.
+async fn apply_events(sdk: &SDKClient, batch: &[SDKMarketEvent]) -> Result<()> {
+    for event in batch.iter().filter_map(SDKMarketEvent::as_phoenix_event) {
+        let meta = sdk.get_market_metadata(&event.market).await?;
+        if let Some(risk_manager) = sdk.risk_manager().as_mut() {
+            risk_manager.apply_event(event, &meta);
+        }
+    }
+    Ok(())
+}
+
+/// A client with the components a trading app needs, wired together and supervised.
+///
+/// Events on the stack's markets flow from the source, the paper exchange or an `EventPoller`
+/// running as the `"event_poller"` task, through the `"trading_state"` task, which applies them to
+/// the client's `RiskManager`, to an `EventRouter` running as the `"event_router"` task. The risk
+/// manager tracks the trader's open orders and positions, and checks orders sent through the
+/// client against the stack's `RiskConfig`.
+///
+/// The `"heartbeat"` task sends a `Heartbeat` for each market through the same path on a fixed
+/// interval, so subscribers can tell a quiet market from a stalled stack. Outside paper mode it
+/// also tracks the chain slot, so heartbeats carry how far the poller lags behind the chain. The
+/// stack's `EventMetrics` count what the sources deliver.
+///
+/// ```no_run
+/// use phoenix_sdk::prelude::*;
+///
+/// # async fn run(market: Pubkey) -> anyhow::Result<()> {
+/// let stack = TradingStack::builder("https://api.devnet.solana.com", Keypair::new())
+///     .market(market)
+///     .with_paper_mode()
+///     .build()
+///     .await?;
+/// let mut events = stack.subscribe(market);
+/// // ... quote with `stack.send_ixs`, read `stack.open_orders` and `stack.position` ...
+/// # let _ = events.recv().await;
+/// stack.shutdown().await
+/// # }
+/// ```
+pub struct TradingStack {
+    runtime: PhoenixRuntime,
+    router: EventRouter,
+    metrics: Arc<EventMetrics>,
+    markets: Vec<Pubkey>,
+    cancel_on_shutdown: bool,
+}
+
+impl TradingStack {
+    /// A stack that connects to `rpc_url` and trades with `payer`.
+    pub fn builder(rpc_url: impl Into<String>, payer: Keypair) -> TradingStackBuilder {
+        Self::builder_with_client_source(ClientSource::Rpc {
+            url: rpc_url.into(),
+            payer: Box::new(payer),
+        })
+    }
+
+    /// A stack around an existing client, e.g. one with an `RpcPool` or rate limiter set. Its
+    /// risk manager is replaced.
+    pub fn builder_from_client(sdk: SDKClient) -> TradingStackBuilder {
+        Self::builder_with_client_source(ClientSource::Client(Box::new(sdk)))
+    }
+
+    fn builder_with_client_source(client: ClientSource) -> TradingStackBuilder {
+        TradingStackBuilder {
+            client,
+            markets: vec![],
+            risk: RiskConfig::default(),
+            paper: false,
+            poll_schedule: PollScheduleConfig::default(),
+            heartbeat_interval: Duration::from_secs(5),
+            event_capacity: 1024,
+            cancel_on_shutdown: true,
+        }
+    }
+
+    pub fn sdk(&self) -> &Arc<SDKClient> {
+        self.runtime.sdk()
+    }
+
+    /// The runtime the components run under, to supervise application tasks alongside them.
+    pub fn runtime(&self) -> &PhoenixRuntime {
+        &self.runtime
+    }
+
+    pub fn markets(&self) -> &[Pubkey] {
+        &self.markets
+    }
+
+    /// The router, to subscribe with other backpressure or to every market.
+    pub fn router(&self) -> &EventRouter {
+        &self.router
+    }
+
+    /// Counts the batches delivered by the stack's event source, and the chain slot it lags.
+    pub fn metrics(&self) -> &Arc<EventMetrics> {
+        &self.metrics
+    }
+
+    /// The events on `market`, after they were applied to the open orders and positions. A slow
+    /// receiver delays the other subscribers rather than missing batches.
+    pub fn subscribe(&self, market: Pubkey) -> RoutedReceiver {
+        self.router.subscribe(market, Backpressure::Wait)
+    }
+
+    /// Sends `ixs` through the client, which checks their orders against the risk limits.
+    pub async fn send_ixs(&self, ixs: Vec<Instruction>) -> Result<Signature> {
+        self.sdk().send_ixs(ixs).await
+    }
+
+    pub fn open_orders(&self, market: &Pubkey) -> Vec<OpenOrder> {
+        self.sdk()
+            .risk_manager()
+            .as_ref()
+            .map(|risk_manager| risk_manager.orders.open_orders(market).copied().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn position(&self, market: &Pubkey) -> Position {
+        self.sdk()
+            .risk_manager()
+            .as_ref()
+            .map(|risk_manager| risk_manager.positions.position(market))
+            .unwrap_or_default()
+    }
+
+    /// Fetches a market's state and sets the risk manager's mid price from its book. In paper
+    /// mode, the state also becomes the snapshot paper orders match against. A failed fetch is
+    /// an error and changes neither.
+    pub async fn refresh_market(&self, market: &Pubkey) -> Result<MarketState> {
+        let sdk = self.sdk();
+        let market_state = sdk.fetch_market_state(market).await?;
+        if sdk.trading_mode == TradingMode::Paper {
+            sdk.paper_exchange().set_market_state(market, &market_state);
+        }
+        if let Some(risk_manager) = sdk.risk_manager().as_mut() {
+            risk_manager.update_mid_price(market, &market_state.orderbook);
+        }
+        Ok(market_state)
+    }
+
+    pub fn health(&self) -> Vec<TaskHealth> {
+        self.runtime.health()
+    }
+
+    /// Cancels the trader's orders on every market of the stack, unless disabled with
+    /// `with_cancel_on_shutdown`, then stops the components, producers first. The components are
+    /// stopped even if a cancel fails, and the first failure is returned.
+    pub async fn shutdown(self) -> Result<()> {
+        let mut result = Ok(());
+        if self.cancel_on_shutdown {
+            for market in &self.markets {
+                let cancelled = match self.sdk().get_cancel_all_ix(market) {
+                    Ok(ix) => self
+                        .sdk()
+                        .send_ixs_with_risk_override(vec![ix], true)
+                        .await
+                        .map(|_| ()),
+                    Err(e) => Err(e),
+                };
+                if let (Ok(()), Err(e)) = (&result, cancelled) {
+                    result = Err(anyhow!("Failed to cancel orders on {}: {}", market, e));
+                }
+            }
+        }
+        self.runtime.shutdown().await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use phoenix::state::enums::Side;
+    use phoenix_sdk_core::market_event::MarketEventDetails;
+
+    use super::*;
+    use crate::paper_trading::test_utils::paper_client;
+    use crate::task_supervisor::TaskState;
+
+    #[tokio::test]
+    async fn test_paper_stack_tracks_orders_and_cancels_on_shutdown() {
+        let (sdk, market) = paper_client(&[(Side::Ask, 105, 100)]).await;
+        let stack = TradingStack::builder_from_client(sdk)
+            .market(market)
+            .with_paper_mode()
+            .with_event_capacity(16)
+            .build()
+            .await
+            .unwrap();
+        let mut events = stack.subscribe(market);
+
+        let ix = stack
+            .sdk()
+            .get_post_only_ix_from_tick_price(&market, 100, Side::Bid, 10, 7, false)
+            .unwrap();
+        stack.send_ixs(vec![ix]).await.unwrap();
+        let batch = loop {
+            let batch = tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if !batch.iter().all(SDKMarketEvent::is_heartbeat) {
+                break batch;
+            }
+        };
+        assert!(batch.iter().any(|event| matches!(
+            event.as_phoenix_event().map(|event| event.details),
+            Some(MarketEventDetails::Place(_))
+        )));
+        let open_orders = stack.open_orders(&market);
+        assert_eq!(open_orders.len(), 1);
+        assert_eq!(open_orders[0].client_order_id, 7);
+        assert_eq!(stack.position(&market).base_lots, 0);
+        assert!(stack
+            .health()
+            .iter()
+            .all(|task| task.state == TaskState::Running));
+
+        let sdk = stack.sdk().clone();
+        stack.shutdown().await.unwrap();
+        assert!(sdk.paper_exchange().open_orders(&market).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stack_sends_heartbeats() {
+        let (sdk, market) = paper_client(&[]).await;
+        let stack = TradingStack::builder_from_client(sdk)
+            .market(market)
+            .with_paper_mode()
+            .with_heartbeat_interval(Duration::from_millis(10))
+            .build()
+            .await
+            .unwrap();
+        let mut events = stack.subscribe(market);
+
+        for _ in 0..3 {
+            let batch = tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(matches!(
+                batch[..],
+                [SDKMarketEvent::Heartbeat { market: heartbeat_market, .. }] if heartbeat_market == market
+            ));
+        }
+        // Heartbeats are not transactions
+        assert_eq!(stack.metrics().snapshot().transactions_processed, 0);
+        stack.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_refresh_is_an_error() {
+        let (sdk, market) = paper_client(&[(Side::Ask, 105, 100)]).await;
+        let stack = TradingStack::builder_from_client(sdk)
+            .market(market)
+            .with_paper_mode()
+            .build()
+            .await
+            .unwrap();
+        // Nothing listens on the client's RPC address
+        assert!(stack.refresh_market(&market).await.is_err());
+        stack.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_requires_a_market() {
+        let (sdk, _) = paper_client(&[]).await;
+        assert!(TradingStack::builder_from_client(sdk)
+            .with_paper_mode()
+            .build()
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,637 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use phoenix::quantities::WrapperU64;
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk_core::market_event::{Fill, MarketEventDetails, PhoenixEvent};
+use phoenix_sdk_core::orderbook::Orderbook;
+use phoenix_sdk_core::sdk_client_core::{PhoenixOrder, SDKClientCore};
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+use crate::sdk_client::SDKClient;
+use crate::sdk_market_event::SDKMarketEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum TriggerCondition {
+    /// The most recent fill on the market traded at or below this price.
+    LastTradeAtOrBelow { price_in_ticks: u64 },
+    /// The most recent fill on the market traded at or above this price.
+    LastTradeAtOrAbove { price_in_ticks: u64 },
+    /// The best bid has been at or above this price after each of the last `consecutive_events`
+    /// events on the market.
+    BestBidAtOrAbove {
+        price_in_ticks: u64,
+        consecutive_events: u32,
+    },
+    /// The best ask has been at or below this price after each of the last `consecutive_events`
+    /// events on the market.
+    BestAskAtOrBelow {
+        price_in_ticks: u64,
+        consecutive_events: u32,
+    },
+}
+
+/// The order sent when a trigger fires. Prices are in ticks and sizes in base lots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum TriggerAction {
+    /// `price_in_ticks` is the limit price of the IOC order.
+    ImmediateOrCancel {
+        side: Side,
+        price_in_ticks: u64,
+        num_base_lots: u64,
+    },
+    PostOnly {
+        side: Side,
+        price_in_ticks: u64,
+        num_base_lots: u64,
+        client_order_id: u128,
+    },
+}
+
+impl TriggerAction {
+    pub fn to_instruction(&self, core: &SDKClientCore, market: &Pubkey) -> Result<Instruction> {
+        match *self {
+            TriggerAction::ImmediateOrCancel {
+                side,
+                price_in_ticks,
+                num_base_lots,
+            } => core.get_ioc_from_tick_price_generic_ix(
+                market,
+                price_in_ticks,
+                side,
+                num_base_lots,
+                None,
+                None,
+                None,
+            ),
+            TriggerAction::PostOnly {
+                side,
+                price_in_ticks,
+                num_base_lots,
+                client_order_id,
+            } => core.get_post_only_ix_from_tick_price(
+                market,
+                price_in_ticks,
+                side,
+                num_base_lots,
+                client_order_id,
+                false,
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct Trigger {
+    pub id: u64,
+    pub market: Pubkey,
+    pub condition: TriggerCondition,
+    pub action: TriggerAction,
+    /// Number of consecutive events the condition has held for, for the book conditions.
+    pub consecutive_events: u32,
+}
+
+/// Why a trigger fired: the price that satisfied the condition and the event after which it did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FireReason {
+    pub observed_price_in_ticks: u64,
+    /// For the book conditions, the number of consecutive events the condition held for.
+    pub consecutive_events: u32,
+    pub signature: Signature,
+    pub sequence_number: u64,
+    pub slot: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FiredTrigger {
+    pub trigger: Trigger,
+    pub reason: FireReason,
+}
+
+/// The outcome of sending a fired trigger's order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggerExecution {
+    pub fired: FiredTrigger,
+    pub result: Result<Signature, String>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+struct PersistedTriggers {
+    next_id: u64,
+    triggers: Vec<Trigger>,
+}
+
+/// Client-side conditional orders, e.g. stops, evaluated against a market event stream.
+///
+/// Each trigger fires at most once: it is removed (and the removal persisted) before its order is
+/// built, so a crash between firing and sending loses the order rather than sending it twice.
+/// Book conditions are evaluated against books maintained from the same stream, which start empty
+/// unless seeded with `set_orderbook`.
+#[derive(Debug, Default)]
+pub struct TriggerEngine {
+    triggers: BTreeMap<u64, Trigger>,
+    next_id: u64,
+    books: BTreeMap<Pubkey, Orderbook<FIFOOrderId, PhoenixOrder>>,
+    path: Option<PathBuf>,
+}
+
+impl TriggerEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an engine that saves its triggers to `path` after every change, loading any triggers
+    /// already saved there.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut engine = Self {
+            path: Some(path.clone()),
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        match File::open(&path) {
+            Ok(mut file) => {
+                file.read_to_end(&mut bytes)?;
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(engine),
+            Err(e) => return Err(e.into()),
+        }
+        let persisted = PersistedTriggers::try_from_slice(&bytes)
+            .map_err(|e| anyhow!("Failed to load triggers from {}: {}", path.display(), e))?;
+        engine.next_id = persisted.next_id;
+        engine.triggers = persisted
+            .triggers
+            .into_iter()
+            .map(|trigger| (trigger.id, trigger))
+            .collect();
+        Ok(engine)
+    }
+
+    /// Registers a trigger and returns its id.
+    pub fn add_trigger(
+        &mut self,
+        market: &Pubkey,
+        condition: TriggerCondition,
+        action: TriggerAction,
+    ) -> Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.triggers.insert(
+            id,
+            Trigger {
+                id,
+                market: *market,
+                condition,
+                action,
+                consecutive_events: 0,
+            },
+        );
+        self.save()?;
+        Ok(id)
+    }
+
+    /// Removes a trigger, returning it if it had not fired or been cancelled already.
+    pub fn cancel_trigger(&mut self, id: u64) -> Result<Option<Trigger>> {
+        let trigger = self.triggers.remove(&id);
+        if trigger.is_some() {
+            self.save()?;
+        }
+        Ok(trigger)
+    }
+
+    pub fn triggers(&self) -> impl Iterator<Item = &Trigger> {
+        self.triggers.values()
+    }
+
+    /// Seeds the book that book conditions on `market` are evaluated against.
+    pub fn set_orderbook(
+        &mut self,
+        market: &Pubkey,
+        orderbook: Orderbook<FIFOOrderId, PhoenixOrder>,
+    ) {
+        self.books.insert(*market, orderbook);
+    }
+
+    /// Applies an event and returns the triggers it fired, in id order.
+    pub fn on_event(&mut self, event: &PhoenixEvent) -> Result<Vec<FiredTrigger>> {
+        let book = self.books.entry(event.market).or_default();
+        book.apply_event(event);
+        let best_bid = book.bids.keys().next().map(|id| id.price_in_ticks.as_u64());
+        let best_ask = book.asks.keys().next().map(|id| id.price_in_ticks.as_u64());
+        let last_trade = match event.details {
+            MarketEventDetails::Fill(Fill { price_in_ticks, .. }) => Some(price_in_ticks),
+            _ => None,
+        };
+
+        let mut fired = vec![];
+        for trigger in self.triggers.values_mut() {
+            if trigger.market != event.market {
+                continue;
+            }
+            let observed = match trigger.condition {
+                TriggerCondition::LastTradeAtOrBelow { price_in_ticks } => {
+                    last_trade.filter(|&price| price <= price_in_ticks)
+                }
+                TriggerCondition::LastTradeAtOrAbove { price_in_ticks } => {
+                    last_trade.filter(|&price| price >= price_in_ticks)
+                }
+                TriggerCondition::BestBidAtOrAbove {
+                    price_in_ticks,
+                    consecutive_events,
+                } => count_consecutive(
+                    trigger,
+                    best_bid.filter(|&price| price >= price_in_ticks),
+                    consecutive_events,
+                ),
+                TriggerCondition::BestAskAtOrBelow {
+                    price_in_ticks,
+                    consecutive_events,
+                } => count_consecutive(
+                    trigger,
+                    best_ask.filter(|&price| price <= price_in_ticks),
+                    consecutive_events,
+                ),
+            };
+            if let Some(observed_price_in_ticks) = observed {
+                fired.push(FiredTrigger {
+                    trigger: *trigger,
+                    reason: FireReason {
+                        observed_price_in_ticks,
+                        consecutive_events: trigger.consecutive_events,
+                        signature: event.signature,
+                        sequence_number: event.sequence_number,
+                        slot: event.slot,
+                    },
+                });
+            }
+        }
+
+        for fired_trigger in fired.iter() {
+            self.triggers.remove(&fired_trigger.trigger.id);
+        }
+        if !fired.is_empty() {
+            self.save()?;
+        }
+        Ok(fired)
+    }
+
+    /// Rollbacks are ignored: triggers that already fired stay fired.
+    pub fn on_events(&mut self, events: &[SDKMarketEvent]) -> Result<Vec<FiredTrigger>> {
+        let mut fired = vec![];
+        for message in events {
+            if let SDKMarketEvent::PhoenixEvent { event } = message {
+                fired.extend(self.on_event(event)?);
+            }
+        }
+        Ok(fired)
+    }
+
+    /// Evaluates triggers against `events` until the channel closes, sending each fired trigger's
+    /// order with `SDKClient::send_ixs` and reporting the outcome on `executions`, if given.
+    /// A failed send is reported rather than retried.
+    pub async fn run(
+        &mut self,
+        client: &SDKClient,
+        mut events: Receiver<Vec<SDKMarketEvent>>,
+        executions: Option<Sender<TriggerExecution>>,
+    ) -> Result<()> {
+        while let Some(batch) = events.recv().await {
+            self.execute(client, &batch, executions.as_ref()).await?;
+        }
+        Ok(())
+    }
+
+    /// Evaluates triggers against one batch and sends the fired orders, as `run` does.
+    pub async fn execute(
+        &mut self,
+        client: &SDKClient,
+        batch: &[SDKMarketEvent],
+        executions: Option<&Sender<TriggerExecution>>,
+    ) -> Result<()> {
+        for fired in self.on_events(batch)? {
+            let result = match fired
+                .trigger
+                .action
+                .to_instruction(&client.core, &fired.trigger.market)
+            {
+                Ok(ix) => client.send_ixs(vec![ix]).await,
+                Err(e) => Err(e),
+            };
+            if let Some(executions) = executions {
+                executions
+                    .send(TriggerExecution {
+                        fired,
+                        result: result.map_err(|e| e.to_string()),
+                    })
+                    .await
+                    .map_err(|_| anyhow!("Trigger execution receiver dropped"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes to a temporary file first so that a crash mid-write leaves the previous state intact.
+    fn save(&self) -> Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let persisted = PersistedTriggers {
+            next_id: self.next_id,
+            triggers: self.triggers.values().copied().collect(),
+        };
+        let tmp_path = path.with_extension("tmp");
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&persisted.try_to_vec()?)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// Updates the trigger's run of consecutive events satisfying a book condition, returning the
+/// observed price once the run is long enough.
+fn count_consecutive(
+    trigger: &mut Trigger,
+    observed_price_in_ticks: Option<u64>,
+    required_events: u32,
+) -> Option<u64> {
+    match observed_price_in_ticks {
+        Some(price) => {
+            trigger.consecutive_events += 1;
+            (trigger.consecutive_events >= required_events).then_some(price)
+        }
+        None => {
+            trigger.consecutive_events = 0;
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_recorder::{EventRecorder, EventReplayer, ReplaySpeed};
+    use crate::paper_trading::test_utils::paper_client;
+    use phoenix_sdk_core::market_event::Place;
+    use phoenix_sdk_core::test_utils::event;
+    use tokio::sync::mpsc::channel;
+
+    /// An event whose slot is its sequence number
+    fn sequenced(
+        market: Pubkey,
+        sequence_number: u64,
+        details: MarketEventDetails,
+    ) -> PhoenixEvent {
+        PhoenixEvent {
+            sequence_number,
+            slot: sequence_number,
+            ..event(market, details)
+        }
+    }
+
+    fn place_bid(market: Pubkey, sequence_number: u64, price_in_ticks: u64) -> PhoenixEvent {
+        sequenced(
+            market,
+            sequence_number,
+            MarketEventDetails::Place(Place {
+                order_sequence_number: !sequence_number,
+                client_order_id: 0,
+                maker: Pubkey::new_unique(),
+                price_in_ticks,
+                base_lots_placed: 10,
+            }),
+        )
+    }
+
+    fn fill(market: Pubkey, sequence_number: u64, price_in_ticks: u64) -> PhoenixEvent {
+        sequenced(
+            market,
+            sequence_number,
+            MarketEventDetails::Fill(Fill {
+                order_sequence_number: sequence_number,
+                maker: Pubkey::new_unique(),
+                taker: Pubkey::new_unique(),
+                price_in_ticks,
+                base_lots_filled: 1,
+                base_lots_remaining: 0,
+                side_filled: Side::Ask,
+                maker_side: Side::Ask,
+                taker_side: Side::Ask.opposite(),
+                is_full_fill: true,
+            }),
+        )
+    }
+
+    /// Records one event per batch to an in-memory event file.
+    fn event_file(events: &[PhoenixEvent]) -> Vec<u8> {
+        let mut recorder = EventRecorder::new(Vec::new());
+        for (i, event) in events.iter().enumerate() {
+            recorder
+                .record_with_timestamp(&[(*event).into()], i as u64)
+                .unwrap();
+        }
+        recorder.into_inner()
+    }
+
+    fn stop_sell() -> TriggerAction {
+        TriggerAction::ImmediateOrCancel {
+            side: Side::Ask,
+            price_in_ticks: 90,
+            num_base_lots: 5,
+        }
+    }
+
+    #[test]
+    fn test_triggers_from_replayed_file() {
+        let market = Pubkey::new_unique();
+        let file = event_file(&[
+            fill(market, 1, 100),
+            place_bid(market, 2, 105),
+            fill(Pubkey::new_unique(), 3, 50),
+            place_bid(market, 4, 104),
+            fill(market, 5, 95),
+            fill(market, 6, 94),
+        ]);
+
+        let mut engine = TriggerEngine::new();
+        let stop = engine
+            .add_trigger(
+                &market,
+                TriggerCondition::LastTradeAtOrBelow { price_in_ticks: 95 },
+                stop_sell(),
+            )
+            .unwrap();
+        let bid_trigger = engine
+            .add_trigger(
+                &market,
+                TriggerCondition::BestBidAtOrAbove {
+                    price_in_ticks: 105,
+                    consecutive_events: 2,
+                },
+                TriggerAction::PostOnly {
+                    side: Side::Ask,
+                    price_in_ticks: 110,
+                    num_base_lots: 5,
+                    client_order_id: 7,
+                },
+            )
+            .unwrap();
+        let cancelled = engine
+            .add_trigger(
+                &market,
+                TriggerCondition::LastTradeAtOrAbove { price_in_ticks: 0 },
+                stop_sell(),
+            )
+            .unwrap();
+        assert!(engine.cancel_trigger(cancelled).unwrap().is_some());
+
+        let mut fired = vec![];
+        for batch in EventReplayer::new(std::io::Cursor::new(file)) {
+            fired.extend(
+                engine
+                    .on_events(&batch.unwrap().to_sdk_market_events())
+                    .unwrap(),
+            );
+        }
+
+        // Only events on the trigger's market count: the bid at 105 is best after the place at 105
+        // and again after the place at 104.
+        assert_eq!(fired.len(), 2);
+        assert_eq!(fired[0].trigger.id, bid_trigger);
+        assert_eq!(fired[0].reason.observed_price_in_ticks, 105);
+        assert_eq!(fired[0].reason.consecutive_events, 2);
+        assert_eq!(fired[0].reason.sequence_number, 4);
+        // The stop fires on the first trade at or below 95 and never again
+        assert_eq!(fired[1].trigger.id, stop);
+        assert_eq!(fired[1].reason.observed_price_in_ticks, 95);
+        assert_eq!(fired[1].reason.sequence_number, 5);
+        assert_eq!(engine.triggers().count(), 0);
+    }
+
+    #[test]
+    fn test_consecutive_run_resets() {
+        let market = Pubkey::new_unique();
+        let mut engine = TriggerEngine::new();
+        engine
+            .add_trigger(
+                &market,
+                TriggerCondition::BestBidAtOrAbove {
+                    price_in_ticks: 100,
+                    consecutive_events: 2,
+                },
+                stop_sell(),
+            )
+            .unwrap();
+        let cancel_bid = sequenced(
+            market,
+            2,
+            MarketEventDetails::Reduce(phoenix_sdk_core::market_event::Reduce {
+                order_sequence_number: !1,
+                maker: Pubkey::new_unique(),
+                price_in_ticks: 100,
+                base_lots_removed: 10,
+                base_lots_remaining: 0,
+                is_full_cancel: true,
+            }),
+        );
+        assert!(engine
+            .on_event(&place_bid(market, 1, 100))
+            .unwrap()
+            .is_empty());
+        assert!(engine.on_event(&cancel_bid).unwrap().is_empty());
+        assert!(engine
+            .on_event(&place_bid(market, 3, 101))
+            .unwrap()
+            .is_empty());
+        assert_eq!(engine.on_event(&fill(market, 4, 101)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_triggers_persist_across_restart() {
+        let path =
+            std::env::temp_dir().join(format!("phoenix-triggers-{}.bin", Pubkey::new_unique()));
+        let market = Pubkey::new_unique();
+        let condition = TriggerCondition::LastTradeAtOrBelow { price_in_ticks: 95 };
+        {
+            let mut engine = TriggerEngine::open(&path).unwrap();
+            engine.add_trigger(&market, condition, stop_sell()).unwrap();
+            let cancelled = engine.add_trigger(&market, condition, stop_sell()).unwrap();
+            engine.add_trigger(&market, condition, stop_sell()).unwrap();
+            engine.cancel_trigger(cancelled).unwrap();
+        }
+
+        let mut engine = TriggerEngine::open(&path).unwrap();
+        assert_eq!(
+            engine
+                .triggers()
+                .map(|trigger| trigger.id)
+                .collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert_eq!(engine.on_event(&fill(market, 1, 90)).unwrap().len(), 2);
+        // New ids continue after the restored ones, and fired triggers stay gone
+        assert_eq!(
+            engine.add_trigger(&market, condition, stop_sell()).unwrap(),
+            3
+        );
+        drop(engine);
+        let engine = TriggerEngine::open(&path).unwrap();
+        assert_eq!(
+            engine
+                .triggers()
+                .map(|trigger| trigger.id)
+                .collect::<Vec<_>>(),
+            vec![3]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_sends_through_paper_exchange() {
+        let (sdk, market) = paper_client(&[(Side::Bid, 92, 3), (Side::Bid, 91, 10)]).await;
+        let file = event_file(&[fill(market, 1, 100), fill(market, 2, 94)]);
+        let mut engine = TriggerEngine::new();
+        engine
+            .add_trigger(
+                &market,
+                TriggerCondition::LastTradeAtOrBelow { price_in_ticks: 95 },
+                stop_sell(),
+            )
+            .unwrap();
+
+        let (event_sender, event_receiver) = channel(16);
+        EventReplayer::new(std::io::Cursor::new(file))
+            .replay(event_sender, ReplaySpeed::AsFastAsPossible)
+            .await
+            .unwrap();
+        let (execution_sender, mut execution_receiver) = channel(16);
+        engine
+            .run(&sdk, event_receiver, Some(execution_sender))
+            .await
+            .unwrap();
+
+        let execution = execution_receiver.recv().await.unwrap();
+        assert_eq!(execution.fired.reason.observed_price_in_ticks, 94);
+        let fills = sdk.parse_fills(&execution.result.unwrap()).await;
+        // The stop sells 5 lots down to its limit of 90
+        assert_eq!(
+            fills
+                .iter()
+                .map(|event| match event.details {
+                    MarketEventDetails::Fill(fill) => (fill.price_in_ticks, fill.base_lots_filled),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>(),
+            vec![(92, 3), (91, 2)]
+        );
+        assert!(execution_receiver.recv().await.is_none());
+    }
+}
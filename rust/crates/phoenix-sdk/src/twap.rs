@@ -0,0 +1,484 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use phoenix::state::enums::Side;
+use phoenix_sdk_core::market_event::{Fill, MarketEventDetails, PhoenixEvent};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio::sync::mpsc::Receiver;
+use tokio::time::{sleep_until, Instant};
+
+use crate::sdk_client::SDKClient;
+use crate::sdk_market_event::{ControlMsg, SDKMarketEvent};
+
+/// The parent order a `TwapExecutor` works.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TwapSpec {
+    pub market: Pubkey,
+    pub side: Side,
+    /// Total size in Phoenix base units, not raw base units. It is rounded down to whole base lots.
+    pub size_in_base_units: f64,
+    /// No child order trades through this price.
+    pub limit_price_in_ticks: u64,
+    /// Time from the first child order to the deadline.
+    pub duration: Duration,
+    pub num_slices: u32,
+    /// If set, the executor's cumulative fills never exceed this fraction of the volume traded by
+    /// others on the market since it started, as observed on the event channel.
+    pub max_participation: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TwapStatus {
+    Running,
+    Paused,
+    /// The full size was filled.
+    Completed,
+    /// The deadline passed with size left unfilled, e.g. because of the limit price or the
+    /// participation cap.
+    Underfilled,
+    Cancelled,
+    /// A child order could not be built or sent. Fills from earlier children are kept.
+    Failed(String),
+}
+
+impl TwapStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, TwapStatus::Running | TwapStatus::Paused)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwapProgress {
+    pub status: TwapStatus,
+    pub total_base_lots: u64,
+    pub filled_base_lots: u64,
+    /// Sum of `price_in_ticks * base_lots_filled` over all fills.
+    pub filled_tick_lots: u128,
+    pub child_orders_sent: u32,
+}
+
+impl TwapProgress {
+    pub fn remaining_base_lots(&self) -> u64 {
+        self.total_base_lots - self.filled_base_lots
+    }
+
+    /// Volume-weighted average fill price in ticks, or `None` before the first fill.
+    pub fn avg_price_in_ticks(&self) -> Option<f64> {
+        if self.filled_base_lots == 0 {
+            return None;
+        }
+        Some(self.filled_tick_lots as f64 / self.filled_base_lots as f64)
+    }
+}
+
+#[derive(Debug)]
+struct TwapShared {
+    progress: TwapProgress,
+    paused: bool,
+    cancelled: bool,
+}
+
+/// Controls a running `TwapExecutor` and reads its progress. Pause and cancel take effect at the
+/// next slice boundary; an order already in flight is not recalled.
+#[derive(Debug, Clone)]
+pub struct TwapHandle {
+    shared: Arc<Mutex<TwapShared>>,
+}
+
+impl TwapHandle {
+    fn lock(&self) -> MutexGuard<'_, TwapShared> {
+        self.shared
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn progress(&self) -> TwapProgress {
+        self.lock().progress.clone()
+    }
+
+    pub fn status(&self) -> TwapStatus {
+        self.lock().progress.status.clone()
+    }
+
+    /// Base lots filled so far.
+    pub fn filled(&self) -> u64 {
+        self.lock().progress.filled_base_lots
+    }
+
+    /// Base lots left to fill.
+    pub fn remaining(&self) -> u64 {
+        self.lock().progress.remaining_base_lots()
+    }
+
+    /// Average fill price in ticks.
+    pub fn avg_price(&self) -> Option<f64> {
+        self.lock().progress.avg_price_in_ticks()
+    }
+
+    /// Slices that come due while paused are skipped. Since slice targets are cumulative, the
+    /// first slice after resuming catches up on the skipped size.
+    pub fn pause(&self) {
+        let mut shared = self.lock();
+        shared.paused = true;
+        if shared.progress.status == TwapStatus::Running {
+            shared.progress.status = TwapStatus::Paused;
+        }
+    }
+
+    pub fn resume(&self) {
+        let mut shared = self.lock();
+        shared.paused = false;
+        if shared.progress.status == TwapStatus::Paused {
+            shared.progress.status = TwapStatus::Running;
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.lock().cancelled = true;
+    }
+}
+
+/// Works a parent order by sending IOC child orders at evenly spaced intervals.
+///
+/// Child `k` (counting from 1) of `n` is sized to bring cumulative fills up to `k / n` of the total,
+/// so size left unfilled by one child rolls into the next. The first child is sent immediately and
+/// the last at `duration * (n - 1) / n`. Fills are read back from each child's transaction; the
+/// event channel is only used to observe other traders' volume for the participation cap.
+pub struct TwapExecutor {
+    spec: TwapSpec,
+    handle: TwapHandle,
+    observed_volume_base_lots: u64,
+    sent_signatures: HashSet<Signature>,
+}
+
+impl TwapExecutor {
+    pub fn new(client: &SDKClient, spec: TwapSpec) -> Result<(Self, TwapHandle)> {
+        let meta = client.get_market_metadata_from_cache(&spec.market)?;
+        if spec.num_slices == 0 {
+            bail!("A TWAP needs at least one slice");
+        }
+        if let Some(max_participation) = spec.max_participation {
+            if !(max_participation > 0.0 && max_participation <= 1.0) {
+                bail!(
+                    "Participation cap must be in (0, 1], got {}",
+                    max_participation
+                );
+            }
+        }
+        let total_base_lots =
+            (spec.size_in_base_units * meta.num_base_lots_per_base_unit as f64).floor() as u64;
+        if total_base_lots == 0 {
+            bail!(
+                "Size of {} base units is less than one base lot",
+                spec.size_in_base_units
+            );
+        }
+
+        let handle = TwapHandle {
+            shared: Arc::new(Mutex::new(TwapShared {
+                progress: TwapProgress {
+                    status: TwapStatus::Running,
+                    total_base_lots,
+                    filled_base_lots: 0,
+                    filled_tick_lots: 0,
+                    child_orders_sent: 0,
+                },
+                paused: false,
+                cancelled: false,
+            })),
+        };
+        Ok((
+            Self {
+                spec,
+                handle: handle.clone(),
+                observed_volume_base_lots: 0,
+                sent_signatures: HashSet::new(),
+            },
+            handle,
+        ))
+    }
+
+    /// Runs the schedule to completion and returns the final progress.
+    pub async fn run(
+        mut self,
+        client: &SDKClient,
+        events: &mut Receiver<Vec<SDKMarketEvent>>,
+    ) -> TwapProgress {
+        let start = Instant::now();
+        let interval = self.spec.duration / self.spec.num_slices;
+        for slice in 0..self.spec.num_slices {
+            sleep_until(start + interval * slice).await;
+            self.observe_events(events);
+
+            let (paused, cancelled, filled, total) = {
+                let shared = self.handle.lock();
+                (
+                    shared.paused,
+                    shared.cancelled,
+                    shared.progress.filled_base_lots,
+                    shared.progress.total_base_lots,
+                )
+            };
+            if cancelled {
+                return self.finish(TwapStatus::Cancelled);
+            }
+            if paused {
+                continue;
+            }
+
+            let child_base_lots = self.slice_target(slice + 1, total).saturating_sub(filled);
+            if child_base_lots == 0 {
+                continue;
+            }
+            if let Err(e) = self.send_child(client, child_base_lots).await {
+                return self.finish(TwapStatus::Failed(e.to_string()));
+            }
+            if self.handle.filled() == total {
+                return self.finish(TwapStatus::Completed);
+            }
+        }
+
+        sleep_until(start + self.spec.duration).await;
+        if self.handle.lock().cancelled {
+            return self.finish(TwapStatus::Cancelled);
+        }
+        if self.handle.remaining() == 0 {
+            self.finish(TwapStatus::Completed)
+        } else {
+            self.finish(TwapStatus::Underfilled)
+        }
+    }
+
+    /// Cumulative size the schedule wants filled after `slices_elapsed` slices.
+    fn slice_target(&self, slices_elapsed: u32, total_base_lots: u64) -> u64 {
+        let scheduled = (total_base_lots as u128 * slices_elapsed as u128
+            / self.spec.num_slices as u128) as u64;
+        match self.spec.max_participation {
+            Some(max_participation) => scheduled
+                .min((self.observed_volume_base_lots as f64 * max_participation).floor() as u64),
+            None => scheduled,
+        }
+    }
+
+    async fn send_child(&mut self, client: &SDKClient, num_base_lots: u64) -> Result<()> {
+        let ix = client.get_ioc_from_tick_price_generic_ix(
+            &self.spec.market,
+            self.spec.limit_price_in_ticks,
+            self.spec.side,
+            num_base_lots,
+            None,
+            None,
+            None,
+        )?;
+        let signature = client.send_ixs(vec![ix]).await?;
+        self.sent_signatures.insert(signature);
+        let fills = client.parse_fills(&signature).await;
+
+        let mut shared = self.handle.lock();
+        shared.progress.child_orders_sent += 1;
+        for fill in fills
+            .iter()
+            .filter_map(|event| self.own_fill(event, &client.trader))
+        {
+            shared.progress.filled_base_lots += fill.base_lots_filled;
+            shared.progress.filled_tick_lots +=
+                fill.price_in_ticks as u128 * fill.base_lots_filled as u128;
+        }
+        Ok(())
+    }
+
+    fn own_fill(&self, event: &PhoenixEvent, trader: &Pubkey) -> Option<Fill> {
+        match event.details {
+            MarketEventDetails::Fill(fill)
+                if event.market == self.spec.market && fill.taker == *trader =>
+            {
+                Some(fill)
+            }
+            _ => None,
+        }
+    }
+
+    /// Drains the channel without waiting, adding fills on the market that are not ours to the
+    /// observed volume.
+    fn observe_events(&mut self, events: &mut Receiver<Vec<SDKMarketEvent>>) {
+        while let Ok(batch) = events.try_recv() {
+            for message in batch {
+                match message {
+                    SDKMarketEvent::PhoenixEvent { event } => {
+                        self.observed_volume_base_lots += self.observed_fill_volume(&event);
+                    }
+                    SDKMarketEvent::Control(ControlMsg::Rollback { events, .. }) => {
+                        for event in events.iter() {
+                            self.observed_volume_base_lots = self
+                                .observed_volume_base_lots
+                                .saturating_sub(self.observed_fill_volume(event));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Base lots filled by `event` that count towards the observed volume.
+    fn observed_fill_volume(&self, event: &PhoenixEvent) -> u64 {
+        match event.details {
+            MarketEventDetails::Fill(fill)
+                if event.market == self.spec.market
+                    && !self.sent_signatures.contains(&event.signature) =>
+            {
+                fill.base_lots_filled
+            }
+            _ => 0,
+        }
+    }
+
+    fn finish(self, status: TwapStatus) -> TwapProgress {
+        let mut shared = self.handle.lock();
+        shared.progress.status = status;
+        shared.progress.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::paper_trading::test_utils::paper_client;
+    use crate::paper_trading::TradingMode;
+    use phoenix_sdk_core::test_utils::event;
+    use tokio::sync::mpsc::channel;
+
+    fn spec(market: Pubkey) -> TwapSpec {
+        TwapSpec {
+            market,
+            side: Side::Bid,
+            size_in_base_units: 0.25,
+            limit_price_in_ticks: 103,
+            duration: Duration::from_millis(100),
+            num_slices: 5,
+            max_participation: None,
+        }
+    }
+
+    fn others_fill(market: Pubkey, base_lots_filled: u64) -> SDKMarketEvent {
+        PhoenixEvent {
+            signature: Signature::new_unique(),
+            signer: Pubkey::new_unique(),
+            ..event(
+                market,
+                MarketEventDetails::Fill(Fill {
+                    order_sequence_number: 0,
+                    maker: Pubkey::new_unique(),
+                    taker: Pubkey::new_unique(),
+                    price_in_ticks: 101,
+                    base_lots_filled,
+                    base_lots_remaining: 0,
+                    side_filled: Side::Ask,
+                    maker_side: Side::Ask,
+                    taker_side: Side::Ask.opposite(),
+                    is_full_fill: false,
+                }),
+            )
+        }
+        .into()
+    }
+
+    #[tokio::test]
+    async fn test_schedule_completes() {
+        let (sdk, market) = paper_client(&[
+            (Side::Ask, 101, 100),
+            (Side::Ask, 102, 100),
+            (Side::Ask, 103, 100),
+        ])
+        .await;
+        let (tx, mut rx) = channel(16);
+        sdk.paper_exchange().set_event_sender(Some(tx));
+
+        let (executor, handle) = TwapExecutor::new(&sdk, spec(market)).unwrap();
+        assert_eq!(handle.remaining(), 250);
+        let start = Instant::now();
+        let progress = executor.run(&sdk, &mut rx).await;
+
+        // Five children of 50 lots, the last one sent 80ms in
+        assert!(start.elapsed() >= Duration::from_millis(80));
+        assert_eq!(progress.status, TwapStatus::Completed);
+        assert_eq!(progress.child_orders_sent, 5);
+        assert_eq!(handle.filled(), 250);
+        assert_eq!(handle.remaining(), 0);
+        let expected = (100 * 101 + 100 * 102 + 50 * 103) as f64 / 250.0;
+        assert!((handle.avg_price().unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_participation_cap_and_limit_price() {
+        let (sdk, market) = paper_client(&[(Side::Ask, 101, 1000), (Side::Ask, 110, 1000)]).await;
+        let (tx, mut rx) = channel(16);
+        // Others trade 500 lots on our market before we start, and more on another one
+        tx.send(vec![
+            others_fill(market, 300),
+            others_fill(market, 200),
+            others_fill(Pubkey::new_unique(), 10_000),
+        ])
+        .await
+        .unwrap();
+        sdk.paper_exchange().set_event_sender(Some(tx));
+
+        let (executor, _) = TwapExecutor::new(
+            &sdk,
+            TwapSpec {
+                max_participation: Some(0.1),
+                ..spec(market)
+            },
+        )
+        .unwrap();
+        let progress = executor.run(&sdk, &mut rx).await;
+
+        // 10% of 500 lots caps the fills at 50, reached after the first child. Our own fills on the
+        // channel do not raise the cap.
+        assert_eq!(progress.status, TwapStatus::Underfilled);
+        assert_eq!(progress.filled_base_lots, 50);
+        assert_eq!(progress.child_orders_sent, 1);
+        assert_eq!(progress.remaining_base_lots(), 200);
+
+        // Without the cap, nothing trades through the limit price
+        let (sdk, market) = paper_client(&[(Side::Ask, 101, 100), (Side::Ask, 110, 1000)]).await;
+        let (_tx, mut rx) = channel(16);
+        let (executor, _) = TwapExecutor::new(&sdk, spec(market)).unwrap();
+        let progress = executor.run(&sdk, &mut rx).await;
+        assert_eq!(progress.status, TwapStatus::Underfilled);
+        assert_eq!(progress.filled_base_lots, 100);
+        assert_eq!(progress.avg_price_in_ticks(), Some(101.0));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_and_failure() {
+        let (sdk, market) = paper_client(&[(Side::Ask, 101, 1000)]).await;
+        let (_tx, mut rx) = channel(16);
+        let (executor, handle) = TwapExecutor::new(&sdk, spec(market)).unwrap();
+        handle.cancel();
+        let progress = executor.run(&sdk, &mut rx).await;
+        assert_eq!(progress.status, TwapStatus::Cancelled);
+        assert_eq!(progress.child_orders_sent, 0);
+
+        // Live sends go to an RPC node that is unreachable here
+        let (mut sdk, market) = paper_client(&[(Side::Ask, 101, 1000)]).await;
+        sdk.set_trading_mode(TradingMode::Live);
+        let (executor, handle) = TwapExecutor::new(&sdk, spec(market)).unwrap();
+        let progress = executor.run(&sdk, &mut rx).await;
+        assert!(matches!(progress.status, TwapStatus::Failed(_)));
+        assert!(handle.status().is_finished());
+        assert_eq!(handle.filled(), 0);
+
+        assert!(TwapExecutor::new(
+            &sdk,
+            TwapSpec {
+                size_in_base_units: 0.0001,
+                ..spec(market)
+            }
+        )
+        .is_err());
+    }
+}
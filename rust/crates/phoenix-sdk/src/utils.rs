@@ -1,12 +1,9 @@
 use std::{collections::BTreeMap, mem::size_of};
 
 use ellipsis_client::EllipsisClient;
-use phoenix::program::{
-    dispatch_market, get_seat_address, status::SeatApprovalStatus, MarketHeader, Seat,
-};
-use phoenix_sdk_core::ata_utils::{
-    get_associated_token_address, create_associated_token_account,
-};
+use phoenix::program::{get_seat_address, status::SeatApprovalStatus, MarketHeader, Seat};
+use phoenix_sdk_core::ata_utils::{create_associated_token_account, get_associated_token_address};
+use phoenix_sdk_core::sdk_client_core::load_market;
 use phoenix_seat_manager::{
     get_seat_manager_address,
     instruction_builders::{
@@ -81,21 +78,19 @@ pub async fn get_evictable_trader_ix(
         .map_err(|e| anyhow::anyhow!("Error deserializing market header. Error: {:?}", e))?;
 
     let max_traders = market_header.market_size_params.num_seats;
-    let num_traders =
-        dispatch_market::load_with_dispatch(&market_header.market_size_params, market_bytes)?
-            .inner
-            .get_registered_traders()
-            .len() as u64;
+    let num_traders = load_market(&market_header.market_size_params, market_bytes)?
+        .inner
+        .get_registered_traders()
+        .len() as u64;
 
     // If the market's trader state is full, evict a trader to make room for a new trader.
     if num_traders == max_traders {
-        let trader_tree =
-            dispatch_market::load_with_dispatch(&market_header.market_size_params, market_bytes)?
-                .inner
-                .get_registered_traders()
-                .iter()
-                .map(|(k, v)| (*k, *v))
-                .collect::<BTreeMap<_, _>>();
+        let trader_tree = load_market(&market_header.market_size_params, market_bytes)?
+            .inner
+            .get_registered_traders()
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .collect::<BTreeMap<_, _>>();
 
         let seat_manager_address = get_seat_manager_address(market_pubkey).0;
         let seat_manager_account = client.get_account_data(&seat_manager_address).await?;
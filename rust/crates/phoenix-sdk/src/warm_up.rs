@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use phoenix::program::status::SeatApprovalStatus;
+use phoenix_sdk_core::sdk_client_core::{MarketMetadata, MarketSnapshot};
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::sdk_client::WalletBalances;
+
+/// How long state prefetched by `SDKClient::warm_up` is served by the getters. Metadata never
+/// changes and is kept for good.
+pub const WARMED_STATE_MAX_AGE: Duration = Duration::from_secs(10);
+
+/// One piece of state `SDKClient::warm_up` prefetches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WarmupTarget {
+    Slot,
+    Blockhash,
+    Metadata(Pubkey),
+    Snapshot(Pubkey),
+    SeatStatus(Pubkey),
+    WalletBalances(Pubkey),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WarmupItem {
+    pub target: WarmupTarget,
+    /// The error, if the item could not be fetched.
+    pub error: Option<String>,
+}
+
+/// What `SDKClient::warm_up` fetched, item by item. A readiness check can gate trading on
+/// `is_ready`.
+#[derive(Debug, Clone, Default)]
+pub struct WarmupReport {
+    pub items: Vec<WarmupItem>,
+    pub slot: Option<u64>,
+    pub blockhash: Option<Hash>,
+    pub elapsed: Duration,
+}
+
+impl WarmupReport {
+    pub fn is_ready(&self) -> bool {
+        self.items.iter().all(|item| item.error.is_none())
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &WarmupItem> {
+        self.items.iter().filter(|item| item.error.is_some())
+    }
+
+    pub(crate) fn record<T, E: ToString>(&mut self, target: WarmupTarget, result: &Result<T, E>) {
+        self.items.push(WarmupItem {
+            target,
+            error: result.as_ref().err().map(ToString::to_string),
+        });
+    }
+}
+
+struct Warmed<T> {
+    value: T,
+    fetched_at: Instant,
+}
+
+/// Per-market state prefetched by `SDKClient::warm_up`. Everything but the metadata is served
+/// once, to the first getter that asks for it within `WARMED_STATE_MAX_AGE`, so that a warm-up
+/// only saves the first round of requests and never serves stale state for long.
+#[derive(Default)]
+pub(crate) struct WarmedState {
+    metadata: BTreeMap<Pubkey, MarketMetadata>,
+    snapshots: BTreeMap<Pubkey, Warmed<MarketSnapshot>>,
+    seat_statuses: BTreeMap<Pubkey, Warmed<Option<SeatApprovalStatus>>>,
+    wallet_balances: BTreeMap<Pubkey, Warmed<WalletBalances>>,
+}
+
+fn take<T>(entries: &mut BTreeMap<Pubkey, Warmed<T>>, market: &Pubkey) -> Option<T> {
+    entries
+        .remove(market)
+        .filter(|entry| entry.fetched_at.elapsed() <= WARMED_STATE_MAX_AGE)
+        .map(|entry| entry.value)
+}
+
+fn warmed<T>(value: T) -> Warmed<T> {
+    Warmed {
+        value,
+        fetched_at: Instant::now(),
+    }
+}
+
+impl WarmedState {
+    pub(crate) fn metadata(&self, market: &Pubkey) -> Option<MarketMetadata> {
+        self.metadata.get(market).copied()
+    }
+
+    pub(crate) fn insert_metadata(&mut self, market: &Pubkey, metadata: MarketMetadata) {
+        self.metadata.insert(*market, metadata);
+    }
+
+    pub(crate) fn insert_snapshot(&mut self, market: &Pubkey, snapshot: MarketSnapshot) {
+        self.snapshots.insert(*market, warmed(snapshot));
+    }
+
+    pub(crate) fn insert_seat_status(
+        &mut self,
+        market: &Pubkey,
+        seat_status: Option<SeatApprovalStatus>,
+    ) {
+        self.seat_statuses.insert(*market, warmed(seat_status));
+    }
+
+    pub(crate) fn insert_wallet_balances(&mut self, market: &Pubkey, balances: WalletBalances) {
+        self.wallet_balances.insert(*market, warmed(balances));
+    }
+
+    pub(crate) fn take_snapshot(&mut self, market: &Pubkey) -> Option<MarketSnapshot> {
+        take(&mut self.snapshots, market)
+    }
+
+    pub(crate) fn take_seat_status(
+        &mut self,
+        market: &Pubkey,
+    ) -> Option<Option<SeatApprovalStatus>> {
+        take(&mut self.seat_statuses, market)
+    }
+
+    pub(crate) fn take_wallet_balances(&mut self, market: &Pubkey) -> Option<WalletBalances> {
+        take(&mut self.wallet_balances, market)
+    }
+}
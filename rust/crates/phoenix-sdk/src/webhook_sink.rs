@@ -0,0 +1,585 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use phoenix::state::enums::Side;
+use phoenix_sdk_core::market_event::{MarketEventDetails, PhoenixEvent};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::event_sink::EventSink;
+use crate::sdk_market_event::SDKMarketEvent;
+
+/// The header that carries the hex-encoded HMAC-SHA256 of the request body, see `sign_body`.
+pub const SIGNATURE_HEADER: &str = "X-Phoenix-Signature";
+
+/// A fill as posted by `WebhookSink`, in lots and ticks since the sink has no market metadata.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookFill {
+    /// Block time in seconds since the Unix epoch, or 0 if unknown.
+    pub timestamp: i64,
+    pub slot: u64,
+    pub signature: String,
+    pub market: String,
+    pub sequence_number: u64,
+    pub event_index: u64,
+    pub order_sequence_number: u64,
+    pub maker: String,
+    pub taker: String,
+    /// The taker's side, "buy" or "sell".
+    pub side: String,
+    pub price_in_ticks: u64,
+    pub base_lots_filled: u64,
+    pub base_lots_remaining: u64,
+}
+
+impl WebhookFill {
+    /// Returns `None` for events other than fills.
+    pub fn from_event(event: &PhoenixEvent) -> Option<Self> {
+        let MarketEventDetails::Fill(fill) = event.details else {
+            return None;
+        };
+        Some(Self {
+            timestamp: event.timestamp_seconds(),
+            slot: event.slot,
+            signature: event.signature.to_string(),
+            market: event.market.to_string(),
+            sequence_number: event.sequence_number,
+            event_index: event.event_index,
+            order_sequence_number: fill.order_sequence_number,
+            maker: fill.maker.to_string(),
+            taker: fill.taker.to_string(),
+            side: match fill.taker_side {
+                Side::Bid => "buy",
+                Side::Ask => "sell",
+            }
+            .to_string(),
+            price_in_ticks: fill.price_in_ticks,
+            base_lots_filled: fill.base_lots_filled,
+            base_lots_remaining: fill.base_lots_remaining,
+        })
+    }
+}
+
+/// The JSON body of a webhook request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub fills: Vec<WebhookFill>,
+}
+
+/// Which fills `WebhookSink` gives up when its queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Keep the queued fills and drop the incoming ones.
+    #[default]
+    DropNewest,
+    /// Make room by dropping the oldest queued fills.
+    DropOldest,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// The key of the HMAC-SHA256 signature of each body.
+    pub secret: Vec<u8>,
+    /// A batch is posted once it has this many fills...
+    pub max_batch_size: usize,
+    /// ...or once its first fill has waited this long.
+    pub max_batch_delay: Duration,
+    /// Fills waiting to be posted, beyond which `overflow_policy` applies.
+    pub queue_capacity: usize,
+    pub overflow_policy: OverflowPolicy,
+    /// Attempts after the first for a batch that gets a 5xx or no response. A batch that runs
+    /// out of retries, or gets any other non-success status, is dropped.
+    pub max_retries: u32,
+    /// The wait before the first retry, doubled for each one after it up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub request_timeout: Duration,
+}
+
+impl WebhookConfig {
+    pub fn new(url: impl Into<String>, secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            url: url.into(),
+            secret: secret.into(),
+            max_batch_size: 100,
+            max_batch_delay: Duration::from_millis(500),
+            queue_capacity: 10_000,
+            overflow_policy: OverflowPolicy::default(),
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(10),
+        }
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+/// Point-in-time copy of `WebhookMetrics`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WebhookMetricsSnapshot {
+    /// Fills taken into the queue, including ones later dropped by `OverflowPolicy::DropOldest`.
+    pub fills_queued: u64,
+    pub fills_delivered: u64,
+    pub batches_delivered: u64,
+    /// Requests sent again after a 5xx or no response.
+    pub retries: u64,
+    /// Fills dropped because the queue was full.
+    pub fills_dropped_on_overflow: u64,
+    /// Fills dropped because their batch ran out of retries or was rejected.
+    pub fills_dropped_on_failure: u64,
+}
+
+/// Counters of a `WebhookSink`, shared as an `Arc` with whatever reads them.
+#[derive(Debug, Default)]
+pub struct WebhookMetrics {
+    fills_queued: AtomicU64,
+    fills_delivered: AtomicU64,
+    batches_delivered: AtomicU64,
+    retries: AtomicU64,
+    fills_dropped_on_overflow: AtomicU64,
+    fills_dropped_on_failure: AtomicU64,
+}
+
+impl WebhookMetrics {
+    pub fn snapshot(&self) -> WebhookMetricsSnapshot {
+        WebhookMetricsSnapshot {
+            fills_queued: self.fills_queued.load(Ordering::Relaxed),
+            fills_delivered: self.fills_delivered.load(Ordering::Relaxed),
+            batches_delivered: self.batches_delivered.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            fills_dropped_on_overflow: self.fills_dropped_on_overflow.load(Ordering::Relaxed),
+            fills_dropped_on_failure: self.fills_dropped_on_failure.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The hex-encoded HMAC-SHA256 of `body` under `secret`, as sent in `SIGNATURE_HEADER`.
+/// Receivers recompute it over the raw body to authenticate a request.
+pub fn sign_body(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+struct Shared {
+    queue: Mutex<VecDeque<WebhookFill>>,
+    notify: Notify,
+    closed: AtomicBool,
+    metrics: Arc<WebhookMetrics>,
+}
+
+impl Shared {
+    fn queue(&self) -> MutexGuard<'_, VecDeque<WebhookFill>> {
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// An `EventSink` that POSTs the fills among its events to a webhook as a JSON `WebhookPayload`,
+/// signed with `sign_body` in `SIGNATURE_HEADER`. Other events are ignored.
+///
+/// `send_events` only queues the fills, so a slow or failing endpoint never holds up the producer:
+/// a background task batches them, posts each batch and retries with exponential backoff. When
+/// the queue is full, `WebhookConfig::overflow_policy` decides which fills are dropped. Drops
+/// and retries are counted in `metrics`.
+///
+/// The task drains the queue and exits when the sink is dropped; `shutdown` also waits for it.
+pub struct WebhookSink {
+    shared: Arc<Shared>,
+    config: WebhookConfig,
+    task: Option<JoinHandle<()>>,
+}
+
+impl WebhookSink {
+    /// Starts the delivery task, so it must be called within a Tokio runtime.
+    pub fn new(config: WebhookConfig) -> Result<Self> {
+        if config.max_batch_size == 0 || config.queue_capacity == 0 {
+            return Err(anyhow!(
+                "Webhook batch size and queue capacity must be at least 1"
+            ));
+        }
+        let http = reqwest::Client::builder()
+            .timeout(config.request_timeout)
+            .build()
+            .map_err(|e| anyhow!("Failed to build webhook HTTP client: {}", e))?;
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(VecDeque::new()),
+            notify: Notify::new(),
+            closed: AtomicBool::new(false),
+            metrics: Arc::new(WebhookMetrics::default()),
+        });
+        let task = tokio::spawn(deliver(shared.clone(), config.clone(), http));
+        Ok(Self {
+            shared,
+            config,
+            task: Some(task),
+        })
+    }
+
+    pub fn metrics(&self) -> &Arc<WebhookMetrics> {
+        &self.shared.metrics
+    }
+
+    /// Fills queued and not yet taken into a batch.
+    pub fn queue_len(&self) -> usize {
+        self.shared.queue().len()
+    }
+
+    /// Posts the queued fills, without waiting for batches to fill up, and waits for the
+    /// delivery task to finish.
+    pub async fn shutdown(mut self) {
+        self.shared.closed.store(true, Ordering::Relaxed);
+        self.shared.notify.notify_one();
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for WebhookSink {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Relaxed);
+        self.shared.notify.notify_one();
+    }
+}
+
+#[async_trait]
+impl EventSink for WebhookSink {
+    async fn send_events(&self, events: Vec<SDKMarketEvent>) -> Result<()> {
+        let fills = events
+            .iter()
+            .filter_map(|event| match event {
+                SDKMarketEvent::PhoenixEvent { event } => WebhookFill::from_event(event),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if fills.is_empty() {
+            return Ok(());
+        }
+        let metrics = &self.shared.metrics;
+        let mut dropped = 0;
+        {
+            let mut queue = self.shared.queue();
+            for fill in fills {
+                if queue.len() >= self.config.queue_capacity {
+                    dropped += 1;
+                    match self.config.overflow_policy {
+                        OverflowPolicy::DropNewest => continue,
+                        OverflowPolicy::DropOldest => {
+                            queue.pop_front();
+                        }
+                    }
+                }
+                queue.push_back(fill);
+                metrics.fills_queued.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        metrics
+            .fills_dropped_on_overflow
+            .fetch_add(dropped, Ordering::Relaxed);
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+}
+
+/// The delivery task: waits for a batch to fill up or time out, then posts it.
+async fn deliver(shared: Arc<Shared>, config: WebhookConfig, http: reqwest::Client) {
+    loop {
+        // Wait for the first fill of the next batch
+        loop {
+            if !shared.queue().is_empty() {
+                break;
+            }
+            if shared.closed.load(Ordering::Relaxed) {
+                return;
+            }
+            shared.notify.notified().await;
+        }
+        let deadline = Instant::now() + config.max_batch_delay;
+        while shared.queue().len() < config.max_batch_size && !shared.closed.load(Ordering::Relaxed)
+        {
+            tokio::select! {
+                _ = shared.notify.notified() => {}
+                _ = tokio::time::sleep_until(deadline) => break,
+            }
+        }
+        let batch = {
+            let mut queue = shared.queue();
+            let len = queue.len().min(config.max_batch_size);
+            queue.drain(..len).collect::<Vec<_>>()
+        };
+        post_batch(&shared.metrics, &config, &http, batch).await;
+    }
+}
+
+async fn post_batch(
+    metrics: &WebhookMetrics,
+    config: &WebhookConfig,
+    http: &reqwest::Client,
+    fills: Vec<WebhookFill>,
+) {
+    let count = fills.len() as u64;
+    let body = match serde_json::to_vec(&WebhookPayload { fills }) {
+        Ok(body) => body,
+        Err(e) => {
+            println!("Failed to serialize webhook batch: {}", e);
+            metrics
+                .fills_dropped_on_failure
+                .fetch_add(count, Ordering::Relaxed);
+            return;
+        }
+    };
+    let signature = sign_body(&config.secret, &body);
+    for attempt in 0..=config.max_retries {
+        if attempt > 0 {
+            metrics.retries.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(config.backoff(attempt - 1)).await;
+        }
+        let response = http
+            .post(&config.url)
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.clone())
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => {
+                metrics.fills_delivered.fetch_add(count, Ordering::Relaxed);
+                metrics.batches_delivered.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Ok(response) if response.status().is_server_error() => {}
+            Ok(response) => {
+                println!(
+                    "Webhook rejected a batch of {} fills: {}",
+                    count,
+                    response.status()
+                );
+                break;
+            }
+            Err(_) => {}
+        }
+    }
+    metrics
+        .fills_dropped_on_failure
+        .fetch_add(count, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server, StatusCode};
+    use phoenix_sdk_core::market_event::Fill;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Signature;
+
+    use super::*;
+    use phoenix_sdk_core::test_utils::event;
+
+    /// The requests a test server received, as `(signature header, body)`.
+    type Received = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+    /// Serves on a local port, answering with `statuses` in turn and then 200.
+    async fn serve(statuses: Vec<u16>) -> (SocketAddr, Received) {
+        let received = Received::default();
+        let statuses = Arc::new(Mutex::new(VecDeque::from(statuses)));
+        let make_service = {
+            let received = received.clone();
+            make_service_fn(move |_| {
+                let received = received.clone();
+                let statuses = statuses.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                        let received = received.clone();
+                        let statuses = statuses.clone();
+                        async move {
+                            let signature = request.headers()[SIGNATURE_HEADER]
+                                .to_str()
+                                .unwrap()
+                                .to_string();
+                            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                            received.lock().unwrap().push((signature, body.to_vec()));
+                            let status = statuses.lock().unwrap().pop_front().unwrap_or(200);
+                            let mut response = Response::new(Body::empty());
+                            *response.status_mut() = StatusCode::from_u16(status).unwrap();
+                            Ok::<_, Infallible>(response)
+                        }
+                    }))
+                }
+            })
+        };
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, received)
+    }
+
+    fn config(addr: SocketAddr) -> WebhookConfig {
+        WebhookConfig {
+            max_batch_size: 2,
+            max_batch_delay: Duration::from_millis(20),
+            initial_backoff: Duration::from_millis(1),
+            ..WebhookConfig::new(format!("http://{}/fills", addr), "secret")
+        }
+    }
+
+    fn fill(sequence_number: u64) -> SDKMarketEvent {
+        PhoenixEvent {
+            sequence_number,
+            slot: 10,
+            signature: Signature::new_unique(),
+            signer: Pubkey::new_unique(),
+            ..event(
+                Pubkey::new_unique(),
+                MarketEventDetails::Fill(Fill {
+                    order_sequence_number: 1,
+                    maker: Pubkey::new_unique(),
+                    taker: Pubkey::new_unique(),
+                    price_in_ticks: 100,
+                    base_lots_filled: 5,
+                    base_lots_remaining: 0,
+                    side_filled: Side::Ask,
+                    maker_side: Side::Ask,
+                    taker_side: Side::Bid,
+                    is_full_fill: true,
+                }),
+            )
+        }
+        .into()
+    }
+
+    fn sequence_numbers(body: &[u8]) -> Vec<u64> {
+        serde_json::from_slice::<WebhookPayload>(body)
+            .unwrap()
+            .fills
+            .iter()
+            .map(|fill| fill.sequence_number)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_batches_are_signed() {
+        let (addr, received) = serve(vec![]).await;
+        let sink = WebhookSink::new(config(addr)).unwrap();
+        sink.send_events(vec![fill(1), fill(2), fill(3)])
+            .await
+            .unwrap();
+        let metrics = sink.metrics().clone();
+        sink.shutdown().await;
+
+        let received = received.lock().unwrap().clone();
+        let batches = received
+            .iter()
+            .map(|(_, body)| sequence_numbers(body))
+            .collect::<Vec<_>>();
+        assert_eq!(batches, vec![vec![1, 2], vec![3]]);
+        for (signature, body) in &received {
+            assert_eq!(*signature, sign_body(b"secret", body));
+            assert_ne!(*signature, sign_body(b"other secret", body));
+        }
+        let fill = &serde_json::from_slice::<WebhookPayload>(&received[0].1)
+            .unwrap()
+            .fills[0];
+        assert_eq!((fill.side.as_str(), fill.base_lots_filled), ("buy", 5));
+        let metrics = metrics.snapshot();
+        assert_eq!((metrics.fills_delivered, metrics.batches_delivered), (3, 2));
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        let (addr, received) = serve(vec![500, 503]).await;
+        let sink = WebhookSink::new(config(addr)).unwrap();
+        sink.send_events(vec![fill(1)]).await.unwrap();
+        let metrics = sink.metrics().clone();
+        sink.shutdown().await;
+
+        // The same signed body each time
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 3);
+        assert!(received.windows(2).all(|pair| pair[0] == pair[1]));
+        let metrics = metrics.snapshot();
+        assert_eq!((metrics.retries, metrics.fills_delivered), (2, 1));
+        assert_eq!(metrics.fills_dropped_on_failure, 0);
+    }
+
+    #[tokio::test]
+    async fn test_drops_after_retry_budget() {
+        let (addr, received) = serve(vec![500; 10]).await;
+        let sink = WebhookSink::new(WebhookConfig {
+            max_retries: 2,
+            ..config(addr)
+        })
+        .unwrap();
+        sink.send_events(vec![fill(1), fill(2)]).await.unwrap();
+        let metrics = sink.metrics().clone();
+        sink.shutdown().await;
+
+        assert_eq!(received.lock().unwrap().len(), 3);
+        let metrics = metrics.snapshot();
+        assert_eq!((metrics.retries, metrics.fills_dropped_on_failure), (2, 2));
+        assert_eq!(metrics.fills_delivered, 0);
+
+        // A client error is not retried
+        let (addr, received) = serve(vec![400]).await;
+        let sink = WebhookSink::new(config(addr)).unwrap();
+        sink.send_events(vec![fill(1)]).await.unwrap();
+        let metrics = sink.metrics().clone();
+        sink.shutdown().await;
+        assert_eq!(received.lock().unwrap().len(), 1);
+        assert_eq!(metrics.snapshot().fills_dropped_on_failure, 1);
+    }
+
+    #[tokio::test]
+    async fn test_overflow_policy() {
+        for (policy, kept, queued) in [
+            (OverflowPolicy::DropNewest, vec![1, 2, 3], 3),
+            (OverflowPolicy::DropOldest, vec![3, 4, 5], 5),
+        ] {
+            let (addr, received) = serve(vec![]).await;
+            // Nothing is posted until shutdown, so the queue fills up
+            let sink = WebhookSink::new(WebhookConfig {
+                max_batch_size: 10,
+                max_batch_delay: Duration::from_secs(60),
+                queue_capacity: 3,
+                overflow_policy: policy,
+                ..config(addr)
+            })
+            .unwrap();
+            sink.send_events((1..=5).map(fill).collect()).await.unwrap();
+            assert_eq!(sink.queue_len(), 3);
+            let metrics = sink.metrics().clone();
+            sink.shutdown().await;
+
+            let received = received.lock().unwrap().clone();
+            assert_eq!(received.len(), 1);
+            assert_eq!(sequence_numbers(&received[0].1), kept);
+            let metrics = metrics.snapshot();
+            assert_eq!(
+                (metrics.fills_queued, metrics.fills_dropped_on_overflow),
+                (queued, 2)
+            );
+        }
+    }
+}
@@ -0,0 +1,688 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use phoenix::quantities::WrapperU64;
+use phoenix::state::TraderState;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::error;
+use crate::paper_trading::TradingMode;
+use crate::sdk_client::SDKClient;
+
+/// What `WithdrawalFlow::run` withdraws from the trader's seat. Nothing is ever sold: base stays
+/// base and quote stays quote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawalAmounts {
+    /// Everything deposited on the market once the orders are cancelled.
+    All,
+    /// Exactly these amounts, which must be free once the orders are cancelled.
+    Lots { base_lots: u64, quote_lots: u64 },
+}
+
+/// The step a `WithdrawalFlow` failed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawalStep {
+    Cancel,
+    ConfirmCancels,
+    Withdraw,
+    ConfirmWithdraw,
+}
+
+impl fmt::Display for WithdrawalStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WithdrawalStep::Cancel => "cancel",
+            WithdrawalStep::ConfirmCancels => "confirm cancels",
+            WithdrawalStep::Withdraw => "withdraw",
+            WithdrawalStep::ConfirmWithdraw => "confirm withdraw",
+        })
+    }
+}
+
+/// Where a `WithdrawalFlow` is. Serializable so that a flow interrupted by a restart can be
+/// resumed with `WithdrawalFlow::with_state`, and displayable for UIs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum WithdrawalState {
+    #[default]
+    NotStarted,
+    /// A cancel of every resting order was sent.
+    CancelSubmitted {
+        #[serde(with = "signature_str")]
+        signature: Signature,
+    },
+    /// The trader has no resting orders and nothing locked on the market.
+    CancelsConfirmed,
+    WithdrawSubmitted {
+        #[serde(with = "signature_str")]
+        signature: Signature,
+    },
+    /// Finished. `signature` is the withdrawal, `None` if there was nothing to withdraw.
+    Done {
+        #[serde(with = "signature_str::optional")]
+        signature: Option<Signature>,
+    },
+    /// Stopped at `step`. Resume by setting the state before the step with `with_state`.
+    Failed { step: WithdrawalStep, error: String },
+}
+
+impl WithdrawalState {
+    /// Whether `run` has nothing left to do.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            WithdrawalState::Done { .. } | WithdrawalState::Failed { .. }
+        )
+    }
+}
+
+impl fmt::Display for WithdrawalState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WithdrawalState::NotStarted => write!(f, "Not started"),
+            WithdrawalState::CancelSubmitted { signature } => {
+                write!(f, "Cancelling orders ({})", signature)
+            }
+            WithdrawalState::CancelsConfirmed => write!(f, "Orders cancelled"),
+            WithdrawalState::WithdrawSubmitted { signature } => {
+                write!(f, "Withdrawing ({})", signature)
+            }
+            WithdrawalState::Done {
+                signature: Some(signature),
+            } => write!(f, "Withdrawn ({})", signature),
+            WithdrawalState::Done { signature: None } => write!(f, "Nothing to withdraw"),
+            WithdrawalState::Failed { step, error } => write!(f, "Failed to {}: {}", step, error),
+        }
+    }
+}
+
+mod signature_str {
+    use std::str::FromStr;
+
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use solana_sdk::signature::Signature;
+
+    pub fn serialize<S: Serializer>(
+        signature: &Signature,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(signature)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Signature, D::Error> {
+        Signature::from_str(&String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+
+    pub mod optional {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            signature: &Option<Signature>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match signature {
+                Some(signature) => serializer.collect_str(signature),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Signature>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|signature| Signature::from_str(&signature).map_err(de::Error::custom))
+                .transpose()
+        }
+    }
+}
+
+/// Options for `WithdrawalFlow`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WithdrawalOptions {
+    /// Cancels to send before giving up on orders that keep resting, e.g. because another
+    /// process is still quoting.
+    pub max_cancel_attempts: usize,
+    /// How often the seat is polled for the cancelled orders' funds to be released.
+    pub poll_interval: Duration,
+    /// Polls after a confirmed cancel before sending another one.
+    pub max_polls: usize,
+}
+
+impl Default for WithdrawalOptions {
+    fn default() -> Self {
+        Self {
+            max_cancel_attempts: 3,
+            poll_interval: Duration::from_secs(1),
+            max_polls: 5,
+        }
+    }
+}
+
+/// The operations a `WithdrawalFlow` needs, `SDKClient` in practice.
+#[async_trait]
+pub trait WithdrawalClient: Send + Sync {
+    async fn open_orders(&self, market: &Pubkey) -> Result<usize>;
+    async fn trader_state(&self, market: &Pubkey) -> Result<TraderState>;
+    async fn send_cancel_all(&self, market: &Pubkey) -> Result<Signature>;
+    async fn send_withdraw(&self, market: &Pubkey, amounts: WithdrawalAmounts)
+        -> Result<Signature>;
+    async fn confirm(&self, signature: &Signature) -> Result<()>;
+}
+
+/// Sends with risk checks overridden, as `flatten_market` does, since a withdrawal only
+/// reduces exposure. Not supported in paper mode, which does not simulate seats.
+#[async_trait]
+impl WithdrawalClient for SDKClient {
+    async fn open_orders(&self, market: &Pubkey) -> Result<usize> {
+        Ok(self.get_open_orders(market).await?.len())
+    }
+
+    async fn trader_state(&self, market: &Pubkey) -> Result<TraderState> {
+        self.get_trader_state(market).await
+    }
+
+    async fn send_cancel_all(&self, market: &Pubkey) -> Result<Signature> {
+        if self.trading_mode == TradingMode::Paper {
+            bail!("WithdrawalFlow is not supported in paper mode");
+        }
+        let ix = self.get_cancel_all_ix(market)?;
+        self.send_ixs_with_risk_override(vec![ix], true).await
+    }
+
+    async fn send_withdraw(
+        &self,
+        market: &Pubkey,
+        amounts: WithdrawalAmounts,
+    ) -> Result<Signature> {
+        if self.trading_mode == TradingMode::Paper {
+            bail!("WithdrawalFlow is not supported in paper mode");
+        }
+        let ix = match amounts {
+            WithdrawalAmounts::All => self.get_withdraw_ix(market)?,
+            WithdrawalAmounts::Lots {
+                base_lots,
+                quote_lots,
+            } => self.get_withdraw_lots_ix(market, base_lots, quote_lots)?,
+        };
+        self.send_ixs_with_risk_override(vec![ix], true).await
+    }
+
+    async fn confirm(&self, signature: &Signature) -> Result<()> {
+        self.confirm_signature(signature).await
+    }
+}
+
+type TransitionCallback = Box<dyn Fn(&Pubkey, &WithdrawalState) + Send + Sync>;
+
+/// Withdraws funds from a market seat in the order that cannot strand them: cancel every resting
+/// order, wait for the cancels to confirm and the locked funds to be released, then withdraw.
+///
+/// Unlike `SDKClient::flatten_market`, inventory is never sold, and every intermediate state is
+/// exposed through `state` and the `with_on_transition` callback. Persisting the state from the
+/// callback makes the flow resumable: after a restart, pass the saved state to `with_state` and
+/// call `run` again. A submitted transaction is then confirmed rather than sent twice.
+///
+/// A transaction that fails to confirm with a retryable error, such as an expired blockhash, is
+/// sent again after its preconditions are checked again. Other errors end the flow in `Failed`.
+pub struct WithdrawalFlow<C: WithdrawalClient> {
+    client: C,
+    options: WithdrawalOptions,
+    state: Mutex<WithdrawalState>,
+    on_transition: Option<TransitionCallback>,
+}
+
+impl<C: WithdrawalClient> WithdrawalFlow<C> {
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            options: WithdrawalOptions::default(),
+            state: Mutex::new(WithdrawalState::default()),
+            on_transition: None,
+        }
+    }
+
+    pub fn with_options(mut self, options: WithdrawalOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Resumes from a state saved by a previous run.
+    pub fn with_state(self, state: WithdrawalState) -> Self {
+        *self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = state;
+        self
+    }
+
+    /// Called with every new state, before the flow acts on it.
+    pub fn with_on_transition(
+        mut self,
+        on_transition: impl Fn(&Pubkey, &WithdrawalState) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_transition = Some(Box::new(on_transition));
+        self
+    }
+
+    pub fn state(&self) -> WithdrawalState {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Runs the flow on `market` from its current state until it is `Done` or `Failed`, and
+    /// returns the final state. Resuming must use the same market and amounts as the original run.
+    pub async fn run(&self, market: &Pubkey, amounts: WithdrawalAmounts) -> WithdrawalState {
+        let mut cancel_attempts = 0;
+        loop {
+            let state = self.state();
+            if state.is_terminal() {
+                return state;
+            }
+            let next = match self
+                .step(market, amounts, &state, &mut cancel_attempts)
+                .await
+            {
+                Ok(next) => next,
+                Err((step, e)) => WithdrawalState::Failed {
+                    step,
+                    error: e.to_string(),
+                },
+            };
+            self.transition(market, next);
+        }
+    }
+
+    fn transition(&self, market: &Pubkey, state: WithdrawalState) {
+        *self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = state.clone();
+        if let Some(on_transition) = &self.on_transition {
+            on_transition(market, &state);
+        }
+    }
+
+    async fn step(
+        &self,
+        market: &Pubkey,
+        amounts: WithdrawalAmounts,
+        state: &WithdrawalState,
+        cancel_attempts: &mut usize,
+    ) -> std::result::Result<WithdrawalState, (WithdrawalStep, anyhow::Error)> {
+        use WithdrawalStep::*;
+        match state {
+            WithdrawalState::NotStarted => {
+                if self
+                    .client
+                    .open_orders(market)
+                    .await
+                    .map_err(|e| (Cancel, e))?
+                    == 0
+                {
+                    return Ok(WithdrawalState::CancelsConfirmed);
+                }
+                self.cancel(market, cancel_attempts).await
+            }
+            WithdrawalState::CancelSubmitted { signature } => {
+                if let Err(e) = self.client.confirm(signature).await {
+                    if !error::is_retryable(&e) {
+                        return Err((ConfirmCancels, e));
+                    }
+                    println!("Re-sending unconfirmed cancel {}: {}", signature, e);
+                    return self.cancel(market, cancel_attempts).await;
+                }
+                for poll in 0..=self.options.max_polls {
+                    if poll > 0 {
+                        tokio::time::sleep(self.options.poll_interval).await;
+                    }
+                    let (open_orders, trader_state) =
+                        self.seat(market).await.map_err(|e| (ConfirmCancels, e))?;
+                    if open_orders == 0
+                        && trader_state.base_lots_locked.as_u64() == 0
+                        && trader_state.quote_lots_locked.as_u64() == 0
+                    {
+                        return Ok(WithdrawalState::CancelsConfirmed);
+                    }
+                }
+                // Orders were placed after the cancel, or its effects are not visible yet
+                self.cancel(market, cancel_attempts).await
+            }
+            WithdrawalState::CancelsConfirmed => {
+                let trader_state = self
+                    .client
+                    .trader_state(market)
+                    .await
+                    .map_err(|e| (Withdraw, e))?;
+                let base_lots_free = trader_state.base_lots_free.as_u64();
+                let quote_lots_free = trader_state.quote_lots_free.as_u64();
+                match amounts {
+                    WithdrawalAmounts::All if base_lots_free == 0 && quote_lots_free == 0 => {
+                        return Ok(WithdrawalState::Done { signature: None });
+                    }
+                    WithdrawalAmounts::Lots {
+                        base_lots,
+                        quote_lots,
+                    } if base_lots > base_lots_free || quote_lots > quote_lots_free => {
+                        return Err((
+                            Withdraw,
+                            anyhow::anyhow!(
+                                "Requested {} base lots and {} quote lots, but only {} and {} are free",
+                                base_lots,
+                                quote_lots,
+                                base_lots_free,
+                                quote_lots_free
+                            ),
+                        ));
+                    }
+                    _ => {}
+                }
+                let signature = self
+                    .client
+                    .send_withdraw(market, amounts)
+                    .await
+                    .map_err(|e| (Withdraw, e))?;
+                Ok(WithdrawalState::WithdrawSubmitted { signature })
+            }
+            WithdrawalState::WithdrawSubmitted { signature } => {
+                match self.client.confirm(signature).await {
+                    Ok(()) => Ok(WithdrawalState::Done {
+                        signature: Some(*signature),
+                    }),
+                    Err(e) if error::is_retryable(&e) => {
+                        println!("Re-sending unconfirmed withdrawal {}: {}", signature, e);
+                        Ok(WithdrawalState::CancelsConfirmed)
+                    }
+                    Err(e) => Err((ConfirmWithdraw, e)),
+                }
+            }
+            WithdrawalState::Done { .. } | WithdrawalState::Failed { .. } => Ok(state.clone()),
+        }
+    }
+
+    async fn cancel(
+        &self,
+        market: &Pubkey,
+        cancel_attempts: &mut usize,
+    ) -> std::result::Result<WithdrawalState, (WithdrawalStep, anyhow::Error)> {
+        if *cancel_attempts >= self.options.max_cancel_attempts {
+            return Err((
+                WithdrawalStep::Cancel,
+                anyhow::anyhow!(
+                    "Orders still resting after {} cancels",
+                    self.options.max_cancel_attempts
+                ),
+            ));
+        }
+        *cancel_attempts += 1;
+        let signature = self
+            .client
+            .send_cancel_all(market)
+            .await
+            .map_err(|e| (WithdrawalStep::Cancel, e))?;
+        Ok(WithdrawalState::CancelSubmitted { signature })
+    }
+
+    async fn seat(&self, market: &Pubkey) -> Result<(usize, TraderState)> {
+        Ok((
+            self.client.open_orders(market).await?,
+            self.client.trader_state(market).await?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use phoenix::quantities::{BaseLots, QuoteLots};
+    use solana_sdk::transaction::TransactionError;
+
+    use super::*;
+    use crate::error::PhoenixSdkError;
+
+    /// A seat with resting orders whose funds are released one poll after the cancel lands.
+    #[derive(Default)]
+    struct MockSeat {
+        inner: Mutex<MockSeatState>,
+    }
+
+    #[derive(Default)]
+    struct MockSeatState {
+        open_orders: usize,
+        locked: (u64, u64),
+        free: (u64, u64),
+        polls_until_released: usize,
+        sent: Vec<(&'static str, Signature)>,
+        /// Confirmations that fail with an expired blockhash before one succeeds.
+        expired_confirms: usize,
+        pending_withdrawal: Option<WithdrawalAmounts>,
+    }
+
+    impl MockSeat {
+        fn new(open_orders: usize, locked: (u64, u64), free: (u64, u64)) -> Arc<Self> {
+            Arc::new(Self {
+                inner: Mutex::new(MockSeatState {
+                    open_orders,
+                    locked,
+                    free,
+                    ..Default::default()
+                }),
+            })
+        }
+
+        fn sent(&self) -> Vec<&'static str> {
+            self.inner
+                .lock()
+                .unwrap()
+                .sent
+                .iter()
+                .map(|(kind, _)| *kind)
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl WithdrawalClient for Arc<MockSeat> {
+        async fn open_orders(&self, _market: &Pubkey) -> Result<usize> {
+            Ok(self.inner.lock().unwrap().open_orders)
+        }
+
+        async fn trader_state(&self, _market: &Pubkey) -> Result<TraderState> {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.open_orders == 0 {
+                if inner.polls_until_released == 0 {
+                    inner.free.0 += std::mem::take(&mut inner.locked.0);
+                    inner.free.1 += std::mem::take(&mut inner.locked.1);
+                } else {
+                    inner.polls_until_released -= 1;
+                }
+            }
+            let mut trader_state = TraderState::default();
+            trader_state.base_lots_locked = BaseLots::new(inner.locked.0);
+            trader_state.base_lots_free = BaseLots::new(inner.free.0);
+            trader_state.quote_lots_locked = QuoteLots::new(inner.locked.1);
+            trader_state.quote_lots_free = QuoteLots::new(inner.free.1);
+            Ok(trader_state)
+        }
+
+        async fn send_cancel_all(&self, _market: &Pubkey) -> Result<Signature> {
+            let mut inner = self.inner.lock().unwrap();
+            inner.open_orders = 0;
+            inner.polls_until_released = 1;
+            let signature = Signature::new_unique();
+            inner.sent.push(("cancel", signature));
+            Ok(signature)
+        }
+
+        async fn send_withdraw(
+            &self,
+            _market: &Pubkey,
+            amounts: WithdrawalAmounts,
+        ) -> Result<Signature> {
+            let mut inner = self.inner.lock().unwrap();
+            inner.pending_withdrawal = Some(amounts);
+            let signature = Signature::new_unique();
+            inner.sent.push(("withdraw", signature));
+            Ok(signature)
+        }
+
+        async fn confirm(&self, signature: &Signature) -> Result<()> {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.expired_confirms > 0 {
+                inner.expired_confirms -= 1;
+                inner.pending_withdrawal = None;
+                return Err(PhoenixSdkError::transaction_failed(
+                    Some(*signature),
+                    TransactionError::BlockhashNotFound,
+                    vec![],
+                )
+                .into());
+            }
+            // A withdrawal lands with its confirmation; an expired one never does
+            match inner.pending_withdrawal.take() {
+                Some(WithdrawalAmounts::All) => inner.free = (0, 0),
+                Some(WithdrawalAmounts::Lots {
+                    base_lots,
+                    quote_lots,
+                }) => {
+                    inner.free.0 -= base_lots;
+                    inner.free.1 -= quote_lots;
+                }
+                None => {}
+            }
+            Ok(())
+        }
+    }
+
+    fn options() -> WithdrawalOptions {
+        WithdrawalOptions {
+            poll_interval: Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_confirm_withdraw() {
+        let market = Pubkey::new_unique();
+        let seat = MockSeat::new(2, (10, 500), (5, 0));
+        let states = Arc::new(Mutex::new(vec![]));
+        let flow = WithdrawalFlow::new(seat.clone())
+            .with_options(options())
+            .with_on_transition({
+                let states = states.clone();
+                move |_, state: &WithdrawalState| states.lock().unwrap().push(state.to_string())
+            });
+        let state = flow.run(&market, WithdrawalAmounts::All).await;
+        let withdraw = seat.inner.lock().unwrap().sent[1].1;
+        assert_eq!(
+            state,
+            WithdrawalState::Done {
+                signature: Some(withdraw)
+            }
+        );
+        assert_eq!(seat.sent(), vec!["cancel", "withdraw"]);
+        let states = states.lock().unwrap().clone();
+        assert_eq!(states.len(), 4);
+        assert!(states[0].starts_with("Cancelling orders"));
+        assert_eq!(states[1], "Orders cancelled");
+        assert!(states[3].starts_with("Withdrawn"));
+        assert_eq!(seat.inner.lock().unwrap().free, (0, 0));
+
+        // Nothing left to withdraw
+        let flow = WithdrawalFlow::new(seat.clone()).with_options(options());
+        assert_eq!(
+            flow.run(&market, WithdrawalAmounts::All).await,
+            WithdrawalState::Done { signature: None }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resume_after_restart() {
+        let market = Pubkey::new_unique();
+        let seat = MockSeat::new(1, (0, 100), (0, 50));
+        let amounts = WithdrawalAmounts::Lots {
+            base_lots: 0,
+            quote_lots: 120,
+        };
+
+        // The process dies right after the cancel is sent, with the state saved as JSON
+        let saved = Arc::new(Mutex::new(String::new()));
+        let flow = WithdrawalFlow::new(seat.clone()).with_on_transition({
+            let saved = saved.clone();
+            move |_, state: &WithdrawalState| {
+                *saved.lock().unwrap() = serde_json::to_string(state).unwrap()
+            }
+        });
+        let cancel = WithdrawalClient::send_cancel_all(&seat, &market)
+            .await
+            .unwrap();
+        flow.transition(
+            &market,
+            WithdrawalState::CancelSubmitted { signature: cancel },
+        );
+        assert_eq!(
+            *saved.lock().unwrap(),
+            format!(r#"{{"state":"cancel_submitted","signature":"{}"}}"#, cancel)
+        );
+
+        // The restarted flow confirms the cancel instead of sending another, then withdraws
+        let state: WithdrawalState = serde_json::from_str(&saved.lock().unwrap()).unwrap();
+        let flow = WithdrawalFlow::new(seat.clone())
+            .with_options(options())
+            .with_state(state);
+        assert!(matches!(
+            flow.run(&market, amounts).await,
+            WithdrawalState::Done { signature: Some(_) }
+        ));
+        assert_eq!(seat.sent(), vec!["cancel", "withdraw"]);
+        assert_eq!(seat.inner.lock().unwrap().free, (0, 30));
+    }
+
+    #[tokio::test]
+    async fn test_retries_and_failures() {
+        let market = Pubkey::new_unique();
+
+        // An expired withdrawal is re-sent once the balances are checked again
+        let seat = MockSeat::new(0, (0, 0), (7, 0));
+        seat.inner.lock().unwrap().expired_confirms = 1;
+        let flow = WithdrawalFlow::new(seat.clone()).with_options(options());
+        assert!(matches!(
+            flow.run(&market, WithdrawalAmounts::All).await,
+            WithdrawalState::Done { signature: Some(_) }
+        ));
+        assert_eq!(seat.sent(), vec!["withdraw", "withdraw"]);
+
+        // More than is free is never sent
+        let seat = MockSeat::new(0, (0, 0), (7, 0));
+        let flow = WithdrawalFlow::new(seat.clone()).with_options(options());
+        let state = flow
+            .run(
+                &market,
+                WithdrawalAmounts::Lots {
+                    base_lots: 8,
+                    quote_lots: 0,
+                },
+            )
+            .await;
+        assert!(matches!(
+            state,
+            WithdrawalState::Failed {
+                step: WithdrawalStep::Withdraw,
+                ..
+            }
+        ));
+        assert!(seat.sent().is_empty());
+        assert_eq!(flow.state(), state);
+        // A failed flow stays failed
+        assert_eq!(flow.run(&market, WithdrawalAmounts::All).await, state);
+    }
+}
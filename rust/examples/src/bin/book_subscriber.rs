@@ -0,0 +1,82 @@
+use clap::Parser;
+use phoenix::quantities::WrapperU64;
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk::book_subscriber::BookSubscriber;
+use phoenix_sdk_core::sdk_client_core::PhoenixOrder;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use tokio::sync::broadcast::error::RecvError;
+
+#[derive(clap::Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Websocket endpoint
+    #[clap(short, long, default_value = "wss://api.mainnet-beta.solana.com")]
+    pub ws: String,
+    /// Market to stream, defaults to SOL/USDC
+    #[clap(
+        short,
+        long,
+        default_value = "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg"
+    )]
+    pub market: String,
+}
+
+/// Returns the price and total size of the best level, given one side of a book ordered best first.
+fn best_level<'a>(
+    mut orders: impl Iterator<Item = (&'a FIFOOrderId, &'a PhoenixOrder)>,
+) -> Option<(u64, u64)> {
+    let (order_id, order) = orders.next()?;
+    let price_in_ticks = order_id.price_in_ticks.as_u64();
+    let base_lots = order.num_base_lots
+        + orders
+            .take_while(|(order_id, _)| order_id.price_in_ticks.as_u64() == price_in_ticks)
+            .map(|(_, order)| order.num_base_lots)
+            .sum::<u64>();
+    Some((price_in_ticks, base_lots))
+}
+
+/// Prints top-of-book changes for a market as they happen.
+/// Can run this via: cargo run --bin book_subscriber -- --ws wss://api.mainnet-beta.solana.com
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let market = Pubkey::from_str(&args.market)?;
+
+    let subscriber = BookSubscriber::new(&args.ws, market, 64);
+    let mut receiver = subscriber.subscribe();
+    tokio::spawn(subscriber.run());
+
+    let mut top_of_book = None;
+    loop {
+        let update = match receiver.recv().await {
+            Ok(update) => update,
+            Err(RecvError::Lagged(skipped)) => {
+                println!("Skipped {} updates", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        let book = &update.market_state.orderbook;
+        let top = (best_level(book.bids.iter()), best_level(book.asks.iter()));
+        if update.diff.is_full || top_of_book != Some(top) {
+            let format_level = |level: Option<(u64, u64)>| match level {
+                Some((price_in_ticks, base_lots)) => format!(
+                    "{:.4} x {:.4}",
+                    price_in_ticks as f64 * book.quote_units_per_raw_base_unit_per_tick,
+                    base_lots as f64 * book.raw_base_units_per_base_lot
+                ),
+                None => "-".to_string(),
+            };
+            println!(
+                "slot {}{}: bid {} | ask {}",
+                update.slot,
+                if update.diff.is_full { " (full)" } else { "" },
+                format_level(top.0),
+                format_level(top.1),
+            );
+            top_of_book = Some(top);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use clap::Parser;
+use phoenix_sdk::market_registry::MarketRegistry;
+use phoenix_sdk::sdk_client::SDKClient;
+use solana_sdk::signature::Keypair;
+use std::sync::Arc;
+
+#[derive(clap::Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Market to print, by address or by symbol
+    #[clap(default_value = "SOL/USDC")]
+    pub market: String,
+    /// RPC endpoint
+    #[clap(short, long, default_value = "https://api.mainnet-beta.solana.com")]
+    pub rpc: String,
+    /// Number of levels per side
+    #[clap(short, long, default_value = "20")]
+    pub depth: usize,
+}
+
+/// Prints a snapshot of a market's book, asks above bids.
+/// Can run this via: cargo run --bin ladder -- SOL/USDC
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let registry = Arc::new(MarketRegistry::mainnet());
+    println!("SOL/USDC is {}", registry.resolve("SOL/USDC")?);
+
+    // Reading the book needs no signer
+    let mut sdk = SDKClient::new(&Keypair::new(), &args.rpc).await?;
+    sdk.set_market_registry(Some(registry.clone()));
+    // Checks the market's mints against the registry when given a symbol
+    let market = sdk.resolve_market(args.market.as_str()).await?;
+    let ladder = sdk.get_market_ladder_as_floats(&market, args.depth).await?;
+
+    let symbol = registry.symbol(&market).unwrap_or("unregistered market");
+    println!("{} ({}) at slot {}", symbol, market, ladder.slot);
+    for (price, size) in ladder.asks.iter().rev() {
+        println!("{:>15.6} {:>15.4}", price, size);
+    }
+    println!("{:-^31}", "");
+    for (price, size) in ladder.bids.iter() {
+        println!("{:>15.6} {:>15.4}", price, size);
+    }
+    Ok(())
+}
@@ -0,0 +1,442 @@
+//! Runs instructions built by the SDK against the Phoenix program in `solana-program-test`.
+//!
+//! `PhoenixTestContext::new` creates two mints, an active market and funded traders with
+//! approved seats. Each trader has its own `SDKClientCore`, so instructions are built exactly as
+//! an application would build them, then executed with `place_and_confirm` or `process`.
+//!
+//! The program runs natively from `phoenix-common` by default. To run a deployed binary instead,
+//! put `phoenix.so` in a directory and point `SBF_OUT_DIR` at it.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use phoenix::program::instruction_builders::{
+    create_change_market_status_instruction, create_change_seat_status_instruction,
+    create_initialize_market_instructions_default, create_request_seat_authorized_instruction,
+};
+use phoenix::program::status::{MarketStatus, SeatApprovalStatus};
+use phoenix::program::{MarketHeader, MarketSizeParams};
+use phoenix::state::TraderState;
+use phoenix_sdk_core::ata_utils::{create_associated_token_account, get_associated_token_address};
+use phoenix_sdk_core::sdk_client_core::{MarketMetadata, MarketState, SDKClientCore};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::instruction::Instruction;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+
+pub const BASE_DECIMALS: u8 = 9;
+pub const QUOTE_DECIMALS: u8 = 6;
+/// Base units (e.g. SOL) each trader starts with in their wallet.
+pub const INITIAL_BASE_UNITS: u64 = 1_000;
+/// Quote units (e.g. USDC) each trader starts with in their wallet.
+pub const INITIAL_QUOTE_UNITS: u64 = 1_000_000;
+
+/// A funded trader with an approved seat on the test market.
+pub struct TestTrader {
+    pub keypair: Keypair,
+    /// Builds instructions for this trader.
+    pub core: SDKClientCore,
+}
+
+impl TestTrader {
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+}
+
+pub struct PhoenixTestContext {
+    pub context: ProgramTestContext,
+    pub market: Pubkey,
+    pub meta: MarketMetadata,
+    pub authority: Keypair,
+    pub traders: Vec<TestTrader>,
+}
+
+impl PhoenixTestContext {
+    /// Starts a validator with a SOL/USDC style market, with 1000 base lots per base unit and
+    /// a tick of 0.001 quote units, and `num_traders` traders.
+    pub async fn new(num_traders: usize) -> Result<Self> {
+        let mut program_test = ProgramTest::new(
+            "phoenix",
+            phoenix::id(),
+            processor!(phoenix::process_instruction),
+        );
+        program_test.prefer_bpf(std::env::var("SBF_OUT_DIR").is_ok());
+        let mut context = program_test.start_with_context().await;
+
+        let authority = Keypair::new();
+        let base_mint = Keypair::new();
+        let quote_mint = Keypair::new();
+        let payer = clone_keypair(&context.payer);
+        process(
+            &mut context,
+            vec![system_instruction::transfer(
+                &payer.pubkey(),
+                &authority.pubkey(),
+                100_000_000_000,
+            )],
+            &[&payer],
+        )
+        .await?;
+        for (mint, decimals) in [(&base_mint, BASE_DECIMALS), (&quote_mint, QUOTE_DECIMALS)] {
+            create_mint(&mut context, &authority, mint, decimals).await?;
+        }
+        let (market_key, meta) = create_market(
+            &mut context,
+            &authority,
+            &base_mint.pubkey(),
+            &quote_mint.pubkey(),
+        )
+        .await?;
+
+        let mut traders = vec![];
+        for _ in 0..num_traders {
+            let trader = Keypair::new();
+            process(
+                &mut context,
+                vec![system_instruction::transfer(
+                    &authority.pubkey(),
+                    &trader.pubkey(),
+                    10_000_000_000,
+                )],
+                &[&authority],
+            )
+            .await?;
+            let funds = [
+                (
+                    meta.base_mint,
+                    INITIAL_BASE_UNITS * meta.base_atoms_per_raw_base_unit,
+                ),
+                (
+                    meta.quote_mint,
+                    INITIAL_QUOTE_UNITS * meta.quote_atoms_per_quote_unit,
+                ),
+            ];
+            fund_and_seat(
+                &mut context,
+                &authority,
+                &trader.pubkey(),
+                &market_key,
+                &funds,
+            )
+            .await?;
+            traders.push(TestTrader {
+                core: SDKClientCore {
+                    markets: BTreeMap::from([(market_key, meta)]).into(),
+                    trader: trader.pubkey(),
+                    default_self_trade_behavior: Default::default(),
+                },
+                keypair: trader,
+            });
+        }
+
+        Ok(Self {
+            context,
+            market: market_key,
+            meta,
+            authority,
+            traders,
+        })
+    }
+
+    /// Creates another active market with the same parameters and quote mint, and a new base
+    /// mint. Every trader is funded with `INITIAL_BASE_UNITS` of its base, gets an approved seat
+    /// and can build instructions for it.
+    pub async fn add_market(&mut self) -> Result<(Pubkey, MarketMetadata)> {
+        let base_mint = Keypair::new();
+        create_mint(
+            &mut self.context,
+            &self.authority,
+            &base_mint,
+            BASE_DECIMALS,
+        )
+        .await?;
+        let (market, meta) = create_market(
+            &mut self.context,
+            &self.authority,
+            &base_mint.pubkey(),
+            &self.meta.quote_mint,
+        )
+        .await?;
+        let funds = [(
+            meta.base_mint,
+            INITIAL_BASE_UNITS * meta.base_atoms_per_raw_base_unit,
+        )];
+        for trader in &self.traders {
+            fund_and_seat(
+                &mut self.context,
+                &self.authority,
+                &trader.pubkey(),
+                &market,
+                &funds,
+            )
+            .await?;
+            trader.core.markets.insert(market, meta);
+        }
+        Ok((market, meta))
+    }
+
+    /// The SDK client of trader `i`.
+    pub fn core(&self, i: usize) -> &SDKClientCore {
+        &self.traders[i].core
+    }
+
+    /// Signs `ixs` with trader `i`, who pays for the transaction, and waits for it to be
+    /// processed. Fails if the transaction fails.
+    pub async fn process(&mut self, i: usize, ixs: Vec<Instruction>) -> Result<()> {
+        let trader = clone_keypair(&self.traders[i].keypair);
+        process(&mut self.context, ixs, &[&trader]).await
+    }
+
+    /// Simulates `ixs` signed by trader `i` and returns the compute units they consume, like
+    /// `SDKClient::estimate_compute_units` does over RPC. Fails if the simulation fails.
+    pub async fn simulate_compute_units(&mut self, i: usize, ixs: Vec<Instruction>) -> Result<u64> {
+        let trader = clone_keypair(&self.traders[i].keypair);
+        let blockhash = self.context.get_new_latest_blockhash().await?;
+        let transaction =
+            Transaction::new_signed_with_payer(&ixs, Some(&trader.pubkey()), &[&trader], blockhash);
+        let simulation = self
+            .context
+            .banks_client
+            .simulate_transaction(transaction)
+            .await?;
+        if let Some(Err(e)) = simulation.result {
+            return Err(anyhow!("Simulation failed: {}", e));
+        }
+        simulation
+            .simulation_details
+            .map(|details| details.units_consumed)
+            .ok_or_else(|| anyhow!("The simulation did not report the compute units consumed"))
+    }
+
+    /// Like `process`, returning the compute units the transaction consumed.
+    pub async fn process_with_compute_units(
+        &mut self,
+        i: usize,
+        ixs: Vec<Instruction>,
+    ) -> Result<u64> {
+        let trader = clone_keypair(&self.traders[i].keypair);
+        let blockhash = self.context.get_new_latest_blockhash().await?;
+        let transaction =
+            Transaction::new_signed_with_payer(&ixs, Some(&trader.pubkey()), &[&trader], blockhash);
+        let processed = self
+            .context
+            .banks_client
+            .process_transaction_with_metadata(transaction)
+            .await?;
+        processed
+            .result
+            .map_err(|e| anyhow!("Transaction failed: {}", e))?;
+        processed
+            .metadata
+            .map(|metadata| metadata.compute_units_consumed)
+            .ok_or_else(|| anyhow!("The transaction did not report the compute units consumed"))
+    }
+
+    /// Executes an instruction built for trader `i`, e.g. `core.get_limit_order_ix(..)`, and
+    /// returns the market state after it.
+    pub async fn place_and_confirm(
+        &mut self,
+        i: usize,
+        ix: Result<Instruction>,
+    ) -> Result<MarketState> {
+        self.process(i, vec![ix?]).await?;
+        self.market_state().await
+    }
+
+    pub async fn market_state(&mut self) -> Result<MarketState> {
+        let market = self.market;
+        self.market_state_of(&market).await
+    }
+
+    /// The state of `market`, e.g. one created with `add_market`.
+    pub async fn market_state_of(&mut self, market: &Pubkey) -> Result<MarketState> {
+        let data = self.account_data(market).await?;
+        MarketState::from_account_data(&data)
+    }
+
+    /// Trader `i`'s deposits on the market.
+    pub async fn trader_state(&mut self, i: usize) -> Result<TraderState> {
+        let trader = self.traders[i].pubkey();
+        Ok(self
+            .market_state()
+            .await?
+            .traders
+            .get(&trader)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    /// Trader `i`'s wallet balances, in base and quote atoms.
+    pub async fn wallet_balances(&mut self, i: usize) -> Result<(u64, u64)> {
+        let (base_mint, quote_mint) = (self.meta.base_mint, self.meta.quote_mint);
+        Ok((
+            self.token_balance(i, &base_mint).await?,
+            self.token_balance(i, &quote_mint).await?,
+        ))
+    }
+
+    /// Trader `i`'s wallet balance of `mint`, in atoms.
+    pub async fn token_balance(&mut self, i: usize, mint: &Pubkey) -> Result<u64> {
+        let trader = self.traders[i].pubkey();
+        let data = self
+            .account_data(&get_associated_token_address(&trader, mint))
+            .await?;
+        Ok(spl_token::state::Account::unpack(&data)?.amount)
+    }
+
+    async fn account_data(&mut self, key: &Pubkey) -> Result<Vec<u8>> {
+        self.context
+            .banks_client
+            .get_account(*key)
+            .await?
+            .map(|account| account.data)
+            .ok_or_else(|| anyhow!("Account {} not found", key))
+    }
+}
+
+async fn process(
+    context: &mut ProgramTestContext,
+    ixs: Vec<Instruction>,
+    signers: &[&Keypair],
+) -> Result<()> {
+    // A fresh blockhash keeps identical transactions, e.g. two equal orders, from being
+    // deduplicated
+    let blockhash = context.get_new_latest_blockhash().await?;
+    let transaction =
+        Transaction::new_signed_with_payer(&ixs, Some(&signers[0].pubkey()), signers, blockhash);
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|e| anyhow!("Transaction failed: {}", e))
+}
+
+async fn create_mint(
+    context: &mut ProgramTestContext,
+    authority: &Keypair,
+    mint: &Keypair,
+    decimals: u8,
+) -> Result<()> {
+    let rent = context.banks_client.get_rent().await?;
+    let ixs = vec![
+        system_instruction::create_account(
+            &authority.pubkey(),
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &authority.pubkey(),
+            None,
+            decimals,
+        )?,
+    ];
+    process(context, ixs, &[authority, mint]).await
+}
+
+/// Creates and activates a market of `base_mint` against `quote_mint`, with 1000 base lots per
+/// base unit and a tick of 0.001 quote units.
+async fn create_market(
+    context: &mut ProgramTestContext,
+    authority: &Keypair,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+) -> Result<(Pubkey, MarketMetadata)> {
+    let market = Keypair::new();
+    let mut ixs = create_initialize_market_instructions_default(
+        &market.pubkey(),
+        base_mint,
+        quote_mint,
+        &authority.pubkey(),
+        MarketSizeParams {
+            bids_size: 512,
+            asks_size: 512,
+            num_seats: 128,
+        },
+        // One quote lot is one quote atom
+        10u64.pow(QUOTE_DECIMALS as u32),
+        1_000,
+        1_000,
+        0,
+        None,
+    )
+    .map_err(|e| anyhow!("Failed to build market initialization: {}", e))?;
+    ixs.push(create_change_market_status_instruction(
+        &authority.pubkey(),
+        &market.pubkey(),
+        MarketStatus::Active,
+    ));
+    process(context, ixs, &[authority, &market]).await?;
+    let meta = load_metadata(context, &market.pubkey()).await?;
+    Ok((market.pubkey(), meta))
+}
+
+/// Mints each of `funds`, `(mint, atoms)`, to new token accounts of `trader`, and gives them an
+/// approved seat on `market`.
+async fn fund_and_seat(
+    context: &mut ProgramTestContext,
+    authority: &Keypair,
+    trader: &Pubkey,
+    market: &Pubkey,
+    funds: &[(Pubkey, u64)],
+) -> Result<()> {
+    let mut ixs = vec![];
+    for (mint, amount) in funds {
+        ixs.push(create_associated_token_account(
+            &authority.pubkey(),
+            trader,
+            mint,
+            &spl_token::id(),
+        ));
+        ixs.push(spl_token::instruction::mint_to(
+            &spl_token::id(),
+            mint,
+            &get_associated_token_address(trader, mint),
+            &authority.pubkey(),
+            &[],
+            *amount,
+        )?);
+    }
+    ixs.push(create_request_seat_authorized_instruction(
+        &authority.pubkey(),
+        &authority.pubkey(),
+        market,
+        trader,
+    ));
+    ixs.push(create_change_seat_status_instruction(
+        &authority.pubkey(),
+        market,
+        trader,
+        SeatApprovalStatus::Approved,
+    ));
+    process(context, ixs, &[authority]).await
+}
+
+async fn load_metadata(
+    context: &mut ProgramTestContext,
+    market: &Pubkey,
+) -> Result<MarketMetadata> {
+    let account = context
+        .banks_client
+        .get_account(*market)
+        .await?
+        .ok_or_else(|| anyhow!("Market {} not found", market))?;
+    let header_bytes = account
+        .data
+        .get(..std::mem::size_of::<MarketHeader>())
+        .ok_or_else(|| anyhow!("Market account data is too short"))?;
+    bytemuck::try_from_bytes(header_bytes)
+        .map_err(|_| anyhow!("Failed to deserialize market header"))
+        .and_then(MarketMetadata::from_header)
+}
+
+fn clone_keypair(keypair: &Keypair) -> Keypair {
+    Keypair::from_bytes(&keypair.to_bytes()).expect("a valid keypair round-trips")
+}
@@ -0,0 +1,372 @@
+use phoenix::quantities::WrapperU64;
+use phoenix::state::enums::Side;
+use phoenix_sdk_core::order_manager::OpenOrder;
+use phoenix_sdk_core::sdk_client_core::{IocLeg, MarketState};
+use phoenix_sdk_test::PhoenixTestContext;
+use solana_sdk::pubkey::Pubkey;
+
+const MAKER: usize = 0;
+const TAKER: usize = 1;
+
+/// The builders that take a `price` expect quote atoms per base unit.
+fn price(ctx: &PhoenixTestContext, price_in_ticks: u64) -> u64 {
+    price_in_ticks * ctx.meta.tick_size_in_quote_atoms_per_base_unit
+}
+
+/// `(price_in_ticks, num_base_lots, maker)` for each order on one side, best first.
+fn orders(state: &MarketState, side: Side) -> Vec<(u64, u64, Pubkey)> {
+    state
+        .orderbook
+        .iter_side(side)
+        .map(|(order_id, order)| {
+            (
+                order_id.price_in_ticks.as_u64(),
+                order.num_base_lots,
+                order.maker_id,
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_limit_order() {
+    let mut ctx = PhoenixTestContext::new(1).await.unwrap();
+    let maker = ctx.traders[MAKER].pubkey();
+    let ix = ctx
+        .core(MAKER)
+        .get_limit_order_ix(&ctx.market, price(&ctx, 10_000), Side::Bid, 100);
+    let state = ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    assert_eq!(orders(&state, Side::Bid), vec![(10_000, 100, maker)]);
+    assert!(orders(&state, Side::Ask).is_empty());
+
+    // The bid locks its notional: 0.1 base units at 10 quote units
+    let trader_state = ctx.trader_state(MAKER).await.unwrap();
+    assert_eq!(
+        ctx.meta
+            .quote_lots_to_quote_atoms(trader_state.quote_lots_locked.as_u64()),
+        1_000_000
+    );
+}
+
+#[tokio::test]
+async fn test_post_only_order() {
+    let mut ctx = PhoenixTestContext::new(2).await.unwrap();
+    let maker = ctx.traders[MAKER].pubkey();
+    let taker = ctx.traders[TAKER].pubkey();
+    let ix = ctx
+        .core(MAKER)
+        .get_post_only_ix(&ctx.market, price(&ctx, 10_100), Side::Ask, 100);
+    let state = ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    assert_eq!(orders(&state, Side::Ask), vec![(10_100, 100, maker)]);
+
+    // Rejected on cross
+    let ix = ctx.core(TAKER).get_post_only_generic_ix(
+        &ctx.market,
+        price(&ctx, 10_100),
+        Side::Bid,
+        50,
+        None,
+        Some(true),
+        None,
+        None,
+        None,
+        None,
+    );
+    assert!(ctx.place_and_confirm(TAKER, ix).await.is_err());
+    assert!(orders(&ctx.market_state().await.unwrap(), Side::Bid).is_empty());
+
+    // Amended to the best price that does not cross
+    let ix = ctx
+        .core(TAKER)
+        .get_post_only_ix(&ctx.market, price(&ctx, 10_200), Side::Bid, 50);
+    let state = ctx.place_and_confirm(TAKER, ix).await.unwrap();
+    assert_eq!(orders(&state, Side::Bid), vec![(10_099, 50, taker)]);
+    assert_eq!(orders(&state, Side::Ask), vec![(10_100, 100, maker)]);
+}
+
+#[tokio::test]
+async fn test_ioc_order() {
+    let mut ctx = PhoenixTestContext::new(2).await.unwrap();
+    let ix = ctx
+        .core(MAKER)
+        .get_limit_order_ix(&ctx.market, price(&ctx, 10_000), Side::Bid, 100);
+    ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    let (base_before, quote_before) = ctx.wallet_balances(TAKER).await.unwrap();
+
+    // Sells 100 of 150 lots, and the rest is cancelled rather than resting
+    let ix = ctx
+        .core(TAKER)
+        .get_ioc_ix(&ctx.market, price(&ctx, 10_000), Side::Ask, 150);
+    let state = ctx.place_and_confirm(TAKER, ix).await.unwrap();
+    assert!(orders(&state, Side::Bid).is_empty());
+    assert!(orders(&state, Side::Ask).is_empty());
+
+    let (base_after, quote_after) = ctx.wallet_balances(TAKER).await.unwrap();
+    assert_eq!(
+        base_before - base_after,
+        ctx.meta.base_lots_to_base_atoms(100)
+    );
+    assert_eq!(quote_after - quote_before, 1_000_000);
+    let maker_state = ctx.trader_state(MAKER).await.unwrap();
+    assert_eq!(maker_state.base_lots_free.as_u64(), 100);
+}
+
+#[tokio::test]
+async fn test_fok_orders() {
+    let mut ctx = PhoenixTestContext::new(2).await.unwrap();
+    let ix = ctx
+        .core(MAKER)
+        .get_limit_order_ix(&ctx.market, price(&ctx, 10_100), Side::Ask, 100);
+    ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    let ix = ctx
+        .core(MAKER)
+        .get_limit_order_ix(&ctx.market, price(&ctx, 10_000), Side::Bid, 100);
+    ctx.place_and_confirm(MAKER, ix).await.unwrap();
+
+    // More than the bid can absorb, so nothing fills and the transaction fails
+    let ix = ctx.core(TAKER).get_fok_sell_ix(
+        &ctx.market,
+        price(&ctx, 10_000),
+        ctx.meta.base_lots_to_base_atoms(1_000),
+    );
+    assert!(ctx.place_and_confirm(TAKER, ix).await.is_err());
+
+    // Exactly the ask's 100 lots
+    let ix = ctx
+        .core(TAKER)
+        .get_fok_buy_ix(&ctx.market, price(&ctx, 10_100), 100);
+    let state = ctx.place_and_confirm(TAKER, ix).await.unwrap();
+    assert!(orders(&state, Side::Ask).is_empty());
+    assert_eq!(orders(&state, Side::Bid).len(), 1);
+}
+
+#[tokio::test]
+async fn test_cancel_by_id() {
+    let mut ctx = PhoenixTestContext::new(1).await.unwrap();
+    let maker = ctx.traders[MAKER].pubkey();
+    for price_in_ticks in [9_900, 10_000] {
+        let ix = ctx.core(MAKER).get_limit_order_ix(
+            &ctx.market,
+            price(&ctx, price_in_ticks),
+            Side::Bid,
+            100,
+        );
+        ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    }
+    let state = ctx.market_state().await.unwrap();
+    let best = *state.orderbook.iter_bids().next().unwrap().0;
+
+    let ix = ctx.core(MAKER).get_cancel_ids_ix(&ctx.market, vec![best]);
+    let state = ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    assert_eq!(orders(&state, Side::Bid), vec![(9_900, 100, maker)]);
+}
+
+/// Rests a post-only bid of `MAKER`'s and returns it as an `OpenOrder`.
+async fn resting_bid(ctx: &mut PhoenixTestContext, price_in_ticks: u64, size: u64) -> OpenOrder {
+    let ix = ctx.core(MAKER).get_post_only_ix_from_tick_price(
+        &ctx.market,
+        price_in_ticks,
+        Side::Bid,
+        size,
+        7,
+        false,
+    );
+    let state = ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    let (order_id, order) = state.orderbook.iter_bids().next().unwrap();
+    OpenOrder {
+        order_id: *order_id,
+        side: Side::Bid,
+        client_order_id: 7,
+        num_base_lots: order.num_base_lots,
+    }
+}
+
+#[tokio::test]
+async fn test_move_order() {
+    let mut ctx = PhoenixTestContext::new(1).await.unwrap();
+    let maker = ctx.traders[MAKER].pubkey();
+    let order = resting_bid(&mut ctx, 10_000, 100).await;
+
+    let ixs = ctx
+        .core(MAKER)
+        .get_move_order_ixs(&ctx.market, &order, 10_050, 80)
+        .unwrap();
+    ctx.process(MAKER, ixs).await.unwrap();
+    let state = ctx.market_state().await.unwrap();
+    assert_eq!(orders(&state, Side::Bid), vec![(10_050, 80, maker)]);
+
+    // A replacement that would cross fails the transaction, which keeps the order
+    let ask = ctx.core(MAKER).get_post_only_ix_from_tick_price(
+        &ctx.market,
+        10_100,
+        Side::Ask,
+        10,
+        8,
+        false,
+    );
+    let state = ctx.place_and_confirm(MAKER, ask).await.unwrap();
+    let (order_id, _) = state.orderbook.iter_bids().next().unwrap();
+    let moved = OpenOrder {
+        order_id: *order_id,
+        num_base_lots: 80,
+        ..order
+    };
+    let ixs = ctx
+        .core(MAKER)
+        .get_move_order_ixs(&ctx.market, &moved, 10_100, 80)
+        .unwrap();
+    assert!(ctx.process(MAKER, ixs).await.is_err());
+    let state = ctx.market_state().await.unwrap();
+    assert_eq!(orders(&state, Side::Bid), vec![(10_050, 80, maker)]);
+}
+
+#[tokio::test]
+async fn test_move_filled_order() {
+    let mut ctx = PhoenixTestContext::new(2).await.unwrap();
+    let maker = ctx.traders[MAKER].pubkey();
+    let order = resting_bid(&mut ctx, 10_000, 100).await;
+
+    // The bid fills before the move lands
+    let ix = ctx
+        .core(TAKER)
+        .get_ioc_ix(&ctx.market, price(&ctx, 10_000), Side::Ask, 100);
+    let state = ctx.place_and_confirm(TAKER, ix).await.unwrap();
+    assert!(orders(&state, Side::Bid).is_empty());
+
+    // Phoenix skips the unknown id rather than failing the cancel, so the replacement is placed
+    // anyway. This is the race `SDKClient::move_order` detects from the transaction's events.
+    let ixs = ctx
+        .core(MAKER)
+        .get_move_order_ixs(&ctx.market, &order, 10_050, 80)
+        .unwrap();
+    ctx.process(MAKER, ixs).await.unwrap();
+    let state = ctx.market_state().await.unwrap();
+    assert_eq!(orders(&state, Side::Bid), vec![(10_050, 80, maker)]);
+}
+
+#[tokio::test]
+async fn test_cancel_up_to() {
+    let mut ctx = PhoenixTestContext::new(1).await.unwrap();
+    let maker = ctx.traders[MAKER].pubkey();
+    for price_in_ticks in [10_100, 10_200, 10_300] {
+        let ix = ctx.core(MAKER).get_limit_order_ix(
+            &ctx.market,
+            price(&ctx, price_in_ticks),
+            Side::Ask,
+            100,
+        );
+        ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    }
+
+    let ix = ctx
+        .core(MAKER)
+        .get_cancel_up_to_ix(&ctx.market, Some(10_200), Side::Ask);
+    let state = ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    assert_eq!(orders(&state, Side::Ask), vec![(10_300, 100, maker)]);
+}
+
+#[tokio::test]
+async fn test_deposit_and_withdraw() {
+    let mut ctx = PhoenixTestContext::new(1).await.unwrap();
+    let wallet_before = ctx.wallet_balances(MAKER).await.unwrap();
+
+    let ix = ctx
+        .core(MAKER)
+        .get_deposit_ix(&ctx.market, 1_000, 5_000_000);
+    ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    let trader_state = ctx.trader_state(MAKER).await.unwrap();
+    assert_eq!(trader_state.base_lots_free.as_u64(), 1_000);
+    assert_eq!(trader_state.quote_lots_free.as_u64(), 5_000_000);
+    let (base, quote) = ctx.wallet_balances(MAKER).await.unwrap();
+    assert_eq!(
+        wallet_before.0 - base,
+        ctx.meta.base_lots_to_base_atoms(1_000)
+    );
+    assert_eq!(
+        wallet_before.1 - quote,
+        ctx.meta.quote_lots_to_quote_atoms(5_000_000)
+    );
+
+    let ix = ctx.core(MAKER).get_withdraw_lots_ix(&ctx.market, 400, 0);
+    ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    assert_eq!(
+        ctx.trader_state(MAKER)
+            .await
+            .unwrap()
+            .base_lots_free
+            .as_u64(),
+        600
+    );
+
+    let ix = ctx.core(MAKER).get_withdraw_ix(&ctx.market);
+    ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    let trader_state = ctx.trader_state(MAKER).await.unwrap();
+    assert_eq!(trader_state.base_lots_free.as_u64(), 0);
+    assert_eq!(trader_state.quote_lots_free.as_u64(), 0);
+    assert_eq!(ctx.wallet_balances(MAKER).await.unwrap(), wallet_before);
+}
+
+async fn token_balances(ctx: &mut PhoenixTestContext, i: usize, mints: &[Pubkey]) -> Vec<u64> {
+    let mut balances = vec![];
+    for mint in mints {
+        balances.push(ctx.token_balance(i, mint).await.unwrap());
+    }
+    balances
+}
+
+#[tokio::test]
+async fn test_multi_market_ioc_reverts_together() {
+    let mut ctx = PhoenixTestContext::new(2).await.unwrap();
+    let market_a = ctx.market;
+    let (market_b, meta_b) = ctx.add_market().await.unwrap();
+    // 100 lots offered at 10 on A and bid at 10.5 on B
+    let ix = ctx
+        .core(MAKER)
+        .get_limit_order_ix(&market_a, price(&ctx, 10_000), Side::Ask, 100);
+    ctx.place_and_confirm(MAKER, ix).await.unwrap();
+    let ix = ctx
+        .core(MAKER)
+        .get_limit_order_ix(&market_b, price(&ctx, 10_500), Side::Bid, 100);
+    ctx.process(MAKER, vec![ix.unwrap()]).await.unwrap();
+
+    // Buy the 100 lots on A for 1,000,000 quote lots, then sell 100 lots on B for 1,050,000
+    let legs = |ctx: &PhoenixTestContext, min_quote_lots_out| {
+        ctx.core(TAKER).get_multi_market_ioc_ixs(&[
+            IocLeg {
+                market: market_a,
+                side: Side::Bid,
+                price_in_ticks: Some(10_000),
+                size_lots: 1_000_000,
+                min_lots_out: 100,
+            },
+            IocLeg {
+                market: market_b,
+                side: Side::Ask,
+                price_in_ticks: Some(10_500),
+                size_lots: 100,
+                min_lots_out: min_quote_lots_out,
+            },
+        ])
+    };
+    let mints = [ctx.meta.base_mint, meta_b.base_mint, ctx.meta.quote_mint];
+    let before = token_balances(&mut ctx, TAKER, &mints).await;
+
+    // The second leg cannot receive more than 1,050,000 quote lots, so the first leg's fill is
+    // reverted with it
+    let ixs = legs(&ctx, 1_050_001).unwrap();
+    assert!(ctx.process(TAKER, ixs).await.is_err());
+    assert_eq!(token_balances(&mut ctx, TAKER, &mints).await, before);
+    let state_a = ctx.market_state().await.unwrap();
+    assert_eq!(orders(&state_a, Side::Ask).len(), 1);
+    assert_eq!(orders(&state_a, Side::Ask)[0].1, 100);
+
+    let ixs = legs(&ctx, 1_050_000).unwrap();
+    ctx.process(TAKER, ixs).await.unwrap();
+    let lots = 100 * ctx.meta.base_atoms_per_base_lot;
+    assert_eq!(
+        token_balances(&mut ctx, TAKER, &mints).await,
+        [before[0] + lots, before[1] - lots, before[2] + 50_000]
+    );
+    assert!(orders(&ctx.market_state().await.unwrap(), Side::Ask).is_empty());
+    assert!(orders(&ctx.market_state_of(&market_b).await.unwrap(), Side::Bid).is_empty());
+}
@@ -0,0 +1,61 @@
+use phoenix::state::enums::Side;
+use phoenix_sdk_core::compute_units::{with_compute_unit_limit, ComputeUnitMargin};
+use phoenix_sdk_test::PhoenixTestContext;
+use solana_sdk::instruction::Instruction;
+
+const MAKER: usize = 0;
+const TAKER: usize = 1;
+
+fn price(ctx: &PhoenixTestContext, price_in_ticks: u64) -> u64 {
+    price_in_ticks * ctx.meta.tick_size_in_quote_atoms_per_base_unit
+}
+
+/// Estimates `ixs` by simulation, sends them with the default margin as their limit, and
+/// checks that they used about what was estimated.
+async fn check_estimate(ctx: &mut PhoenixTestContext, i: usize, ixs: Vec<Instruction>) {
+    let estimate = ctx.simulate_compute_units(i, ixs.clone()).await.unwrap();
+    let limit = ComputeUnitMargin::default().limit(estimate);
+    let consumed = ctx
+        .process_with_compute_units(i, with_compute_unit_limit(ixs, limit))
+        .await
+        .unwrap();
+    assert!(consumed <= limit as u64);
+    // The limit instruction itself costs a little
+    assert!(
+        consumed.abs_diff(estimate) <= estimate / 20,
+        "estimated {} units, consumed {}",
+        estimate,
+        consumed
+    );
+}
+
+#[tokio::test]
+async fn test_limit_order_estimate() {
+    let mut ctx = PhoenixTestContext::new(1).await.unwrap();
+    let ix = ctx
+        .core(MAKER)
+        .get_limit_order_ix(&ctx.market, price(&ctx, 10_000), Side::Bid, 100)
+        .unwrap();
+    check_estimate(&mut ctx, MAKER, vec![ix]).await;
+}
+
+#[tokio::test]
+async fn test_sweep_estimate() {
+    let mut ctx = PhoenixTestContext::new(2).await.unwrap();
+    let bids = [10_000, 9_990, 9_980]
+        .into_iter()
+        .map(|price_in_ticks| {
+            ctx.core(MAKER)
+                .get_limit_order_ix(&ctx.market, price(&ctx, price_in_ticks), Side::Bid, 50)
+                .unwrap()
+        })
+        .collect();
+    ctx.process(MAKER, bids).await.unwrap();
+
+    // Sells through all three levels
+    let ix = ctx
+        .core(TAKER)
+        .get_ioc_ix(&ctx.market, price(&ctx, 9_980), Side::Ask, 150)
+        .unwrap();
+    check_estimate(&mut ctx, TAKER, vec![ix]).await;
+}